@@ -0,0 +1,59 @@
+/// The maximum number of annotations that can be set at any one time
+pub const MAX_ANNOTATIONS: usize = 64;
+/// The maximum length of an annotation key, in bytes
+pub const MAX_ANNOTATION_KEY_LEN: usize = 64;
+/// The maximum length of an annotation value, in bytes
+pub const MAX_ANNOTATION_VALUE_LEN: usize = 256;
+
+/// The [`Annotation::state`] of a slot that doesn't contain an annotation
+pub const ANNOTATION_EMPTY: u32 = 0;
+/// The [`Annotation::state`] of a slot that is in the middle of being written,
+/// and thus may be torn
+pub const ANNOTATION_WRITING: u32 = 1;
+/// The [`Annotation::state`] of a slot that contains a valid annotation
+pub const ANNOTATION_VALID: u32 = 2;
+
+/// A single key/value annotation slot, as it is laid out in the memory of the
+/// crashing process.
+///
+/// The crashing process keeps a fixed size array of [`MAX_ANNOTATIONS`] of
+/// these, whose location is recorded in the crash context so that a process
+/// handling the crash can read them from the memory of the crashing process.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Annotation {
+    /// Whether the slot is empty, being written, or valid
+    pub state: u32,
+    /// The length of the key in [`Self::key`]
+    pub key_len: u16,
+    /// The length of the value in [`Self::value`]
+    pub value_len: u16,
+    /// The key, which is utf-8
+    pub key: [u8; MAX_ANNOTATION_KEY_LEN],
+    /// The value, which is utf-8
+    pub value: [u8; MAX_ANNOTATION_VALUE_LEN],
+}
+
+impl Annotation {
+    /// An empty slot
+    pub const EMPTY: Self = Self {
+        state: ANNOTATION_EMPTY,
+        key_len: 0,
+        value_len: 0,
+        key: [0; MAX_ANNOTATION_KEY_LEN],
+        value: [0; MAX_ANNOTATION_VALUE_LEN],
+    };
+
+    /// Retrieves the key and value, if the slot contains a valid annotation
+    #[inline]
+    pub fn get(&self) -> Option<(&str, &str)> {
+        if self.state != ANNOTATION_VALID {
+            return None;
+        }
+
+        let key = std::str::from_utf8(self.key.get(..self.key_len as usize)?).ok()?;
+        let value = std::str::from_utf8(self.value.get(..self.value_len as usize)?).ok()?;
+
+        Some((key, value))
+    }
+}
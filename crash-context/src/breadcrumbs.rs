@@ -0,0 +1,86 @@
+/// The number of breadcrumbs that are kept, once this many have been added,
+/// each new breadcrumb replaces the oldest one
+pub const MAX_BREADCRUMBS: usize = 64;
+/// The maximum length of a breadcrumb message, in bytes
+pub const MAX_BREADCRUMB_MESSAGE_LEN: usize = 256;
+
+/// The [`Breadcrumb::sequence`] of a slot that is in the middle of being
+/// written, and thus may be torn
+pub const BREADCRUMB_WRITING: u64 = u64::MAX;
+
+/// The severity of a [`Breadcrumb`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum BreadcrumbLevel {
+    Debug = 0,
+    Info = 1,
+    Warning = 2,
+    Error = 3,
+}
+
+impl BreadcrumbLevel {
+    /// Converts the raw value stored in a [`Breadcrumb`]
+    #[inline]
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        Some(match raw {
+            0 => Self::Debug,
+            1 => Self::Info,
+            2 => Self::Warning,
+            3 => Self::Error,
+            _ => return None,
+        })
+    }
+}
+
+/// A single breadcrumb slot, as it is laid out in the memory of the crashing
+/// process.
+///
+/// The crashing process keeps a fixed size ring of [`MAX_BREADCRUMBS`] of
+/// these, whose location is recorded in the crash context so that a process
+/// handling the crash can read them from the memory of the crashing process.
+/// Since the ring wraps, the breadcrumbs must be sorted by their
+/// [`Self::sequence`] to retrieve them in the order they were added.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Breadcrumb {
+    /// The order the breadcrumb was added in, starting at 1. This is 0 if
+    /// the slot is empty, and [`BREADCRUMB_WRITING`] while it is being
+    /// written
+    pub sequence: u64,
+    /// The time the breadcrumb was added, in milliseconds since the unix epoch
+    pub timestamp: u64,
+    /// The [`BreadcrumbLevel`] of the breadcrumb
+    pub level: u8,
+    /// Padding, always 0
+    pub _padding: u8,
+    /// The length of the message in [`Self::message`]
+    pub message_len: u16,
+    /// The message, which is utf-8
+    pub message: [u8; MAX_BREADCRUMB_MESSAGE_LEN],
+}
+
+impl Breadcrumb {
+    /// An empty slot
+    pub const EMPTY: Self = Self {
+        sequence: 0,
+        timestamp: 0,
+        level: 0,
+        _padding: 0,
+        message_len: 0,
+        message: [0; MAX_BREADCRUMB_MESSAGE_LEN],
+    };
+
+    /// Retrieves the level and message, if the slot contains a valid
+    /// breadcrumb
+    #[inline]
+    pub fn get(&self) -> Option<(BreadcrumbLevel, &str)> {
+        if self.sequence == 0 || self.sequence == BREADCRUMB_WRITING {
+            return None;
+        }
+
+        let level = BreadcrumbLevel::from_raw(self.level)?;
+        let message = std::str::from_utf8(self.message.get(..self.message_len as usize)?).ok()?;
+
+        Some((level, message))
+    }
+}
@@ -0,0 +1,83 @@
+/// The full context for a FreeBSD/OpenBSD crash
+#[repr(C)]
+#[derive(Clone)]
+pub struct CrashContext {
+    /// Crashing thread context.
+    ///
+    /// Unlike Linux, the BSDs only have a single libc, so the `ucontext_t`
+    /// (which is just `sigcontext` on OpenBSD) received from a signal matches
+    /// [`libc::ucontext_t`], which we can use directly.
+    ///
+    /// Note that `ucontext_t::uc_link` on FreeBSD is a pointer and thus can't
+    /// be accessed in a process other than the one the `CrashContext` was
+    /// created in.
+    pub context: libc::ucontext_t,
+    /// The signal info for the crash.
+    ///
+    /// Unlike Linux, the BSDs don't have a `signalfd_siginfo` with a stable
+    /// layout, so this is the plain `siginfo_t` received by the signal handler.
+    pub siginfo: libc::siginfo_t,
+    /// The id of the crashing process
+    pub pid: libc::pid_t,
+    /// The id of the crashing thread, as retrieved via
+    /// `pthread_getthreadid_np` on FreeBSD and `getthrid` on OpenBSD
+    pub tid: libc::pid_t,
+    /// The address of the array of [`crate::Annotation`]s in the crashing
+    /// process, or 0 if there are none
+    pub annotations: usize,
+    /// The number of [`crate::Annotation`]s in the array at [`Self::annotations`]
+    pub annotation_count: usize,
+    /// The address of the ring of [`crate::Breadcrumb`]s in the crashing
+    /// process, or 0 if there are none
+    pub breadcrumbs: usize,
+    /// The number of [`crate::Breadcrumb`]s in the ring at [`Self::breadcrumbs`]
+    pub breadcrumb_count: usize,
+    /// The time at which the crash was captured, which is all zeroes if it
+    /// wasn't
+    pub time: crate::CrashTime,
+}
+
+unsafe impl Send for CrashContext {}
+
+impl CrashContext {
+    /// Reinterprets the context as raw bytes.
+    ///
+    /// Note the layout is only valid for the same OS, architecture, and
+    /// version of this crate.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let size = std::mem::size_of_val(self);
+            let ptr = (self as *const Self).cast();
+            std::slice::from_raw_parts(ptr, size)
+        }
+    }
+
+    /// Reinterprets the bytes retrieved via [`Self::as_bytes`] as a context,
+    /// returning `None` if the length is invalid.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != std::mem::size_of::<Self>() {
+            return None;
+        }
+
+        // SAFETY: every field is plain old data, so any bit pattern is valid
+        unsafe { Some(bytes.as_ptr().cast::<Self>().read_unaligned()) }
+    }
+
+    /// Retrieves the id of the calling thread, in the same form as
+    /// [`Self::tid`].
+    ///
+    /// This is async signal safe.
+    #[inline]
+    pub fn current_tid() -> libc::pid_t {
+        // SAFETY: syscalls that can't fail
+        unsafe {
+            cfg_if::cfg_if! {
+                if #[cfg(target_os = "freebsd")] {
+                    libc::pthread_getthreadid_np()
+                } else {
+                    libc::getthrid()
+                }
+            }
+        }
+    }
+}
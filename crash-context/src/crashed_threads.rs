@@ -0,0 +1,66 @@
+/// The maximum number of threads that can be recorded as having crashed while
+/// another thread's crash was being handled
+pub const MAX_CRASHED_THREADS: usize = 16;
+
+/// The [`CrashedThread::state`] of a slot that doesn't contain a thread
+pub const CRASHED_THREAD_EMPTY: u32 = 0;
+/// The [`CrashedThread::state`] of a slot that is in the middle of being
+/// written, and thus may be torn
+pub const CRASHED_THREAD_WRITING: u32 = 1;
+/// The [`CrashedThread::state`] of a slot that contains a valid thread
+pub const CRASHED_THREAD_VALID: u32 = 2;
+
+/// The type of the minidump stream that records every thread that also
+/// crashed while the crash the minidump was written for was being handled.
+///
+/// The stream is a `u32` count, followed by that many entries, each of which
+/// is the `u32` thread id, `u32` signal number, `i32` signal code, 4 bytes of
+/// padding, and the `u64` fault address, instruction pointer, and stack
+/// pointer, all little endian.
+pub const CRASHED_THREADS_STREAM: u32 = 0x4d52_0002;
+
+/// A thread that crashed while the crash of another thread was being handled,
+/// as it is laid out in the memory of the crashing process.
+///
+/// Only the first thread to crash is handled, every other thread that crashes
+/// in the meantime is parked until the handling completes, which in most cases
+/// means until the process is terminated. So that these crashes aren't lost,
+/// the crashing process keeps a fixed size array of [`MAX_CRASHED_THREADS`] of
+/// these, whose location is recorded in the crash context.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CrashedThread {
+    /// Whether the slot is empty, being written, or valid
+    pub state: u32,
+    /// The id of the thread
+    pub tid: u32,
+    /// The signal the thread crashed with
+    pub signo: u32,
+    /// The code of the signal
+    pub code: i32,
+    /// The address that caused the fault, or 0 if the signal wasn't a fault
+    pub address: u64,
+    /// The instruction pointer of the thread when it crashed
+    pub instruction_pointer: u64,
+    /// The stack pointer of the thread when it crashed
+    pub stack_pointer: u64,
+}
+
+impl CrashedThread {
+    /// An empty slot
+    pub const EMPTY: Self = Self {
+        state: CRASHED_THREAD_EMPTY,
+        tid: 0,
+        signo: 0,
+        code: 0,
+        address: 0,
+        instruction_pointer: 0,
+        stack_pointer: 0,
+    };
+
+    /// Whether the slot contains a valid thread
+    #[inline]
+    pub fn is_valid(&self) -> bool {
+        self.state == CRASHED_THREAD_VALID
+    }
+}
@@ -0,0 +1,143 @@
+/// The maximum size of an encoded [`Environment`], arguments and variables
+/// that don't fit are dropped
+pub const MAX_ENVIRONMENT_LEN: usize = 32 * 1024;
+
+/// The size of the counts preceding the strings of an encoded [`Environment`]
+const HEADER_LEN: usize = 8;
+
+/// The command line and environment variables of the crashing process, as
+/// they were captured when the crash handler was attached.
+///
+/// The crashing process keeps them encoded in a single buffer, whose location
+/// is recorded in the crash context, so that a process handling the crash can
+/// read them from its memory. The buffer is the `u32` number of arguments and
+/// the `u32` number of variables, in native byte order, followed by the
+/// arguments and then the variables as `KEY=VALUE`, each nul terminated.
+#[derive(Copy, Clone, Debug)]
+pub struct Environment<'a> {
+    arg_count: usize,
+    var_count: usize,
+    strings: &'a [u8],
+}
+
+impl<'a> Environment<'a> {
+    /// Encodes the arguments and variables, dropping any that contain a nul
+    /// byte, or that don't fit in [`MAX_ENVIRONMENT_LEN`]
+    pub fn encode<'s>(
+        args: impl IntoIterator<Item = &'s str>,
+        vars: impl IntoIterator<Item = (&'s str, &'s str)>,
+    ) -> Vec<u8> {
+        let mut buf = vec![0u8; HEADER_LEN];
+
+        let mut push = |parts: &[&str]| {
+            let len: usize = parts.iter().map(|part| part.len()).sum();
+            if parts.iter().any(|part| part.contains('\0'))
+                || buf.len() + len + 1 > MAX_ENVIRONMENT_LEN
+            {
+                return false;
+            }
+
+            for part in parts {
+                buf.extend_from_slice(part.as_bytes());
+            }
+            buf.push(0);
+            true
+        };
+
+        let arg_count = args.into_iter().filter(|arg| push(&[arg])).count() as u32;
+        let var_count = vars
+            .into_iter()
+            .filter(|(key, value)| !key.contains('=') && push(&[key, "=", value]))
+            .count() as u32;
+
+        buf[..4].copy_from_slice(&arg_count.to_ne_bytes());
+        buf[4..8].copy_from_slice(&var_count.to_ne_bytes());
+        buf
+    }
+
+    /// Parses an encoded environment, returning `None` if it is truncated
+    pub fn parse(buf: &'a [u8]) -> Option<Self> {
+        let count = |offset: usize| -> Option<usize> {
+            Some(u32::from_ne_bytes(buf.get(offset..offset + 4)?.try_into().ok()?) as usize)
+        };
+
+        let env = Self {
+            arg_count: count(0)?,
+            var_count: count(4)?,
+            strings: &buf[HEADER_LEN..],
+        };
+
+        let strings = env.strings.iter().filter(|b| **b == 0).count();
+        if strings < env.arg_count + env.var_count {
+            return None;
+        }
+
+        Some(env)
+    }
+
+    /// The arguments the process was started with, including the executable
+    pub fn args(&self) -> impl Iterator<Item = &'a str> {
+        self.strings().take(self.arg_count)
+    }
+
+    /// The environment variables that were captured
+    pub fn vars(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.strings()
+            .skip(self.arg_count)
+            .take(self.var_count)
+            .map(|var| var.split_once('=').unwrap_or((var, "")))
+    }
+
+    #[inline]
+    fn strings(&self) -> impl Iterator<Item = &'a str> {
+        self.strings
+            .split(|b| *b == 0)
+            .map(|s| std::str::from_utf8(s).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let buf = Environment::encode(
+            ["/usr/bin/app", "--config", "", "bad\0arg"],
+            [
+                ("RUST_LOG", "debug"),
+                ("EMPTY", ""),
+                ("A=B", "c"),
+                ("TOKEN", "<redacted>"),
+            ],
+        );
+
+        let env = Environment::parse(&buf).unwrap();
+        assert_eq!(
+            env.args().collect::<Vec<_>>(),
+            ["/usr/bin/app", "--config", ""]
+        );
+        assert_eq!(
+            env.vars().collect::<Vec<_>>(),
+            [
+                ("RUST_LOG", "debug"),
+                ("EMPTY", ""),
+                ("TOKEN", "<redacted>")
+            ]
+        );
+
+        assert!(Environment::parse(&buf[..buf.len() - 1]).is_none());
+        assert!(Environment::parse(&buf[..4]).is_none());
+    }
+
+    #[test]
+    fn truncates() {
+        let long = "x".repeat(MAX_ENVIRONMENT_LEN / 4);
+        let buf = Environment::encode(std::iter::repeat(long.as_str()).take(8), [("SHORT", "1")]);
+
+        assert!(buf.len() <= MAX_ENVIRONMENT_LEN);
+        let env = Environment::parse(&buf).unwrap();
+        assert_eq!(env.args().count(), 3);
+        assert_eq!(env.vars().collect::<Vec<_>>(), [("SHORT", "1")]);
+    }
+}
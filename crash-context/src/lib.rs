@@ -12,6 +12,20 @@
 //! implementations (notably `musl`) implement it as it has been deprecated from
 //! POSIX.
 //!
+//! ## FreeBSD/OpenBSD
+//!
+//! The [`CrashContext`] contains the plain `ucontext_t` and `siginfo_t`
+//! received by the signal handler, as the BSDs only have a single libc and
+//! thus don't need the portable types used for Linux.
+//!
+//! ## Annotations
+//!
+//! On Linux/Android, FreeBSD/OpenBSD, and Macos, the [`CrashContext`] also
+//! contains the location of a fixed size array of [`Annotation`]s in the
+//! memory of the crashed process, so that application metadata can be added
+//! to a crash report without needing to allocate or serialize anything at the
+//! time of the crash.
+//!
 //! ## Macos
 //!
 //! One major difference on Macos is that the details in the [`CrashContext`]
@@ -24,14 +38,44 @@
 // crate-specific exceptions:
 #![allow(unsafe_code, nonstandard_style)]
 
+mod annotations;
+pub use annotations::*;
+mod breadcrumbs;
+pub use breadcrumbs::*;
+mod crashed_threads;
+pub use crashed_threads::*;
+mod environment;
+pub use environment::*;
+mod memory_regions;
+pub use memory_regions::*;
+mod open_files;
+pub use open_files::*;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+mod time;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+pub use time::CrashTime;
+
 cfg_if::cfg_if! {
     if #[cfg(any(target_os = "linux", target_os = "android"))] {
         mod linux;
         pub use linux::*;
+    } else if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+        mod bsd;
+        pub use bsd::*;
     } else if #[cfg(target_os = "windows")] {
         mod windows;
         pub use windows::*;
-    } else if #[cfg(target_os = "macos")] {
+    } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
         mod mac;
         pub use mac::*;
     }
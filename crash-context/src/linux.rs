@@ -1,6 +1,24 @@
+mod cpu;
+mod fault;
+mod fmt;
 mod getcontext;
+mod json;
+mod memory;
+mod module;
+mod registers;
+mod seccomp;
+mod synthetic;
+mod wire;
 
+pub use cpu::CpuInfo;
+pub use fault::{AccessType, FaultInfo, NULL_ADDRESS_LIMIT};
+pub use fmt::{signal_name, write_report, Report};
 pub use getcontext::crash_context_getcontext;
+pub use memory::MemoryStats;
+pub use module::{CrashingModule, MAX_BUILD_ID_LEN, MAX_MODULE_PATH_LEN};
+pub use registers::GpRegs;
+pub use seccomp::{SeccompViolation, NATIVE_AUDIT_ARCH, SYS_SECCOMP};
+pub use wire::{DecodeError, WIRE_ARCH, WIRE_VERSION};
 
 /// The full context for a Linux/Android crash
 #[repr(C)]
@@ -10,7 +28,7 @@ pub struct CrashContext {
     ///
     /// Note that we use [`crate::ucontext_t`] instead of [`libc::ucontext_t`]
     /// as libc's differs between glibc and musl <https://github.com/rust-lang/libc/pull/1646>
-    /// even though the ucontext_t received from a signal will be the same
+    /// even though the `ucontext_t` received from a signal will be the same
     /// regardless of the libc implementation used as it is only arch specific
     /// and not libc specific
     ///
@@ -20,13 +38,15 @@ pub struct CrashContext {
     ///
     /// Note that the same applies to [`mcontext_t::fpregs`], but since that points
     /// to floating point registers and _is_ interesting to read in another process,
-    /// those registers available as [`Self::float_state`], except on the `arm`
-    /// architecture since they aren't part of `mcontext_t` at all.
+    /// those registers available as [`Self::float_state`]. On `arm` they
+    /// aren't part of `mcontext_t` at all, but are instead one of the
+    /// coprocessor frames in `ucontext_t::uc_regspace`, which is copied as is.
     pub context: ucontext_t,
-    /// State of floating point registers.
+    /// State of floating point registers, which is the `fpsimd_context` on
+    /// `aarch64`, and the VFP/NEON `vfp_sigframe` on `arm`.
     ///
-    /// This isn't part of the user ABI for Linux arm
-    #[cfg(not(target_arch = "arm"))]
+    /// This is all zeroes if the floating point state wasn't available, use
+    /// [`Self::float_state`] to only retrieve it if it was actually captured.
     pub float_state: fpregset_t,
     /// The signal info for the crash
     pub siginfo: libc::signalfd_siginfo,
@@ -34,11 +54,168 @@ pub struct CrashContext {
     pub pid: libc::pid_t,
     /// The id of the crashing thread
     pub tid: libc::pid_t,
+    /// The reason for the crash, as determined by the crash handler in the
+    /// crashing process.
+    ///
+    /// Some crash reasons can only be reliably determined from within the
+    /// crashing process itself, so this is recorded at the time of the crash
+    /// rather than being inferred from the rest of the context afterwards
+    pub reason: CrashReason,
+    /// The address of the array of [`crate::Annotation`]s in the crashing
+    /// process, or 0 if there are none
+    pub annotations: usize,
+    /// The number of [`crate::Annotation`]s in the array at [`Self::annotations`]
+    pub annotation_count: usize,
+    /// The address of the ring of [`crate::Breadcrumb`]s in the crashing
+    /// process, or 0 if there are none
+    pub breadcrumbs: usize,
+    /// The number of [`crate::Breadcrumb`]s in the ring at [`Self::breadcrumbs`]
+    pub breadcrumb_count: usize,
+    /// The address of the array of [`crate::MemoryRegion`]s in the crashing
+    /// process, or 0 if there are none
+    pub memory_regions: usize,
+    /// The number of [`crate::MemoryRegion`]s in the array at
+    /// [`Self::memory_regions`]
+    pub memory_region_count: usize,
+    /// The address of the array of [`crate::CrashedThread`]s in the crashing
+    /// process, recording the threads that crashed while this crash was being
+    /// handled, or 0 if there are none
+    pub crashed_threads: usize,
+    /// The number of [`crate::CrashedThread`]s in the array at
+    /// [`Self::crashed_threads`]
+    pub crashed_thread_count: usize,
+    /// The address of the [`crate::Environment`] of the crashing process,
+    /// which is only valid in the memory of that process, or 0 if it wasn't
+    /// captured
+    pub environment: usize,
+    /// The length in bytes of the encoded [`crate::Environment`] at
+    /// [`Self::environment`]
+    pub environment_len: usize,
+    /// The time at which the crash was captured, which is all zeroes if it
+    /// wasn't
+    pub time: crate::CrashTime,
+    /// The time at which the crashing process was started, or forked, so that
+    /// eg. crashes on startup can be told apart from crashes after running
+    /// for days. All zeroes if it wasn't captured
+    pub process_start: crate::CrashTime,
+    /// The time at which the crash handler was attached, which is all zeroes
+    /// if it wasn't captured
+    pub attach_time: crate::CrashTime,
+    /// The features of the CPU the crashing process was running on, which
+    /// are all zeroes if they weren't captured
+    pub cpu: CpuInfo,
+    /// The memory usage of the crashing process at the time of the crash,
+    /// which is all zeroes if it wasn't captured
+    pub memory: MemoryStats,
+    /// The name of the crashing thread, nul terminated, as set via eg.
+    /// [`std::thread::Builder::name`] or `pthread_setname_np`. Use
+    /// [`Self::thread_name`] to retrieve it as a string.
+    ///
+    /// Note that the kernel truncates thread names to 15 bytes.
+    pub thread_name: [u8; THREAD_NAME_LEN],
+    /// The message describing why the process aborted, nul terminated, eg.
+    /// the assertion that failed, which is only captured for `SIGABRT`. Use
+    /// [`Self::abort_message`] to retrieve it as a string.
+    pub abort_message: [u8; ABORT_MESSAGE_LEN],
+}
+
+/// The maximum length of a thread name, including the nul terminator, ie.
+/// `TASK_COMM_LEN` in the kernel
+pub const THREAD_NAME_LEN: usize = 16;
+
+/// The maximum length of an abort message, including the nul terminator,
+/// longer messages are truncated
+pub const ABORT_MESSAGE_LEN: usize = 256;
+
+/// The reason a crash occurred, beyond what is described by the signal itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum CrashReason {
+    /// The crash is fully described by [`CrashContext::siginfo`]
+    Signal = 0,
+    /// The crashing thread exhausted its stack, ie. the `SIGSEGV` was caused
+    /// by an access to the guard page(s) immediately below the thread's stack
+    StackOverflow = 1,
+    /// The crash was a Rust panic that was routed through the crash handler,
+    /// rather than an actual signal
+    Panic = 2,
+    /// The `SIGTRAP` was caused by a software breakpoint instruction, ie.
+    /// `int3` on x86 or `brk`/`bkpt` on ARM, eg. from
+    /// `std::intrinsics::breakpoint`
+    Breakpoint = 3,
+    /// The `SIGTRAP` was caused by a hardware breakpoint or watchpoint set in
+    /// the debug registers, eg. by a debugger
+    HardwareBreakpoint = 4,
+    /// The crash was caused by an instruction emitted to deliberately crash,
+    /// eg. by `__builtin_trap` or `std::intrinsics::abort`, which is `ud2` on
+    /// x86 and thus raises a `SIGILL`, and `brk #0x3e8` on aarch64 which
+    /// raises a `SIGTRAP`
+    Trap = 5,
+    /// Not a crash, but a forewarning that the process is likely to be killed
+    /// by the OOM killer, which can't be handled as it uses `SIGKILL`. The
+    /// context is of the thread that detected the memory pressure
+    MemoryPressure = 6,
+    /// The `SIGABRT` was raised by the same thread right after a heap
+    /// allocation failed, ie. the process ran out of memory rather than
+    /// aborting due to a bug. The [`CrashContext::abort_message`] records the
+    /// size of the allocation
+    AllocationFailure = 7,
+    /// Not a crash, but a thread that stopped making progress, eg. due to a
+    /// deadlock, as detected by `crash_handler::hang_monitor`. The context is
+    /// of the thread that hung, while it was hung
+    Hang = 8,
+    /// Not a crash, but a dump requested by sending the process a signal, as
+    /// configured by `crash_handler::CrashHandlerBuilder::dump_signal`. The
+    /// context is of the thread that handled the request, and the process
+    /// continues running afterwards
+    DumpRequest = 9,
+}
+
+impl CrashReason {
+    #[inline]
+    fn from_raw(raw: u32) -> Option<Self> {
+        Some(match raw {
+            0 => Self::Signal,
+            1 => Self::StackOverflow,
+            2 => Self::Panic,
+            3 => Self::Breakpoint,
+            4 => Self::HardwareBreakpoint,
+            5 => Self::Trap,
+            6 => Self::MemoryPressure,
+            7 => Self::AllocationFailure,
+            8 => Self::Hang,
+            9 => Self::DumpRequest,
+            _ => return None,
+        })
+    }
+
+    /// Returns true if the crash was caused by a trap that was deliberately
+    /// placed in the code or set by a debugger, as opposed to eg. a `SIGTRAP`
+    /// that was sent by another process or raised for an unknown reason
+    #[inline]
+    pub fn is_deliberate_trap(self) -> bool {
+        matches!(
+            self,
+            Self::Breakpoint | Self::HardwareBreakpoint | Self::Trap
+        )
+    }
+}
+
+impl Default for CrashReason {
+    #[inline]
+    fn default() -> Self {
+        Self::Signal
+    }
 }
 
 unsafe impl Send for CrashContext {}
 
 impl CrashContext {
+    /// Reinterprets the context as raw bytes.
+    ///
+    /// Note the layout is only valid for the same architecture and version of
+    /// this crate, see [`Self::serialize_into`] for a format that can be
+    /// validated by the receiver.
     pub fn as_bytes(&self) -> &[u8] {
         unsafe {
             let size = std::mem::size_of_val(self);
@@ -47,12 +224,215 @@ impl CrashContext {
         }
     }
 
+    /// Reinterprets the bytes retrieved via [`Self::as_bytes`] as a context,
+    /// returning `None` if the length or [`Self::reason`] is invalid.
+    ///
+    /// See [`Self::deserialize`] for a format that can be validated.
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         if bytes.len() != std::mem::size_of::<Self>() {
             return None;
         }
 
-        unsafe { Some((*bytes.as_ptr().cast::<Self>()).clone()) }
+        unsafe {
+            let ctx = bytes.as_ptr().cast::<Self>();
+
+            // The reason is an enum, so ensure its discriminant is actually
+            // valid before we materialize the context from the raw bytes
+            let reason = std::ptr::addr_of!((*ctx).reason)
+                .cast::<u32>()
+                .read_unaligned();
+            CrashReason::from_raw(reason)?;
+
+            Some((*ctx).clone())
+        }
+    }
+
+    /// Retrieves the name of the crashing thread, if it had one
+    #[inline]
+    pub fn thread_name(&self) -> Option<&str> {
+        let len = self
+            .thread_name
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(THREAD_NAME_LEN);
+
+        if len == 0 {
+            return None;
+        }
+
+        std::str::from_utf8(&self.thread_name[..len]).ok()
+    }
+
+    /// Retrieves the message describing why the process aborted, if there
+    /// was one, without the trailing newline, and without the last character
+    /// if it was cut in half when the message was truncated
+    #[inline]
+    pub fn abort_message(&self) -> Option<&str> {
+        let len = self
+            .abort_message
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(ABORT_MESSAGE_LEN);
+
+        let message = match std::str::from_utf8(&self.abort_message[..len]) {
+            Ok(message) => message,
+            Err(err) => std::str::from_utf8(&self.abort_message[..err.valid_up_to()]).ok()?,
+        }
+        .trim_end();
+
+        if message.is_empty() {
+            return None;
+        }
+
+        Some(message)
+    }
+
+    /// Fills out [`Self::thread_name`] with the name of the calling thread.
+    ///
+    /// This is async signal safe, as it only reads the name via `prctl`.
+    #[inline]
+    pub fn capture_thread_name(&mut self) {
+        self.thread_name = [0; THREAD_NAME_LEN];
+        // SAFETY: syscall, the buffer is `TASK_COMM_LEN` as required, though
+        // the kernel will always nul terminate it, we keep the last byte for
+        // the terminator in case it didn't
+        unsafe {
+            libc::prctl(libc::PR_GET_NAME, self.thread_name.as_mut_ptr(), 0, 0, 0);
+        }
+        self.thread_name[THREAD_NAME_LEN - 1] = 0;
+    }
+
+    /// Fills out [`Self::time`] with the current time.
+    ///
+    /// This is async signal safe, as it only uses `clock_gettime`.
+    #[inline]
+    pub fn capture_time(&mut self) {
+        self.time = crate::CrashTime::now();
+    }
+
+    /// Retrieves the floating point state of the crashing thread, if it was
+    /// captured.
+    ///
+    /// On `aarch64` and `arm` the state is identified by the magic value the
+    /// kernel writes in its header, while on `x86` and `x86_64` the control
+    /// register, which is never 0 in practice, is used to determine whether
+    /// the state was filled out. On `riscv64` the state is always available.
+    ///
+    /// Note that [`Self::capture`] does not save the VFP state on `arm`, as
+    /// the instructions to do so are not available on every `arm` target.
+    #[inline]
+    pub fn float_state(&self) -> Option<&fpregset_t> {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                let captured = self.float_state.mxcsr != 0;
+            } else if #[cfg(target_arch = "x86")] {
+                let captured = self.float_state.cw != 0;
+            } else if #[cfg(target_arch = "aarch64")] {
+                let captured = self.float_state.head.magic == FPSIMD_MAGIC;
+            } else if #[cfg(target_arch = "arm")] {
+                let captured = self.float_state.magic == VFP_MAGIC;
+            } else if #[cfg(target_arch = "riscv64")] {
+                // The floating point state is always part of the context
+                let captured = true;
+            }
+        }
+
+        if captured {
+            Some(&self.float_state)
+        } else {
+            None
+        }
+    }
+
+    /// Fills out [`Self::float_state`] from the floating point state
+    /// referenced by [`Self::context`], leaving it untouched if there is none.
+    ///
+    /// This is async signal safe.
+    ///
+    /// # Safety
+    ///
+    /// On `x86` and `x86_64`, [`mcontext_t::fpregs`] must be null or point to
+    /// valid memory in the current process, ie. the context must have been
+    /// received by a signal handler, or filled out by
+    /// [`crate::crash_context_getcontext`], in the current process.
+    pub unsafe fn capture_float_state(&mut self) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "aarch64")] {
+                // Both the kernel and our getcontext write the fpsimd context
+                // at the beginning of the reserved space
+                let fp_ptr = self.context.uc_mcontext.__reserved.as_ptr().cast::<fpsimd_context>();
+
+                if (*fp_ptr).head.magic == FPSIMD_MAGIC {
+                    std::ptr::copy_nonoverlapping(fp_ptr, &mut self.float_state, 1);
+                }
+            } else if #[cfg(target_arch = "arm")] {
+                // The kernel writes a variable sequence of coprocessor frames,
+                // eg. iWMMXt, before the VFP frame, each with a magic and size
+                // header, and terminated by a magic of 0
+                let regspace = &self.context.uc_regspace;
+                let len = std::mem::size_of_val(regspace);
+                let base = regspace.as_ptr().cast::<u8>();
+                let mut offset = 0;
+
+                while offset + std::mem::size_of::<vfp_sigframe>() <= len {
+                    let frame = base.add(offset);
+                    let magic = frame.cast::<u32>().read_unaligned();
+                    let size = frame.add(4).cast::<u32>().read_unaligned() as usize;
+
+                    if magic == VFP_MAGIC {
+                        self.float_state = frame.cast::<vfp_sigframe>().read_unaligned();
+                        break;
+                    }
+
+                    if magic == 0 || size == 0 {
+                        break;
+                    }
+
+                    offset += size;
+                }
+            } else if #[cfg(target_arch = "riscv64")] {
+                self.float_state = self.context.uc_mcontext.__fpregs.clone();
+            } else {
+                if !self.context.uc_mcontext.fpregs.is_null() {
+                    std::ptr::copy_nonoverlapping(self.context.uc_mcontext.fpregs, &mut self.float_state, 1);
+                }
+            }
+        }
+    }
+
+    /// Captures the context of the current thread, without an actual signal
+    /// having been raised.
+    ///
+    /// This is useful for creating "requested" dumps, eg. on an assertion
+    /// failure, via the same pipeline used for actual crashes. The
+    /// [`Self::siginfo`] will have a signal number of 0 and a code of
+    /// `SI_USER`.
+    pub fn capture() -> Self {
+        // SAFETY: every field is plain old data for which all zeroes is valid,
+        // including the reason which is `CrashReason::Signal`
+        let mut cc: Self = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the context is valid to write to, and the function can't fail
+        unsafe {
+            crate::crash_context_getcontext(&mut cc.context);
+        }
+
+        // SAFETY: our getcontext points fpregs to the floating point state it
+        // saved inside of the context itself
+        unsafe {
+            cc.capture_float_state();
+        }
+
+        cc.pid = std::process::id() as i32;
+        // SAFETY: syscall
+        cc.tid = unsafe { libc::syscall(libc::SYS_gettid) } as i32;
+        cc.capture_thread_name();
+        cc.capture_time();
+        cc.cpu = CpuInfo::capture();
+        cc.siginfo.ssi_code = libc::SI_USER;
+        cc.siginfo.ssi_pid = cc.pid as u32;
+
+        cc
     }
 }
 
@@ -215,6 +595,19 @@ cfg_if::cfg_if! {
 
         #[doc(hidden)]
         pub type fpregset_t = fpsimd_context;
+
+        /// Magic value written by the kernel for the exception syndrome
+        /// register, which is only present for faults
+        #[doc(hidden)]
+        pub const ESR_MAGIC: u32 = 0x45535201;
+
+        #[repr(C)]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct esr_context {
+            pub head: _aarch64_ctx,
+            pub esr: u64,
+        }
     } else if #[cfg(target_arch = "arm")] {
         #[repr(C)]
         #[derive(Clone)]
@@ -256,6 +649,79 @@ cfg_if::cfg_if! {
             pub arm_cpsr: u32,
             pub fault_address: u32,
         }
+
+        /// Magic value written by the kernel for the VFP coprocessor frame in
+        /// `ucontext_t::uc_regspace`
+        #[doc(hidden)]
+        pub const VFP_MAGIC: u32 = 0x56465001;
+
+        #[repr(C)]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct user_vfp {
+            /// The 32 double precision registers, which alias the 16 NEON
+            /// quad registers
+            pub fpregs: [u64; 32],
+            pub fpscr: u32,
+        }
+
+        #[repr(C)]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct user_vfp_exc {
+            pub fpexc: u32,
+            pub fpinst: u32,
+            pub fpinst2: u32,
+        }
+
+        #[repr(C, align(8))]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct vfp_sigframe {
+            pub magic: u32,
+            pub size: u32,
+            pub ufp: user_vfp,
+            pub ufp_exc: user_vfp_exc,
+        }
+
+        #[doc(hidden)]
+        pub type fpregset_t = vfp_sigframe;
+    } else if #[cfg(target_arch = "riscv64")] {
+        #[repr(C)]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct ucontext_t {
+            pub uc_flags: u64,
+            uc_link: *mut ucontext_t,
+            pub uc_stack: stack_t,
+            // The kernel only uses the first 8 bytes, but reserves space for
+            // a sigset_t that can be extended to 1024 signals
+            pub uc_sigmask: sigset_t,
+            pub uc_mcontext: mcontext_t,
+        }
+
+        #[repr(C, align(16))]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct mcontext_t {
+            /// The pc, followed by x1-x31, ie. `REG_PC` is 0, `REG_RA` is 1,
+            /// and `REG_SP` is 2
+            pub __gregs: [u64; 32],
+            pub __fpregs: fpregset_t,
+        }
+
+        /// The `__riscv_mc_fp_state` union, viewed as the D extension state,
+        /// which is what the kernel writes on every `riscv64` target Rust
+        /// supports
+        #[repr(C, align(16))]
+        #[derive(Clone)]
+        #[doc(hidden)]
+        pub struct fpregset_t {
+            pub f: [u64; 32],
+            pub fcsr: u32,
+            // The remainder of the union, which is sized for the Q extension
+            __reserved: [u32; 67],
+        }
     }
 }
 
@@ -270,4 +736,64 @@ mod test {
             std::mem::size_of::<super::ucontext_t>()
         );
     }
+
+    #[cfg(target_arch = "arm")]
+    #[test]
+    fn vfp_sigframe_matches_kernel() {
+        // VFP_STORAGE_SIZE in arch/arm/include/asm/ucontext.h
+        assert_eq!(std::mem::size_of::<super::vfp_sigframe>(), 0x120);
+    }
+
+    #[test]
+    fn captures_current_thread() {
+        let cc = super::CrashContext::capture();
+
+        assert_eq!(cc.pid, std::process::id() as i32);
+        // SAFETY: syscall
+        assert_eq!(cc.tid, unsafe { libc::syscall(libc::SYS_gettid) } as i32);
+        assert_eq!(cc.siginfo.ssi_signo, 0);
+        assert_eq!(cc.reason, super::CrashReason::Signal);
+        #[cfg(not(target_arch = "arm"))]
+        assert!(cc.float_state().is_some());
+        assert_ne!(cc.time.monotonic, 0);
+        assert!(cc.time.wall_time().unwrap() <= std::time::SystemTime::now());
+
+        // The context should roundtrip like any other
+        assert!(super::CrashContext::from_bytes(cc.as_bytes()).is_some());
+    }
+
+    #[test]
+    fn captures_thread_name() {
+        let cc = std::thread::Builder::new()
+            .name("a-very-long-thread-name".to_owned())
+            .spawn(super::CrashContext::capture)
+            .unwrap()
+            .join()
+            .unwrap();
+
+        // The kernel truncates the name
+        assert_eq!(cc.thread_name(), Some("a-very-long-thr"));
+    }
+
+    #[test]
+    fn abort_message() {
+        let mut cc = super::CrashContext::capture();
+        assert_eq!(cc.abort_message(), None);
+
+        let message = b"app: main.c:42: main: Assertion `x == 1' failed.\n";
+        cc.abort_message[..message.len()].copy_from_slice(message);
+        assert_eq!(
+            cc.abort_message(),
+            Some("app: main.c:42: main: Assertion `x == 1' failed.")
+        );
+
+        // A character cut in half by truncation is dropped
+        let mut message = [b'a'; super::ABORT_MESSAGE_LEN];
+        message[super::ABORT_MESSAGE_LEN - 2..].copy_from_slice(&[0xc3, 0]);
+        cc.abort_message = message;
+        assert_eq!(
+            cc.abort_message().map(str::len),
+            Some(super::ABORT_MESSAGE_LEN - 2)
+        );
+    }
 }
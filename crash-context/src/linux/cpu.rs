@@ -0,0 +1,174 @@
+//! The features of the CPU the crashing process was running on.
+//!
+//! A `SIGILL` is often not caused by corrupted code, but by the binary using
+//! an instruction set extension, eg. AVX2, that the CPU it was deployed to
+//! doesn't support, which can't be told apart without knowing the CPU. As
+//! the features don't change while the process is running, they are meant to
+//! be [captured](CpuInfo::capture) once, eg. when the crash handler is
+//! attached, rather than at the time of the crash.
+
+/// The features of the CPU, see [`crate::CrashContext::cpu`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CpuInfo {
+    /// `getauxval(AT_HWCAP)`, the architecture specific feature bits the
+    /// kernel reports, eg. `HWCAP_ASIMD` on `aarch64`
+    pub hwcap: u64,
+    /// `getauxval(AT_HWCAP2)`, the architecture specific feature bits that
+    /// didn't fit in [`Self::hwcap`]
+    pub hwcap2: u64,
+    /// The vendor id, eg. `GenuineIntel`, from `cpuid` leaf 0 on `x86` and
+    /// `x86_64`, all zeroes on other architectures
+    pub cpuid_vendor: [u8; 12],
+    /// `eax` of `cpuid` leaf 1, the processor signature, on `x86` and
+    /// `x86_64`, see [`Self::family`], [`Self::model`], and [`Self::stepping`]
+    pub cpuid_signature: u32,
+    /// The feature bits of `cpuid` leaf 1 `ecx` and `edx`, followed by leaf 7
+    /// `ebx` and `ecx`, on `x86` and `x86_64`, which cover everything from
+    /// SSE to AVX-512
+    pub cpuid_features: [u32; 4],
+}
+
+impl CpuInfo {
+    /// Captures the features of the CPU the current process is running on.
+    ///
+    /// This is async signal safe, as it only uses `getauxval` and `cpuid`.
+    pub fn capture() -> Self {
+        let mut info = Self {
+            // SAFETY: getauxval only reads the auxiliary vector
+            hwcap: unsafe { libc::getauxval(libc::AT_HWCAP) } as u64,
+            // SAFETY: as above
+            hwcap2: unsafe { libc::getauxval(libc::AT_HWCAP2) } as u64,
+            ..Self::default()
+        };
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+                #[cfg(target_arch = "x86")]
+                use std::arch::x86::{__cpuid, __cpuid_count};
+                #[cfg(target_arch = "x86_64")]
+                use std::arch::x86_64::{__cpuid, __cpuid_count};
+
+                // cpuid is unsafe in older versions of std, as it isn't
+                // available on very old 32-bit CPUs that Rust doesn't support
+                #[allow(unused_unsafe)]
+                // SAFETY: every CPU Rust supports has cpuid
+                let (leaf0, leaf1) = unsafe { (__cpuid(0), __cpuid(1)) };
+
+                info.cpuid_vendor[..4].copy_from_slice(&leaf0.ebx.to_le_bytes());
+                info.cpuid_vendor[4..8].copy_from_slice(&leaf0.edx.to_le_bytes());
+                info.cpuid_vendor[8..].copy_from_slice(&leaf0.ecx.to_le_bytes());
+                info.cpuid_signature = leaf1.eax;
+                info.cpuid_features[0] = leaf1.ecx;
+                info.cpuid_features[1] = leaf1.edx;
+
+                if leaf0.eax >= 7 {
+                    #[allow(unused_unsafe)]
+                    // SAFETY: the leaf is supported
+                    let leaf7 = unsafe { __cpuid_count(7, 0) };
+                    info.cpuid_features[2] = leaf7.ebx;
+                    info.cpuid_features[3] = leaf7.ecx;
+                }
+            }
+        }
+
+        info
+    }
+
+    /// Whether the info was captured, as every field is zero if it wasn't
+    #[inline]
+    pub fn is_captured(&self) -> bool {
+        *self != Self::default()
+    }
+
+    /// The [vendor id](Self::cpuid_vendor) as a string, if there is one
+    #[inline]
+    pub fn vendor(&self) -> Option<&str> {
+        let len = self
+            .cpuid_vendor
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.cpuid_vendor.len());
+
+        if len == 0 {
+            return None;
+        }
+
+        std::str::from_utf8(&self.cpuid_vendor[..len]).ok()
+    }
+
+    /// The family from the [processor signature](Self::cpuid_signature),
+    /// including the extended family
+    #[inline]
+    pub fn family(&self) -> u32 {
+        let family = (self.cpuid_signature >> 8) & 0xf;
+        if family == 0xf {
+            family + ((self.cpuid_signature >> 20) & 0xff)
+        } else {
+            family
+        }
+    }
+
+    /// The model from the [processor signature](Self::cpuid_signature),
+    /// including the extended model
+    #[inline]
+    pub fn model(&self) -> u32 {
+        let model = (self.cpuid_signature >> 4) & 0xf;
+        let family = (self.cpuid_signature >> 8) & 0xf;
+        if family == 0x6 || family == 0xf {
+            model | ((self.cpuid_signature >> 12) & 0xf0)
+        } else {
+            model
+        }
+    }
+
+    /// The stepping from the [processor signature](Self::cpuid_signature)
+    #[inline]
+    pub fn stepping(&self) -> u32 {
+        self.cpuid_signature & 0xf
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_signature() {
+        let info = CpuInfo {
+            cpuid_vendor: *b"GenuineIntel",
+            // Skylake-SP
+            cpuid_signature: 0x0005_0654,
+            ..CpuInfo::default()
+        };
+
+        assert_eq!(info.vendor(), Some("GenuineIntel"));
+        assert_eq!((info.family(), info.model(), info.stepping()), (6, 0x55, 4));
+
+        let info = CpuInfo {
+            // Zen 2
+            cpuid_signature: 0x0083_0f10,
+            ..CpuInfo::default()
+        };
+
+        assert_eq!(info.vendor(), None);
+        assert_eq!(
+            (info.family(), info.model(), info.stepping()),
+            (0x17, 0x31, 0)
+        );
+    }
+
+    #[test]
+    fn captures_current_cpu() {
+        let info = CpuInfo::capture();
+        assert!(info.is_captured());
+
+        #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+        {
+            assert!(info.vendor().is_some());
+            // SSE2 is part of the x86_64 baseline, and required by Rust's
+            // i686 targets
+            assert_ne!(info.cpuid_features[1] & (1 << 26), 0);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Decoding of memory access faults.
+//!
+//! Whether a fault was caused by a read, write, or instruction fetch is not
+//! part of the `siginfo_t`, but is recorded by the kernel in an architecture
+//! specific location in the thread context, so [`CrashContext::fault`] decodes
+//! it so that consumers can eg. distinguish a null pointer write from a wild
+//! read without knowledge of every architecture.
+
+use super::CrashContext;
+
+/// Faulting addresses below this are considered to be null pointer
+/// dereferences, ie. a null pointer plus a field or array offset.
+///
+/// This is the default `vm.mmap_min_addr`, below which nothing can be mapped.
+pub const NULL_ADDRESS_LIMIT: u64 = 0x10000;
+
+/// The kind of memory access that caused a fault
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccessType {
+    /// A read of data
+    Read,
+    /// A write of data
+    Write,
+    /// An instruction fetch, eg. a jump to an invalid or non-executable
+    /// address
+    Execute,
+    /// The kind of access could not be determined, either because the
+    /// architecture doesn't record it, or the fault was not a page fault
+    Unknown,
+}
+
+/// A decoded memory access fault, see [`CrashContext::fault`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FaultInfo {
+    /// The address that was accessed, ie. `si_addr`
+    pub address: u64,
+    /// The kind of access to the address
+    pub access: AccessType,
+}
+
+impl FaultInfo {
+    /// Returns true if the address is below [`NULL_ADDRESS_LIMIT`], ie. the
+    /// fault was most likely caused by dereferencing a null pointer
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        self.address < NULL_ADDRESS_LIMIT
+    }
+}
+
+impl CrashContext {
+    /// Decodes the faulting address and the kind of access that caused it,
+    /// if the crash was a `SIGSEGV` or `SIGBUS` raised by the kernel.
+    ///
+    /// The access type is determined from the page fault error code on `x86`
+    /// and `x86_64`, the fault status register on `arm`, and the exception
+    /// syndrome register on `aarch64`. It is always [`AccessType::Unknown`]
+    /// on `riscv64`, as the kernel doesn't expose the cause of the fault.
+    pub fn fault(&self) -> Option<FaultInfo> {
+        let signo = self.siginfo.ssi_signo as i32;
+
+        // Signals sent by a process don't have an address
+        if (signo != libc::SIGSEGV && signo != libc::SIGBUS) || self.siginfo.ssi_code <= 0 {
+            return None;
+        }
+
+        Some(FaultInfo {
+            address: self.siginfo.ssi_addr,
+            access: self.access_type(),
+        })
+    }
+
+    fn access_type(&self) -> AccessType {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+                /// The trap number of a page fault
+                const PAGE_FAULT: i64 = 14;
+                /// Set if the access was a write
+                const PF_WRITE: i64 = 1 << 1;
+                /// Set if the access was an instruction fetch
+                const PF_INSTR: i64 = 1 << 4;
+
+                let gregs = &self.context.uc_mcontext.gregs;
+
+                cfg_if::cfg_if! {
+                    if #[cfg(target_arch = "x86_64")] {
+                        let (trapno, err) = (gregs[libc::REG_TRAPNO as usize], gregs[libc::REG_ERR as usize]);
+                    } else {
+                        // REG_TRAPNO and REG_ERR, which libc doesn't define for musl
+                        let (trapno, err) = (gregs[12], gregs[13]);
+                    }
+                }
+
+                if trapno != PAGE_FAULT {
+                    AccessType::Unknown
+                } else if err & PF_INSTR != 0 {
+                    AccessType::Execute
+                } else if err & PF_WRITE != 0 {
+                    AccessType::Write
+                } else {
+                    AccessType::Read
+                }
+            } else if #[cfg(target_arch = "aarch64")] {
+                /// Exception classes for instruction aborts from a lower and
+                /// the same exception level
+                const EC_IABT: [u64; 2] = [0x20, 0x21];
+                /// Exception class for a misaligned pc
+                const EC_PC_ALIGN: u64 = 0x22;
+                /// Exception classes for data aborts from a lower and the same
+                /// exception level
+                const EC_DABT: [u64; 2] = [0x24, 0x25];
+                /// Set in the ISS of a data abort if the access was a write
+                const ESR_WNR: u64 = 1 << 6;
+
+                let esr = match self.esr() {
+                    Some(esr) => esr,
+                    None => return AccessType::Unknown,
+                };
+
+                let ec = (esr >> 26) & 0x3f;
+                if EC_IABT.contains(&ec) || ec == EC_PC_ALIGN {
+                    AccessType::Execute
+                } else if !EC_DABT.contains(&ec) {
+                    AccessType::Unknown
+                } else if esr & ESR_WNR != 0 {
+                    AccessType::Write
+                } else {
+                    AccessType::Read
+                }
+            } else if #[cfg(target_arch = "arm")] {
+                /// The trap number the kernel records for page faults
+                const PAGE_FAULT: u32 = 14;
+                /// Set in the FSR if the access was a write
+                const FSR_WRITE: u32 = 1 << 11;
+                /// Set by the kernel in the FSR for prefetch aborts
+                const FSR_LNX_PF: u32 = 1 << 31;
+
+                let mc = &self.context.uc_mcontext;
+
+                if mc.trap_no != PAGE_FAULT {
+                    AccessType::Unknown
+                } else if mc.error_code & FSR_LNX_PF != 0 {
+                    AccessType::Execute
+                } else if mc.error_code & FSR_WRITE != 0 {
+                    AccessType::Write
+                } else {
+                    AccessType::Read
+                }
+            } else {
+                AccessType::Unknown
+            }
+        }
+    }
+
+    /// Finds the exception syndrome register the kernel writes into the
+    /// reserved space of the context for faults
+    #[cfg(target_arch = "aarch64")]
+    fn esr(&self) -> Option<u64> {
+        let reserved = &self.context.uc_mcontext.__reserved;
+        let len = std::mem::size_of_val(reserved);
+        let base = reserved.as_ptr().cast::<u8>();
+        let mut offset = 0;
+
+        while offset + std::mem::size_of::<super::esr_context>() <= len {
+            // SAFETY: we've checked the record header is within the reserved
+            // space, and every record is at least the size of esr_context
+            let (magic, size) = unsafe {
+                let head = base
+                    .add(offset)
+                    .cast::<super::_aarch64_ctx>()
+                    .read_unaligned();
+                (head.magic, head.size as usize)
+            };
+
+            if magic == super::ESR_MAGIC {
+                // SAFETY: the magic identifies the record as an esr_context
+                let esr = unsafe {
+                    base.add(offset)
+                        .cast::<super::esr_context>()
+                        .read_unaligned()
+                };
+                return Some(esr.esr);
+            }
+
+            if magic == 0 || size == 0 {
+                break;
+            }
+
+            offset += size;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fault_context(signo: i32, address: u64) -> CrashContext {
+        // SAFETY: every field is plain old data for which all zeroes is valid
+        let mut cc: CrashContext = unsafe { std::mem::zeroed() };
+        cc.siginfo.ssi_signo = signo as u32;
+        cc.siginfo.ssi_code = 1; // SEGV_MAPERR
+        cc.siginfo.ssi_addr = address;
+        cc
+    }
+
+    #[test]
+    fn ignores_user_signals() {
+        let mut cc = fault_context(libc::SIGSEGV, 0);
+        cc.siginfo.ssi_code = libc::SI_USER;
+        assert!(cc.fault().is_none());
+
+        assert!(fault_context(libc::SIGABRT, 0).fault().is_none());
+    }
+
+    #[test]
+    fn detects_null() {
+        let fault = fault_context(libc::SIGSEGV, 0x18).fault().unwrap();
+        assert!(fault.is_null());
+        assert_eq!(fault.address, 0x18);
+
+        assert!(!fault_context(libc::SIGBUS, 0xdead_0000)
+            .fault()
+            .unwrap()
+            .is_null());
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn decodes_page_fault_error_code() {
+        let decode = |trapno: i64, err: i64| {
+            let mut cc = fault_context(libc::SIGSEGV, 0);
+            cc.context.uc_mcontext.gregs[libc::REG_TRAPNO as usize] = trapno;
+            cc.context.uc_mcontext.gregs[libc::REG_ERR as usize] = err;
+            cc.fault().unwrap().access
+        };
+
+        assert_eq!(decode(14, 0x4), AccessType::Read);
+        assert_eq!(decode(14, 0x6), AccessType::Write);
+        assert_eq!(decode(14, 0x15), AccessType::Execute);
+        // General protection fault, eg. a non-canonical address
+        assert_eq!(decode(13, 0), AccessType::Unknown);
+    }
+}
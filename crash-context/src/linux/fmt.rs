@@ -0,0 +1,375 @@
+//! Rendering of a [`CrashContext`] as a human readable report.
+//!
+//! The report contains the crashing process and thread, the signal and the
+//! reason for the crash, the decoded fault or seccomp violation, if any, and
+//! the general purpose registers of the crashing thread, eg.
+//!
+//! ```text
+//! Crash in process 1234, thread 1240 "worker"
+//! Signal: SIGSEGV (11), code 1
+//! Reason: Signal
+//! Fault: Write at 0x0000000000000010 (null pointer)
+//! Registers:
+//!   r8  0x0000000000000000  r9  0x00007ffc3a4b2c10  r10 0x0000000000000008  r11 0x0000000000000246
+//!   ...
+//! ```
+//!
+//! Rendering only uses [`core::fmt`], so [`write_report`], which formats the
+//! report into a buffer on the stack, can be used to write it to eg. `stderr`
+//! from within a crash handler, while [`Report`] can be used to write it
+//! anywhere else, eg. to a log file in a process handling the crash.
+
+use super::CrashContext;
+use std::{fmt, os::unix::io::RawFd};
+
+/// The number of registers written on each line of the report
+const REGISTERS_PER_LINE: usize = 4;
+
+/// Renders the [`CrashContext`] it was created from via its [`fmt::Display`]
+/// implementation, see [`CrashContext::report`]
+pub struct Report<'cc> {
+    cc: &'cc CrashContext,
+}
+
+impl CrashContext {
+    /// Creates a human readable report of the crash, which can be written via
+    /// its [`fmt::Display`] implementation, or [`write_report`]
+    #[inline]
+    pub fn report(&self) -> Report<'_> {
+        Report { cc: self }
+    }
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cc = self.cc;
+
+        write!(f, "Crash in process {}, thread {}", cc.pid, cc.tid)?;
+        if let Some(name) = cc.thread_name() {
+            write!(f, " \"{name}\"")?;
+        }
+        writeln!(f)?;
+
+        let signo = cc.siginfo.ssi_signo as i32;
+        writeln!(
+            f,
+            "Signal: {} ({signo}), code {}",
+            signal_name(signo),
+            cc.siginfo.ssi_code
+        )?;
+        writeln!(f, "Reason: {:?}", cc.reason)?;
+
+        if let Some(message) = cc.abort_message() {
+            writeln!(f, "Abort message: {message}")?;
+        }
+
+        if let Some(fault) = cc.fault() {
+            write!(f, "Fault: {:?} at {:#018x}", fault.access, fault.address)?;
+            if fault.is_null() {
+                f.write_str(" (null pointer)")?;
+            }
+            writeln!(f)?;
+        }
+
+        if let Some(violation) = cc.seccomp_violation() {
+            writeln!(
+                f,
+                "Seccomp: syscall {} (arch {:#x}) at {:#018x}",
+                violation.syscall, violation.arch, violation.call_addr
+            )?;
+        }
+
+        if let Some(wall) = cc.time.wall_time() {
+            if let Ok(since_epoch) = wall.duration_since(std::time::UNIX_EPOCH) {
+                writeln!(
+                    f,
+                    "Time: {}.{:09} since the unix epoch",
+                    since_epoch.as_secs(),
+                    since_epoch.subsec_nanos()
+                )?;
+            }
+        }
+
+        let cpu = &cc.cpu;
+        if let Some(vendor) = cpu.vendor() {
+            writeln!(
+                f,
+                "CPU: {vendor} family {:#x} model {:#x} stepping {}, features {:08x} {:08x} {:08x} {:08x}",
+                cpu.family(),
+                cpu.model(),
+                cpu.stepping(),
+                cpu.cpuid_features[0],
+                cpu.cpuid_features[1],
+                cpu.cpuid_features[2],
+                cpu.cpuid_features[3]
+            )?;
+        } else if cpu.is_captured() {
+            writeln!(f, "CPU: hwcap {:#x}, hwcap2 {:#x}", cpu.hwcap, cpu.hwcap2)?;
+        }
+
+        let memory = &cc.memory;
+        if memory.is_captured() {
+            writeln!(
+                f,
+                "Memory: {} KiB virtual, {} KiB resident, {} KiB swapped",
+                memory.size / 1024,
+                memory.resident / 1024,
+                memory.swap / 1024
+            )?;
+        }
+
+        f.write_str("Registers:")?;
+        let mut result = Ok(());
+        let mut i = 0;
+        cc.for_each_register(|name, value| {
+            if result.is_err() {
+                return;
+            }
+
+            result = if i % REGISTERS_PER_LINE == 0 {
+                write!(f, "\n  {name:<3} {value:#018x}")
+            } else {
+                write!(f, "  {name:<3} {value:#018x}")
+            };
+            i += 1;
+        });
+        result?;
+        writeln!(f)
+    }
+}
+
+/// Writes the [report](CrashContext::report) for the crash to the file
+/// descriptor, eg. `libc::STDERR_FILENO`.
+///
+/// The report is formatted into a fixed size buffer on the stack, which is
+/// written whenever it is full, so this neither allocates nor takes any
+/// locks, and is thus safe to call from a signal handler.
+///
+/// # Errors
+///
+/// An error is returned if writing to the file descriptor fails
+pub fn write_report(cc: &CrashContext, fd: RawFd) -> std::io::Result<()> {
+    let mut w = FdWriter {
+        fd,
+        buf: [0; 1024],
+        len: 0,
+        error: None,
+    };
+
+    let res = fmt::Write::write_fmt(&mut w, format_args!("{}", cc.report()));
+    if let Some(err) = w.error.take() {
+        return Err(err);
+    }
+    res.map_err(|_err| std::io::Error::from(std::io::ErrorKind::Other))?;
+
+    w.flush()
+}
+
+/// Buffers formatted output on the stack, writing it to a file descriptor
+/// whenever the buffer is full
+struct FdWriter {
+    fd: RawFd,
+    buf: [u8; 1024],
+    len: usize,
+    /// The error that caused formatting to fail, as [`fmt::Error`] can't
+    /// carry one
+    error: Option<std::io::Error>,
+}
+
+impl FdWriter {
+    fn flush(&mut self) -> std::io::Result<()> {
+        let mut written = 0;
+        while written < self.len {
+            // SAFETY: syscall, the buffer is valid for the remaining length
+            let rv = unsafe {
+                libc::write(
+                    self.fd,
+                    self.buf[written..self.len].as_ptr().cast(),
+                    self.len - written,
+                )
+            };
+
+            if rv < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    self.len = 0;
+                    return Err(err);
+                }
+            } else if rv == 0 {
+                self.len = 0;
+                return Err(std::io::ErrorKind::WriteZero.into());
+            } else {
+                written += rv as usize;
+            }
+        }
+
+        self.len = 0;
+        Ok(())
+    }
+}
+
+impl fmt::Write for FdWriter {
+    fn write_str(&mut self, mut s: &str) -> fmt::Result {
+        while !s.is_empty() {
+            if self.len == self.buf.len() {
+                if let Err(err) = self.flush() {
+                    self.error = Some(err);
+                    return Err(fmt::Error);
+                }
+            }
+
+            let chunk = s.len().min(self.buf.len() - self.len);
+            self.buf[self.len..self.len + chunk].copy_from_slice(&s.as_bytes()[..chunk]);
+            self.len += chunk;
+            s = &s[chunk..];
+        }
+
+        Ok(())
+    }
+}
+
+/// Retrieves the name of the signal, or `"unknown"` if it's not one that is
+/// normally associated with a crash
+pub fn signal_name(signo: i32) -> &'static str {
+    match signo {
+        0 => "none",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGILL => "SIGILL",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGSYS => "SIGSYS",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGINT => "SIGINT",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        _ => "unknown",
+    }
+}
+
+impl CrashContext {
+    /// Invokes the callback with the name and value of each of the general
+    /// purpose registers of the crashing thread
+    pub(super) fn for_each_register(&self, mut cb: impl FnMut(&'static str, u64)) {
+        let mc = &self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                const NAMES: [&str; 18] = [
+                    "r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15", "rdi", "rsi", "rbp",
+                    "rbx", "rdx", "rax", "rcx", "rsp", "rip", "efl",
+                ];
+
+                for (name, value) in NAMES.iter().zip(&mc.gregs) {
+                    cb(name, *value as u64);
+                }
+            } else if #[cfg(target_arch = "x86")] {
+                const NAMES: [(&str, i32); 10] = [
+                    ("edi", libc::REG_EDI), ("esi", libc::REG_ESI), ("ebp", libc::REG_EBP),
+                    ("esp", libc::REG_ESP), ("ebx", libc::REG_EBX), ("edx", libc::REG_EDX),
+                    ("ecx", libc::REG_ECX), ("eax", libc::REG_EAX), ("eip", libc::REG_EIP),
+                    ("efl", libc::REG_EFL),
+                ];
+
+                for (name, reg) in NAMES {
+                    cb(name, mc.gregs[reg as usize] as u32 as u64);
+                }
+            } else if #[cfg(target_arch = "aarch64")] {
+                const NAMES: [&str; 31] = [
+                    "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11",
+                    "x12", "x13", "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22",
+                    "x23", "x24", "x25", "x26", "x27", "x28", "fp", "lr",
+                ];
+
+                for (name, value) in NAMES.iter().zip(&mc.regs) {
+                    cb(name, *value);
+                }
+                cb("sp", mc.sp);
+                cb("pc", mc.pc);
+                cb("pst", mc.pstate);
+            } else if #[cfg(target_arch = "arm")] {
+                let regs = [
+                    ("r0", mc.arm_r0), ("r1", mc.arm_r1), ("r2", mc.arm_r2), ("r3", mc.arm_r3),
+                    ("r4", mc.arm_r4), ("r5", mc.arm_r5), ("r6", mc.arm_r6), ("r7", mc.arm_r7),
+                    ("r8", mc.arm_r8), ("r9", mc.arm_r9), ("r10", mc.arm_r10), ("fp", mc.arm_fp),
+                    ("ip", mc.arm_ip), ("sp", mc.arm_sp), ("lr", mc.arm_lr), ("pc", mc.arm_pc),
+                    ("cps", mc.arm_cpsr),
+                ];
+
+                for (name, value) in regs {
+                    cb(name, u64::from(value));
+                }
+            } else if #[cfg(target_arch = "riscv64")] {
+                // REG_PC is 0, followed by x1-x31
+                const NAMES: [&str; 32] = [
+                    "pc", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2",
+                    "a3", "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9",
+                    "s10", "s11", "t3", "t4", "t5", "t6",
+                ];
+
+                for (name, value) in NAMES.iter().zip(&mc.__gregs) {
+                    cb(name, *value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn renders_report() {
+        let mut cc = CrashContext::capture();
+        cc.siginfo.ssi_signo = libc::SIGSEGV as u32;
+        cc.siginfo.ssi_code = 1;
+        cc.siginfo.ssi_addr = 0x10;
+        cc.memory = crate::MemoryStats {
+            size: 8 << 30,
+            resident: 300 << 20,
+            swap: 0,
+        };
+        cc.abort_message[..18].copy_from_slice(b"assertion failed\n\0");
+
+        let report = cc.report().to_string();
+        assert!(report.starts_with(&format!("Crash in process {}, thread {}", cc.pid, cc.tid)));
+        assert!(report.contains("Signal: SIGSEGV (11), code 1\n"));
+        assert!(report.contains("Reason: Signal\nAbort message: assertion failed\n"));
+        #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
+        assert!(report.contains(&format!("CPU: {} family ", cc.cpu.vendor().unwrap())));
+        assert!(
+            report.contains("Memory: 8388608 KiB virtual, 307200 KiB resident, 0 KiB swapped\n")
+        );
+        assert!(report.contains("(null pointer)\n"));
+        assert!(report.contains(&format!("{:#018x}", cc.instruction_pointer())));
+
+        // The report is the same when written to a file descriptor, even when
+        // it's larger than the buffer
+        let mut fds = [0; 2];
+        // SAFETY: syscall
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        write_report(&cc, fds[1]).unwrap();
+        // SAFETY: syscall
+        unsafe { libc::close(fds[1]) };
+
+        let mut written = Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            // SAFETY: syscall
+            let read = unsafe { libc::read(fds[0], buf.as_mut_ptr().cast(), buf.len()) };
+            if read <= 0 {
+                break;
+            }
+            written.extend_from_slice(&buf[..read as usize]);
+        }
+        // SAFETY: syscall
+        unsafe { libc::close(fds[0]) };
+
+        assert_eq!(String::from_utf8(written).unwrap(), report);
+    }
+}
@@ -0,0 +1,72 @@
+// GREGS_OFFSET = 176
+// FPREGS_OFFSET = 432
+// REGISTER_SIZE = 8
+
+std::arch::global_asm! {
+    ".text",
+    ".global crash_context_getcontext",
+    ".hidden crash_context_getcontext",
+    ".type crash_context_getcontext, @function",
+    ".align 2",
+    ".cfi_startproc",
+"crash_context_getcontext:",
+
+    // Place ra into the saved pc, so that the saved context will return to
+    // the caller of getcontext()
+    "sd      ra, 176(a0)", // GREGS_OFFSET + REG_PC * REGISTER_SIZE
+    "sd      ra, 184(a0)", // GREGS_OFFSET + 1 * REGISTER_SIZE
+    "sd      sp, 192(a0)", // GREGS_OFFSET + 2 * REGISTER_SIZE
+    "sd      gp, 200(a0)", // GREGS_OFFSET + 3 * REGISTER_SIZE
+    "sd      tp, 208(a0)", // GREGS_OFFSET + 4 * REGISTER_SIZE
+
+    // Save the callee saved registers s0-s11, ie. x8, x9, and x18-x27
+    "sd      s0, 240(a0)", // GREGS_OFFSET + 8 * REGISTER_SIZE
+    "sd      s1, 248(a0)", // GREGS_OFFSET + 9 * REGISTER_SIZE
+    "sd      s2, 320(a0)", // GREGS_OFFSET + 18 * REGISTER_SIZE
+    "sd      s3, 328(a0)",
+    "sd      s4, 336(a0)",
+    "sd      s5, 344(a0)",
+    "sd      s6, 352(a0)",
+    "sd      s7, 360(a0)",
+    "sd      s8, 368(a0)",
+    "sd      s9, 376(a0)",
+    "sd      s10, 384(a0)",
+    "sd      s11, 392(a0)", // GREGS_OFFSET + 27 * REGISTER_SIZE
+
+    // The saved context will return with a return value of 0 in a0
+    "sd      zero, 256(a0)", // GREGS_OFFSET + 10 * REGISTER_SIZE
+
+    // Save the callee saved floating point registers fs0-fs11, ie. f8, f9,
+    // and f18-f27
+    "fsd     fs0, 496(a0)", // FPREGS_OFFSET + 8 * REGISTER_SIZE
+    "fsd     fs1, 504(a0)", // FPREGS_OFFSET + 9 * REGISTER_SIZE
+    "fsd     fs2, 576(a0)", // FPREGS_OFFSET + 18 * REGISTER_SIZE
+    "fsd     fs3, 584(a0)",
+    "fsd     fs4, 592(a0)",
+    "fsd     fs5, 600(a0)",
+    "fsd     fs6, 608(a0)",
+    "fsd     fs7, 616(a0)",
+    "fsd     fs8, 624(a0)",
+    "fsd     fs9, 632(a0)",
+    "fsd     fs10, 640(a0)",
+    "fsd     fs11, 648(a0)", // FPREGS_OFFSET + 27 * REGISTER_SIZE
+
+    "frcsr   t0",
+    "sw      t0, 688(a0)", // FPREGS_OFFSET + 32 * REGISTER_SIZE
+
+    // Grab the signal mask
+    // rt_sigprocmask (SIG_BLOCK, NULL, &ucp->uc_sigmask, _NSIG8)
+    "addi    a2, a0, 40", // UCONTEXT_SIGMASK_OFFSET
+    "li      a0, 0", // SIG_BLOCK
+    "li      a1, 0", // NULL
+    "li      a3, 8", // _NSIG / 8
+    "li      a7, 135", // __NR_rt_sigprocmask
+    "ecall",
+
+    // Return 0 for success
+    "li      a0, 0",
+    "ret",
+
+    ".cfi_endproc",
+    ".size crash_context_getcontext, . - crash_context_getcontext",
+}
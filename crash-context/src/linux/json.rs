@@ -0,0 +1,312 @@
+//! Export of a [`CrashContext`], along with the annotations and breadcrumbs of
+//! the crashing process, as JSON, so that crashes can be ingested by log
+//! pipelines that can't process minidumps.
+//!
+//! Unlike [`crate::write_report`], this allocates, and is meant to be used
+//! outside of the signal handler, eg. in the process that received the
+//! context via [`CrashContext::deserialize`] or [`CrashContext::from_bytes`].
+
+use super::{CpuInfo, CrashContext, MemoryStats};
+use crate::{Annotation, Breadcrumb, BreadcrumbLevel, CrashTime};
+use std::fmt::Write;
+
+impl CrashContext {
+    /// Renders the crash as a single line JSON object.
+    ///
+    /// The annotations and breadcrumbs are the slots located at
+    /// [`Self::annotations`] and [`Self::breadcrumbs`] in the crashing
+    /// process, which the caller must read, eg. via `process_vm_readv`, as
+    /// they aren't part of the context itself. Slots that are empty or torn
+    /// are skipped, and breadcrumbs are ordered by their sequence. Either can
+    /// be empty if they aren't available.
+    ///
+    /// Addresses and registers are written as hex strings, as JSON numbers
+    /// can't represent every 64-bit value, while times are written in
+    /// nanoseconds, and are `null` if they weren't captured.
+    pub fn to_json(&self, annotations: &[Annotation], breadcrumbs: &[Breadcrumb]) -> String {
+        let mut json = String::with_capacity(4 * 1024);
+        // Writing to a String never fails
+        let _ = self.write_json(&mut json, annotations, breadcrumbs);
+        json
+    }
+
+    fn write_json(
+        &self,
+        w: &mut String,
+        annotations: &[Annotation],
+        breadcrumbs: &[Breadcrumb],
+    ) -> std::fmt::Result {
+        let signo = self.siginfo.ssi_signo as i32;
+
+        write!(
+            w,
+            "{{\"pid\":{},\"tid\":{},\"thread_name\":",
+            self.pid, self.tid
+        )?;
+        match self.thread_name() {
+            Some(name) => write_str(w, name)?,
+            None => w.push_str("null"),
+        }
+
+        write!(
+            w,
+            ",\"signal\":{{\"number\":{signo},\"name\":\"{}\",\"code\":{}}}",
+            crate::signal_name(signo),
+            self.siginfo.ssi_code
+        )?;
+        write!(w, ",\"reason\":\"{:?}\"", self.reason)?;
+
+        w.push_str(",\"abort_message\":");
+        match self.abort_message() {
+            Some(message) => write_str(w, message)?,
+            None => w.push_str("null"),
+        }
+
+        w.push_str(",\"fault\":");
+        match self.fault() {
+            Some(fault) => write!(
+                w,
+                "{{\"address\":\"{:#x}\",\"access\":\"{:?}\",\"null\":{}}}",
+                fault.address,
+                fault.access,
+                fault.is_null()
+            )?,
+            None => w.push_str("null"),
+        }
+
+        w.push_str(",\"seccomp\":");
+        match self.seccomp_violation() {
+            Some(violation) => write!(
+                w,
+                "{{\"syscall\":{},\"arch\":{},\"call_address\":\"{:#x}\"}}",
+                violation.syscall, violation.arch, violation.call_addr
+            )?,
+            None => w.push_str("null"),
+        }
+
+        write!(
+            w,
+            ",\"instruction_pointer\":\"{:#x}\"",
+            self.instruction_pointer()
+        )?;
+
+        for (name, time) in [
+            ("time", &self.time),
+            ("process_start", &self.process_start),
+            ("attach_time", &self.attach_time),
+        ] {
+            write!(w, ",\"{name}\":")?;
+            write_time(w, time)?;
+        }
+
+        w.push_str(",\"cpu\":");
+        write_cpu(w, &self.cpu)?;
+        w.push_str(",\"memory\":");
+        write_memory(w, &self.memory)?;
+
+        w.push_str(",\"registers\":{");
+        let mut first = true;
+        self.for_each_register(|name, value| {
+            if !first {
+                w.push(',');
+            }
+            first = false;
+            let _ = write!(w, "\"{name}\":\"{value:#x}\"");
+        });
+
+        w.push_str("},\"annotations\":{");
+        let mut first = true;
+        for (key, value) in annotations.iter().filter_map(Annotation::get) {
+            if !first {
+                w.push(',');
+            }
+            first = false;
+            write_str(w, key)?;
+            w.push(':');
+            write_str(w, value)?;
+        }
+
+        w.push_str("},\"breadcrumbs\":[");
+        let mut ordered: Vec<_> = breadcrumbs
+            .iter()
+            .filter_map(|bc| bc.get().map(|(level, message)| (bc, level, message)))
+            .collect();
+        ordered.sort_unstable_by_key(|(bc, ..)| bc.sequence);
+
+        for (i, (bc, level, message)) in ordered.into_iter().enumerate() {
+            if i > 0 {
+                w.push(',');
+            }
+
+            let level = match level {
+                BreadcrumbLevel::Debug => "debug",
+                BreadcrumbLevel::Info => "info",
+                BreadcrumbLevel::Warning => "warning",
+                BreadcrumbLevel::Error => "error",
+            };
+
+            write!(
+                w,
+                "{{\"timestamp\":{},\"level\":\"{level}\",\"message\":",
+                bc.timestamp
+            )?;
+            write_str(w, message)?;
+            w.push('}');
+        }
+        w.push_str("]}");
+
+        Ok(())
+    }
+}
+
+#[inline]
+fn write_cpu(w: &mut String, cpu: &CpuInfo) -> std::fmt::Result {
+    if !cpu.is_captured() {
+        w.push_str("null");
+        return Ok(());
+    }
+
+    write!(
+        w,
+        "{{\"hwcap\":\"{:#x}\",\"hwcap2\":\"{:#x}\",\"vendor\":",
+        cpu.hwcap, cpu.hwcap2
+    )?;
+    if let Some(vendor) = cpu.vendor() {
+        write_str(w, vendor)?;
+        let [ecx1, edx1, ebx7, ecx7] = cpu.cpuid_features;
+        write!(
+            w,
+            ",\"family\":{},\"model\":{},\"stepping\":{},\"features\":[\"{ecx1:#x}\",\"{edx1:#x}\",\"{ebx7:#x}\",\"{ecx7:#x}\"]}}",
+            cpu.family(),
+            cpu.model(),
+            cpu.stepping()
+        )
+    } else {
+        w.push_str("null}");
+        Ok(())
+    }
+}
+
+#[inline]
+fn write_memory(w: &mut String, memory: &MemoryStats) -> std::fmt::Result {
+    if !memory.is_captured() {
+        w.push_str("null");
+        return Ok(());
+    }
+
+    write!(
+        w,
+        "{{\"size\":{},\"resident\":{},\"swap\":{}}}",
+        memory.size, memory.resident, memory.swap
+    )
+}
+
+#[inline]
+fn write_time(w: &mut String, time: &CrashTime) -> std::fmt::Result {
+    if *time == CrashTime::default() {
+        w.push_str("null");
+        Ok(())
+    } else {
+        write!(
+            w,
+            "{{\"monotonic\":{},\"wall\":{}}}",
+            time.monotonic, time.wall
+        )
+    }
+}
+
+/// Writes the string as a quoted JSON string, escaping it as needed
+fn write_str(w: &mut String, s: &str) -> std::fmt::Result {
+    w.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => w.push_str("\\\""),
+            '\\' => w.push_str("\\\\"),
+            '\n' => w.push_str("\\n"),
+            '\r' => w.push_str("\\r"),
+            '\t' => w.push_str("\\t"),
+            c if c.is_control() => write!(w, "\\u{:04x}", c as u32)?,
+            c => w.push(c),
+        }
+    }
+    w.push('"');
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn annotation(key: &str, value: &str) -> Annotation {
+        let mut annotation = Annotation::EMPTY;
+        annotation.state = crate::ANNOTATION_VALID;
+        annotation.key[..key.len()].copy_from_slice(key.as_bytes());
+        annotation.key_len = key.len() as u16;
+        annotation.value[..value.len()].copy_from_slice(value.as_bytes());
+        annotation.value_len = value.len() as u16;
+        annotation
+    }
+
+    fn breadcrumb(sequence: u64, level: BreadcrumbLevel, message: &str) -> Breadcrumb {
+        let mut bc = Breadcrumb::EMPTY;
+        bc.sequence = sequence;
+        bc.timestamp = sequence * 1000;
+        bc.level = level as u8;
+        bc.message[..message.len()].copy_from_slice(message.as_bytes());
+        bc.message_len = message.len() as u16;
+        bc
+    }
+
+    #[test]
+    fn exports_json() {
+        let mut cc = CrashContext::capture();
+        cc.siginfo.ssi_signo = libc::SIGSEGV as u32;
+        cc.siginfo.ssi_code = 1;
+        cc.siginfo.ssi_addr = 0x10;
+        cc.process_start = CrashTime::default();
+        cc.cpu = CpuInfo {
+            hwcap: 0x178bfbff,
+            cpuid_vendor: *b"GenuineIntel",
+            cpuid_signature: 0x0005_0654,
+            cpuid_features: [0x7ffefbff, 0xbfebfbff, 0xd39ffffb, 0x8],
+            ..CpuInfo::default()
+        };
+
+        let annotations = [
+            annotation("version", "1.0.0"),
+            Annotation::EMPTY,
+            annotation("path", "C:\\\"quoted\"\n"),
+        ];
+        let breadcrumbs = [
+            breadcrumb(3, BreadcrumbLevel::Error, "third"),
+            Breadcrumb::EMPTY,
+            breadcrumb(2, BreadcrumbLevel::Info, "second"),
+        ];
+
+        let json = cc.to_json(&annotations, &breadcrumbs);
+
+        assert!(json.starts_with(&format!("{{\"pid\":{},\"tid\":{},", cc.pid, cc.tid)));
+        assert!(json.contains(
+            r#""signal":{"number":11,"name":"SIGSEGV","code":1},"reason":"Signal","abort_message":null,"fault":{"address":"0x10","#
+        ));
+        assert!(json.contains(&format!(
+            r#""instruction_pointer":"{:#x}""#,
+            cc.instruction_pointer()
+        )));
+        assert!(json.contains(&format!(
+            r#""time":{{"monotonic":{},"wall":{}}},"process_start":null,"#,
+            cc.time.monotonic, cc.time.wall
+        )));
+        assert!(json.contains(concat!(
+            r#""cpu":{"hwcap":"0x178bfbff","hwcap2":"0x0","vendor":"GenuineIntel","#,
+            r#""family":6,"model":85,"stepping":4,"#,
+            r#""features":["0x7ffefbff","0xbfebfbff","0xd39ffffb","0x8"]},"memory":null,"#,
+            r#""registers":{"#,
+        )));
+        assert!(json.ends_with(concat!(
+            r#""annotations":{"version":"1.0.0","path":"C:\\\"quoted\"\n"},"#,
+            r#""breadcrumbs":[{"timestamp":2000,"level":"info","message":"second"},"#,
+            r#"{"timestamp":3000,"level":"error","message":"third"}]}"#,
+        )));
+    }
+}
@@ -0,0 +1,159 @@
+//! The memory usage of the crashing process.
+//!
+//! Crashes caused by running out of memory, eg. an abort after an allocation
+//! failed, look like any other abort without knowing how much memory the
+//! process was using. The statistics are read from `/proc/<pid>/statm` and
+//! `/proc/<pid>/status`, which can't be opened from within a crash handler,
+//! so [`MemoryStats::read`] takes descriptors that were opened beforehand,
+//! and only reads them into a buffer on the stack.
+
+use std::os::unix::io::RawFd;
+
+/// The size of the buffer the files are read into, which is kept small as it
+/// is on the signal handler's alternate stack. `VmSwap` is well within the
+/// first 2KiB of `/proc/<pid>/status`, before the CPU and memory node masks
+/// that can make the file much larger on big machines
+const BUF_LEN: usize = 2048;
+
+/// The memory usage of the crashing process, see [`crate::CrashContext::memory`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The size of the virtual address space, ie. `VmSize`, in bytes
+    pub size: u64,
+    /// The resident set size, ie. `VmRSS`, in bytes
+    pub resident: u64,
+    /// The amount of memory that is swapped out, ie. `VmSwap`, in bytes
+    pub swap: u64,
+}
+
+impl MemoryStats {
+    /// Reads the statistics from descriptors for `/proc/<pid>/statm`, which
+    /// contains the size and resident set size, and optionally
+    /// `/proc/<pid>/status`, which contains the swap usage. Statistics that
+    /// can't be read are 0.
+    ///
+    /// This is async signal safe, as the files are read via `pread` into a
+    /// buffer on the stack.
+    pub fn read(statm: RawFd, status: Option<RawFd>) -> Self {
+        let mut stats = Self::default();
+        let mut buf = [0u8; BUF_LEN];
+
+        if let Some(len) = pread(statm, &mut buf) {
+            if let Some((size, resident)) = parse_statm(&buf[..len]) {
+                let page_size = page_size();
+                stats.size = size.saturating_mul(page_size);
+                stats.resident = resident.saturating_mul(page_size);
+            }
+        }
+
+        if let Some(len) = status.and_then(|status| pread(status, &mut buf)) {
+            if let Some(swap) = parse_status_kib(&buf[..len], b"VmSwap:") {
+                stats.swap = swap.saturating_mul(1024);
+            }
+        }
+
+        stats
+    }
+
+    /// Whether the statistics were captured, as every field is zero if they
+    /// weren't
+    #[inline]
+    pub fn is_captured(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+/// Reads the file from the start, as procfs files are generated when they
+/// are read from offset 0
+#[inline]
+fn pread(fd: RawFd, buf: &mut [u8]) -> Option<usize> {
+    // SAFETY: syscall, the buffer is valid for its length
+    let read = unsafe { libc::pread(fd, buf.as_mut_ptr().cast(), buf.len(), 0) };
+    if read > 0 {
+        Some(read as usize)
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn page_size() -> u64 {
+    // SAFETY: getauxval only reads the auxiliary vector
+    let page_size = unsafe { libc::getauxval(libc::AT_PAGESZ) } as u64;
+    if page_size == 0 {
+        4096
+    } else {
+        page_size
+    }
+}
+
+/// Parses the size and resident set size, in pages, which are the first two
+/// fields of `statm`
+fn parse_statm(statm: &[u8]) -> Option<(u64, u64)> {
+    let mut fields = statm
+        .split(|b| b.is_ascii_whitespace())
+        .filter(|field| !field.is_empty());
+
+    Some((parse_u64(fields.next()?)?, parse_u64(fields.next()?)?))
+}
+
+/// Parses the value of a `status` line that is in kibibytes, eg.
+/// `VmSwap:\t    1234 kB`
+fn parse_status_kib(status: &[u8], key: &[u8]) -> Option<u64> {
+    let line = status
+        .split(|b| *b == b'\n')
+        .find_map(|line| line.strip_prefix(key))?;
+
+    line.split(|b| b.is_ascii_whitespace())
+        .find(|field| !field.is_empty())
+        .and_then(parse_u64)
+}
+
+#[inline]
+fn parse_u64(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+
+    digits.iter().try_fold(0u64, |acc, b| {
+        if !b.is_ascii_digit() {
+            return None;
+        }
+        acc.checked_mul(10)?.checked_add(u64::from(b - b'0'))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::unix::io::AsRawFd;
+
+    #[test]
+    fn parses_proc_files() {
+        assert_eq!(
+            parse_statm(b"5619 1286 1034 49 0 411 0\n"),
+            Some((5619, 1286))
+        );
+        assert_eq!(parse_statm(b"5619"), None);
+
+        let status =
+            b"Name:\tcat\nVmSize:\t   22476 kB\nVmRSS:\t    5144 kB\nVmSwap:\t     128 kB\n";
+        assert_eq!(parse_status_kib(status, b"VmSwap:"), Some(128));
+        assert_eq!(parse_status_kib(status, b"VmRSS:"), Some(5144));
+        assert_eq!(parse_status_kib(status, b"VmPTE:"), None);
+    }
+
+    #[test]
+    fn reads_own_stats() {
+        let statm = std::fs::File::open("/proc/self/statm").unwrap();
+        let status = std::fs::File::open("/proc/self/status").unwrap();
+
+        let stats = MemoryStats::read(statm.as_raw_fd(), Some(status.as_raw_fd()));
+        assert!(stats.resident > 0);
+        assert!(stats.size >= stats.resident);
+
+        // The files are read from the start every time
+        assert_ne!(MemoryStats::read(statm.as_raw_fd(), None).size, 0);
+    }
+}
@@ -0,0 +1,418 @@
+//! Identification of the module containing the crashing instruction.
+//!
+//! A reporter that doesn't want to send a full minidump can still send enough
+//! information to symbolicate the crashing frame, ie. the build id of the
+//! module the instruction pointer is in, and the offset of the instruction
+//! pointer within that module, which [`CrashContext::crashing_module`]
+//! retrieves without allocating.
+
+use super::CrashContext;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_pointer_width = "64")] {
+        use libc::{Elf64_Ehdr as Ehdr, Elf64_Phdr as Phdr};
+    } else {
+        use libc::{Elf32_Ehdr as Ehdr, Elf32_Phdr as Phdr};
+    }
+}
+
+/// The maximum length of a build id, in bytes, longer ones are truncated
+pub const MAX_BUILD_ID_LEN: usize = 32;
+/// The maximum length of a module path, in bytes, longer ones are truncated
+pub const MAX_MODULE_PATH_LEN: usize = 256;
+
+/// The note type of a GNU build id
+const NT_GNU_BUILD_ID: u32 = 3;
+/// The maximum size of a note segment that is searched for the build id
+const MAX_NOTES_LEN: usize = 4 * 1024;
+/// The size of the buffer `/proc/<pid>/maps` is read into, lines longer than
+/// this are skipped
+const MAPS_BUF_LEN: usize = 4 * 1024;
+
+/// The module that contains the instruction pointer of a crash, see
+/// [`CrashContext::crashing_module`]
+#[derive(Copy, Clone)]
+pub struct CrashingModule {
+    /// The address the module is loaded at, ie. where the start of its file
+    /// is mapped
+    pub base: u64,
+    /// The offset of the instruction pointer from [`Self::base`]
+    pub offset: u64,
+    build_id: [u8; MAX_BUILD_ID_LEN],
+    build_id_len: usize,
+    path: [u8; MAX_MODULE_PATH_LEN],
+    path_len: usize,
+}
+
+impl CrashingModule {
+    /// The `NT_GNU_BUILD_ID` of the module, or empty if it doesn't have one
+    #[inline]
+    pub fn build_id(&self) -> &[u8] {
+        &self.build_id[..self.build_id_len]
+    }
+
+    /// The path of the module, which may be truncated to
+    /// [`MAX_MODULE_PATH_LEN`] bytes, or `None` if it is not utf-8
+    #[inline]
+    pub fn path(&self) -> Option<&str> {
+        std::str::from_utf8(&self.path[..self.path_len]).ok()
+    }
+}
+
+impl CrashContext {
+    /// Retrieves the instruction pointer of the crashing thread
+    pub fn instruction_pointer(&self) -> u64 {
+        let mc = &self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                mc.gregs[libc::REG_RIP as usize] as u64
+            } else if #[cfg(target_arch = "x86")] {
+                mc.gregs[libc::REG_EIP as usize] as u64
+            } else if #[cfg(target_arch = "aarch64")] {
+                mc.pc
+            } else if #[cfg(target_arch = "arm")] {
+                mc.arm_pc as u64
+            } else if #[cfg(target_arch = "riscv64")] {
+                // REG_PC, which libc only defines for glibc
+                mc.__gregs[0]
+            }
+        }
+    }
+
+    /// Finds the module that contains the [instruction pointer](Self::instruction_pointer)
+    /// by searching the mappings of [`Self::pid`], and reads its build id
+    /// from its memory.
+    ///
+    /// This neither allocates nor takes any locks, and works both in the
+    /// crashing process and in a process handling the crash, as long as it
+    /// has permission to `ptrace` the crashing process.
+    ///
+    /// Returns `None` if the instruction pointer is not within a file backed
+    /// mapping, eg. because it jumped to an invalid address or into JIT code.
+    pub fn crashing_module(&self) -> Option<CrashingModule> {
+        let ip = self.instruction_pointer();
+        if ip == 0 {
+            return None;
+        }
+
+        let mut module = CrashingModule {
+            base: 0,
+            offset: 0,
+            build_id: [0; MAX_BUILD_ID_LEN],
+            build_id_len: 0,
+            path: [0; MAX_MODULE_PATH_LEN],
+            path_len: 0,
+        };
+
+        let mut found = false;
+        for_each_mapping(self.pid, |start, end, offset, path| {
+            // Every module starts with a mapping of the start of its file, and
+            // the rest of its mappings follow
+            if offset == 0 {
+                let len = path.len().min(MAX_MODULE_PATH_LEN);
+                module.base = start;
+                module.path[..len].copy_from_slice(&path[..len]);
+                module.path_len = len;
+            }
+
+            if ip >= start && ip < end {
+                found = module.path_len > 0 && path.starts_with(&module.path[..module.path_len]);
+                return false;
+            }
+
+            true
+        });
+
+        if !found {
+            return None;
+        }
+
+        module.offset = ip - module.base;
+        module.build_id_len = read_build_id(self.pid, module.base as usize, &mut module.build_id);
+
+        Some(module)
+    }
+}
+
+/// Invokes the callback with the range, file offset, and path of each file
+/// backed mapping in the process, until it returns false
+fn for_each_mapping(pid: libc::pid_t, mut cb: impl FnMut(u64, u64, u64, &[u8]) -> bool) {
+    // Format "/proc/<pid>/maps" without allocating
+    let mut path = [0u8; 32];
+    let mut len = 0;
+    for part in [
+        &b"/proc/"[..],
+        itoa(pid as u32, &mut [0u8; 10]),
+        &b"/maps\0"[..],
+    ] {
+        path[len..len + part.len()].copy_from_slice(part);
+        len += part.len();
+    }
+
+    // SAFETY: syscall, the path is nul terminated
+    let fd = unsafe { libc::open(path.as_ptr().cast(), libc::O_RDONLY | libc::O_CLOEXEC) };
+    if fd == -1 {
+        return;
+    }
+
+    let mut buf = [0u8; MAPS_BUF_LEN];
+    let mut filled = 0;
+    let mut skipping = false;
+
+    'read: loop {
+        // SAFETY: syscall, we read into the unfilled part of the buffer
+        let read = unsafe { libc::read(fd, buf[filled..].as_mut_ptr().cast(), buf.len() - filled) };
+        if read <= 0 {
+            break;
+        }
+        filled += read as usize;
+
+        let mut consumed = 0;
+        while let Some(newline) = buf[consumed..filled].iter().position(|b| *b == b'\n') {
+            let line = &buf[consumed..consumed + newline];
+            consumed += newline + 1;
+
+            // The remainder of a line that didn't fit in the buffer
+            if skipping {
+                skipping = false;
+                continue;
+            }
+
+            if let Some((start, end, offset, path)) = parse_line(line) {
+                if !cb(start, end, offset, path) {
+                    break 'read;
+                }
+            }
+        }
+
+        if consumed == 0 && filled == buf.len() {
+            skipping = true;
+            filled = 0;
+        } else {
+            buf.copy_within(consumed..filled, 0);
+            filled -= consumed;
+        }
+    }
+
+    // SAFETY: syscall
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+/// Formats the number as decimal into the buffer
+fn itoa(mut n: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    &buf[i..]
+}
+
+/// Parses a line of `/proc/<pid>/maps`, returning the range, file offset,
+/// and path of mappings that are backed by a file or the vdso
+fn parse_line(line: &[u8]) -> Option<(u64, u64, u64, &[u8])> {
+    let hex = |s: &[u8]| u64::from_str_radix(std::str::from_utf8(s).ok()?, 16).ok();
+
+    let mut parts = line.splitn(6, |b| *b == b' ');
+    let range = parts.next()?;
+    let _perms = parts.next()?;
+    let offset = parts.next()?;
+    let _dev = parts.next()?;
+    let _inode = parts.next()?;
+    let path = parts.next()?;
+
+    let path = &path[path.iter().position(|b| *b != b' ')?..];
+    if !path.starts_with(b"/") && path != b"[vdso]" {
+        return None;
+    }
+
+    let dash = range.iter().position(|b| *b == b'-')?;
+
+    Some((
+        hex(&range[..dash])?,
+        hex(&range[dash + 1..])?,
+        hex(offset)?,
+        path,
+    ))
+}
+
+/// Finds the `NT_GNU_BUILD_ID` note of the ELF loaded at the base address,
+/// returning the length written to the buffer
+fn read_build_id(pid: libc::pid_t, base: usize, build_id: &mut [u8; MAX_BUILD_ID_LEN]) -> usize {
+    let ehdr: Ehdr = match read(pid, base) {
+        Some(ehdr) => ehdr,
+        None => return 0,
+    };
+
+    if ehdr.e_ident[..4] != *b"\x7fELF" {
+        return 0;
+    }
+
+    let phdr_at = |i: usize| -> Option<Phdr> {
+        read(
+            pid,
+            base + ehdr.e_phoff as usize + i * std::mem::size_of::<Phdr>(),
+        )
+    };
+
+    // The base is where the start of the file is mapped, so the load bias is
+    // relative to the segment that maps it
+    let bias = match (0..ehdr.e_phnum as usize)
+        .filter_map(phdr_at)
+        .find(|phdr| phdr.p_type == libc::PT_LOAD)
+    {
+        Some(phdr) => base.wrapping_sub(phdr.p_vaddr.wrapping_sub(phdr.p_offset) as usize),
+        None => return 0,
+    };
+
+    let mut notes = [0u8; MAX_NOTES_LEN];
+
+    for phdr in (0..ehdr.e_phnum as usize).filter_map(phdr_at) {
+        if phdr.p_type != libc::PT_NOTE {
+            continue;
+        }
+
+        let len = (phdr.p_memsz as usize).min(MAX_NOTES_LEN);
+        if !read_into(
+            pid,
+            bias.wrapping_add(phdr.p_vaddr as usize),
+            &mut notes[..len],
+        ) {
+            continue;
+        }
+
+        if let Some(desc) = find_build_id(&notes[..len]) {
+            let len = desc.len().min(MAX_BUILD_ID_LEN);
+            build_id[..len].copy_from_slice(&desc[..len]);
+            return len;
+        }
+    }
+
+    0
+}
+
+/// Finds the descriptor of the GNU build id note in a note segment
+fn find_build_id(mut notes: &[u8]) -> Option<&[u8]> {
+    let align = |len: usize| (len + 3) & !3;
+    let u32_at = |buf: &[u8], offset: usize| -> Option<u32> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(buf.get(offset..offset + 4)?);
+        Some(u32::from_ne_bytes(bytes))
+    };
+
+    while notes.len() >= 12 {
+        let name_len = u32_at(notes, 0)? as usize;
+        let desc_len = u32_at(notes, 4)? as usize;
+        let kind = u32_at(notes, 8)?;
+
+        let name = notes.get(12..12 + name_len)?;
+        let desc_start = 12 + align(name_len);
+        let desc = notes.get(desc_start..desc_start + desc_len)?;
+
+        if kind == NT_GNU_BUILD_ID && name == b"GNU\0" {
+            return Some(desc);
+        }
+
+        notes = notes.get(desc_start + align(desc_len)..)?;
+    }
+
+    None
+}
+
+/// Reads a value from the memory of the process
+fn read<T: Copy>(pid: libc::pid_t, address: usize) -> Option<T> {
+    let mut val = std::mem::MaybeUninit::<T>::uninit();
+
+    // SAFETY: the buffer is the size of T
+    let buf = unsafe {
+        std::slice::from_raw_parts_mut(val.as_mut_ptr().cast::<u8>(), std::mem::size_of::<T>())
+    };
+
+    if read_into(pid, address, buf) {
+        // SAFETY: only used for plain old data, which is fully initialized by
+        // the read
+        Some(unsafe { val.assume_init() })
+    } else {
+        None
+    }
+}
+
+/// Reads the memory of the process via `process_vm_readv`, which fails with
+/// `EFAULT` rather than faulting if the address is not mapped
+fn read_into(pid: libc::pid_t, address: usize, buf: &mut [u8]) -> bool {
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut _,
+        iov_len: buf.len(),
+    };
+
+    // SAFETY: syscall, the local buffer is valid for its length
+    let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+    read == buf.len() as isize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_maps() {
+        assert_eq!(
+            parse_line(b"7f1c2a400000-7f1c2a428000 r--p 00001000 08:01 1234                       /usr/lib/libc.so.6"),
+            Some((0x7f1c2a400000, 0x7f1c2a428000, 0x1000, &b"/usr/lib/libc.so.6"[..]))
+        );
+        assert!(parse_line(
+            b"7ffd1b7f2000-7ffd1b7f4000 rw-p 00000000 00:00 0                          [stack]"
+        )
+        .is_none());
+        assert!(parse_line(b"7ffd1b7f2000-7ffd1b7f4000 rw-p 00000000 00:00 0").is_none());
+        assert_eq!(itoa(0, &mut [0; 10]), b"0");
+        assert_eq!(itoa(u32::MAX, &mut [0; 10]), b"4294967295");
+    }
+
+    #[test]
+    fn finds_crashing_module() {
+        let mut cc = CrashContext::capture();
+
+        // The instruction pointer of a captured context is in this test
+        // executable
+        let module = cc.crashing_module().unwrap();
+        let exe = std::env::current_exe().unwrap();
+        assert_eq!(
+            std::fs::canonicalize(module.path().unwrap()).unwrap(),
+            std::fs::canonicalize(exe).unwrap()
+        );
+        assert_eq!(module.base + module.offset, cc.instruction_pointer());
+
+        #[cfg(target_arch = "x86_64")]
+        fn set_ip(cc: &mut CrashContext, ip: u64) {
+            cc.context.uc_mcontext.gregs[libc::REG_RIP as usize] = ip as i64;
+        }
+
+        #[cfg(target_arch = "aarch64")]
+        fn set_ip(cc: &mut CrashContext, ip: u64) {
+            cc.context.uc_mcontext.pc = ip;
+        }
+
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        {
+            // libc is always built with a build id
+            set_ip(&mut cc, libc::getpid as *const () as u64);
+            assert!(!cc.crashing_module().unwrap().build_id().is_empty());
+
+            // A wild jump isn't in any module
+            set_ip(&mut cc, 0x10);
+            assert!(cc.crashing_module().is_none());
+        }
+    }
+}
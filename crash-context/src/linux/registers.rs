@@ -0,0 +1,237 @@
+//! Typed access to the registers of the crashing thread.
+//!
+//! The registers are stored in the architecture specific [`super::mcontext_t`],
+//! whose layout differs between architectures, and on `x86` and `x86_64` is an
+//! array indexed by the `REG_*` constants, so these accessors answer the
+//! common questions, eg. where the thread was executing, without consumers
+//! needing to know every layout.
+
+use super::CrashContext;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "x86_64")] {
+        /// The general purpose registers of the crashing thread, see
+        /// [`CrashContext::gp_regs`]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        pub struct GpRegs {
+            pub rax: u64,
+            pub rbx: u64,
+            pub rcx: u64,
+            pub rdx: u64,
+            pub rsi: u64,
+            pub rdi: u64,
+            pub rbp: u64,
+            pub rsp: u64,
+            pub r8: u64,
+            pub r9: u64,
+            pub r10: u64,
+            pub r11: u64,
+            pub r12: u64,
+            pub r13: u64,
+            pub r14: u64,
+            pub r15: u64,
+            pub rip: u64,
+            pub rflags: u64,
+        }
+    } else if #[cfg(target_arch = "x86")] {
+        /// The general purpose registers of the crashing thread, see
+        /// [`CrashContext::gp_regs`]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        pub struct GpRegs {
+            pub eax: u32,
+            pub ebx: u32,
+            pub ecx: u32,
+            pub edx: u32,
+            pub esi: u32,
+            pub edi: u32,
+            pub ebp: u32,
+            pub esp: u32,
+            pub eip: u32,
+            pub eflags: u32,
+        }
+    } else if #[cfg(target_arch = "aarch64")] {
+        /// The general purpose registers of the crashing thread, see
+        /// [`CrashContext::gp_regs`]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        pub struct GpRegs {
+            /// `x0` to `x30`, where `x29` is the frame pointer and `x30` the
+            /// link register
+            pub x: [u64; 31],
+            pub sp: u64,
+            pub pc: u64,
+            pub pstate: u64,
+        }
+    } else if #[cfg(target_arch = "arm")] {
+        /// The general purpose registers of the crashing thread, see
+        /// [`CrashContext::gp_regs`]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        pub struct GpRegs {
+            /// `r0` to `r15`, where `r11` is the frame pointer, `r12` the
+            /// intra-procedure call scratch register, `r13` the stack pointer,
+            /// `r14` the link register, and `r15` the program counter
+            pub r: [u32; 16],
+            pub cpsr: u32,
+        }
+    } else if #[cfg(target_arch = "riscv64")] {
+        /// The general purpose registers of the crashing thread, see
+        /// [`CrashContext::gp_regs`]
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+        pub struct GpRegs {
+            pub pc: u64,
+            /// `x0` to `x31`, where `x0` is always zero, `x1` is the return
+            /// address, `x2` the stack pointer, and `x8` the frame pointer
+            pub x: [u64; 32],
+        }
+    }
+}
+
+impl CrashContext {
+    /// The instruction pointer of the crashing thread, see
+    /// [`Self::instruction_pointer`]
+    #[inline]
+    pub fn ip(&self) -> u64 {
+        self.instruction_pointer()
+    }
+
+    /// The stack pointer of the crashing thread, see [`Self::stack_pointer`]
+    #[inline]
+    pub fn sp(&self) -> u64 {
+        self.stack_pointer()
+    }
+
+    /// The frame pointer of the crashing thread, ie. `rbp`/`ebp` on x86,
+    /// `x29` on `aarch64`, `r11` on `arm`, and `s0` on `riscv64`.
+    ///
+    /// Note that this is only an actual frame pointer if the code that was
+    /// executing was compiled with frame pointers.
+    pub fn bp(&self) -> u64 {
+        let mc = &self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                mc.gregs[libc::REG_RBP as usize] as u64
+            } else if #[cfg(target_arch = "x86")] {
+                mc.gregs[libc::REG_EBP as usize] as u32 as u64
+            } else if #[cfg(target_arch = "aarch64")] {
+                mc.regs[29]
+            } else if #[cfg(target_arch = "arm")] {
+                mc.arm_fp as u64
+            } else if #[cfg(target_arch = "riscv64")] {
+                mc.__gregs[8]
+            }
+        }
+    }
+
+    /// The general purpose registers of the crashing thread
+    pub fn gp_regs(&self) -> GpRegs {
+        let mc = &self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                let reg = |reg: i32| mc.gregs[reg as usize] as u64;
+
+                GpRegs {
+                    rax: reg(libc::REG_RAX),
+                    rbx: reg(libc::REG_RBX),
+                    rcx: reg(libc::REG_RCX),
+                    rdx: reg(libc::REG_RDX),
+                    rsi: reg(libc::REG_RSI),
+                    rdi: reg(libc::REG_RDI),
+                    rbp: reg(libc::REG_RBP),
+                    rsp: reg(libc::REG_RSP),
+                    r8: reg(libc::REG_R8),
+                    r9: reg(libc::REG_R9),
+                    r10: reg(libc::REG_R10),
+                    r11: reg(libc::REG_R11),
+                    r12: reg(libc::REG_R12),
+                    r13: reg(libc::REG_R13),
+                    r14: reg(libc::REG_R14),
+                    r15: reg(libc::REG_R15),
+                    rip: reg(libc::REG_RIP),
+                    rflags: reg(libc::REG_EFL),
+                }
+            } else if #[cfg(target_arch = "x86")] {
+                let reg = |reg: i32| mc.gregs[reg as usize] as u32;
+
+                GpRegs {
+                    eax: reg(libc::REG_EAX),
+                    ebx: reg(libc::REG_EBX),
+                    ecx: reg(libc::REG_ECX),
+                    edx: reg(libc::REG_EDX),
+                    esi: reg(libc::REG_ESI),
+                    edi: reg(libc::REG_EDI),
+                    ebp: reg(libc::REG_EBP),
+                    esp: reg(libc::REG_ESP),
+                    eip: reg(libc::REG_EIP),
+                    eflags: reg(libc::REG_EFL),
+                }
+            } else if #[cfg(target_arch = "aarch64")] {
+                GpRegs {
+                    x: mc.regs,
+                    sp: mc.sp,
+                    pc: mc.pc,
+                    pstate: mc.pstate,
+                }
+            } else if #[cfg(target_arch = "arm")] {
+                GpRegs {
+                    r: [
+                        mc.arm_r0, mc.arm_r1, mc.arm_r2, mc.arm_r3, mc.arm_r4, mc.arm_r5,
+                        mc.arm_r6, mc.arm_r7, mc.arm_r8, mc.arm_r9, mc.arm_r10, mc.arm_fp,
+                        mc.arm_ip, mc.arm_sp, mc.arm_lr, mc.arm_pc,
+                    ],
+                    cpsr: mc.arm_cpsr,
+                }
+            } else if #[cfg(target_arch = "riscv64")] {
+                // REG_PC is 0, followed by x1-x31, as x0 is always zero
+                let mut x = mc.__gregs;
+                x[0] = 0;
+
+                GpRegs {
+                    pc: mc.__gregs[0],
+                    x,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matches_synthetic() {
+        let mut cc = CrashContext::synthetic(libc::SIGSEGV, 0x1000_1234, 0x7fff_0000);
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                cc.context.uc_mcontext.gregs[libc::REG_RBP as usize] = 0x7fff_0040;
+                let (ip, sp, bp) = { let r = cc.gp_regs(); (r.rip, r.rsp, r.rbp) };
+            } else if #[cfg(target_arch = "x86")] {
+                cc.context.uc_mcontext.gregs[libc::REG_EBP as usize] = 0x7fff_0040;
+                let (ip, sp, bp) = {
+                    let r = cc.gp_regs();
+                    (u64::from(r.eip), u64::from(r.esp), u64::from(r.ebp))
+                };
+            } else if #[cfg(target_arch = "aarch64")] {
+                cc.context.uc_mcontext.regs[29] = 0x7fff_0040;
+                let (ip, sp, bp) = { let r = cc.gp_regs(); (r.pc, r.sp, r.x[29]) };
+            } else if #[cfg(target_arch = "arm")] {
+                cc.context.uc_mcontext.arm_fp = 0x7fff_0040;
+                let (ip, sp, bp) = {
+                    let r = cc.gp_regs();
+                    (u64::from(r.r[15]), u64::from(r.r[13]), u64::from(r.r[11]))
+                };
+            } else if #[cfg(target_arch = "riscv64")] {
+                cc.context.uc_mcontext.__gregs[8] = 0x7fff_0040;
+                let (ip, sp, bp) = { let r = cc.gp_regs(); (r.pc, r.x[2], r.x[8]) };
+            }
+        }
+
+        assert_eq!(
+            (cc.ip(), cc.sp(), cc.bp()),
+            (0x1000_1234, 0x7fff_0000, 0x7fff_0040)
+        );
+        assert_eq!((ip, sp, bp), (cc.ip(), cc.sp(), cc.bp()));
+    }
+}
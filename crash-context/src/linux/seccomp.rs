@@ -0,0 +1,96 @@
+//! Decoding of seccomp violations.
+//!
+//! When a seccomp filter returns `SECCOMP_RET_TRAP` for a syscall, the kernel
+//! doesn't perform the syscall, but instead raises a `SIGSYS` whose
+//! `siginfo_t` describes the syscall that was attempted, which
+//! [`CrashContext::seccomp_violation`] decodes so that sandboxed processes can
+//! report exactly which forbidden syscall caused the crash.
+
+use super::CrashContext;
+
+/// The `si_code` of a `SIGSYS` raised by a seccomp filter
+pub const SYS_SECCOMP: i32 = 1;
+
+/// The `AUDIT_ARCH_*` value the kernel reports for syscalls made with the
+/// native syscall convention of the current architecture
+pub const NATIVE_AUDIT_ARCH: u32 = {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86")] {
+            0x4000_0003
+        } else if #[cfg(target_arch = "x86_64")] {
+            0xc000_003e
+        } else if #[cfg(target_arch = "arm")] {
+            0x4000_0028
+        } else if #[cfg(target_arch = "aarch64")] {
+            0xc000_00b7
+        } else if #[cfg(target_arch = "riscv64")] {
+            0xc000_00f3
+        }
+    }
+};
+
+/// A syscall that was forbidden by a seccomp filter, see
+/// [`CrashContext::seccomp_violation`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SeccompViolation {
+    /// The number of the syscall that was attempted, ie. `si_syscall`
+    pub syscall: i32,
+    /// The `AUDIT_ARCH_*` value of the syscall convention that was used to
+    /// make the syscall, ie. `si_arch`
+    pub arch: u32,
+    /// The address of the instruction following the syscall instruction, ie.
+    /// `si_call_addr`
+    pub call_addr: u64,
+}
+
+impl SeccompViolation {
+    /// Returns true if the syscall was made with the native syscall
+    /// convention, in which case [`Self::syscall`] can be compared against the
+    /// `libc::SYS_*` constants, as opposed to eg. a 32-bit syscall made by a
+    /// 64-bit process on `x86_64`
+    #[inline]
+    pub fn is_native_arch(&self) -> bool {
+        self.arch == NATIVE_AUDIT_ARCH
+    }
+}
+
+impl CrashContext {
+    /// Decodes the syscall that was attempted, if the crash was a `SIGSYS`
+    /// raised by a seccomp filter.
+    pub fn seccomp_violation(&self) -> Option<SeccompViolation> {
+        if self.siginfo.ssi_signo as i32 != libc::SIGSYS || self.siginfo.ssi_code != SYS_SECCOMP {
+            return None;
+        }
+
+        Some(SeccompViolation {
+            syscall: self.siginfo.ssi_syscall,
+            arch: self.siginfo.ssi_arch,
+            call_addr: self.siginfo.ssi_call_addr,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_violation() {
+        // SAFETY: every field is plain old data for which all zeroes is valid
+        let mut cc: CrashContext = unsafe { std::mem::zeroed() };
+        cc.siginfo.ssi_signo = libc::SIGSYS as u32;
+        cc.siginfo.ssi_code = SYS_SECCOMP;
+        cc.siginfo.ssi_syscall = libc::SYS_getppid as i32;
+        cc.siginfo.ssi_arch = NATIVE_AUDIT_ARCH;
+        cc.siginfo.ssi_call_addr = 0x1234;
+
+        let violation = cc.seccomp_violation().unwrap();
+        assert_eq!(violation.syscall, libc::SYS_getppid as i32);
+        assert_eq!(violation.call_addr, 0x1234);
+        assert!(violation.is_native_arch());
+
+        // A SIGSYS sent by another process is not a violation
+        cc.siginfo.ssi_code = libc::SI_USER;
+        assert!(cc.seccomp_violation().is_none());
+    }
+}
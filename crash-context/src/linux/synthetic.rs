@@ -0,0 +1,122 @@
+//! Contexts for crashes that never happened, so that the code that processes
+//! crashes can be tested deterministically without crashing anything.
+
+use super::CrashContext;
+
+impl CrashContext {
+    /// Creates a context for the specified signal, as if the current thread
+    /// had crashed with the specified instruction and stack pointers.
+    ///
+    /// Every other register, as well as the floating point state, is zero,
+    /// and neither the time nor the thread name are captured, so the context
+    /// is identical every time apart from [`Self::pid`] and [`Self::tid`],
+    /// which are those of the current thread, as eg. writing a minidump
+    /// requires them. Any of the other fields, eg. the fault address in
+    /// [`Self::siginfo`], can be filled out afterwards.
+    ///
+    /// The [`Self::siginfo`] has a code of `SI_USER`, like a signal sent via
+    /// `kill`.
+    pub fn synthetic(signal: i32, instruction_pointer: u64, stack_pointer: u64) -> Self {
+        // SAFETY: every field is plain old data for which all zeroes is valid,
+        // including the reason which is `CrashReason::Signal`
+        let mut cc: Self = unsafe { std::mem::zeroed() };
+
+        cc.pid = std::process::id() as i32;
+        // SAFETY: syscall
+        cc.tid = unsafe { libc::syscall(libc::SYS_gettid) } as i32;
+        cc.siginfo.ssi_signo = signal as u32;
+        cc.siginfo.ssi_code = libc::SI_USER;
+        cc.siginfo.ssi_pid = cc.pid as u32;
+        cc.set_instruction_pointer(instruction_pointer);
+        cc.set_stack_pointer(stack_pointer);
+
+        cc
+    }
+
+    /// Retrieves the stack pointer of the crashing thread
+    pub fn stack_pointer(&self) -> u64 {
+        let mc = &self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                mc.gregs[libc::REG_RSP as usize] as u64
+            } else if #[cfg(target_arch = "x86")] {
+                mc.gregs[libc::REG_ESP as usize] as u32 as u64
+            } else if #[cfg(target_arch = "aarch64")] {
+                mc.sp
+            } else if #[cfg(target_arch = "arm")] {
+                mc.arm_sp as u64
+            } else if #[cfg(target_arch = "riscv64")] {
+                // REG_SP, which libc only defines for glibc
+                mc.__gregs[2]
+            }
+        }
+    }
+
+    /// Replaces the [instruction pointer](Self::instruction_pointer) of the
+    /// crashing thread
+    pub fn set_instruction_pointer(&mut self, ip: u64) {
+        let mc = &mut self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                mc.gregs[libc::REG_RIP as usize] = ip as i64;
+            } else if #[cfg(target_arch = "x86")] {
+                mc.gregs[libc::REG_EIP as usize] = ip as u32 as i64;
+            } else if #[cfg(target_arch = "aarch64")] {
+                mc.pc = ip;
+            } else if #[cfg(target_arch = "arm")] {
+                mc.arm_pc = ip as u32;
+            } else if #[cfg(target_arch = "riscv64")] {
+                mc.__gregs[0] = ip;
+            }
+        }
+    }
+
+    /// Replaces the [stack pointer](Self::stack_pointer) of the crashing
+    /// thread
+    pub fn set_stack_pointer(&mut self, sp: u64) {
+        let mc = &mut self.context.uc_mcontext;
+
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                mc.gregs[libc::REG_RSP as usize] = sp as i64;
+            } else if #[cfg(target_arch = "x86")] {
+                mc.gregs[libc::REG_ESP as usize] = sp as u32 as i64;
+            } else if #[cfg(target_arch = "aarch64")] {
+                mc.sp = sp;
+            } else if #[cfg(target_arch = "arm")] {
+                mc.arm_sp = sp as u32;
+            } else if #[cfg(target_arch = "riscv64")] {
+                mc.__gregs[2] = sp;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn synthesizes_context() {
+        let cc = CrashContext::synthetic(libc::SIGSEGV, 0x1000_1234, 0x7fff_0000);
+
+        assert_eq!(cc.instruction_pointer(), 0x1000_1234);
+        assert_eq!(cc.stack_pointer(), 0x7fff_0000);
+        assert_eq!(cc.siginfo.ssi_signo, libc::SIGSEGV as u32);
+        assert_eq!(cc.pid, std::process::id() as i32);
+        assert!(cc.float_state().is_none() || cfg!(target_arch = "riscv64"));
+
+        // Two contexts for the same crash are identical
+        let again = CrashContext::synthetic(libc::SIGSEGV, 0x1000_1234, 0x7fff_0000);
+        assert_eq!(cc.as_bytes(), again.as_bytes());
+
+        // And they survive the trip to another process like any other
+        let mut buf = vec![0u8; CrashContext::SERIALIZED_LEN];
+        let len = cc.serialize_into(&mut buf).unwrap();
+        let decoded = CrashContext::deserialize(&buf[..len]).unwrap();
+        assert_eq!(decoded.instruction_pointer(), 0x1000_1234);
+        assert_eq!(decoded.stack_pointer(), 0x7fff_0000);
+    }
+}
@@ -0,0 +1,544 @@
+//! A versioned wire format for [`CrashContext`], so that a process handling a
+//! crash can detect, and reject, a context that was serialized by a different
+//! version of this crate or for a different architecture, rather than blindly
+//! reinterpreting the bytes as [`CrashContext::from_bytes`] does.
+//!
+//! All fields are little endian and fixed width, laid out as follows.
+//!
+//! | Offset | Size | Field |
+//! | ------ | ---- | ----- |
+//! | 0 | 4 | Magic, `b"CCTX"` |
+//! | 4 | 2 | Format version |
+//! | 6 | 2 | Architecture tag |
+//! | 8 | 4 | [`CrashContext::pid`] |
+//! | 12 | 4 | [`CrashContext::tid`] |
+//! | 16 | 4 | [`CrashContext::reason`] |
+//! | 20 | 4 | Reserved, 0 |
+//! | 24 | 8 | [`CrashContext::annotations`] |
+//! | 32 | 8 | [`CrashContext::annotation_count`] |
+//! | 40 | 8 | [`CrashContext::breadcrumbs`] |
+//! | 48 | 8 | [`CrashContext::breadcrumb_count`] |
+//! | 56 | 8 | [`CrashContext::memory_regions`] |
+//! | 64 | 8 | [`CrashContext::memory_region_count`] |
+//! | 72 | 8 | [`CrashContext::time`], [`crate::CrashTime::monotonic`] |
+//! | 80 | 8 | [`CrashContext::time`], [`crate::CrashTime::wall`] |
+//! | 88 | 8 | [`CrashContext::process_start`], [`crate::CrashTime::monotonic`] |
+//! | 96 | 8 | [`CrashContext::process_start`], [`crate::CrashTime::wall`] |
+//! | 104 | 8 | [`CrashContext::attach_time`], [`crate::CrashTime::monotonic`] |
+//! | 112 | 8 | [`CrashContext::attach_time`], [`crate::CrashTime::wall`] |
+//! | 120 | 16 | [`CrashContext::thread_name`] |
+//! | 136 | 128 | [`CrashContext::siginfo`], in the kernel's `signalfd_siginfo` layout |
+//! | 264 | 8 | [`CrashContext::crashed_threads`] |
+//! | 272 | 8 | [`CrashContext::crashed_thread_count`] |
+//! | 280 | 8 | [`CrashContext::cpu`], [`crate::CpuInfo::hwcap`] |
+//! | 288 | 8 | [`CrashContext::cpu`], [`crate::CpuInfo::hwcap2`] |
+//! | 296 | 12 | [`CrashContext::cpu`], [`crate::CpuInfo::cpuid_vendor`] |
+//! | 308 | 4 | [`CrashContext::cpu`], [`crate::CpuInfo::cpuid_signature`] |
+//! | 312 | 16 | [`CrashContext::cpu`], [`crate::CpuInfo::cpuid_features`] |
+//! | 328 | 8 | [`CrashContext::memory`], [`crate::MemoryStats::size`] |
+//! | 336 | 8 | [`CrashContext::memory`], [`crate::MemoryStats::resident`] |
+//! | 344 | 8 | [`CrashContext::memory`], [`crate::MemoryStats::swap`] |
+//! | 352 | 8 | [`CrashContext::environment`] |
+//! | 360 | 8 | [`CrashContext::environment_len`] |
+//! | 368 | 256 | [`CrashContext::abort_message`] |
+//! | 624 | 4 | Length of the thread context |
+//! | 628 | 4 | Length of the floating point state |
+//! | 632 | N | The thread context, in the layout of the architecture |
+//! | 632 + N | M | The floating point state, in the layout of the architecture |
+//!
+//! Since the thread context and floating point state are inherently
+//! architecture specific they are kept in their native layout, but their
+//! lengths are checked when deserializing.
+
+use super::{CrashContext, CrashReason, ABORT_MESSAGE_LEN, THREAD_NAME_LEN};
+
+/// The magic at the start of every serialized [`CrashContext`]
+const MAGIC: [u8; 4] = *b"CCTX";
+/// The current version of the wire format
+pub const WIRE_VERSION: u16 = 10;
+
+/// Identifies the architecture a [`CrashContext`] was serialized on
+pub const WIRE_ARCH: u16 = {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86")] {
+            1
+        } else if #[cfg(target_arch = "x86_64")] {
+            2
+        } else if #[cfg(target_arch = "arm")] {
+            3
+        } else if #[cfg(target_arch = "aarch64")] {
+            4
+        } else if #[cfg(target_arch = "riscv64")] {
+            5
+        }
+    }
+};
+
+/// The size of the fixed header preceding the thread context
+const HEADER_LEN: usize = 632;
+/// The offset of the siginfo in the header
+const SIGINFO_OFFSET: usize = 136;
+/// The size of `signalfd_siginfo`, which is the same on every architecture
+const SIGINFO_LEN: usize = 128;
+
+const CONTEXT_LEN: usize = std::mem::size_of::<super::ucontext_t>();
+const FLOAT_STATE_LEN: usize = std::mem::size_of::<super::fpregset_t>();
+
+/// The reasons a serialized [`CrashContext`] could not be deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The buffer did not start with the expected magic
+    InvalidMagic,
+    /// The buffer was serialized with a version of the wire format that is
+    /// not supported
+    UnsupportedVersion(u16),
+    /// The buffer was serialized on a different architecture
+    ArchMismatch {
+        /// The architecture tag of this process
+        expected: u16,
+        /// The architecture tag of the buffer
+        actual: u16,
+    },
+    /// The buffer was shorter or longer than its header specified
+    InvalidLength,
+    /// The length of the thread context or floating point state differs from
+    /// this process
+    LayoutMismatch,
+    /// The [`CrashReason`] was not a known value
+    InvalidReason(u32),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => f.write_str("crash context has an invalid magic"),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "crash context has an unsupported version {v}")
+            }
+            Self::ArchMismatch { expected, actual } => write!(
+                f,
+                "crash context was serialized for architecture {actual}, expected {expected}"
+            ),
+            Self::InvalidLength => f.write_str("crash context has an invalid length"),
+            Self::LayoutMismatch => {
+                f.write_str("crash context thread state has a mismatched layout")
+            }
+            Self::InvalidReason(r) => write!(f, "crash context has an invalid reason {r}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Writes fixed width little endian fields into a buffer that is known to be
+/// large enough
+struct Writer<'buf> {
+    buf: &'buf mut [u8],
+    offset: usize,
+}
+
+impl<'buf> Writer<'buf> {
+    #[inline]
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.offset..self.offset + bytes.len()].copy_from_slice(bytes);
+        self.offset += bytes.len();
+    }
+
+    #[inline]
+    fn u16(&mut self, v: u16) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    #[inline]
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    #[inline]
+    fn u64(&mut self, v: u64) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    #[inline]
+    fn zeroes(&mut self, len: usize) {
+        self.buf[self.offset..self.offset + len].fill(0);
+        self.offset += len;
+    }
+}
+
+/// Reads fixed width little endian fields from a buffer that is known to be
+/// large enough
+struct Reader<'buf> {
+    buf: &'buf [u8],
+    offset: usize,
+}
+
+impl<'buf> Reader<'buf> {
+    #[inline]
+    fn bytes(&mut self, len: usize) -> &'buf [u8] {
+        let bytes = &self.buf[self.offset..self.offset + len];
+        self.offset += len;
+        bytes
+    }
+
+    #[inline]
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let mut arr = [0u8; N];
+        arr.copy_from_slice(self.bytes(N));
+        arr
+    }
+
+    #[inline]
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.array())
+    }
+
+    #[inline]
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.array())
+    }
+
+    #[inline]
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.array())
+    }
+}
+
+impl CrashContext {
+    /// The number of bytes [`Self::serialize_into`] writes
+    pub const SERIALIZED_LEN: usize = HEADER_LEN + CONTEXT_LEN + FLOAT_STATE_LEN;
+
+    /// Serializes the context into the buffer in the versioned wire format,
+    /// returning the number of bytes written, or `None` if the buffer is
+    /// smaller than [`Self::SERIALIZED_LEN`].
+    ///
+    /// This neither allocates nor performs any syscalls, and is thus safe to
+    /// call from a signal handler.
+    pub fn serialize_into(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..Self::SERIALIZED_LEN)?;
+        let mut w = Writer { buf, offset: 0 };
+
+        w.bytes(&MAGIC);
+        w.u16(WIRE_VERSION);
+        w.u16(WIRE_ARCH);
+        w.u32(self.pid as u32);
+        w.u32(self.tid as u32);
+        w.u32(self.reason as u32);
+        w.u32(0);
+        w.u64(self.annotations as u64);
+        w.u64(self.annotation_count as u64);
+        w.u64(self.breadcrumbs as u64);
+        w.u64(self.breadcrumb_count as u64);
+        w.u64(self.memory_regions as u64);
+        w.u64(self.memory_region_count as u64);
+        w.u64(self.time.monotonic);
+        w.u64(self.time.wall);
+        w.u64(self.process_start.monotonic);
+        w.u64(self.process_start.wall);
+        w.u64(self.attach_time.monotonic);
+        w.u64(self.attach_time.wall);
+        w.bytes(&self.thread_name);
+
+        let si = &self.siginfo;
+        w.u32(si.ssi_signo);
+        w.u32(si.ssi_errno as u32);
+        w.u32(si.ssi_code as u32);
+        w.u32(si.ssi_pid);
+        w.u32(si.ssi_uid);
+        w.u32(si.ssi_fd as u32);
+        w.u32(si.ssi_tid);
+        w.u32(si.ssi_band);
+        w.u32(si.ssi_overrun);
+        w.u32(si.ssi_trapno);
+        w.u32(si.ssi_status as u32);
+        w.u32(si.ssi_int as u32);
+        w.u64(si.ssi_ptr);
+        w.u64(si.ssi_utime);
+        w.u64(si.ssi_stime);
+        w.u64(si.ssi_addr);
+        w.u16(si.ssi_addr_lsb);
+        w.u16(0);
+        w.u32(si.ssi_syscall as u32);
+        w.u64(si.ssi_call_addr);
+        w.u32(si.ssi_arch);
+        w.zeroes(SIGINFO_OFFSET + SIGINFO_LEN - w.offset);
+
+        w.u64(self.crashed_threads as u64);
+        w.u64(self.crashed_thread_count as u64);
+
+        w.u64(self.cpu.hwcap);
+        w.u64(self.cpu.hwcap2);
+        w.bytes(&self.cpu.cpuid_vendor);
+        w.u32(self.cpu.cpuid_signature);
+        for features in self.cpu.cpuid_features {
+            w.u32(features);
+        }
+
+        w.u64(self.memory.size);
+        w.u64(self.memory.resident);
+        w.u64(self.memory.swap);
+
+        w.u64(self.environment as u64);
+        w.u64(self.environment_len as u64);
+
+        w.bytes(&self.abort_message);
+
+        w.u32(CONTEXT_LEN as u32);
+        w.u32(FLOAT_STATE_LEN as u32);
+
+        // SAFETY: the thread state is plain old data
+        unsafe {
+            w.bytes(std::slice::from_raw_parts(
+                (&self.context as *const super::ucontext_t).cast(),
+                CONTEXT_LEN,
+            ));
+            w.bytes(std::slice::from_raw_parts(
+                (&self.float_state as *const super::fpregset_t).cast(),
+                FLOAT_STATE_LEN,
+            ));
+        }
+
+        Some(w.offset)
+    }
+
+    /// Deserializes a context that was serialized via [`Self::serialize_into`]
+    ///
+    /// # Errors
+    ///
+    /// The buffer was not serialized by the same version of the wire format,
+    /// for the same architecture, or is otherwise invalid
+    pub fn deserialize(buf: &[u8]) -> Result<Self, DecodeError> {
+        if buf.len() < HEADER_LEN {
+            return Err(if buf.get(..4).map_or(true, |m| m == MAGIC) {
+                DecodeError::InvalidLength
+            } else {
+                DecodeError::InvalidMagic
+            });
+        }
+
+        let mut r = Reader { buf, offset: 0 };
+
+        if r.array::<4>() != MAGIC {
+            return Err(DecodeError::InvalidMagic);
+        }
+
+        let version = r.u16();
+        if version != WIRE_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let arch = r.u16();
+        if arch != WIRE_ARCH {
+            return Err(DecodeError::ArchMismatch {
+                expected: WIRE_ARCH,
+                actual: arch,
+            });
+        }
+
+        // SAFETY: every field is plain old data for which all zeroes is valid,
+        // including the reason which is `CrashReason::Signal`
+        let mut cc: Self = unsafe { std::mem::zeroed() };
+
+        cc.pid = r.u32() as i32;
+        cc.tid = r.u32() as i32;
+        let reason = r.u32();
+        cc.reason = CrashReason::from_raw(reason).ok_or(DecodeError::InvalidReason(reason))?;
+        r.u32();
+        cc.annotations = r.u64() as usize;
+        cc.annotation_count = r.u64() as usize;
+        cc.breadcrumbs = r.u64() as usize;
+        cc.breadcrumb_count = r.u64() as usize;
+        cc.memory_regions = r.u64() as usize;
+        cc.memory_region_count = r.u64() as usize;
+        cc.time.monotonic = r.u64();
+        cc.time.wall = r.u64();
+        cc.process_start.monotonic = r.u64();
+        cc.process_start.wall = r.u64();
+        cc.attach_time.monotonic = r.u64();
+        cc.attach_time.wall = r.u64();
+        cc.thread_name = r.array::<THREAD_NAME_LEN>();
+
+        let si = &mut cc.siginfo;
+        si.ssi_signo = r.u32();
+        si.ssi_errno = r.u32() as i32;
+        si.ssi_code = r.u32() as i32;
+        si.ssi_pid = r.u32();
+        si.ssi_uid = r.u32();
+        si.ssi_fd = r.u32() as i32;
+        si.ssi_tid = r.u32();
+        si.ssi_band = r.u32();
+        si.ssi_overrun = r.u32();
+        si.ssi_trapno = r.u32();
+        si.ssi_status = r.u32() as i32;
+        si.ssi_int = r.u32() as i32;
+        si.ssi_ptr = r.u64();
+        si.ssi_utime = r.u64();
+        si.ssi_stime = r.u64();
+        si.ssi_addr = r.u64();
+        si.ssi_addr_lsb = r.u16();
+        r.u16();
+        si.ssi_syscall = r.u32() as i32;
+        si.ssi_call_addr = r.u64();
+        si.ssi_arch = r.u32();
+        r.offset = SIGINFO_OFFSET + SIGINFO_LEN;
+
+        cc.crashed_threads = r.u64() as usize;
+        cc.crashed_thread_count = r.u64() as usize;
+
+        cc.cpu.hwcap = r.u64();
+        cc.cpu.hwcap2 = r.u64();
+        cc.cpu.cpuid_vendor = r.array();
+        cc.cpu.cpuid_signature = r.u32();
+        for features in &mut cc.cpu.cpuid_features {
+            *features = r.u32();
+        }
+
+        cc.memory.size = r.u64();
+        cc.memory.resident = r.u64();
+        cc.memory.swap = r.u64();
+
+        cc.environment = r.u64() as usize;
+        cc.environment_len = r.u64() as usize;
+
+        cc.abort_message = r.array::<ABORT_MESSAGE_LEN>();
+
+        let context_len = r.u32() as usize;
+        let float_state_len = r.u32() as usize;
+        if context_len != CONTEXT_LEN || float_state_len != FLOAT_STATE_LEN {
+            return Err(DecodeError::LayoutMismatch);
+        }
+
+        if buf.len() != Self::SERIALIZED_LEN {
+            return Err(DecodeError::InvalidLength);
+        }
+
+        // SAFETY: the thread state is plain old data, and we've verified the
+        // lengths match
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                r.bytes(CONTEXT_LEN).as_ptr(),
+                (&mut cc.context as *mut super::ucontext_t).cast(),
+                CONTEXT_LEN,
+            );
+            std::ptr::copy_nonoverlapping(
+                r.bytes(FLOAT_STATE_LEN).as_ptr(),
+                (&mut cc.float_state as *mut super::fpregset_t).cast(),
+                FLOAT_STATE_LEN,
+            );
+        }
+
+        Ok(cc)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrips() {
+        let mut cc = CrashContext::capture();
+        cc.reason = CrashReason::Breakpoint;
+        cc.siginfo.ssi_signo = libc::SIGTRAP as u32;
+        cc.siginfo.ssi_addr = 0xdead_beef;
+        cc.annotations = 0x1000;
+        cc.annotation_count = 64;
+        cc.breadcrumbs = 0x2000;
+        cc.breadcrumb_count = 32;
+        cc.memory_regions = 0x3000;
+        cc.memory_region_count = 16;
+        cc.crashed_threads = 0x4000;
+        cc.crashed_thread_count = 8;
+        cc.environment = 0x5000;
+        cc.environment_len = 1234;
+        cc.abort_message[..19].copy_from_slice(b"assertion failed!\n\0");
+        cc.cpu = crate::CpuInfo::capture();
+        cc.memory = crate::MemoryStats {
+            size: 64 << 30,
+            resident: 3 << 30,
+            swap: 512 << 20,
+        };
+        cc.process_start.wall = 1_700_000_000_000_000_000;
+        cc.attach_time.monotonic = 42;
+
+        let mut buf = vec![0u8; CrashContext::SERIALIZED_LEN];
+        assert!(cc.serialize_into(&mut buf[..HEADER_LEN]).is_none());
+        assert_eq!(cc.serialize_into(&mut buf), Some(buf.len()));
+
+        let de = CrashContext::deserialize(&buf).unwrap();
+        assert_eq!(de.pid, cc.pid);
+        assert_eq!(de.tid, cc.tid);
+        assert_eq!(de.reason, cc.reason);
+        assert_eq!(de.annotations, cc.annotations);
+        assert_eq!(de.annotation_count, cc.annotation_count);
+        assert_eq!(de.breadcrumbs, cc.breadcrumbs);
+        assert_eq!(de.breadcrumb_count, cc.breadcrumb_count);
+        assert_eq!(de.memory_regions, cc.memory_regions);
+        assert_eq!(de.memory_region_count, cc.memory_region_count);
+        assert_eq!(de.crashed_threads, cc.crashed_threads);
+        assert_eq!(de.crashed_thread_count, cc.crashed_thread_count);
+        assert_eq!(de.environment, cc.environment);
+        assert_eq!(de.environment_len, cc.environment_len);
+        assert_eq!(de.cpu, cc.cpu);
+        assert_eq!(de.memory, cc.memory);
+        assert_eq!(de.time, cc.time);
+        assert_eq!(de.process_start, cc.process_start);
+        assert_eq!(de.attach_time, cc.attach_time);
+        assert_eq!(de.thread_name, cc.thread_name);
+        assert_eq!(de.abort_message(), Some("assertion failed!"));
+        assert_eq!(de.siginfo.ssi_signo, cc.siginfo.ssi_signo);
+        assert_eq!(de.siginfo.ssi_code, cc.siginfo.ssi_code);
+        assert_eq!(de.siginfo.ssi_addr, cc.siginfo.ssi_addr);
+        assert_eq!(de.as_bytes(), cc.as_bytes());
+    }
+
+    #[test]
+    fn rejects_mismatches() {
+        let cc = CrashContext::capture();
+        let mut buf = vec![0u8; CrashContext::SERIALIZED_LEN];
+        cc.serialize_into(&mut buf).unwrap();
+
+        assert_eq!(
+            CrashContext::deserialize(&buf[..buf.len() - 1]).err(),
+            Some(DecodeError::InvalidLength)
+        );
+
+        let mut bad = buf.clone();
+        bad[0] = b'X';
+        assert_eq!(
+            CrashContext::deserialize(&bad).err(),
+            Some(DecodeError::InvalidMagic)
+        );
+
+        let mut bad = buf.clone();
+        bad[4..6].copy_from_slice(&(WIRE_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            CrashContext::deserialize(&bad).err(),
+            Some(DecodeError::UnsupportedVersion(WIRE_VERSION + 1))
+        );
+
+        let mut bad = buf.clone();
+        bad[6..8].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert_eq!(
+            CrashContext::deserialize(&bad).err(),
+            Some(DecodeError::ArchMismatch {
+                expected: WIRE_ARCH,
+                actual: 0xffff,
+            })
+        );
+
+        let mut bad = buf.clone();
+        bad[16..20].copy_from_slice(&99u32.to_le_bytes());
+        assert_eq!(
+            CrashContext::deserialize(&bad).err(),
+            Some(DecodeError::InvalidReason(99))
+        );
+
+        let mut bad = buf;
+        bad[624..628].copy_from_slice(&1u32.to_le_bytes());
+        assert_eq!(
+            CrashContext::deserialize(&bad).err(),
+            Some(DecodeError::LayoutMismatch)
+        );
+    }
+}
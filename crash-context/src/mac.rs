@@ -29,4 +29,57 @@ pub struct CrashContext {
     pub handler_thread: mt::thread_t,
     /// Optional exception information
     pub exception: Option<ExceptionInfo>,
+    /// The address of the array of [`crate::Annotation`]s in the crashed
+    /// task, or 0 if there are none
+    pub annotations: u64,
+    /// The number of [`crate::Annotation`]s in the array at [`Self::annotations`]
+    pub annotation_count: u64,
+    /// The address of the ring of [`crate::Breadcrumb`]s in the crashed
+    /// task, or 0 if there are none
+    pub breadcrumbs: u64,
+    /// The number of [`crate::Breadcrumb`]s in the ring at [`Self::breadcrumbs`]
+    pub breadcrumb_count: u64,
+}
+
+impl CrashContext {
+    /// Captures the context of the current thread, without an actual exception
+    /// having been raised.
+    ///
+    /// This is useful for creating "requested" dumps, eg. on an assertion
+    /// failure, via the same pipeline used for actual crashes. The
+    /// [`Self::handler_thread`] is the same as the [`Self::thread`], and there
+    /// is no [`Self::exception`].
+    ///
+    /// The [`Self::thread`] is a new send right owned by the caller, which
+    /// must be released via [`Self::release`] once the context is no longer
+    /// needed, eg. after the dump has been written.
+    pub fn capture() -> Self {
+        // SAFETY: syscalls
+        let (task, thread) = unsafe {
+            (
+                mach2::traps::mach_task_self(),
+                mach2::mach_init::mach_thread_self(),
+            )
+        };
+
+        Self {
+            task,
+            thread,
+            handler_thread: thread,
+            exception: None,
+            annotations: 0,
+            annotation_count: 0,
+            breadcrumbs: 0,
+            breadcrumb_count: 0,
+        }
+    }
+
+    /// Releases the send right to the [`Self::thread`] acquired by
+    /// [`Self::capture`]
+    pub fn release(self) {
+        // SAFETY: syscall, the right is owned by this context
+        unsafe {
+            mach2::mach_port::mach_port_deallocate(mach2::traps::mach_task_self(), self.thread);
+        }
+    }
 }
@@ -44,6 +44,7 @@ pub fn extract_guard_target(code: u64) -> u32 {
 }
 
 /// The extracted details of an `EXC_GUARD` exception
+#[derive(Copy, Clone, Debug)]
 pub struct GuardException {
     /// One of [`GuardKind`]
     pub kind: u8,
@@ -76,6 +77,229 @@ pub fn extract_guard_exception(code: u64, subcode: u64) -> GuardException {
     }
 }
 
+impl GuardException {
+    /// Decodes the [`Self::kind`] and [`Self::flavor`] into the reason the
+    /// guard was violated
+    pub fn reason(&self) -> GuardReason {
+        const MACH_PORT: u8 = GuardKind::MachPort as u8;
+        const FD: u8 = GuardKind::Fd as u8;
+        const USER: u8 = GuardKind::User as u8;
+        const VNODE: u8 = GuardKind::Vnode as u8;
+        const VIRTUAL_MEMORY: u8 = GuardKind::VirtualMemory as u8;
+        const REJECTED_SYSCALL: u8 = GuardKind::RejectedSyscall as u8;
+
+        match self.kind {
+            MACH_PORT => GuardReason::MachPort(Flavor::from(self.flavor)),
+            FD => GuardReason::Fd(Flavor::from(self.flavor)),
+            USER => GuardReason::User,
+            VNODE => GuardReason::Vnode(Flavor::from(self.flavor)),
+            VIRTUAL_MEMORY => GuardReason::VirtualMemory,
+            REJECTED_SYSCALL => GuardReason::RejectedSyscall,
+            kind => GuardReason::Unknown {
+                kind,
+                flavor: self.flavor,
+            },
+        }
+    }
+}
+
+/// The reason an `EXC_GUARD` exception was raised, as decoded from its kind
+/// and flavor
+#[derive(Copy, Clone, Debug)]
+pub enum GuardReason {
+    /// A guarded mach port was misused, [`GuardException::target`] is the
+    /// port name
+    MachPort(Flavor<MachPortFlavor>),
+    /// A guarded file descriptor was misused, eg. closing a file descriptor
+    /// owned by a system library, [`GuardException::target`] is the file
+    /// descriptor
+    Fd(Flavor<FdFlavor>),
+    /// A userland assertion, eg. via `os_fault_with_payload`
+    User,
+    /// A guarded vnode was modified, [`GuardException::target`] is the pid of
+    /// the process that guarded it
+    Vnode(Flavor<VnodeFlavor>),
+    /// A guarded virtual memory operation, eg. deallocating a region that is
+    /// owned by the kernel
+    VirtualMemory,
+    /// A system call that was rejected by the sandbox or filter
+    RejectedSyscall,
+    /// An unknown guard kind due to an addition to the set of possible guard
+    /// kinds in exc_guard.h
+    Unknown {
+        /// The raw [`GuardException::kind`]
+        kind: u8,
+        /// The raw [`GuardException::flavor`]
+        flavor: u32,
+    },
+}
+
+/// Each guard kind has a set of flavors, which are forwarded as is if they
+/// are not known, so that new flavors can still be reported
+#[derive(Copy, Clone, Debug)]
+pub enum Flavor<T: Copy + Clone + std::fmt::Debug> {
+    /// A flavor that is known for the guard kind
+    Known(T),
+    /// A flavor that isn't known for the guard kind, eg. one that was added
+    /// in a later version of the OS
+    Unknown(u32),
+}
+
+impl<T: TryFrom<u32> + Copy + Clone + std::fmt::Debug> From<u32> for Flavor<T> {
+    #[inline]
+    fn from(flavor: u32) -> Self {
+        if let Ok(known) = T::try_from(flavor) {
+            Self::Known(known)
+        } else {
+            Self::Unknown(flavor)
+        }
+    }
+}
+
+impl<T: PartialEq + Copy + Clone + std::fmt::Debug> PartialEq<T> for Flavor<T> {
+    fn eq(&self, o: &T) -> bool {
+        match self {
+            Self::Known(flavor) => flavor == o,
+            Self::Unknown(_) => false,
+        }
+    }
+}
+
+/// Implements `TryFrom<u32>` for a flavor enum from its raw values
+macro_rules! flavor {
+    ($name:ident { $($variant:ident),+ $(,)? }) => {
+        impl TryFrom<u32> for $name {
+            type Error = ();
+
+            fn try_from(flavor: u32) -> Result<Self, Self::Error> {
+                $(if flavor == Self::$variant as u32 {
+                    return Ok(Self::$variant);
+                })+
+
+                Err(())
+            }
+        }
+    };
+}
+
+/// The flavors for a [`GuardReason::MachPort`], see `mach_port_guard_exception_codes`
+/// in `osfmk/mach/port.h`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MachPortFlavor {
+    /// A guarded port was destroyed
+    Destroy = 1,
+    /// The reference count of a guarded port was modified
+    ModRefs = 2,
+    /// The context of a guarded port was modified
+    SetContext = 4,
+    /// An unguarded port was unguarded
+    Unguarded = 1 << 3,
+    /// A guarded port was unguarded with the incorrect guard
+    IncorrectGuard = 1 << 4,
+    /// An immovable port was moved, eg. sending the task port
+    Immovable = 1 << 5,
+    /// A reply port was misused with strict reply semantics
+    StrictReply = 1 << 6,
+    /// A message was rejected by the message filter
+    MsgFiltered = 1 << 7,
+    /// An operation was attempted with an invalid right
+    InvalidRight = 1 << 8,
+    /// An operation was attempted with an invalid port name
+    InvalidName = 1 << 9,
+    /// An operation was attempted with an invalid value
+    InvalidValue = 1 << 10,
+    /// An operation was attempted with an invalid argument
+    InvalidArgument = 1 << 11,
+    /// A right that already exists was inserted
+    RightExists = 1 << 12,
+    /// The port space is full
+    KernNoSpace = 1 << 13,
+    /// A kernel operation on the port failed
+    KernFailure = 1 << 14,
+    /// The kernel ran out of resources
+    KernResource = 1 << 15,
+}
+
+flavor!(MachPortFlavor {
+    Destroy,
+    ModRefs,
+    SetContext,
+    Unguarded,
+    IncorrectGuard,
+    Immovable,
+    StrictReply,
+    MsgFiltered,
+    InvalidRight,
+    InvalidName,
+    InvalidValue,
+    InvalidArgument,
+    RightExists,
+    KernNoSpace,
+    KernFailure,
+    KernResource,
+});
+
+/// The flavors for a [`GuardReason::Fd`], see `bsd/sys/guarded.h`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FdFlavor {
+    /// A guarded file descriptor was closed
+    Close = 1 << 0,
+    /// A guarded file descriptor was duplicated
+    Dup = 1 << 1,
+    /// Close on exec was cleared for a guarded file descriptor
+    NoCloexec = 1 << 2,
+    /// A guarded file descriptor was sent over a socket
+    SocketIpc = 1 << 3,
+    /// A fileport was created for a guarded file descriptor
+    Fileport = 1 << 4,
+    /// A guarded file descriptor was accessed with the wrong guard
+    Mismatch = 1 << 5,
+    /// A guarded file descriptor was written to
+    Write = 1 << 6,
+}
+
+flavor!(FdFlavor {
+    Close,
+    Dup,
+    NoCloexec,
+    SocketIpc,
+    Fileport,
+    Mismatch,
+    Write
+});
+
+/// The flavors for a [`GuardReason::Vnode`], see `bsd/sys/guarded.h`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum VnodeFlavor {
+    /// A file was renamed to the guarded path
+    RenameTo = 1 << 0,
+    /// The guarded file was renamed
+    RenameFrom = 1 << 1,
+    /// The guarded file was unlinked
+    Unlink = 1 << 2,
+    /// The guarded file was written to by another process
+    WriteOther = 1 << 3,
+    /// The guarded file was truncated by another process
+    TruncOther = 1 << 4,
+    /// The guarded file was hard linked
+    Link = 1 << 5,
+    /// The data of the guarded file was exchanged via `exchangedata`
+    Exchdata = 1 << 6,
+}
+
+flavor!(VnodeFlavor {
+    RenameTo,
+    RenameFrom,
+    Unlink,
+    WriteOther,
+    TruncOther,
+    Link,
+    Exchdata
+});
+
 impl super::ExceptionInfo {
     /// If this is an `EXC_GUARD` exception, retrieves the exception metadata
     /// from the code, otherwise returns `None`
@@ -90,3 +314,39 @@ impl super::ExceptionInfo {
         ))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decodes_reason() {
+        // close() of a guarded fd 3
+        let exc = extract_guard_exception((2 << 61) | (1 << 32) | 3, 0xdead);
+        assert!(matches!(exc.reason(), GuardReason::Fd(flavor) if flavor == FdFlavor::Close));
+        assert_eq!(exc.target, 3);
+        assert_eq!(exc.identifier, 0xdead);
+
+        let exc = extract_guard_exception((4 << 61) | (1 << 34), 0);
+        assert!(
+            matches!(exc.reason(), GuardReason::Vnode(flavor) if flavor == VnodeFlavor::Unlink)
+        );
+
+        let exc = extract_guard_exception((1 << 61) | (1 << 40), 0);
+        assert!(
+            matches!(exc.reason(), GuardReason::MachPort(flavor) if flavor == MachPortFlavor::InvalidRight)
+        );
+
+        // Flavors that are unknown are still reported
+        let exc = extract_guard_exception((1 << 61) | (1 << 52), 0);
+        assert!(matches!(
+            exc.reason(),
+            GuardReason::MachPort(Flavor::Unknown(0x10_0000))
+        ));
+        let exc = extract_guard_exception(7 << 61, 0);
+        assert!(matches!(
+            exc.reason(),
+            GuardReason::Unknown { kind: 7, flavor: 0 }
+        ));
+    }
+}
@@ -98,6 +98,14 @@ struct CrashContextMessage {
     exception_code: u64,
     /// The optional exception subcode
     exception_subcode: u64,
+    /// The address of the annotations in the crashed task
+    annotations: u64,
+    /// The number of annotations
+    annotation_count: u64,
+    /// The address of the breadcrumbs in the crashed task
+    breadcrumbs: u64,
+    /// The number of breadcrumbs
+    breadcrumb_count: u64,
     /// We don't actually send this, but it's tacked on by the kernel :(
     trailer: MachMsgTrailer,
 }
@@ -255,6 +263,10 @@ impl Client {
                 exception_kind,
                 exception_code,
                 exception_subcode,
+                annotations: ctx.annotations,
+                annotation_count: ctx.annotation_count,
+                breadcrumbs: ctx.breadcrumbs,
+                breadcrumb_count: ctx.breadcrumb_count,
                 // We don't actually send this but I didn't feel like making
                 // two types
                 trailer: MachMsgTrailer { kind: 0, size: 8 },
@@ -386,6 +398,10 @@ impl Server {
                 thread: crash_ctx_msg.crash_thread.name,
                 handler_thread: crash_ctx_msg.handler_thread.name,
                 exception,
+                annotations: crash_ctx_msg.annotations,
+                annotation_count: crash_ctx_msg.annotation_count,
+                breadcrumbs: crash_ctx_msg.breadcrumbs,
+                breadcrumb_count: crash_ctx_msg.breadcrumb_count,
             };
 
             // Translate the task to a pid so the user doesn't have to do it
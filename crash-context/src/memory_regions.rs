@@ -0,0 +1,87 @@
+/// The maximum number of memory regions that can be included at any one time
+pub const MAX_MEMORY_REGIONS: usize = 32;
+/// The maximum number of memory regions that can be redacted at any one time
+pub const MAX_REDACTED_REGIONS: usize = 32;
+/// The total number of [`MemoryRegion`] slots, the first [`MAX_MEMORY_REGIONS`]
+/// of which are included regions, followed by [`MAX_REDACTED_REGIONS`]
+/// redacted regions
+pub const MAX_MEMORY_REGION_SLOTS: usize = MAX_MEMORY_REGIONS + MAX_REDACTED_REGIONS;
+/// The maximum length of a memory region tag, in bytes
+pub const MAX_MEMORY_REGION_TAG_LEN: usize = 32;
+
+/// The [`MemoryRegion::state`] of a slot that doesn't contain a region
+pub const MEMORY_REGION_EMPTY: u32 = 0;
+/// The [`MemoryRegion::state`] of a slot that is in the middle of being
+/// written, and thus may be torn
+pub const MEMORY_REGION_WRITING: u32 = 1;
+/// The [`MemoryRegion::state`] of a slot that contains a valid region
+pub const MEMORY_REGION_VALID: u32 = 2;
+
+/// The [`MemoryRegion::flags`] of a region whose memory must be zero filled
+/// wherever it appears in a minidump, rather than included
+pub const MEMORY_REGION_REDACTED: u16 = 0x1;
+
+/// The type of the minidump stream that records the tag of each memory region
+/// that was written to the `MemoryListStream`.
+///
+/// The stream is a `u32` count, followed by that many entries, each of which
+/// is the `u64` start address and `u64` size of the memory that was written,
+/// followed by the tag, padded with zeroes to [`MAX_MEMORY_REGION_TAG_LEN`] bytes,
+/// all little endian.
+pub const MEMORY_REGION_TAGS_STREAM: u32 = 0x4d52_0001;
+
+/// A single memory region slot, as it is laid out in the memory of the
+/// crashing process.
+///
+/// The crashing process keeps a fixed size array of [`MAX_MEMORY_REGION_SLOTS`]
+/// of these, whose location is recorded in the crash context so that the
+/// memory they describe can be included in, or redacted from, a minidump of
+/// the crashing process.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MemoryRegion {
+    /// Whether the slot is empty, being written, or valid
+    pub state: u32,
+    /// The length of the tag in [`Self::tag`]
+    pub tag_len: u16,
+    /// [`MEMORY_REGION_REDACTED`] if the region is redacted rather than included
+    pub flags: u16,
+    /// The start address of the region
+    pub address: u64,
+    /// The size of the region, in bytes
+    pub size: u64,
+    /// Identifies the region, which is utf-8
+    pub tag: [u8; MAX_MEMORY_REGION_TAG_LEN],
+}
+
+impl MemoryRegion {
+    /// An empty slot
+    pub const EMPTY: Self = Self {
+        state: MEMORY_REGION_EMPTY,
+        tag_len: 0,
+        flags: 0,
+        address: 0,
+        size: 0,
+        tag: [0; MAX_MEMORY_REGION_TAG_LEN],
+    };
+
+    /// Retrieves the tag, if the slot contains a valid included region
+    #[inline]
+    pub fn tag(&self) -> Option<&str> {
+        if self.state != MEMORY_REGION_VALID || self.flags & MEMORY_REGION_REDACTED != 0 {
+            return None;
+        }
+
+        std::str::from_utf8(self.tag.get(..self.tag_len as usize)?).ok()
+    }
+
+    /// Retrieves the address range, if the slot contains a valid redacted region
+    #[inline]
+    pub fn redacted(&self) -> Option<std::ops::Range<u64>> {
+        if self.state != MEMORY_REGION_VALID || self.flags & MEMORY_REGION_REDACTED == 0 {
+            return None;
+        }
+
+        Some(self.address..self.address.saturating_add(self.size))
+    }
+}
@@ -0,0 +1,28 @@
+/// The type of the minidump stream that records the file descriptors that
+/// were open in the crashed process when the minidump was written.
+///
+/// The stream is the `u32` number of file descriptors the process had open,
+/// and the `u32` number of entries that follow, which is lower if there were
+/// too many to record. Each entry is the `i32` file descriptor, the `u32`
+/// kind, which is one of the `OPEN_FILE_*` constants, the `u64` inode of
+/// sockets and pipes, or 0, and the `u32` length of the UTF-8 description
+/// that follows, all little endian.
+///
+/// The description is the path of files, or a description of the protocol,
+/// addresses, and state of sockets that could be resolved, eg.
+/// `tcp 127.0.0.1:8080 -> 127.0.0.1:51234 ESTABLISHED`, otherwise it is the
+/// target of the `/proc/<pid>/fd` link, eg. `socket:[1234]`.
+pub const OPEN_FILES_STREAM: u32 = 0x4d52_0003;
+
+/// The kind of an open file that isn't one of the other kinds
+pub const OPEN_FILE_OTHER: u32 = 0;
+/// The kind of an open file that is a path in the file system, including
+/// devices
+pub const OPEN_FILE_PATH: u32 = 1;
+/// The kind of an open file that is a socket
+pub const OPEN_FILE_SOCKET: u32 = 2;
+/// The kind of an open file that is an anonymous pipe
+pub const OPEN_FILE_PIPE: u32 = 3;
+/// The kind of an open file that has an anonymous inode, eg. an `eventfd`,
+/// `epoll`, or `timerfd`
+pub const OPEN_FILE_ANON_INODE: u32 = 4;
@@ -0,0 +1,52 @@
+use std::time::{Duration, SystemTime};
+
+/// The time at which a crash was captured, so that it can be correlated with
+/// logs and other events even if the crash is only processed much later.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CrashTime {
+    /// `CLOCK_MONOTONIC`, in nanoseconds, which is unaffected by changes to
+    /// the system clock, but is only meaningful on the same boot of the same
+    /// machine
+    pub monotonic: u64,
+    /// `CLOCK_REALTIME`, in nanoseconds since the unix epoch
+    pub wall: u64,
+}
+
+impl CrashTime {
+    /// Retrieves the current time.
+    ///
+    /// This is async signal safe, as it only uses `clock_gettime`.
+    #[inline]
+    pub fn now() -> Self {
+        Self {
+            monotonic: clock_nanos(libc::CLOCK_MONOTONIC),
+            wall: clock_nanos(libc::CLOCK_REALTIME),
+        }
+    }
+
+    /// Retrieves [`Self::wall`] as a [`SystemTime`], or `None` if the time
+    /// wasn't captured
+    #[inline]
+    pub fn wall_time(&self) -> Option<SystemTime> {
+        (self.wall != 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_nanos(self.wall))
+    }
+}
+
+/// Retrieves the time of the clock in nanoseconds, or 0 if it is unavailable
+#[inline]
+fn clock_nanos(clock: libc::clockid_t) -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    // SAFETY: syscall
+    if unsafe { libc::clock_gettime(clock, &mut ts) } != 0 {
+        return 0;
+    }
+
+    (ts.tv_sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(ts.tv_nsec as u64)
+}
@@ -16,4 +16,76 @@ pub struct CrashContext {
     pub process_id: u32,
     /// The thread id on which the exception occurred
     pub thread_id: u32,
+    /// The reason for the crash, as determined from the exception record in
+    /// the crashing process
+    pub reason: CrashReason,
+}
+
+/// `STATUS_HEAP_CORRUPTION`, raised when the heap manager detects corruption
+pub const STATUS_HEAP_CORRUPTION: i32 = 0xc000_0374_u32 as i32;
+/// `STATUS_STACK_BUFFER_OVERRUN`, which despite its name is raised for every
+/// [`__fastfail`](https://docs.microsoft.com/en-us/cpp/intrinsics/fastfail),
+/// with the fast fail code as the first exception parameter
+pub const STATUS_STACK_BUFFER_OVERRUN: i32 = 0xc000_0409_u32 as i32;
+/// `STATUS_FAIL_FAST_EXCEPTION`, raised by `RaiseFailFastException`
+pub const STATUS_FAIL_FAST_EXCEPTION: i32 = 0xc000_0602_u32 as i32;
+
+/// The `__fastfail` code for a `/GS` stack cookie check failure, ie. an actual
+/// stack buffer overrun
+pub const FAST_FAIL_STACK_COOKIE_CHECK_FAILURE: u32 = 2;
+/// The `__fastfail` code for a corrupted doubly linked list, eg. in the heap
+pub const FAST_FAIL_CORRUPT_LIST_ENTRY: u32 = 3;
+/// The `__fastfail` code for an invalid argument
+pub const FAST_FAIL_INVALID_ARG: u32 = 5;
+/// The `__fastfail` code used by eg. `abort` and Rust's `std::process::abort`
+pub const FAST_FAIL_FATAL_APP_EXIT: u32 = 7;
+/// The `__fastfail` code for a Control Flow Guard check failure
+pub const FAST_FAIL_GUARD_ICALL_CHECK_FAILURE: u32 = 10;
+
+/// The reason a crash occurred, beyond what is described by the exception
+/// code itself.
+///
+/// Fail fast exceptions bypass frame based exception handlers, and usually
+/// the unhandled exception filter as well, and are thus normally only seen
+/// via a vectored exception handler or a [WER module](https://docs.microsoft.com/en-us/windows/win32/api/werapi/nf-werapi-werregisterruntimeexceptionmodule).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CrashReason {
+    /// The crash is fully described by [`CrashContext::exception_code`]
+    Exception,
+    /// The heap manager detected that the heap is corrupted
+    HeapCorruption,
+    /// A `/GS` stack cookie check failed, ie. a buffer on the stack was
+    /// overrun
+    StackBufferOverrun,
+    /// The process was terminated via `__fastfail` or `RaiseFailFastException`
+    /// with the specified code, eg. [`FAST_FAIL_FATAL_APP_EXIT`]
+    FailFast(u32),
+}
+
+impl CrashReason {
+    /// Determines the reason from the exception code and, for fail fast
+    /// exceptions, the fast fail code, which is the first exception parameter
+    #[inline]
+    pub fn from_exception(exception_code: i32, fast_fail_code: Option<u32>) -> Self {
+        match exception_code {
+            STATUS_HEAP_CORRUPTION => Self::HeapCorruption,
+            STATUS_STACK_BUFFER_OVERRUN => match fast_fail_code {
+                // The legacy code, 0, is also a stack cookie check failure
+                None | Some(0 | FAST_FAIL_STACK_COOKIE_CHECK_FAILURE) => Self::StackBufferOverrun,
+                Some(code) => Self::FailFast(code),
+            },
+            STATUS_FAIL_FAST_EXCEPTION => Self::FailFast(fast_fail_code.unwrap_or_default()),
+            _ => Self::Exception,
+        }
+    }
+
+    /// Retrieves the fast fail code, if this is a fail fast exception
+    #[inline]
+    pub fn fast_fail_code(self) -> Option<u32> {
+        match self {
+            Self::StackBufferOverrun => Some(FAST_FAIL_STACK_COOKIE_CHECK_FAILURE),
+            Self::FailFast(code) => Some(code),
+            Self::Exception | Self::HeapCorruption => None,
+        }
+    }
 }
@@ -0,0 +1,79 @@
+/* C API for the crash-handler crate, see crash-handler-capi/src/lib.rs for the
+ * full documentation of each item */
+
+#ifndef CRASH_HANDLER_H
+#define CRASH_HANDLER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The details of a crash passed to a ch_crash_callback */
+typedef struct ch_crash_context {
+    /* The signal number on Linux/Android and the BSDs, the exception code on
+     * Windows, and the exception kind on Mac, or 0 if there was no
+     * exception */
+    int32_t code;
+    /* The id of the crashing process */
+    uint32_t pid;
+    /* The id of the crashing thread, which is the thread's mach port on Mac */
+    uint64_t tid;
+    /* The platform specific crash_handler::CrashContext, which can be passed
+     * back to Rust code, eg. to write a minidump */
+    const void *raw;
+} ch_crash_context;
+
+/* The result of a ch_crash_callback, any value other than the ones below is
+ * treated as CH_RERAISE */
+typedef int32_t ch_crash_result;
+
+/* The crash is passed on to the previously installed handler, or the default
+ * disposition if there is none */
+#define CH_RERAISE ((ch_crash_result)0)
+/* The crash was handled, and the process is terminated */
+#define CH_HANDLED ((ch_crash_result)1)
+/* Execution of the crashing thread is resumed */
+#define CH_CONTINUE ((ch_crash_result)2)
+
+/* The callback invoked when a crash occurs, along with the user_data that was
+ * passed to ch_attach */
+typedef ch_crash_result (*ch_crash_callback)(const ch_crash_context *context, void *user_data);
+
+/* The result of ch_attach */
+typedef int32_t ch_error;
+
+/* The handler was attached */
+#define CH_OK ((ch_error)0)
+/* The callback was null */
+#define CH_INVALID_ARGUMENT ((ch_error)1)
+/* A handler is already attached via ch_attach */
+#define CH_ALREADY_ATTACHED ((ch_error)2)
+/* Memory for the handler could not be allocated */
+#define CH_OUT_OF_MEMORY ((ch_error)3)
+/* Attaching failed for any other reason, eg. a syscall failed */
+#define CH_OTHER ((ch_error)4)
+
+/* Attaches a crash handler that invokes the callback when a crash occurs.
+ *
+ * Only a single handler can be attached via this function, so this fails with
+ * CH_ALREADY_ATTACHED if one was already attached. Handlers attached by Rust
+ * code can be attached at the same time, in which case they are invoked in
+ * order of their priority, highest first, and only handlers with the same
+ * priority are invoked in the reverse order they were attached in. The handler
+ * attached via this function has the default priority of 0.
+ *
+ * The callback runs in a compromised context, eg. a signal handler, so it
+ * should do as little work as possible, and the user_data must be valid to
+ * use from any thread until the handler is detached. */
+ch_error ch_attach(ch_crash_callback callback, void *user_data);
+
+/* Detaches the handler attached via ch_attach, if there is one */
+void ch_detach(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRASH_HANDLER_H */
@@ -0,0 +1,161 @@
+#![doc = include_str!("../README.md")]
+#![allow(unsafe_code, non_camel_case_types)]
+
+use crash_handler::{CrashContext, CrashEventResult, CrashHandler};
+use std::ffi::c_void;
+
+/// The details of a crash passed to a [`ch_crash_callback`]
+#[repr(C)]
+pub struct ch_crash_context {
+    /// The signal number on Linux/Android and the BSDs, the exception code on
+    /// Windows, and the exception kind on Mac, or 0 if there was no
+    /// exception
+    pub code: i32,
+    /// The id of the crashing process
+    pub pid: u32,
+    /// The id of the crashing thread, which is the thread's mach port on Mac
+    pub tid: u64,
+    /// The platform specific [`crash_handler::CrashContext`], which can be
+    /// passed back to Rust code, eg. to write a minidump
+    pub raw: *const c_void,
+}
+
+impl ch_crash_context {
+    fn new(cc: &CrashContext) -> Self {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let (code, pid, tid) = (cc.siginfo.ssi_signo as i32, cc.pid as u32, cc.tid as u64);
+            } else if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+                let (code, pid, tid) = (cc.siginfo.si_signo, cc.pid as u32, cc.tid as u64);
+            } else if #[cfg(target_os = "windows")] {
+                let (code, pid, tid) = (cc.exception_code, cc.process_id, u64::from(cc.thread_id));
+            } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
+                // Exceptions are only ever handled for our own task
+                let (code, pid, tid) = (
+                    cc.exception.map_or(0, |exc| exc.kind as i32),
+                    std::process::id(),
+                    u64::from(cc.thread),
+                );
+            }
+        }
+
+        Self {
+            code,
+            pid,
+            tid,
+            raw: (cc as *const CrashContext).cast(),
+        }
+    }
+}
+
+/// The crash is passed on to the previously installed handler, or the
+/// default disposition if there is none
+pub const CH_RERAISE: ch_crash_result = 0;
+/// The crash was handled, and the process is terminated, see
+/// [`CrashEventResult::Handled`]
+pub const CH_HANDLED: ch_crash_result = 1;
+/// Execution of the crashing thread is resumed, see
+/// [`CrashEventResult::Continue`]
+pub const CH_CONTINUE: ch_crash_result = 2;
+
+/// The result of a [`ch_crash_callback`], one of [`CH_RERAISE`],
+/// [`CH_HANDLED`], or [`CH_CONTINUE`]. Any other value is treated as
+/// [`CH_RERAISE`].
+pub type ch_crash_result = i32;
+
+/// The callback invoked when a crash occurs, along with the `user_data` that
+/// was passed to [`ch_attach`]
+pub type ch_crash_callback =
+    Option<unsafe extern "C" fn(*const ch_crash_context, *mut c_void) -> ch_crash_result>;
+
+/// The handler was attached
+pub const CH_OK: ch_error = 0;
+/// The callback was null
+pub const CH_INVALID_ARGUMENT: ch_error = 1;
+/// A handler is already attached via [`ch_attach`]
+pub const CH_ALREADY_ATTACHED: ch_error = 2;
+/// Memory for the handler could not be allocated
+pub const CH_OUT_OF_MEMORY: ch_error = 3;
+/// Attaching failed for any other reason, eg. a syscall failed
+pub const CH_OTHER: ch_error = 4;
+
+/// The result of [`ch_attach`]
+pub type ch_error = i32;
+
+struct Callback {
+    callback: unsafe extern "C" fn(*const ch_crash_context, *mut c_void) -> ch_crash_result,
+    user_data: *mut c_void,
+}
+
+// SAFETY: the user data is only ever passed back to the callback, it's up to
+// the C code to ensure it can be used from whichever thread crashes
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+unsafe impl crash_handler::CrashEvent for Callback {
+    fn on_crash(&self, context: &CrashContext) -> CrashEventResult {
+        let cc = ch_crash_context::new(context);
+
+        // SAFETY: the callback was provided by the user via `ch_attach`
+        match unsafe { (self.callback)(&cc, self.user_data) } {
+            CH_HANDLED => CrashEventResult::Handled { exit: None },
+            CH_CONTINUE => CrashEventResult::Continue,
+            _ => CrashEventResult::Reraise,
+        }
+    }
+}
+
+/// The handler attached via [`ch_attach`]
+static HANDLER: parking_lot::Mutex<Option<CrashHandler>> = parking_lot::const_mutex(None);
+
+/// Attaches a crash handler that invokes the callback when a crash occurs.
+///
+/// Only a single handler can be attached via this function, so this fails
+/// with [`CH_ALREADY_ATTACHED`] if one was already attached. Handlers attached
+/// by Rust code can be attached at the same time, see [`CrashHandler::attach`]
+/// for the order they are invoked in. The handler attached via this function
+/// has the [`crash_handler::DEFAULT_PRIORITY`].
+///
+/// # Safety
+///
+/// The callback runs in a compromised context, see [`crash_handler::CrashEvent`],
+/// and the `user_data` must be valid to use from any thread until the handler
+/// is detached via [`ch_detach`].
+#[no_mangle]
+pub unsafe extern "C" fn ch_attach(
+    callback: ch_crash_callback,
+    user_data: *mut c_void,
+) -> ch_error {
+    let Some(callback) = callback else {
+        return CH_INVALID_ARGUMENT;
+    };
+
+    let mut handler = HANDLER.lock();
+    if handler.is_some() {
+        return CH_ALREADY_ATTACHED;
+    }
+
+    match CrashHandler::attach(Box::new(Callback {
+        callback,
+        user_data,
+    })) {
+        Ok(attached) => {
+            *handler = Some(attached);
+            CH_OK
+        }
+        Err(crash_handler::Error::HandlerAlreadyInstalled) => CH_ALREADY_ATTACHED,
+        Err(crash_handler::Error::OutOfMemory) => CH_OUT_OF_MEMORY,
+        Err(_) => CH_OTHER,
+    }
+}
+
+/// Detaches the handler attached via [`ch_attach`], if there is one.
+///
+/// Once this returns, the callback is no longer invoked and the `user_data`
+/// passed to [`ch_attach`] is no longer used.
+#[no_mangle]
+pub extern "C" fn ch_detach() {
+    // Take the handler first so that the lock isn't held while detaching
+    let handler = HANDLER.lock().take();
+    drop(handler);
+}
@@ -0,0 +1,35 @@
+#![allow(unsafe_code)]
+
+use crash_handler_capi::*;
+use std::ffi::c_void;
+
+unsafe extern "C" fn on_crash(
+    _cc: *const ch_crash_context,
+    _user_data: *mut c_void,
+) -> ch_crash_result {
+    CH_RERAISE
+}
+
+#[test]
+fn attaches_once() {
+    unsafe {
+        assert_eq!(ch_attach(None, std::ptr::null_mut()), CH_INVALID_ARGUMENT);
+        assert_eq!(ch_attach(Some(on_crash), std::ptr::null_mut()), CH_OK);
+        assert_eq!(
+            ch_attach(Some(on_crash), std::ptr::null_mut()),
+            CH_ALREADY_ATTACHED
+        );
+
+        // Rust code can still attach its own handlers alongside it
+        let rust_handler =
+            crash_handler::CrashHandler::attach(crash_handler::make_crash_event(|_| {
+                crash_handler::CrashEventResult::Reraise
+            }))
+            .unwrap();
+        drop(rust_handler);
+
+        ch_detach();
+        assert_eq!(ch_attach(Some(on_crash), std::ptr::null_mut()), CH_OK);
+        ch_detach();
+    }
+}
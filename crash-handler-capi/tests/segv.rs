@@ -0,0 +1,41 @@
+#![allow(unsafe_code)]
+
+use crash_handler_capi::*;
+use std::ffi::c_void;
+
+const USER_DATA: usize = 0xdead;
+
+unsafe extern "C" fn on_crash(
+    cc: *const ch_crash_context,
+    user_data: *mut c_void,
+) -> ch_crash_result {
+    let cc = &*cc;
+
+    assert_eq!(user_data as usize, USER_DATA);
+    assert_eq!(cc.pid, std::process::id());
+    assert!(!cc.raw.is_null());
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            // EXCEPTION_ACCESS_VIOLATION
+            assert_eq!(cc.code, 0xc0000005u32 as i32);
+        } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
+            // EXC_BAD_ACCESS
+            assert_eq!(cc.code, 1);
+        } else {
+            assert_eq!(cc.code, libc::SIGSEGV);
+        }
+    }
+
+    // See crash-handler/tests/shared.rs
+    #[allow(clippy::exit)]
+    std::process::exit(0);
+}
+
+#[test]
+fn handles_segv() {
+    unsafe {
+        assert_eq!(ch_attach(Some(on_crash), USER_DATA as *mut c_void), CH_OK);
+        sadness_generator::raise_segfault();
+    }
+}
@@ -0,0 +1,10 @@
+fn main() {
+    // Exports the stand-in for AddressSanitizer's runtime defined by the
+    // sanitizer tests, so that it can be found via `dlsym` as the real one is
+    if matches!(
+        std::env::var("CARGO_CFG_TARGET_OS").as_deref(),
+        Ok("linux" | "android")
+    ) {
+        println!("cargo:rustc-link-arg-tests=-Wl,--export-dynamic-symbol=__asan_init");
+    }
+}
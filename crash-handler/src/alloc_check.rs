@@ -0,0 +1,100 @@
+//! Detection of heap allocations made while handling a signal.
+//!
+//! Nothing in the signal handling path is allowed to allocate once the
+//! handler is attached, as the crash could have occurred within the allocator
+//! itself, eg. due to heap corruption, or while another thread held one of its
+//! locks. [`DetectingAllocator`] can be used as the `#[global_allocator]` of a
+//! test binary to enforce this, as it aborts the process if memory is
+//! allocated or freed by this crate while a signal is being handled.
+//!
+//! Allocations made by the user's [`crate::CrashEvent`], or by a previously
+//! installed handler we chain to, are not our responsibility and are allowed.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    /// Whether the current thread is not allowed to allocate
+    static FORBIDDEN: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Forbids allocations on the current thread until it is dropped, at which
+/// point the previous state is restored
+pub(crate) struct Forbid(bool);
+
+impl Forbid {
+    #[inline]
+    pub(crate) fn enter() -> Self {
+        Self(FORBIDDEN.with(|forbidden| forbidden.replace(true)))
+    }
+
+    /// Allows allocations until the returned guard is dropped, eg. while
+    /// running user code
+    #[inline]
+    pub(crate) fn allow() -> Self {
+        Self(FORBIDDEN.with(|forbidden| forbidden.replace(false)))
+    }
+}
+
+impl Drop for Forbid {
+    #[inline]
+    fn drop(&mut self) {
+        FORBIDDEN.with(|forbidden| forbidden.set(self.0));
+    }
+}
+
+/// A global allocator that forwards to [`System`], but aborts if it is used
+/// by this crate while a signal is being handled
+pub struct DetectingAllocator;
+
+impl DetectingAllocator {
+    #[inline]
+    fn check(op: &str) {
+        // The thread local may already be destroyed if the thread is exiting,
+        // in which case it can't be handling a signal either
+        if FORBIDDEN
+            .try_with(|forbidden| forbidden.replace(false))
+            .unwrap_or(false)
+        {
+            crate::write_stderr("crash-handler: heap ");
+            crate::write_stderr(op);
+            crate::write_stderr(" while handling a signal\n");
+
+            // Don't give our own handler a chance to allocate again
+            // SAFETY: syscalls
+            unsafe {
+                libc::signal(libc::SIGABRT, libc::SIG_DFL);
+                libc::abort();
+            }
+        }
+    }
+}
+
+// SAFETY: every method forwards to the system allocator
+unsafe impl GlobalAlloc for DetectingAllocator {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::check("allocation");
+        System.alloc(layout)
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::check("allocation");
+        System.alloc_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Self::check("deallocation");
+        System.dealloc(ptr, layout);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::check("reallocation");
+        System.realloc(ptr, layout, new_size)
+    }
+}
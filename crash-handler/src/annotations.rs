@@ -0,0 +1,164 @@
+//! Key/value annotations that are attached to crashes.
+//!
+//! Annotations allow application metadata, eg. the version, a user id, or the
+//! feature flags that are enabled, to be attached to a crash without
+//! needing to allocate or serialize anything at the time of the crash. They
+//! are stored in a fixed size array of [`crash_context::Annotation`] slots,
+//! whose location is recorded in the [`crate::CrashContext`] on Linux/Android,
+//! FreeBSD/OpenBSD, and Macos so that a process handling the crash can read
+//! them from the memory of the crashed process.
+//!
+//! New keys are stored in the slots as a ring, so once all
+//! [`crash_context::MAX_ANNOTATIONS`] slots have been used, setting a new key
+//! evicts the key that was added the longest time ago.
+//!
+//! ```
+//! crash_handler::annotations::set("version", env!("CARGO_PKG_VERSION"));
+//! ```
+
+use crash_context::{
+    Annotation, ANNOTATION_EMPTY, ANNOTATION_VALID, ANNOTATION_WRITING, MAX_ANNOTATIONS,
+    MAX_ANNOTATION_KEY_LEN, MAX_ANNOTATION_VALUE_LEN,
+};
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+struct Slots([UnsafeCell<Annotation>; MAX_ANNOTATIONS]);
+
+// SAFETY: Slots are only written while holding the `NEXT` lock, and readers
+// check the sequence number of each slot before and after reading it
+unsafe impl Sync for Slots {}
+
+static SLOTS: Slots = Slots([const { UnsafeCell::new(Annotation::EMPTY) }; MAX_ANNOTATIONS]);
+/// The sequence number of each slot, which is odd while the slot is being
+/// written, so that readers can detect if a slot was modified while they were
+/// reading it
+static SEQUENCES: [AtomicUsize; MAX_ANNOTATIONS] = [const { AtomicUsize::new(0) }; MAX_ANNOTATIONS];
+/// The slot the next new key is stored in
+static NEXT: parking_lot::Mutex<usize> = parking_lot::const_mutex(0);
+
+/// Retrieves the state of the slot
+#[inline]
+fn state(slot: &UnsafeCell<Annotation>) -> &AtomicU32 {
+    // SAFETY: the pointer is valid, aligned, and only ever accessed atomically
+    unsafe { AtomicU32::from_ptr(std::ptr::addr_of_mut!((*slot.get()).state)) }
+}
+
+/// Sets the value of the specified key, adding it if it doesn't exist.
+///
+/// Keys longer than [`crash_context::MAX_ANNOTATION_KEY_LEN`] and values
+/// longer than [`crash_context::MAX_ANNOTATION_VALUE_LEN`] bytes are truncated.
+pub fn set(key: &str, value: &str) {
+    let key = truncate(key, MAX_ANNOTATION_KEY_LEN);
+    let value = truncate(value, MAX_ANNOTATION_VALUE_LEN);
+
+    let mut next = NEXT.lock();
+
+    let index = if let Some(index) = find(key) {
+        index
+    } else {
+        let index = *next;
+        *next = (*next + 1) % MAX_ANNOTATIONS;
+        index
+    };
+
+    let slot = &SLOTS.0[index];
+    let state = state(slot);
+    SEQUENCES[index].fetch_add(1, Ordering::AcqRel);
+    state.store(ANNOTATION_WRITING, Ordering::Release);
+
+    // SAFETY: we hold the lock, and readers will ignore the slot while it is
+    // being written
+    unsafe {
+        let annotation = &mut *slot.get();
+        annotation.key[..key.len()].copy_from_slice(key.as_bytes());
+        annotation.key_len = key.len() as u16;
+        annotation.value[..value.len()].copy_from_slice(value.as_bytes());
+        annotation.value_len = value.len() as u16;
+    }
+
+    state.store(ANNOTATION_VALID, Ordering::Release);
+    SEQUENCES[index].fetch_add(1, Ordering::AcqRel);
+}
+
+/// Removes the specified key, if it exists
+pub fn remove(key: &str) {
+    let key = truncate(key, MAX_ANNOTATION_KEY_LEN);
+
+    let _next = NEXT.lock();
+    if let Some(index) = find(key) {
+        state(&SLOTS.0[index]).store(ANNOTATION_EMPTY, Ordering::Release);
+    }
+}
+
+/// Removes every annotation
+pub fn clear() {
+    let mut next = NEXT.lock();
+    for slot in &SLOTS.0 {
+        state(slot).store(ANNOTATION_EMPTY, Ordering::Release);
+    }
+    *next = 0;
+}
+
+/// Invokes the callback for every annotation that is currently set.
+///
+/// This does not take any locks or allocate, and is thus safe to call from
+/// within a [`crate::CrashEvent`], however annotations that are being set
+/// concurrently are skipped.
+pub fn for_each(mut cb: impl FnMut(&str, &str)) {
+    for (slot, sequence) in SLOTS.0.iter().zip(&SEQUENCES) {
+        let before = sequence.load(Ordering::Acquire);
+        if before % 2 != 0 {
+            continue;
+        }
+
+        // SAFETY: we copy the slot before checking that it was not modified
+        // while we were reading it
+        let annotation = unsafe { std::ptr::read_volatile(slot.get()) };
+        if sequence.load(Ordering::Acquire) != before {
+            continue;
+        }
+
+        if let Some((key, value)) = annotation.get() {
+            cb(key, value);
+        }
+    }
+}
+
+/// Retrieves the address and number of the annotation slots, which are
+/// recorded in the [`crate::CrashContext`]
+#[inline]
+pub(crate) fn location() -> (usize, usize) {
+    (SLOTS.0.as_ptr() as usize, MAX_ANNOTATIONS)
+}
+
+/// Finds the index of the valid slot with the specified key. Must be called
+/// with the lock held
+fn find(key: &str) -> Option<usize> {
+    SLOTS.0.iter().position(|slot| {
+        if state(slot).load(Ordering::Acquire) != ANNOTATION_VALID {
+            return false;
+        }
+
+        // SAFETY: slots are only modified while the lock is held
+        let annotation = unsafe { &*slot.get() };
+        &annotation.key[..annotation.key_len as usize] == key.as_bytes()
+    })
+}
+
+/// Truncates the string to at most `max` bytes, on a char boundary
+#[inline]
+pub(crate) fn truncate(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+
+    let mut len = max;
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    &s[..len]
+}
@@ -0,0 +1,235 @@
+//! Files for crash output, eg. a minidump written from within the crashing
+//! process, that are opened before the crash.
+//!
+//! Opening a file by path from within a crash handler is best avoided, as it
+//! can fail because of a seccomp filter or exhausted file descriptors, follow
+//! a symlink that was planted in the meantime, and the path itself has to be
+//! built somewhere. A [`CrashFile`] instead opens the directory and an
+//! unnamed file within it via `O_TMPFILE` up front, eg. when the handler is
+//! attached, so the handler only needs to write to the file and then
+//! [finalize](CrashFile::finalize) it, which gives it its name via `linkat`.
+//! If the process never crashes, the file disappears once it is closed,
+//! without ever being visible in the directory.
+//!
+//! If the filesystem doesn't support `O_TMPFILE`, the file is instead
+//! created with a hidden temporary name, which is renamed when finalized, and
+//! removed when the [`CrashFile`] is dropped otherwise.
+//!
+//! ```no_run
+//! use crash_handler::{artifact::CrashFile, write::write_bytes};
+//! use std::os::fd::AsRawFd;
+//!
+//! let file = CrashFile::create(
+//!     std::env::temp_dir(),
+//!     &format!("crash-{}.txt", std::process::id()),
+//! )
+//! .unwrap();
+//!
+//! let _handler = crash_handler::CrashHandler::attach(unsafe {
+//!     crash_handler::make_crash_event(move |_cc| {
+//!         let _ = write_bytes(file.as_raw_fd(), b"crashed\n");
+//!         let _ = file.finalize();
+//!         crash_handler::CrashEventResult::Handled { exit: None }
+//!     })
+//! })
+//! .unwrap();
+//! ```
+
+use crate::Error;
+use std::{
+    ffi::CString,
+    fs::File,
+    io,
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// A file that is opened before a crash and named once it has been written,
+/// see the [module documentation](self)
+pub struct CrashFile {
+    dir: File,
+    file: File,
+    /// The name the file is given when it is finalized
+    name: CString,
+    /// The path of the file in `/proc/self/fd`, which is linked to the name
+    /// if the file was opened with `O_TMPFILE` and linking it via
+    /// `AT_EMPTY_PATH` isn't permitted
+    proc_path: CString,
+    /// The name the file was created with if `O_TMPFILE` isn't supported
+    temp_name: Option<CString>,
+    finalized: AtomicBool,
+}
+
+impl CrashFile {
+    /// Opens the directory at the specified path, and an unnamed file within
+    /// it, which is given the specified name once it is
+    /// [finalized](Self::finalize).
+    ///
+    /// The name should be unique, eg. by including the pid, as finalizing
+    /// fails if a file with the name already exists, or, if `O_TMPFILE` isn't
+    /// supported, replaces it.
+    ///
+    /// # Errors
+    ///
+    /// The name contains a `/` or nul, or the directory or file could not be
+    /// opened
+    pub fn create(dir: impl AsRef<Path>, name: &str) -> Result<Self, Error> {
+        let invalid = || Error::from(io::Error::from(io::ErrorKind::InvalidInput));
+        if name.is_empty() || name.contains('/') {
+            return Err(invalid());
+        }
+        let name = CString::new(name).map_err(|_err| invalid())?;
+
+        let dir_path =
+            CString::new(dir.as_ref().as_os_str().as_bytes()).map_err(|_err| invalid())?;
+        // SAFETY: syscall
+        let dir_fd = unsafe {
+            libc::open(
+                dir_path.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+            )
+        };
+        if dir_fd == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        // SAFETY: we own the descriptor
+        let dir = unsafe { File::from_raw_fd(dir_fd) };
+
+        let flags = libc::O_WRONLY | libc::O_CLOEXEC;
+
+        // SAFETY: syscall
+        let fd = unsafe { libc::openat(dir_fd, c".".as_ptr(), flags | libc::O_TMPFILE, 0o600) };
+        let (fd, temp_name) = if fd != -1 {
+            (fd, None)
+        } else {
+            let err = io::Error::last_os_error();
+            // Older kernels report a missing O_TMPFILE as EISDIR, as the
+            // directory itself is opened for writing
+            if !matches!(
+                err.raw_os_error(),
+                Some(libc::EOPNOTSUPP | libc::EISDIR | libc::EINVAL)
+            ) {
+                return Err(err.into());
+            }
+
+            let temp_name = CString::new(format!(
+                ".{}.{}.tmp",
+                name.to_string_lossy(),
+                std::process::id()
+            ))
+            .map_err(|_err| invalid())?;
+
+            // SAFETY: syscall
+            let fd = unsafe {
+                libc::openat(
+                    dir_fd,
+                    temp_name.as_ptr(),
+                    flags | libc::O_CREAT | libc::O_TRUNC | libc::O_NOFOLLOW,
+                    0o600,
+                )
+            };
+            if fd == -1 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            (fd, Some(temp_name))
+        };
+        // SAFETY: we own the descriptor
+        let file = unsafe { File::from_raw_fd(fd) };
+
+        let proc_path = CString::new(format!("/proc/self/fd/{fd}")).map_err(|_err| invalid())?;
+
+        Ok(Self {
+            dir,
+            file,
+            name,
+            proc_path,
+            temp_name,
+            finalized: AtomicBool::new(false),
+        })
+    }
+
+    /// The file that the crash output is written to
+    #[inline]
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// Gives the file its name in the directory, after which it is visible
+    /// to other processes. Finalizing a file that was already finalized does
+    /// nothing.
+    ///
+    /// This is async signal safe, as it only uses `linkat` or `renameat`
+    /// with the names that were built when the file was created.
+    ///
+    /// # Errors
+    ///
+    /// The file could not be linked or renamed, eg. because a file with the
+    /// name already exists
+    pub fn finalize(&self) -> io::Result<()> {
+        if self.finalized.swap(true, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let dir = self.dir.as_raw_fd();
+
+        // SAFETY: syscalls, every name is a valid C string
+        let res = unsafe {
+            if let Some(temp_name) = &self.temp_name {
+                libc::renameat(dir, temp_name.as_ptr(), dir, self.name.as_ptr())
+            } else {
+                // Linking the descriptor itself requires CAP_DAC_READ_SEARCH,
+                // while linking its /proc entry doesn't
+                let res = libc::linkat(
+                    self.file.as_raw_fd(),
+                    c"".as_ptr(),
+                    dir,
+                    self.name.as_ptr(),
+                    libc::AT_EMPTY_PATH,
+                );
+                if res == -1 && io::Error::last_os_error().raw_os_error() == Some(libc::ENOENT) {
+                    libc::linkat(
+                        libc::AT_FDCWD,
+                        self.proc_path.as_ptr(),
+                        dir,
+                        self.name.as_ptr(),
+                        libc::AT_SYMLINK_FOLLOW,
+                    )
+                } else {
+                    res
+                }
+            }
+        };
+
+        if res == -1 {
+            self.finalized.store(false, Ordering::Release);
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+impl AsRawFd for CrashFile {
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl Drop for CrashFile {
+    fn drop(&mut self) {
+        if let Some(temp_name) = &self.temp_name {
+            if !*self.finalized.get_mut() {
+                // SAFETY: syscall
+                unsafe {
+                    libc::unlinkat(self.dir.as_raw_fd(), temp_name.as_ptr(), 0);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! A log of the most recent application events that is attached to crashes.
+//!
+//! Breadcrumbs are stored in a fixed size ring of [`crash_context::Breadcrumb`]
+//! slots that is allocated up front, so adding one never allocates or takes a
+//! lock, and the location of the ring is recorded in the
+//! [`crate::CrashContext`] on Linux/Android, FreeBSD/OpenBSD, and Macos so
+//! that a process handling the crash can read the last
+//! [`crash_context::MAX_BREADCRUMBS`] events from the memory of the crashed
+//! process.
+//!
+//! ```
+//! use crash_handler::breadcrumbs::{self, BreadcrumbLevel};
+//!
+//! breadcrumbs::add(BreadcrumbLevel::Info, "loaded config");
+//! ```
+
+pub use crash_context::BreadcrumbLevel;
+use crash_context::{Breadcrumb, BREADCRUMB_WRITING, MAX_BREADCRUMBS, MAX_BREADCRUMB_MESSAGE_LEN};
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+struct Slots([UnsafeCell<Breadcrumb>; MAX_BREADCRUMBS]);
+
+// SAFETY: Slots are only written by the writer that claimed them by swapping
+// their sequence to `BREADCRUMB_WRITING`, and readers check the sequence of
+// each slot before and after reading it
+unsafe impl Sync for Slots {}
+
+static SLOTS: Slots = Slots([const { UnsafeCell::new(Breadcrumb::EMPTY) }; MAX_BREADCRUMBS]);
+/// The sequence number of the most recently added breadcrumb
+static LAST: AtomicU64 = AtomicU64::new(0);
+
+/// Retrieves the sequence number of the slot
+#[inline]
+fn sequence(slot: &UnsafeCell<Breadcrumb>) -> &AtomicU64 {
+    // SAFETY: the pointer is valid, aligned, and only ever accessed atomically
+    unsafe { AtomicU64::from_ptr(std::ptr::addr_of_mut!((*slot.get()).sequence)) }
+}
+
+/// Adds a breadcrumb, replacing the oldest one if the ring is full.
+///
+/// Messages longer than [`crash_context::MAX_BREADCRUMB_MESSAGE_LEN`] bytes
+/// are truncated. This neither allocates nor takes any locks, and is thus
+/// safe to call from any thread, including within a [`crate::CrashEvent`].
+/// If the ring wraps around while another thread is still writing the slot
+/// this breadcrumb would replace, the breadcrumb is dropped.
+pub fn add(level: BreadcrumbLevel, message: &str) {
+    let message = crate::annotations::truncate(message, MAX_BREADCRUMB_MESSAGE_LEN);
+    let seq = LAST.fetch_add(1, Ordering::AcqRel) + 1;
+
+    let slot = &SLOTS.0[((seq - 1) % MAX_BREADCRUMBS as u64) as usize];
+    let slot_seq = sequence(slot);
+
+    // Claim the slot, unless another writer is still writing it, or has
+    // already written a newer breadcrumb to it
+    let current = slot_seq.load(Ordering::Acquire);
+    if current >= seq
+        || slot_seq
+            .compare_exchange(
+                current,
+                BREADCRUMB_WRITING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+    {
+        return;
+    }
+
+    // SAFETY: we've claimed the slot, and readers will ignore it while it is
+    // being written
+    unsafe {
+        let breadcrumb = &mut *slot.get();
+        breadcrumb.timestamp = timestamp();
+        breadcrumb.level = level as u8;
+        breadcrumb.message[..message.len()].copy_from_slice(message.as_bytes());
+        breadcrumb.message_len = message.len() as u16;
+    }
+
+    slot_seq.store(seq, Ordering::Release);
+}
+
+/// Removes every breadcrumb
+pub fn clear() {
+    for slot in &SLOTS.0 {
+        let slot_seq = sequence(slot);
+        let current = slot_seq.load(Ordering::Acquire);
+        if current != BREADCRUMB_WRITING {
+            let _res = slot_seq.compare_exchange(current, 0, Ordering::AcqRel, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Invokes the callback for every breadcrumb, oldest first, with the time it
+/// was added in milliseconds since the unix epoch.
+///
+/// This does not take any locks or allocate, and is thus safe to call from
+/// within a [`crate::CrashEvent`], however breadcrumbs that are being added
+/// concurrently are skipped.
+pub fn for_each(mut cb: impl FnMut(u64, BreadcrumbLevel, &str)) {
+    let last = LAST.load(Ordering::Acquire);
+    let first = last.saturating_sub(MAX_BREADCRUMBS as u64) + 1;
+
+    for seq in first..=last {
+        let slot = &SLOTS.0[((seq - 1) % MAX_BREADCRUMBS as u64) as usize];
+
+        // SAFETY: we copy the slot before checking that it was not modified
+        // while we were reading it
+        let breadcrumb = unsafe { std::ptr::read_volatile(slot.get()) };
+        if breadcrumb.sequence != seq || sequence(slot).load(Ordering::Acquire) != seq {
+            continue;
+        }
+
+        if let Some((level, message)) = breadcrumb.get() {
+            cb(breadcrumb.timestamp, level, message);
+        }
+    }
+}
+
+/// Retrieves the address and number of the breadcrumb slots, which are
+/// recorded in the [`crate::CrashContext`]
+#[inline]
+pub(crate) fn location() -> (usize, usize) {
+    (SLOTS.0.as_ptr() as usize, MAX_BREADCRUMBS)
+}
+
+/// The current time in milliseconds since the unix epoch
+#[inline]
+fn timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |dur| dur.as_millis() as u64)
+}
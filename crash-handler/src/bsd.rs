@@ -0,0 +1,221 @@
+pub mod jmp;
+mod state;
+
+use crate::{events::EventId, Error};
+
+#[cfg(feature = "panic")]
+pub(crate) use state::simulate_panic;
+
+/// The signals that we support catching and raising
+#[derive(Copy, Clone, PartialEq)]
+#[repr(i32)]
+pub enum Signal {
+    Abort = libc::SIGABRT,
+    Bus = libc::SIGBUS,
+    Fpe = libc::SIGFPE,
+    Illegal = libc::SIGILL,
+    Segv = libc::SIGSEGV,
+    Trap = libc::SIGTRAP,
+}
+
+impl Signal {
+    #[inline]
+    pub fn ignore(self) {
+        unsafe {
+            state::ignore_signal(self);
+        }
+    }
+}
+
+/// A FreeBSD/OpenBSD signal handler
+pub struct CrashHandler {
+    id: EventId,
+    /// Cleared when the handler is detached
+    _marker: Option<crate::marker::CrashMarker>,
+}
+
+/// Configures a [`CrashHandler`] before attaching it
+pub struct CrashHandlerBuilder {
+    priority: i32,
+    alt_stack_size: usize,
+    signals: Vec<Signal>,
+    marker: Option<std::path::PathBuf>,
+}
+
+impl CrashHandlerBuilder {
+    /// Sets the priority of the handler, which determines the order in which
+    /// it is invoked relative to any other attached handlers. Defaults to
+    /// [`crate::DEFAULT_PRIORITY`].
+    ///
+    /// Handlers with a higher priority are invoked first, and handlers with
+    /// the same priority are invoked in the reverse order they were attached
+    /// in. Once a handler returns anything other than
+    /// [`crate::CrashEventResult::Reraise`], no further handlers are invoked.
+    #[inline]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the size, in bytes, of the alternate signal stack that is
+    /// installed on each thread so that signals caused by a stack overflow can
+    /// still be handled.
+    ///
+    /// The default is the larger of `SIGSTKSZ` and 16KiB. The size is rounded
+    /// up to the page size, and attaching will fail with
+    /// [`Error::InvalidAltStackSize`] if it is smaller than `MINSIGSTKSZ`.
+    ///
+    /// Note that this only applies to threads created after the handler is
+    /// attached, as well as the thread that attaches the handler, as any other
+    /// existing threads will keep the alternate stack they already have.
+    #[inline]
+    pub fn alt_stack_size(mut self, size: usize) -> Self {
+        self.alt_stack_size = size;
+        self
+    }
+
+    /// Sets the signals that a handler is installed for, which defaults to
+    /// every [`Signal`]. Any signal that is not in this set keeps whatever
+    /// handler it currently has.
+    #[inline]
+    pub fn signals(mut self, signals: &[Signal]) -> Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// Writes a [`crate::marker::CrashMarker`] to the specified path when the
+    /// handler is attached, which is cleared when the handler is detached, so
+    /// that [`crate::marker::CrashMarker::previous_run`] can detect if the
+    /// process was terminated abnormally, eg. by `SIGKILL`, on the next run.
+    #[inline]
+    pub fn crash_marker(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.marker = Some(path.into());
+        self
+    }
+
+    /// Attaches the signal handler with the current configuration.
+    ///
+    /// If another handler is already attached, only the priority applies, as
+    /// the signal handlers, and the rest of their configuration, are shared by
+    /// every attached handler.
+    ///
+    /// See [`CrashHandler::attach`]
+    pub fn attach(self, on_crash: Box<dyn crate::CrashEvent>) -> Result<CrashHandler, Error> {
+        // Written before attaching so that it is removed again if attaching fails
+        let marker = self
+            .marker
+            .map(crate::marker::CrashMarker::create)
+            .transpose()?;
+
+        let id = state::attach(on_crash, self.priority, self.alt_stack_size, &self.signals)?;
+        Ok(CrashHandler {
+            id,
+            _marker: marker,
+        })
+    }
+}
+
+impl Default for CrashHandlerBuilder {
+    fn default() -> Self {
+        Self {
+            priority: crate::DEFAULT_PRIORITY,
+            alt_stack_size: crate::unix::DEFAULT_ALT_STACK_SIZE,
+            signals: state::EXCEPTION_SIGNALS.to_vec(),
+            marker: None,
+        }
+    }
+}
+
+#[allow(clippy::unused_self)]
+impl CrashHandler {
+    /// Creates a builder that can be used to configure the handler before it
+    /// is attached
+    #[inline]
+    pub fn builder() -> CrashHandlerBuilder {
+        CrashHandlerBuilder::default()
+    }
+
+    /// Attaches the signal handler.
+    ///
+    /// The provided callback will be invoked if a signal is caught, providing a
+    /// [`crate::CrashContext`] with the details of the thread where the
+    /// signal was raised.
+    ///
+    /// The callback runs in a compromised context, so it is highly recommended
+    /// to not perform actions that may fail due to corrupted state that caused
+    /// or is a symptom of the original signal. This includes doing heap
+    /// allocations from the same allocator as the crashing code.
+    ///
+    /// Multiple handlers can be attached at the same time, see
+    /// [`CrashHandlerBuilder::priority`] for the order they are invoked in.
+    pub fn attach(on_crash: Box<dyn crate::CrashEvent>) -> Result<Self, Error> {
+        Self::builder().attach(on_crash)
+    }
+
+    /// Attaches the signal handler with the specified priority.
+    ///
+    /// See [`CrashHandlerBuilder::priority`]
+    pub fn attach_with_priority(
+        priority: i32,
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, Error> {
+        Self::builder().priority(priority).attach(on_crash)
+    }
+
+    /// Attaches the signal handler, but only for the specified signals,
+    /// leaving the handlers for any other signals untouched.
+    ///
+    /// See [`CrashHandler::attach`]
+    pub fn attach_with(
+        signals: &[Signal],
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, Error> {
+        Self::builder().signals(signals).attach(on_crash)
+    }
+
+    /// Detaches the handler, and if it is the last one attached, restores
+    /// the signal handlers that were installed before it.
+    ///
+    /// This is done automatically when this [`CrashHandler`] is dropped.
+    #[inline]
+    pub fn detach(self) {
+        state::detach(self.id);
+    }
+
+    /// Sends the specified user signal.
+    ///
+    /// Unlike Linux, there is no `getcontext` we can rely on for every BSD, so
+    /// the [`crate::CrashContext::context`] passed to the callback is zeroed.
+    pub fn simulate_signal(&self, signal: Signal) -> crate::CrashEventResult {
+        // Normally this would be an unsafe function, since this unsafe encompasses
+        // the entirety of the body, however the user is really not required to
+        // uphold any guarantees on their end, so no real need to declare the
+        // function itself unsafe.
+        unsafe {
+            let Some(_in_handler) = state::InHandler::enter() else {
+                return crate::CrashEventResult::Reraise;
+            };
+
+            let mut siginfo: libc::siginfo_t = std::mem::zeroed();
+            siginfo.si_signo = signal as i32;
+            siginfo.si_code = state::SI_USER;
+
+            let mut context: libc::ucontext_t = std::mem::zeroed();
+
+            if let Some(handler) = state::HANDLER.read() {
+                handler.handle_signal(
+                    &mut siginfo,
+                    &mut *(&mut context as *mut libc::ucontext_t).cast::<libc::c_void>(),
+                )
+            } else {
+                crate::CrashEventResult::Reraise
+            }
+        }
+    }
+}
+
+impl Drop for CrashHandler {
+    fn drop(&mut self) {
+        state::detach(self.id);
+    }
+}
@@ -0,0 +1,52 @@
+//! FFI bindings for non-local goto
+//!
+//! ```
+//! use crash_handler::jmp;
+//!
+//! unsafe {
+//!     let mut jmp_buf = std::mem::MaybeUninit::uninit();
+//!
+//!     let val = jmp::sigsetjmp(jmp_buf.as_mut_ptr(), 1);
+//!
+//!     if val == 0 {
+//!         jmp::siglongjmp(jmp_buf.as_mut_ptr(), 22);
+//!     } else {
+//!         assert_eq!(val, 22);
+//!     }
+//! }
+//! ```
+
+/// A jump buffer.
+///
+/// This is essentially the register state of a point in execution at the time
+/// of a [`sigsetjmp`] call that can be returned to by passing this buffer to
+/// [`siglongjmp`].
+///
+/// The layout is private to each libc, so this is just an opaque buffer that
+/// is larger than `sigjmp_buf` on every architecture supported by FreeBSD
+/// and OpenBSD.
+#[repr(C, align(16))]
+pub struct JmpBuf {
+    __opaque: [u64; 80],
+}
+
+extern "C" {
+    /// Set jump point for a non-local goto.
+    ///
+    /// The return value will be 0 if this is a direct invocation (ie the "first
+    /// time" `sigsetjmp` is executed), and will be the value passed to `siglongjmp`
+    /// otherwise.
+    ///
+    /// See [sigsetjmp](https://man.freebsd.org/cgi/man.cgi?query=sigsetjmp)
+    /// for more information.
+    pub fn sigsetjmp(jb: *mut JmpBuf, save_mask: i32) -> i32;
+    /// Non-local goto with signal handling
+    ///
+    /// The value passed here will be returned by `sigsetjmp` when returning
+    /// to that callsite. Note that passing a value of 0 here will be changed
+    /// to a 1.
+    ///
+    /// See [siglongjmp](https://man.freebsd.org/cgi/man.cgi?query=siglongjmp)
+    /// for more information.
+    pub fn siglongjmp(jb: *mut JmpBuf, val: i32) -> !;
+}
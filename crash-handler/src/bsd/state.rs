@@ -0,0 +1,452 @@
+use crate::{
+    events::{EventId, Events},
+    Error, Signal,
+};
+use std::{mem, ptr};
+
+cfg_if::cfg_if! {
+    if #[cfg(target_os = "freebsd")] {
+        /// kill, we define this ourselves as it is missing from libc
+        pub(super) const SI_USER: i32 = 0x10001;
+
+        /// Returns true if the signal was sent by a process via eg. `kill`
+        /// rather than generated by a hardware fault
+        #[inline]
+        fn is_user_signal(code: i32) -> bool {
+            // SI_NOINFO, or any of the SI_USER..=SI_LWP codes
+            code == 0 || code >= SI_USER
+        }
+    } else {
+        /// kill, we define this ourselves as it is missing from libc
+        pub(super) const SI_USER: i32 = 0;
+
+        /// Returns true if the signal was sent by a process via eg. `kill`
+        /// rather than generated by a hardware fault
+        #[inline]
+        fn is_user_signal(code: i32) -> bool {
+            /// No info, ie. the signal was sent via the old `kill` path
+            const SI_NOINFO: i32 = 32767;
+
+            // SI_USER, SI_LWP, SI_QUEUE, or SI_TIMER
+            code <= SI_USER || code == SI_NOINFO
+        }
+    }
+}
+
+/// Restores the signal handler for the specified signal back to its default
+/// handler, which _should_ perform the default signal action
+#[inline]
+unsafe fn install_default_handler(sig: Signal) {
+    libc::signal(sig as i32, libc::SIG_DFL);
+}
+
+#[inline]
+pub(crate) unsafe fn ignore_signal(sig: Signal) {
+    libc::signal(sig as i32, libc::SIG_IGN);
+}
+
+/// The various signals we attempt to handle
+pub(super) const EXCEPTION_SIGNALS: [Signal; 6] = [
+    Signal::Abort,
+    Signal::Bus,
+    Signal::Fpe,
+    Signal::Illegal,
+    Signal::Segv,
+    Signal::Trap,
+];
+
+/// The handlers that were installed before ours, for each of the
+/// [`EXCEPTION_SIGNALS`] that we actually installed a handler for
+type OldHandlers = [Option<libc::sigaction>; EXCEPTION_SIGNALS.len()];
+
+/// Restores all of the signal handlers back to their previous values, or the
+/// default if the previous value cannot be restored
+unsafe fn restore_handlers(old: &OldHandlers) {
+    for (sig, action) in EXCEPTION_SIGNALS.into_iter().zip(old.iter()) {
+        let Some(action) = action else {
+            continue;
+        };
+
+        if libc::sigaction(sig as i32, action, ptr::null_mut()) == -1 {
+            install_default_handler(sig);
+        }
+    }
+}
+
+/// Invokes the previously installed handler for a signal directly, with the
+/// same arguments we received from the kernel
+unsafe fn chain_handler(
+    previous: &libc::sigaction,
+    sig: Signal,
+    info: &mut libc::siginfo_t,
+    uc: &mut libc::c_void,
+) {
+    if previous.sa_flags & libc::SA_SIGINFO != 0 {
+        let handler = mem::transmute::<
+            usize,
+            unsafe extern "C" fn(i32, *mut libc::siginfo_t, *mut libc::c_void),
+        >(previous.sa_sigaction);
+        handler(sig as i32, info, uc);
+    } else {
+        let handler = mem::transmute::<usize, unsafe extern "C" fn(i32)>(previous.sa_sigaction);
+        handler(sig as i32);
+    }
+}
+
+/// Installs our signal handler for each of the specified signals, returning
+/// the handlers they replaced
+unsafe fn install_handlers(signals: &[Signal]) -> OldHandlers {
+    // Attempt store all of the current handlers so we can restore them later
+    let mut old_handlers = [None; EXCEPTION_SIGNALS.len()];
+
+    for (sig, handler) in EXCEPTION_SIGNALS
+        .iter()
+        .copied()
+        .zip(old_handlers.iter_mut())
+    {
+        if !signals.contains(&sig) {
+            continue;
+        }
+
+        let mut old = mem::zeroed();
+        if libc::sigaction(sig as i32, ptr::null(), &mut old) == -1 {
+            return [None; EXCEPTION_SIGNALS.len()];
+        }
+        *handler = Some(old);
+    }
+
+    let mut sa: libc::sigaction = mem::zeroed();
+    libc::sigemptyset(&mut sa.sa_mask);
+
+    // Mask all exception signals when we're handling one of them.
+    for sig in EXCEPTION_SIGNALS {
+        libc::sigaddset(&mut sa.sa_mask, sig as i32);
+    }
+
+    sa.sa_sigaction = signal_handler as *const () as usize;
+    sa.sa_flags = libc::SA_ONSTACK | libc::SA_SIGINFO;
+
+    // Use our signal_handler for all of the signals we wish to catch
+    for sig in signals.iter().copied() {
+        // At this point it is impractical to back out changes, and so failure to
+        // install a signal is intentionally ignored.
+        let _ = libc::sigaction(sig as i32, &sa, ptr::null_mut());
+    }
+
+    old_handlers
+}
+
+/// Attaches the event, installing our signal handlers if this is the first
+/// one to be attached, in which case the remaining arguments configure them
+pub(super) fn attach(
+    on_crash: Box<dyn crate::CrashEvent>,
+    priority: i32,
+    alt_stack_size: usize,
+    signals: &[Signal],
+) -> Result<EventId, Error> {
+    let _lock = ATTACH_LOCK.lock();
+
+    if let Some(current) = HANDLER.read() {
+        let mut inner = HandlerInner::clone(&current);
+        let id = inner.events.insert(on_crash, priority);
+
+        // The guard must be released before replacing the handler, which
+        // waits for all readers to finish
+        drop(current);
+        HANDLER.set(inner);
+
+        return Ok(id);
+    }
+
+    let minimum = crate::unix::min_alt_stack_size();
+    if alt_stack_size < minimum {
+        return Err(Error::InvalidAltStackSize {
+            requested: alt_stack_size,
+            minimum,
+        });
+    }
+
+    // Round up to the page size, as that is the granularity of the mapping
+    // SAFETY: syscall
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    crate::unix::set_alt_stack_size((alt_stack_size + page_size - 1) & !(page_size - 1));
+
+    // SAFETY: syscalls
+    let old_handlers = unsafe {
+        crate::unix::install_sigaltstack()?;
+        install_handlers(signals)
+    };
+
+    let mut events = Events::default();
+    let id = events.insert(on_crash, priority);
+    HANDLER.set(HandlerInner {
+        events,
+        old_handlers,
+    });
+
+    #[cfg(feature = "panic")]
+    crate::panic::install();
+
+    Ok(id)
+}
+
+/// Detaches the event, and if it was the last one, our signal handlers,
+/// restoring the previously installed or default handlers
+pub(super) fn detach(id: EventId) {
+    let _lock = ATTACH_LOCK.lock();
+
+    let mut inner = {
+        let Some(current) = HANDLER.read() else {
+            return;
+        };
+
+        HandlerInner::clone(&current)
+    };
+
+    if !inner.events.remove(id) {
+        return;
+    }
+
+    if !inner.events.is_empty() {
+        HANDLER.set(inner);
+        return;
+    }
+
+    // SAFETY: syscalls
+    unsafe {
+        crate::unix::restore_sigaltstack();
+        restore_handlers(&inner.old_handlers);
+    }
+    HANDLER.take();
+
+    #[cfg(feature = "panic")]
+    crate::panic::uninstall();
+}
+
+/// Routes a panic through the attached handler, see [`crate::panic`]
+#[cfg(feature = "panic")]
+pub(crate) fn simulate_panic() -> crate::CrashEventResult {
+    let Some(_in_handler) = InHandler::enter() else {
+        return crate::CrashEventResult::Reraise;
+    };
+
+    if let Some(handler) = HANDLER.read() {
+        // Panics are reported as an abort, since that is what they would
+        // become if the process was compiled with `panic = "abort"`
+        // SAFETY: the context is zeroed, which is valid for each of its fields
+        unsafe {
+            let mut siginfo: libc::siginfo_t = mem::zeroed();
+            siginfo.si_signo = libc::SIGABRT;
+            siginfo.si_code = SI_USER;
+
+            let mut context: libc::ucontext_t = mem::zeroed();
+
+            handler.handle_signal(
+                &mut siginfo,
+                &mut *(&mut context as *mut libc::ucontext_t).cast::<libc::c_void>(),
+            )
+        }
+    } else {
+        crate::CrashEventResult::Reraise
+    }
+}
+
+/// The attached handler, which is read from the signal handler without
+/// locking, so that a crash while attaching or detaching can't deadlock.
+///
+/// Everything the signal handler needs is in this single immutable value,
+/// which is replaced wholesale whenever a handler is attached or detached
+pub(super) static HANDLER: crate::unix::HandlerSlot<HandlerInner> = crate::unix::HandlerSlot::new();
+/// Serializes attaching and detaching, this is never locked from the signal
+/// handler
+static ATTACH_LOCK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+
+/// The threads that are currently running the user's handler, which is not
+/// thread local storage, as that isn't async signal safe
+static IN_HANDLER: crate::unix::ThreadSet = crate::unix::ThreadSet::new();
+
+/// Marks the current thread as running the user's handler until it is dropped,
+/// so that a crash within the handler itself can be detected rather than
+/// re-entering the handler, or detaching it from within itself
+pub(super) struct InHandler {
+    _entry: crate::unix::ThreadSetEntry<'static>,
+}
+
+impl InHandler {
+    /// Returns `None` if the current thread is already running the handler
+    #[inline]
+    pub(super) fn enter() -> Option<Self> {
+        IN_HANDLER.enter().map(|_entry| Self { _entry })
+    }
+}
+
+/// This is the actual function installed for each signal we support, invoked
+/// by the kernel
+unsafe extern "C" fn signal_handler(
+    sig: Signal,
+    info: *mut libc::siginfo_t,
+    uc: *mut libc::c_void,
+) {
+    let info = &mut *info;
+    let uc = &mut *uc;
+
+    enum Action {
+        RestoreDefault,
+        Retrigger,
+        Chain(libc::sigaction),
+        Continue,
+        Exit(i32),
+        Jump((*mut super::jmp::JmpBuf, i32)),
+    }
+
+    let action = {
+        // The crash occurred within `catch_crash`, so jump straight back to
+        // it before touching any of our own state
+        if let Some(jmp_buf) = crate::recover::take_recovery_point() {
+            debug_print!("recovering from crash");
+            super::jmp::siglongjmp(jmp_buf, sig as i32);
+        }
+
+        // If the signal was raised while this thread was already handling a
+        // signal, eg. because the user's handler crashed, nothing we do is
+        // safe, and invoking the handler again would most likely just crash
+        // again, so we just restore the default dispositions so that the
+        // retriggered signal kills the process
+        let Some(_in_handler) = InHandler::enter() else {
+            debug_print!("signal raised within handler, installing default handlers");
+            for sig in EXCEPTION_SIGNALS {
+                install_default_handler(sig);
+            }
+
+            retrigger_signal(sig, info);
+            return;
+        };
+
+        if let Some(handler) = HANDLER.read() {
+            match handler.handle_signal(info, uc) {
+                crate::CrashEventResult::Handled { exit } => {
+                    exit.map_or(Action::RestoreDefault, Action::Exit)
+                }
+                crate::CrashEventResult::Continue => Action::Continue,
+                crate::CrashEventResult::Reraise => {
+                    if let Some(previous) = handler.previous_handler(sig) {
+                        Action::Chain(previous)
+                    } else {
+                        // The previous handlers are only reachable while we
+                        // still hold the handler
+                        debug_print!("restoring handlers");
+                        restore_handlers(&handler.old_handlers);
+                        Action::Retrigger
+                    }
+                }
+                crate::CrashEventResult::Jump { jmp_buf, value } => Action::Jump((jmp_buf, value)),
+            }
+        } else {
+            // We are in the middle of being attached or detached on another
+            // thread, so we don't know what was installed before us
+            Action::RestoreDefault
+        }
+    };
+
+    // See the Linux implementation for why we either restore a handler and
+    // retrigger the signal, or chain directly to the previous handler
+    match action {
+        Action::RestoreDefault => {
+            debug_print!("installing default handler");
+            install_default_handler(sig);
+        }
+        Action::Retrigger => {}
+        Action::Chain(previous) => {
+            debug_print!("chaining to previous handler");
+            chain_handler(&previous, sig, info, uc);
+            return;
+        }
+        Action::Continue => {
+            debug_print!("continuing execution");
+            return;
+        }
+        Action::Exit(code) => {
+            debug_print!("exiting with {code}");
+            crate::exit_process(code);
+        }
+        Action::Jump((jmp_buf, value)) => {
+            debug_print!("jumping");
+            super::jmp::siglongjmp(jmp_buf, value);
+        }
+    }
+
+    debug_print!("finishing signal handler");
+
+    retrigger_signal(sig, info);
+}
+
+/// Ensures the signal is raised again once the signal handler returns, now
+/// that a different disposition is installed for it
+unsafe fn retrigger_signal(sig: Signal, info: &libc::siginfo_t) {
+    if is_user_signal(info.si_code) || sig == Signal::Abort {
+        // This signal was sent to us rather than being caused by a fault, so
+        // in order to retrigger it we have to send it again ourselves, to the
+        // same thread so that it is handled in the same context
+        if libc::pthread_kill(libc::pthread_self(), sig as i32) != 0 {
+            // If we failed to kill ourselves (e.g. because a sandbox disallows us
+            // to do so), we instead resort to terminating our process. This will
+            // result in an incorrect exit code.
+            libc::_exit(1);
+        }
+    } else {
+        // This was a synchronous signal triggered by a hard fault (e.g. SIGSEGV).
+        // No need to reissue the signal. It will automatically trigger again,
+        // when we return from the signal handler.
+    }
+}
+
+/// The size of `CrashContext` can be too big w.r.t the size of alternatate stack
+/// for `signal_handler`. Keep the crash context as a .bss field.
+static CRASH_CONTEXT: parking_lot::Mutex<mem::MaybeUninit<crash_context::CrashContext>> =
+    parking_lot::const_mutex(mem::MaybeUninit::uninit());
+
+#[derive(Clone)]
+pub(super) struct HandlerInner {
+    events: Events,
+    old_handlers: OldHandlers,
+}
+
+impl HandlerInner {
+    /// Retrieves the handler that was installed for the specified signal
+    /// before we installed our own, as long as it was an actual function
+    /// rather than the default or ignore disposition
+    #[inline]
+    fn previous_handler(&self, sig: Signal) -> Option<libc::sigaction> {
+        let index = EXCEPTION_SIGNALS.iter().position(|s| *s == sig)?;
+        let previous = self.old_handlers[index]?;
+
+        (previous.sa_sigaction != libc::SIG_DFL && previous.sa_sigaction != libc::SIG_IGN)
+            .then_some(previous)
+    }
+
+    pub(super) unsafe fn handle_signal(
+        &self,
+        info: &mut libc::siginfo_t,
+        uc: &mut libc::c_void,
+    ) -> crate::CrashEventResult {
+        let mut crash_ctx = CRASH_CONTEXT.lock();
+
+        {
+            *crash_ctx = mem::MaybeUninit::zeroed();
+            let cc = &mut *crash_ctx.as_mut_ptr();
+
+            ptr::copy_nonoverlapping(info, &mut cc.siginfo, 1);
+
+            let uc_ptr = (uc as *const libc::c_void).cast::<libc::ucontext_t>();
+            ptr::copy_nonoverlapping(uc_ptr, &mut cc.context, 1);
+
+            cc.pid = std::process::id() as i32;
+            cc.tid = crash_context::CrashContext::current_tid();
+            (cc.annotations, cc.annotation_count) = crate::annotations::location();
+            (cc.breadcrumbs, cc.breadcrumb_count) = crate::breadcrumbs::location();
+            cc.time = crash_context::CrashTime::now();
+        }
+
+        self.events.on_crash(&*crash_ctx.as_ptr())
+    }
+}
@@ -0,0 +1,192 @@
+//! Crash detection for child processes that have no crash handler of their
+//! own, eg. third-party tools or processes that crashed before attaching one.
+//!
+//! A parent is notified when a child changes state with `SIGCHLD`, and can
+//! retrieve the details with `waitid`, both of which describe the change with
+//! a `siginfo_t`. [`ChildStatus`] interprets it, distinguishing a child that
+//! exited normally from one that was killed by a signal or stopped, and
+//! [`ChildStatus::crash_context`] turns a child that was killed by one of the
+//! crash [`crate::Signal`]s into a [`crate::CrashContext`], so that it can be
+//! reported the same way as a crash of the parent itself, eg. via
+//! [`crate::CrashHandler::simulate_context`].
+//!
+//! ```no_run
+//! use crash_handler::child;
+//!
+//! let mut child = std::process::Command::new("some-tool").spawn().unwrap();
+//!
+//! if let Some(status) = child::wait(Some(child.id() as i32), false).unwrap() {
+//!     if let Some(cc) = status.crash_context() {
+//!         println!("child crashed with signal {}", cc.siginfo.ssi_signo);
+//!     }
+//! }
+//! ```
+
+use std::io;
+
+/// How a child process changed state
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChildExit {
+    /// The child exited normally with the specified code
+    Exited(i32),
+    /// The child was terminated by the specified signal
+    Killed {
+        /// The signal the child was terminated by
+        signal: i32,
+        /// Whether a core dump was written, ie. `WCOREDUMP`, which depends on
+        /// both the signal and the system's configuration
+        core_dumped: bool,
+    },
+    /// The child was stopped by the specified signal, eg. `SIGSTOP`
+    Stopped(i32),
+    /// The child was stopped by the specified signal while being traced
+    Trapped(i32),
+    /// The child was resumed by `SIGCONT` after being stopped
+    Continued,
+}
+
+/// A child process that changed state, see the [module level docs](self)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChildStatus {
+    /// The id of the child process
+    pub pid: libc::pid_t,
+    /// The real user id of the child process
+    pub uid: libc::uid_t,
+    /// How the child changed state
+    pub exit: ChildExit,
+}
+
+impl ChildStatus {
+    /// Interprets the `siginfo_t` of a `SIGCHLD`, as received by a signal
+    /// handler, or as filled out by `waitid`.
+    ///
+    /// Returns `None` if it isn't for a child changing state, eg. because
+    /// `waitid` was called with `WNOHANG` and no child had changed state.
+    pub fn from_siginfo(info: &libc::siginfo_t) -> Option<Self> {
+        // SAFETY: the fields are only read if the code says they're valid
+        let (pid, uid, status) = unsafe { (info.si_pid(), info.si_uid(), info.si_status()) };
+        Self::from_parts(info.si_signo, info.si_code, pid, uid, status)
+    }
+
+    /// Interprets the siginfo of a `SIGCHLD` received via a `signalfd`.
+    ///
+    /// Returns `None` if it isn't for a child changing state.
+    pub fn from_signalfd(info: &libc::signalfd_siginfo) -> Option<Self> {
+        Self::from_parts(
+            info.ssi_signo as i32,
+            info.ssi_code,
+            info.ssi_pid as libc::pid_t,
+            info.ssi_uid,
+            info.ssi_status,
+        )
+    }
+
+    fn from_parts(
+        signo: i32,
+        code: i32,
+        pid: libc::pid_t,
+        uid: libc::uid_t,
+        status: i32,
+    ) -> Option<Self> {
+        if signo != libc::SIGCHLD || pid == 0 {
+            return None;
+        }
+
+        let exit = match code {
+            libc::CLD_EXITED => ChildExit::Exited(status),
+            libc::CLD_KILLED => ChildExit::Killed {
+                signal: status,
+                core_dumped: false,
+            },
+            libc::CLD_DUMPED => ChildExit::Killed {
+                signal: status,
+                core_dumped: true,
+            },
+            libc::CLD_STOPPED => ChildExit::Stopped(status),
+            libc::CLD_TRAPPED => ChildExit::Trapped(status),
+            libc::CLD_CONTINUED => ChildExit::Continued,
+            _ => return None,
+        };
+
+        Some(Self { pid, uid, exit })
+    }
+
+    /// The crash signal the child was killed by, if it crashed, as opposed to
+    /// eg. being killed by `SIGKILL` or `SIGTERM`
+    pub fn crash_signal(&self) -> Option<crate::Signal> {
+        let ChildExit::Killed { signal, .. } = self.exit else {
+            return None;
+        };
+
+        use crate::Signal;
+
+        Some(match signal {
+            libc::SIGABRT => Signal::Abort,
+            libc::SIGBUS => Signal::Bus,
+            libc::SIGFPE => Signal::Fpe,
+            libc::SIGILL => Signal::Illegal,
+            libc::SIGSEGV => Signal::Segv,
+            libc::SIGSYS => Signal::Sys,
+            libc::SIGTRAP => Signal::Trap,
+            _ => return None,
+        })
+    }
+
+    /// Creates a context for the crash of the child, if it was killed by a
+    /// [crash signal](Self::crash_signal), so that it can be reported like
+    /// any other crash.
+    ///
+    /// As the child no longer exists, the context only describes the signal,
+    /// and the time the crash was detected at. The thread is the main thread
+    /// of the child, as the thread that crashed isn't known, and all of the
+    /// registers are zero.
+    pub fn crash_context(&self) -> Option<crate::CrashContext> {
+        let signal = self.crash_signal()?;
+
+        let mut cc = crate::CrashContext::synthetic(signal as i32, 0, 0);
+        cc.pid = self.pid;
+        cc.tid = self.pid;
+        cc.siginfo.ssi_pid = self.pid as u32;
+        cc.siginfo.ssi_uid = self.uid;
+        cc.capture_time();
+
+        Some(cc)
+    }
+}
+
+/// Waits for a child process to exit, or to be stopped or resumed, and reaps
+/// it if it exited. If `pid` is `None`, any child is waited for.
+///
+/// If `nohang` is true, this returns `Ok(None)` immediately rather than
+/// blocking if no child has changed state.
+///
+/// # Errors
+///
+/// `waitid` failed, eg. with `ECHILD` because there is no child to wait for
+pub fn wait(pid: Option<libc::pid_t>, nohang: bool) -> io::Result<Option<ChildStatus>> {
+    let (idtype, id) = match pid {
+        Some(pid) => (libc::P_PID, pid as libc::id_t),
+        None => (libc::P_ALL, 0),
+    };
+
+    let mut options = libc::WEXITED | libc::WSTOPPED | libc::WCONTINUED;
+    if nohang {
+        options |= libc::WNOHANG;
+    }
+
+    loop {
+        // SAFETY: syscall, waitid zeroes si_pid if there is no child to report
+        // with WNOHANG, so that is detected by `from_siginfo`
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: syscall
+        if unsafe { libc::waitid(idtype, id, &mut info, options) } == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        return Ok(ChildStatus::from_siginfo(&info));
+    }
+}
@@ -0,0 +1,194 @@
+//! Detection of an application that is crashing repeatedly.
+//!
+//! A [`CrashLog`] is a small file with the time of each crash the handler
+//! caught, which [`status`] reads on startup, so that an application that
+//! crashed several times in a short period can eg. reset its configuration or
+//! start in a safe mode rather than crashing yet again.
+//!
+//! The log can also limit how many crashes per hour are handled, see
+//! [`CrashLog::max_dumps_per_hour`], so that an application that is being
+//! restarted in a loop doesn't amplify an outage by writing and uploading a
+//! minidump for every single crash.
+//!
+//! ```no_run
+//! use crash_handler::crash_loop::{self, CrashLog};
+//! use std::time::Duration;
+//!
+//! let path = std::env::temp_dir().join("my-app.crashes");
+//!
+//! let status = crash_loop::status(&path, Duration::from_secs(10 * 60), 3);
+//! if status.looping {
+//!     eprintln!("crashed {} times recently, starting in safe mode", status.recent_crashes);
+//! }
+//!
+//! let log = CrashLog::open(&path).unwrap().max_dumps_per_hour(5);
+//! ```
+
+use crate::{
+    write::{format_dec, write_bytes, DEC_BUF_LEN},
+    Error,
+};
+use std::{
+    fs::File,
+    os::fd::AsRawFd,
+    path::Path,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// The number of crashes that are kept in the log, older crashes are removed
+/// when it is opened
+const MAX_RECORDS: usize = 64;
+
+const HOUR: u64 = 60 * 60;
+
+/// Whether the application is crashing repeatedly, see [`status`]
+#[derive(Clone, Debug)]
+pub struct Status {
+    /// The number of crashes within the window
+    pub recent_crashes: usize,
+    /// The time of the most recent crash, regardless of the window
+    pub last_crash: Option<SystemTime>,
+    /// True if the number of recent crashes reached the threshold
+    pub looping: bool,
+}
+
+/// Reads the [`CrashLog`] at the specified path, and determines whether the
+/// application crashed at least `threshold` times within the `window` before
+/// now.
+///
+/// This should be called before [`CrashLog::open`], which removes old crashes
+/// from the log. A log that doesn't exist, or can't be read, has no crashes.
+pub fn status(path: impl AsRef<Path>, window: Duration, threshold: usize) -> Status {
+    let crashes = read(path.as_ref());
+    let since = now().saturating_sub(window.as_secs());
+    let recent_crashes = crashes.iter().filter(|time| **time >= since).count();
+
+    Status {
+        recent_crashes,
+        last_crash: crashes
+            .iter()
+            .max()
+            .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(*secs)),
+        looping: threshold > 0 && recent_crashes >= threshold,
+    }
+}
+
+/// A file that the time of each crash is appended to, see the
+/// [module documentation](self).
+///
+/// On Linux/Android, the log is updated by the handler itself if it is passed
+/// to [`crate::CrashHandlerBuilder::crash_log`], otherwise [`Self::record`]
+/// can be called from the [`crate::CrashEvent`].
+pub struct CrashLog {
+    file: File,
+    /// The times of the most recent crashes, in seconds since the Unix epoch,
+    /// including the ones read when the log was opened
+    times: [AtomicU64; MAX_RECORDS],
+    /// The index in `times` the next crash is recorded at
+    next: AtomicUsize,
+    max_per_hour: Option<usize>,
+}
+
+impl CrashLog {
+    /// Opens the log at the specified path, creating it if it doesn't exist,
+    /// and removing all but the most recent crashes from it.
+    ///
+    /// # Errors
+    ///
+    /// The file could not be opened or written
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let mut crashes = read(path);
+        crashes.sort_unstable();
+        let crashes = &crashes[crashes.len().saturating_sub(MAX_RECORDS)..];
+
+        let mut contents = String::new();
+        for time in crashes {
+            contents.push_str(&time.to_string());
+            contents.push('\n');
+        }
+        std::fs::write(path, contents)?;
+
+        let file = std::fs::OpenOptions::new().append(true).open(path)?;
+
+        let times = std::array::from_fn(|i| AtomicU64::new(crashes.get(i).copied().unwrap_or(0)));
+
+        Ok(Self {
+            file,
+            times,
+            next: AtomicUsize::new(crashes.len() % MAX_RECORDS),
+            max_per_hour: None,
+        })
+    }
+
+    /// Sets the maximum number of crashes per hour that are handled, any
+    /// further crashes within the same hour are still recorded, but the
+    /// callbacks are not invoked for them, as if no handler was attached.
+    ///
+    /// Crashes from previous runs count towards the limit.
+    #[inline]
+    pub fn max_dumps_per_hour(mut self, max: usize) -> Self {
+        self.max_per_hour = Some(max);
+        self
+    }
+
+    /// Records a crash at the current time, returning false if the crash
+    /// exceeds [`Self::max_dumps_per_hour`], ie. it shouldn't be handled.
+    ///
+    /// This is async signal safe.
+    pub fn record(&self) -> bool {
+        let now = now();
+
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % MAX_RECORDS;
+        self.times[index].store(now, Ordering::Relaxed);
+
+        // A single write, so that concurrent crashes, even in other processes
+        // using the same log, can't interleave with each other
+        let mut digits = [0u8; DEC_BUF_LEN];
+        let len = format_dec(now, &mut digits).len();
+        let mut line = [b'\n'; DEC_BUF_LEN + 1];
+        line[..len].copy_from_slice(&digits[DEC_BUF_LEN - len..]);
+        let _res = write_bytes(self.file.as_raw_fd(), &line[..=len]);
+
+        let Some(max) = self.max_per_hour else {
+            return true;
+        };
+
+        let since = now.saturating_sub(HOUR);
+        let crashes = self
+            .times
+            .iter()
+            .filter(|time| {
+                let time = time.load(Ordering::Relaxed);
+                time != 0 && time >= since
+            })
+            .count();
+
+        crashes <= max
+    }
+}
+
+/// The current time in seconds since the Unix epoch, which is async signal
+/// safe as it is just `clock_gettime`
+#[inline]
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Reads the crash times in the log, ignoring any malformed lines, eg. from
+/// a write that was interrupted
+fn read(path: &Path) -> Vec<u64> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
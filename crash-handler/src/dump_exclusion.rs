@@ -0,0 +1,156 @@
+//! Excludes large application buffers, eg. multi-gigabyte caches, from dumps
+//! of the process written by the operating system, so that they stay small.
+//!
+//! * Linux/Android - The pages are marked with `MADV_DONTDUMP`, which omits
+//!   them from core dumps, eg. those written by `systemd-coredump`
+//! * FreeBSD - The pages are marked with `MADV_NOCORE`, which omits them from
+//!   core dumps
+//! * Windows - The memory is registered via `WerRegisterExcludedMemoryBlock`,
+//!   which omits it from the dumps written by Windows Error Reporting
+//! * macOS/iOS/OpenBSD - There is no way to exclude memory, so this does
+//!   nothing
+//!
+//! Minidumps written by `minidumper`, on every platform, only ever contain
+//! thread stacks and the memory explicitly included via
+//! `crash_handler::memory_regions`, so buffers are never part of one unless
+//! they are included on purpose.
+//!
+//! ```
+//! let cache = vec![0u8; 1024 * 1024];
+//!
+//! crash_handler::dump_exclusion::exclude_from_dumps(cache.as_ptr(), cache.len()).unwrap();
+//! // ...
+//! crash_handler::dump_exclusion::include_in_dumps(cache.as_ptr(), cache.len()).unwrap();
+//! ```
+//!
+//! Note that on Linux/Android/FreeBSD the exclusion applies to every page
+//! containing part of the buffer, as well as to the mapping itself rather
+//! than the buffer, so it is reset if the memory is unmapped and mapped
+//! again. On Windows, the buffer must be included again before it is freed.
+
+use std::io;
+
+/// Excludes the `size` bytes starting at `address` from dumps of the process,
+/// see the [module level docs](self).
+///
+/// # Errors
+///
+/// The range isn't mapped, or the exclusion couldn't be registered
+pub fn exclude_from_dumps(address: *const u8, size: usize) -> io::Result<()> {
+    imp::exclude(address, size)
+}
+
+/// Includes memory previously excluded via [`exclude_from_dumps`], with the
+/// same `address` and `size`, in dumps of the process again
+///
+/// # Errors
+///
+/// The range isn't mapped, or wasn't excluded
+pub fn include_in_dumps(address: *const u8, size: usize) -> io::Result<()> {
+    imp::include(address, size)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+mod imp {
+    use std::io;
+
+    #[cfg(target_os = "freebsd")]
+    use libc::{MADV_CORE as INCLUDE, MADV_NOCORE as EXCLUDE};
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    use libc::{MADV_DODUMP as INCLUDE, MADV_DONTDUMP as EXCLUDE};
+
+    pub(super) fn exclude(address: *const u8, size: usize) -> io::Result<()> {
+        advise(address, size, EXCLUDE)
+    }
+
+    pub(super) fn include(address: *const u8, size: usize) -> io::Result<()> {
+        advise(address, size, INCLUDE)
+    }
+
+    /// Applies the advice to every page containing part of the range, as
+    /// `madvise` requires a page aligned address
+    fn advise(address: *const u8, size: usize, advice: i32) -> io::Result<()> {
+        if size == 0 {
+            return Ok(());
+        }
+
+        // SAFETY: syscall
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let start = address as usize & !(page_size - 1);
+        let end = (address as usize)
+            .saturating_add(size)
+            .saturating_add(page_size - 1)
+            & !(page_size - 1);
+
+        // SAFETY: syscall, the advice doesn't change the contents of the memory
+        if unsafe { libc::madvise(start as *mut _, end - start, advice) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use std::io;
+    use windows_sys::Win32::System::ErrorReporting::{
+        WerRegisterExcludedMemoryBlock, WerUnregisterExcludedMemoryBlock,
+    };
+
+    /// The largest block that can be registered at once, larger buffers are
+    /// registered as multiple consecutive blocks
+    const MAX_BLOCK: usize = u32::MAX as usize;
+
+    pub(super) fn exclude(address: *const u8, size: usize) -> io::Result<()> {
+        for (i, offset) in (0..size).step_by(MAX_BLOCK).enumerate() {
+            let len = (size - offset).min(MAX_BLOCK);
+            // SAFETY: syscall
+            let hr =
+                unsafe { WerRegisterExcludedMemoryBlock(address.add(offset).cast(), len as u32) };
+            if hr < 0 {
+                // Don't leave the blocks that were registered behind
+                for registered in (0..i).map(|block| block * MAX_BLOCK) {
+                    // SAFETY: syscall
+                    unsafe {
+                        WerUnregisterExcludedMemoryBlock(address.add(registered).cast());
+                    }
+                }
+                return Err(io::Error::from_raw_os_error(hr));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn include(address: *const u8, size: usize) -> io::Result<()> {
+        let mut result = Ok(());
+        for offset in (0..size).step_by(MAX_BLOCK) {
+            // SAFETY: syscall
+            let hr = unsafe { WerUnregisterExcludedMemoryBlock(address.add(offset).cast()) };
+            if hr < 0 {
+                result = Err(io::Error::from_raw_os_error(hr));
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "windows"
+)))]
+mod imp {
+    use std::io;
+
+    pub(super) fn exclude(_address: *const u8, _size: usize) -> io::Result<()> {
+        Ok(())
+    }
+
+    pub(super) fn include(_address: *const u8, _size: usize) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -5,17 +5,151 @@ use std::fmt;
 pub enum Error {
     /// Unable to `mmap` memory
     OutOfMemory,
-    /// For simplicity sake, only one [`crate::CrashHandler`] can be registered
-    /// at any one time.
+    /// A handler is already installed.
+    ///
+    /// This is no longer returned when attaching a [`crate::CrashHandler`], as
+    /// multiple handlers can be attached at the same time.
     HandlerAlreadyInstalled,
+    /// The requested alternate signal stack size is smaller than the minimum
+    /// size required by the system
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "openbsd"
+    ))]
+    InvalidAltStackSize {
+        /// The size that was requested
+        requested: usize,
+        /// The minimum size supported by the system
+        minimum: usize,
+    },
+    /// A raw signal number passed to [`crate::CrashHandlerBuilder::raw_signals`]
+    /// is not a signal a handler can be installed for
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    InvalidSignal(i32),
+    /// The process is running with a sanitizer, or under Valgrind, so the
+    /// handler stepped aside, see
+    /// [`crate::CrashHandlerBuilder::sanitizer_policy`]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    SanitizerDetected(crate::sanitizers::Tool),
+    /// A step of installing the crash handler failed.
+    ///
+    /// This is returned when attaching the first [`crate::CrashHandler`], as
+    /// that is when the alternate signal stack and signal handlers are
+    /// actually installed.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "openbsd"
+    ))]
+    Install {
+        /// The step that failed
+        step: InstallStep,
+        /// The error returned by the syscall
+        error: std::io::Error,
+        /// True if the syscall was most likely denied by a seccomp filter,
+        /// rather than failing for another reason
+        seccomp: bool,
+        /// True if every change made before the step failed was undone, ie.
+        /// the signal handlers and alternate stack are the same as before
+        /// attaching. If false, some signals may be handled by the crash
+        /// handler, while others aren't.
+        rolled_back: bool,
+    },
     /// An I/O or other syscall failed
     Io(std::io::Error),
 }
 
+/// A step of installing the crash handler, see [`Error::Install`]
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InstallStep {
+    /// Retrieving the current alternate signal stack via `sigaltstack`
+    QueryAltStack,
+    /// Mapping the alternate signal stack of the specified size
+    AllocateAltStack {
+        /// The size of the stack, excluding its guard page
+        size: usize,
+    },
+    /// Registering the alternate signal stack via `sigaltstack`
+    RegisterAltStack,
+    /// The calling thread is currently executing on an existing alternate
+    /// signal stack, which is too small and can't be replaced while it is in
+    /// use
+    AltStackInUse {
+        /// The size of the existing stack
+        size: usize,
+    },
+    /// Retrieving the existing handler for the signal via `sigaction`, so
+    /// that it can be restored
+    QueryHandler(i32),
+    /// Installing our handler for the signal via `sigaction`
+    InstallHandler(i32),
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+impl Error {
+    /// Creates an [`Error::Install`] for the step that failed with the error
+    pub(crate) fn install(step: InstallStep, error: std::io::Error, rolled_back: bool) -> Self {
+        Self::Install {
+            step,
+            seccomp: crate::unix::denied_by_seccomp(&error),
+            error,
+            rolled_back,
+        }
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+impl fmt::Display for InstallStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueryAltStack => f.write_str("retrieve the current alternate signal stack"),
+            Self::AllocateAltStack { size } => {
+                write!(f, "allocate an alternate signal stack of {} bytes", size)
+            }
+            Self::RegisterAltStack => f.write_str("register the alternate signal stack"),
+            Self::AltStackInUse { size } => write!(
+                f,
+                "replace the alternate signal stack of {} bytes, which is in use",
+                size
+            ),
+            Self::QueryHandler(sig) => {
+                write!(f, "retrieve the existing handler for signal {}", sig)
+            }
+            Self::InstallHandler(sig) => write!(f, "install the handler for signal {}", sig),
+        }
+    }
+}
+
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Io(inner) => Some(inner),
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "openbsd"
+            ))]
+            Self::Install { error, .. } => Some(error),
             _ => None,
         }
     }
@@ -28,6 +162,49 @@ impl fmt::Display for Error {
             Self::HandlerAlreadyInstalled => {
                 f.write_str("an exception handler is already installed")
             }
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "openbsd"
+            ))]
+            Self::InvalidAltStackSize { requested, minimum } => write!(
+                f,
+                "alternate stack size of {} is smaller than the minimum of {}",
+                requested, minimum
+            ),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::InvalidSignal(sig) => write!(f, "{} is not a signal that can be handled", sig),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::SanitizerDetected(tool) => {
+                write!(
+                    f,
+                    "{} is handling crashes, the handler was not attached",
+                    tool
+                )
+            }
+            #[cfg(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "freebsd",
+                target_os = "openbsd"
+            ))]
+            Self::Install {
+                step,
+                error,
+                seccomp,
+                rolled_back,
+            } => {
+                write!(f, "failed to {}: {}", step, error)?;
+                if *seccomp {
+                    f.write_str(" (denied by seccomp)")?;
+                }
+                if *rolled_back {
+                    f.write_str(", the installation was rolled back")
+                } else {
+                    f.write_str(", the handler is only partially installed")
+                }
+            }
             Self::Io(e) => write!(f, "{}", e),
         }
     }
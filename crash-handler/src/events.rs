@@ -0,0 +1,100 @@
+//! Bookkeeping for multiple [`CrashEvent`]s attached at the same time.
+//!
+//! Only a single set of OS level handlers is ever installed, by the first
+//! [`crate::CrashHandler`] to be attached, but any number of handlers can be
+//! attached afterwards, eg. by different libraries in the same process, each
+//! of which adds its [`CrashEvent`] to the set that a crash is dispatched to.
+//!
+//! Events are invoked in order of their priority, highest first, with events
+//! of the same priority invoked in the reverse order they were attached in.
+//! Dispatch stops at the first event that returns anything other than
+//! [`CrashEventResult::Reraise`], which is then the result of the crash.
+
+use crate::{CrashContext, CrashEvent, CrashEventResult};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// The priority of a handler attached without specifying one
+pub const DEFAULT_PRIORITY: i32 = 0;
+
+/// Identifies an attached event so that it can be detached again
+pub(crate) type EventId = u64;
+
+/// The id of the next event to be attached, ids are never reused so that
+/// detaching a handler twice can't detach a different one
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone)]
+struct Entry {
+    id: EventId,
+    priority: i32,
+    event: Arc<dyn CrashEvent>,
+}
+
+/// The attached events, in the order they are invoked.
+///
+/// This is cheap to clone, so that the platforms that read the events from a
+/// signal handler without locking can replace them wholesale instead of
+/// modifying them in place.
+#[derive(Clone, Default)]
+pub(crate) struct Events {
+    entries: Vec<Entry>,
+}
+
+impl Events {
+    /// Adds an event, returning the id used to remove it
+    pub(crate) fn insert(&mut self, event: Box<dyn CrashEvent>, priority: i32) -> EventId {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        // Insert before any events with the same priority, as the most
+        // recently attached handler is invoked first
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.priority <= priority)
+            .unwrap_or(self.entries.len());
+
+        self.entries.insert(
+            index,
+            Entry {
+                id,
+                priority,
+                event: Arc::from(event),
+            },
+        );
+
+        id
+    }
+
+    /// Removes the event with the specified id, returning true if it was found
+    pub(crate) fn remove(&mut self, id: EventId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Invokes each event in turn until one of them doesn't return
+    /// [`CrashEventResult::Reraise`]
+    pub(crate) fn on_crash(&self, context: &CrashContext) -> CrashEventResult {
+        for entry in &self.entries {
+            let result = {
+                // The user's code is free to allocate if it wishes
+                #[cfg(feature = "alloc-check")]
+                let _allow = crate::alloc_check::Forbid::allow();
+                entry.event.on_crash(context)
+            };
+            if !matches!(result, CrashEventResult::Reraise) {
+                return result;
+            }
+        }
+
+        CrashEventResult::Reraise
+    }
+}
@@ -0,0 +1,124 @@
+//! Writing crashes to a local file, for applications without a separate
+//! process, eg. a `minidumper` server, to send them to.
+//!
+//! A [`FallbackFile`] passed to [`crate::CrashHandlerBuilder::fallback_file`]
+//! is written by the handler itself for every crash, before the callbacks are
+//! invoked, so that something is on disk even if no callback is attached that
+//! does anything with the crash, or the callback itself fails. The crash
+//! contexts can then be retrieved via [`read`] on the next run.
+//!
+//! ```no_run
+//! use crash_handler::fallback::{self, FallbackFile};
+//!
+//! let path = std::env::temp_dir().join("my-app.crash");
+//!
+//! for cc in fallback::read(&path).unwrap() {
+//!     eprintln!("previous run crashed:\n{}", cc.report());
+//! }
+//! std::fs::remove_file(&path).ok();
+//!
+//! let file = FallbackFile::open(&path).unwrap();
+//! let _handler = crash_handler::CrashHandler::builder()
+//!     .fallback_file(file)
+//!     .attach(unsafe {
+//!         crash_handler::make_crash_event(|_cc| crash_handler::CrashEventResult::Reraise)
+//!     })
+//!     .unwrap();
+//! ```
+
+use crate::{write::write_bytes, CrashContext, Error};
+use std::{
+    fs::{File, OpenOptions},
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    path::Path,
+};
+
+/// The files each crash is written to, see the [module documentation](self)
+pub struct FallbackFile {
+    /// Each crash is appended as a [`CrashContext::serialize_into`] record
+    context: File,
+    /// Each crash is appended as a [`CrashContext::report`]
+    report: Option<File>,
+}
+
+impl FallbackFile {
+    /// Opens the file at the specified path, creating it if it doesn't exist.
+    /// Crashes are appended to any that are already in the file.
+    ///
+    /// # Errors
+    ///
+    /// The file could not be opened
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self::from_file(open_append(path.as_ref())?))
+    }
+
+    /// Uses an already opened file, eg. one inherited from a parent process,
+    /// which should be opened for appending
+    #[inline]
+    pub fn from_file(file: File) -> Self {
+        Self {
+            context: file,
+            report: None,
+        }
+    }
+
+    /// Also appends a human readable [report](CrashContext::report) of each
+    /// crash to the file at the specified path
+    ///
+    /// # Errors
+    ///
+    /// The file could not be opened
+    pub fn report(mut self, path: impl AsRef<Path>) -> Result<Self, Error> {
+        self.report = Some(open_append(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Appends the crash to the file(s).
+    ///
+    /// This is async signal safe, as the context is serialized on the stack,
+    /// and written via `write`.
+    ///
+    /// # Errors
+    ///
+    /// The crash could not be written
+    pub fn write(&self, cc: &CrashContext) -> std::io::Result<()> {
+        let mut buf = [0u8; CrashContext::SERIALIZED_LEN];
+        if let Some(len) = cc.serialize_into(&mut buf) {
+            write_bytes(self.context.as_raw_fd(), &buf[..len])?;
+        }
+
+        if let Some(report) = &self.report {
+            crash_context::write_report(cc, report.as_raw_fd())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads every crash that was written to the file at the specified path by a
+/// [`FallbackFile`], oldest first. A file that doesn't exist has no crashes,
+/// and a crash that was only partially written is skipped.
+///
+/// # Errors
+///
+/// The file could not be read
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<CrashContext>, Error> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(contents
+        .chunks_exact(CrashContext::SERIALIZED_LEN)
+        .filter_map(|record| CrashContext::deserialize(record).ok())
+        .collect())
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o600)
+        .open(path)
+}
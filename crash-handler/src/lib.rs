@@ -1,44 +1,77 @@
 #![doc = include_str!("../README.md")]
 #![allow(unsafe_code)]
 
+#[cfg(feature = "alloc-check")]
+pub mod alloc_check;
+pub mod annotations;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod artifact;
+pub mod breadcrumbs;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod child;
+#[cfg(unix)]
+pub mod crash_loop;
+pub mod dump_exclusion;
 mod error;
+mod events;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod fallback;
+pub mod marker;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod memory_regions;
+#[cfg(not(any(target_os = "freebsd", target_os = "openbsd")))]
+pub mod modules;
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "tvos")))]
+pub mod recover;
+pub mod stack_memory;
+pub mod unwind;
+pub mod write;
 
 pub use error::Error;
-
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "openbsd"
+))]
+pub use error::InstallStep;
+pub use events::DEFAULT_PRIORITY;
+
+/// Writes the formatted message, followed by a newline, to stderr in an async
+/// signal safe manner if the `debug-print` feature is enabled. See [`dprintf!`]
 #[cfg(feature = "debug-print")]
 #[macro_export]
 macro_rules! debug_print {
-    ($s:literal) => {
-        let cstr = concat!($s, "\n");
-        $crate::write_stderr(cstr);
+    ($($arg:tt)*) => {
+        $crate::dprintf!(
+            $crate::write::STDERR,
+            "{}\n",
+            ::std::format_args!($($arg)*)
+        )
     };
 }
 
+/// Writes the formatted message, followed by a newline, to stderr in an async
+/// signal safe manner if the `debug-print` feature is enabled. See [`dprintf!`]
 #[cfg(not(feature = "debug-print"))]
 #[macro_export]
 macro_rules! debug_print {
-    ($s:literal) => {};
+    ($($arg:tt)*) => {};
 }
 
 /// Writes the specified string directly to stderr.
 ///
 /// This is safe to be called from within a compromised context.
 #[inline]
-pub fn write_stderr(s: &'static str) {
-    unsafe {
-        #[cfg(target_os = "windows")]
-        libc::write(2, s.as_ptr().cast(), s.len() as u32);
-
-        #[cfg(not(target_os = "windows"))]
-        libc::write(2, s.as_ptr().cast(), s.len());
-    }
+pub fn write_stderr(s: &str) {
+    write::write_str(write::STDERR, s);
 }
 
 cfg_if::cfg_if! {
-    if #[cfg(all(unix, not(target_os = "macos")))] {
-        /// The sole purpose of the unix module is to hook pthread_create to ensure
+    if #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "tvos"))))] {
+        /// The sole purpose of the unix module is to hook `pthread_create` to ensure
         /// an alternate stack is installed for every native thread in case of a
-        /// stack overflow. This doesn't apply to MacOS as it uses exception ports,
+        /// stack overflow. This doesn't apply to macOS/iOS as they use exception ports,
         /// which are always delivered to a specific thread owned by the exception
         /// handler
         pub mod unix;
@@ -47,11 +80,41 @@ cfg_if::cfg_if! {
 
 pub use crash_context::CrashContext;
 
-/// The result of the user code executed during a crash event
+/// The result of the user code executed during a crash event, which determines
+/// what happens once the [`CrashEvent`] returns
 pub enum CrashEventResult {
-    /// The event was handled in some way
-    Handled(bool),
-    #[cfg(not(target_os = "macos"))]
+    /// The crash was handled, and the process is terminated without invoking
+    /// any other handlers.
+    ///
+    /// If `exit` is `None`, the default disposition for the crash is restored
+    /// and the crash is raised again, so the process terminates exactly as it
+    /// would have if no handler had been installed, eg. with the same signal or
+    /// exception code reported to the parent process. Otherwise, the process
+    /// is terminated immediately with the specified exit code.
+    ///
+    /// Note that on Linux/Android, if
+    /// [`CrashHandlerBuilder::chain_debuggerd`](crate::CrashHandlerBuilder)
+    /// is enabled, the previous handler is still invoked first.
+    Handled {
+        /// The code to exit the process with, or `None` to terminate it with
+        /// the original crash
+        exit: Option<i32>,
+    },
+    /// Resumes execution of the crashing thread as if the crash hadn't
+    /// occurred, without changing how future crashes are handled.
+    ///
+    /// This is only useful if the cause of the crash was resolved by the
+    /// callback, eg. by mapping the memory that was accessed or modifying the
+    /// context, or if the crash was not caused by the thread itself, eg. a
+    /// signal sent by another process, as otherwise the same crash will just
+    /// immediately occur again.
+    Continue,
+    /// The crash was not handled, and is passed on to whatever handler was
+    /// installed before ours, which on Linux/Android means chaining directly
+    /// to the previous signal handler if there was one, or terminating the
+    /// process with the original crash if there wasn't.
+    Reraise,
+    #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "tvos")))]
     /// The handler wishes to jump somewhere else, presumably to return
     /// execution and skip the code that caused the exception
     Jump {
@@ -63,12 +126,27 @@ pub enum CrashEventResult {
     },
 }
 
+/// Converts the boolean result of older callbacks, where `true` is
+/// [`CrashEventResult::Handled`] without an exit code, and `false` is
+/// [`CrashEventResult::Reraise`]
 impl From<bool> for CrashEventResult {
     fn from(b: bool) -> Self {
-        Self::Handled(b)
+        if b {
+            Self::Handled { exit: None }
+        } else {
+            Self::Reraise
+        }
     }
 }
 
+/// Terminates the process immediately with the specified exit code, without
+/// running any exit handlers, as the process is in a compromised state
+#[inline]
+pub(crate) fn exit_process(code: i32) -> ! {
+    // SAFETY: syscall
+    unsafe { libc::_exit(code) }
+}
+
 /// User implemented trait for handling a crash event that has ocurred.
 ///
 /// # Safety
@@ -100,8 +178,9 @@ impl From<bool> for CrashEventResult {
 /// exception handler which makes them slightly safer to handle than UNIX signals,
 /// but it is again recommended to do as little work as possible.
 pub unsafe trait CrashEvent: Send + Sync {
-    /// Method invoked when a crash occurs. Returning true indicates your handler
-    /// has processed the crash and that no further handlers should run.
+    /// Method invoked when a crash occurs. The returned [`CrashEventResult`]
+    /// determines whether the process is terminated, execution is resumed, or
+    /// the crash is passed on to the previously installed handler.
     fn on_crash(&self, context: &CrashContext) -> CrashEventResult;
 }
 
@@ -135,14 +214,25 @@ cfg_if::cfg_if! {
     if #[cfg(any(target_os = "linux", target_os = "android"))] {
         mod linux;
 
-        pub use linux::{CrashHandler, Signal, jmp};
+        pub use linux::{CrashGuard, CrashHandler, CrashHandlerBuilder, ForkBehavior, SanitizerPolicy, Signal, Termination, abort_message, alloc_failure, crashed_threads, environment, hang_monitor, introspect, jmp, memory_pressure, sanitizers, signals, threads};
+        pub use crash_context::{AccessType, CrashReason, FaultInfo, SeccompViolation};
+    } else if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+        mod bsd;
+
+        pub use bsd::{CrashHandler, CrashHandlerBuilder, Signal, jmp};
     } else if #[cfg(target_os = "windows")] {
         mod windows;
 
-        pub use windows::{CrashHandler, ExceptionCode, jmp};
-    } else if #[cfg(target_os = "macos")] {
+        pub use windows::{CrashHandler, CrashHandlerBuilder, ExceptionCode, HandlerMode, jmp, wer};
+        pub use crash_context::CrashReason;
+    } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
         mod mac;
 
-        pub use mac::{CrashHandler, ExceptionType};
+        pub use mac::{CrashHandler, CrashHandlerBuilder, ExceptionType};
     }
 }
+
+#[cfg(feature = "panic")]
+mod panic;
+#[cfg(feature = "panic")]
+pub use panic::panic_message;
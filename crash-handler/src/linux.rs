@@ -1,7 +1,27 @@
+pub mod abort_message;
+pub mod alloc_failure;
+pub mod crashed_threads;
+mod dispatch;
+mod dump_request;
+pub mod environment;
+pub mod hang_monitor;
+mod hold;
+pub mod introspect;
 pub mod jmp;
+pub mod memory_pressure;
+pub mod sanitizers;
+pub mod signals;
+mod stack;
 mod state;
+pub mod threads;
+mod trap;
+mod watchdog;
 
-use crate::Error;
+use crate::{events::EventId, Error};
+pub use hold::CrashGuard;
+
+#[cfg(feature = "panic")]
+pub(crate) use state::simulate_panic;
 
 /// The signals that we support catching and raising
 #[derive(Copy, Clone, PartialEq)]
@@ -12,7 +32,15 @@ pub enum Signal {
     Fpe = libc::SIGFPE,
     Illegal = libc::SIGILL,
     Segv = libc::SIGSEGV,
+    /// Raised when a seccomp filter forbids a syscall, see
+    /// [`crate::CrashContext::seccomp_violation`]
+    Sys = libc::SIGSYS,
     Trap = libc::SIGTRAP,
+    /// `BIONIC_SIGNAL_DEBUGGER`, which is sent to request a dump of a process
+    /// that continues running afterwards, eg. via `debuggerd -b` or when the
+    /// system detects an ANR. See [`CrashHandlerBuilder::chain_debuggerd`]
+    #[cfg(target_os = "android")]
+    Debugger = 35, // __SIGRTMIN + 3
 }
 
 impl Signal {
@@ -24,11 +52,462 @@ impl Signal {
     }
 }
 
+/// What happens to the attached handler in the child process after a `fork`,
+/// see [`CrashHandlerBuilder::fork_behavior`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ForkBehavior {
+    /// The handler stays installed in the child, and is invoked for a crash
+    /// there just as in the parent
+    #[default]
+    Keep,
+    /// The signal handlers that were installed before ours are restored in the
+    /// child, until [`CrashHandler::reattach_after_fork`] is called
+    Disarm,
+}
+
+/// How the process is terminated once a signal has been handled, see
+/// [`CrashHandlerBuilder::termination`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Termination {
+    /// The default disposition is restored and the signal is raised again
+    /// with its original `siginfo_t`, so that the process dies with the
+    /// signal, eg. a shell reports it as killed by `SIGSEGV`, and a core dump
+    /// is written if the system is configured to do so.
+    ///
+    /// If the signal can't be raised again, eg. because a sandbox forbids it,
+    /// the process exits with 128 plus the signal number instead.
+    #[default]
+    Reraise,
+    /// The process exits with 128 plus the signal number, ie. the code a
+    /// shell reports for a process killed by the signal, without actually
+    /// being killed by it, so no core dump is written.
+    ExitCode,
+    /// Like [`Self::Reraise`], but additionally ensures that the kernel writes
+    /// a core dump when the process is killed, eg. for `systemd-coredump`, so
+    /// that both the report from the callback and the core file are available.
+    ///
+    /// Before the signal is raised again, the soft `RLIMIT_CORE` is raised to
+    /// the hard limit, or removed entirely if the process is privileged enough
+    /// to do so, and the process is made dumpable if it isn't, eg. because it
+    /// called `PR_SET_DUMPABLE` or changed its credentials. Note that the
+    /// latter allows the core dump of a process that dropped privileges to
+    /// contain data it read while it was privileged.
+    ///
+    /// This only applies to signals whose default action is to dump core,
+    /// which includes every [`Signal`], but not eg. `SIGTERM`.
+    CoreDump,
+}
+
+/// What happens when the handler is attached in a process that is running
+/// with a sanitizer or under Valgrind, see
+/// [`CrashHandlerBuilder::sanitizer_policy`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SanitizerPolicy {
+    /// The handler is attached, but the handler that was installed before
+    /// ours, eg. the sanitizer's, is always invoked after the callback, even
+    /// if it returns [`crate::CrashEventResult::Handled`], so that the tool
+    /// still reports the crash.
+    ///
+    /// Valgrind doesn't install a handler, so this is the same as
+    /// [`Self::Ignore`] under Valgrind, where Valgrind only reports a crash if
+    /// the callback lets the process be killed by the signal.
+    #[default]
+    Chain,
+    /// The handler is not attached, and attaching fails with
+    /// [`Error::SanitizerDetected`], so that the tool reports crashes as if
+    /// the crash handler wasn't used at all
+    StepAside,
+    /// The tool isn't detected, and the handler is attached as usual, which
+    /// hides the tool's own crash reports
+    Ignore,
+}
+
 /// A Linux/Android signal handler
-pub struct CrashHandler;
+pub struct CrashHandler {
+    id: EventId,
+    /// Cleared when the handler is detached
+    _marker: Option<crate::marker::CrashMarker>,
+}
+
+/// Configures a [`CrashHandler`] before attaching it
+pub struct CrashHandlerBuilder {
+    priority: i32,
+    alt_stack_size: usize,
+    signals: Vec<Signal>,
+    raw_signals: Vec<i32>,
+    dump_signal: Option<i32>,
+    marker: Option<std::path::PathBuf>,
+    chain_debuggerd: bool,
+    callback_timeout: Option<std::time::Duration>,
+    callback_thread: bool,
+    fork_behavior: ForkBehavior,
+    termination: Termination,
+    sanitizer_policy: SanitizerPolicy,
+    crash_log: Option<crate::crash_loop::CrashLog>,
+    fallback_file: Option<crate::fallback::FallbackFile>,
+    capture_args: bool,
+    env_filter: Option<environment::Filter>,
+}
+
+impl CrashHandlerBuilder {
+    /// Sets the priority of the handler, which determines the order in which
+    /// it is invoked relative to any other attached handlers. Defaults to
+    /// [`crate::DEFAULT_PRIORITY`].
+    ///
+    /// Handlers with a higher priority are invoked first, and handlers with
+    /// the same priority are invoked in the reverse order they were attached
+    /// in. Once a handler returns anything other than
+    /// [`crate::CrashEventResult::Reraise`], no further handlers are invoked.
+    #[inline]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the size, in bytes, of the [alternate signal stack](https://man7.org/linux/man-pages/man2/sigaltstack.2.html)
+    /// that is installed on each thread so that signals caused by a stack
+    /// overflow can still be handled.
+    ///
+    /// The default is the larger of `SIGSTKSZ` and 16KiB, which may not be
+    /// enough for callbacks that do a lot of work, eg. serializing a large
+    /// context. The size is rounded up to the page size, and attaching will
+    /// fail with [`Error::InvalidAltStackSize`] if it is smaller than the
+    /// minimum size supported by the system.
+    ///
+    /// Note that this only applies to threads created after the handler is
+    /// attached, as well as the thread that attaches the handler, as any other
+    /// existing threads will keep the alternate stack they already have.
+    #[inline]
+    pub fn alt_stack_size(mut self, size: usize) -> Self {
+        self.alt_stack_size = size;
+        self
+    }
+
+    /// Sets the signals that a handler is installed for, which defaults to
+    /// every [`Signal`]. Any signal that is not in this set keeps whatever
+    /// handler it currently has.
+    #[inline]
+    pub fn signals(mut self, signals: &[Signal]) -> Self {
+        self.signals = signals.to_vec();
+        self
+    }
+
+    /// Installs the handler for additional signals by their raw signal number,
+    /// which is how signals that aren't a [`Signal`] are handled, eg. the
+    /// realtime signals between `libc::SIGRTMIN()` and `libc::SIGRTMAX()`
+    /// that an application uses for fault injection or watchdog kicks.
+    ///
+    /// The callback is invoked for these just as it is for a crash, so it
+    /// should check [`crate::CrashContext::siginfo`] to tell them apart, and
+    /// return [`crate::CrashEventResult::Continue`] if the process should keep
+    /// running. Attaching fails with [`Error::InvalidSignal`] if any of the
+    /// numbers is not a signal a handler can be installed for.
+    #[inline]
+    pub fn raw_signals(mut self, signals: &[i32]) -> Self {
+        self.raw_signals = signals.to_vec();
+        self
+    }
+
+    /// Installs a handler for the specified signal, eg. `SIGUSR2`, that
+    /// requests a dump of the process while it continues running, like the
+    /// JVM does for `SIGQUIT`. Defaults to `None`.
+    ///
+    /// The callback is invoked with [`crate::CrashReason::DumpRequest`] on a
+    /// dedicated thread that is spawned when the handler is attached, rather
+    /// than in the signal handler, so it is free to eg. call
+    /// `minidumper::Client::request_dump`, or [`crate::threads::suspend_and_capture`].
+    /// The pid of the process that sent the signal is in
+    /// [`crate::CrashContext::siginfo`]. The result of the callback is ignored.
+    ///
+    /// Attaching fails with [`Error::InvalidSignal`] if the signal is not one
+    /// a handler can be installed for, or is one of the crash signals. Like
+    /// the [callback thread](Self::callback_thread), this doesn't apply in the
+    /// child after a `fork` until [`CrashHandler::reattach_after_fork`] is
+    /// called.
+    #[inline]
+    pub fn dump_signal(mut self, signal: Option<i32>) -> Self {
+        self.dump_signal = signal;
+        self
+    }
+
+    /// Cooperates with Bionic's `debuggerd`, whose signal handlers are
+    /// installed in every process before `main`, so that both the callback
+    /// runs and a tombstone is written for a crash. Defaults to `false`.
+    ///
+    /// If enabled, the handler that was installed before ours, ie. usually
+    /// `debuggerd`'s, is always invoked after the callback, even if the
+    /// callback returns [`crate::CrashEventResult::Handled`], and a handler
+    /// is also installed for [`Signal::Debugger`]. The callback is
+    /// invoked for dump requests as well, but since the process continues
+    /// running afterwards, the result of the callback is ignored, so it
+    /// should check [`crate::CrashContext::siginfo`] to distinguish them from
+    /// actual crashes.
+    #[cfg(target_os = "android")]
+    #[inline]
+    pub fn chain_debuggerd(mut self, chain: bool) -> Self {
+        self.chain_debuggerd = chain;
+        self
+    }
+
+    /// Writes a [`crate::marker::CrashMarker`] to the specified path when the
+    /// handler is attached, which is cleared when the handler is detached, so
+    /// that [`crate::marker::CrashMarker::previous_run`] can detect if the
+    /// process was terminated abnormally, eg. by `SIGKILL`, on the next run.
+    ///
+    /// Like the rest of the configuration, this only applies to the first
+    /// handler to be attached.
+    #[inline]
+    pub fn crash_marker(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.marker = Some(path.into());
+        self
+    }
+
+    /// Terminates the process if the callback hasn't returned within the
+    /// specified time after a signal is caught, eg. because it is deadlocked
+    /// on a lock that was held by the crashing thread. Defaults to no timeout.
+    ///
+    /// A background thread is spawned when the handler is attached that, once
+    /// the timeout elapses, restores the default signal handlers and raises
+    /// the original signal again, so that the process still dies with it.
+    /// The timeout doesn't apply to [`CrashHandler::simulate_signal`].
+    #[inline]
+    pub fn callback_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.callback_timeout = Some(timeout);
+        self
+    }
+
+    /// Invokes the callback on a dedicated thread rather than in the signal
+    /// handler itself. Defaults to `false`.
+    ///
+    /// The thread is spawned when the handler is attached, and the signal
+    /// handler then only captures the [`crate::CrashContext`] and wakes the
+    /// thread, parking the crashing thread until the callback has returned.
+    /// As the callback is no longer run in the context of a signal handler,
+    /// it can allocate, or take locks that it knows the crashing thread can't
+    /// be holding. It is still invoked while the rest of the process keeps
+    /// running though, so any state the crashing thread was modifying may be
+    /// inconsistent.
+    ///
+    /// Like the [callback timeout](Self::callback_timeout), this doesn't apply
+    /// in the child after a `fork` until [`CrashHandler::reattach_after_fork`]
+    /// is called, and the callback is invoked on the crashing thread instead.
+    #[inline]
+    pub fn callback_thread(mut self, enabled: bool) -> Self {
+        self.callback_thread = enabled;
+        self
+    }
+
+    /// Sets what happens to the handler in the child process after a `fork`.
+    /// Defaults to [`ForkBehavior::Keep`].
+    ///
+    /// Regardless of this, any state that belonged to the other threads of
+    /// the parent, which don't exist in the child, is reset in the child, and
+    /// the [callback timeout](Self::callback_timeout) doesn't apply until
+    /// [`CrashHandler::reattach_after_fork`] is called, as the watchdog thread
+    /// isn't copied either.
+    ///
+    /// The callback itself is copied as is though, so if it refers to state
+    /// that is wrong in the child, eg. a connection to a process that writes
+    /// minidumps for the parent, [`ForkBehavior::Disarm`] can be used so that
+    /// the child doesn't report crashes until that state has been
+    /// re-initialized and [`CrashHandler::reattach_after_fork`] is called.
+    #[inline]
+    pub fn fork_behavior(mut self, behavior: ForkBehavior) -> Self {
+        self.fork_behavior = behavior;
+        self
+    }
+
+    /// Sets how the process is terminated once the callback returns
+    /// [`crate::CrashEventResult::Handled`] without an exit code, or the crash
+    /// isn't handled at all because of the [crash log](Self::crash_log).
+    /// Defaults to [`Termination::Reraise`].
+    #[inline]
+    pub fn termination(mut self, termination: Termination) -> Self {
+        self.termination = termination;
+        self
+    }
+
+    /// Sets what happens if the process is running with a sanitizer, eg.
+    /// `AddressSanitizer`, or under Valgrind, when the handler is attached.
+    /// Defaults to [`SanitizerPolicy::Chain`].
+    ///
+    /// These tools report crashes themselves, which attaching the handler
+    /// would otherwise hide. See [`sanitizers::detect`] for how they are
+    /// detected.
+    #[inline]
+    pub fn sanitizer_policy(mut self, policy: SanitizerPolicy) -> Self {
+        self.sanitizer_policy = policy;
+        self
+    }
+
+    /// Records every crash in the specified [`crate::crash_loop::CrashLog`],
+    /// so that [`crate::crash_loop::status`] can detect a crash loop on the
+    /// next run, and stops invoking the callbacks for crashes that exceed
+    /// [`crate::crash_loop::CrashLog::max_dumps_per_hour`].
+    ///
+    /// Only the crash signals, ie. every [`Signal`], as well as panics routed
+    /// through the handler, are recorded, not [raw signals](Self::raw_signals).
+    #[inline]
+    pub fn crash_log(mut self, log: crate::crash_loop::CrashLog) -> Self {
+        self.crash_log = Some(log);
+        self
+    }
+
+    /// Writes every crash to the specified [`crate::fallback::FallbackFile`]
+    /// before the callbacks are invoked, so that the crash can still be
+    /// retrieved via [`crate::fallback::read`] if there is nothing else to
+    /// report it to, eg. a `minidumper` server.
+    ///
+    /// Like the [crash log](Self::crash_log), only the crash signals and
+    /// panics are written, not [raw signals](Self::raw_signals).
+    #[inline]
+    pub fn fallback_file(mut self, file: crate::fallback::FallbackFile) -> Self {
+        self.fallback_file = Some(file);
+        self
+    }
+
+    /// Captures the arguments the process was started with when the handler
+    /// is attached, and records them in every [`crate::CrashContext`]. Defaults
+    /// to `false`.
+    ///
+    /// See [`crate::environment`] for how they are recorded.
+    #[inline]
+    pub fn capture_args(mut self, capture: bool) -> Self {
+        self.capture_args = capture;
+        self
+    }
+
+    /// Captures the environment variables for which the filter returns a
+    /// value when the handler is attached, and records them, with the value
+    /// the filter returned so that secrets can be redacted, in every
+    /// [`crate::CrashContext`]. Defaults to capturing none of them.
+    ///
+    /// The filter is invoked with the name and value of every variable once,
+    /// when attaching, rather than at the time of a crash. See
+    /// [`crate::environment::allowlist`] for a filter that keeps only the
+    /// specified variables.
+    #[inline]
+    pub fn capture_env(
+        mut self,
+        filter: impl FnMut(&str, &str) -> Option<String> + 'static,
+    ) -> Self {
+        self.env_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Attaches the signal handler with the current configuration.
+    ///
+    /// If another handler is already attached, only the priority applies, as
+    /// the signal handlers, and the rest of their configuration, are shared by
+    /// every attached handler. In that case the [crash marker](Self::crash_marker)
+    /// isn't written, and the [environment](Self::capture_env) isn't captured,
+    /// either.
+    ///
+    /// See [`CrashHandler::attach`]
+    pub fn attach(self, on_crash: Box<dyn crate::CrashEvent>) -> Result<CrashHandler, Error> {
+        let sanitizer = match self.sanitizer_policy {
+            SanitizerPolicy::Ignore => None,
+            SanitizerPolicy::Chain | SanitizerPolicy::StepAside => sanitizers::detect(),
+        };
+        if let Some(tool) = sanitizer {
+            if self.sanitizer_policy == SanitizerPolicy::StepAside {
+                return Err(Error::SanitizerDetected(tool));
+            }
+        }
+
+        let mut signals: Vec<i32> = self.signals.iter().map(|sig| *sig as i32).collect();
+
+        #[cfg(target_os = "android")]
+        if self.chain_debuggerd {
+            signals.push(Signal::Debugger as i32);
+        }
+
+        for sig in self.raw_signals {
+            state::validate_signal(sig)?;
+            signals.push(sig);
+        }
+
+        if let Some(sig) = self.dump_signal {
+            state::validate_signal(sig)?;
+            if state::EXCEPTION_SIGNALS
+                .iter()
+                .any(|crash| *crash as i32 == sig)
+            {
+                return Err(Error::InvalidSignal(sig));
+            }
+            signals.push(sig);
+        }
+
+        signals.sort_unstable();
+        signals.dedup();
+
+        let mut marker = None;
+        let id = state::attach(on_crash, self.priority, || {
+            // Written before the signal handlers are installed so that it is
+            // removed again if that fails
+            marker = self
+                .marker
+                .map(crate::marker::CrashMarker::create)
+                .transpose()?;
+
+            let environment = (self.capture_args || self.env_filter.is_some())
+                .then(|| environment::capture(self.capture_args, self.env_filter));
+
+            Ok(state::Settings {
+                alt_stack_size: self.alt_stack_size,
+                signals,
+                // Valgrind has no handler to chain to
+                always_chain: self.chain_debuggerd
+                    || sanitizer.is_some_and(|tool| tool != sanitizers::Tool::Valgrind),
+                callback_timeout: self.callback_timeout,
+                callback_thread: self.callback_thread,
+                dump_signal: self.dump_signal,
+                fork_behavior: self.fork_behavior,
+                termination: self.termination,
+                crash_log: self.crash_log,
+                fallback_file: self.fallback_file,
+                environment,
+            })
+        })?;
+        Ok(CrashHandler {
+            id,
+            _marker: marker,
+        })
+    }
+}
+
+impl Default for CrashHandlerBuilder {
+    fn default() -> Self {
+        Self {
+            priority: crate::DEFAULT_PRIORITY,
+            alt_stack_size: crate::unix::DEFAULT_ALT_STACK_SIZE,
+            signals: state::EXCEPTION_SIGNALS.to_vec(),
+            raw_signals: Vec::new(),
+            dump_signal: None,
+            marker: None,
+            chain_debuggerd: false,
+            callback_timeout: None,
+            callback_thread: false,
+            fork_behavior: ForkBehavior::Keep,
+            termination: Termination::Reraise,
+            sanitizer_policy: SanitizerPolicy::default(),
+            crash_log: None,
+            fallback_file: None,
+            capture_args: false,
+            env_filter: None,
+        }
+    }
+}
 
 #[allow(clippy::unused_self)]
 impl CrashHandler {
+    /// Creates a builder that can be used to configure the handler before it
+    /// is attached
+    #[inline]
+    pub fn builder() -> CrashHandlerBuilder {
+        CrashHandlerBuilder::default()
+    }
+
     /// Attaches the signal handler.
     ///
     /// The provided callback will be invoked if a signal is caught, providing a
@@ -39,17 +518,56 @@ impl CrashHandler {
     /// to not perform actions that may fail due to corrupted state that caused
     /// or is a symptom of the original signal. This includes doing heap
     /// allocations from the same allocator as the crashing code.
+    ///
+    /// Multiple handlers can be attached at the same time, see
+    /// [`CrashHandlerBuilder::priority`] for the order they are invoked in.
     pub fn attach(on_crash: Box<dyn crate::CrashEvent>) -> Result<Self, Error> {
-        state::attach(on_crash)?;
-        Ok(Self)
+        Self::builder().attach(on_crash)
+    }
+
+    /// Attaches the signal handler with the specified priority.
+    ///
+    /// See [`CrashHandlerBuilder::priority`]
+    pub fn attach_with_priority(
+        priority: i32,
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, Error> {
+        Self::builder().priority(priority).attach(on_crash)
     }
 
-    /// Detaches the handler.
+    /// Attaches the signal handler, but only for the specified signals,
+    /// leaving the handlers for any other signals untouched.
+    ///
+    /// See [`CrashHandler::attach`]
+    pub fn attach_with(
+        signals: &[Signal],
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, Error> {
+        Self::builder().signals(signals).attach(on_crash)
+    }
+
+    /// Detaches the handler, and if it is the last one attached, restores
+    /// the signal handlers that were installed before it.
     ///
     /// This is done automatically when this [`CrashHandler`] is dropped.
     #[inline]
     pub fn detach(self) {
-        state::detach();
+        state::detach(self.id);
+    }
+
+    /// Re-arms the handler in the child process after a `fork`, eg. in a
+    /// daemon that forks worker processes.
+    ///
+    /// This re-installs the signal handlers if they were disarmed by
+    /// [`ForkBehavior::Disarm`], and restarts the watchdog thread if a
+    /// [callback timeout](CrashHandlerBuilder::callback_timeout) was
+    /// configured, as well as the [callback thread](CrashHandlerBuilder::callback_thread). It does nothing in a process that hasn't forked.
+    ///
+    /// # Errors
+    ///
+    /// The watchdog or callback thread could not be started
+    pub fn reattach_after_fork(&self) -> Result<(), Error> {
+        state::reattach_after_fork()
     }
 
     /// Sends the specified user signal.
@@ -59,6 +577,10 @@ impl CrashHandler {
         // uphold any guarantees on their end, so no real need to declare the
         // function itself unsafe.
         unsafe {
+            let Some(_in_handler) = state::InHandler::enter() else {
+                return crate::CrashEventResult::Reraise;
+            };
+
             let mut siginfo: libc::signalfd_siginfo = std::mem::zeroed();
             siginfo.ssi_code = state::SI_USER;
             siginfo.ssi_pid = std::process::id();
@@ -66,22 +588,40 @@ impl CrashHandler {
             let mut context = std::mem::zeroed();
             crash_context::crash_context_getcontext(&mut context);
 
-            let lock = state::HANDLER.lock();
-            if let Some(handler) = &*lock {
+            if let Some(handler) = state::HANDLER.read() {
                 handler.handle_signal(
                     signal as i32,
                     &mut *(&mut siginfo as *mut libc::signalfd_siginfo).cast::<libc::siginfo_t>(),
                     &mut *(&mut context as *mut crash_context::ucontext_t).cast::<libc::c_void>(),
                 )
             } else {
-                crate::CrashEventResult::Handled(false)
+                crate::CrashEventResult::Reraise
             }
         }
     }
+
+    /// Invokes the attached callbacks with the specified context, exactly as
+    /// it is, as if it was an actual crash, eg. one created with
+    /// [`crate::CrashContext::synthetic`], so that the code that processes
+    /// crashes can be tested deterministically.
+    ///
+    /// Unlike [`Self::simulate_signal`], none of the context is filled out,
+    /// and the result is only returned rather than acted upon.
+    pub fn simulate_context(&self, context: &crate::CrashContext) -> crate::CrashEventResult {
+        state::simulate_context(context)
+    }
+
+    /// Returns every signal the handler was installed for, along with its
+    /// previous and current disposition, so that other code that replaced
+    /// the handler can be detected, see [`introspect`]
+    #[inline]
+    pub fn installed_signals(&self) -> Vec<introspect::InstalledSignal> {
+        introspect::installed_signals()
+    }
 }
 
 impl Drop for CrashHandler {
     fn drop(&mut self) {
-        state::detach();
+        state::detach(self.id);
     }
 }
@@ -0,0 +1,237 @@
+//! The message describing why the process aborted, which is recorded in the
+//! [`crate::CrashContext::abort_message`] of a `SIGABRT`, so that eg. the
+//! assertion that failed is visible in the report of the crash.
+//!
+//! With glibc, the message glibc itself records before aborting, ie. of a
+//! failed `assert`, or of heap corruption it detected, eg.
+//! `free(): invalid pointer`, is captured. Bionic doesn't expose the message
+//! set via `android_set_abort_message`, eg. by `LOG_ALWAYS_FATAL`, so on
+//! Android only messages set via [`set`] are captured. A message set via
+//! [`set`] takes precedence over one recorded by libc.
+//!
+//! With `panic = "abort"`, the message of the panic that caused the abort can
+//! be recorded as well, via [`install_panic_hook`].
+//!
+//! ```
+//! crash_handler::abort_message::set("invariant violated: queue is empty");
+//! crash_handler::abort_message::install_panic_hook();
+//! ```
+
+use crash_context::ABORT_MESSAGE_LEN;
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Message(UnsafeCell<[u8; ABORT_MESSAGE_LEN]>);
+
+// SAFETY: The message is only written while holding the `WRITER` lock, and
+// readers check the sequence number before and after reading it
+unsafe impl Sync for Message {}
+
+static MESSAGE: Message = Message(UnsafeCell::new([0; ABORT_MESSAGE_LEN]));
+/// The length of the message, or 0 if there is none
+static LEN: AtomicUsize = AtomicUsize::new(0);
+/// Odd while the message is being written, so that readers can detect if it
+/// was modified while they were reading it
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+static WRITER: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+/// The address of glibc's `__abort_msg`, or 0 if it couldn't be resolved
+static LIBC_MESSAGE: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the message recorded if the process aborts, which is truncated to
+/// [`crash_context::ABORT_MESSAGE_LEN`] - 1 bytes.
+///
+/// On Android, the message is also passed on to `android_set_abort_message`,
+/// so that it appears in the tombstone as well.
+pub fn set(message: &str) {
+    // Nul bytes would cut the message short
+    let message = message.split('\0').next().unwrap_or_default();
+
+    record(format_args!("{message}"));
+
+    #[cfg(target_os = "android")]
+    {
+        extern "C" {
+            fn android_set_abort_message(msg: *const std::ffi::c_char);
+        }
+
+        if let Ok(message) = std::ffi::CString::new(message) {
+            // SAFETY: the message is nul terminated, and copied by Bionic
+            unsafe { android_set_abort_message(message.as_ptr()) };
+        }
+    }
+}
+
+/// Installs a [panic hook](std::panic::set_hook) that records the message and
+/// location of a panic as the abort message, before passing the panic on to
+/// the hook that was installed before it.
+///
+/// This is meant for processes compiled with `panic = "abort"`, where the
+/// `SIGABRT` that follows a panic would otherwise carry no hint of what
+/// panicked. The message is formatted directly into the buffer that is
+/// captured by the signal handler, eg. `panicked at src/main.rs:4:5: oh no`.
+///
+/// If the panic is caught instead, its message remains the abort message
+/// until it is replaced via [`set`] or removed via [`clear`]. Installing the
+/// hook more than once has no effect.
+pub fn install_panic_hook() {
+    static INSTALLED: std::sync::Once = std::sync::Once::new();
+
+    INSTALLED.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let payload = info.payload();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("Box<dyn Any>");
+
+            match info.location() {
+                Some(location) => record(format_args!("panicked at {location}: {message}")),
+                None => record(format_args!("panicked: {message}")),
+            }
+
+            previous(info);
+        }));
+    });
+}
+
+/// Formats the message into the buffer, and publishes it to the signal
+/// handler
+fn record(message: std::fmt::Arguments<'_>) {
+    let _writer = WRITER.lock();
+
+    SEQUENCE.fetch_add(1, Ordering::AcqRel);
+    // SAFETY: we hold the lock, and readers will discard what they read
+    // while the message is being written
+    let len = unsafe { write(&mut *MESSAGE.0.get(), message) };
+    LEN.store(len, Ordering::Release);
+    SEQUENCE.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Formats the message into the buffer, nul terminated, truncating it at the
+/// first nul byte, or on a character boundary if it doesn't fit, and returns
+/// its length.
+///
+/// This does not allocate, so it can be used from the signal handler.
+pub(super) fn write(buf: &mut [u8; ABORT_MESSAGE_LEN], message: std::fmt::Arguments<'_>) -> usize {
+    struct Writer<'buf> {
+        buf: &'buf mut [u8; ABORT_MESSAGE_LEN],
+        len: usize,
+        full: bool,
+    }
+
+    impl std::fmt::Write for Writer<'_> {
+        fn write_str(&mut self, s: &str) -> std::fmt::Result {
+            if self.full {
+                return Ok(());
+            }
+
+            let nul = s.find('\0');
+            let s = &s[..nul.unwrap_or(s.len())];
+
+            let mut len = s.len().min(ABORT_MESSAGE_LEN - 1 - self.len);
+            while !s.is_char_boundary(len) {
+                len -= 1;
+            }
+
+            self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+            self.len += len;
+            self.full = nul.is_some() || len < s.len();
+            Ok(())
+        }
+    }
+
+    let mut writer = Writer {
+        buf,
+        len: 0,
+        full: false,
+    };
+    // Formatting into the buffer itself can't fail, only the `Display` of the
+    // arguments can, in which case we keep what was written
+    let _ = std::fmt::write(&mut writer, message);
+    writer.buf[writer.len] = 0;
+    writer.len
+}
+
+/// Clears the message set via [`set`]
+pub fn clear() {
+    let _writer = WRITER.lock();
+    LEN.store(0, Ordering::Release);
+}
+
+/// Resolves the message recorded by libc, which uses `dlsym`, so it is done
+/// when attaching rather than in the signal handler
+pub(super) fn resolve() {
+    cfg_if::cfg_if! {
+        if #[cfg(all(target_os = "linux", target_env = "gnu"))] {
+            // SAFETY: dlsym with a valid, nul terminated name
+            let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"__abort_msg".as_ptr()) };
+            LIBC_MESSAGE.store(address as usize, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Copies the current message into the buffer, nul terminated, or leaves the
+/// buffer untouched if there is none.
+///
+/// This does not take any locks or allocate.
+pub(super) fn capture(buf: &mut [u8; ABORT_MESSAGE_LEN]) {
+    if capture_set(buf) {
+        return;
+    }
+
+    let address = LIBC_MESSAGE.load(Ordering::Relaxed);
+    if address == 0 {
+        return;
+    }
+
+    // glibc's `struct abort_msg_s *__abort_msg`, where the struct is the
+    // `u32` size of its mapping, followed by the nul terminated message
+    // SAFETY: the address is that of the pointer, which is either null, or
+    // points to the mapping, which glibc never unmaps once it is set
+    unsafe {
+        let abort_msg = std::ptr::read_volatile(address as *const *const u8);
+        if abort_msg.is_null() {
+            return;
+        }
+
+        let size = std::ptr::read_unaligned(abort_msg.cast::<u32>()) as usize;
+        let message = abort_msg.add(4);
+        let max = size.saturating_sub(4).min(ABORT_MESSAGE_LEN - 1);
+
+        let mut len = 0;
+        while len < max {
+            let b = *message.add(len);
+            if b == 0 {
+                break;
+            }
+            buf[len] = b;
+            len += 1;
+        }
+        buf[len] = 0;
+    }
+}
+
+/// Copies the message set via [`set`], returning false if there is none, or
+/// it was being written concurrently
+#[inline]
+fn capture_set(buf: &mut [u8; ABORT_MESSAGE_LEN]) -> bool {
+    let before = SEQUENCE.load(Ordering::Acquire);
+    let len = LEN.load(Ordering::Acquire);
+    if !before.is_multiple_of(2) || len == 0 {
+        return false;
+    }
+
+    // SAFETY: we copy the message before checking that it was not modified
+    // while we were reading it
+    let message = unsafe { std::ptr::read_volatile(MESSAGE.0.get()) };
+    if SEQUENCE.load(Ordering::Acquire) != before {
+        return false;
+    }
+
+    buf[..=len].copy_from_slice(&message[..=len]);
+    true
+}
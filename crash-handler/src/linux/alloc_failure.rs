@@ -0,0 +1,103 @@
+//! Classification of aborts caused by running out of memory.
+//!
+//! When a heap allocation fails, Rust calls [`std::alloc::handle_alloc_error`],
+//! which aborts the process, so the resulting `SIGABRT` would otherwise look
+//! the same as eg. a failed assertion. Replacing what it does requires the
+//! unstable `set_alloc_error_hook`, so instead [`RecordingAllocator`] wraps
+//! the global allocator and records the size of any allocation that fails. If
+//! the thread that failed to allocate then aborts, the crash is reported with
+//! [`crate::CrashReason::AllocationFailure`], and an abort message of
+//! `memory allocation of <size> bytes failed`, the same as the one Rust prints.
+//!
+//! A failed allocation doesn't necessarily abort, eg. one made via
+//! `Vec::try_reserve`, so the failure is forgotten as soon as that thread
+//! successfully allocates again.
+//!
+//! ```
+//! use crash_handler::alloc_failure::RecordingAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: RecordingAllocator = RecordingAllocator::new(std::alloc::System);
+//! ```
+
+use crash_context::ABORT_MESSAGE_LEN;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicI32, AtomicUsize, Ordering},
+};
+
+/// The size of the allocation that failed, or 0 if none has
+static FAILED_SIZE: AtomicUsize = AtomicUsize::new(0);
+/// The thread the allocation failed on
+static FAILED_THREAD: AtomicI32 = AtomicI32::new(0);
+
+/// A global allocator that forwards to another allocator, [`System`] by
+/// default, and records allocations that fail, see the [module](self) docs
+pub struct RecordingAllocator<A = System>(A);
+
+impl<A> RecordingAllocator<A> {
+    /// Wraps the specified allocator
+    pub const fn new(inner: A) -> Self {
+        Self(inner)
+    }
+
+    #[inline]
+    fn record(ptr: *mut u8, size: usize) -> *mut u8 {
+        if ptr.is_null() {
+            FAILED_THREAD.store(current_thread(), Ordering::Relaxed);
+            FAILED_SIZE.store(size, Ordering::Release);
+        } else if FAILED_SIZE.load(Ordering::Relaxed) != 0
+            && FAILED_THREAD.load(Ordering::Relaxed) == current_thread()
+        {
+            FAILED_SIZE.store(0, Ordering::Relaxed);
+        }
+
+        ptr
+    }
+}
+
+// SAFETY: every method forwards to the wrapped allocator
+unsafe impl<A: GlobalAlloc> GlobalAlloc for RecordingAllocator<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::record(self.0.alloc(layout), layout.size())
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::record(self.0.alloc_zeroed(layout), layout.size())
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout);
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::record(self.0.realloc(ptr, layout, new_size), new_size)
+    }
+}
+
+#[inline]
+fn current_thread() -> i32 {
+    // SAFETY: syscall
+    unsafe { libc::syscall(libc::SYS_gettid) as i32 }
+}
+
+/// If the last allocation made by the thread failed, writes a description of
+/// it into the abort message and returns true.
+///
+/// This does not take any locks or allocate.
+pub(super) fn capture(tid: i32, abort_message: &mut [u8; ABORT_MESSAGE_LEN]) -> bool {
+    let size = FAILED_SIZE.load(Ordering::Acquire);
+    if size == 0 || FAILED_THREAD.load(Ordering::Relaxed) != tid {
+        return false;
+    }
+
+    super::abort_message::write(
+        abort_message,
+        format_args!("memory allocation of {size} bytes failed"),
+    );
+    true
+}
@@ -0,0 +1,208 @@
+//! Threads that crash while another thread's crash is being handled.
+//!
+//! Only one crash is handled at a time, and the first thread to crash wins.
+//! Every other thread that crashes in the meantime is parked until the
+//! handling completes, and is recorded in a fixed size array of
+//! [`crash_context::CrashedThread`] slots, whose location is in the
+//! [`crate::CrashContext`], so that the crash being handled, eg. a minidump
+//! written for it, can report them as having also crashed rather than them
+//! being silently dropped when the process is terminated.
+//!
+//! If the handled crash doesn't terminate the process, eg. because the
+//! callback [jumped](crate::CrashEventResult::Jump) or
+//! [continued](crate::CrashEventResult::Continue), the next parked thread is
+//! resumed, removed from the array, and its own crash handled in turn. If it
+//! does, the parked threads stay parked until the process is gone.
+//!
+//! ```
+//! crash_handler::crashed_threads::for_each(|thread| {
+//!     eprintln!("thread {} also crashed with signal {}", thread.tid, thread.signo);
+//! });
+//! ```
+
+use crash_context::{
+    CrashedThread, CRASHED_THREAD_EMPTY, CRASHED_THREAD_VALID, CRASHED_THREAD_WRITING,
+    MAX_CRASHED_THREADS,
+};
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+struct Slots([UnsafeCell<CrashedThread>; MAX_CRASHED_THREADS]);
+
+// SAFETY: A slot is only written by the thread that claimed it by moving it
+// from empty to writing, and only read while it is valid
+unsafe impl Sync for Slots {}
+
+static SLOTS: Slots = Slots([const { UnsafeCell::new(CrashedThread::EMPTY) }; MAX_CRASHED_THREADS]);
+/// The id of the thread whose crash is being handled, or 0 if there is none,
+/// which is also the futex parked threads wait on
+static CRASHING: AtomicU32 = AtomicU32::new(0);
+
+/// Retrieves the state of the slot
+#[inline]
+fn state(slot: &UnsafeCell<CrashedThread>) -> &AtomicU32 {
+    // SAFETY: the pointer is valid, aligned, and only ever accessed atomically
+    unsafe { AtomicU32::from_ptr(std::ptr::addr_of_mut!((*slot.get()).state)) }
+}
+
+/// Invokes the callback with every thread that is currently parked, see the
+/// [module documentation](self).
+///
+/// This does not take any locks or allocate, and is thus safe to call from
+/// within a [`crate::CrashEvent`].
+pub fn for_each(mut cb: impl FnMut(&CrashedThread)) {
+    for slot in &SLOTS.0 {
+        if state(slot).load(Ordering::Acquire) != CRASHED_THREAD_VALID {
+            continue;
+        }
+
+        // SAFETY: the slot is fully written before it becomes valid, and isn't
+        // written again until the thread is resumed
+        cb(unsafe { &*slot.get() });
+    }
+}
+
+/// Retrieves the address and number of the crashed thread slots, which are
+/// recorded in the [`crate::CrashContext`]
+#[inline]
+pub(crate) fn location() -> (usize, usize) {
+    (SLOTS.0.as_ptr() as usize, MAX_CRASHED_THREADS)
+}
+
+/// The right to handle a crash, which is released when dropped, resuming the
+/// next parked thread
+pub(super) struct Gate {
+    _private: (),
+}
+
+impl Gate {
+    /// Claims the right to handle the crash of the current thread, parking it
+    /// until the crash of any other thread has been handled
+    ///
+    /// # Safety
+    ///
+    /// Must only be called from the signal handler with its arguments
+    pub(super) unsafe fn enter(
+        sig: libc::c_int,
+        info: &libc::siginfo_t,
+        uc: &crash_context::ucontext_t,
+    ) -> Self {
+        let tid = crate::unix::current_thread() as u32;
+        let mut slot = None;
+
+        loop {
+            match CRASHING.compare_exchange(0, tid, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => break,
+                // The thread crashed again within the handler, which is
+                // caught before getting here, or forked from within it
+                Err(current) if current == tid => break,
+                Err(current) => {
+                    if slot.is_none() {
+                        slot = record(tid, sig, info, uc);
+                    }
+
+                    libc::syscall(
+                        libc::SYS_futex,
+                        CRASHING.as_ptr(),
+                        libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+                        current,
+                        std::ptr::null::<libc::timespec>(),
+                    );
+                }
+            }
+        }
+
+        // This thread's crash is the one being handled now, so it is no
+        // longer one that also crashed
+        if let Some(slot) = slot {
+            state(slot).store(CRASHED_THREAD_EMPTY, Ordering::Release);
+        }
+
+        Self { _private: () }
+    }
+
+    /// Keeps every other crashing thread parked, as the process is about to be
+    /// terminated
+    #[inline]
+    pub(super) fn hold(self) {
+        #[allow(clippy::mem_forget)]
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Gate {
+    fn drop(&mut self) {
+        CRASHING.store(0, Ordering::Release);
+        // SAFETY: syscall
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                CRASHING.as_ptr(),
+                libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                i32::MAX,
+            );
+        }
+    }
+}
+
+/// Records the current thread as having crashed, returning the slot it was
+/// recorded in, or `None` if every slot is in use
+unsafe fn record(
+    tid: u32,
+    sig: libc::c_int,
+    info: &libc::siginfo_t,
+    uc: &crash_context::ucontext_t,
+) -> Option<&'static UnsafeCell<CrashedThread>> {
+    let slot = SLOTS.0.iter().find(|slot| {
+        state(slot)
+            .compare_exchange(
+                CRASHED_THREAD_EMPTY,
+                CRASHED_THREAD_WRITING,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+    })?;
+
+    // The fault address is only valid for signals raised by the kernel
+    let address = if info.si_code > 0
+        && matches!(
+            sig,
+            libc::SIGSEGV | libc::SIGBUS | libc::SIGILL | libc::SIGFPE | libc::SIGTRAP
+        ) {
+        info.si_addr() as u64
+    } else {
+        0
+    };
+
+    // The state is accessed atomically by readers, so the rest of the fields
+    // are written without taking a reference to the whole slot
+    let thread = slot.get();
+    (*thread).tid = tid;
+    (*thread).signo = sig as u32;
+    (*thread).code = info.si_code;
+    (*thread).address = address;
+    (*thread).instruction_pointer = super::trap::instruction_pointer(uc) as u64;
+    (*thread).stack_pointer = super::stack::stack_pointer(uc) as u64;
+
+    state(slot).store(CRASHED_THREAD_VALID, Ordering::Release);
+    Some(slot)
+}
+
+/// Invoked in the child after a `fork`, where the only thread is the one that
+/// forked, so every other parked thread is gone, and the crash being handled
+/// only remains if the forking thread was the one handling it
+pub(super) fn after_fork(forked_in_handler: bool) {
+    for slot in &SLOTS.0 {
+        state(slot).store(CRASHED_THREAD_EMPTY, Ordering::Release);
+    }
+
+    if forked_in_handler {
+        // The thread has a new id in the child
+        CRASHING.store(crate::unix::current_thread() as u32, Ordering::Release);
+    } else {
+        CRASHING.store(0, Ordering::Release);
+    }
+}
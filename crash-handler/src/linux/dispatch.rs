@@ -0,0 +1,236 @@
+//! Invokes the user's callback on a dedicated thread rather than in the signal
+//! handler, see [`super::CrashHandlerBuilder::callback_thread`].
+//!
+//! The thread is spawned when the handler is attached, so nothing needs to be
+//! allocated once a signal is caught. The signal handler only captures the
+//! context, then writes a request to a pipe the thread is blocked reading,
+//! and parks the crashing thread by reading the reply from a second pipe, as
+//! both `read` and `write` are async signal safe.
+
+use super::state;
+use crate::{events::Events, CrashContext, CrashEventResult, Error};
+use std::{
+    io, mem,
+    sync::atomic::{AtomicI32, AtomicUsize, Ordering},
+};
+
+/// The write end of the pipe requests are sent to, or -1 if there is no
+/// callback thread
+static REQUEST_FD: AtomicI32 = AtomicI32::new(-1);
+/// The read end of the pipe replies are received from
+static REPLY_FD: AtomicI32 = AtomicI32::new(-1);
+/// The ends of both pipes that are owned by the callback thread, but are kept
+/// here so that they can be closed in the child after a `fork`, where the
+/// thread no longer exists
+static SERVE_FDS: [AtomicI32; 2] = [AtomicI32::new(-1), AtomicI32::new(-1)];
+/// The callback thread, so that a signal raised on it isn't sent to itself
+static THREAD: AtomicUsize = AtomicUsize::new(0);
+/// Serializes requests, as several threads can crash at the same time, and
+/// each of them must receive the reply to its own request
+static DISPATCH_LOCK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+
+/// A request to invoke the callback, which lives on the stack of the crashing
+/// thread until the reply is received
+struct Request {
+    events: *const Events,
+    context: *const CrashContext,
+    result: Option<CrashEventResult>,
+}
+
+fn pipe() -> Result<[i32; 2], Error> {
+    let mut fds = [-1; 2];
+    // SAFETY: syscall
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+    Ok(fds)
+}
+
+fn close(fds: &[i32]) {
+    for fd in fds.iter().copied().filter(|fd| *fd != -1) {
+        // SAFETY: syscall
+        unsafe {
+            libc::close(fd);
+        }
+    }
+}
+
+/// Starts the callback thread, which is stopped by [`stop`]
+pub(super) fn start() -> Result<(), Error> {
+    let [request_read, request_write] = pipe()?;
+    let [reply_read, reply_write] = match pipe() {
+        Ok(fds) => fds,
+        Err(err) => {
+            close(&[request_read, request_write]);
+            return Err(err);
+        }
+    };
+
+    let (tx, rx) = std::sync::mpsc::sync_channel(1);
+    if let Err(err) = std::thread::Builder::new()
+        .name("crash-callback".to_owned())
+        .spawn(move || {
+            let _ = tx.send(crate::unix::current_thread());
+            serve(request_read, reply_write);
+        })
+    {
+        // The thread was never started so we still own every end
+        close(&[request_read, request_write, reply_read, reply_write]);
+        return Err(err.into());
+    }
+
+    // Wait for the thread to identify itself, so that it is known before a
+    // signal can be dispatched to it
+    THREAD.store(rx.recv().unwrap_or(0), Ordering::Release);
+    SERVE_FDS[0].store(request_read, Ordering::Release);
+    SERVE_FDS[1].store(reply_write, Ordering::Release);
+    REPLY_FD.store(reply_read, Ordering::Release);
+    REQUEST_FD.store(request_write, Ordering::Release);
+    Ok(())
+}
+
+/// Returns true if the callback thread is running
+#[inline]
+pub(super) fn is_running() -> bool {
+    REQUEST_FD.load(Ordering::Acquire) != -1
+}
+
+/// Stops the callback thread, if any, by closing the write end of the request
+/// pipe
+pub(super) fn stop() {
+    // The thread closes its own ends once it sees the request pipe closed
+    for fd in &SERVE_FDS {
+        fd.store(-1, Ordering::Release);
+    }
+    let fds = [
+        REQUEST_FD.swap(-1, Ordering::AcqRel),
+        REPLY_FD.swap(-1, Ordering::AcqRel),
+    ];
+    THREAD.store(0, Ordering::Release);
+    close(&fds);
+}
+
+/// Forgets the callback thread in the child after a `fork`, as only the
+/// thread that forked is copied to the child, closing every end of both pipes.
+///
+/// This is async signal safe.
+pub(super) fn after_fork() {
+    let fds = [
+        REQUEST_FD.swap(-1, Ordering::AcqRel),
+        REPLY_FD.swap(-1, Ordering::AcqRel),
+        SERVE_FDS[0].swap(-1, Ordering::AcqRel),
+        SERVE_FDS[1].swap(-1, Ordering::AcqRel),
+    ];
+    THREAD.store(0, Ordering::Release);
+    close(&fds);
+
+    // The thread holding the lock, if any, doesn't exist in the child
+    if DISPATCH_LOCK.is_locked() {
+        // SAFETY: the owner doesn't exist in the child
+        unsafe {
+            DISPATCH_LOCK.force_unlock();
+        }
+    }
+}
+
+/// Invokes the events on the callback thread, parking the current thread
+/// until they have returned.
+///
+/// Returns `None` if there is no callback thread, or this is the callback
+/// thread, in which case the events must be invoked on the current thread.
+///
+/// This is async signal safe.
+pub(super) fn run(events: &Events, context: &CrashContext) -> Option<CrashEventResult> {
+    if THREAD.load(Ordering::Acquire) == crate::unix::current_thread() {
+        return None;
+    }
+
+    let _lock = DISPATCH_LOCK.lock();
+
+    let request_fd = REQUEST_FD.load(Ordering::Acquire);
+    let reply_fd = REPLY_FD.load(Ordering::Acquire);
+    if request_fd == -1 || reply_fd == -1 {
+        return None;
+    }
+
+    let mut request = Request {
+        events,
+        context,
+        result: None,
+    };
+
+    let bytes = (std::ptr::addr_of_mut!(request) as usize).to_ne_bytes();
+    // Writes smaller than PIPE_BUF are atomic
+    // SAFETY: syscall
+    if unsafe { libc::write(request_fd, bytes.as_ptr().cast(), bytes.len()) }
+        != bytes.len() as isize
+    {
+        return None;
+    }
+
+    // The thread only replies once it is done with the request, so it can't
+    // outlive this frame. If the thread went away instead, the callback may
+    // or may not have run, so the crash is passed on rather than invoking it
+    // a second time
+    if !receive(reply_fd, &mut [0u8; 1]) {
+        return Some(CrashEventResult::Reraise);
+    }
+
+    Some(request.result.unwrap_or(CrashEventResult::Reraise))
+}
+
+/// Fills the buffer from the pipe, returning false if it was closed
+fn receive(fd: i32, buf: &mut [u8]) -> bool {
+    let mut filled = 0;
+    while filled < buf.len() {
+        // SAFETY: syscall
+        let read = unsafe { libc::read(fd, buf[filled..].as_mut_ptr().cast(), buf.len() - filled) };
+        match read {
+            // Retried
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            0 | -1 => return false,
+            read => filled += read as usize,
+        }
+    }
+    true
+}
+
+fn serve(request_fd: i32, reply_fd: i32) {
+    // The thread that attached may have had the crash signals blocked, which
+    // we would have inherited, but this thread must be able to receive them,
+    // so that a crash within the callback is still detected
+    super::signals::unblock_crash_signals_on_this_thread();
+
+    // The reply pipe can be closed by [`stop`] while a callback is running,
+    // in which case the reply should fail rather than kill the process
+    // SAFETY: syscalls
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGPIPE);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+    }
+
+    let mut bytes = [0u8; mem::size_of::<usize>()];
+    while receive(request_fd, &mut bytes) {
+        // SAFETY: the crashing thread is parked until we reply, so the
+        // request, and everything it points to, is still valid
+        let request = unsafe { &mut *(usize::from_ne_bytes(bytes) as *mut Request) };
+
+        // Marks this thread as handling the signal as well, so that a crash
+        // within the callback isn't dispatched again
+        let result = match state::InHandler::enter() {
+            // SAFETY: see above
+            Some(_in_handler) => unsafe { (*request.events).on_crash(&*request.context) },
+            None => CrashEventResult::Reraise,
+        };
+        request.result = Some(result);
+
+        // SAFETY: syscall
+        unsafe {
+            libc::write(reply_fd, [1u8].as_ptr().cast(), 1);
+        }
+    }
+
+    close(&[request_fd, reply_fd]);
+}
@@ -0,0 +1,172 @@
+//! Dumps requested by sending the process a signal, see
+//! [`super::CrashHandlerBuilder::dump_signal`].
+//!
+//! The signal can arrive on any thread, at any point, so the signal handler
+//! only writes the pid of the sender to a pipe, which a dedicated thread is
+//! blocked reading. That thread then invokes the user's callback outside of
+//! the signal handler, so that, unlike for a crash, it is free to eg. suspend
+//! every other thread, or ask a separate process for a live dump, both of
+//! which would be unsafe from within a signal handler.
+
+use super::state;
+use crate::{CrashContext, Error};
+use std::{
+    io, mem,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// The signal that requests a dump, or -1 if there is none
+static SIGNAL: AtomicI32 = AtomicI32::new(-1);
+/// The write end of the pipe requests are sent to, or -1 if there is no
+/// dump thread
+static REQUEST_FD: AtomicI32 = AtomicI32::new(-1);
+/// The read end of the pipe, which is owned by the dump thread, but is kept
+/// here so that it can be closed in the child after a `fork`, where the
+/// thread no longer exists
+static SERVE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Starts the dump thread, which is stopped by [`stop`]
+pub(super) fn start(sig: i32) -> Result<(), Error> {
+    let mut fds = [-1; 2];
+    // The write end is non-blocking so that a flood of requests can't block
+    // the signal handler, requests that don't fit are dropped instead
+    // SAFETY: syscalls
+    unsafe {
+        if libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) == -1 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        libc::fcntl(fds[1], libc::F_SETFL, libc::O_NONBLOCK);
+    }
+    let [read, write] = fds;
+
+    if let Err(err) = std::thread::Builder::new()
+        .name("crash-dump-request".to_owned())
+        .spawn(move || serve(sig, read))
+    {
+        // SAFETY: syscalls, the thread was never started so we still own
+        // both ends
+        unsafe {
+            libc::close(read);
+            libc::close(write);
+        }
+        return Err(err.into());
+    }
+
+    SERVE_FD.store(read, Ordering::Release);
+    REQUEST_FD.store(write, Ordering::Release);
+    SIGNAL.store(sig, Ordering::Release);
+    Ok(())
+}
+
+/// Returns true if the dump thread is running
+#[inline]
+pub(super) fn is_running() -> bool {
+    REQUEST_FD.load(Ordering::Acquire) != -1
+}
+
+/// Stops the dump thread, if any, by closing the write end of the pipe
+pub(super) fn stop() {
+    SIGNAL.store(-1, Ordering::Release);
+    SERVE_FD.store(-1, Ordering::Release);
+
+    let fd = REQUEST_FD.swap(-1, Ordering::AcqRel);
+    if fd != -1 {
+        // SAFETY: syscall, the thread closes its own end once it sees the
+        // pipe closed
+        unsafe {
+            libc::close(fd);
+        }
+    }
+}
+
+/// Forgets the dump thread in the child after a `fork`, closing both ends of
+/// the pipe.
+///
+/// This is async signal safe.
+pub(super) fn after_fork() {
+    for fd in [
+        REQUEST_FD.swap(-1, Ordering::AcqRel),
+        SERVE_FD.swap(-1, Ordering::AcqRel),
+    ] {
+        if fd != -1 {
+            // SAFETY: syscall
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+/// Returns true if the signal is a request for a dump, which should be passed
+/// on to [`notify`] rather than handled as a crash.
+///
+/// This is async signal safe.
+#[inline]
+pub(super) fn is_dump_signal(sig: i32) -> bool {
+    sig == SIGNAL.load(Ordering::Acquire)
+}
+
+/// Wakes the dump thread with the pid of the process that sent the signal.
+///
+/// This is async signal safe.
+pub(super) fn notify(info: &libc::siginfo_t) {
+    let fd = REQUEST_FD.load(Ordering::Acquire);
+    if fd == -1 {
+        return;
+    }
+
+    // SAFETY: the pid is valid for signals sent by a process, which is the
+    // only way this signal is expected to be raised
+    let sender = unsafe { info.si_pid() } as u32;
+
+    // The write is smaller than PIPE_BUF so it is atomic, and errno is saved
+    // as the interrupted code may be about to read it
+    // SAFETY: syscalls
+    unsafe {
+        let errno = *libc::__errno_location();
+        libc::write(fd, sender.to_ne_bytes().as_ptr().cast(), 4);
+        *libc::__errno_location() = errno;
+    }
+}
+
+fn serve(sig: i32, fd: i32) {
+    // Requests that arrive while a dump is in progress are coalesced, as the
+    // process is dumped after they arrived either way
+    let mut buf = [0u8; 256];
+
+    loop {
+        // SAFETY: syscall, the buffer is the size we specify
+        let read = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        let sender = match read {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            0 | -1 => break,
+            read => {
+                // The last request is the most recent sender
+                let last = (read as usize / 4).saturating_sub(1) * 4;
+                buf.get(last..last + 4)
+                    .map_or(0, |pid| u32::from_ne_bytes(pid.try_into().unwrap()))
+            }
+        };
+
+        // The context is too large to comfortably put on the stack of this
+        // thread
+        let mut cc = Box::new(CrashContext::capture());
+        // SAFETY: all zeroes is a valid siginfo
+        cc.siginfo = unsafe { mem::zeroed() };
+        cc.siginfo.ssi_signo = sig as u32;
+        cc.siginfo.ssi_code = state::SI_USER;
+        cc.siginfo.ssi_pid = sender;
+        cc.reason = crash_context::CrashReason::DumpRequest;
+        (cc.annotations, cc.annotation_count) = crate::annotations::location();
+        (cc.breadcrumbs, cc.breadcrumb_count) = crate::breadcrumbs::location();
+        (cc.memory_regions, cc.memory_region_count) = crate::memory_regions::location();
+
+        // The process continues running regardless of what was requested
+        let _result = state::simulate_context(&cc);
+    }
+
+    // SAFETY: syscall, we own the read end
+    unsafe {
+        libc::close(fd);
+    }
+}
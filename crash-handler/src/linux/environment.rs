@@ -0,0 +1,73 @@
+//! The command line and environment variables of the process, which can be
+//! captured when the handler is attached, see
+//! [`crate::CrashHandlerBuilder::capture_args`] and
+//! [`crate::CrashHandlerBuilder::capture_env`].
+//!
+//! Environment variables often hold the configuration that explains a crash,
+//! but just as often secrets, so only the variables a filter keeps are
+//! captured, and the filter can replace their values. The filter is invoked
+//! once when the handler is attached, rather than at the time of a crash
+//! where it couldn't allocate, so changes to the environment after attaching
+//! aren't reflected.
+//!
+//! The captured [`crash_context::Environment`] is kept in the memory of the
+//! process, and its location is recorded in the [`crate::CrashContext`].
+//!
+//! ```
+//! let builder = crash_handler::CrashHandler::builder()
+//!     .capture_args(true)
+//!     .capture_env(|key, value| match key {
+//!         "RUST_LOG" | "RUST_BACKTRACE" => Some(value.to_owned()),
+//!         "DATABASE_URL" => Some("<redacted>".to_owned()),
+//!         _ => None,
+//!     });
+//! ```
+
+/// Decides whether an environment variable is captured, and with which
+/// value, see [`crate::CrashHandlerBuilder::capture_env`]
+pub(super) type Filter = Box<dyn FnMut(&str, &str) -> Option<String>>;
+
+/// Creates a filter that captures the variables with the specified names as
+/// they are, and no others
+pub fn allowlist<S: Into<String>>(
+    names: impl IntoIterator<Item = S>,
+) -> impl FnMut(&str, &str) -> Option<String> {
+    let names: Vec<String> = names.into_iter().map(Into::into).collect();
+    move |key, value| {
+        names
+            .iter()
+            .any(|name| name == key)
+            .then(|| value.to_owned())
+    }
+}
+
+/// Captures the arguments, if requested, and the variables the filter keeps,
+/// encoded as a [`crash_context::Environment`]
+pub(super) fn capture(args: bool, filter: Option<Filter>) -> Box<[u8]> {
+    let args: Vec<String> = if args {
+        std::env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let vars: Vec<(String, String)> = filter
+        .map(|mut filter| {
+            std::env::vars_os()
+                .filter_map(|(key, value)| {
+                    let key = key.to_string_lossy();
+                    let value = filter(&key, &value.to_string_lossy())?;
+                    Some((key.into_owned(), value))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    crash_context::Environment::encode(
+        args.iter().map(String::as_str),
+        vars.iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    )
+    .into_boxed_slice()
+}
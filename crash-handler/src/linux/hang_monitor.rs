@@ -0,0 +1,484 @@
+//! Detection of threads that stop making progress, eg. due to a deadlock.
+//!
+//! A hang doesn't raise a signal, so it goes unreported until the process is
+//! killed, eg. by the user or a service manager, and whatever the thread was
+//! stuck on is lost along with it. A [`HangMonitor`] runs a background thread
+//! that expects the monitored thread to [ping](HangMonitor::ping) it
+//! periodically, eg. once per iteration of its event loop. If no ping arrives
+//! within the timeout, the context of the monitored thread is captured and
+//! routed through the attached [`crate::CrashEvent`], with a reason of
+//! [`CrashReason::Hang`](crate::CrashReason::Hang), the same as for a crash.
+//! The event can then eg. write a minidump that includes every thread via
+//! [`crate::threads::suspend_and_capture`], or send the context to a separate
+//! process that dumps it.
+//!
+//! The monitor reports a hang once, and only reports another one after the
+//! thread has pinged it again.
+//!
+//! ```no_run
+//! use crash_handler::hang_monitor::HangMonitor;
+//! use std::time::Duration;
+//!
+//! let monitor = HangMonitor::start(Duration::from_secs(5)).unwrap();
+//!
+//! loop {
+//!     monitor.ping();
+//!     // ...process events...
+//! }
+//! ```
+//!
+//! # Event loops
+//!
+//! For the main thread of a GUI application, where the question is rather
+//! whether the application is unresponsive, similar to an ANR on Android,
+//! the event loop can instead call [`report_iteration`] once per iteration.
+//! Besides pinging a monitor started via [`HangMonitor::start_for_event_loop`],
+//! this records the time between iterations, the distribution of which can be
+//! retrieved via [`iteration_latency`], eg. to be sent as telemetry, or
+//! included in the report of a hang.
+//!
+//! The event loop must iterate regularly for this to be meaningful, eg. on
+//! every frame, or via a timer, as otherwise the time spent idly waiting for
+//! events is indistinguishable from the time spent processing them.
+//!
+//! ```no_run
+//! use crash_handler::hang_monitor::{self, HangMonitor};
+//! use std::time::Duration;
+//!
+//! let _monitor = HangMonitor::start_for_event_loop(Duration::from_secs(5)).unwrap();
+//!
+//! loop {
+//!     hang_monitor::report_iteration();
+//!     // ...process events, render the frame...
+//! #   break;
+//! }
+//!
+//! let latency = hang_monitor::iteration_latency();
+//! println!("p99 frame time: {:?}", latency.percentile(0.99));
+//! ```
+
+use crate::{CrashContext, Error};
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::unix::io::{AsRawFd, FromRawFd},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// A background thread that reports the thread that started it as hung if it
+/// doesn't [ping](Self::ping) it in time, see the [module documentation](self).
+///
+/// The thread is stopped when this is dropped.
+pub struct HangMonitor {
+    pings: Pings,
+    /// An eventfd that is signaled to stop the thread
+    shutdown: File,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HangMonitor {
+    /// Starts a thread that monitors the calling thread, reporting it as hung
+    /// if more than `timeout` elapses between two pings, or after starting
+    /// before the first.
+    ///
+    /// The hang is detected within a quarter of the timeout after it elapses.
+    /// If the context of the hung thread can't be captured, eg. because it
+    /// has blocked [`crate::threads::suspend_signal`], the context is of the
+    /// monitoring thread instead, though the reason is still
+    /// [`CrashReason::Hang`](crate::CrashReason::Hang).
+    ///
+    /// # Errors
+    ///
+    /// The eventfd used to stop the thread, or the thread itself, can't be
+    /// created
+    pub fn start(timeout: Duration) -> Result<Self, Error> {
+        Self::spawn(timeout, Pings::Monitor(Arc::new(AtomicU64::new(0))))
+    }
+
+    /// Starts a thread that monitors the event loop of the calling thread,
+    /// which is expected to call [`report_iteration`] on every iteration,
+    /// rather than [`Self::ping`]. It is otherwise the same as [`Self::start`].
+    ///
+    /// # Errors
+    ///
+    /// The eventfd used to stop the thread, or the thread itself, can't be
+    /// created
+    pub fn start_for_event_loop(timeout: Duration) -> Result<Self, Error> {
+        Self::spawn(timeout, Pings::EventLoop)
+    }
+
+    fn spawn(timeout: Duration, pings: Pings) -> Result<Self, Error> {
+        // SAFETY: syscall
+        let efd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if efd == -1 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        // SAFETY: we just created the fd and nothing else owns it
+        let shutdown = unsafe { File::from_raw_fd(efd) };
+
+        // SAFETY: syscall
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t;
+
+        let monitor = Monitor {
+            tid,
+            timeout,
+            pings: pings.clone(),
+            shutdown: shutdown.as_raw_fd(),
+        };
+        let thread = std::thread::Builder::new()
+            .name("hang-monitor".to_owned())
+            .spawn(move || monitor.run())?;
+
+        Ok(Self {
+            pings,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Signals that the monitored thread is making progress. This is a single
+    /// atomic increment, so it can be called as often as is convenient.
+    ///
+    /// For a monitor of an event loop, this counts as an iteration, but
+    /// without recording its latency.
+    #[inline]
+    pub fn ping(&self) {
+        self.pings.counter().fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Stops the thread, waiting for it to exit. This is equivalent to
+    /// dropping the monitor.
+    #[inline]
+    pub fn stop(self) {}
+}
+
+impl Drop for HangMonitor {
+    fn drop(&mut self) {
+        // The write can only fail if the counter would overflow, which means
+        // the thread has already been told to stop
+        let _res = (&self.shutdown).write_all(&1u64.to_ne_bytes());
+
+        if let Some(thread) = self.thread.take() {
+            let _res = thread.join();
+        }
+    }
+}
+
+/// The number of pings received so far
+#[derive(Clone)]
+enum Pings {
+    Monitor(Arc<AtomicU64>),
+    /// The pings are the iterations reported via [`report_iteration`]
+    EventLoop,
+}
+
+impl Pings {
+    #[inline]
+    fn counter(&self) -> &AtomicU64 {
+        match self {
+            Self::Monitor(pings) => pings,
+            Self::EventLoop => &ITERATIONS,
+        }
+    }
+}
+
+struct Monitor {
+    tid: libc::pid_t,
+    timeout: Duration,
+    pings: Pings,
+    shutdown: i32,
+}
+
+impl Monitor {
+    fn run(self) {
+        let interval = (self.timeout / 4).as_millis().clamp(1, i32::MAX as u128) as i32;
+
+        let mut pings = self.pings.counter().load(Ordering::Relaxed);
+        let mut progressed = Instant::now();
+        let mut reported = false;
+
+        loop {
+            let mut fds = [libc::pollfd {
+                fd: self.shutdown,
+                events: libc::POLLIN,
+                revents: 0,
+            }];
+
+            // SAFETY: syscall, the fd is valid for the duration of the call
+            let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, interval) };
+
+            if res == -1 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+
+                return;
+            }
+
+            if fds[0].revents != 0 {
+                return;
+            }
+
+            let current = self.pings.counter().load(Ordering::Relaxed);
+            if current != pings {
+                pings = current;
+                progressed = Instant::now();
+                reported = false;
+            } else if !reported && progressed.elapsed() >= self.timeout {
+                reported = true;
+                self.report();
+            }
+        }
+    }
+
+    fn report(&self) {
+        // The context is captured while the thread is suspended, but the
+        // threads are resumed before the handler is invoked, so that it can
+        // suspend them itself
+        let captured = super::threads::suspend_and_capture().and_then(|suspended| {
+            suspended
+                .iter()
+                .find(|cc| cc.tid == self.tid)
+                .map(|cc| Box::new(cc.clone()))
+        });
+
+        let mut cc = captured.unwrap_or_else(|| Box::new(CrashContext::capture()));
+        // The siginfo is that of the signal used to capture the context,
+        // which is an implementation detail
+        // SAFETY: all zeroes is a valid siginfo
+        cc.siginfo = unsafe { std::mem::zeroed() };
+        cc.reason = crash_context::CrashReason::Hang;
+
+        if let crate::CrashEventResult::Handled { exit: Some(code) } =
+            super::state::simulate_context(&cc)
+        {
+            crate::exit_process(code);
+        }
+    }
+}
+
+/// The number of iterations reported via [`report_iteration`]
+static ITERATIONS: AtomicU64 = AtomicU64::new(0);
+/// The monotonic time of the last iteration, in nanoseconds, or 0 if there
+/// hasn't been one yet
+static LAST_ITERATION: AtomicU64 = AtomicU64::new(0);
+static LATENCY: Histogram = Histogram::new();
+
+/// Reports that the event loop completed an iteration, recording the time
+/// since the previous one, and pinging a monitor started via
+/// [`HangMonitor::start_for_event_loop`].
+///
+/// This is meant to be called from a single thread, usually the main thread,
+/// as the latency is the time since the previous call from any thread. It
+/// doesn't lock or allocate.
+pub fn report_iteration() {
+    let now = monotonic_nanos();
+    let last = LAST_ITERATION.swap(now, Ordering::Relaxed);
+    if last != 0 {
+        LATENCY.record(now.saturating_sub(last) / 1000);
+    }
+
+    ITERATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Retrieves the distribution of the latencies of the iterations reported
+/// via [`report_iteration`] so far
+pub fn iteration_latency() -> Latency {
+    let mut latency = Latency {
+        buckets: [0; BUCKETS],
+        max: LATENCY.max.load(Ordering::Relaxed),
+    };
+    for (count, bucket) in latency.buckets.iter_mut().zip(&LATENCY.buckets) {
+        *count = bucket.load(Ordering::Relaxed);
+    }
+    latency
+}
+
+/// Forgets the latencies recorded so far, eg. after they have been sent as
+/// telemetry, so that the next [`iteration_latency`] only covers the
+/// iterations since. The next iteration is not recorded, as its latency
+/// would include the time before the reset.
+pub fn reset_iteration_latency() {
+    LAST_ITERATION.store(0, Ordering::Relaxed);
+    for bucket in &LATENCY.buckets {
+        bucket.store(0, Ordering::Relaxed);
+    }
+    LATENCY.max.store(0, Ordering::Relaxed);
+}
+
+#[inline]
+fn monotonic_nanos() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: syscall
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The number of sub-buckets each power of two is divided into is
+/// `1 << SUB_BITS`, so a bucket spans at most 12.5% of its lower bound
+const SUB_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BITS;
+/// Enough buckets for latencies of up to 2^36us, about 19 hours, anything
+/// longer is recorded in the last bucket
+const BUCKETS: usize = ((36 - SUB_BITS as usize) + 1) * SUB_BUCKETS as usize;
+
+/// A histogram of latencies in microseconds, with logarithmically sized
+/// buckets, so that it can be updated without locking
+struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+    max: AtomicU64,
+}
+
+impl Histogram {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+            max: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn record(&self, micros: u64) {
+        self.buckets[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(micros, Ordering::Relaxed);
+    }
+}
+
+/// The bucket the latency is counted in
+#[inline]
+fn bucket(micros: u64) -> usize {
+    if micros < SUB_BUCKETS * 2 {
+        return micros as usize;
+    }
+
+    let shift = 63 - micros.leading_zeros() - SUB_BITS;
+    let index = (shift as u64 + 1) * SUB_BUCKETS + (micros >> shift) - SUB_BUCKETS;
+    (index as usize).min(BUCKETS - 1)
+}
+
+/// The largest latency that is counted in the bucket
+#[inline]
+fn bucket_upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS * 2 {
+        return index;
+    }
+
+    let shift = index / SUB_BUCKETS - 1;
+    let mantissa = index % SUB_BUCKETS + SUB_BUCKETS;
+    ((mantissa + 1) << shift) - 1
+}
+
+/// The distribution of the latencies of event loop iterations, see
+/// [`iteration_latency`]
+#[derive(Clone)]
+pub struct Latency {
+    buckets: [u64; BUCKETS],
+    max: u64,
+}
+
+impl Latency {
+    /// The number of iterations whose latency was recorded
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// The longest latency recorded
+    pub fn max(&self) -> Duration {
+        Duration::from_micros(self.max)
+    }
+
+    /// The latency that the specified fraction of iterations, between 0 and
+    /// 1, didn't exceed, eg. 0.99 for the 99th percentile, or `None` if no
+    /// latency was recorded.
+    ///
+    /// The latency is the upper bound of the bucket it was counted in, so it
+    /// overestimates the actual latency by at most 12.5%, but never exceeds
+    /// [`Self::max`].
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let target = ((fraction.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let index = self.buckets.iter().position(|bucket| {
+            seen += bucket;
+            seen >= target
+        })?;
+
+        Some(Duration::from_micros(
+            bucket_upper_bound(index).min(self.max),
+        ))
+    }
+}
+
+impl std::fmt::Debug for Latency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Latency")
+            .field("count", &self.count())
+            .field("p50", &self.percentile(0.5))
+            .field("p90", &self.percentile(0.9))
+            .field("p99", &self.percentile(0.99))
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        // Every latency is within the bounds of its bucket, and the buckets
+        // are contiguous
+        let mut previous = 0;
+        for micros in (0..100_000).chain([1 << 35, (1 << 36) - 1]) {
+            let index = bucket(micros);
+            assert!(index >= previous, "{micros}");
+            assert!(micros <= bucket_upper_bound(index), "{micros}");
+            if index > 0 {
+                assert!(micros > bucket_upper_bound(index - 1), "{micros}");
+            }
+            previous = index;
+        }
+
+        assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+        assert_eq!(bucket(1 << 36), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut latency = Latency {
+            buckets: [0; BUCKETS],
+            max: 0,
+        };
+        assert_eq!(latency.percentile(0.5), None);
+
+        for micros in 1..=1000 {
+            latency.buckets[bucket(micros * 1000)] += 1;
+        }
+        latency.max = 1_000_000;
+
+        assert_eq!(latency.count(), 1000);
+        for (fraction, expected) in [(0.5, 500_000), (0.9, 900_000), (0.99, 990_000)] {
+            let actual = latency.percentile(fraction).unwrap().as_micros() as u64;
+            assert!(
+                actual >= expected && actual <= expected + expected / 8,
+                "{fraction}: {actual}"
+            );
+        }
+        assert_eq!(latency.percentile(1.0), Some(latency.max()));
+    }
+}
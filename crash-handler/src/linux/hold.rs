@@ -0,0 +1,201 @@
+//! Keeps the crashing thread parked after the callback has returned, until
+//! the consumer decides what happens to the process, see [`CrashGuard`].
+//!
+//! Holding the crash creates a pipe whose read end the crashing thread blocks
+//! on once the callback has returned, as `read` is async signal safe. The
+//! disposition is written to the pipe by [`CrashGuard::release`], and closing
+//! the pipe without writing one keeps the result of the callback.
+
+use crate::CrashEventResult;
+use std::{
+    io,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// No crash is being handled, so it can't be held
+const IDLE: i32 = -1;
+/// A crash is being handled, but hasn't been held, any other value is the
+/// read end of the pipe the crashing thread waits on
+const HANDLING: i32 = -2;
+
+static HELD_FD: AtomicI32 = AtomicI32::new(IDLE);
+
+/// The size of an encoded [`CrashEventResult`], which is well below
+/// `PIPE_BUF`, so that it is written atomically
+const MESSAGE_SIZE: usize = 24;
+
+fn encode(result: &CrashEventResult) -> [u8; MESSAGE_SIZE] {
+    let (tag, exit, value, ptr): (u32, Option<i32>, i32, usize) = match result {
+        CrashEventResult::Handled { exit } => (0, *exit, 0, 0),
+        CrashEventResult::Continue => (1, None, 0, 0),
+        CrashEventResult::Reraise => (2, None, 0, 0),
+        CrashEventResult::Jump { jmp_buf, value } => (3, None, *value, *jmp_buf as usize),
+    };
+
+    let mut bytes = [0u8; MESSAGE_SIZE];
+    bytes[0..4].copy_from_slice(&tag.to_ne_bytes());
+    bytes[4..8].copy_from_slice(&u32::from(exit.is_some()).to_ne_bytes());
+    bytes[8..12].copy_from_slice(&exit.unwrap_or_default().to_ne_bytes());
+    bytes[12..16].copy_from_slice(&value.to_ne_bytes());
+    bytes[16..24].copy_from_slice(&(ptr as u64).to_ne_bytes());
+    bytes
+}
+
+fn decode(bytes: &[u8; MESSAGE_SIZE]) -> Option<CrashEventResult> {
+    let field = |offset: usize| -> [u8; 4] { bytes[offset..offset + 4].try_into().unwrap() };
+
+    Some(match u32::from_ne_bytes(field(0)) {
+        0 => CrashEventResult::Handled {
+            exit: (u32::from_ne_bytes(field(4)) != 0).then(|| i32::from_ne_bytes(field(8))),
+        },
+        1 => CrashEventResult::Continue,
+        2 => CrashEventResult::Reraise,
+        3 => CrashEventResult::Jump {
+            jmp_buf: u64::from_ne_bytes(bytes[16..24].try_into().unwrap()) as usize as *mut _,
+            value: i32::from_ne_bytes(field(12)),
+        },
+        _ => return None,
+    })
+}
+
+/// Keeps the thread that crashed parked at the point of the crash, even after
+/// the callback has returned, until it is [released](Self::release).
+///
+/// This is useful when the crash is processed by something other than the
+/// callback itself, eg. a [dedicated thread](crate::CrashHandlerBuilder::callback_thread)
+/// or an external process that writes a minidump, as the crashing thread
+/// must not run, or terminate the process, until that has finished. The
+/// callback holds the crash and hands the guard to whatever is processing it,
+/// then returns immediately.
+///
+/// Note that the [callback timeout](crate::CrashHandlerBuilder::callback_timeout),
+/// if any, still applies while the crash is held.
+pub struct CrashGuard {
+    fd: i32,
+}
+
+impl CrashGuard {
+    /// Holds the crash that is currently being handled.
+    ///
+    /// Returns `None` if no crash is being handled, or the crash is already
+    /// held. Note that this doesn't check which thread it is called from, so
+    /// any thread can hold the crash while the callback is running, not just
+    /// the one running the callback.
+    pub fn hold() -> Option<Self> {
+        let mut fds = [-1; 2];
+        // SAFETY: syscall
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            return None;
+        }
+        let [read_fd, write_fd] = fds;
+
+        if HELD_FD
+            .compare_exchange(HANDLING, read_fd, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            // SAFETY: syscalls
+            unsafe {
+                libc::close(read_fd);
+                libc::close(write_fd);
+            }
+            return None;
+        }
+
+        Some(Self { fd: write_fd })
+    }
+
+    /// Releases the crashing thread, which then proceeds as if the callback
+    /// had returned the specified disposition rather than its actual result.
+    ///
+    /// Dropping the guard without releasing it releases the crashing thread
+    /// with the result the callback returned.
+    pub fn release(self, disposition: CrashEventResult) {
+        let bytes = encode(&disposition);
+        // SAFETY: syscall
+        unsafe {
+            libc::write(self.fd, bytes.as_ptr().cast(), bytes.len());
+        }
+    }
+}
+
+impl Drop for CrashGuard {
+    fn drop(&mut self) {
+        // SAFETY: syscall
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Allows the crash being handled to be held until dropped, at which point
+/// the crashing thread waits for it to be released, see [`Self::wait`]
+pub(super) struct Holdable(());
+
+impl Holdable {
+    /// Returns `None` if another crash is already being handled, in which
+    /// case this crash can't be held
+    pub(super) fn enter() -> Option<Self> {
+        HELD_FD
+            .compare_exchange(IDLE, HANDLING, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+            .then_some(Self(()))
+    }
+
+    /// Waits for the crash to be released if it was held once the callback
+    /// returned the specified result, returning the disposition it was
+    /// released with.
+    ///
+    /// This is async signal safe.
+    pub(super) fn wait(self, result: CrashEventResult) -> CrashEventResult {
+        let fd = HELD_FD.load(Ordering::Acquire);
+        if fd < 0 {
+            return result;
+        }
+
+        let mut bytes = [0u8; MESSAGE_SIZE];
+        let disposition = loop {
+            // SAFETY: syscall
+            let read = unsafe { libc::read(fd, bytes.as_mut_ptr().cast(), bytes.len()) };
+            match read {
+                // Retried
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                read if read as usize == MESSAGE_SIZE => break decode(&bytes),
+                // The guard was dropped without being released
+                _ => break None,
+            }
+        };
+
+        // Closes the read end of the pipe
+        drop(self);
+
+        disposition.unwrap_or(result)
+    }
+}
+
+impl Drop for Holdable {
+    fn drop(&mut self) {
+        // A crash that is still held at this point, eg. because the callback
+        // jumped out of the handler, can't be waited for anymore
+        let fd = HELD_FD.swap(IDLE, Ordering::AcqRel);
+        if fd >= 0 {
+            // SAFETY: syscall
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+/// Forgets a crash that was being handled in the parent, as the thread that
+/// handled it doesn't exist in the child after a `fork`.
+///
+/// This is async signal safe.
+pub(super) fn after_fork() {
+    let fd = HELD_FD.swap(IDLE, Ordering::AcqRel);
+    if fd >= 0 {
+        // SAFETY: syscall
+        unsafe {
+            libc::close(fd);
+        }
+    }
+}
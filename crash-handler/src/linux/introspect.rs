@@ -0,0 +1,156 @@
+//! Introspection of the signal handlers and alternate signal stack that are
+//! currently installed, to detect conflicts with other code that handles
+//! signals.
+//!
+//! Only one handler can be installed for each signal, so a runtime or library
+//! that installs its own after the [`crate::CrashHandler`] was attached, eg.
+//! the Go runtime or a sanitizer, silently replaces ours, and the crashes it
+//! doesn't chain to us are no longer reported. [`installed_signals`] reports
+//! whether each of our handlers is still installed, as well as the handler it
+//! replaced, so that such conflicts can be logged, eg. after initializing the
+//! library.
+//!
+//! ```
+//! use crash_handler::{introspect, modules};
+//!
+//! let modules = modules::enumerate();
+//! for installed in introspect::installed_signals() {
+//!     if let introspect::Disposition::Handler { address, .. } = installed.current {
+//!         if installed.replaced {
+//!             let module = modules.find(address).map_or("<unknown>", |module| module.path());
+//!             eprintln!("the handler for signal {} was replaced by one in {module}", installed.signal);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{mem, ptr};
+
+/// What happens when a signal is delivered, ie. its `sa_handler`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Disposition {
+    /// `SIG_DFL`, the default action for the signal, eg. terminating the
+    /// process
+    Default,
+    /// `SIG_IGN`, the signal is discarded
+    Ignore,
+    /// A function is invoked
+    Handler {
+        /// The address of the function, which can be attributed to a module
+        /// via [`crate::modules`]
+        address: usize,
+        /// Whether the handler was installed with `SA_SIGINFO`
+        siginfo: bool,
+        /// Whether the handler was installed with `SA_ONSTACK`, without which
+        /// it can't handle a stack overflow
+        on_stack: bool,
+    },
+}
+
+impl Disposition {
+    fn from_action(action: &libc::sigaction) -> Self {
+        match action.sa_sigaction {
+            libc::SIG_DFL => Self::Default,
+            libc::SIG_IGN => Self::Ignore,
+            address => Self::Handler {
+                address,
+                siginfo: action.sa_flags & libc::SA_SIGINFO != 0,
+                on_stack: action.sa_flags & libc::SA_ONSTACK != 0,
+            },
+        }
+    }
+}
+
+/// A signal our handler was installed for
+#[derive(Copy, Clone, Debug)]
+pub struct InstalledSignal {
+    /// The signal number
+    pub signal: i32,
+    /// The disposition the signal had before our handler was installed, which
+    /// is restored when the last handler is detached
+    pub previous: Disposition,
+    /// The disposition the signal has now
+    pub current: Disposition,
+    /// True if our handler is no longer installed, ie. it was replaced after
+    /// being attached, or was disarmed in the child after a `fork`, see
+    /// [`crate::ForkBehavior::Disarm`]
+    pub replaced: bool,
+}
+
+/// Returns every signal our handler was installed for, or nothing if no
+/// [`crate::CrashHandler`] is attached
+pub fn installed_signals() -> Vec<InstalledSignal> {
+    super::state::previous_handlers()
+        .into_iter()
+        .map(|(signal, previous)| {
+            let current = query(signal);
+            InstalledSignal {
+                signal,
+                previous: Disposition::from_action(&previous),
+                current: current
+                    .as_ref()
+                    .map_or(Disposition::Default, Disposition::from_action),
+                replaced: !current.is_some_and(|current| super::state::is_our_handler(&current)),
+            }
+        })
+        .collect()
+}
+
+/// Returns the current disposition of the signal, or `None` if it isn't a
+/// valid signal number
+pub fn disposition(signal: i32) -> Option<Disposition> {
+    query(signal).as_ref().map(Disposition::from_action)
+}
+
+fn query(signal: i32) -> Option<libc::sigaction> {
+    // SAFETY: syscall
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        (libc::sigaction(signal, ptr::null(), &mut action) == 0).then_some(action)
+    }
+}
+
+/// The alternate signal stack of a thread, see [`alt_stack`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AltStack {
+    /// There is no alternate stack, so a stack overflow on the thread can't
+    /// be handled
+    None,
+    /// The stack was installed by this crate, either when the handler was
+    /// attached or when the thread was created
+    Ours {
+        /// The size of the stack
+        size: usize,
+    },
+    /// The stack was installed by something else, eg. Rust's `std`, which is
+    /// used as is if it is at least as large as
+    /// [`crate::CrashHandlerBuilder::alt_stack_size`]
+    Foreign {
+        /// The size of the stack
+        size: usize,
+    },
+}
+
+/// Returns the alternate signal stack of the calling thread
+pub fn alt_stack() -> AltStack {
+    // SAFETY: syscall
+    let stack = unsafe {
+        let mut stack: libc::stack_t = mem::zeroed();
+        if libc::sigaltstack(ptr::null(), &mut stack) != 0 {
+            return AltStack::None;
+        }
+        stack
+    };
+
+    if stack.ss_flags & libc::SS_DISABLE != 0 {
+        AltStack::None
+    } else if crate::unix::is_our_alt_stack(&stack) {
+        AltStack::Ours {
+            size: stack.ss_size,
+        }
+    } else {
+        AltStack::Foreign {
+            size: stack.ss_size,
+        }
+    }
+}
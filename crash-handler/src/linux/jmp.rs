@@ -33,6 +33,10 @@ cfg_if::cfg_if! {
         #[repr(C)]
         #[doc(hidden)]
         pub struct __jmp_buf([u64; 22]);
+    } else if #[cfg(target_arch = "riscv64")] {
+        #[repr(C)]
+        #[doc(hidden)]
+        pub struct __jmp_buf([u64; 26]);
     }
 }
 
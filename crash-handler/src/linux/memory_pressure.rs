@@ -0,0 +1,261 @@
+//! Forewarning of the process being killed for running out of memory.
+//!
+//! The OOM killer terminates processes with `SIGKILL`, which can't be caught,
+//! so state that is normally only flushed when a crash is handled, eg.
+//! breadcrumbs and [`crate::annotations`], is lost along with the process. A
+//! [`MemoryWatcher`] runs a background thread that detects memory pressure
+//! before that happens, and invokes a callback with a [`CrashContext`] whose
+//! reason is [`CrashReason::MemoryPressure`](crate::CrashReason::MemoryPressure)
+//! so that the state can be flushed, or a dump requested, while the process is
+//! still alive.
+
+use crate::{CrashContext, Error};
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::unix::io::{AsRawFd, FromRawFd},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Determines when a [`MemoryWatcher`] considers the process to be under
+/// memory pressure
+#[derive(Copy, Clone, Debug)]
+pub enum Trigger {
+    /// Uses the kernel's [pressure stall information](https://docs.kernel.org/accounting/psi.html),
+    /// available since Linux 5.2, firing when tasks have been stalled waiting
+    /// on memory for a total of at least `stall` within any `window`.
+    ///
+    /// The pressure of the cgroup the process belongs to is used if cgroup v2
+    /// is available, as that is where a container's memory limit is enforced,
+    /// otherwise the pressure of the whole system is used.
+    ///
+    /// The `window` must be between 500ms and 10s, and must be a multiple of
+    /// 2s if the process doesn't have `CAP_SYS_RESOURCE`.
+    Stall {
+        /// The total stall time within the window that fires the trigger
+        stall: Duration,
+        /// The window the stall time is tracked over
+        window: Duration,
+    },
+    /// Polls the resident set size of the process every `interval`, firing
+    /// when it exceeds `limit` bytes. This works on every kernel, but can
+    /// miss allocation spikes that occur between polls.
+    ///
+    /// The trigger fires again only once the resident set size has dropped
+    /// back below the limit and then exceeded it again.
+    Rss {
+        /// The resident set size, in bytes, that fires the trigger
+        limit: u64,
+        /// How often the resident set size is checked
+        interval: Duration,
+    },
+}
+
+/// A background thread that invokes a callback when the process is under
+/// memory pressure, see the [module documentation](self).
+///
+/// The thread is stopped when this is dropped.
+pub struct MemoryWatcher {
+    /// An eventfd that is signaled to stop the thread
+    shutdown: File,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MemoryWatcher {
+    /// Starts a thread that invokes `on_pressure` every time the [`Trigger`]
+    /// fires.
+    ///
+    /// Unlike a [`crate::CrashEvent`], the callback is not run in a
+    /// compromised context, though as memory is by definition scarce when it
+    /// is invoked, it should avoid allocating more than necessary.
+    ///
+    /// # Errors
+    ///
+    /// The eventfd used to stop the thread can't be created, or the pressure
+    /// stall trigger can't be registered, eg. because the kernel doesn't
+    /// support it or the window is invalid
+    pub fn start<F>(trigger: Trigger, on_pressure: F) -> Result<Self, Error>
+    where
+        F: Fn(&CrashContext) + Send + 'static,
+    {
+        // SAFETY: syscall
+        let efd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if efd == -1 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        // SAFETY: we just created the fd and nothing else owns it
+        let shutdown = unsafe { File::from_raw_fd(efd) };
+
+        // Register the trigger before spawning the thread so that the caller
+        // is told if it is not supported
+        let source = match trigger {
+            Trigger::Stall { stall, window } => Source::Stall(register_stall(stall, window)?),
+            Trigger::Rss { limit, interval } => Source::Rss {
+                limit,
+                interval,
+                exceeded: false,
+            },
+        };
+
+        let shutdown_fd = shutdown.as_raw_fd();
+        let thread = std::thread::Builder::new()
+            .name("memory-watcher".to_owned())
+            .spawn(move || watch(source, shutdown_fd, on_pressure))?;
+
+        Ok(Self {
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stops the thread, waiting for it to exit. This is equivalent to
+    /// dropping the watcher.
+    #[inline]
+    pub fn stop(self) {}
+}
+
+impl Drop for MemoryWatcher {
+    fn drop(&mut self) {
+        // The write can only fail if the counter would overflow, which means
+        // the thread has already been told to stop
+        let _res = (&self.shutdown).write_all(&1u64.to_ne_bytes());
+
+        if let Some(thread) = self.thread.take() {
+            let _res = thread.join();
+        }
+    }
+}
+
+enum Source {
+    /// A pressure stall trigger, which is signaled with `POLLPRI`
+    Stall(File),
+    Rss {
+        limit: u64,
+        interval: Duration,
+        exceeded: bool,
+    },
+}
+
+/// Opens the memory pressure file for the cgroup of the process, or the
+/// system, and writes a trigger to it. The trigger is kept alive for as long
+/// as the file is open.
+fn register_stall(stall: Duration, window: Duration) -> Result<File, Error> {
+    let path =
+        cgroup_pressure_path().unwrap_or_else(|| std::path::PathBuf::from("/proc/pressure/memory"));
+
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+
+    // The kernel expects the trigger to be written with a single write, and
+    // rejects it with EINVAL if the window is out of range
+    let trigger = format!("some {} {}\0", stall.as_micros(), window.as_micros());
+    file.write_all(trigger.as_bytes())?;
+
+    Ok(file)
+}
+
+/// Finds the `memory.pressure` file of the cgroup v2 the process belongs to
+fn cgroup_pressure_path() -> Option<std::path::PathBuf> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+
+    // The cgroup v2 hierarchy is always listed with an id of 0 and no
+    // controllers
+    let cgroup = cgroups.lines().find_map(|line| line.strip_prefix("0::"))?;
+
+    let path = std::path::Path::new("/sys/fs/cgroup")
+        .join(cgroup.trim_start_matches('/'))
+        .join("memory.pressure");
+
+    path.exists().then_some(path)
+}
+
+/// Retrieves the resident set size of the process, in bytes
+fn resident_set_size() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+    // SAFETY: syscall
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    Some(pages * page_size as u64)
+}
+
+fn watch<F>(mut source: Source, shutdown: i32, on_pressure: F)
+where
+    F: Fn(&CrashContext),
+{
+    loop {
+        let (trigger_fd, timeout) = match &source {
+            Source::Stall(file) => (file.as_raw_fd(), -1),
+            // A negative fd is ignored by poll
+            Source::Rss { interval, .. } => (-1, interval.as_millis().min(i32::MAX as u128) as i32),
+        };
+
+        let mut fds = [
+            libc::pollfd {
+                fd: shutdown,
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: trigger_fd,
+                events: libc::POLLPRI,
+                revents: 0,
+            },
+        ];
+
+        // SAFETY: syscall, the fds are valid for the duration of the call
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout) };
+
+        if res == -1 {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+
+            return;
+        }
+
+        if fds[0].revents != 0 {
+            return;
+        }
+
+        let fire = match &mut source {
+            Source::Stall(_) => {
+                // POLLERR is signaled if the cgroup is removed, in which case
+                // the trigger can never fire again
+                if fds[1].revents & libc::POLLERR != 0 {
+                    return;
+                }
+
+                fds[1].revents & libc::POLLPRI != 0
+            }
+            Source::Rss {
+                limit, exceeded, ..
+            } => {
+                let Some(rss) = resident_set_size() else {
+                    continue;
+                };
+
+                let was_exceeded = *exceeded;
+                *exceeded = rss > *limit;
+                *exceeded && !was_exceeded
+            }
+        };
+
+        if fire {
+            // The context is too large to comfortably put on the stack of a
+            // thread that may be running low on memory
+            let mut cc = Box::new(CrashContext::capture());
+            cc.reason = crash_context::CrashReason::MemoryPressure;
+            (cc.annotations, cc.annotation_count) = crate::annotations::location();
+            (cc.breadcrumbs, cc.breadcrumb_count) = crate::breadcrumbs::location();
+            (cc.memory_regions, cc.memory_region_count) = crate::memory_regions::location();
+
+            on_pressure(&cc);
+        }
+    }
+}
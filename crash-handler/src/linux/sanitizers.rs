@@ -0,0 +1,69 @@
+//! Detection of sanitizers and Valgrind, which report crashes themselves.
+//!
+//! `AddressSanitizer` and the other sanitizers install their signal handlers
+//! before `main`, so once ours replaces them, a crash is only reported by the
+//! callback, and the sanitizer's report, which is usually far more useful
+//! while testing, is lost unless ours chains to it.
+//!
+//! Valgrind doesn't install a handler in the process, so there is nothing to
+//! chain to. It instead reports a crash when the process is killed by the
+//! signal, which it isn't if the callback exits the process with an exit
+//! code, so [`crate::SanitizerPolicy::StepAside`] is the only policy that
+//! ensures Valgrind reports every crash.
+//!
+//! The tool is detected when the handler is attached, and what happens then
+//! is determined by [`crate::CrashHandlerBuilder::sanitizer_policy`].
+
+use std::fmt;
+
+/// A tool that reports crashes itself
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tool {
+    /// `-fsanitize=address`
+    AddressSanitizer,
+    /// `-fsanitize=thread`
+    ThreadSanitizer,
+    /// `-fsanitize=memory`
+    MemorySanitizer,
+    /// The process is running under Valgrind
+    Valgrind,
+}
+
+impl fmt::Display for Tool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::AddressSanitizer => "AddressSanitizer",
+            Self::ThreadSanitizer => "ThreadSanitizer",
+            Self::MemorySanitizer => "MemorySanitizer",
+            Self::Valgrind => "Valgrind",
+        })
+    }
+}
+
+/// Returns the tool the process is running with, if any.
+///
+/// The sanitizers are detected by looking up their runtime's initialization
+/// function, which is exported from the executable, or from the shared
+/// runtime if the sanitizer was linked dynamically. Valgrind is detected by
+/// the preload library it injects into every process via `LD_PRELOAD`.
+pub fn detect() -> Option<Tool> {
+    for (symbol, tool) in [
+        (c"__asan_init", Tool::AddressSanitizer),
+        (c"__tsan_init", Tool::ThreadSanitizer),
+        (c"__msan_init", Tool::MemorySanitizer),
+    ] {
+        // SAFETY: dlsym with a valid, nul terminated name
+        if !unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) }.is_null() {
+            return Some(tool);
+        }
+    }
+
+    std::env::var_os("LD_PRELOAD")
+        .is_some_and(|preload| {
+            preload
+                .as_encoded_bytes()
+                .windows(b"vgpreload".len())
+                .any(|window| window == b"vgpreload")
+        })
+        .then_some(Tool::Valgrind)
+}
@@ -0,0 +1,100 @@
+//! Control over which threads receive crash signals sent to the process.
+//!
+//! A signal sent to the process as a whole, eg. via `kill`, is delivered to
+//! an arbitrary thread that doesn't have it blocked, which may be a thread
+//! that can't afford to be interrupted, eg. a realtime audio thread. Blocking
+//! the crash signals on such threads ensures that the signal is handled on
+//! another thread instead, as is done for the callback thread, see
+//! [`crate::CrashHandlerBuilder::callback_thread`], which has them unblocked.
+//!
+//! This has no effect on the signals a thread causes itself, eg. a `SIGSEGV`
+//! due to an invalid memory access, which are always delivered to that
+//! thread. If such a signal is blocked, the kernel terminates the process
+//! without invoking any handler, so a crash on a thread that has the crash
+//! signals blocked is not reported. The exception is `abort`, which unblocks
+//! `SIGABRT` before raising it.
+//!
+//! ```
+//! use crash_handler::signals;
+//!
+//! std::thread::spawn(|| {
+//!     signals::block_crash_signals_on_this_thread();
+//!     // ...process audio...
+//! });
+//!
+//! {
+//!     let _blocked = signals::BlockedCrashSignals::enter();
+//!     // ...a section that must not be interrupted...
+//! }
+//! ```
+
+use std::{marker::PhantomData, mem};
+
+/// The signals the crash handler handles by default, see
+/// [`crate::CrashHandlerBuilder::signals`]
+fn crash_signals() -> libc::sigset_t {
+    // SAFETY: syscalls
+    unsafe {
+        let mut set: libc::sigset_t = mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for sig in super::state::EXCEPTION_SIGNALS {
+            libc::sigaddset(&mut set, sig as i32);
+        }
+        set
+    }
+}
+
+/// Blocks the crash signals on the calling thread, for the rest of its
+/// lifetime unless they are unblocked again. Threads it spawns afterwards
+/// inherit the blocked signals.
+pub fn block_crash_signals_on_this_thread() {
+    // SAFETY: syscall
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_BLOCK, &crash_signals(), std::ptr::null_mut());
+    }
+}
+
+/// Unblocks the crash signals on the calling thread, eg. on a thread spawned
+/// by a thread that has them blocked
+pub fn unblock_crash_signals_on_this_thread() {
+    // SAFETY: syscall
+    unsafe {
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &crash_signals(), std::ptr::null_mut());
+    }
+}
+
+/// Blocks the crash signals on the current thread until it is dropped, at
+/// which point the previous signal mask is restored, and any signal sent to
+/// the thread specifically while they were blocked is delivered
+#[must_use = "the signals are unblocked when this is dropped"]
+pub struct BlockedCrashSignals {
+    previous: libc::sigset_t,
+    /// The mask is specific to the thread, so must be restored on it
+    _thread: PhantomData<*const ()>,
+}
+
+impl BlockedCrashSignals {
+    /// Blocks the crash signals, remembering the current signal mask
+    #[inline]
+    pub fn enter() -> Self {
+        // SAFETY: syscall
+        unsafe {
+            let mut previous: libc::sigset_t = mem::zeroed();
+            libc::pthread_sigmask(libc::SIG_BLOCK, &crash_signals(), &mut previous);
+            Self {
+                previous,
+                _thread: PhantomData,
+            }
+        }
+    }
+}
+
+impl Drop for BlockedCrashSignals {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: syscall
+        unsafe {
+            libc::pthread_sigmask(libc::SIG_SETMASK, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
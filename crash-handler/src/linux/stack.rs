@@ -0,0 +1,254 @@
+//! Async signal safe detection of stack overflows.
+//!
+//! When a thread exhausts its stack it will attempt to access the guard
+//! page(s) immediately below it, which results in a `SIGSEGV` that is otherwise
+//! indistinguishable from any other invalid memory access. We determine if this
+//! is the case by comparing the fault address against the bounds of the
+//! crashing thread's stack as found in `/proc/self/maps`, which we read with
+//! raw syscalls and a fixed size stack buffer since we are inside a signal
+//! handler.
+//!
+//! Note that `/proc` is not always accessible, eg. in sandboxed Android app
+//! processes, in which case we fall back to a heuristic based on the stack
+//! pointer alone.
+
+use std::ops::Range;
+
+/// The maximum distance below the start of a thread's stack that a fault can
+/// occur and still be considered a stack overflow. This matches the kernel's
+/// default `stack_guard_gap` of 256 pages, which is also larger than the
+/// guard page(s) that glibc and musl place below thread stacks.
+const MAX_GUARD_GAP: usize = 256 * 4096;
+
+struct Mapping {
+    range: Range<usize>,
+    writable: bool,
+}
+
+impl Mapping {
+    /// Parses the address range and permissions from the beginning of a line
+    /// in `/proc/<pid>/maps`, eg. `7ffd2c9a1000-7ffd2c9c2000 rw-p ...`
+    fn parse(line: &[u8]) -> Option<Self> {
+        let mut fields = line.split(|b| *b == b' ');
+
+        let mut range = fields.next()?.split(|b| *b == b'-');
+        let start = parse_hex(range.next()?)?;
+        let end = parse_hex(range.next()?)?;
+
+        let perms = fields.next()?;
+
+        Some(Self {
+            range: start..end,
+            writable: perms.get(1) == Some(&b'w'),
+        })
+    }
+}
+
+#[inline]
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() {
+        return None;
+    }
+
+    s.iter().try_fold(0usize, |acc, c| {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => return None,
+        };
+
+        acc.checked_mul(16)?.checked_add(digit as usize)
+    })
+}
+
+/// Invokes the callback for each mapping in `/proc/self/maps`, in ascending
+/// address order, until the callback returns `false`.
+///
+/// Returns `false` if the maps could not be read.
+///
+/// # Safety
+///
+/// Performs syscalls
+unsafe fn for_each_mapping(mut cb: impl FnMut(&Mapping) -> bool) -> bool {
+    let fd = libc::open(
+        c"/proc/self/maps".as_ptr(),
+        libc::O_RDONLY | libc::O_CLOEXEC,
+    );
+    if fd == -1 {
+        return false;
+    }
+
+    // Lines can be longer than this buffer due to the pathname, but we only
+    // care about the first couple of fields which are of bounded length, so
+    // we just skip the remainder of any line that doesn't fit
+    let mut buf = [0u8; 512];
+    let mut filled = 0;
+    let mut skip_line = false;
+
+    'read: loop {
+        let read = libc::read(fd, buf[filled..].as_mut_ptr().cast(), buf.len() - filled);
+        if read <= 0 {
+            break;
+        }
+
+        filled += read as usize;
+
+        let mut start = 0;
+        while let Some(nl) = buf[start..filled].iter().position(|b| *b == b'\n') {
+            let line = &buf[start..start + nl];
+            start += nl + 1;
+
+            if skip_line {
+                skip_line = false;
+                continue;
+            }
+
+            if let Some(mapping) = Mapping::parse(line) {
+                if !cb(&mapping) {
+                    break 'read;
+                }
+            }
+        }
+
+        if start == 0 && filled == buf.len() {
+            // The line didn't fit, parse what we have and skip the rest
+            if !skip_line {
+                if let Some(mapping) = Mapping::parse(&buf) {
+                    if !cb(&mapping) {
+                        break;
+                    }
+                }
+            }
+
+            skip_line = true;
+            filled = 0;
+        } else {
+            buf.copy_within(start..filled, 0);
+            filled -= start;
+        }
+    }
+
+    libc::close(fd);
+    true
+}
+
+/// Determines if a fault at the specified address, on a thread whose stack
+/// pointer was at `sp` at the time of the fault, was due to a stack overflow
+///
+/// # Safety
+///
+/// Performs syscalls
+pub(super) unsafe fn is_stack_overflow(fault_addr: usize, sp: usize) -> bool {
+    // The thread's stack is the lowest writable mapping that ends above the
+    // stack pointer. Note that the stack pointer itself may already be inside
+    // the guard page if the thread has overflowed its stack, which is why we
+    // don't require that the mapping actually contains it
+    let mut stack = None;
+    if !for_each_mapping(|mapping| {
+        if mapping.range.end <= sp || !mapping.writable {
+            return true;
+        }
+
+        stack = Some(mapping.range.clone());
+        false
+    }) {
+        return is_below_stack_pointer(fault_addr, sp);
+    }
+
+    match stack {
+        Some(stack) if stack.start <= sp || stack.start - sp <= MAX_GUARD_GAP => {
+            fault_addr < stack.start && stack.start - fault_addr <= MAX_GUARD_GAP
+        }
+        _ => false,
+    }
+}
+
+/// Determines if the fault address is just below the stack pointer, which is
+/// the case when a thread overflows its stack, as it faults when pushing to,
+/// or calling with, a stack pointer that has moved into the guard page(s).
+///
+/// This is only used when the stack mapping can't be found, as unlike
+/// [`is_stack_overflow`] it can't account for the stack pointer itself being
+/// corrupted.
+#[inline]
+fn is_below_stack_pointer(fault_addr: usize, sp: usize) -> bool {
+    fault_addr <= sp && sp - fault_addr <= MAX_GUARD_GAP
+}
+
+/// Retrieves the stack pointer from the thread context
+#[inline]
+pub(super) fn stack_pointer(uc: &crash_context::ucontext_t) -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            uc.uc_mcontext.gregs[libc::REG_RSP as usize] as usize
+        } else if #[cfg(target_arch = "x86")] {
+            uc.uc_mcontext.gregs[libc::REG_ESP as usize] as usize
+        } else if #[cfg(target_arch = "aarch64")] {
+            uc.uc_mcontext.sp as usize
+        } else if #[cfg(target_arch = "arm")] {
+            uc.uc_mcontext.arm_sp as usize
+        } else if #[cfg(target_arch = "riscv64")] {
+            // REG_SP, which libc only defines for glibc
+            uc.uc_mcontext.__gregs[2] as usize
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_mappings() {
+        let mapping =
+            Mapping::parse(b"7ffd2c9a1000-7ffd2c9c2000 rw-p 00000000 00:00 0      [stack]")
+                .unwrap();
+        assert_eq!(mapping.range, 0x7ffd_2c9a_1000..0x7ffd_2c9c_2000);
+        assert!(mapping.writable);
+
+        let mapping = Mapping::parse(b"7f6b1c000000-7f6b1c001000 ---p 00000000 00:00 0").unwrap();
+        assert!(!mapping.writable);
+
+        assert!(Mapping::parse(b"").is_none());
+        assert!(Mapping::parse(b"7f6b1c000000 ---p").is_none());
+    }
+
+    #[test]
+    fn detects_overflow() {
+        let local = 0u64;
+        let sp = std::ptr::addr_of!(local) as usize;
+
+        // SAFETY: syscalls
+        unsafe {
+            // An address on our own stack is obviously not an overflow
+            assert!(!is_stack_overflow(sp, sp));
+            // Nor is a null pointer access
+            assert!(!is_stack_overflow(0, sp));
+
+            let mut stack_start = 0;
+            assert!(for_each_mapping(|mapping| {
+                if mapping.range.contains(&sp) {
+                    stack_start = mapping.range.start;
+                    false
+                } else {
+                    true
+                }
+            }));
+
+            // But just below it is
+            assert!(is_stack_overflow(stack_start - 8, sp));
+            assert!(is_stack_overflow(stack_start - 8, stack_start - 16));
+        }
+    }
+
+    #[test]
+    fn falls_back_to_stack_pointer() {
+        let sp = 0x7ffd_2c9c_2000;
+
+        assert!(is_below_stack_pointer(sp, sp));
+        assert!(is_below_stack_pointer(sp - 8, sp));
+        assert!(!is_below_stack_pointer(sp + 8, sp));
+        assert!(!is_below_stack_pointer(0, sp));
+    }
+}
@@ -1,151 +1,48 @@
-use crate::{Error, Signal};
-use std::{mem, ptr};
-
-// std::cmp::max is not const :(
-const fn get_stack_size() -> usize {
-    if libc::SIGSTKSZ > 16 * 1024 {
-        libc::SIGSTKSZ
-    } else {
-        16 * 1024
-    }
-}
-
-/// The size of the alternate stack that is mapped for every thread.
-///
-/// This has a minimum size of 16k, which might seem a bit large, but this
-/// memory will only ever be committed in case we actually get a stack overflow,
-/// which is (hopefully) exceedingly rare
-const SIG_STACK_SIZE: usize = get_stack_size();
+use crate::{
+    events::{EventId, Events},
+    Error, Signal,
+};
+use std::{
+    mem, ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Once,
+    },
+};
 
 /// kill
 pub(crate) const SI_USER: i32 = 0;
 
-struct StackSave {
-    old: Option<libc::stack_t>,
-    new: libc::stack_t,
+/// Restores the signal handler for the specified signal back to its default
+/// handler, which _should_ perform the default signal action as seen in
+/// <https://man7.org/linux/man-pages/man7/signal.7.html>
+#[inline]
+unsafe fn install_default_handler(sig: i32) {
+    set_handler(sig, libc::SIG_DFL);
 }
 
-unsafe impl Send for StackSave {}
-
-static STACK_SAVE: parking_lot::Mutex<Option<StackSave>> = parking_lot::const_mutex(None);
-
-/// Create an alternative stack to run the signal handlers on. This is done since
-/// the signal might have been caused by a stack overflow.
-pub unsafe fn install_sigaltstack() -> Result<(), Error> {
-    // Check to see if the existing sigaltstack, and if it exists, is it big
-    // enough. If so we don't need to allocate our own.
-    let mut old_stack = mem::zeroed();
-    let r = libc::sigaltstack(ptr::null(), &mut old_stack);
-    assert_eq!(
-        r,
-        0,
-        "learning about sigaltstack failed: {}",
-        std::io::Error::last_os_error()
-    );
-
-    if old_stack.ss_flags & libc::SS_DISABLE == 0 && old_stack.ss_size >= SIG_STACK_SIZE {
-        return Ok(());
+/// Restores the default handlers for all of the [`EXCEPTION_SIGNALS`], as
+/// well as any other signal we installed a handler for
+pub(super) unsafe fn install_default_handlers() {
+    for sig in EXCEPTION_SIGNALS {
+        install_default_handler(sig as i32);
     }
 
-    // ... but failing that we need to allocate our own, so do all that
-    // here.
-    let guard_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
-    let alloc_size = guard_size + SIG_STACK_SIZE;
-
-    let ptr = libc::mmap(
-        ptr::null_mut(),
-        alloc_size,
-        libc::PROT_NONE,
-        libc::MAP_PRIVATE | libc::MAP_ANON,
-        -1,
-        0,
-    );
-    if ptr == libc::MAP_FAILED {
-        return Err(Error::OutOfMemory);
-    }
-
-    // Prepare the stack with readable/writable memory and then register it
-    // with `sigaltstack`.
-    let stack_ptr = (ptr as usize + guard_size) as *mut libc::c_void;
-    let r = libc::mprotect(
-        stack_ptr,
-        SIG_STACK_SIZE,
-        libc::PROT_READ | libc::PROT_WRITE,
-    );
-    assert_eq!(
-        r,
-        0,
-        "mprotect to configure memory for sigaltstack failed: {}",
-        std::io::Error::last_os_error()
-    );
-    let new_stack = libc::stack_t {
-        ss_sp: stack_ptr,
-        ss_flags: 0,
-        ss_size: SIG_STACK_SIZE,
-    };
-    let r = libc::sigaltstack(&new_stack, ptr::null_mut());
-    assert_eq!(
-        r,
-        0,
-        "registering new sigaltstack failed: {}",
-        std::io::Error::last_os_error()
-    );
-
-    *STACK_SAVE.lock() = Some(StackSave {
-        old: (old_stack.ss_flags & libc::SS_DISABLE != 0).then(|| old_stack),
-        new: new_stack,
-    });
-
-    Ok(())
-}
-
-pub unsafe fn restore_sigaltstack() {
-    let mut ssl = STACK_SAVE.lock();
-
-    // Only restore the old_stack if the current alternative stack is the one
-    // installed by the call to install_sigaltstack.
-    if let Some(ss) = &mut *ssl {
-        let mut current_stack = mem::zeroed();
-        if libc::sigaltstack(ptr::null(), &mut current_stack) == -1 {
-            return;
-        }
-
-        if current_stack.ss_sp == ss.new.ss_sp {
-            if let Some(old) = ss.old {
-                // Restore the old alt stack if there was one
-                if libc::sigaltstack(&old, ptr::null_mut()) == -1 {
-                    return;
-                }
-            } else {
-                // Restore to the default alt stack otherwise
-                let mut disable: libc::stack_t = mem::zeroed();
-                disable.ss_flags = libc::SS_DISABLE;
-                if libc::sigaltstack(&disable, ptr::null_mut()) == -1 {
-                    return;
-                }
+    if let Some(handler) = HANDLER.read() {
+        for (sig, action) in handler.old_handlers.iter().enumerate() {
+            if action.is_some() {
+                install_default_handler(sig as i32);
             }
         }
-
-        let r = libc::munmap(ss.new.ss_sp, ss.new.ss_size);
-        debug_assert_eq!(r, 0, "munmap failed during thread shutdown");
-        *ssl = None;
     }
 }
 
-/// Restores the signal handler for the specified signal back to its default
-/// handler, which _should_ perform the default signal action as seen in
-/// <https://man7.org/linux/man-pages/man7/signal.7.html>
-#[inline]
-unsafe fn install_default_handler(sig: Signal) {
-    set_handler(sig, libc::SIG_DFL);
-}
-
 #[inline]
 pub(crate) unsafe fn ignore_signal(sig: Signal) {
-    set_handler(sig, libc::SIG_IGN);
+    set_handler(sig as i32, libc::SIG_IGN);
 }
 
-unsafe fn set_handler(sig: Signal, action: usize) {
+unsafe fn set_handler(sig: i32, action: usize) {
     // Android L+ expose signal and sigaction symbols that override the system
     // ones. There is a bug in these functions where a request to set the handler
     // to SIG_DFL is ignored. In that case, an infinite loop is entered as the
@@ -159,140 +56,691 @@ unsafe fn set_handler(sig: Signal, action: usize) {
             sa.sa_flags = libc::SA_RESTART;
             libc::syscall(
                 libc::SYS_rt_sigaction,
-                sig as i32,
+                sig,
                 &sa,
                 ptr::null::<libc::sigaction>(),
                 mem::size_of::<libc::sigset_t>(),
             );
         } else {
-            libc::signal(sig as i32, action);
+            libc::signal(sig, action);
         }
     }
 }
 
 /// The various signals we attempt to handle
-const EXCEPTION_SIGNALS: [Signal; 6] = [
+pub(super) const EXCEPTION_SIGNALS: [Signal; 7] = [
     Signal::Abort,
     Signal::Bus,
     Signal::Fpe,
     Signal::Illegal,
     Signal::Segv,
+    Signal::Sys,
     Signal::Trap,
 ];
 
-static OLD_HANDLERS: parking_lot::Mutex<Option<[libc::sigaction; 6]>> =
-    parking_lot::const_mutex(None);
+/// `_NSIG`, every valid signal number, including the realtime signals, is
+/// less than this
+pub(super) const SIGNAL_COUNT: usize = 65;
+
+/// Checks that a handler can be installed for the raw signal number
+pub(super) fn validate_signal(sig: i32) -> Result<(), Error> {
+    if sig <= 0 || sig as usize >= SIGNAL_COUNT || sig == libc::SIGKILL || sig == libc::SIGSTOP {
+        Err(Error::InvalidSignal(sig))
+    } else {
+        Ok(())
+    }
+}
+
+/// The handlers that were installed before ours, indexed by signal number,
+/// for each signal that we actually installed a handler for
+type OldHandlers = [Option<libc::sigaction>; SIGNAL_COUNT];
 
 /// Restores all of the signal handlers back to their previous values, or the
-/// default if the previous value cannot be restored
-pub unsafe fn restore_handlers() {
-    let mut ohl = OLD_HANDLERS.lock();
-
-    if let Some(old) = &*ohl {
-        for (sig, action) in EXCEPTION_SIGNALS.into_iter().zip(old.iter()) {
-            if libc::sigaction(sig as i32, action, ptr::null_mut()) == -1 {
-                install_default_handler(sig);
-            }
+/// default if the previous value cannot be restored, returning false if any
+/// of them couldn't be restored
+unsafe fn restore_handlers(old: &OldHandlers) -> bool {
+    let mut restored = true;
+    for (sig, action) in old.iter().enumerate() {
+        let Some(action) = action else {
+            continue;
+        };
+
+        if libc::sigaction(sig as i32, action, ptr::null_mut()) == -1 {
+            install_default_handler(sig as i32);
+            restored = false;
         }
     }
+    restored
+}
 
-    ohl.take();
+/// Invokes the previously installed handler for a signal directly, with the
+/// same arguments we received from the kernel
+unsafe fn chain_handler(
+    previous: &libc::sigaction,
+    sig: i32,
+    info: &mut libc::siginfo_t,
+    uc: &mut libc::c_void,
+) {
+    if previous.sa_flags & libc::SA_SIGINFO != 0 {
+        let handler = mem::transmute::<
+            usize,
+            unsafe extern "C" fn(i32, *mut libc::siginfo_t, *mut libc::c_void),
+        >(previous.sa_sigaction);
+        handler(sig, info, uc);
+    } else {
+        let handler = mem::transmute::<usize, unsafe extern "C" fn(i32)>(previous.sa_sigaction);
+        handler(sig);
+    }
 }
 
-pub unsafe fn install_handlers() {
-    let mut ohl = OLD_HANDLERS.lock();
+/// Retrieves the handlers that are currently installed for each of the
+/// specified signal numbers, which must have been checked with
+/// [`validate_signal`], so that they can be restored later
+unsafe fn query_handlers(signals: &[i32]) -> Result<Arc<OldHandlers>, Error> {
+    let mut old_handlers = [None; SIGNAL_COUNT];
 
-    if ohl.is_some() {
-        return;
+    for sig in signals.iter().copied() {
+        let mut old = mem::zeroed();
+        if libc::sigaction(sig, ptr::null(), &mut old) == -1 {
+            return Err(Error::install(
+                crate::InstallStep::QueryHandler(sig),
+                std::io::Error::last_os_error(),
+                true,
+            ));
+        }
+        old_handlers[sig as usize] = Some(old);
     }
 
-    // Attempt store all of the current handlers so we can restore them later
-    let mut old_handlers: [mem::MaybeUninit<libc::sigaction>; 6] =
-        mem::MaybeUninit::uninit().assume_init();
+    Ok(Arc::new(old_handlers))
+}
 
-    for (sig, handler) in EXCEPTION_SIGNALS
-        .iter()
-        .copied()
-        .zip(old_handlers.iter_mut())
-    {
-        let mut old = mem::zeroed();
-        if libc::sigaction(sig as i32, ptr::null(), &mut old) == -1 {
-            return;
+/// Installs our signal handler for each of the specified signal numbers,
+/// replacing the handlers retrieved via [`query_handlers`].
+///
+/// If any of the handlers can't be installed, the ones that were are restored
+unsafe fn install_handlers(signals: &[i32], old_handlers: &OldHandlers) -> Result<(), Error> {
+    if let Err((sig, error)) = set_signal_handlers(signals) {
+        // Only the handlers before the one that failed were replaced, and
+        // restoring the failed one would most likely fail again
+        let mut replaced = *old_handlers;
+        for later in signals.iter().skip_while(|later| **later != sig) {
+            replaced[*later as usize] = None;
         }
-        *handler = mem::MaybeUninit::new(old);
+        let rolled_back = restore_handlers(&replaced);
+        return Err(Error::install(
+            crate::InstallStep::InstallHandler(sig),
+            error,
+            rolled_back,
+        ));
     }
 
+    Ok(())
+}
+
+/// Sets our signal handler as the handler for each of the signals, without
+/// storing the handlers they replace, stopping at the first signal it fails
+/// for
+unsafe fn set_signal_handlers(signals: &[i32]) -> Result<(), (i32, std::io::Error)> {
     let mut sa: libc::sigaction = mem::zeroed();
     libc::sigemptyset(&mut sa.sa_mask);
 
-    // Mask all exception signals when we're handling one of them.
+    // Mask all exception signals, and any others we handle, when we're
+    // handling one of them.
     for sig in EXCEPTION_SIGNALS {
         libc::sigaddset(&mut sa.sa_mask, sig as i32);
     }
+    for sig in signals.iter().copied() {
+        libc::sigaddset(&mut sa.sa_mask, sig);
+    }
 
-    sa.sa_sigaction = signal_handler as usize;
+    sa.sa_sigaction = signal_handler as *const () as usize;
     sa.sa_flags = libc::SA_ONSTACK | libc::SA_SIGINFO;
 
     // Use our signal_handler for all of the signals we wish to catch
-    for sig in EXCEPTION_SIGNALS {
-        // At this point it is impractical to back out changes, and so failure to
-        // install a signal is intentionally ignored.
-        let _ = libc::sigaction(sig as i32, &sa, ptr::null_mut());
+    for sig in signals.iter().copied() {
+        if libc::sigaction(sig, &sa, ptr::null_mut()) == -1 {
+            return Err((sig, std::io::Error::last_os_error()));
+        }
     }
 
-    // Everything is initialized. Transmute the array to the
-    // initialized type.
-    *ohl = Some(mem::transmute::<_, [libc::sigaction; 6]>(old_handlers));
+    Ok(())
+}
+
+/// The configuration of our signal handlers, which is only applied by the
+/// first handler to be attached, see [`super::CrashHandlerBuilder`]
+pub(super) struct Settings {
+    pub(super) alt_stack_size: usize,
+    /// Checked with [`validate_signal`]
+    pub(super) signals: Vec<i32>,
+    pub(super) always_chain: bool,
+    pub(super) callback_timeout: Option<std::time::Duration>,
+    pub(super) callback_thread: bool,
+    /// Checked with [`validate_signal`], and included in `signals`
+    pub(super) dump_signal: Option<i32>,
+    pub(super) fork_behavior: super::ForkBehavior,
+    pub(super) termination: super::Termination,
+    pub(super) crash_log: Option<crate::crash_loop::CrashLog>,
+    pub(super) fallback_file: Option<crate::fallback::FallbackFile>,
+    /// Captured by [`super::environment::capture`]
+    pub(super) environment: Option<Box<[u8]>>,
 }
 
-pub(super) fn attach(on_crash: Box<dyn crate::CrashEvent>) -> Result<(), Error> {
-    let mut lock = HANDLER.lock();
+/// The signals our handler was installed for, along with the handler each had
+/// before, or nothing if no handler is attached
+pub(super) fn previous_handlers() -> Vec<(i32, libc::sigaction)> {
+    let Some(handler) = HANDLER.read() else {
+        return Vec::new();
+    };
+
+    handler
+        .old_handlers
+        .iter()
+        .enumerate()
+        .filter_map(|(sig, action)| action.map(|action| (sig as i32, action)))
+        .collect()
+}
+
+/// Returns true if the action invokes our signal handler
+#[inline]
+pub(super) fn is_our_handler(action: &libc::sigaction) -> bool {
+    action.sa_sigaction == signal_handler as *const () as usize
+}
+
+/// Attaches the event, installing our signal handlers if this is the first
+/// one to be attached, in which case the settings are created to configure
+/// them, otherwise they aren't created at all
+pub(super) fn attach(
+    on_crash: Box<dyn crate::CrashEvent>,
+    priority: i32,
+    settings: impl FnOnce() -> Result<Settings, Error>,
+) -> Result<EventId, Error> {
+    let _lock = ATTACH_LOCK.lock();
+
+    if let Some(current) = HANDLER.read() {
+        let mut inner = HandlerInner::clone(&current);
+        let id = inner.events.insert(on_crash, priority);
+
+        // The guard must be released before replacing the handler, which
+        // waits for all readers to finish
+        drop(current);
+        HANDLER.set(inner);
+
+        return Ok(id);
+    }
 
-    if lock.is_some() {
-        return Err(Error::HandlerAlreadyInstalled);
+    let Settings {
+        alt_stack_size,
+        signals,
+        always_chain,
+        callback_timeout,
+        callback_thread,
+        dump_signal,
+        fork_behavior,
+        termination,
+        crash_log,
+        fallback_file,
+        environment,
+    } = settings()?;
+
+    let minimum = crate::unix::min_alt_stack_size();
+    if alt_stack_size < minimum {
+        return Err(Error::InvalidAltStackSize {
+            requested: alt_stack_size,
+            minimum,
+        });
+    }
+
+    // Round up to the page size, as that is the granularity of the mapping
+    // SAFETY: syscall
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    crate::unix::set_alt_stack_size((alt_stack_size + page_size - 1) & !(page_size - 1));
+
+    // Started before the handlers are installed so that a crash can't be
+    // handled without it
+    if let Some(timeout) = callback_timeout {
+        super::watchdog::start(timeout)?;
+    }
+    if callback_thread {
+        if let Err(err) = super::dispatch::start() {
+            super::watchdog::stop();
+            return Err(err);
+        }
+    }
+    if let Some(sig) = dump_signal {
+        if let Err(err) = super::dump_request::start(sig) {
+            super::watchdog::stop();
+            super::dispatch::stop();
+            return Err(err);
+        }
+    }
+
+    // SAFETY: syscalls
+    let prepared = unsafe {
+        query_handlers(&signals)
+            .and_then(|old_handlers| crate::unix::install_sigaltstack().map(|()| old_handlers))
+    };
+    let old_handlers = match prepared {
+        Ok(old_handlers) => old_handlers,
+        Err(err) => {
+            super::watchdog::stop();
+            super::dispatch::stop();
+            super::dump_request::stop();
+            return Err(err);
+        }
+    };
+
+    super::abort_message::resolve();
+
+    // The handler is published before our signal handlers are installed, so
+    // that a signal delivered to them always finds it, even on a thread that
+    // crashes while we are still attaching
+    let mut events = Events::default();
+    let id = events.insert(on_crash, priority);
+    let attached = crash_context::CrashTime::now();
+    HANDLER.set(HandlerInner {
+        events,
+        always_chain,
+        old_handlers: old_handlers.clone(),
+        callback_timeout,
+        callback_thread,
+        dump_signal,
+        fork_behavior,
+        termination,
+        crash_log: crash_log.map(Arc::new),
+        fallback_file: fallback_file.map(Arc::new),
+        process_start: process_start_time(attached),
+        attached,
+        cpu: crash_context::CpuInfo::capture(),
+        memory_files: MemoryFiles::open().map(Arc::new),
+        environment: environment.map(Arc::from),
+    });
+
+    // SAFETY: syscalls
+    if let Err(err) = unsafe { install_handlers(&signals, &old_handlers) } {
+        HANDLER.take();
+        // SAFETY: syscall
+        unsafe {
+            crate::unix::restore_sigaltstack();
+        }
+        super::watchdog::stop();
+        super::dispatch::stop();
+        super::dump_request::stop();
+        return Err(err);
+    }
+    DISARMED.store(false, Ordering::Relaxed);
+
+    ATFORK.call_once(|| {
+        // SAFETY: syscall, the hooks are never unregistered, and do nothing
+        // if no handler is attached
+        unsafe {
+            libc::pthread_atfork(
+                Some(prepare_fork),
+                Some(parent_after_fork),
+                Some(child_after_fork),
+            );
+        }
+    });
+
+    #[cfg(feature = "panic")]
+    crate::panic::install();
+
+    Ok(id)
+}
+
+/// Detaches the event, and if it was the last one, our signal handlers,
+/// restoring the previously installed or default handlers
+pub(super) fn detach(id: EventId) {
+    let _lock = ATTACH_LOCK.lock();
+
+    let mut inner = {
+        let Some(current) = HANDLER.read() else {
+            return;
+        };
+
+        HandlerInner::clone(&current)
+    };
+
+    if !inner.events.remove(id) {
+        return;
+    }
+
+    if !inner.events.is_empty() {
+        HANDLER.set(inner);
+        return;
     }
 
     // SAFETY: syscalls
     unsafe {
-        install_sigaltstack()?;
-        install_handlers();
+        crate::unix::restore_sigaltstack();
+        restore_handlers(&inner.old_handlers);
+    }
+    HANDLER.take();
+    DISARMED.store(false, Ordering::Relaxed);
+    super::watchdog::stop();
+    super::dispatch::stop();
+    super::dump_request::stop();
+
+    #[cfg(feature = "panic")]
+    crate::panic::uninstall();
+}
+
+/// Re-arms the handler in the child after a `fork`, see
+/// [`super::CrashHandler::reattach_after_fork`]
+pub(super) fn reattach_after_fork() -> Result<(), Error> {
+    let _lock = ATTACH_LOCK.lock();
+
+    let Some(handler) = HANDLER.read() else {
+        return Ok(());
+    };
+
+    if DISARMED.swap(false, Ordering::Relaxed) {
+        // The handlers we replaced are still the ones we restored in the
+        // child, so there's no need to query them again
+        let signals: Vec<i32> = handler
+            .old_handlers
+            .iter()
+            .enumerate()
+            .filter_map(|(sig, action)| action.map(|_| sig as i32))
+            .collect();
+
+        // SAFETY: syscalls
+        if let Err((sig, error)) = unsafe { set_signal_handlers(&signals) } {
+            return Err(Error::install(
+                crate::InstallStep::InstallHandler(sig),
+                error,
+                false,
+            ));
+        }
+    }
+
+    // Neither the watchdog nor the callback thread is copied to the child
+    if let Some(timeout) = handler.callback_timeout {
+        if !super::watchdog::is_running() {
+            super::watchdog::start(timeout)?;
+        }
+    }
+    if handler.callback_thread && !super::dispatch::is_running() {
+        super::dispatch::start()?;
+    }
+    if let Some(sig) = handler.dump_signal {
+        if !super::dump_request::is_running() {
+            super::dump_request::start(sig)?;
+        }
     }
 
-    *lock = Some(HandlerInner::new(on_crash));
+    // The child is a new process, so its crashes are relative to the fork
+    // rather than when the parent was started
+    let mut inner = HandlerInner::clone(&handler);
+    inner.attached = crash_context::CrashTime::now();
+    inner.process_start = process_start_time(inner.attached);
+    inner.memory_files = MemoryFiles::open().map(Arc::new);
+
+    // The guard must be released before replacing the handler, which waits
+    // for all readers to finish
+    drop(handler);
+    HANDLER.set(inner);
 
     Ok(())
 }
 
-/// Detaches our signal handle, restoring the previously installed or default
-/// handlers
-pub(super) fn detach() {
-    let mut lock = HANDLER.lock();
-    if lock.is_some() {
-        // SAFETY: syscalls
+/// The `/proc` files the [`crash_context::MemoryStats`] are read from, which
+/// are opened when attaching as they can't be opened in the signal handler
+struct MemoryFiles {
+    /// The process the files belong to, as they keep referring to it in a
+    /// forked child that hasn't [reattached](reattach_after_fork)
+    pid: u32,
+    statm: std::fs::File,
+    status: Option<std::fs::File>,
+}
+
+impl MemoryFiles {
+    fn open() -> Option<Self> {
+        Some(Self {
+            pid: std::process::id(),
+            statm: std::fs::File::open("/proc/self/statm").ok()?,
+            status: std::fs::File::open("/proc/self/status").ok(),
+        })
+    }
+
+    /// Reads the current memory usage, which is all zeroes if the files
+    /// belong to another process
+    #[inline]
+    fn read(&self) -> crash_context::MemoryStats {
+        use std::os::unix::io::AsRawFd;
+
+        if self.pid != std::process::id() {
+            return crash_context::MemoryStats::default();
+        }
+
+        crash_context::MemoryStats::read(
+            self.statm.as_raw_fd(),
+            self.status.as_ref().map(|status| status.as_raw_fd()),
+        )
+    }
+}
+
+/// Retrieves the time the process was started, relative to `now`, from the
+/// start time in `/proc/self/stat`, which is in clock ticks since boot.
+///
+/// Returns all zeroes if it can't be determined.
+fn process_start_time(now: crash_context::CrashTime) -> crash_context::CrashTime {
+    let age = || {
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        // The command name can contain spaces and parentheses, so skip past
+        // the last one, after which `starttime` is the 20th field
+        let started: u64 = stat
+            .get(stat.rfind(')')? + 2..)?
+            .split(' ')
+            .nth(19)?
+            .parse()
+            .ok()?;
+
+        // SAFETY: syscall
+        let ticks_per_sec = u64::try_from(unsafe { libc::sysconf(libc::_SC_CLK_TCK) }).ok()?;
+        let started = std::time::Duration::from_nanos(
+            (u128::from(started) * 1_000_000_000 / u128::from(ticks_per_sec.max(1))) as u64,
+        );
+
+        let mut boottime = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: syscall
+        if unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut boottime) } != 0 {
+            return None;
+        }
+        let boottime = std::time::Duration::new(boottime.tv_sec as u64, boottime.tv_nsec as u32);
+
+        Some(boottime.saturating_sub(started).as_nanos() as u64)
+    };
+
+    age().map_or_else(Default::default, |age| crash_context::CrashTime {
+        monotonic: now.monotonic.saturating_sub(age),
+        wall: now.wall.saturating_sub(age),
+    })
+}
+
+/// Whether our signal handlers were uninstalled in the child after a `fork`,
+/// see [`super::ForkBehavior::Disarm`]
+static DISARMED: AtomicBool = AtomicBool::new(false);
+/// Ensures the `fork` hooks are only registered once, as they can't be
+/// unregistered
+static ATFORK: Once = Once::new();
+/// Whether [`prepare_fork`] acquired [`ATTACH_LOCK`]
+static LOCKED_FOR_FORK: AtomicBool = AtomicBool::new(false);
+/// The thread that is forking, as the thread has a different id in the child
+static FORKING_THREAD: AtomicUsize = AtomicUsize::new(0);
+
+/// Invoked in the parent before a `fork`, so that the child isn't created
+/// while a handler is being attached or detached.
+///
+/// The lock isn't waited for, as the thread holding it could be waiting for
+/// the forking thread, eg. if the user's callback forks while a handler is
+/// detached on another thread, in which case the child gets the lock in
+/// whatever state it was in, and [`child_after_fork`] cleans up after it.
+extern "C" fn prepare_fork() {
+    if let Some(lock) = ATTACH_LOCK.try_lock() {
+        // Released by `parent_after_fork` and `child_after_fork` instead
+        #[allow(clippy::mem_forget)]
+        mem::forget(lock);
+        LOCKED_FOR_FORK.store(true, Ordering::Relaxed);
+    }
+
+    FORKING_THREAD.store(crate::unix::current_thread(), Ordering::Relaxed);
+}
+
+extern "C" fn parent_after_fork() {
+    if LOCKED_FOR_FORK.swap(false, Ordering::Relaxed) {
+        // SAFETY: the lock was acquired by `prepare_fork` on this thread
         unsafe {
-            restore_sigaltstack();
-            restore_handlers();
+            ATTACH_LOCK.force_unlock();
         }
-        lock.take();
     }
 }
 
-pub(super) static HANDLER: parking_lot::Mutex<Option<HandlerInner>> =
-    parking_lot::const_mutex(None);
+/// Invoked in the child after a `fork`, where the only thread is the one that
+/// forked, so any state belonging to the other threads in the parent is
+/// removed, as they no longer exist to release it.
+///
+/// This must be async signal safe, as the parent may have been multithreaded.
+extern "C" fn child_after_fork() {
+    LOCKED_FOR_FORK.store(false, Ordering::Relaxed);
+
+    let parent = FORKING_THREAD.load(Ordering::Relaxed);
+    let child = crate::unix::current_thread();
+
+    // The forking thread can't be attaching or detaching, so the lock, if it
+    // is held, is held by a thread that doesn't exist in the child
+    if ATTACH_LOCK.is_locked() {
+        // SAFETY: the owner doesn't exist in the child
+        unsafe {
+            ATTACH_LOCK.force_unlock();
+        }
+    }
+
+    // Whereas the crash context is held by the forking thread if it forked
+    // from within the user's callback, in which case it is still in use
+    let forked_in_handler = IN_HANDLER.contains(parent);
+    if !forked_in_handler && CRASH_CONTEXT.is_locked() {
+        // SAFETY: the owner doesn't exist in the child
+        unsafe {
+            CRASH_CONTEXT.force_unlock();
+        }
+    }
+
+    super::crashed_threads::after_fork(forked_in_handler);
+    IN_HANDLER.after_fork(parent, child);
+    HANDLER.after_fork(parent, child);
+    super::watchdog::after_fork();
+    super::dispatch::after_fork();
+    super::dump_request::after_fork();
+    super::hold::after_fork();
+
+    if let Some(handler) = HANDLER.read() {
+        if handler.fork_behavior == super::ForkBehavior::Disarm {
+            // SAFETY: syscalls
+            unsafe {
+                restore_handlers(&handler.old_handlers);
+            }
+            DISARMED.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Routes a panic through the attached handler, see [`crate::panic`]
+#[cfg(feature = "panic")]
+pub(crate) fn simulate_panic() -> crate::CrashEventResult {
+    let Some(_in_handler) = InHandler::enter() else {
+        return crate::CrashEventResult::Reraise;
+    };
+
+    if let Some(handler) = HANDLER.read() {
+        if handler.over_crash_limit(libc::SIGABRT) {
+            return crate::CrashEventResult::Reraise;
+        }
+
+        // Panics are reported as an abort, since that is what they would
+        // become if the process was compiled with `panic = "abort"`
+        let mut cc = crash_context::CrashContext::capture();
+        cc.siginfo.ssi_signo = libc::SIGABRT as u32;
+        cc.reason = crash_context::CrashReason::Panic;
+        (cc.annotations, cc.annotation_count) = crate::annotations::location();
+        (cc.breadcrumbs, cc.breadcrumb_count) = crate::breadcrumbs::location();
+        (cc.memory_regions, cc.memory_region_count) = crate::memory_regions::location();
+        (cc.process_start, cc.attach_time) = (handler.process_start, handler.attached);
+        cc.cpu = handler.cpu;
+        cc.memory = handler.memory_stats();
+        (cc.environment, cc.environment_len) = handler.environment_location();
+        // Recorded by `abort_message::install_panic_hook`, if it was installed
+        // after attaching, or left over from a previous panic or `set` otherwise
+        super::abort_message::capture(&mut cc.abort_message);
+
+        // Allow ourselves to be dumped, if that is what the user handler wishes to do
+        // SAFETY: syscalls
+        let _set_dumpable = unsafe { SetDumpable::new() };
+        handler.invoke(&cc)
+    } else {
+        crate::CrashEventResult::Reraise
+    }
+}
+
+/// Routes a context through the attached handler as if it was an actual
+/// crash, see [`super::CrashHandler::simulate_context`]
+pub(super) fn simulate_context(cc: &crash_context::CrashContext) -> crate::CrashEventResult {
+    let Some(_in_handler) = InHandler::enter() else {
+        return crate::CrashEventResult::Reraise;
+    };
+
+    match HANDLER.read() {
+        Some(handler) => handler.invoke(cc),
+        None => crate::CrashEventResult::Reraise,
+    }
+}
+
+/// The attached handler, which is read from the signal handler without
+/// locking, so that a crash while attaching or detaching can't deadlock.
+///
+/// Everything the signal handler needs is in this single immutable value,
+/// which is replaced wholesale whenever a handler is attached or detached
+pub(super) static HANDLER: crate::unix::HandlerSlot<HandlerInner> = crate::unix::HandlerSlot::new();
+/// Serializes attaching and detaching, this is never locked from the signal
+/// handler
+static ATTACH_LOCK: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+
+/// The threads that are currently running the user's handler, which is not
+/// thread local storage, as that isn't async signal safe
+static IN_HANDLER: crate::unix::ThreadSet = crate::unix::ThreadSet::new();
+
+/// Marks the current thread as running the user's handler until it is dropped,
+/// so that a crash within the handler itself can be detected rather than
+/// re-entering the handler, or detaching it from within itself
+pub(super) struct InHandler {
+    _entry: crate::unix::ThreadSetEntry<'static>,
+}
+
+impl InHandler {
+    /// Returns `None` if the current thread is already running the handler
+    #[inline]
+    pub(super) fn enter() -> Option<Self> {
+        IN_HANDLER.enter().map(|_entry| Self { _entry })
+    }
+}
 
 /// This is the actual function installed for each signal we support, invoked
 /// by the kernel
-unsafe extern "C" fn signal_handler(
-    sig: Signal,
-    info: *mut libc::siginfo_t,
-    uc: *mut libc::c_void,
-) {
+unsafe extern "C" fn signal_handler(sig: i32, info: *mut libc::siginfo_t, uc: *mut libc::c_void) {
     let info = &mut *info;
     let uc = &mut *uc;
 
+    #[cfg(feature = "alloc-check")]
+    let forbid = crate::alloc_check::Forbid::enter();
+
     enum Action {
         RestoreDefault,
-        RestorePrevious,
+        Retrigger,
+        Chain(libc::sigaction),
+        Continue,
+        Exit(i32),
         Jump((*mut super::jmp::JmpBuf, i32)),
     }
 
@@ -309,18 +757,18 @@ unsafe extern "C" fn signal_handler(
         // will call the function with the right arguments.
         {
             let mut cur_handler = mem::zeroed();
-            if libc::sigaction(sig as i32, ptr::null_mut(), &mut cur_handler) == 0
-                && cur_handler.sa_sigaction == signal_handler as usize
+            if libc::sigaction(sig, ptr::null_mut(), &mut cur_handler) == 0
+                && cur_handler.sa_sigaction == signal_handler as *const () as usize
                 && cur_handler.sa_flags & libc::SA_SIGINFO == 0
             {
                 // Reset signal handler with the correct flags.
                 libc::sigemptyset(&mut cur_handler.sa_mask);
-                libc::sigaddset(&mut cur_handler.sa_mask, sig as i32);
+                libc::sigaddset(&mut cur_handler.sa_mask, sig);
 
-                cur_handler.sa_sigaction = signal_handler as usize;
+                cur_handler.sa_sigaction = signal_handler as *const () as usize;
                 cur_handler.sa_flags = libc::SA_ONSTACK | libc::SA_SIGINFO;
 
-                if libc::sigaction(sig as i32, &cur_handler, ptr::null_mut()) == -1 {
+                if libc::sigaction(sig, &cur_handler, ptr::null_mut()) == -1 {
                     // When resetting the handler fails, try to reset the
                     // default one to avoid an infinite loop here.
                     install_default_handler(sig);
@@ -331,52 +779,271 @@ unsafe extern "C" fn signal_handler(
             }
         }
 
-        let handler = HANDLER.lock();
+        #[cfg(target_os = "android")]
+        if sig == Signal::Debugger as i32 {
+            handle_debugger_signal(info, uc);
+            return;
+        }
+
+        // Dump requests are handled on their own thread, and never by the
+        // previous handler, as the process keeps running
+        if super::dump_request::is_dump_signal(sig) {
+            super::dump_request::notify(info);
+            return;
+        }
+
+        // The crash occurred within `catch_crash`, so jump straight back to
+        // it before touching any of our own state
+        if let Some(jmp_buf) = crate::recover::take_recovery_point() {
+            debug_print!("recovering from crash");
+            #[cfg(feature = "alloc-check")]
+            drop(forbid);
+            super::jmp::siglongjmp(jmp_buf, sig);
+        }
+
+        // If the signal was raised while this thread was already handling a
+        // signal, eg. because the user's handler crashed, nothing we do is
+        // safe, and invoking the handler again would most likely just crash
+        // again, so we just restore the default dispositions so that the
+        // retriggered signal kills the process
+        let Some(_in_handler) = InHandler::enter() else {
+            debug_print!("signal raised within handler, installing default handlers");
+            install_default_handlers();
 
-        if let Some(handler) = &*handler {
-            match handler.handle_signal(sig as i32, info, uc) {
-                crate::CrashEventResult::Handled(true) => Action::RestoreDefault,
-                crate::CrashEventResult::Handled(false) => Action::RestorePrevious,
+            retrigger_signal(sig, info);
+            return;
+        };
+
+        if let Some(handler) = HANDLER.read() {
+            // Only the first thread to crash is handled, any other is parked
+            // here and recorded as having also crashed until it is done
+            let gate = super::crashed_threads::Gate::enter(
+                sig,
+                info,
+                &*(uc as *const libc::c_void).cast::<crash_context::ucontext_t>(),
+            );
+
+            let result = if handler.over_crash_limit(sig) {
+                // Restores the default handler, as if we were never installed
+                debug_print!("crash limit exceeded, not invoking handlers");
+                crate::CrashEventResult::Handled { exit: None }
+            } else {
+                let _armed = super::watchdog::Armed::arm(sig, info);
+                handler.handle_signal(sig, info, uc)
+            };
+
+            let action = match result {
+                crate::CrashEventResult::Handled { exit } => {
+                    match handler
+                        .previous_handler(sig)
+                        .filter(|_| handler.always_chain)
+                    {
+                        Some(previous) => Action::Chain(previous),
+                        None => match (exit, handler.termination) {
+                            (Some(code), _) => Action::Exit(code),
+                            (None, super::Termination::Reraise) => Action::RestoreDefault,
+                            (None, super::Termination::CoreDump) => {
+                                enable_core_dump();
+                                Action::RestoreDefault
+                            }
+                            (None, super::Termination::ExitCode) => {
+                                Action::Exit(signal_exit_code(sig))
+                            }
+                        },
+                    }
+                }
+                crate::CrashEventResult::Continue => Action::Continue,
+                crate::CrashEventResult::Reraise => {
+                    if let Some(previous) = handler.previous_handler(sig) {
+                        Action::Chain(previous)
+                    } else {
+                        // The previous handlers are only reachable while we
+                        // still hold the handler
+                        debug_print!("restoring handlers");
+                        restore_handlers(&handler.old_handlers);
+                        if handler.termination == super::Termination::CoreDump {
+                            enable_core_dump();
+                        }
+                        Action::Retrigger
+                    }
+                }
                 crate::CrashEventResult::Jump { jmp_buf, value } => Action::Jump((jmp_buf, value)),
+            };
+
+            // The parked threads are only resumed if this thread survives,
+            // otherwise they would race to handle their own crashes before
+            // the process is terminated, and are instead reported as having
+            // also crashed. Chaining may or may not terminate the process, so
+            // the previous handler is left to decide
+            if matches!(
+                action,
+                Action::RestoreDefault | Action::Retrigger | Action::Exit(_)
+            ) {
+                gate.hold();
             }
+
+            action
+        } else if is_installed(sig) {
+            // The handler is published before our signal handlers are
+            // installed, and removed after they are restored, so this should
+            // never happen, but we don't know what was installed before us
+            Action::RestoreDefault
         } else {
-            Action::RestorePrevious
+            // We were detached on another thread after the signal was
+            // delivered to us, so retriggering it delivers it to the handler
+            // we restored instead
+            Action::Retrigger
         }
     };
 
     // Upon returning from this signal handler, sig will become unmasked and
-    // then it will be retriggered. If one of the ExceptionHandlers handled
-    // it successfully, restore the default handler. Otherwise, restore the
-    // previously installed handler. Then, when the signal is retriggered,
-    // it will be delivered to the appropriate handler.
+    // then it will be retriggered. If the user's handler handled it, restore
+    // the default handler. Otherwise, restore the previously installed
+    // handler. Then, when the signal is retriggered, it will be delivered to
+    // the appropriate handler. The exception to this is if the previous
+    // handler was an actual function, in which case we chain directly to it
+    // so that our handler stays installed, which is what eg. JVMs expect as
+    // they rely on handling SIGSEGV as part of normal operation
     match action {
         Action::RestoreDefault => {
             debug_print!("installing default handler");
             install_default_handler(sig);
         }
-        Action::RestorePrevious => {
-            debug_print!("restoring handlers");
-            restore_handlers();
+        Action::Retrigger => {}
+        Action::Chain(previous) => {
+            debug_print!("chaining to previous handler");
+            #[cfg(feature = "alloc-check")]
+            let _allow = crate::alloc_check::Forbid::allow();
+            chain_handler(&previous, sig, info, uc);
+            // The previous handler is responsible for the signal now, so we
+            // don't retrigger it ourselves
+            return;
+        }
+        Action::Continue => {
+            debug_print!("continuing execution");
+            // Nothing has changed, so just return to where the signal was
+            // raised, without retriggering it
+            return;
+        }
+        Action::Exit(code) => {
+            debug_print!("exiting with {code}");
+            crate::exit_process(code);
         }
         Action::Jump((jmp_buf, value)) => {
             debug_print!("jumping");
+            #[cfg(feature = "alloc-check")]
+            drop(forbid);
             super::jmp::siglongjmp(jmp_buf, value);
         }
     }
 
     debug_print!("finishing signal handler");
 
-    if info.si_code <= 0 || sig == Signal::Abort {
+    retrigger_signal(sig, info);
+}
+
+/// Whether our signal handler is the one currently installed for the signal
+#[inline]
+unsafe fn is_installed(sig: i32) -> bool {
+    let mut current = mem::zeroed();
+    libc::sigaction(sig, ptr::null(), &mut current) == 0
+        && current.sa_sigaction == signal_handler as *const () as usize
+}
+
+/// Handles [`Signal::Debugger`], which unlike the other signals is a request to
+/// dump the process rather than a crash, so we invoke the user's handler and
+/// then always chain to `debuggerd`'s handler, without ever retriggering the
+/// signal or changing its disposition, as the process continues running
+#[cfg(target_os = "android")]
+unsafe fn handle_debugger_signal(info: &mut libc::siginfo_t, uc: &mut libc::c_void) {
+    if let Some(_in_handler) = InHandler::enter() {
+        if let Some(handler) = HANDLER.read() {
+            let _result = handler.handle_signal(Signal::Debugger as i32, info, uc);
+        }
+    }
+
+    let previous = HANDLER
+        .read()
+        .and_then(|handler| handler.previous_handler(Signal::Debugger as i32));
+
+    if let Some(previous) = previous {
+        debug_print!("chaining to debuggerd");
+        chain_handler(&previous, Signal::Debugger as i32, info, uc);
+    }
+}
+
+/// How the process is terminated once a signal has been handled, see
+/// [`super::CrashHandlerBuilder::termination`]
+pub(super) fn termination() -> super::Termination {
+    HANDLER
+        .read()
+        .map_or_else(Default::default, |handler| handler.termination)
+}
+
+/// The code a shell reports for a process killed by the signal, which is used
+/// when the process can't actually be killed by it
+#[inline]
+pub(super) fn signal_exit_code(sig: i32) -> i32 {
+    128 + sig
+}
+
+/// Ensures the kernel writes a core dump when the process is killed by a
+/// signal whose default action is to dump core, by raising the soft
+/// `RLIMIT_CORE` to the hard limit, or removing the limit entirely if we are
+/// privileged enough to, and making the process dumpable again if it was made
+/// undumpable, eg. by `PR_SET_DUMPABLE` or by changing its credentials.
+///
+/// This is async signal safe.
+pub(super) unsafe fn enable_core_dump() {
+    debug_print!("enabling core dump");
+
+    let mut limit: libc::rlimit = mem::zeroed();
+    if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) == 0 {
+        let unlimited = libc::rlimit {
+            rlim_cur: libc::RLIM_INFINITY,
+            rlim_max: libc::RLIM_INFINITY,
+        };
+        if libc::setrlimit(libc::RLIMIT_CORE, &unlimited) == -1 {
+            limit.rlim_cur = limit.rlim_max;
+            libc::setrlimit(libc::RLIMIT_CORE, &limit);
+        }
+    }
+
+    if libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) == 0 {
+        libc::prctl(libc::PR_SET_DUMPABLE, 1, 0, 0, 0);
+    }
+}
+
+/// Sends the signal to the calling thread with the specified siginfo, rather
+/// than the `SI_TKILL` one `tgkill` would send, so that any handler, or a
+/// core dump, sees the original code and fault address. Falls back to
+/// `tgkill` if that isn't allowed, returning false if the signal couldn't be
+/// sent at all.
+///
+/// This is async signal safe.
+pub(super) unsafe fn requeue_signal(sig: i32, info: &libc::siginfo_t) -> bool {
+    let pid = std::process::id() as i32;
+    let tid = libc::syscall(libc::SYS_gettid) as i32;
+    let mut info = *info;
+
+    libc::syscall(libc::SYS_rt_tgsigqueueinfo, pid, tid, sig, &mut info) == 0
+        || libc::syscall(libc::SYS_tgkill, pid, tid, sig) == 0
+}
+
+/// Ensures the signal is raised again once the signal handler returns, now
+/// that a different disposition is installed for it
+unsafe fn retrigger_signal(sig: i32, info: &libc::siginfo_t) {
+    if info.si_code <= 0 || sig == libc::SIGABRT {
         // This signal was triggered by somebody sending us the signal with kill().
-        // In order to retrigger it, we have to queue a new signal by calling
-        // kill() ourselves.  The special case (si_pid == 0 && sig == SIGABRT) is
-        // due to the kernel sending a SIGABRT from a user request via SysRQ.
-        let tid = libc::syscall(libc::SYS_gettid) as i32;
-        if libc::syscall(libc::SYS_tgkill, std::process::id(), tid, sig) < 0 {
+        // In order to retrigger it, we have to queue a new signal ourselves,
+        // which we do with the original siginfo, so that eg. a core dump still
+        // records who sent it. The special case (si_pid == 0 && sig == SIGABRT)
+        // is due to the kernel sending a SIGABRT from a user request via SysRQ.
+        if !requeue_signal(sig, info) {
             // If we failed to kill ourselves (e.g. because a sandbox disallows us
-            // to do so), we instead resort to terminating our process. This will
-            // result in an incorrect exit code.
-            libc::_exit(1);
+            // to do so), we instead resort to terminating our process with the
+            // code a shell would report for the signal
+            libc::_exit(signal_exit_code(sig));
         }
     } else {
         // This was a synchronous signal triggered by a hard fault (e.g. SIGSEGV).
@@ -390,19 +1057,109 @@ unsafe extern "C" fn signal_handler(
 static CRASH_CONTEXT: parking_lot::Mutex<mem::MaybeUninit<crash_context::CrashContext>> =
     parking_lot::const_mutex(mem::MaybeUninit::uninit());
 
+#[derive(Clone)]
 pub(super) struct HandlerInner {
-    handler: Box<dyn crate::CrashEvent>,
+    events: Events,
+    /// Whether the previous handler is invoked even if the user's handler
+    /// handled the signal, see [`super::CrashHandlerBuilder::chain_debuggerd`]
+    /// and [`super::CrashHandlerBuilder::sanitizer_policy`]
+    always_chain: bool,
+    /// Shared between every version of the handler, as it is too large to be
+    /// copied around on the alternate stack, eg. when detaching from within
+    /// the handler
+    old_handlers: Arc<OldHandlers>,
+    /// Restarted by [`reattach_after_fork`], see
+    /// [`super::CrashHandlerBuilder::callback_timeout`]
+    callback_timeout: Option<std::time::Duration>,
+    /// Restarted by [`reattach_after_fork`], see
+    /// [`super::CrashHandlerBuilder::callback_thread`]
+    callback_thread: bool,
+    /// Restarted by [`reattach_after_fork`], see
+    /// [`super::CrashHandlerBuilder::dump_signal`]
+    dump_signal: Option<i32>,
+    fork_behavior: super::ForkBehavior,
+    termination: super::Termination,
+    crash_log: Option<Arc<crate::crash_loop::CrashLog>>,
+    fallback_file: Option<Arc<crate::fallback::FallbackFile>>,
+    /// Captured once when attaching, so that the crash path doesn't need to
+    /// read `/proc`, see [`crash_context::CrashContext::process_start`]
+    process_start: crash_context::CrashTime,
+    attached: crash_context::CrashTime,
+    /// Captured once when attaching, as the CPU doesn't change, see
+    /// [`crash_context::CrashContext::cpu`]
+    cpu: crash_context::CpuInfo,
+    /// Opened when attaching, and read when crashing, see
+    /// [`crash_context::CrashContext::memory`]
+    memory_files: Option<Arc<MemoryFiles>>,
+    /// The encoded [`crash_context::Environment`], if any, see
+    /// [`crash_context::CrashContext::environment`]
+    environment: Option<Arc<[u8]>>,
 }
 
 impl HandlerInner {
+    /// Records the crash in the [`crate::crash_loop::CrashLog`], if any,
+    /// returning true if it exceeds the limit, so that it shouldn't be
+    /// handled. Only the [`EXCEPTION_SIGNALS`] are crashes
+    #[inline]
+    fn over_crash_limit(&self, sig: i32) -> bool {
+        EXCEPTION_SIGNALS.iter().any(|crash| *crash as i32 == sig)
+            && self.crash_log.as_ref().is_some_and(|log| !log.record())
+    }
+
+    /// Reads the memory usage of the process, which is all zeroes if the
+    /// files couldn't be opened
+    #[inline]
+    fn memory_stats(&self) -> crash_context::MemoryStats {
+        self.memory_files
+            .as_ref()
+            .map(|files| files.read())
+            .unwrap_or_default()
+    }
+
+    /// Retrieves the address and length of the captured environment, or
+    /// zeroes if it wasn't captured
     #[inline]
-    pub(super) fn new(handler: Box<dyn crate::CrashEvent>) -> Self {
-        Self { handler }
+    fn environment_location(&self) -> (usize, usize) {
+        self.environment
+            .as_ref()
+            .map_or((0, 0), |env| (env.as_ptr() as usize, env.len()))
+    }
+
+    /// Retrieves the handler that was installed for the specified signal
+    /// before we installed our own, as long as it was an actual function
+    /// rather than the default or ignore disposition
+    #[inline]
+    fn previous_handler(&self, sig: i32) -> Option<libc::sigaction> {
+        let previous = (*self.old_handlers.get(sig as usize)?)?;
+
+        (previous.sa_sigaction != libc::SIG_DFL && previous.sa_sigaction != libc::SIG_IGN)
+            .then_some(previous)
+    }
+
+    /// Writes the crash to the [`crate::fallback::FallbackFile`], if any,
+    /// then invokes the events, on the callback thread if there is one, then
+    /// waits for the crash to be released if the callback held it
+    #[inline]
+    fn invoke(&self, cc: &crash_context::CrashContext) -> crate::CrashEventResult {
+        if let Some(fallback) = &self.fallback_file {
+            let signo = cc.siginfo.ssi_signo as i32;
+            if EXCEPTION_SIGNALS.iter().any(|crash| *crash as i32 == signo) {
+                let _res = fallback.write(cc);
+            }
+        }
+
+        let holdable = super::hold::Holdable::enter();
+        let result =
+            super::dispatch::run(&self.events, cc).unwrap_or_else(|| self.events.on_crash(cc));
+        match holdable {
+            Some(holdable) => holdable.wait(result),
+            None => result,
+        }
     }
 
     pub(super) unsafe fn handle_signal(
         &self,
-        _sig: libc::c_int,
+        sig: libc::c_int,
         info: &mut libc::siginfo_t,
         uc: &mut libc::c_void,
     ) -> crate::CrashEventResult {
@@ -416,34 +1173,85 @@ impl HandlerInner {
         let mut crash_ctx = CRASH_CONTEXT.lock();
 
         {
-            *crash_ctx = mem::MaybeUninit::zeroed();
-            let mut cc = &mut *crash_ctx.as_mut_ptr();
+            // Zeroed in place, as a zeroed temporary would take up room on the
+            // alternate stack, which a crash within the user's handler needs
+            // to still be able to run on
+            ptr::write_bytes(crash_ctx.as_mut_ptr(), 0, 1);
+            let cc = &mut *crash_ctx.as_mut_ptr();
 
             ptr::copy_nonoverlapping(nix_info, &mut cc.siginfo, 1);
 
-            let uc_ptr = &*(uc as *const libc::c_void).cast::<crash_context::ucontext_t>();
-            ptr::copy_nonoverlapping(uc_ptr, &mut cc.context, 1);
-
-            cfg_if::cfg_if! {
-                if #[cfg(target_arch = "aarch64")] {
-                    let fp_ptr = uc_ptr.uc_mcontext.__reserved.as_ptr().cast::<crash_context::fpsimd_context>();
-
-                    if (*fp_ptr).head.magic == crash_context::FPSIMD_MAGIC {
-                        ptr::copy_nonoverlapping(fp_ptr, &mut cc.float_state, 1);
-                    }
-                } else if #[cfg(not(target_arch = "arm"))] {
-                    if !uc_ptr.uc_mcontext.fpregs.is_null() {
-                        ptr::copy_nonoverlapping(uc_ptr.uc_mcontext.fpregs, ((&mut cc.float_state) as *mut crash_context::fpregset_t).cast(), 1);
+            // The fault address is at a different offset in the siginfo_t, and
+            // is only valid for signals raised by the kernel
+            if info.si_code > 0
+                && matches!(
+                    sig,
+                    libc::SIGSEGV | libc::SIGBUS | libc::SIGILL | libc::SIGFPE | libc::SIGTRAP
+                )
+            {
+                cc.siginfo.ssi_addr = info.si_addr() as u64;
+            }
 
-                    }
+            // Likewise the syscall a seccomp filter forbade is at a different
+            // offset, and libc doesn't expose it
+            if sig == libc::SIGSYS && info.si_code == crash_context::SYS_SECCOMP {
+                #[repr(C)]
+                struct SigsysInfo {
+                    si_signo: i32,
+                    si_errno: i32,
+                    si_code: i32,
+                    call_addr: *mut libc::c_void,
+                    syscall: i32,
+                    arch: u32,
                 }
+
+                let sigsys = &*(info as *const libc::siginfo_t).cast::<SigsysInfo>();
+                cc.siginfo.ssi_call_addr = sigsys.call_addr as u64;
+                cc.siginfo.ssi_syscall = sigsys.syscall;
+                cc.siginfo.ssi_arch = sigsys.arch;
             }
 
+            let uc_ptr = &*(uc as *const libc::c_void).cast::<crash_context::ucontext_t>();
+            ptr::copy_nonoverlapping(uc_ptr, &mut cc.context, 1);
+
+            // The fpregs pointer, if any, points into the signal frame, which
+            // is still valid while we are handling the signal
+            cc.capture_float_state();
+
             cc.pid = std::process::id() as i32;
             cc.tid = libc::syscall(libc::SYS_gettid) as i32;
+            cc.capture_thread_name();
+            cc.capture_time();
+            (cc.process_start, cc.attach_time) = (self.process_start, self.attached);
+            cc.cpu = self.cpu;
+            cc.memory = self.memory_stats();
+            (cc.environment, cc.environment_len) = self.environment_location();
+            if sig == libc::SIGABRT {
+                super::abort_message::capture(&mut cc.abort_message);
+                if super::alloc_failure::capture(cc.tid, &mut cc.abort_message) {
+                    cc.reason = crash_context::CrashReason::AllocationFailure;
+                }
+            }
+            (cc.annotations, cc.annotation_count) = crate::annotations::location();
+            (cc.breadcrumbs, cc.breadcrumb_count) = crate::breadcrumbs::location();
+            (cc.memory_regions, cc.memory_region_count) = crate::memory_regions::location();
+            (cc.crashed_threads, cc.crashed_thread_count) = super::crashed_threads::location();
+
+            // Note we use the si_addr from the original siginfo rather than the
+            // signalfd_siginfo, as the layouts of the two differ
+            if sig == libc::SIGSEGV
+                && super::stack::is_stack_overflow(
+                    info.si_addr() as usize,
+                    super::stack::stack_pointer(uc_ptr),
+                )
+            {
+                cc.reason = crash_context::CrashReason::StackOverflow;
+            } else if let Some(reason) = super::trap::classify(sig, info.si_code, uc_ptr) {
+                cc.reason = reason;
+            }
         }
 
-        self.handler.on_crash(&*crash_ctx.as_ptr())
+        self.invoke(&*crash_ctx.as_ptr())
     }
 }
 
@@ -478,3 +1286,57 @@ impl Drop for SetDumpable {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn requeues_original_siginfo() {
+        /// The start of the `sigfault` member of the siginfo, which libc
+        /// only exposes a getter for
+        #[repr(C)]
+        struct FaultInfo {
+            si_signo: i32,
+            si_errno: i32,
+            si_code: i32,
+            si_addr: *mut libc::c_void,
+        }
+
+        /// `SEGV_MAPERR`, which libc doesn't define
+        const SEGV_MAPERR: i32 = 1;
+
+        let sig = libc::SIGUSR2;
+
+        // SAFETY: syscalls, the signal is blocked on this thread while it is
+        // pending, so it is only ever received by `sigtimedwait`
+        unsafe {
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, sig);
+            let mut old_set: libc::sigset_t = std::mem::zeroed();
+            libc::pthread_sigmask(libc::SIG_BLOCK, &set, &mut old_set);
+
+            let mut info: libc::siginfo_t = std::mem::zeroed();
+            {
+                let fault = &mut *(&mut info as *mut libc::siginfo_t).cast::<FaultInfo>();
+                fault.si_signo = sig;
+                fault.si_code = SEGV_MAPERR;
+                fault.si_addr = 0x1234 as *mut _;
+            }
+
+            assert!(super::requeue_signal(sig, &info));
+
+            let mut received: libc::siginfo_t = std::mem::zeroed();
+            let timeout = libc::timespec {
+                tv_sec: 1,
+                tv_nsec: 0,
+            };
+            assert_eq!(libc::sigtimedwait(&set, &mut received, &timeout), sig);
+
+            libc::pthread_sigmask(libc::SIG_SETMASK, &old_set, std::ptr::null_mut());
+
+            // Rather than the SI_TKILL that tgkill would have sent
+            assert_eq!(received.si_code, SEGV_MAPERR);
+            assert_eq!(received.si_addr() as usize, 0x1234);
+        }
+    }
+}
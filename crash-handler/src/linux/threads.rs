@@ -0,0 +1,401 @@
+//! Suspension of every other thread in the process, and capture of their
+//! contexts.
+//!
+//! The registers of a thread can only be retrieved by the thread itself or
+//! via `ptrace`, which a process can't use on its own threads. So an
+//! in-process dump only contains the crashing thread unless the other threads
+//! are asked to report their own contexts, which [`suspend_and_capture`] does
+//! by sending each of them a realtime signal, see [`suspend_signal`]. Each
+//! thread's handler copies its context into a slot that was allocated up
+//! front, then parks until the returned [`SuspendedThreads`] is dropped.
+//!
+//! ```no_run
+//! use crash_handler::{make_crash_event, threads, CrashEventResult, CrashHandler};
+//!
+//! let handler = CrashHandler::attach(unsafe {
+//!     make_crash_event(move |cc| {
+//!         if let Some(suspended) = threads::suspend_and_capture() {
+//!             for thread in suspended.iter() {
+//!                 // ...write the thread's context and stack...
+//!             }
+//!         }
+//!
+//!         CrashEventResult::Handled { exit: None }
+//!     })
+//! });
+//! ```
+//!
+//! Threads that have the signal blocked, or are blocked in the kernel in a way
+//! that prevents signal delivery, don't respond, and are instead reported by
+//! [`SuspendedThreads::unresponsive`] once [`CAPTURE_TIMEOUT`] has elapsed.
+
+use crash_context::CrashContext;
+use std::{
+    cell::UnsafeCell,
+    mem,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering},
+    time::Duration,
+};
+
+/// The maximum number of threads that are suspended, any more are left
+/// running and are not captured
+pub const MAX_THREADS: usize = 256;
+
+/// How long to wait for every thread to respond to the signal
+pub const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long to wait for resumed threads to leave the signal handler
+const RESUME_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The slot is not in use
+const EMPTY: u32 = 0;
+/// The thread has been sent the signal, but has not captured its context yet
+const SIGNALED: u32 = 1;
+/// The thread has captured its context, and is parked
+const CAPTURED: u32 = 2;
+
+struct Slot {
+    tid: AtomicI32,
+    state: AtomicU32,
+    context: UnsafeCell<mem::MaybeUninit<CrashContext>>,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Self {
+            tid: AtomicI32::new(0),
+            state: AtomicU32::new(EMPTY),
+            context: UnsafeCell::new(mem::MaybeUninit::uninit()),
+        }
+    }
+}
+
+/// The slots are in static memory as contexts are too large to be put on
+/// a signal stack, and can't be allocated while handling a crash
+struct Slots([Slot; MAX_THREADS]);
+
+// SAFETY: A slot's context is only written by the thread whose tid is in the
+// slot while the slot is `SIGNALED`, and only read once it is `CAPTURED`
+unsafe impl Sync for Slots {}
+
+static SLOTS: Slots = Slots([const { Slot::new() }; MAX_THREADS]);
+/// Set while a [`SuspendedThreads`] exists, as there is only one set of slots
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// The futex that parked threads wait on, which is set to 1 to resume them
+static RESUME: AtomicU32 = AtomicU32::new(0);
+/// The handler is installed the first time it's needed and never removed, as
+/// the default action for realtime signals is to terminate the process, so a
+/// thread that only receives the signal after the timeout must still find
+/// our handler
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// The realtime signal sent to threads to capture their context, which is
+/// `SIGRTMIN + 4`, after the signals reserved by Bionic's `debuggerd`
+#[inline]
+pub fn suspend_signal() -> libc::c_int {
+    libc::SIGRTMIN() + 4
+}
+
+/// The threads suspended by [`suspend_and_capture`], which are resumed when
+/// this is dropped
+pub struct SuspendedThreads {
+    count: usize,
+}
+
+impl SuspendedThreads {
+    /// The contexts of the threads that were captured, which exclude the
+    /// thread that called [`suspend_and_capture`]
+    pub fn iter(&self) -> impl Iterator<Item = &CrashContext> {
+        SLOTS.0[..self.count].iter().filter_map(|slot| {
+            if slot.state.load(Ordering::Acquire) != CAPTURED {
+                return None;
+            }
+
+            // SAFETY: the context was fully written before the slot became
+            // captured, and is not written again until we are dropped
+            Some(unsafe { &*(*slot.context.get()).as_ptr() })
+        })
+    }
+
+    /// The ids of threads that were sent the signal, but didn't capture their
+    /// context within [`CAPTURE_TIMEOUT`], and may still be running
+    pub fn unresponsive(&self) -> impl Iterator<Item = libc::pid_t> + '_ {
+        SLOTS.0[..self.count]
+            .iter()
+            .filter(|slot| slot.state.load(Ordering::Acquire) == SIGNALED)
+            .map(|slot| slot.tid.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for SuspendedThreads {
+    fn drop(&mut self) {
+        RESUME.store(1, Ordering::Release);
+        // SAFETY: syscall
+        unsafe {
+            libc::syscall(
+                libc::SYS_futex,
+                RESUME.as_ptr(),
+                libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                i32::MAX,
+            );
+        }
+
+        // Wait for the resumed threads to leave the handler before the slots
+        // can be reused
+        wait_until(RESUME_TIMEOUT, || {
+            SLOTS.0[..self.count]
+                .iter()
+                .all(|slot| slot.state.load(Ordering::Acquire) != CAPTURED)
+        });
+
+        // Threads that never responded may still do so, but will find their
+        // slot is no longer signaled and return immediately
+        for slot in &SLOTS.0[..self.count] {
+            slot.tid.store(0, Ordering::Relaxed);
+            slot.state.store(EMPTY, Ordering::Release);
+        }
+
+        ACTIVE.store(false, Ordering::Release);
+    }
+}
+
+/// Suspends every other thread in the process and captures their contexts,
+/// keeping them suspended until the returned [`SuspendedThreads`] is dropped.
+///
+/// This only uses async signal safe operations, and can thus be called from
+/// within a [`crate::CrashEvent`]. It returns `None` if another
+/// [`SuspendedThreads`] already exists, or if the threads of the process
+/// couldn't be enumerated.
+///
+/// Note that any thread that holds a lock, eg. the allocator's, when it is
+/// suspended holds it until it is resumed, so the caller should be as careful
+/// while the threads are suspended as it would be in a signal handler.
+pub fn suspend_and_capture() -> Option<SuspendedThreads> {
+    if ACTIVE.swap(true, Ordering::AcqRel) {
+        return None;
+    }
+
+    if !install_handler() {
+        ACTIVE.store(false, Ordering::Release);
+        return None;
+    }
+
+    RESUME.store(0, Ordering::Release);
+
+    // SAFETY: syscalls
+    let (pid, current) = unsafe { (libc::getpid(), libc::syscall(libc::SYS_gettid) as i32) };
+    let sig = suspend_signal();
+    let mut count = 0;
+
+    let enumerated = for_each_thread(|tid| {
+        if tid == current {
+            return true;
+        }
+
+        let slot = &SLOTS.0[count];
+        slot.tid.store(tid, Ordering::Relaxed);
+        slot.state.store(SIGNALED, Ordering::Release);
+
+        // SAFETY: syscall, the thread may have exited since it was enumerated,
+        // in which case this fails
+        if unsafe { libc::syscall(libc::SYS_tgkill, pid, tid, sig) } == 0 {
+            count += 1;
+        } else {
+            slot.state.store(EMPTY, Ordering::Release);
+        }
+
+        count < MAX_THREADS
+    });
+
+    // Dropping this if enumeration failed resumes any threads that were
+    // already signaled
+    let suspended = SuspendedThreads { count };
+
+    if !enumerated {
+        return None;
+    }
+
+    wait_until(CAPTURE_TIMEOUT, || {
+        SLOTS.0[..count]
+            .iter()
+            .all(|slot| slot.state.load(Ordering::Acquire) != SIGNALED)
+    });
+
+    Some(suspended)
+}
+
+fn install_handler() -> bool {
+    if INSTALLED.load(Ordering::Acquire) {
+        return true;
+    }
+
+    // SAFETY: syscalls
+    unsafe {
+        let mut sa: libc::sigaction = mem::zeroed();
+        libc::sigemptyset(&mut sa.sa_mask);
+        sa.sa_sigaction = suspend_handler as *const () as usize;
+        sa.sa_flags = libc::SA_ONSTACK | libc::SA_SIGINFO | libc::SA_RESTART;
+
+        if libc::sigaction(suspend_signal(), &sa, std::ptr::null_mut()) == -1 {
+            return false;
+        }
+    }
+
+    INSTALLED.store(true, Ordering::Release);
+    true
+}
+
+unsafe extern "C" fn suspend_handler(
+    _sig: libc::c_int,
+    info: *mut libc::siginfo_t,
+    uc: *mut libc::c_void,
+) {
+    let tid = libc::syscall(libc::SYS_gettid) as i32;
+
+    let Some(slot) = SLOTS.0.iter().find(|slot| {
+        slot.tid.load(Ordering::Relaxed) == tid && slot.state.load(Ordering::Acquire) == SIGNALED
+    }) else {
+        return;
+    };
+
+    // The futex syscall can clobber errno, which the interrupted code may be
+    // about to read
+    let errno = errno();
+    let saved_errno = *errno;
+
+    {
+        let cc = &mut *(*slot.context.get()).as_mut_ptr();
+        *cc = mem::zeroed();
+
+        std::ptr::copy_nonoverlapping(
+            info.cast::<libc::signalfd_siginfo>().cast_const(),
+            &mut cc.siginfo,
+            1,
+        );
+        std::ptr::copy_nonoverlapping(
+            uc.cast::<crash_context::ucontext_t>().cast_const(),
+            &mut cc.context,
+            1,
+        );
+
+        // The fpregs pointer, if any, points into the signal frame, which is
+        // still valid while we are handling the signal
+        cc.capture_float_state();
+
+        cc.pid = libc::getpid();
+        cc.tid = tid;
+        cc.capture_thread_name();
+        cc.capture_time();
+        (cc.annotations, cc.annotation_count) = crate::annotations::location();
+        (cc.breadcrumbs, cc.breadcrumb_count) = crate::breadcrumbs::location();
+        (cc.memory_regions, cc.memory_region_count) = crate::memory_regions::location();
+    }
+
+    slot.state.store(CAPTURED, Ordering::Release);
+
+    while RESUME.load(Ordering::Acquire) == 0 {
+        libc::syscall(
+            libc::SYS_futex,
+            RESUME.as_ptr(),
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            0,
+            std::ptr::null::<libc::timespec>(),
+        );
+    }
+
+    slot.state.store(EMPTY, Ordering::Release);
+    *errno = saved_errno;
+}
+
+#[inline]
+unsafe fn errno() -> *mut libc::c_int {
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "android")] {
+            libc::__errno()
+        } else {
+            libc::__errno_location()
+        }
+    }
+}
+
+/// Polls the condition until it is true or the timeout elapses
+fn wait_until(timeout: Duration, mut cond: impl FnMut() -> bool) {
+    // Instant is not guaranteed to be async signal safe, but nanosleep is, so
+    // we count sleeps instead
+    const INTERVAL: Duration = Duration::from_millis(1);
+
+    let interval = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: INTERVAL.as_nanos() as _,
+    };
+
+    for _ in 0..timeout.as_millis() / INTERVAL.as_millis() {
+        if cond() {
+            return;
+        }
+
+        // SAFETY: syscall
+        unsafe {
+            libc::nanosleep(&interval, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Invokes the callback with the id of every thread in the process, until it
+/// returns false, returning false if the threads couldn't be enumerated.
+///
+/// This reads `/proc/self/task` with `getdents64`, as `readdir` allocates.
+fn for_each_thread(mut cb: impl FnMut(libc::pid_t) -> bool) -> bool {
+    // SAFETY: syscall, the path is nul terminated
+    let fd = unsafe {
+        libc::open(
+            c"/proc/self/task".as_ptr(),
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC,
+        )
+    };
+    if fd == -1 {
+        return false;
+    }
+
+    // The offset of d_name in linux_dirent64, after d_ino, d_off, d_reclen,
+    // and d_type
+    const NAME_OFFSET: usize = 19;
+
+    let mut buf = [0u8; 4096];
+
+    'read: loop {
+        // SAFETY: syscall, the buffer is the size we specify
+        let read = unsafe { libc::syscall(libc::SYS_getdents64, fd, buf.as_mut_ptr(), buf.len()) };
+        if read <= 0 {
+            break;
+        }
+
+        let mut offset = 0;
+        while offset + NAME_OFFSET <= read as usize {
+            let reclen = u16::from_ne_bytes([buf[offset + 16], buf[offset + 17]]) as usize;
+            if reclen == 0 {
+                break;
+            }
+
+            let name = &buf[offset + NAME_OFFSET..offset + reclen];
+            let name = &name[..name.iter().position(|b| *b == 0).unwrap_or(name.len())];
+            offset += reclen;
+
+            // Skip "." and ".."
+            let Some(tid) = std::str::from_utf8(name).ok().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+
+            if !cb(tid) {
+                break 'read;
+            }
+        }
+    }
+
+    // SAFETY: syscall
+    unsafe {
+        libc::close(fd);
+    }
+
+    true
+}
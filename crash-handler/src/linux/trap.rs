@@ -0,0 +1,161 @@
+//! Async signal safe classification of traps.
+//!
+//! A `SIGTRAP` can be raised for a variety of reasons, eg. a breakpoint
+//! instruction, a hardware watchpoint, single stepping, or simply by another
+//! process sending it. Similarly, a `SIGILL` on x86 is often not an actual
+//! invalid instruction, but a `ud2` emitted by `__builtin_trap`. We distinguish
+//! these via the signal code and by inspecting the instruction that raised the
+//! signal.
+
+use crash_context::CrashReason;
+
+/// Determines if the signal was caused by a deliberate trap, returning the
+/// [`CrashReason`] that describes it
+///
+/// # Safety
+///
+/// Performs syscalls
+pub(super) unsafe fn classify(
+    sig: libc::c_int,
+    code: libc::c_int,
+    uc: &crash_context::ucontext_t,
+) -> Option<CrashReason> {
+    // Signals sent by a process, including ourselves via eg. `raise`, have a
+    // code <= 0, and are never the result of an instruction
+    if code <= 0 {
+        return None;
+    }
+
+    let ip = instruction_pointer(uc);
+
+    match sig {
+        libc::SIGTRAP if code == libc::TRAP_HWBKPT => Some(CrashReason::HardwareBreakpoint),
+        libc::SIGTRAP => classify_breakpoint(ip),
+        libc::SIGILL => classify_illegal(ip),
+        _ => None,
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_arch = "x86_64", target_arch = "x86"))] {
+        /// `int3` leaves the instruction pointer after the instruction
+        unsafe fn classify_breakpoint(ip: usize) -> Option<CrashReason> {
+            let mut insn = [0u8; 2];
+
+            // int3
+            if read_code(ip.checked_sub(1)?, &mut insn[..1]) && insn[0] == 0xcc {
+                return Some(CrashReason::Breakpoint);
+            }
+
+            // int 3
+            (read_code(ip.checked_sub(2)?, &mut insn) && insn == [0xcd, 0x03])
+                .then_some(CrashReason::Breakpoint)
+        }
+
+        unsafe fn classify_illegal(ip: usize) -> Option<CrashReason> {
+            let mut insn = [0u8; 2];
+            // ud2
+            (read_code(ip, &mut insn) && insn == [0x0f, 0x0b]).then_some(CrashReason::Trap)
+        }
+    } else if #[cfg(target_arch = "aarch64")] {
+        /// The immediate used by `brk` for `__builtin_trap`, as opposed to
+        /// breakpoints which typically use 0 or `0xf000`
+        const BRK_TRAP: u32 = 0x3e8;
+
+        unsafe fn classify_breakpoint(ip: usize) -> Option<CrashReason> {
+            let insn = read_insn(ip)?;
+            // brk #imm
+            if insn & 0xffe0_001f != 0xd420_0000 {
+                return None;
+            }
+
+            Some(if (insn >> 5) & 0xffff == BRK_TRAP {
+                CrashReason::Trap
+            } else {
+                CrashReason::Breakpoint
+            })
+        }
+
+        unsafe fn classify_illegal(_ip: usize) -> Option<CrashReason> {
+            None
+        }
+    } else if #[cfg(target_arch = "arm")] {
+        unsafe fn classify_breakpoint(ip: usize) -> Option<CrashReason> {
+            let insn = read_insn(ip)?;
+            // The undefined instruction the kernel uses for breakpoints, or bkpt #imm
+            (insn == 0xe7f0_01f0 || insn & 0x0ff0_00f0 == 0x0120_0070)
+                .then_some(CrashReason::Breakpoint)
+        }
+
+        unsafe fn classify_illegal(ip: usize) -> Option<CrashReason> {
+            // udf #0xfe, as emitted for __builtin_trap
+            (read_insn(ip)? == 0xe7ff_defe).then_some(CrashReason::Trap)
+        }
+    } else if #[cfg(target_arch = "riscv64")] {
+        /// `ebreak` leaves the pc at the instruction itself, which may be
+        /// either the compressed or full size encoding
+        unsafe fn classify_breakpoint(ip: usize) -> Option<CrashReason> {
+            // c.ebreak, or ebreak
+            (read_parcel(ip)? == 0x9002 || read_insn(ip)? == 0x0010_0073)
+                .then_some(CrashReason::Breakpoint)
+        }
+
+        unsafe fn classify_illegal(ip: usize) -> Option<CrashReason> {
+            // unimp, as emitted for __builtin_trap, which is c.unimp, ie. all
+            // zeroes, when compressed instructions are enabled, and a write
+            // to the read only cycle CSR otherwise
+            (read_parcel(ip)? == 0 || read_insn(ip)? == 0xc000_1073)
+                .then_some(CrashReason::Trap)
+        }
+
+        /// Reads the 16-bit parcel at the specified address, which is the
+        /// size of a compressed instruction
+        #[inline]
+        unsafe fn read_parcel(ip: usize) -> Option<u16> {
+            let mut insn = [0u8; 2];
+            read_code(ip, &mut insn).then(|| u16::from_le_bytes(insn))
+        }
+    }
+}
+
+/// Reads the 32-bit instruction at the specified address
+#[cfg(any(target_arch = "aarch64", target_arch = "arm", target_arch = "riscv64"))]
+#[inline]
+unsafe fn read_insn(ip: usize) -> Option<u32> {
+    let mut insn = [0u8; 4];
+    read_code(ip, &mut insn).then(|| u32::from_le_bytes(insn))
+}
+
+/// Reads code from our own process without risking a fault, in case the
+/// instruction pointer is not actually pointing to mapped memory
+unsafe fn read_code(addr: usize, buf: &mut [u8]) -> bool {
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: addr as *mut _,
+        iov_len: buf.len(),
+    };
+
+    libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) == buf.len() as isize
+}
+
+/// Retrieves the instruction pointer from the thread context
+#[inline]
+pub(super) fn instruction_pointer(uc: &crash_context::ucontext_t) -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "x86_64")] {
+            uc.uc_mcontext.gregs[libc::REG_RIP as usize] as usize
+        } else if #[cfg(target_arch = "x86")] {
+            uc.uc_mcontext.gregs[libc::REG_EIP as usize] as usize
+        } else if #[cfg(target_arch = "aarch64")] {
+            uc.uc_mcontext.pc as usize
+        } else if #[cfg(target_arch = "arm")] {
+            uc.uc_mcontext.arm_pc as usize
+        } else if #[cfg(target_arch = "riscv64")] {
+            // REG_PC, which libc only defines for glibc
+            uc.uc_mcontext.__gregs[0] as usize
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Terminates the process if the user's callback takes too long to handle a
+//! signal, see [`super::CrashHandlerBuilder::callback_timeout`].
+//!
+//! The timer is a thread spawned when the handler is attached rather than eg.
+//! `SIGALRM`, as the alarm could be delivered to the very thread that is stuck
+//! in the callback, and might already be used by the application. The signal
+//! handler communicates with the thread via a pipe, as `write` is async signal
+//! safe, sending the `siginfo_t` of the signal being handled along with it, so
+//! that the signal can be raised again exactly as it was received.
+
+use super::state;
+use crate::Error;
+use std::{
+    io,
+    sync::atomic::{AtomicI32, Ordering},
+    time::{Duration, Instant},
+};
+
+/// The write end of the pipe the watchdog thread reads from, or -1 if there
+/// is no watchdog
+static ARM_FD: AtomicI32 = AtomicI32::new(-1);
+/// The read end of the pipe, which is owned by the watchdog thread, but is
+/// kept here so that it can be closed in the child after a `fork`, where the
+/// thread no longer exists
+static WATCH_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Sent when the callback has returned, any other value is the signal being
+/// handled
+const DISARM: i32 = 0;
+
+/// A message sent to the watchdog, which is smaller than `PIPE_BUF`, so that
+/// it can't be interleaved with one sent by another thread
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Message {
+    sig: i32,
+    info: libc::siginfo_t,
+}
+
+const MESSAGE_SIZE: usize = std::mem::size_of::<Message>();
+
+/// How long we wait for the re-raised signal to terminate the process before
+/// exiting ourselves
+const RERAISE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Starts the watchdog thread, which is stopped by [`stop`]
+pub(super) fn start(timeout: Duration) -> Result<(), Error> {
+    let mut fds = [-1; 2];
+    // SAFETY: syscall
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+        return Err(Error::Io(io::Error::last_os_error()));
+    }
+
+    let [read_fd, write_fd] = fds;
+
+    if let Err(err) = std::thread::Builder::new()
+        .name("crash-watchdog".to_owned())
+        .spawn(move || watch(read_fd, timeout))
+    {
+        // SAFETY: syscalls, the thread was never started so we still own both
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+        return Err(err.into());
+    }
+
+    WATCH_FD.store(read_fd, Ordering::Release);
+    ARM_FD.store(write_fd, Ordering::Release);
+    Ok(())
+}
+
+/// Returns true if the watchdog thread is running
+#[inline]
+pub(super) fn is_running() -> bool {
+    ARM_FD.load(Ordering::Acquire) != -1
+}
+
+/// Stops the watchdog thread, if any, by closing the write end of the pipe
+pub(super) fn stop() {
+    // The thread closes the read end itself once it sees the write end closed
+    WATCH_FD.store(-1, Ordering::Release);
+    let fd = ARM_FD.swap(-1, Ordering::AcqRel);
+    if fd != -1 {
+        // SAFETY: syscall
+        unsafe {
+            libc::close(fd);
+        }
+    }
+}
+
+/// Forgets the watchdog in the child after a `fork`, as only the thread that
+/// forked is copied to the child, closing both ends of the pipe.
+///
+/// This is async signal safe.
+pub(super) fn after_fork() {
+    for fd in [&ARM_FD, &WATCH_FD] {
+        let fd = fd.swap(-1, Ordering::AcqRel);
+        if fd != -1 {
+            // SAFETY: syscall
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+/// Disarms the watchdog when dropped, ie. once the callback has returned
+pub(super) struct Armed {
+    fd: i32,
+}
+
+impl Armed {
+    /// Arms the watchdog, if there is one, before the callback is invoked for
+    /// the specified signal
+    pub(super) fn arm(sig: i32, info: &libc::siginfo_t) -> Option<Self> {
+        let fd = ARM_FD.load(Ordering::Acquire);
+        if fd == -1 {
+            return None;
+        }
+
+        send(fd, &Message { sig, info: *info });
+        Some(Self { fd })
+    }
+}
+
+impl Drop for Armed {
+    fn drop(&mut self) {
+        // SAFETY: all zeroes is a valid siginfo_t
+        let info = unsafe { std::mem::zeroed() };
+        send(self.fd, &Message { sig: DISARM, info });
+    }
+}
+
+/// Writes a message to the watchdog, which is async signal safe
+fn send(fd: i32, message: &Message) {
+    // SAFETY: syscall, the message is plain old data
+    unsafe {
+        libc::write(fd, (message as *const Message).cast(), MESSAGE_SIZE);
+    }
+}
+
+enum Received {
+    Message(Message),
+    TimedOut,
+}
+
+/// Waits for a message until the deadline, if any, returning `None` if the
+/// pipe was closed
+fn receive(fd: i32, deadline: Option<Instant>) -> Option<Received> {
+    loop {
+        let timeout = match deadline {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Some(Received::TimedOut);
+                }
+                // Round up so that we don't spin for the last millisecond
+                (remaining.as_micros().div_ceil(1000)).min(i32::MAX as u128) as i32
+            }
+            None => -1,
+        };
+
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // SAFETY: syscall
+        match unsafe { libc::poll(&mut pfd, 1, timeout) } {
+            0 => continue,
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return None,
+            _ => {}
+        }
+
+        let mut message = std::mem::MaybeUninit::<Message>::uninit();
+        // SAFETY: syscall
+        let read = unsafe { libc::read(fd, message.as_mut_ptr().cast(), MESSAGE_SIZE) };
+        return match read {
+            // SAFETY: the message was written in full, as writes to a pipe
+            // smaller than PIPE_BUF are atomic
+            read if read as usize == MESSAGE_SIZE => {
+                Some(Received::Message(unsafe { message.assume_init() }))
+            }
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            // The write end was closed
+            _ => None,
+        };
+    }
+}
+
+fn watch(fd: i32, timeout: Duration) {
+    // The signal being handled, and when the callback must return by
+    let mut armed: Option<(Message, Instant)> = None;
+    // The number of callbacks in progress, as several threads can crash at
+    // the same time
+    let mut pending = 0usize;
+
+    while let Some(received) = receive(fd, armed.map(|(_, deadline)| deadline)) {
+        match received {
+            Received::TimedOut => {
+                if let Some((message, _)) = armed {
+                    terminate(message.sig, &message.info);
+                }
+            }
+            Received::Message(Message { sig: DISARM, .. }) => {
+                pending = pending.saturating_sub(1);
+                // Any other callback in progress gets a fresh deadline, as
+                // it is most likely waiting for the one that just returned
+                armed = armed
+                    .filter(|_| pending > 0)
+                    .map(|(message, _)| (message, Instant::now() + timeout));
+            }
+            Received::Message(message) => {
+                pending += 1;
+                if armed.is_none() {
+                    armed = Some((message, Instant::now() + timeout));
+                }
+            }
+        }
+    }
+
+    // SAFETY: syscall
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+/// Restores the default handlers and re-raises the signal, so that the
+/// process dies as if our handler was never installed, unless the process is
+/// configured to exit instead
+fn terminate(sig: i32, info: &libc::siginfo_t) -> ! {
+    match state::termination() {
+        super::Termination::Reraise => {}
+        super::Termination::ExitCode => {
+            // SAFETY: syscall
+            unsafe { libc::_exit(state::signal_exit_code(sig)) }
+        }
+        // SAFETY: syscalls
+        super::Termination::CoreDump => unsafe { state::enable_core_dump() },
+    }
+
+    // SAFETY: syscalls
+    unsafe {
+        state::install_default_handlers();
+
+        // The crashing thread has the signal blocked while it is in our
+        // handler, so it is raised on this thread instead, with the original
+        // siginfo so that eg. a core dump still records the fault address
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, sig);
+        libc::pthread_sigmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+
+        state::requeue_signal(sig, info);
+    }
+
+    // Only reached if the signal didn't terminate the process, eg. because
+    // a sandbox prevents us from signaling ourselves
+    std::thread::sleep(RERAISE_TIMEOUT);
+
+    // SAFETY: syscall
+    unsafe { libc::_exit(state::signal_exit_code(sig)) }
+}
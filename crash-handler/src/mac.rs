@@ -53,27 +53,126 @@ pub enum ExceptionType {
     CorpseNotify = 13,
 }
 
+/// Routes a panic through the attached handler, see [`crate::panic`]
+#[cfg(feature = "panic")]
+pub(crate) fn simulate_panic() -> bool {
+    // Panics are reported as an abort, since that is what they would become
+    // if the process was compiled with `panic = "abort"`, which is how they
+    // are reported by the SIGABRT handler
+    state::simulate_exception(Some(crash_context::ExceptionInfo {
+        kind: ffi::et::EXC_SOFTWARE,
+        code: ffi::EXC_SOFT_SIGNAL as u64, // Unix signal
+        subcode: Some(libc::SIGABRT as _),
+    }))
+}
+
 /// A Macos exception handler
-pub struct CrashHandler;
+pub struct CrashHandler {
+    id: crate::events::EventId,
+    /// Cleared when the handler is detached
+    _marker: Option<crate::marker::CrashMarker>,
+}
+
+/// Configures a [`CrashHandler`] before attaching it
+pub struct CrashHandlerBuilder {
+    priority: i32,
+    marker: Option<std::path::PathBuf>,
+}
+
+impl CrashHandlerBuilder {
+    /// Sets the priority of the handler, which determines the order in which
+    /// it is invoked relative to any other attached handlers. Defaults to
+    /// [`crate::DEFAULT_PRIORITY`].
+    ///
+    /// Handlers with a higher priority are invoked first, and handlers with
+    /// the same priority are invoked in the reverse order they were attached
+    /// in. Once a handler returns anything other than
+    /// [`crate::CrashEventResult::Reraise`], no further handlers are invoked.
+    #[inline]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Writes a [`crate::marker::CrashMarker`] to the specified path when the
+    /// handler is attached, which is cleared when the handler is detached, so
+    /// that [`crate::marker::CrashMarker::previous_run`] can detect if the
+    /// process was terminated abnormally, eg. by `SIGKILL` or jetsam, on the
+    /// next run.
+    #[inline]
+    pub fn crash_marker(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.marker = Some(path.into());
+        self
+    }
+
+    /// Attaches the exception handler with the current configuration.
+    ///
+    /// See [`CrashHandler::attach`]
+    pub fn attach(
+        self,
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<CrashHandler, crate::Error> {
+        // Written before attaching so that it is removed again if attaching fails
+        let marker = self
+            .marker
+            .map(crate::marker::CrashMarker::create)
+            .transpose()?;
+
+        let id = state::attach(on_crash, self.priority)?;
+        Ok(CrashHandler {
+            id,
+            _marker: marker,
+        })
+    }
+}
+
+impl Default for CrashHandlerBuilder {
+    fn default() -> Self {
+        Self {
+            priority: crate::DEFAULT_PRIORITY,
+            marker: None,
+        }
+    }
+}
 
 #[allow(clippy::unused_self)]
 impl CrashHandler {
+    /// Creates a builder that can be used to configure the handler before it
+    /// is attached
+    #[inline]
+    pub fn builder() -> CrashHandlerBuilder {
+        CrashHandlerBuilder::default()
+    }
+
     /// Attaches the exception handler.
     ///
     /// The provided callback will be invoked if an exception is caught,
     /// providing a [`crate::CrashContext`] with the details of the thread where
     /// the exception was thrown.
+    ///
+    /// Multiple handlers can be attached at the same time, see
+    /// [`CrashHandlerBuilder::priority`] for the order they are invoked in.
     pub fn attach(on_crash: Box<dyn crate::CrashEvent>) -> Result<Self, crate::Error> {
-        state::attach(on_crash)?;
-        Ok(Self)
+        Self::builder().attach(on_crash)
+    }
+
+    /// Attaches the exception handler with the specified priority.
+    ///
+    /// See [`CrashHandlerBuilder::priority`]
+    pub fn attach_with_priority(
+        priority: i32,
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, crate::Error> {
+        Self::builder().priority(priority).attach(on_crash)
     }
 
-    /// Detaches the handler.
+    /// Detaches the handler, and if it is the last one attached, restores
+    /// the exception ports that were installed before it.
     ///
     /// This is done automatically when [`CrashHandler`] is dropped.
     #[inline]
     pub fn detach(self) {
-        state::detach(false);
+        state::detach(self.id);
     }
 
     // Raises the specified user exception
@@ -85,6 +184,6 @@ impl CrashHandler {
 
 impl Drop for CrashHandler {
     fn drop(&mut self) {
-        state::detach(false);
+        state::detach(self.id);
     }
 }
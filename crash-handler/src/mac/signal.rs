@@ -51,3 +51,93 @@ unsafe extern "C" fn signal_handler(
         subcode: Some(signal as _),
     }));
 }
+
+/// The signals that correspond to the exceptions we handle, which are handled
+/// as a fallback on iOS and tvOS in case the exception was not delivered to us
+#[cfg(any(target_os = "ios", target_os = "tvos"))]
+pub(crate) const FALLBACK_SIGNALS: [libc::c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGTRAP,
+];
+
+/// Installs our handler for each of the [`FALLBACK_SIGNALS`], returning the
+/// previously registered handlers, which should be restored later
+///
+/// # Safety
+///
+/// Performs syscalls
+#[cfg(any(target_os = "ios", target_os = "tvos"))]
+pub(crate) unsafe fn install_fallback_handlers(
+) -> Result<[libc::sigaction; FALLBACK_SIGNALS.len()], std::io::Error> {
+    let mut sa: libc::sigaction = mem::zeroed();
+    libc::sigemptyset(&mut sa.sa_mask);
+    for sig in FALLBACK_SIGNALS {
+        libc::sigaddset(&mut sa.sa_mask, sig);
+    }
+    sa.sa_sigaction = fallback_signal_handler as *const () as usize;
+    // The fallback is mainly for crashes, including stack overflows, so we
+    // need the alternate stack that std installs for every thread it creates
+    sa.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+
+    let mut old_actions = [mem::zeroed::<libc::sigaction>(); FALLBACK_SIGNALS.len()];
+
+    for (i, sig) in FALLBACK_SIGNALS.into_iter().enumerate() {
+        if libc::sigaction(sig, &sa, &mut old_actions[i]) == -1 {
+            let err = std::io::Error::last_os_error();
+            restore_fallback_handlers(&old_actions[..i]);
+            return Err(err);
+        }
+    }
+
+    Ok(old_actions)
+}
+
+/// Restores the actions for each of the [`FALLBACK_SIGNALS`] to the specified
+/// handlers
+///
+/// # Safety
+///
+/// Performs syscalls
+#[cfg(any(target_os = "ios", target_os = "tvos"))]
+pub(crate) unsafe fn restore_fallback_handlers(handlers: &[libc::sigaction]) {
+    for (sig, handler) in FALLBACK_SIGNALS.into_iter().zip(handlers) {
+        libc::sigaction(sig, handler, std::ptr::null_mut());
+    }
+}
+
+/// Our fallback signal handler, transforms the signal into the
+/// [`crash_context::ExceptionInfo`] that would have been raised for it, and
+/// sends it to the thread that handles all exceptions
+#[cfg(any(target_os = "ios", target_os = "tvos"))]
+unsafe extern "C" fn fallback_signal_handler(
+    signal: i32,
+    info: *mut libc::siginfo_t,
+    _uc: *mut std::ffi::c_void,
+) {
+    use super::ffi::et;
+
+    // Restore the default action first, so that when we return and the
+    // faulting instruction is executed again the process is terminated
+    // instead of looping forever, regardless of what the user callback does
+    libc::signal(signal, libc::SIG_DFL);
+
+    let (kind, code, subcode) = match signal {
+        libc::SIGSEGV | libc::SIGBUS => (
+            et::EXC_BAD_ACCESS,
+            libc::KERN_INVALID_ADDRESS as u64,
+            Some((*info).si_addr as u64),
+        ),
+        libc::SIGILL => (et::EXC_BAD_INSTRUCTION, 0, None),
+        libc::SIGFPE => (et::EXC_ARITHMETIC, 0, None),
+        _ => (et::EXC_BREAKPOINT, 0, None),
+    };
+
+    super::state::simulate_exception(Some(crash_context::ExceptionInfo {
+        kind,
+        code,
+        subcode,
+    }));
+}
@@ -1,4 +1,5 @@
 use super::ffi::*;
+use crate::events::{EventId, Events};
 use crate::CrashEventResult;
 use crate::Error;
 use std::mem;
@@ -51,6 +52,7 @@ pub(crate) fn kern_ret(func: impl FnOnce() -> kern_return_t) -> Result<(), Error
     }
 }
 
+#[derive(Copy, Clone)]
 struct PreviousPort {
     /// The exception the port is masking
     mask: et::exception_mask_t,
@@ -62,17 +64,65 @@ struct PreviousPort {
     flavor: ts::thread_state_flavor_t,
 }
 
+#[derive(Copy, Clone)]
 struct PreviousPorts {
     count: usize,
     ports: [PreviousPort; EXC_TYPES_COUNT],
 }
 
+impl PreviousPorts {
+    /// Restores the previous ports as the task exception ports
+    ///
+    /// SAFETY: syscalls
+    unsafe fn restore(&self) -> Result<(), Error> {
+        let current_task = mach_task_self();
+
+        for pp in &self.ports[..self.count] {
+            kern_ret(|| {
+                task_set_exception_ports(current_task, pp.mask, pp.port, pp.behavior, pp.flavor)
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
 type UserSignal = std::sync::Arc<(parking_lot::Mutex<Option<bool>>, parking_lot::Condvar)>;
 
 struct AllocatedPort {
     port: mach_port_t,
 }
 
+impl AllocatedPort {
+    /// Allocates a port with both a receive and send right
+    ///
+    /// SAFETY: syscalls
+    unsafe fn new() -> Result<Self, Error> {
+        let current_task = mach_task_self();
+
+        let mut port = MACH_PORT_NULL;
+
+        // Create a receive right so that we can actually receive exception messages on the port
+        kern_ret(|| {
+            mp::mach_port_allocate(current_task, port::MACH_PORT_RIGHT_RECEIVE, &mut port)
+        })?;
+
+        let port = Self { port };
+
+        // Add send right
+        kern_ret(|| {
+            mp::mach_port_insert_right(
+                current_task,
+                port.port,
+                port.port,
+                msg::MACH_MSG_TYPE_MAKE_SEND,
+            )
+        })?;
+
+        Ok(port)
+    }
+}
+
 impl Drop for AllocatedPort {
     fn drop(&mut self) {
         unsafe {
@@ -82,11 +132,16 @@ impl Drop for AllocatedPort {
 }
 
 pub(super) struct HandlerInner {
-    pub(super) crash_event: Box<dyn crate::CrashEvent>,
+    pub(super) events: Events,
     handler_port: AllocatedPort,
     user_signal: UserSignal,
     handler_thread: std::thread::JoinHandle<()>,
+    /// The port exceptions raised on the handler thread itself are sent to
+    fault_port: AllocatedPort,
+    fault_thread: std::thread::JoinHandle<()>,
     previous_abort_action: libc::sigaction,
+    #[cfg(any(target_os = "ios", target_os = "tvos"))]
+    previous_fallback_actions: [libc::sigaction; super::signal::FALLBACK_SIGNALS.len()],
     previous: PreviousPorts,
 }
 
@@ -96,42 +151,51 @@ impl HandlerInner {
     /// SAFETY: syscalls
     unsafe fn uninstall(&self) -> Result<(), Error> {
         super::signal::restore_abort_handler(self.previous_abort_action);
+        #[cfg(any(target_os = "ios", target_os = "tvos"))]
+        super::signal::restore_fallback_handlers(&self.previous_fallback_actions);
 
-        let current_task = mach_task_self();
-
-        // Restore the previous ports
-        for pp in &self.previous.ports[..self.previous.count] {
-            kern_ret(|| {
-                task_set_exception_ports(current_task, pp.mask, pp.port, pp.behavior, pp.flavor)
-            })?;
-        }
-
-        Ok(())
+        self.previous.restore()
     }
 
     /// SAFETY: syscalls
     unsafe fn shutdown(self, is_handler_thread: bool) -> Result<(), Error> {
         self.uninstall()?;
 
-        let mut exc_msg: UserException = mem::zeroed();
-        exc_msg.header.msgh_id = MessageIds::Shutdown as i32;
+        let shutdown_msg = || {
+            let mut exc_msg: UserException = mem::zeroed();
+            exc_msg.header.msgh_id = MessageIds::Shutdown as i32;
+            exc_msg
+        };
 
-        if self.send_message(exc_msg) {
-            // We don't really care if there was some error in the thread, note
-            // that we check the thread in case we're being uninstalled from
-            // the handler thread itself
-            if !is_handler_thread {
-                let _res = self.handler_thread.join();
-            }
+        let fault_shutdown = self.send_message_to(self.fault_port.port, shutdown_msg());
+        let handler_shutdown = self.send_message(shutdown_msg());
+
+        // The fault thread never uninstalls the handler, so it is always safe
+        // to wait on it
+        if fault_shutdown {
+            let _res = self.fault_thread.join();
+        }
+
+        // We don't really care if there was some error in the thread, note
+        // that we check the thread in case we're being uninstalled from
+        // the handler thread itself
+        if handler_shutdown && !is_handler_thread {
+            let _res = self.handler_thread.join();
         }
 
         Ok(())
     }
 
     /// SAFETY: syscalls
-    unsafe fn send_message(&self, mut msg: UserException) -> bool {
+    #[inline]
+    unsafe fn send_message(&self, msg: UserException) -> bool {
+        self.send_message_to(self.handler_port.port, msg)
+    }
+
+    /// SAFETY: syscalls
+    unsafe fn send_message_to(&self, port: mach_port_t, mut msg: UserException) -> bool {
         msg.header.msgh_size = mem::size_of_val(&msg) as u32;
-        msg.header.msgh_remote_port = self.handler_port.port;
+        msg.header.msgh_remote_port = port;
 
         // Reset the condition variable in case a user signal was already raised
         {
@@ -176,47 +240,43 @@ static HANDLER_THREAD: parking_lot::Mutex<Option<mach_port_t>> = parking_lot::co
 /// are sent to it, as well as a signal handler for `SIGABRT` as it is not an
 /// exception on macos.
 ///
-/// This spawns a message loop thread that waits on messages to the exception port.
+/// This spawns a message loop thread that waits on messages to the exception port,
+/// as well as a thread that handles exceptions raised by the message loop thread
+/// itself, eg. if the user callback crashes, as those would otherwise be sent to
+/// the exception port that the message loop thread is servicing.
+///
+/// On iOS and tvOS, handlers are also installed for the signals that correspond
+/// to the exceptions we handle, as other crash reporters commonly replace the
+/// task exception ports after we've installed ours.
+///
+/// If a handler is already installed, the event is just added to it.
 ///
 /// # Errors
 ///
-/// - A handler has already been installed, we only allow one
 /// - Any of the various syscalls that are made fail
-pub(super) fn attach(crash_event: Box<dyn crate::CrashEvent>) -> Result<(), Error> {
+pub(super) fn attach(
+    crash_event: Box<dyn crate::CrashEvent>,
+    priority: i32,
+) -> Result<EventId, Error> {
     let mut lock = HANDLER.write();
 
-    if lock.is_some() {
-        return Err(Error::HandlerAlreadyInstalled);
+    if let Some(handler) = &mut *lock {
+        return Ok(handler.events.insert(crash_event, priority));
     }
 
+    let mut events = Events::default();
+    let id = events.insert(crash_event, priority);
+
     // SAFETY: this is basically just a lot of syscalls we're doing
     unsafe {
         let current_task = mach_task_self();
 
-        let mut handler_port = MACH_PORT_NULL;
-
-        // Create a receive right so that we can actually receive exception messages on the port
-        kern_ret(|| {
-            mp::mach_port_allocate(
-                current_task,
-                port::MACH_PORT_RIGHT_RECEIVE,
-                &mut handler_port,
-            )
-        })?;
-
-        let handler_port = AllocatedPort { port: handler_port };
-
-        // Add send right
-        kern_ret(|| {
-            mp::mach_port_insert_right(
-                current_task,
-                handler_port.port,
-                handler_port.port,
-                msg::MACH_MSG_TYPE_MAKE_SEND,
-            )
-        })?;
+        let handler_port = AllocatedPort::new()?;
+        let fault_port = AllocatedPort::new()?;
 
         let previous_abort_action = super::signal::install_abort_handler()?;
+        #[cfg(any(target_os = "ios", target_os = "tvos"))]
+        let previous_fallback_actions = super::signal::install_fallback_handlers()?;
 
         let mut count = EXC_TYPES_COUNT as u32;
         let mut masks = [0; EXC_TYPES_COUNT];
@@ -271,11 +331,31 @@ pub(super) fn attach(crash_event: Box<dyn crate::CrashEvent>) -> Result<(), Erro
         let us = user_signal.clone();
 
         let port = handler_port.port;
+        let fport = fault_port.port;
+
+        // Spawn the thread that handles exceptions raised on the handler thread,
+        // before the handler thread itself so that it is ready by the time the
+        // user callback could possibly be invoked
+        let fault_thread = std::thread::spawn(move || {
+            fault_handler(fport, previous);
+        });
 
         // Spawn a thread that will handle the actual exception/user messages sent
         // to the exception port we've just created
         let handler_thread = std::thread::spawn(move || {
-            *HANDLER_THREAD.lock() = Some(mach_thread_self());
+            let this_thread = mach_thread_self();
+            *HANDLER_THREAD.lock() = Some(this_thread);
+
+            // Exceptions on this thread are sent to the fault thread rather than
+            // the task exception port, which this thread would never service
+            // as it would be blocked waiting for the exception to be handled
+            mach2::thread_act::thread_set_exception_ports(
+                this_thread,
+                EXCEPTION_MASK,
+                fport,
+                behavior as _,
+                THREAD_STATE_NONE,
+            );
 
             exception_handler(port, us);
 
@@ -283,25 +363,56 @@ pub(super) fn attach(crash_event: Box<dyn crate::CrashEvent>) -> Result<(), Erro
         });
 
         *lock = Some(HandlerInner {
-            crash_event,
+            events,
             handler_port,
             user_signal,
             handler_thread,
+            fault_port,
+            fault_thread,
             previous_abort_action,
+            #[cfg(any(target_os = "ios", target_os = "tvos"))]
+            previous_fallback_actions,
             previous,
         });
     }
 
-    Ok(())
+    #[cfg(feature = "panic")]
+    crate::panic::install();
+
+    Ok(id)
 }
 
-pub(super) fn detach(is_handler_thread: bool) {
+/// Detaches the event, and if it was the last one, the handler
+pub(super) fn detach(id: EventId) {
     let mut lock = HANDLER.write();
+
+    let Some(handler) = &mut *lock else {
+        return;
+    };
+
+    if handler.events.remove(id) && handler.events.is_empty() {
+        remove_handler(lock, false);
+    }
+}
+
+/// Detaches every event, restoring the previous exception ports, which is
+/// done when a crash is not handled so that the OS sees it
+fn detach_all(is_handler_thread: bool) {
+    remove_handler(HANDLER.write(), is_handler_thread);
+}
+
+fn remove_handler(
+    mut lock: parking_lot::RwLockWriteGuard<'_, Option<HandlerInner>>,
+    is_handler_thread: bool,
+) {
     if let Some(handler) = lock.take() {
         // user can't really do anything if something fails at this point, but
         // should have a clean way of surfacing the error happened
         // SAFETY: syscalls
         let _result = unsafe { handler.shutdown(is_handler_thread) };
+
+        #[cfg(feature = "panic")]
+        crate::panic::uninstall();
     }
 }
 
@@ -374,9 +485,9 @@ pub(super) fn simulate_exception(info: Option<crash_context::ExceptionInfo>) ->
 fn call_user_callback(cc: &crash_context::CrashContext) -> CrashEventResult {
     let lock = HANDLER.read();
     if let Some(handler) = &*lock {
-        handler.crash_event.on_crash(cc)
+        handler.events.on_crash(cc)
     } else {
-        CrashEventResult::Handled(false)
+        CrashEventResult::Reraise
     }
 }
 
@@ -430,26 +541,40 @@ unsafe fn exception_handler(port: mach_port_t, us: UserSignal) {
                     // and importantly _don't_ detach the exception handler like we
                     // do for fatal exceptions
                     if !is_exception_non_fatal(exc_info, request.task.name) {
+                        let (annotations, annotation_count) = crate::annotations::location();
+                        let (breadcrumbs, breadcrumb_count) = crate::breadcrumbs::location();
                         let cc = crash_context::CrashContext {
                             thread: request.thread.name,
                             task: request.task.name,
                             handler_thread: mach_thread_self(),
                             exception: Some(exc_info),
+                            annotations: annotations as u64,
+                            annotation_count: annotation_count as u64,
+                            breadcrumbs: breadcrumbs as u64,
+                            breadcrumb_count: breadcrumb_count as u64,
                         };
 
-                        let ret_code =
-                            if let CrashEventResult::Handled(true) = call_user_callback(&cc) {
-                                KERN_SUCCESS
-                            } else {
-                                mach2::kern_return::KERN_FAILURE
-                            };
-
-                        // Restores the previous exception ports, in most cases
-                        // this will be the default for the OS, which will kill this
-                        // process when we reply that we've handled the exception
-                        detach(true);
-
-                        ret_code
+                        match call_user_callback(&cc) {
+                            CrashEventResult::Handled { exit: Some(code) } => {
+                                crate::exit_process(code)
+                            }
+                            // The thread is resumed with our ports still in
+                            // place, so that we see the exception if it occurs again
+                            CrashEventResult::Continue => KERN_SUCCESS,
+                            result => {
+                                // Restores the previous exception ports, in most cases
+                                // this will be the default for the OS, which will kill this
+                                // process when we reply, either because the exception
+                                // is raised again, or because we failed to handle it
+                                detach_all(true);
+
+                                if matches!(result, CrashEventResult::Handled { .. }) {
+                                    KERN_SUCCESS
+                                } else {
+                                    mach2::kern_return::KERN_FAILURE
+                                }
+                            }
+                        }
                     } else {
                         KERN_SUCCESS
                     }
@@ -457,29 +582,7 @@ unsafe fn exception_handler(port: mach_port_t, us: UserSignal) {
                     KERN_SUCCESS
                 };
 
-                // This magic incantation to send a reply back to the kernel was
-                // derived from the exc_server generated by
-                // 'mig -v /usr/include/mach/mach_exc.defs', or you can look at
-                // https://github.com/doadam/xnu-4570.1.46/blob/2ad7fbf85ff567495a572cd4583961ffd8525083/BUILD/obj/RELEASE_X86_64/osfmk/RELEASE/mach/exc_server.c#L491-L520
-                let mut reply: ExceptionRaiseReply = mem::zeroed();
-                reply.header.bits =
-                    msg::MACH_MSGH_BITS(request.header.bits & msg::MACH_MSGH_BITS_REMOTE_MASK, 0);
-                reply.header.size = mem::size_of_val(&reply) as u32;
-                reply.header.remote_port = request.header.remote_port;
-                reply.header.local_port = MACH_PORT_NULL;
-                reply.header.id = request.header.id + 100;
-                reply.ndr = NDR_record;
-                reply.ret_code = ret_code;
-
-                msg::mach_msg(
-                    ((&mut reply.header) as *mut MachMsgHeader).cast(),
-                    msg::MACH_SEND_MSG,
-                    mem::size_of_val(&reply) as u32,
-                    0,
-                    MACH_PORT_NULL,
-                    msg::MACH_MSG_TIMEOUT_NONE,
-                    MACH_PORT_NULL,
-                );
+                reply(&request, ret_code);
             }
             Ok(MessageIds::Shutdown) => return,
             Ok(MessageIds::SignalCrash) => {
@@ -500,11 +603,17 @@ unsafe fn exception_handler(port: mach_port_t, us: UserSignal) {
                     };
 
                     // Reconstruct a crash context from the message we received
+                    let (annotations, annotation_count) = crate::annotations::location();
+                    let (breadcrumbs, breadcrumb_count) = crate::breadcrumbs::location();
                     let cc = crash_context::CrashContext {
                         task: mach_task_self(),
                         thread: user_exception.crash_thread.name,
                         handler_thread: mach_thread_self(),
                         exception,
+                        annotations: annotations as u64,
+                        annotation_count: annotation_count as u64,
+                        breadcrumbs: breadcrumbs as u64,
+                        breadcrumb_count: breadcrumb_count as u64,
                     };
 
                     call_user_callback(&cc)
@@ -513,7 +622,7 @@ unsafe fn exception_handler(port: mach_port_t, us: UserSignal) {
                 {
                     let &(ref lock, ref cvar) = &*us;
                     let mut processed = lock.lock();
-                    *processed = Some(matches!(res, CrashEventResult::Handled(true)));
+                    *processed = Some(matches!(res, CrashEventResult::Handled { .. }));
                     cvar.notify_one();
                 }
             }
@@ -522,6 +631,77 @@ unsafe fn exception_handler(port: mach_port_t, us: UserSignal) {
     }
 }
 
+/// Sends the reply to an exception message back to the kernel
+///
+/// SAFETY: syscalls
+unsafe fn reply(request: &ExceptionMessage, ret_code: kern_return_t) {
+    // This magic incantation to send a reply back to the kernel was
+    // derived from the exc_server generated by
+    // 'mig -v /usr/include/mach/mach_exc.defs', or you can look at
+    // https://github.com/doadam/xnu-4570.1.46/blob/2ad7fbf85ff567495a572cd4583961ffd8525083/BUILD/obj/RELEASE_X86_64/osfmk/RELEASE/mach/exc_server.c#L491-L520
+    let mut reply: ExceptionRaiseReply = mem::zeroed();
+    reply.header.bits =
+        msg::MACH_MSGH_BITS(request.header.bits & msg::MACH_MSGH_BITS_REMOTE_MASK, 0);
+    reply.header.size = mem::size_of_val(&reply) as u32;
+    reply.header.remote_port = request.header.remote_port;
+    reply.header.local_port = MACH_PORT_NULL;
+    reply.header.id = request.header.id + 100;
+    reply.ndr = NDR_record;
+    reply.ret_code = ret_code;
+
+    msg::mach_msg(
+        ((&mut reply.header) as *mut MachMsgHeader).cast(),
+        msg::MACH_SEND_MSG,
+        mem::size_of_val(&reply) as u32,
+        0,
+        MACH_PORT_NULL,
+        msg::MACH_MSG_TIMEOUT_NONE,
+        MACH_PORT_NULL,
+    );
+}
+
+/// Message loop for the thread that receives exceptions raised on the handler
+/// thread, eg. an `EXC_BAD_ACCESS` in the user callback.
+///
+/// Since the handler thread is blocked until the exception is handled, we
+/// can't report it, so instead we restore the exception ports that were
+/// registered before ours and fail the exception, which makes the kernel
+/// deliver it to those ports instead, which by default will terminate the
+/// process rather than leaving it deadlocked.
+unsafe fn fault_handler(port: mach_port_t, previous: PreviousPorts) {
+    let mut request: ExceptionMessage = mem::zeroed();
+
+    loop {
+        request.header.local_port = port;
+        request.header.size = mem::size_of_val(&request) as _;
+
+        let kret = msg::mach_msg(
+            ((&mut request.header) as *mut MachMsgHeader).cast(),
+            msg::MACH_RCV_MSG | msg::MACH_RCV_LARGE,
+            0,
+            mem::size_of_val(&request) as u32,
+            port,
+            msg::MACH_MSG_TIMEOUT_NONE,
+            MACH_PORT_NULL,
+        );
+
+        if kret != KERN_SUCCESS {
+            return;
+        }
+
+        match MessageIds::try_from(request.header.id) {
+            Ok(MessageIds::Exception | MessageIds::ExceptionStateIdentity) => {
+                crate::debug_print!("exception raised on the handler thread");
+
+                let _res = previous.restore();
+                reply(&request, mach2::kern_return::KERN_FAILURE);
+            }
+            Ok(MessageIds::Shutdown) => return,
+            _ => {}
+        }
+    }
+}
+
 struct ScopedSuspend;
 
 impl ScopedSuspend {
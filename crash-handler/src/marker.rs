@@ -0,0 +1,280 @@
+//! Detection of abnormal terminations that can't be handled.
+//!
+//! Some terminations, notably `SIGKILL`, which is used by the OOM killer, or
+//! the system losing power, never invoke a [`crate::CrashEvent`], so the only
+//! way to know they happened is to notice it on the next run. A
+//! [`CrashMarker`] is a small file that is written when the process starts and
+//! removed when it exits cleanly, so if the file still exists the next time
+//! the process starts, the previous run ended abnormally.
+//!
+//! ```no_run
+//! use crash_handler::marker::CrashMarker;
+//!
+//! let path = std::env::temp_dir().join("my-app.marker");
+//!
+//! if let Some(previous) = CrashMarker::previous_run(&path) {
+//!     eprintln!("previous run ({}) ended abnormally: {:?}", previous.pid, previous.cause);
+//! }
+//!
+//! let marker = CrashMarker::create(&path).unwrap();
+//! // ...run the application...
+//! marker.clear();
+//! ```
+
+use crate::Error;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The most likely reason the previous run ended abnormally
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AbnormalExit {
+    /// The system was restarted while the process was running, eg. because
+    /// it lost power or the kernel panicked
+    SystemRestart,
+    /// The kernel log records the process being killed by the OOM killer.
+    ///
+    /// This is only detected on Linux/Android, and only if the kernel log can
+    /// be read by the process, which may be disallowed by `dmesg_restrict`.
+    OutOfMemory,
+    /// The process terminated without clearing the marker for an unknown
+    /// reason, eg. it crashed, was killed with `SIGKILL`, or simply exited
+    /// without clearing the marker
+    Unknown,
+}
+
+/// Information about a previous run that ended abnormally, read from the
+/// marker it left behind
+#[derive(Clone, Debug)]
+pub struct PreviousRun {
+    /// The process id of the previous run
+    pub pid: u32,
+    /// The boot id of the system when the previous run started, only
+    /// available on Linux/Android
+    pub boot_id: Option<String>,
+    /// The time the previous run created its marker
+    pub started: SystemTime,
+    /// The most likely reason the previous run ended abnormally
+    pub cause: AbnormalExit,
+}
+
+/// A marker file that is removed when the process exits cleanly, see the
+/// [module documentation](self).
+///
+/// The marker is removed when this is dropped, so it must not be dropped
+/// until the process is about to exit. Note that Rust doesn't drop statics,
+/// so a marker stored in one must be cleared manually.
+pub struct CrashMarker {
+    path: PathBuf,
+    /// The process that wrote the marker, as a child created by `fork` must
+    /// not clear its parent's marker
+    pid: u32,
+}
+
+impl CrashMarker {
+    /// Writes a marker for the current process to the specified path,
+    /// replacing any marker left by a previous run.
+    ///
+    /// The path should be unique to each instance of the application that
+    /// can run concurrently, as otherwise a marker written by one instance
+    /// will be reported by another as an abnormal exit.
+    ///
+    /// # Errors
+    ///
+    /// The marker file can't be written
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self, Error> {
+        let path = path.into();
+
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let pid = std::process::id();
+        let mut contents = format!("pid={pid}\ntimestamp={started}\n");
+        if let Some(boot_id) = boot_id() {
+            contents.push_str("boot_id=");
+            contents.push_str(&boot_id);
+            contents.push('\n');
+        }
+
+        std::fs::write(&path, contents)?;
+
+        Ok(Self { path, pid })
+    }
+
+    /// Reads the marker at the specified path, returning information about
+    /// the run that wrote it if it was not cleared, ie. the run ended
+    /// abnormally.
+    ///
+    /// This should be called before [`Self::create`], which overwrites the
+    /// marker.
+    pub fn previous_run(path: impl AsRef<Path>) -> Option<PreviousRun> {
+        let contents = std::fs::read_to_string(path).ok()?;
+        let mut previous = parse(&contents)?;
+
+        previous.cause = match (&previous.boot_id, boot_id()) {
+            (Some(previous_boot), Some(current_boot)) if *previous_boot != current_boot => {
+                AbnormalExit::SystemRestart
+            }
+            _ if was_oom_killed(previous.pid) => AbnormalExit::OutOfMemory,
+            _ => AbnormalExit::Unknown,
+        };
+
+        Some(previous)
+    }
+
+    /// The path of the marker file
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the marker, marking the run as having ended cleanly. This is
+    /// equivalent to dropping the marker.
+    #[inline]
+    pub fn clear(self) {}
+}
+
+impl Drop for CrashMarker {
+    fn drop(&mut self) {
+        if self.pid != std::process::id() {
+            return;
+        }
+
+        let _res = std::fs::remove_file(&self.path);
+    }
+}
+
+fn parse(contents: &str) -> Option<PreviousRun> {
+    let mut pid = None;
+    let mut started = None;
+    let mut boot_id = None;
+
+    for line in contents.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+
+        match key {
+            "pid" => pid = value.parse().ok(),
+            "timestamp" => {
+                started = value
+                    .parse()
+                    .ok()
+                    .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+            }
+            "boot_id" => boot_id = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+
+    Some(PreviousRun {
+        pid: pid?,
+        boot_id,
+        started: started?,
+        cause: AbnormalExit::Unknown,
+    })
+}
+
+/// Retrieves the id the kernel randomly generates on each boot
+fn boot_id() -> Option<String> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+            Some(boot_id.trim().to_owned())
+        } else {
+            None
+        }
+    }
+}
+
+/// Searches the kernel log for the OOM killer killing the specified process
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn was_oom_killed(pid: u32) -> bool {
+    use std::{io::Read, os::unix::fs::OpenOptionsExt};
+
+    let Ok(mut kmsg) = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open("/dev/kmsg")
+    else {
+        return false;
+    };
+
+    // Each read returns exactly one record, which are limited to 1024 bytes
+    // of text plus their metadata
+    let mut record = [0u8; 8 * 1024];
+
+    loop {
+        match kmsg.read(&mut record) {
+            Ok(0) => return false,
+            Ok(len) => {
+                if is_oom_kill(&String::from_utf8_lossy(&record[..len]), pid) {
+                    return true;
+                }
+            }
+            // The record was overwritten in the ring buffer before we read it
+            Err(err) if err.raw_os_error() == Some(libc::EPIPE) => {}
+            // WouldBlock once every record has been read
+            Err(_) => return false,
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn was_oom_killed(_pid: u32) -> bool {
+    false
+}
+
+/// Checks if a `/dev/kmsg` record is the OOM killer reporting that it killed
+/// the specified process, either via the summary added in Linux 4.19, or the
+/// message that every version logs
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn is_oom_kill(record: &str, pid: u32) -> bool {
+    // The metadata is separated from the message by the first ';'
+    let Some((_, message)) = record.split_once(';') else {
+        return false;
+    };
+
+    (message.starts_with("oom-kill:") && message.contains(&format!(",pid={},", pid)))
+        || message.contains(&format!("Killed process {} ", pid))
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_marker() {
+        let previous = parse("pid=42\ntimestamp=1000\nboot_id=abc\n").unwrap();
+        assert_eq!(previous.pid, 42);
+        assert_eq!(previous.boot_id.as_deref(), Some("abc"));
+        assert_eq!(
+            previous.started,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1000)
+        );
+
+        assert!(parse("timestamp=1000\n").is_none());
+        assert!(parse("garbage").is_none());
+    }
+
+    #[test]
+    fn matches_oom_kills() {
+        assert!(is_oom_kill(
+            "3,1234,5678,-;Out of memory: Killed process 42 (app) total-vm:1kB, anon-rss:1kB",
+            42
+        ));
+        assert!(is_oom_kill("6,1235,5679,-;oom-kill:constraint=CONSTRAINT_MEMCG,nodemask=(null),cpuset=/,mems_allowed=0,task=app,pid=42,uid=1000", 42));
+
+        assert!(!is_oom_kill(
+            "3,1234,5678,-;Out of memory: Killed process 420 (app)",
+            42
+        ));
+        assert!(!is_oom_kill(
+            "6,1235,5679,-;app[42]: segfault at 0 ip 0 sp 0 error 4",
+            42
+        ));
+    }
+}
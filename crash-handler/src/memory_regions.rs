@@ -0,0 +1,261 @@
+//! Application memory that is included in minidumps.
+//!
+//! A minidump normally only contains the stacks of the threads in the
+//! process, so state that lives elsewhere, eg. a log ring buffer or the
+//! current game state, can't be inspected by a debugger. Regions registered
+//! here are stored in a fixed size array of [`crash_context::MemoryRegion`]
+//! slots, whose location is recorded in the [`crate::CrashContext`], so that
+//! both `minidumper`'s in-process writer and its `ptrace` based writer in
+//! another process can append the memory to the minidump.
+//!
+//! Each region is identified by a tag, including a region with the same tag
+//! as an existing one replaces it. Once all [`crash_context::MAX_MEMORY_REGIONS`]
+//! slots have been used, including a new region evicts the region that was
+//! included the longest time ago.
+//!
+//! ```
+//! static LOG: [u8; 4096] = [0; 4096];
+//!
+//! crash_handler::memory_regions::include_region(LOG.as_ptr(), LOG.len(), "log");
+//! ```
+//!
+//! The memory is read without faulting, so a region that is no longer mapped
+//! at the time of the crash is omitted rather than crashing the writer, but
+//! regions should still be excluded once the memory is freed, as it may
+//! otherwise contain unrelated data by the time of the crash.
+//!
+//! Conversely, memory holding secrets, eg. encryption keys or authentication
+//! tokens, can be redacted, in which case every page containing it is zero
+//! filled wherever it would otherwise appear in a minidump, such as in the
+//! stack of the crashing thread or in an included region. Redacted regions
+//! are kept in their own [`crash_context::MAX_REDACTED_REGIONS`] slots, and
+//! are never evicted.
+//!
+//! ```
+//! static TOKEN: [u8; 64] = [0; 64];
+//!
+//! assert!(crash_handler::memory_regions::redact_region(TOKEN.as_ptr(), TOKEN.len()));
+//! ```
+
+use crash_context::{
+    MemoryRegion, MAX_MEMORY_REGIONS, MAX_MEMORY_REGION_SLOTS, MAX_MEMORY_REGION_TAG_LEN,
+    MEMORY_REGION_EMPTY, MEMORY_REGION_REDACTED, MEMORY_REGION_VALID, MEMORY_REGION_WRITING,
+};
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+struct Slots([UnsafeCell<MemoryRegion>; MAX_MEMORY_REGION_SLOTS]);
+
+// SAFETY: Slots are only written while holding the `NEXT` lock, and readers
+// check the sequence number of each slot before and after reading it
+unsafe impl Sync for Slots {}
+
+static SLOTS: Slots =
+    Slots([const { UnsafeCell::new(MemoryRegion::EMPTY) }; MAX_MEMORY_REGION_SLOTS]);
+/// The sequence number of each slot, which is odd while the slot is being
+/// written, so that readers can detect if a slot was modified while they were
+/// reading it
+static SEQUENCES: [AtomicUsize; MAX_MEMORY_REGION_SLOTS] =
+    [const { AtomicUsize::new(0) }; MAX_MEMORY_REGION_SLOTS];
+/// The slot the next new region is stored in
+static NEXT: parking_lot::Mutex<usize> = parking_lot::const_mutex(0);
+
+/// Retrieves the state of the slot
+#[inline]
+fn state(slot: &UnsafeCell<MemoryRegion>) -> &AtomicU32 {
+    // SAFETY: the pointer is valid, aligned, and only ever accessed atomically
+    unsafe { AtomicU32::from_ptr(std::ptr::addr_of_mut!((*slot.get()).state)) }
+}
+
+/// Includes the `size` bytes starting at `address` in minidumps, replacing
+/// any region with the same tag.
+///
+/// Tags longer than [`crash_context::MAX_MEMORY_REGION_TAG_LEN`] bytes are
+/// truncated.
+pub fn include_region(address: *const u8, size: usize, tag: &str) {
+    let tag = crate::annotations::truncate(tag, MAX_MEMORY_REGION_TAG_LEN);
+
+    let mut next = NEXT.lock();
+
+    let index = if let Some(index) = find(tag) {
+        index
+    } else {
+        let index = *next;
+        *next = (*next + 1) % MAX_MEMORY_REGIONS;
+        index
+    };
+
+    write(index, address as u64, size as u64, tag, 0);
+}
+
+/// Excludes the region with the specified tag, if it exists
+pub fn exclude_region(tag: &str) {
+    let tag = crate::annotations::truncate(tag, MAX_MEMORY_REGION_TAG_LEN);
+
+    let _next = NEXT.lock();
+    if let Some(index) = find(tag) {
+        state(&SLOTS.0[index]).store(MEMORY_REGION_EMPTY, Ordering::Release);
+    }
+}
+
+/// Excludes every region. Redacted regions are not affected
+pub fn clear() {
+    let mut next = NEXT.lock();
+    for slot in &SLOTS.0[..MAX_MEMORY_REGIONS] {
+        state(slot).store(MEMORY_REGION_EMPTY, Ordering::Release);
+    }
+    *next = 0;
+}
+
+/// Invokes the callback with the address, size, and tag of every region that
+/// is currently included.
+///
+/// This does not take any locks or allocate, and is thus safe to call from
+/// within a [`crate::CrashEvent`], however regions that are being included
+/// concurrently are skipped.
+pub fn for_each(mut cb: impl FnMut(usize, usize, &str)) {
+    for_each_slot(|region| {
+        if let Some(tag) = region.tag() {
+            cb(region.address as usize, region.size as usize, tag);
+        }
+    });
+}
+
+/// Redacts every page containing the `size` bytes starting at `address`
+/// from minidumps, returning `false` if all [`crash_context::MAX_REDACTED_REGIONS`]
+/// slots are already in use.
+///
+/// Redacting a range that is already redacted has no effect.
+pub fn redact_region(address: *const u8, size: usize) -> bool {
+    let (address, size) = page_range(address as usize, size);
+
+    let _next = NEXT.lock();
+
+    if find_redacted(address, size).is_some() {
+        return true;
+    }
+
+    let Some(index) = (MAX_MEMORY_REGIONS..MAX_MEMORY_REGION_SLOTS)
+        .find(|&index| state(&SLOTS.0[index]).load(Ordering::Acquire) != MEMORY_REGION_VALID)
+    else {
+        return false;
+    };
+
+    write(index, address, size, "", MEMORY_REGION_REDACTED);
+    true
+}
+
+/// Removes a redaction previously added via [`redact_region`] with the same
+/// `address` and `size`, if it exists
+pub fn unredact_region(address: *const u8, size: usize) {
+    let (address, size) = page_range(address as usize, size);
+
+    let _next = NEXT.lock();
+    if let Some(index) = find_redacted(address, size) {
+        state(&SLOTS.0[index]).store(MEMORY_REGION_EMPTY, Ordering::Release);
+    }
+}
+
+/// Invokes the callback with the address and size of every page range that
+/// is currently redacted.
+///
+/// This does not take any locks or allocate, and is thus safe to call from
+/// within a [`crate::CrashEvent`], however regions that are being redacted
+/// concurrently are skipped.
+pub fn for_each_redacted(mut cb: impl FnMut(usize, usize)) {
+    for_each_slot(|region| {
+        if let Some(range) = region.redacted() {
+            cb(range.start as usize, (range.end - range.start) as usize);
+        }
+    });
+}
+
+/// Invokes the callback with a copy of every slot that was not being modified
+/// while it was read
+fn for_each_slot(mut cb: impl FnMut(&MemoryRegion)) {
+    for (slot, sequence) in SLOTS.0.iter().zip(&SEQUENCES) {
+        let before = sequence.load(Ordering::Acquire);
+        if before % 2 != 0 {
+            continue;
+        }
+
+        // SAFETY: we copy the slot before checking that it was not modified
+        // while we were reading it
+        let region = unsafe { std::ptr::read_volatile(slot.get()) };
+        if sequence.load(Ordering::Acquire) != before {
+            continue;
+        }
+
+        cb(&region);
+    }
+}
+
+/// Retrieves the address and number of the region slots, which are recorded
+/// in the [`crate::CrashContext`]
+#[inline]
+pub(crate) fn location() -> (usize, usize) {
+    (SLOTS.0.as_ptr() as usize, MAX_MEMORY_REGION_SLOTS)
+}
+
+/// Writes the region to the slot. Must be called with the lock held
+fn write(index: usize, address: u64, size: u64, tag: &str, flags: u16) {
+    let slot = &SLOTS.0[index];
+    let state = state(slot);
+    SEQUENCES[index].fetch_add(1, Ordering::AcqRel);
+    state.store(MEMORY_REGION_WRITING, Ordering::Release);
+
+    // SAFETY: we hold the lock, and readers will ignore the slot while it is
+    // being written
+    unsafe {
+        let region = &mut *slot.get();
+        region.address = address;
+        region.size = size;
+        region.tag[..tag.len()].copy_from_slice(tag.as_bytes());
+        region.tag_len = tag.len() as u16;
+        region.flags = flags;
+    }
+
+    state.store(MEMORY_REGION_VALID, Ordering::Release);
+    SEQUENCES[index].fetch_add(1, Ordering::AcqRel);
+}
+
+/// Expands the range to the pages containing it
+fn page_range(address: usize, size: usize) -> (u64, u64) {
+    // SAFETY: syscall
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+
+    let start = address & !(page_size - 1);
+    let end = address.saturating_add(size).saturating_add(page_size - 1) & !(page_size - 1);
+    (start as u64, (end - start) as u64)
+}
+
+/// Finds the index of the valid slot with the specified tag. Must be called
+/// with the lock held
+fn find(tag: &str) -> Option<usize> {
+    SLOTS.0[..MAX_MEMORY_REGIONS].iter().position(|slot| {
+        if state(slot).load(Ordering::Acquire) != MEMORY_REGION_VALID {
+            return false;
+        }
+
+        // SAFETY: slots are only modified while the lock is held
+        let region = unsafe { &*slot.get() };
+        &region.tag[..region.tag_len as usize] == tag.as_bytes()
+    })
+}
+
+/// Finds the index of the valid redacted slot with the specified page range.
+/// Must be called with the lock held
+fn find_redacted(address: u64, size: u64) -> Option<usize> {
+    (MAX_MEMORY_REGIONS..MAX_MEMORY_REGION_SLOTS).find(|&index| {
+        let slot = &SLOTS.0[index];
+        if state(slot).load(Ordering::Acquire) != MEMORY_REGION_VALID {
+            return false;
+        }
+
+        // SAFETY: slots are only modified while the lock is held
+        let region = unsafe { &*slot.get() };
+        region.address == address && region.size == size
+    })
+}
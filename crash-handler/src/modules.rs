@@ -0,0 +1,244 @@
+//! Enumeration of the modules, ie. executables and shared libraries, that are
+//! loaded in the current process.
+//!
+//! Addresses in a [`crate::CrashContext`], eg. the instruction pointer, are
+//! only meaningful to a symbolicator if they can be attributed to a module,
+//! and the module matched with its debug information via its build id. The
+//! modules are retrieved from `/proc/self/maps` on Linux/Android, from `dyld`
+//! on Macos, and from the loader on Windows.
+//!
+//! [`enumerate`] allocates and reads files, so it can't be used while handling
+//! a crash. Instead, [`refresh`] stores the modules in a cache that was
+//! allocated up front, which can then be accessed via [`with_cached`] without
+//! taking any locks or allocating.
+//!
+//! ```
+//! use crash_handler::modules;
+//!
+//! modules::refresh();
+//!
+//! let ip = modules::refresh as *const () as usize;
+//! modules::with_cached(|modules| {
+//!     if let Some(module) = modules.iter().find(|module| module.contains(ip)) {
+//!         println!("{:#x} is at {:#x} in {}", ip, ip - module.base, module.path());
+//!     }
+//! });
+//! ```
+
+use std::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        mod linux;
+        use linux as imp;
+    } else if #[cfg(target_os = "windows")] {
+        mod windows;
+        use windows as imp;
+    } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
+        mod mac;
+        use mac as imp;
+    }
+}
+
+/// The maximum number of modules that are enumerated, any more are ignored
+pub const MAX_MODULES: usize = 1024;
+/// The maximum length of a module's path, in bytes, longer paths are truncated
+pub const MAX_MODULE_PATH_LEN: usize = 256;
+/// The maximum length of a module's build id, in bytes
+pub const MAX_BUILD_ID_LEN: usize = 32;
+
+/// A module loaded in the current process
+#[derive(Copy, Clone)]
+pub struct Module {
+    /// The address the module is loaded at
+    pub base: usize,
+    /// The size of the address range the module occupies
+    pub size: usize,
+    build_id: [u8; MAX_BUILD_ID_LEN],
+    build_id_len: u8,
+    path: [u8; MAX_MODULE_PATH_LEN],
+    path_len: u16,
+}
+
+impl Module {
+    const EMPTY: Self = Self {
+        base: 0,
+        size: 0,
+        build_id: [0; MAX_BUILD_ID_LEN],
+        build_id_len: 0,
+        path: [0; MAX_MODULE_PATH_LEN],
+        path_len: 0,
+    };
+
+    pub(crate) fn new(base: usize, size: usize, path: &str, build_id: &[u8]) -> Self {
+        let path = crate::annotations::truncate(path, MAX_MODULE_PATH_LEN);
+        let build_id = &build_id[..build_id.len().min(MAX_BUILD_ID_LEN)];
+
+        let mut module = Self {
+            base,
+            size,
+            build_id_len: build_id.len() as u8,
+            path_len: path.len() as u16,
+            ..Self::EMPTY
+        };
+        module.path[..path.len()].copy_from_slice(path.as_bytes());
+        module.build_id[..build_id.len()].copy_from_slice(build_id);
+        module
+    }
+
+    /// The path the module was loaded from, which may be truncated to
+    /// [`MAX_MODULE_PATH_LEN`] bytes
+    #[inline]
+    pub fn path(&self) -> &str {
+        std::str::from_utf8(&self.path[..self.path_len as usize]).unwrap_or_default()
+    }
+
+    /// The build id of the module, which identifies the exact build so that
+    /// it can be matched with its debug information, or empty if it doesn't
+    /// have one.
+    ///
+    /// This is the `NT_GNU_BUILD_ID` note on Linux/Android, the `LC_UUID` on
+    /// Macos, and the PDB GUID followed by the little endian age on Windows.
+    #[inline]
+    pub fn build_id(&self) -> &[u8] {
+        &self.build_id[..self.build_id_len as usize]
+    }
+
+    /// Returns true if the address is within the module
+    #[inline]
+    pub fn contains(&self, address: usize) -> bool {
+        address >= self.base && address - self.base < self.size
+    }
+}
+
+/// A list of modules, with capacity for [`MAX_MODULES`] allocated up front so
+/// that it can be refilled via [`enumerate_into`] without allocating
+pub struct ModuleList {
+    modules: Box<[Module]>,
+    len: usize,
+}
+
+impl ModuleList {
+    /// Creates an empty list
+    pub fn new() -> Self {
+        Self {
+            modules: vec![Module::EMPTY; MAX_MODULES].into_boxed_slice(),
+            len: 0,
+        }
+    }
+
+    /// The modules in the list
+    #[inline]
+    pub fn as_slice(&self) -> &[Module] {
+        &self.modules[..self.len]
+    }
+
+    /// Finds the module that contains the address
+    #[inline]
+    pub fn find(&self, address: usize) -> Option<&Module> {
+        self.as_slice()
+            .iter()
+            .find(|module| module.contains(address))
+    }
+}
+
+impl Default for ModuleList {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Enumerates the modules that are currently loaded.
+///
+/// This allocates and performs I/O, and is thus not safe to call while
+/// handling a crash, see [`with_cached`] instead.
+pub fn enumerate() -> ModuleList {
+    let mut list = ModuleList::new();
+    enumerate_into(&mut list);
+    list
+}
+
+/// Enumerates the modules that are currently loaded into an existing list,
+/// replacing its previous contents
+pub fn enumerate_into(list: &mut ModuleList) {
+    list.len = imp::fill(&mut list.modules);
+}
+
+/// The cache is double buffered so that it can be refreshed while a crash
+/// handler is reading the previous contents
+struct Cache([UnsafeCell<[Module; MAX_MODULES]>; 2]);
+
+// SAFETY: Buffers are only written while holding the `REFRESH` lock, and the
+// active buffer is never the one being written
+unsafe impl Sync for Cache {}
+
+static CACHE: Cache = Cache([const { UnsafeCell::new([Module::EMPTY; MAX_MODULES]) }; 2]);
+static CACHE_LENS: [AtomicUsize; 2] = [const { AtomicUsize::new(0) }; 2];
+/// The index of the buffer that readers use, or [`usize::MAX`] if the cache
+/// has never been refreshed
+static ACTIVE: AtomicUsize = AtomicUsize::new(usize::MAX);
+static REFRESH: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+
+/// Refreshes the cache read by [`with_cached`].
+///
+/// # Linux/Android
+///
+/// There is no way to be notified when libraries are loaded or unloaded
+/// without using `LD_AUDIT`, which can only be set before the process starts,
+/// so this should be called after attaching the [`crate::CrashHandler`] and
+/// again after loading libraries, eg. via `dlopen`.
+///
+/// # Windows
+///
+/// As with Linux/Android, this should be called after attaching the
+/// [`crate::CrashHandler`] and after loading libraries via `LoadLibrary`.
+///
+/// # Macos
+///
+/// The first call registers with `dyld` to be notified when images are added
+/// or removed, after which the cache is refreshed automatically.
+pub fn refresh() {
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))]
+    if mac::install_hooks() {
+        // Installing the hooks refreshes the cache
+        return;
+    }
+
+    refresh_cache();
+}
+
+fn refresh_cache() {
+    let _lock = REFRESH.lock();
+
+    let active = ACTIVE.load(Ordering::Acquire);
+    let next = if active == 0 { 1 } else { 0 };
+
+    // SAFETY: we hold the lock, and readers only read the active buffer
+    let len = imp::fill(unsafe { &mut *CACHE.0[next].get() });
+    CACHE_LENS[next].store(len, Ordering::Release);
+    ACTIVE.store(next, Ordering::Release);
+}
+
+/// Invokes the callback with the modules stored by the last [`refresh`],
+/// which is empty if it has never been called.
+///
+/// This does not take any locks or allocate, and is thus safe to call from
+/// within a [`crate::CrashEvent`]. Note the modules are only overwritten by
+/// the second [`refresh`] after the one that stored them, so the callback
+/// should not run for longer than it takes to load two libraries.
+pub fn with_cached<R>(f: impl FnOnce(&[Module]) -> R) -> R {
+    let active = ACTIVE.load(Ordering::Acquire);
+    if active > 1 {
+        return f(&[]);
+    }
+
+    let len = CACHE_LENS[active].load(Ordering::Acquire);
+
+    // SAFETY: the active buffer is not written until another buffer becomes
+    // active
+    let buffer = unsafe { &*CACHE.0[active].get() };
+    f(&buffer[..len])
+}
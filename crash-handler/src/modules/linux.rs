@@ -0,0 +1,249 @@
+use super::Module;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_pointer_width = "64")] {
+        use libc::{Elf64_Ehdr as Ehdr, Elf64_Phdr as Phdr};
+    } else {
+        use libc::{Elf32_Ehdr as Ehdr, Elf32_Phdr as Phdr};
+    }
+}
+
+/// The note type of a GNU build id
+const NT_GNU_BUILD_ID: u32 = 3;
+/// The maximum size of the notes we are willing to read for a module
+const MAX_NOTES_LEN: usize = 4 * 1024;
+
+/// A contiguous run of mappings from the same file
+struct Mapping<'maps> {
+    start: usize,
+    end: usize,
+    path: &'maps str,
+}
+
+pub(super) fn fill(modules: &mut [Module]) -> usize {
+    let Ok(maps) = std::fs::read_to_string("/proc/self/maps") else {
+        return 0;
+    };
+
+    let mut len = 0;
+    let mut current: Option<Mapping<'_>> = None;
+
+    for line in maps.lines() {
+        let Some((start, end, offset, path)) = parse_line(line) else {
+            continue;
+        };
+
+        // Subsequent mappings of the same file extend the module, unless the
+        // file is mapped again from the start, ie. it was loaded twice
+        if let Some(cur) = &mut current {
+            if cur.path == path && offset != 0 {
+                cur.end = end;
+                continue;
+            }
+        }
+
+        if let Some(module) = current.take().and_then(|mapping| to_module(&mapping)) {
+            if len == modules.len() {
+                return len;
+            }
+
+            modules[len] = module;
+            len += 1;
+        }
+
+        // A module always starts with the ELF header, at the start of the file
+        if offset == 0 {
+            current = Some(Mapping { start, end, path });
+        }
+    }
+
+    if let Some(module) = current.and_then(|mapping| to_module(&mapping)) {
+        if len < modules.len() {
+            modules[len] = module;
+            len += 1;
+        }
+    }
+
+    len
+}
+
+/// Parses a line of `/proc/self/maps`, returning the address range, file
+/// offset, and path of mappings that are backed by a file or the vdso
+fn parse_line(line: &str) -> Option<(usize, usize, u64, &str)> {
+    let mut parts = line.splitn(6, ' ');
+
+    let (start, end) = parts.next()?.split_once('-')?;
+    let _perms = parts.next()?;
+    let offset = parts.next()?;
+    let _dev = parts.next()?;
+    let _inode = parts.next()?;
+    let path = parts.next()?.trim_start();
+
+    if !path.starts_with('/') && path != "[vdso]" {
+        return None;
+    }
+
+    Some((
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(end, 16).ok()?,
+        u64::from_str_radix(offset, 16).ok()?,
+        path,
+    ))
+}
+
+/// Converts the mappings into a module, if they are an ELF
+fn to_module(mapping: &Mapping<'_>) -> Option<Module> {
+    let ehdr: Ehdr = read(mapping.start)?;
+    if ehdr.e_ident[..4] != *b"\x7fELF" {
+        return None;
+    }
+
+    let mut build_id = [0u8; super::MAX_BUILD_ID_LEN];
+    let build_id_len = read_build_id(mapping.start, &ehdr, &mut build_id).unwrap_or(0);
+
+    Some(Module::new(
+        mapping.start,
+        mapping.end - mapping.start,
+        mapping.path,
+        &build_id[..build_id_len],
+    ))
+}
+
+/// Finds the `NT_GNU_BUILD_ID` note in the module loaded at the base address
+fn read_build_id(base: usize, ehdr: &Ehdr, build_id: &mut [u8]) -> Option<usize> {
+    let phdr_at = |i: usize| -> Option<Phdr> {
+        read(base + ehdr.e_phoff as usize + i * std::mem::size_of::<Phdr>())
+    };
+
+    // The base is where the start of the file is mapped, so the load bias is
+    // relative to the segment that maps it
+    let bias = (0..ehdr.e_phnum as usize)
+        .filter_map(phdr_at)
+        .find(|phdr| phdr.p_type == libc::PT_LOAD)
+        .map(|phdr| base.wrapping_sub(phdr.p_vaddr.wrapping_sub(phdr.p_offset) as usize))?;
+
+    let mut notes = [0u8; MAX_NOTES_LEN];
+
+    for phdr in (0..ehdr.e_phnum as usize).filter_map(phdr_at) {
+        if phdr.p_type != libc::PT_NOTE {
+            continue;
+        }
+
+        let len = (phdr.p_memsz as usize).min(MAX_NOTES_LEN);
+        if !read_into(bias.wrapping_add(phdr.p_vaddr as usize), &mut notes[..len]) {
+            continue;
+        }
+
+        if let Some(desc) = find_build_id(&notes[..len]) {
+            let len = desc.len().min(build_id.len());
+            build_id[..len].copy_from_slice(&desc[..len]);
+            return Some(len);
+        }
+    }
+
+    None
+}
+
+/// Finds the descriptor of the GNU build id note in a note segment
+fn find_build_id(mut notes: &[u8]) -> Option<&[u8]> {
+    let align = |len: usize| (len + 3) & !3;
+    let u32_at = |buf: &[u8], offset: usize| -> Option<u32> {
+        Some(u32::from_ne_bytes(
+            buf.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+
+    while notes.len() >= 12 {
+        let name_len = u32_at(notes, 0)? as usize;
+        let desc_len = u32_at(notes, 4)? as usize;
+        let kind = u32_at(notes, 8)?;
+
+        let name = notes.get(12..12 + name_len)?;
+        let desc_start = 12 + align(name_len);
+        let desc = notes.get(desc_start..desc_start + desc_len)?;
+
+        if kind == NT_GNU_BUILD_ID && name == b"GNU\0" {
+            return Some(desc);
+        }
+
+        notes = notes.get(desc_start + align(desc_len)..)?;
+    }
+
+    None
+}
+
+/// Reads a value from our own memory, without faulting if the address is not
+/// mapped
+fn read<T: Copy>(address: usize) -> Option<T> {
+    let mut val = std::mem::MaybeUninit::<T>::uninit();
+
+    // SAFETY: the buffer is the size of T
+    let buf = unsafe {
+        std::slice::from_raw_parts_mut(val.as_mut_ptr().cast::<u8>(), std::mem::size_of::<T>())
+    };
+
+    // SAFETY: only used for plain old data, which is fully initialized by
+    // the read
+    read_into(address, buf).then(|| unsafe { val.assume_init() })
+}
+
+/// Reads our own memory via `process_vm_readv`, which fails with `EFAULT`
+/// rather than faulting if the address is not mapped
+fn read_into(address: usize, buf: &mut [u8]) -> bool {
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut _,
+        iov_len: buf.len(),
+    };
+
+    // SAFETY: syscall, the local buffer is valid for its length
+    let read = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    read == buf.len() as isize
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn parses_maps() {
+        assert_eq!(
+            super::parse_line(
+                "7f1c2a400000-7f1c2a428000 r--p 00001000 08:01 1234                       /usr/lib/libc.so.6"
+            ),
+            Some((0x7f1c2a400000, 0x7f1c2a428000, 0x1000, "/usr/lib/libc.so.6"))
+        );
+        assert_eq!(
+            super::parse_line(
+                "7ffd1b7f2000-7ffd1b7f4000 r-xp 00000000 00:00 0                          [vdso]"
+            ),
+            Some((0x7ffd1b7f2000, 0x7ffd1b7f4000, 0, "[vdso]"))
+        );
+        assert!(super::parse_line(
+            "7ffd1b7f2000-7ffd1b7f4000 rw-p 00000000 00:00 0                          [stack]"
+        )
+        .is_none());
+        assert!(super::parse_line("7ffd1b7f2000-7ffd1b7f4000 rw-p 00000000 00:00 0").is_none());
+    }
+
+    #[test]
+    fn finds_build_id() {
+        let mut notes = Vec::new();
+        // An ABI tag note, which is skipped
+        notes.extend_from_slice(&4u32.to_ne_bytes());
+        notes.extend_from_slice(&16u32.to_ne_bytes());
+        notes.extend_from_slice(&1u32.to_ne_bytes());
+        notes.extend_from_slice(b"GNU\0");
+        notes.extend_from_slice(&[0; 16]);
+        // The build id, with a length that requires padding
+        notes.extend_from_slice(&4u32.to_ne_bytes());
+        notes.extend_from_slice(&3u32.to_ne_bytes());
+        notes.extend_from_slice(&3u32.to_ne_bytes());
+        notes.extend_from_slice(b"GNU\0");
+        notes.extend_from_slice(&[0xab, 0xcd, 0xef, 0]);
+
+        assert_eq!(super::find_build_id(&notes), Some(&[0xab, 0xcd, 0xef][..]));
+        assert_eq!(super::find_build_id(&notes[..36]), None);
+    }
+}
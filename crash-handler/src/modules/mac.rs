@@ -0,0 +1,172 @@
+use super::Module;
+use mach2::{dyld, loader::mach_header};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const LC_SEGMENT_64: u32 = 0x19;
+const LC_UUID: u32 = 0x1b;
+
+/// `mach_header_64`, which is a `mach_header` followed by a reserved field
+#[repr(C)]
+struct MachHeader64 {
+    magic: u32,
+    cputype: i32,
+    cpusubtype: i32,
+    filetype: u32,
+    ncmds: u32,
+    sizeofcmds: u32,
+    flags: u32,
+    reserved: u32,
+}
+
+#[repr(C)]
+struct LoadCommand {
+    cmd: u32,
+    cmdsize: u32,
+}
+
+#[repr(C)]
+struct SegmentCommand64 {
+    cmd: u32,
+    cmdsize: u32,
+    segname: [u8; 16],
+    vmaddr: u64,
+    vmsize: u64,
+    fileoff: u64,
+    filesize: u64,
+    maxprot: i32,
+    initprot: i32,
+    nsects: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct UuidCommand {
+    cmd: u32,
+    cmdsize: u32,
+    uuid: [u8; 16],
+}
+
+extern "C" {
+    fn _dyld_register_func_for_add_image(func: extern "C" fn(*const mach_header, isize));
+    fn _dyld_register_func_for_remove_image(func: extern "C" fn(*const mach_header, isize));
+}
+
+static HOOKS_INSTALLED: AtomicBool = AtomicBool::new(false);
+/// Set while registering, as `dyld` immediately invokes the add callback for
+/// every image that is already loaded
+static REGISTERING: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_image_changed(_header: *const mach_header, _slide: isize) {
+    if !REGISTERING.load(Ordering::Acquire) {
+        super::refresh_cache();
+    }
+}
+
+/// Registers with `dyld` to refresh the cache whenever an image is added or
+/// removed, returning true if this call registered the hooks.
+///
+/// The hooks can't be unregistered, so they stay in place for the lifetime of
+/// the process.
+pub(super) fn install_hooks() -> bool {
+    if HOOKS_INSTALLED.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+
+    REGISTERING.store(true, Ordering::Release);
+    // SAFETY: syscalls, the callback is valid for the lifetime of the process
+    unsafe {
+        _dyld_register_func_for_add_image(on_image_changed);
+        _dyld_register_func_for_remove_image(on_image_changed);
+    }
+    REGISTERING.store(false, Ordering::Release);
+
+    super::refresh_cache();
+    true
+}
+
+pub(super) fn fill(modules: &mut [Module]) -> usize {
+    let mut len = 0;
+
+    // SAFETY: syscall
+    let count = unsafe { dyld::_dyld_image_count() };
+
+    for index in 0..count {
+        if len == modules.len() {
+            break;
+        }
+
+        // SAFETY: syscalls, note that images can be removed concurrently, in
+        // which case these return null
+        let (header, name) = unsafe {
+            (
+                dyld::_dyld_get_image_header(index),
+                dyld::_dyld_get_image_name(index),
+            )
+        };
+
+        if header.is_null() || name.is_null() {
+            continue;
+        }
+
+        // SAFETY: dyld returns valid headers and nul terminated names for
+        // loaded images
+        let module = unsafe {
+            let path = std::ffi::CStr::from_ptr(name).to_string_lossy();
+            to_module(header.cast(), &path)
+        };
+
+        if let Some(module) = module {
+            modules[len] = module;
+            len += 1;
+        }
+    }
+
+    len
+}
+
+/// Determines the size and uuid of the image from its load commands
+///
+/// # Safety
+///
+/// The header must be the header of a loaded image
+unsafe fn to_module(header: *const MachHeader64, path: &str) -> Option<Module> {
+    if (*header).magic != MH_MAGIC_64 {
+        return None;
+    }
+
+    let mut uuid: &[u8] = &[];
+    let mut text_size = None;
+
+    let mut cmd = header.add(1).cast::<u8>();
+    for _ in 0..(*header).ncmds {
+        let lc = &*cmd.cast::<LoadCommand>();
+
+        match lc.cmd {
+            LC_SEGMENT_64 => {
+                let seg = &*cmd.cast::<SegmentCommand64>();
+
+                // Images in the shared cache have their other segments, notably
+                // __LINKEDIT, nowhere near their __TEXT, so only the __TEXT
+                // segment, which starts with the header, is considered part of
+                // the module
+                if seg.segname.starts_with(b"__TEXT\0") {
+                    text_size = Some(seg.vmsize);
+                }
+            }
+            LC_UUID => {
+                uuid = &(*cmd.cast::<UuidCommand>()).uuid;
+            }
+            _ => {}
+        }
+
+        cmd = cmd.add(lc.cmdsize as usize);
+    }
+
+    Some(Module::new(
+        header as usize,
+        text_size? as usize,
+        path,
+        uuid,
+    ))
+}
@@ -0,0 +1,128 @@
+use super::Module;
+use windows_sys::Win32::{
+    Foundation::HINSTANCE,
+    System::{LibraryLoader::GetModuleFileNameW, ProcessStatus, Threading::GetCurrentProcess},
+};
+
+/// The index of the debug directory in the optional header's data directories
+const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+/// The type of a debug directory entry that points to `CodeView` information
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+/// The signature of PDB 7.0 `CodeView` information
+const CV_SIGNATURE_RSDS: u32 = u32::from_le_bytes(*b"RSDS");
+
+pub(super) fn fill(modules: &mut [Module]) -> usize {
+    let mut handles = [0 as HINSTANCE; super::MAX_MODULES];
+    let mut needed = 0;
+
+    // SAFETY: syscalls, the buffer is the size we specify
+    let count = unsafe {
+        let process = GetCurrentProcess();
+        if ProcessStatus::K32EnumProcessModules(
+            process,
+            handles.as_mut_ptr(),
+            std::mem::size_of_val(&handles) as u32,
+            &mut needed,
+        ) == 0
+        {
+            return 0;
+        }
+
+        (needed as usize / std::mem::size_of::<HINSTANCE>()).min(handles.len())
+    };
+
+    let mut len = 0;
+    let mut path = [0u16; 1024];
+
+    for &handle in &handles[..count] {
+        if len == modules.len() {
+            break;
+        }
+
+        // SAFETY: syscalls, modules can be unloaded concurrently, in which
+        // case these fail
+        let (info, path_len) = unsafe {
+            let mut info = std::mem::zeroed::<ProcessStatus::MODULEINFO>();
+            if ProcessStatus::K32GetModuleInformation(
+                GetCurrentProcess(),
+                handle,
+                &mut info,
+                std::mem::size_of::<ProcessStatus::MODULEINFO>() as u32,
+            ) == 0
+            {
+                continue;
+            }
+
+            let path_len = GetModuleFileNameW(handle, path.as_mut_ptr(), path.len() as u32);
+            (info, path_len as usize)
+        };
+
+        let base = info.lpBaseOfDll as usize;
+        let mut build_id = [0u8; 20];
+
+        // SAFETY: the module is loaded, so its headers are mapped
+        let build_id_len = unsafe { read_build_id(base, &mut build_id) };
+
+        modules[len] = Module::new(
+            base,
+            info.SizeOfImage as usize,
+            &String::from_utf16_lossy(&path[..path_len]),
+            &build_id[..build_id_len],
+        );
+        len += 1;
+    }
+
+    len
+}
+
+/// Reads the PDB GUID and age from the `CodeView` debug information of the
+/// module, which is what debug information is keyed by.
+///
+/// # Safety
+///
+/// The base must be the base of a loaded module
+unsafe fn read_build_id(base: usize, build_id: &mut [u8; 20]) -> usize {
+    let read_u16 = |offset: usize| ((base + offset) as *const u16).read_unaligned();
+    let read_u32 = |offset: usize| ((base + offset) as *const u32).read_unaligned();
+
+    // The DOS header points to the NT headers, which start with "PE\0\0"
+    let nt = read_u32(0x3c) as usize;
+    if read_u16(0) != u16::from_le_bytes(*b"MZ") || read_u32(nt) != u32::from_le_bytes(*b"PE\0\0") {
+        return 0;
+    }
+
+    // The optional header follows the 20 byte file header, and its data
+    // directories are at a different offset for PE32 and PE32+
+    let optional = nt + 24;
+    let directories = match read_u16(optional) {
+        0x10b => optional + 96,
+        0x20b => optional + 112,
+        _ => return 0,
+    };
+
+    let debug_rva = read_u32(directories + IMAGE_DIRECTORY_ENTRY_DEBUG * 8) as usize;
+    let debug_size = read_u32(directories + IMAGE_DIRECTORY_ENTRY_DEBUG * 8 + 4) as usize;
+    if debug_rva == 0 {
+        return 0;
+    }
+
+    // Each IMAGE_DEBUG_DIRECTORY is 28 bytes
+    for entry in (debug_rva..debug_rva + debug_size).step_by(28) {
+        if read_u32(entry + 12) != IMAGE_DEBUG_TYPE_CODEVIEW {
+            continue;
+        }
+
+        // AddressOfRawData, which is 0 if the CodeView information is not
+        // mapped
+        let cv = read_u32(entry + 20) as usize;
+        if cv == 0 || read_u32(cv) != CV_SIGNATURE_RSDS {
+            continue;
+        }
+
+        // The GUID followed by the age
+        std::ptr::copy_nonoverlapping((base + cv + 4) as *const u8, build_id.as_mut_ptr(), 20);
+        return 20;
+    }
+
+    0
+}
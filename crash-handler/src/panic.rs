@@ -0,0 +1,101 @@
+//! Routes Rust panics through the attached [`crate::CrashEvent`].
+//!
+//! Unless the process is compiled with `panic = "abort"`, panics never raise
+//! a signal or exception, which means they would otherwise bypass the crash
+//! handler entirely. When the `panic` feature is enabled, attaching a
+//! [`crate::CrashHandler`] also installs a [panic hook](std::panic::set_hook)
+//! that synthesizes a [`crate::CrashContext`] for the panicking thread and
+//! invokes the [`crate::CrashEvent`] with it, the same as for any other crash.
+//!
+//! The panic is reported as if it were an abort, ie. `SIGABRT` on Linux and
+//! macOS, though on Linux it is distinguishable via
+//! [`crate::CrashReason::Panic`], and on Windows it is reported with
+//! [`crate::ExceptionCode::Panic`]. The panic message itself can be retrieved
+//! via [`panic_message`] while the [`crate::CrashEvent`] is running.
+//!
+//! If the [`crate::CrashEvent`] returns [`crate::CrashEventResult::Reraise`],
+//! the panic is passed on to the panic hook that was installed before ours,
+//! which is restored when the handler is detached. Otherwise, the panic
+//! continues as normal after the [`crate::CrashEvent`] returns, unless it
+//! requested the process exit with a specific code.
+
+use std::{panic::PanicHookInfo, sync::Arc};
+
+type PanicHook = Arc<dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+/// The panic hook that was installed when we installed our own
+static PREVIOUS_HOOK: parking_lot::Mutex<Option<PanicHook>> = parking_lot::const_mutex(None);
+
+/// The message of the panic that is currently being handled
+static PANIC_MESSAGE: parking_lot::Mutex<Option<String>> = parking_lot::const_mutex(None);
+
+/// Retrieves the message, including the location, of the panic that is
+/// currently being handled by the [`crate::CrashEvent`].
+///
+/// Returns `None` if the crash being handled is not a panic.
+pub fn panic_message() -> Option<String> {
+    PANIC_MESSAGE.lock().clone()
+}
+
+/// Installs our panic hook, if it isn't already installed
+pub(crate) fn install() {
+    let mut previous = PREVIOUS_HOOK.lock();
+    if previous.is_some() {
+        return;
+    }
+
+    *previous = Some(Arc::from(std::panic::take_hook()));
+    std::panic::set_hook(Box::new(on_panic));
+}
+
+/// Restores the panic hook that was installed before ours
+pub(crate) fn uninstall() {
+    // The panic hook can't be changed while panicking, in which case our hook
+    // is left in place, but will just defer to the previous hook now that the
+    // handler has been detached
+    if std::thread::panicking() {
+        return;
+    }
+
+    if let Some(previous) = PREVIOUS_HOOK.lock().take() {
+        std::panic::set_hook(Box::new(move |info| previous(info)));
+    }
+}
+
+fn on_panic(info: &PanicHookInfo<'_>) {
+    *PANIC_MESSAGE.lock() = Some(info.to_string());
+
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            let handled = is_handled(crate::linux::simulate_panic());
+        } else if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+            let handled = is_handled(crate::bsd::simulate_panic());
+        } else if #[cfg(target_os = "windows")] {
+            let handled = is_handled(crate::windows::simulate_panic());
+        } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
+            let handled = crate::mac::simulate_panic();
+        }
+    }
+
+    PANIC_MESSAGE.lock().take();
+
+    if !handled {
+        let previous = PREVIOUS_HOOK.lock().clone();
+        if let Some(previous) = previous {
+            previous(info);
+        }
+    }
+}
+
+/// Determines whether the previous panic hook should be skipped, exiting the
+/// process if that is what the handler requested
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "tvos")))]
+fn is_handled(result: crate::CrashEventResult) -> bool {
+    match result {
+        crate::CrashEventResult::Handled { exit: Some(code) } => crate::exit_process(code),
+        crate::CrashEventResult::Handled { exit: None } | crate::CrashEventResult::Continue => true,
+        // Jumping out of a panic hook would skip the unwinding of the stack
+        // entirely, so it is treated the same as not handling the panic
+        crate::CrashEventResult::Reraise | crate::CrashEventResult::Jump { .. } => false,
+    }
+}
@@ -0,0 +1,122 @@
+//! Recovery from crashes within a specific region of code.
+//!
+//! [`catch_crash`] runs a closure, and if it crashes on the calling thread
+//! while a [`crate::CrashHandler`] is attached, execution returns from
+//! [`catch_crash`] with the signal or exception that occurred, rather than
+//! the crash being passed to the [`crate::CrashEvent`]. This is useful to
+//! sandbox calls that are known to be risky, eg. into third party FFI code,
+//! without needing to use [`crate::jmp`] and [`crate::CrashEventResult::Jump`]
+//! directly.
+//!
+//! Unlike [`crate::CrashEventResult::Jump`], the jump back to [`catch_crash`]
+//! is performed by the crate's own signal handler/exception filter before any
+//! of its internal state is locked, so the [`crate::CrashHandler`] continues
+//! to function normally afterwards.
+//!
+//! ```no_run
+//! use crash_handler::recover::catch_crash;
+//!
+//! let result = catch_crash(|| unsafe { sadness_generator::raise_segfault() });
+//! assert!(result.is_err());
+//! ```
+//!
+//! Note that no destructors are run for anything on the stack between the
+//! crash and [`catch_crash`], so any resources owned by the closure, eg. a
+//! `MutexGuard`, are leaked, and any state it was modifying may be left
+//! inconsistent.
+//!
+//! On Windows, crashes are only caught by the unhandled exception filter,
+//! invalid parameter handler, and purecall handler, ie. not when attached in
+//! [`crate::HandlerMode::FirstChance`] mode, as the vectored exception handler
+//! can't distinguish crashes from exceptions that will be handled further up
+//! the stack.
+
+use crate::jmp::JmpBuf;
+use std::{cell::Cell, fmt, mem, ptr};
+
+thread_local! {
+    /// The jump buffer of the innermost [`catch_crash`] on the current thread
+    static RECOVERY_POINT: Cell<*mut JmpBuf> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// A crash that was caught by [`catch_crash`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CaughtCrash {
+    /// The signal number on Linux/Android and the BSDs, or the exception code
+    /// on Windows
+    pub code: i32,
+}
+
+impl fmt::Display for CaughtCrash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "windows")] {
+                write!(f, "caught exception 0x{:x}", self.code)
+            } else {
+                write!(f, "caught signal {}", self.code)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CaughtCrash {}
+
+/// Restores the previous recovery point when dropped, including when the
+/// closure unwinds
+struct RestoreRecoveryPoint(*mut JmpBuf);
+
+impl Drop for RestoreRecoveryPoint {
+    #[inline]
+    fn drop(&mut self) {
+        RECOVERY_POINT.with(|rp| rp.set(self.0));
+    }
+}
+
+/// Runs the closure, returning an error if it crashed.
+///
+/// This has no effect if a [`crate::CrashHandler`] is not attached, in which
+/// case the crash is handled as normal. Calls can be nested, in which case
+/// the innermost call catches the crash.
+#[inline(never)]
+pub fn catch_crash<R>(f: impl FnOnce() -> R) -> Result<R, CaughtCrash> {
+    // The closure is only ever called in the branch for the first return from
+    // setjmp, so we can't let the compiler drop it in the other branch
+    let mut f = mem::ManuallyDrop::new(f);
+    let mut jmp_buf = mem::MaybeUninit::<JmpBuf>::uninit();
+    let previous = RECOVERY_POINT.with(|rp| rp.get());
+
+    // SAFETY: the buffer outlives the recovery point, as the previous recovery
+    // point is restored before we return
+    let code = unsafe {
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "windows")] {
+                crate::jmp::setjmp(jmp_buf.as_mut_ptr())
+            } else {
+                // Save the signal mask, as the signal will still be blocked
+                // when we jump back from the signal handler
+                crate::jmp::sigsetjmp(jmp_buf.as_mut_ptr(), 1)
+            }
+        }
+    };
+
+    let _restore = RestoreRecoveryPoint(previous);
+
+    if code == 0 {
+        RECOVERY_POINT.with(|rp| rp.set(jmp_buf.as_mut_ptr()));
+        // SAFETY: this is the only place the closure is taken
+        let f = unsafe { mem::ManuallyDrop::take(&mut f) };
+        Ok(f())
+    } else {
+        Err(CaughtCrash { code })
+    }
+}
+
+/// Takes the recovery point for the current thread, if the thread is within
+/// [`catch_crash`], so that the crash handler can jump back to it.
+///
+/// This is async signal safe.
+#[inline]
+pub(crate) fn take_recovery_point() -> Option<*mut JmpBuf> {
+    let jmp_buf = RECOVERY_POINT.with(|rp| rp.replace(ptr::null_mut()));
+    (!jmp_buf.is_null()).then_some(jmp_buf)
+}
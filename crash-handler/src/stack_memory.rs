@@ -0,0 +1,452 @@
+//! Capture of the crashing thread's stack memory.
+//!
+//! A reporter that doesn't write a full minidump can still unwind the
+//! crashing thread offline if it sends the thread's registers along with the
+//! raw bytes at the top of its stack. [`capture`] copies the memory starting
+//! at the stack pointer of a [`crate::CrashContext`] into a buffer provided by
+//! the caller, one page at a time, and stops at the first page that can't be
+//! read rather than crashing again, so it can be called from within a
+//! [`crate::CrashEvent`].
+//!
+//! ```no_run
+//! use crash_handler::{make_crash_event, stack_memory, CrashEventResult, CrashHandler};
+//!
+//! let handler = CrashHandler::attach(unsafe {
+//!     make_crash_event(move |cc| {
+//!         // Allocated on the signal stack rather than the heap
+//!         let mut buf = [0u8; 8 * 1024];
+//!
+//!         if let Some(stack) = stack_memory::capture(cc, &mut buf) {
+//!             // ...send stack.start and stack.bytes() along with the registers...
+//!         }
+//!
+//!         CrashEventResult::Handled { exit: None }
+//!     })
+//! });
+//! ```
+//!
+//! Memory is read via `process_vm_readv` on Linux/Android,
+//! `mach_vm_read_overwrite` on Macos, and `ReadProcessMemory` on Windows, all
+//! of which fail rather than fault if the memory isn't readable. The BSDs have
+//! no equivalent, so pages are instead checked with `msync`, which detects
+//! pages that aren't mapped, but not mapped pages that aren't readable, eg.
+//! guard pages.
+
+use crate::CrashContext;
+
+/// The granularity at which readability is checked, which evenly divides the
+/// page size of every supported target
+const PAGE_SIZE: usize = 4096;
+
+/// The number of bytes below the stack pointer that are also captured.
+///
+/// This is the red zone, which leaf functions can use without adjusting the
+/// stack pointer, so may contain live data.
+pub const RED_ZONE: usize = if cfg!(any(
+    all(target_arch = "x86_64", not(target_os = "windows")),
+    all(
+        target_arch = "aarch64",
+        any(target_os = "macos", target_os = "ios", target_os = "tvos")
+    )
+)) {
+    128
+} else {
+    0
+};
+
+/// Memory copied from the stack of the crashing thread
+pub struct StackMemory<'buf> {
+    /// The address the memory was copied from, which is [`RED_ZONE`] bytes
+    /// below the stack pointer if that memory was readable
+    pub start: usize,
+    /// The stack pointer of the crashing thread
+    pub stack_pointer: usize,
+    bytes: &'buf [u8],
+}
+
+impl<'buf> StackMemory<'buf> {
+    /// The bytes copied from the stack, which is shorter than the buffer if
+    /// the stack, or the readable portion of it, ended before the buffer was
+    /// filled
+    #[inline]
+    pub fn bytes(&self) -> &'buf [u8] {
+        self.bytes
+    }
+}
+
+/// The registers of the crashing thread that are needed to walk its stack
+#[derive(Copy, Clone)]
+pub(crate) struct Registers {
+    /// The instruction pointer
+    pub(crate) ip: usize,
+    /// The stack pointer
+    pub(crate) sp: usize,
+    /// The frame pointer, or 0 if the architecture doesn't have a frame
+    /// pointer register that is usable for unwinding
+    pub(crate) fp: usize,
+    /// The link register, or 0 if the architecture doesn't have one
+    pub(crate) lr: usize,
+}
+
+/// Retrieves the registers of the crashing thread
+#[inline]
+pub(crate) fn registers(context: &CrashContext) -> Option<Registers> {
+    imp::registers(context)
+}
+
+/// Retrieves the stack pointer of the crashing thread
+pub fn stack_pointer(context: &CrashContext) -> Option<usize> {
+    let sp = imp::registers(context)?.sp;
+    (sp != 0).then_some(sp)
+}
+
+/// Copies as much of the crashing thread's stack into the buffer as possible,
+/// starting [`RED_ZONE`] bytes below the stack pointer.
+///
+/// Returns `None` if the stack pointer couldn't be retrieved or doesn't point
+/// to readable memory, eg. because the crash was a stack overflow and the
+/// stack pointer is in the guard page.
+///
+/// This doesn't allocate or take any locks.
+pub fn capture<'buf>(context: &CrashContext, buf: &'buf mut [u8]) -> Option<StackMemory<'buf>> {
+    let sp = stack_pointer(context)?;
+
+    // The red zone is captured if it's readable, but it's not unusual for it
+    // to be in a different mapping than the stack pointer, eg. if the stack
+    // overflowed
+    let mut start = sp.saturating_sub(RED_ZONE);
+    if start != sp {
+        let len = (sp - start).min(buf.len());
+        if !read(context, start, &mut buf[..len]) {
+            start = sp;
+        }
+    }
+
+    let mut len = 0;
+    while len < buf.len() {
+        let Some(address) = start.checked_add(len) else {
+            break;
+        };
+
+        let chunk = (PAGE_SIZE - address % PAGE_SIZE).min(buf.len() - len);
+        if !imp::read(context, address, &mut buf[len..len + chunk]) {
+            break;
+        }
+
+        len += chunk;
+    }
+
+    // Only the red zone being readable means the stack pointer itself isn't
+    (start + len > sp).then(|| StackMemory {
+        start,
+        stack_pointer: sp,
+        bytes: &buf[..len],
+    })
+}
+
+/// Reads memory of the crashing process, returning false rather than faulting
+/// if any of it isn't readable
+pub(crate) fn read(context: &CrashContext, address: usize, buf: &mut [u8]) -> bool {
+    let mut len = 0;
+    while len < buf.len() {
+        let Some(address) = address.checked_add(len) else {
+            return false;
+        };
+
+        let chunk = (PAGE_SIZE - address % PAGE_SIZE).min(buf.len() - len);
+        if !imp::read(context, address, &mut buf[len..len + chunk]) {
+            return false;
+        }
+
+        len += chunk;
+    }
+
+    true
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        mod imp {
+            use crate::CrashContext;
+
+            use super::Registers;
+
+            pub(super) fn registers(cc: &CrashContext) -> Option<Registers> {
+                let mc = &cc.context.uc_mcontext;
+
+                cfg_if::cfg_if! {
+                    if #[cfg(target_arch = "x86_64")] {
+                        let gregs = &mc.gregs;
+                        Some(Registers {
+                            ip: gregs[libc::REG_RIP as usize] as usize,
+                            sp: gregs[libc::REG_RSP as usize] as usize,
+                            fp: gregs[libc::REG_RBP as usize] as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(target_arch = "x86")] {
+                        let gregs = &mc.gregs;
+                        Some(Registers {
+                            ip: gregs[libc::REG_EIP as usize] as usize,
+                            sp: gregs[libc::REG_ESP as usize] as usize,
+                            fp: gregs[libc::REG_EBP as usize] as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(target_arch = "aarch64")] {
+                        Some(Registers {
+                            ip: mc.pc as usize,
+                            sp: mc.sp as usize,
+                            fp: mc.regs[29] as usize,
+                            lr: mc.regs[30] as usize,
+                        })
+                    } else if #[cfg(target_arch = "arm")] {
+                        // Which register is the frame pointer, and the layout of
+                        // frame records, differ between ARM and Thumb code
+                        Some(Registers {
+                            ip: mc.arm_pc as usize,
+                            sp: mc.arm_sp as usize,
+                            fp: 0,
+                            lr: mc.arm_lr as usize,
+                        })
+                    } else if #[cfg(target_arch = "riscv64")] {
+                        // REG_PC, REG_RA, REG_SP, and REG_S0, which libc only
+                        // defines for glibc
+                        Some(Registers {
+                            ip: mc.__gregs[0] as usize,
+                            sp: mc.__gregs[2] as usize,
+                            fp: mc.__gregs[8] as usize,
+                            lr: mc.__gregs[1] as usize,
+                        })
+                    }
+                }
+            }
+
+            pub(super) fn read(cc: &CrashContext, address: usize, buf: &mut [u8]) -> bool {
+                let local = libc::iovec {
+                    iov_base: buf.as_mut_ptr().cast(),
+                    iov_len: buf.len(),
+                };
+                let remote = libc::iovec {
+                    iov_base: address as *mut _,
+                    iov_len: buf.len(),
+                };
+
+                // SAFETY: syscall, the local buffer is valid for its length
+                let read = unsafe { libc::process_vm_readv(cc.pid, &local, 1, &remote, 1, 0) };
+                read == buf.len() as isize
+            }
+        }
+    } else if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+        mod imp {
+            use crate::CrashContext;
+
+            use super::Registers;
+
+            pub(super) fn registers(cc: &CrashContext) -> Option<Registers> {
+                cfg_if::cfg_if! {
+                    if #[cfg(all(target_os = "freebsd", target_arch = "x86_64"))] {
+                        let mc = &cc.context.uc_mcontext;
+                        Some(Registers {
+                            ip: mc.mc_rip as usize,
+                            sp: mc.mc_rsp as usize,
+                            fp: mc.mc_rbp as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(all(target_os = "freebsd", target_arch = "aarch64"))] {
+                        let gp = &cc.context.uc_mcontext.mc_gpregs;
+                        Some(Registers {
+                            ip: gp.gp_elr as usize,
+                            sp: gp.gp_sp as usize,
+                            fp: gp.gp_x[29] as usize,
+                            lr: gp.gp_lr as usize,
+                        })
+                    } else if #[cfg(all(target_os = "openbsd", target_arch = "x86_64"))] {
+                        // OpenBSD's ucontext_t is its sigcontext
+                        let sc = &cc.context;
+                        Some(Registers {
+                            ip: sc.sc_rip as usize,
+                            sp: sc.sc_rsp as usize,
+                            fp: sc.sc_rbp as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(all(target_os = "openbsd", target_arch = "aarch64"))] {
+                        let sc = &cc.context;
+                        Some(Registers {
+                            ip: sc.sc_elr as usize,
+                            sp: sc.sc_sp as usize,
+                            fp: sc.sc_x[29] as usize,
+                            lr: sc.sc_lr as usize,
+                        })
+                    } else {
+                        let _ = cc;
+                        None
+                    }
+                }
+            }
+
+            pub(super) fn read(_cc: &CrashContext, address: usize, buf: &mut [u8]) -> bool {
+                let page = address & !(super::PAGE_SIZE - 1);
+
+                // SAFETY: syscall, msync doesn't touch the memory, it only
+                // fails with ENOMEM if the range isn't mapped, after which we
+                // copy within the same page
+                unsafe {
+                    if libc::msync(page as *mut _, super::PAGE_SIZE, libc::MS_ASYNC) != 0 {
+                        return false;
+                    }
+
+                    std::ptr::copy_nonoverlapping(
+                        address as *const u8,
+                        buf.as_mut_ptr(),
+                        buf.len(),
+                    );
+                }
+
+                true
+            }
+        }
+    } else if #[cfg(target_os = "windows")] {
+        mod imp {
+            use crate::CrashContext;
+            use windows_sys::Win32::System::{
+                Diagnostics::Debug::{ReadProcessMemory, EXCEPTION_POINTERS},
+                Threading::GetCurrentProcess,
+            };
+
+            use super::Registers;
+
+            pub(super) fn registers(cc: &CrashContext) -> Option<Registers> {
+                let ptrs = cc.exception_pointers.cast::<EXCEPTION_POINTERS>();
+                if ptrs.is_null() {
+                    return None;
+                }
+
+                // SAFETY: the exception pointers are valid for the duration of
+                // the crash event
+                let context = unsafe {
+                    let context = (*ptrs).ContextRecord;
+                    if context.is_null() {
+                        return None;
+                    }
+                    &*context
+                };
+
+                cfg_if::cfg_if! {
+                    if #[cfg(target_arch = "x86_64")] {
+                        Some(Registers {
+                            ip: context.Rip as usize,
+                            sp: context.Rsp as usize,
+                            fp: context.Rbp as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(target_arch = "x86")] {
+                        Some(Registers {
+                            ip: context.Eip as usize,
+                            sp: context.Esp as usize,
+                            fp: context.Ebp as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(target_arch = "aarch64")] {
+                        // SAFETY: every variant of the union is plain integers
+                        let x = unsafe { context.Anonymous.X };
+                        Some(Registers {
+                            ip: context.Pc as usize,
+                            sp: context.Sp as usize,
+                            fp: x[29] as usize,
+                            lr: x[30] as usize,
+                        })
+                    }
+                }
+            }
+
+            pub(super) fn read(_cc: &CrashContext, address: usize, buf: &mut [u8]) -> bool {
+                let mut read = 0;
+
+                // SAFETY: syscall, the buffer is valid for its length
+                let res = unsafe {
+                    ReadProcessMemory(
+                        GetCurrentProcess(),
+                        address as *const _,
+                        buf.as_mut_ptr().cast(),
+                        buf.len(),
+                        &mut read,
+                    )
+                };
+
+                res != 0 && read == buf.len()
+            }
+        }
+    } else if #[cfg(any(target_os = "macos", target_os = "ios", target_os = "tvos"))] {
+        mod imp {
+            use crate::CrashContext;
+            use mach2::{
+                kern_return::KERN_SUCCESS, thread_act::thread_get_state, thread_status as ts,
+            };
+
+            use super::Registers;
+
+            pub(super) fn registers(cc: &CrashContext) -> Option<Registers> {
+                cfg_if::cfg_if! {
+                    if #[cfg(target_arch = "x86_64")] {
+                        type State = mach2::structs::x86_thread_state64_t;
+                        let flavor = ts::x86_THREAD_STATE64;
+                    } else if #[cfg(target_arch = "aarch64")] {
+                        type State = mach2::structs::arm_thread_state64_t;
+                        let flavor = ts::ARM_THREAD_STATE64;
+                    }
+                }
+
+                let mut state = State::new();
+                let mut count = State::count();
+
+                // SAFETY: syscall, the state is the size of the count
+                let kr = unsafe {
+                    thread_get_state(
+                        cc.thread,
+                        flavor,
+                        (&mut state as *mut State).cast(),
+                        &mut count,
+                    )
+                };
+
+                if kr != KERN_SUCCESS {
+                    return None;
+                }
+
+                cfg_if::cfg_if! {
+                    if #[cfg(target_arch = "x86_64")] {
+                        Some(Registers {
+                            ip: state.__rip as usize,
+                            sp: state.__rsp as usize,
+                            fp: state.__rbp as usize,
+                            lr: 0,
+                        })
+                    } else if #[cfg(target_arch = "aarch64")] {
+                        Some(Registers {
+                            ip: state.__pc as usize,
+                            sp: state.__sp as usize,
+                            fp: state.__fp as usize,
+                            lr: state.__lr as usize,
+                        })
+                    }
+                }
+            }
+
+            pub(super) fn read(cc: &CrashContext, address: usize, buf: &mut [u8]) -> bool {
+                let mut read = 0;
+
+                // SAFETY: syscall, the buffer is valid for its length
+                let kr = unsafe {
+                    mach2::vm::mach_vm_read_overwrite(
+                        cc.task,
+                        address as u64,
+                        buf.len() as u64,
+                        buf.as_mut_ptr() as u64,
+                        &mut read,
+                    )
+                };
+
+                kr == KERN_SUCCESS && read == buf.len() as u64
+            }
+        }
+    }
+}
@@ -1,6 +1,226 @@
+mod handler_slot;
 mod pthread_interpose;
+mod thread_set;
+
+pub(crate) use handler_slot::HandlerSlot;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use thread_set::current_thread;
+pub(crate) use thread_set::{Entry as ThreadSetEntry, ThreadSet};
 
 // Force this function to be linked, but it shouldn't actually be called by
 // users directly as it interposes the libc `pthread_create`
 #[doc(hidden)]
 pub use pthread_interpose::pthread_create;
+
+use std::{
+    mem, ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// std::cmp::max is not const :(
+const fn default_alt_stack_size() -> usize {
+    if libc::SIGSTKSZ > 16 * 1024 {
+        libc::SIGSTKSZ
+    } else {
+        16 * 1024
+    }
+}
+
+/// The default size of the alternate stack that is mapped for every thread.
+///
+/// This has a minimum size of 16k, which might seem a bit large, but this
+/// memory will only ever be committed in case we actually get a stack overflow,
+/// which is (hopefully) exceedingly rare
+pub(crate) const DEFAULT_ALT_STACK_SIZE: usize = default_alt_stack_size();
+
+/// The size of the alternate stack that is mapped for every thread, which can
+/// be configured by the user when attaching the handler
+static ALT_STACK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_ALT_STACK_SIZE);
+
+#[inline]
+pub(crate) fn alt_stack_size() -> usize {
+    ALT_STACK_SIZE.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub(crate) fn set_alt_stack_size(size: usize) {
+    ALT_STACK_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Retrieves the minimum size of an alternate signal stack.
+///
+/// Newer Linux kernels report the actual minimum in the auxiliary vector, as it
+/// depends on the size of the register state that is pushed onto the stack
+/// (eg. AVX-512), which can be larger than the static `MINSIGSTKSZ`.
+pub(crate) fn min_alt_stack_size() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            /// We define this ourselves as it is missing from libc
+            const AT_MINSIGSTKSZ: libc::c_ulong = 51;
+
+            // SAFETY: syscall
+            let dynamic = unsafe { libc::getauxval(AT_MINSIGSTKSZ) } as usize;
+            dynamic.max(libc::MINSIGSTKSZ)
+        } else {
+            libc::MINSIGSTKSZ
+        }
+    }
+}
+
+struct StackSave {
+    old: Option<libc::stack_t>,
+    new: libc::stack_t,
+}
+
+unsafe impl Send for StackSave {}
+
+static STACK_SAVE: parking_lot::Mutex<Option<StackSave>> = parking_lot::const_mutex(None);
+
+/// Create an alternative stack to run the signal handlers on. This is done since
+/// the signal might have been caused by a stack overflow.
+pub(crate) unsafe fn install_sigaltstack() -> Result<(), crate::Error> {
+    use crate::InstallStep;
+
+    let stack_size = alt_stack_size();
+
+    // Nothing has been changed if any of the steps fail, other than the
+    // mapping, which is unmapped again
+    let fail = |step| crate::Error::install(step, std::io::Error::last_os_error(), true);
+
+    // Check to see if the existing sigaltstack, and if it exists, is it big
+    // enough. If so we don't need to allocate our own.
+    let mut old_stack: libc::stack_t = mem::zeroed();
+    if libc::sigaltstack(ptr::null(), &mut old_stack) != 0 {
+        return Err(fail(InstallStep::QueryAltStack));
+    }
+
+    if old_stack.ss_flags & libc::SS_DISABLE == 0 && old_stack.ss_size >= stack_size {
+        return Ok(());
+    }
+
+    // ... but failing that we need to allocate our own, so do all that
+    // here.
+    let guard_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+    let alloc_size = guard_size + stack_size;
+
+    let ptr = libc::mmap(
+        ptr::null_mut(),
+        alloc_size,
+        libc::PROT_NONE,
+        libc::MAP_PRIVATE | libc::MAP_ANON,
+        -1,
+        0,
+    );
+    if ptr == libc::MAP_FAILED {
+        return Err(fail(InstallStep::AllocateAltStack { size: stack_size }));
+    }
+
+    // Prepare the stack with readable/writable memory and then register it
+    // with `sigaltstack`.
+    let stack_ptr = (ptr as usize + guard_size) as *mut libc::c_void;
+    if libc::mprotect(stack_ptr, stack_size, libc::PROT_READ | libc::PROT_WRITE) != 0 {
+        let err = fail(InstallStep::AllocateAltStack { size: stack_size });
+        libc::munmap(ptr, alloc_size);
+        return Err(err);
+    }
+
+    let new_stack = libc::stack_t {
+        ss_sp: stack_ptr,
+        ss_flags: 0,
+        ss_size: stack_size,
+    };
+    if libc::sigaltstack(&new_stack, ptr::null_mut()) != 0 {
+        // The kernel refuses to replace the stack the thread is executing on
+        let err = fail(if old_stack.ss_flags & libc::SS_ONSTACK != 0 {
+            InstallStep::AltStackInUse {
+                size: old_stack.ss_size,
+            }
+        } else {
+            InstallStep::RegisterAltStack
+        });
+        libc::munmap(ptr, alloc_size);
+        return Err(err);
+    }
+
+    *STACK_SAVE.lock() = Some(StackSave {
+        old: (old_stack.ss_flags & libc::SS_DISABLE == 0).then_some(old_stack),
+        new: new_stack,
+    });
+
+    Ok(())
+}
+
+/// Returns true if the alternate stack was installed by us, either when the
+/// handler was attached, or for a thread created via `pthread_create`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn is_our_alt_stack(stack: &libc::stack_t) -> bool {
+    STACK_SAVE
+        .lock()
+        .as_ref()
+        .is_some_and(|ss| ss.new.ss_sp == stack.ss_sp)
+        || pthread_interpose::is_thread_alt_stack(stack.ss_sp)
+}
+
+/// Determines if the syscall that failed with the error was most likely
+/// denied by a seccomp filter, ie. it failed with one of the errors filters
+/// typically return, and the process is actually running under a filter
+pub(crate) fn denied_by_seccomp(error: &std::io::Error) -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            /// `SECCOMP_MODE_FILTER`, which is missing from libc
+            const SECCOMP_MODE_FILTER: libc::c_int = 2;
+
+            if !matches!(
+                error.raw_os_error(),
+                Some(libc::EPERM | libc::EACCES | libc::ENOSYS)
+            ) {
+                return false;
+            }
+
+            // SAFETY: syscall, this can't be called in strict mode, as we
+            // would have already been killed by the syscall that failed
+            unsafe { libc::prctl(libc::PR_GET_SECCOMP) == SECCOMP_MODE_FILTER }
+        } else {
+            let _ = error;
+            false
+        }
+    }
+}
+
+pub(crate) unsafe fn restore_sigaltstack() {
+    let mut ssl = STACK_SAVE.lock();
+
+    // Only restore the old_stack if the current alternative stack is the one
+    // installed by the call to install_sigaltstack.
+    if let Some(ss) = &mut *ssl {
+        let mut current_stack = mem::zeroed();
+        if libc::sigaltstack(ptr::null(), &mut current_stack) == -1 {
+            return;
+        }
+
+        if current_stack.ss_sp == ss.new.ss_sp {
+            if let Some(old) = ss.old {
+                // Restore the old alt stack if there was one
+                if libc::sigaltstack(&old, ptr::null_mut()) == -1 {
+                    return;
+                }
+            } else {
+                // Restore to the default alt stack otherwise
+                let mut disable: libc::stack_t = mem::zeroed();
+                disable.ss_flags = libc::SS_DISABLE;
+                if libc::sigaltstack(&disable, ptr::null_mut()) == -1 {
+                    return;
+                }
+            }
+        }
+
+        // Unmap the guard page along with the stack itself
+        let guard_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let r = libc::munmap(
+            (ss.new.ss_sp as usize - guard_size) as *mut libc::c_void,
+            ss.new.ss_size + guard_size,
+        );
+        debug_assert_eq!(r, 0, "munmap failed during thread shutdown");
+        *ssl = None;
+    }
+}
@@ -0,0 +1,141 @@
+//! Storage for the attached handler that can be read from a signal handler
+//! without ever blocking.
+//!
+//! The handler is stored as a pointer to an immutable value that is swapped
+//! atomically when it is attached or detached, along with the threads that
+//! are currently reading it, so that detaching waits for any readers on other
+//! threads to finish before dropping the handler, similarly to RCU.
+
+use super::thread_set::{self, ThreadSet};
+use std::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+pub(crate) struct HandlerSlot<T> {
+    value: AtomicPtr<T>,
+    readers: ThreadSet,
+}
+
+impl<T> HandlerSlot<T> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            value: AtomicPtr::new(ptr::null_mut()),
+            readers: ThreadSet::new(),
+        }
+    }
+
+    /// Stores the value, dropping any previous value.
+    ///
+    /// Callers must serialize calls to this and [`Self::take`] themselves.
+    pub(crate) fn set(&self, value: T) {
+        let previous = self
+            .value
+            .swap(Box::into_raw(Box::new(value)), Ordering::SeqCst);
+        self.release(previous);
+    }
+
+    /// Removes and drops the value, returning true if there was one.
+    ///
+    /// Callers must serialize calls to this and [`Self::set`] themselves.
+    pub(crate) fn take(&self) -> bool {
+        let previous = self.value.swap(ptr::null_mut(), Ordering::SeqCst);
+        self.release(previous)
+    }
+
+    /// Drops a value that has been removed from the slot once no readers can
+    /// still be using it
+    fn release(&self, value: *mut T) -> bool {
+        if value.is_null() {
+            return false;
+        }
+
+        // If the current thread is a reader itself, eg. because the handler is
+        // being detached from within the user's callback, waiting would never
+        // finish, so we leak the value instead, which is fine since the
+        // process is most likely about to terminate anyway
+        if self.readers.contains(thread_set::current_thread()) {
+            return true;
+        }
+
+        // Any reader that starts after the swap will see the new value, so we
+        // only need to wait for the ones that were already in progress, eg. a
+        // crash on another thread that is still running the user's callback
+        while !self.readers.is_empty() {
+            // Readers that didn't fit in a slot can't be told apart, so the
+            // current thread may be one of them, in which case waiting would
+            // never finish either, so the value is leaked the same as above
+            if self.readers.has_overflow() {
+                return true;
+            }
+
+            std::thread::yield_now();
+        }
+
+        // SAFETY: the pointer was created by `Box::into_raw` in `set`, and
+        // there are no readers left that could be using it
+        drop(unsafe { Box::from_raw(value) });
+        true
+    }
+
+    /// Forgets the readers on every thread other than the one that forked,
+    /// see [`ThreadSet::after_fork`]
+    #[inline]
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn after_fork(&self, parent: usize, child: usize) {
+        self.readers.after_fork(parent, child);
+    }
+
+    /// Retrieves the current value, if there is one.
+    ///
+    /// This is async signal safe, as it never blocks.
+    #[inline]
+    pub(crate) fn read(&self) -> Option<SlotGuard<'_, T>> {
+        let guard = SlotGuard {
+            _reader: self.readers.insert(thread_set::current_thread()),
+            value: self.value.load(Ordering::SeqCst),
+        };
+
+        (!guard.value.is_null()).then_some(guard)
+    }
+}
+
+/// Keeps the value in a [`HandlerSlot`] alive until it is dropped
+pub(crate) struct SlotGuard<'slot, T> {
+    _reader: thread_set::Entry<'slot>,
+    value: *mut T,
+}
+
+impl<T> std::ops::Deref for SlotGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: the value is not dropped while there are any readers, and
+        // we only create guards with non-null values
+        unsafe { &*self.value }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Detaching from within the callback of a thread whose read didn't fit
+    /// in a slot must not wait for that read to finish
+    #[test]
+    fn release_with_overflowed_reader() {
+        let slot = HandlerSlot::new();
+        slot.set(1u32);
+
+        // Fill every slot with other threads, so that our read overflows
+        let others: Vec<_> = (0..thread_set::CAPACITY)
+            .map(|i| slot.readers.insert(usize::MAX - i))
+            .collect();
+        let guard = slot.read().unwrap();
+        drop(others);
+
+        assert!(slot.take());
+        assert_eq!(*guard, 1);
+    }
+}
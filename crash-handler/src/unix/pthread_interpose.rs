@@ -6,7 +6,10 @@
 #![allow(non_camel_case_types)]
 
 use libc::c_void;
-use std::ptr;
+use std::{
+    ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 pub type pthread_main_t = unsafe extern "C" fn(_: *mut c_void) -> *mut c_void;
 type pthread_create_t = unsafe extern "C" fn(
@@ -25,6 +28,14 @@ struct PthreadCreateParams {
 /// alternate stack memory as per-thread data that is uninstalled and unmapped
 /// in the `pthread_key` destructor
 static mut THREAD_DESTRUCTOR_KEY: libc::pthread_key_t = 0;
+/// Set once [`THREAD_DESTRUCTOR_KEY`] has been created
+static KEY_CREATED: AtomicBool = AtomicBool::new(false);
+
+/// The size of the header at the beginning of each alternate stack mapping,
+/// which holds the total size of the mapping. This is placed at the bottom of
+/// the mapping since the stack grows down, and is large enough to keep the
+/// stack itself 16 byte aligned.
+const ALT_STACK_HEADER_SIZE: usize = 16;
 
 #[cfg(target_env = "musl")]
 extern "C" {
@@ -49,6 +60,7 @@ extern "C" {
 /// libc `pthread_create`, or if we do find the address but it's actually the
 /// address of this interpose function which would result in infinte recursion
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn pthread_create(
     thread: *mut libc::pthread_t,
     attr: *const libc::pthread_attr_t,
@@ -71,18 +83,22 @@ pub extern "C" fn pthread_create(
         #[cfg(not(target_env = "musl"))]
         {
             const RTLD_NEXT: *mut c_void = -1isize as *mut c_void;
-            ptr = libc::dlsym(RTLD_NEXT, b"pthread_create\0".as_ptr().cast());
+            ptr = libc::dlsym(RTLD_NEXT, c"pthread_create".as_ptr());
         }
 
         if !ptr.is_null() {
-            REAL_PTHREAD_CREATE = Some(std::mem::transmute(ptr));
+            REAL_PTHREAD_CREATE = Some(std::mem::transmute::<*mut c_void, pthread_create_t>(ptr));
         }
 
-        libc::pthread_key_create(&mut THREAD_DESTRUCTOR_KEY, Some(uninstall_sig_alt_stack));
+        libc::pthread_key_create(
+            ptr::addr_of_mut!(THREAD_DESTRUCTOR_KEY),
+            Some(uninstall_sig_alt_stack),
+        );
+        KEY_CREATED.store(true, Ordering::Release);
     });
 
-    let real_pthread_create = unsafe { REAL_PTHREAD_CREATE.as_ref() }.expect("pthread_create() intercept failed but the intercept function is still being called, this won't work");
-    assert!(*real_pthread_create != pthread_create as pthread_create_t, "We could not obtain the real pthread_create(). Calling the symbol we got would make us enter an infinte loop so stop here instead.");
+    let real_pthread_create = unsafe { *ptr::addr_of!(REAL_PTHREAD_CREATE) }.expect("pthread_create() intercept failed but the intercept function is still being called, this won't work");
+    assert!(real_pthread_create as *const c_void != pthread_create as *const c_void, "We could not obtain the real pthread_create(). Calling the symbol we got would make us enter an infinte loop so stop here instead.");
 
     let create_params = Box::new(PthreadCreateParams { main, arg });
     let create_params = Box::into_raw(create_params);
@@ -107,22 +123,6 @@ pub extern "C" fn pthread_create(
     result
 }
 
-// std::cmp::max is not const :(
-const fn get_stack_size() -> usize {
-    if libc::SIGSTKSZ > 16 * 1024 {
-        libc::SIGSTKSZ
-    } else {
-        16 * 1024
-    }
-}
-
-/// The size of the alternate stack that is mapped for every thread.
-///
-/// This has a minimum size of 16k, which might seem a bit large, but this
-/// memory will only ever be committed in case we actually get a stack overflow,
-/// which is (hopefully) exceedingly rare
-const SIG_STACK_SIZE: usize = get_stack_size();
-
 /// This is the replacment function for the user's thread entry, it installs
 /// the alternate stack before invoking the original thread entry, then cleans
 /// it up after the user's thread entry exits.
@@ -154,9 +154,11 @@ unsafe extern "C" fn set_alt_signal_stack_and_start(params: *mut c_void) -> *mut
 /// If we're able to map memory, but unable to install the alternate stack, we
 /// expect that we can unmap the memory
 unsafe fn install_sig_alt_stack() -> *mut libc::c_void {
+    let stack_size = super::alt_stack_size();
+    let map_size = stack_size + ALT_STACK_HEADER_SIZE;
     let alt_stack_mem = libc::mmap(
         ptr::null_mut(),
-        SIG_STACK_SIZE,
+        map_size,
         libc::PROT_READ | libc::PROT_WRITE,
         libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
         -1,
@@ -164,14 +166,18 @@ unsafe fn install_sig_alt_stack() -> *mut libc::c_void {
     );
 
     // Check that we successfully mapped some memory
-    if alt_stack_mem.is_null() {
-        return alt_stack_mem;
+    if alt_stack_mem == libc::MAP_FAILED {
+        return ptr::null_mut();
     }
 
+    // The alternate stack size can be changed by the user while this thread is
+    // running, so we record the size of the mapping so we can unmap it correctly
+    alt_stack_mem.cast::<usize>().write(map_size);
+
     let alt_stack = libc::stack_t {
-        ss_sp: alt_stack_mem,
+        ss_sp: alt_stack_mem.cast::<u8>().add(ALT_STACK_HEADER_SIZE).cast(),
         ss_flags: 0,
-        ss_size: SIG_STACK_SIZE,
+        ss_size: stack_size,
     };
 
     // Attempt to install the alternate stack
@@ -179,7 +185,7 @@ unsafe fn install_sig_alt_stack() -> *mut libc::c_void {
 
     // Attempt to cleanup the mapping if we failed to install the alternate stack
     if rv != 0 {
-        assert_eq!(libc::munmap(alt_stack_mem, SIG_STACK_SIZE), 0, "failed to install an alternate signal stack, and failed to unmap the alternate stack memory");
+        assert_eq!(libc::munmap(alt_stack_mem, map_size), 0, "failed to install an alternate signal stack, and failed to unmap the alternate stack memory");
         ptr::null_mut()
     } else {
         alt_stack_mem
@@ -210,9 +216,27 @@ unsafe extern "C" fn uninstall_sig_alt_stack(alt_stack_mem: *mut libc::c_void) {
         0,
         "failed to uninstall alternate signal stack"
     );
+
     assert_eq!(
-        libc::munmap(alt_stack_mem, SIG_STACK_SIZE),
+        libc::munmap(alt_stack_mem, alt_stack_mem.cast::<usize>().read()),
         0,
         "failed to unmap alternate stack memory"
     );
 }
+
+/// Returns true if the stack pointer is that of the alternate stack we
+/// installed for the calling thread when it was created
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(super) fn is_thread_alt_stack(ss_sp: *mut c_void) -> bool {
+    if !KEY_CREATED.load(Ordering::Acquire) {
+        return false;
+    }
+
+    // SAFETY: the key has been created
+    let alt_stack_mem = unsafe { libc::pthread_getspecific(THREAD_DESTRUCTOR_KEY) };
+    !alt_stack_mem.is_null()
+        && alt_stack_mem
+            .cast::<u8>()
+            .wrapping_add(ALT_STACK_HEADER_SIZE)
+            == ss_sp.cast()
+}
@@ -0,0 +1,142 @@
+//! A set of thread ids that can be used from a signal handler in place of
+//! thread local storage.
+//!
+//! Thread locals are not async signal safe in general, eg. the first access
+//! of a thread local in a library that was loaded with `dlopen` can allocate
+//! via `__tls_get_addr`, so the state that the signal handler needs to track
+//! per thread is instead kept in a fixed size table keyed by the thread id.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of threads that can be tracked individually, any more than
+/// this are only counted
+pub(super) const CAPACITY: usize = 64;
+
+/// The id of the current thread, which is never 0.
+///
+/// This is async signal safe.
+#[inline]
+pub(crate) fn current_thread() -> usize {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            // SAFETY: syscall
+            unsafe { libc::syscall(libc::SYS_gettid) as usize }
+        } else {
+            // SAFETY: syscall
+            unsafe { libc::pthread_self() as usize }
+        }
+    }
+}
+
+pub(crate) struct ThreadSet {
+    slots: [AtomicUsize; CAPACITY],
+    /// The number of entries that didn't fit in a slot
+    overflow: AtomicUsize,
+}
+
+impl ThreadSet {
+    pub(crate) const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: AtomicUsize = AtomicUsize::new(0);
+
+        Self {
+            slots: [EMPTY; CAPACITY],
+            overflow: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds the thread to the set until the returned entry is dropped. A
+    /// thread can be added multiple times.
+    ///
+    /// This is async signal safe.
+    pub(crate) fn insert(&self, thread: usize) -> Entry<'_> {
+        let slot = self.slots.iter().position(|slot| {
+            slot.compare_exchange(0, thread, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+
+        if slot.is_none() {
+            self.overflow.fetch_add(1, Ordering::SeqCst);
+        }
+
+        Entry { set: self, slot }
+    }
+
+    /// Adds the current thread to the set until the returned entry is
+    /// dropped, unless it is already in it.
+    ///
+    /// This is async signal safe.
+    #[inline]
+    pub(crate) fn enter(&self) -> Option<Entry<'_>> {
+        let thread = current_thread();
+        (!self.contains(thread)).then(|| self.insert(thread))
+    }
+
+    /// Returns true if the thread is in the set, which is only reliable for
+    /// the current thread, as only a thread adds itself.
+    ///
+    /// Note that if the set is full, threads that didn't fit in a slot are
+    /// not considered to be in it.
+    #[inline]
+    pub(crate) fn contains(&self, thread: usize) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.load(Ordering::SeqCst) == thread)
+    }
+
+    /// Returns true if any entry didn't fit in a slot, which can't be
+    /// attributed to a thread
+    #[inline]
+    pub(crate) fn has_overflow(&self) -> bool {
+        self.overflow.load(Ordering::SeqCst) != 0
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.overflow.load(Ordering::SeqCst) == 0
+            && self
+                .slots
+                .iter()
+                .all(|slot| slot.load(Ordering::SeqCst) == 0)
+    }
+
+    /// Called in the child after a `fork`, where the only thread left is the
+    /// one that forked, which now has a different id.
+    ///
+    /// Every other thread is removed, as their entries would otherwise never
+    /// be dropped. This is async signal safe.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn after_fork(&self, parent: usize, child: usize) {
+        for slot in &self.slots {
+            let thread = slot.load(Ordering::SeqCst);
+            slot.store(if thread == parent { child } else { 0 }, Ordering::SeqCst);
+        }
+
+        // There is no way to tell which of these belonged to the forking thread
+        self.overflow.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Removes a thread from a [`ThreadSet`] when dropped
+pub(crate) struct Entry<'set> {
+    set: &'set ThreadSet,
+    slot: Option<usize>,
+}
+
+impl Drop for Entry<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        match self.slot {
+            Some(slot) => self.set.slots[slot].store(0, Ordering::SeqCst),
+            None => {
+                // Saturating, as the count is reset after a fork
+                let _ =
+                    self.set
+                        .overflow
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                            count.checked_sub(1)
+                        });
+            }
+        }
+    }
+}
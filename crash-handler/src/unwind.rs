@@ -0,0 +1,248 @@
+//! Best effort unwinding of the crashing thread's stack.
+//!
+//! Writing a minidump and symbolicating it offline gives the most accurate
+//! backtrace, but is overkill if all that is wanted is an immediate, rough
+//! backtrace in a log. [`unwind`] produces the return addresses of the
+//! crashing thread by following its chain of frame pointers, and if that chain
+//! is broken, eg. because the code was compiled without frame pointers, by
+//! scanning the stack for values that point into a loaded module.
+//!
+//! ```no_run
+//! use crash_handler::{make_crash_event, unwind, write, CrashEventResult, CrashHandler};
+//!
+//! crash_handler::modules::refresh();
+//!
+//! let handler = CrashHandler::attach(unsafe {
+//!     make_crash_event(move |cc| {
+//!         let mut frames = [unwind::Frame::EMPTY; 32];
+//!         for frame in unwind::unwind(cc, &mut frames) {
+//!             write::write_hex(write::STDERR, frame.address as u64);
+//!             write::write_str(write::STDERR, "\n");
+//!         }
+//!
+//!         CrashEventResult::Handled { exit: None }
+//!     })
+//! });
+//! ```
+//!
+//! Since DWARF or PE unwind information is not used, the result can both miss
+//! frames, notably the caller of a leaf function that doesn't set up a frame,
+//! and, when scanning, include addresses that are merely left over on the
+//! stack. Scanning is only performed if the [`crate::modules`] cache has been
+//! refreshed, as stack values can't otherwise be distinguished from return
+//! addresses, and is not available on the BSDs.
+
+use crate::{stack_memory, CrashContext};
+
+/// The maximum number of words that are scanned for return addresses once the
+/// frame pointer chain is broken
+pub const MAX_SCAN_WORDS: usize = 1024;
+
+/// Frame records that are farther than this above the stack pointer are
+/// considered invalid
+const MAX_FRAME_DISTANCE: usize = 8 * 1024 * 1024;
+
+const WORD: usize = std::mem::size_of::<usize>();
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        /// The frame pointer points to the top of the frame, and the frame
+        /// record, ie. the previous frame pointer followed by the return
+        /// address, is stored just below it
+        const RECORD_OFFSET: isize = -2 * WORD as isize;
+    } else {
+        /// The frame pointer points to the frame record, ie. the previous
+        /// frame pointer followed by the return address
+        const RECORD_OFFSET: isize = 0;
+    }
+}
+
+/// How an unwound frame was found, from most to least reliable
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FrameTrust {
+    /// The address was taken from the crash context's registers
+    Context,
+    /// The address was taken from a frame record found via the frame
+    /// pointer chain
+    FramePointer,
+    /// The address was found by scanning the stack for values that point
+    /// into a loaded module
+    Scan,
+}
+
+/// A frame of the crashing thread's stack
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The instruction pointer for the first frame, and the return address
+    /// for the others, which is the address of the instruction following the
+    /// call, so symbolicators will usually want to look up `address - 1`
+    pub address: usize,
+    /// How the frame was found
+    pub trust: FrameTrust,
+}
+
+impl Frame {
+    /// An empty frame, for initializing the buffer passed to [`unwind`]
+    pub const EMPTY: Self = Self {
+        address: 0,
+        trust: FrameTrust::Scan,
+    };
+}
+
+struct Frames<'f> {
+    frames: &'f mut [Frame],
+    len: usize,
+}
+
+impl<'f> Frames<'f> {
+    /// Adds a frame, returning false if the buffer is full
+    #[inline]
+    fn push(&mut self, address: usize, trust: FrameTrust) -> bool {
+        if self.len == self.frames.len() {
+            return false;
+        }
+
+        self.frames[self.len] = Frame { address, trust };
+        self.len += 1;
+        self.len < self.frames.len()
+    }
+}
+
+/// Unwinds the stack of the crashing thread, filling the buffer with as many
+/// frames as could be found, starting with the crashing frame, and returns
+/// the filled portion.
+///
+/// This doesn't allocate or take any locks, and never faults even if the
+/// stack is corrupted.
+pub fn unwind<'f>(context: &CrashContext, frames: &'f mut [Frame]) -> &'f [Frame] {
+    let mut frames = Frames { frames, len: 0 };
+
+    if let Some(regs) = stack_memory::registers(context) {
+        walk(context, regs, &mut frames);
+    }
+
+    let Frames { frames, len } = frames;
+    &frames[..len]
+}
+
+fn walk(context: &CrashContext, regs: stack_memory::Registers, frames: &mut Frames<'_>) {
+    if regs.ip == 0 || !frames.push(regs.ip, FrameTrust::Context) {
+        return;
+    }
+
+    let mut fp = regs.fp;
+    let mut sp = regs.sp;
+    let mut first = true;
+
+    while let Some((prev_fp, ret)) = read_record(context, fp, sp) {
+        // A null return address marks the outermost frame
+        if ret == 0 {
+            return;
+        }
+
+        if !is_code(ret) {
+            break;
+        }
+
+        if !frames.push(ret, FrameTrust::FramePointer) {
+            return;
+        }
+
+        first = false;
+
+        // A null frame pointer also marks the outermost frame
+        if prev_fp == 0 {
+            return;
+        }
+
+        // The frames of callers are always above the frames of their callees
+        if prev_fp <= fp {
+            break;
+        }
+
+        sp = fp.wrapping_add_signed(RECORD_OFFSET) + 2 * WORD;
+        fp = prev_fp;
+    }
+
+    // If the frame pointer was unusable from the start, the crashing function
+    // likely doesn't set up a frame, in which case its return address is
+    // still in the link register
+    if first && regs.lr != 0 && is_code(regs.lr) && !frames.push(regs.lr, FrameTrust::Context) {
+        return;
+    }
+
+    scan(context, sp, frames);
+}
+
+/// Reads the frame record the frame pointer points to, if it plausibly points
+/// to one
+fn read_record(context: &CrashContext, fp: usize, sp: usize) -> Option<(usize, usize)> {
+    let record = fp.checked_add_signed(RECORD_OFFSET)?;
+
+    if !fp.is_multiple_of(WORD) || record < sp || record - sp > MAX_FRAME_DISTANCE {
+        return None;
+    }
+
+    let mut buf = [0u8; 2 * WORD];
+    if !stack_memory::read(context, record, &mut buf) {
+        return None;
+    }
+
+    let (prev_fp, ret) = buf.split_at(WORD);
+    Some((
+        usize::from_ne_bytes(prev_fp.try_into().ok()?),
+        usize::from_ne_bytes(ret.try_into().ok()?),
+    ))
+}
+
+/// Scans the stack above the stack pointer for values that point into a
+/// loaded module
+fn scan(context: &CrashContext, sp: usize, frames: &mut Frames<'_>) {
+    #[cfg(not(any(target_os = "freebsd", target_os = "openbsd")))]
+    crate::modules::with_cached(|modules| {
+        if modules.is_empty() {
+            return;
+        }
+
+        let mut buf = [0u8; 64 * WORD];
+        let mut address = sp - sp % WORD;
+
+        for _ in 0..MAX_SCAN_WORDS / 64 {
+            if !stack_memory::read(context, address, &mut buf) {
+                return;
+            }
+
+            for word in buf.chunks_exact(WORD) {
+                let mut value = [0u8; WORD];
+                value.copy_from_slice(word);
+                let value = usize::from_ne_bytes(value);
+
+                if modules.iter().any(|module| module.contains(value))
+                    && !frames.push(value, FrameTrust::Scan)
+                {
+                    return;
+                }
+            }
+
+            address += buf.len();
+        }
+    });
+
+    #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+    let _ = (context, sp, frames);
+}
+
+/// Checks if the address plausibly points to code, which can only be
+/// determined if the modules cache has been refreshed
+#[inline]
+fn is_code(address: usize) -> bool {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+            address != 0
+        } else {
+            crate::modules::with_cached(|modules| {
+                modules.is_empty() || modules.iter().any(|module| module.contains(address))
+            })
+        }
+    }
+}
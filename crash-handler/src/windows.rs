@@ -1,7 +1,8 @@
 pub mod jmp;
 mod state;
+pub mod wer;
 
-use crate::Error;
+use crate::{events::EventId, Error};
 
 use windows_sys::Win32::Foundation as found;
 
@@ -21,21 +22,195 @@ pub enum ExceptionCode {
     Trap = found::EXCEPTION_BREAKPOINT,
     InvalidParameter = found::STATUS_INVALID_PARAMETER,
     Purecall = found::STATUS_NONCONTINUABLE_EXCEPTION,
+    HeapCorruption = crash_context::STATUS_HEAP_CORRUPTION,
+    /// Raised by `__fastfail`, including for stack buffer overruns
+    FailFast = crash_context::STATUS_STACK_BUFFER_OVERRUN,
+    /// A Rust panic routed through the handler by the `panic` feature. This
+    /// is the code of the MSVC C++ exceptions that panics are implemented with
+    Panic = 0xe06d_7363_u32 as i32,
+}
+
+/// Determines which exceptions are delivered to the [`CrashHandler`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum HandlerMode {
+    /// Only exceptions that are not handled by the application are delivered,
+    /// via an [unhandled exception filter](https://docs.microsoft.com/en-us/windows/win32/api/errhandlingapi/nf-errhandlingapi-setunhandledexceptionfilter),
+    /// ie. exceptions that would otherwise terminate the process
+    #[default]
+    LastChance,
+    /// Every exception is delivered, via a [vectored exception handler](https://docs.microsoft.com/en-us/windows/win32/debug/vectored-exception-handling),
+    /// before any frame based handlers get a chance to handle it, which can
+    /// be useful for logging.
+    ///
+    /// Note that this includes exceptions that the application handles itself
+    /// as part of its normal operation, as well as informational ones, such
+    /// as those used to print to, or name threads for, an attached debugger.
+    ///
+    /// If the handler returns [`crate::CrashEventResult::Continue`], execution
+    /// continues at the point the exception occurred, which means the handler
+    /// must have fixed whatever caused the exception, eg. by modifying the
+    /// context, otherwise the exception will occur again. If
+    /// [`crate::CrashEventResult::Reraise`] is returned, the exception is
+    /// processed as if the handler was not attached, and if
+    /// [`crate::CrashEventResult::Handled`] is returned, the process is
+    /// terminated immediately, without any other handlers seeing the
+    /// exception.
+    FirstChance,
 }
 
 /// A Windows exception handler
-pub struct CrashHandler;
+pub struct CrashHandler {
+    id: EventId,
+    /// Cleared when the handler is detached
+    _marker: Option<crate::marker::CrashMarker>,
+}
+
+/// Configures a [`CrashHandler`] before attaching it
+pub struct CrashHandlerBuilder {
+    priority: i32,
+    mode: HandlerMode,
+    marker: Option<std::path::PathBuf>,
+    wer_module: Option<std::path::PathBuf>,
+}
+
+impl CrashHandlerBuilder {
+    /// Sets the priority of the handler, which determines the order in which
+    /// it is invoked relative to any other attached handlers. Defaults to
+    /// [`crate::DEFAULT_PRIORITY`].
+    ///
+    /// Handlers with a higher priority are invoked first, and handlers with
+    /// the same priority are invoked in the reverse order they were attached
+    /// in. Once a handler returns anything other than
+    /// [`crate::CrashEventResult::Reraise`], no further handlers are invoked.
+    #[inline]
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets the [`HandlerMode`], which determines which exceptions are
+    /// delivered to the handler. Defaults to [`HandlerMode::LastChance`].
+    #[inline]
+    pub fn mode(mut self, mode: HandlerMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Writes a [`crate::marker::CrashMarker`] to the specified path when the
+    /// handler is attached, which is cleared when the handler is detached, so
+    /// that [`crate::marker::CrashMarker::previous_run`] can detect if the
+    /// process was terminated abnormally, eg. via `TerminateProcess`, on the
+    /// next run.
+    #[inline]
+    pub fn crash_marker(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.marker = Some(path.into());
+        self
+    }
+
+    /// Registers the DLL at the specified path as a WER runtime exception
+    /// module once the handler is attached, see
+    /// [`CrashHandler::register_wer_module`]
+    #[inline]
+    pub fn wer_module(mut self, dll_path: impl Into<std::path::PathBuf>) -> Self {
+        self.wer_module = Some(dll_path.into());
+        self
+    }
+
+    /// Attaches the exception handler with the current configuration.
+    ///
+    /// If another handler is already attached, only the priority applies, as
+    /// the exception handlers, and thus the mode, are shared by every
+    /// attached handler.
+    ///
+    /// See [`CrashHandler::attach`]
+    pub fn attach(self, on_crash: Box<dyn crate::CrashEvent>) -> Result<CrashHandler, Error> {
+        // Written before attaching so that it is removed again if attaching fails
+        let marker = self
+            .marker
+            .map(crate::marker::CrashMarker::create)
+            .transpose()?;
+
+        let id = state::attach(on_crash, self.priority, self.mode)?;
+        let handler = CrashHandler {
+            id,
+            _marker: marker,
+        };
+
+        if let Some(dll_path) = &self.wer_module {
+            handler.register_wer_module(dll_path)?;
+        }
+
+        Ok(handler)
+    }
+}
+
+impl Default for CrashHandlerBuilder {
+    fn default() -> Self {
+        Self {
+            priority: crate::DEFAULT_PRIORITY,
+            mode: HandlerMode::LastChance,
+            marker: None,
+            wer_module: None,
+        }
+    }
+}
 
 #[allow(clippy::unused_self)]
 impl CrashHandler {
+    /// Creates a builder that can be used to configure the handler before it
+    /// is attached
+    #[inline]
+    pub fn builder() -> CrashHandlerBuilder {
+        CrashHandlerBuilder::default()
+    }
+
     /// Attaches the crash handler.
     ///
     /// The provided callback will be invoked if an exception is caught,
     /// providing a [`crate::CrashContext`] with the details of the thread where
     /// the exception was thrown.
+    ///
+    /// Multiple handlers can be attached at the same time, see
+    /// [`CrashHandlerBuilder::priority`] for the order they are invoked in.
     pub fn attach(on_crash: Box<dyn crate::CrashEvent>) -> Result<Self, Error> {
-        state::attach(on_crash)?;
-        Ok(Self)
+        Self::builder().attach(on_crash)
+    }
+
+    /// Attaches the crash handler, in the specified [`HandlerMode`].
+    ///
+    /// If another handler is already attached, the mode it was attached with
+    /// is used instead, as the exception handlers are shared by every attached
+    /// handler.
+    ///
+    /// See [`CrashHandlerBuilder::mode`]
+    pub fn attach_with_mode(
+        mode: HandlerMode,
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, Error> {
+        Self::builder().mode(mode).attach(on_crash)
+    }
+
+    /// Attaches the crash handler with the specified priority.
+    ///
+    /// See [`CrashHandlerBuilder::priority`]
+    pub fn attach_with_priority(
+        priority: i32,
+        on_crash: Box<dyn crate::CrashEvent>,
+    ) -> Result<Self, Error> {
+        Self::builder().priority(priority).attach(on_crash)
+    }
+
+    /// Registers the DLL at the specified path as a [WER runtime exception module](https://docs.microsoft.com/en-us/windows/win32/api/werapi/nf-werapi-werregisterruntimeexceptionmodule)
+    /// so that crashes that never reach the unhandled exception filter, such
+    /// as fast fails, are still delivered to this handler.
+    ///
+    /// The DLL must be implemented with [`wer::handle_exception`], and WER
+    /// must be configured to trust it by adding a `DWORD` value with the path
+    /// of the DLL to `HKEY_LOCAL_MACHINE\SOFTWARE\Microsoft\Windows\Windows Error Reporting\RuntimeExceptionHelperModules`.
+    ///
+    /// The module is unregistered when the last attached handler is detached.
+    pub fn register_wer_module(&self, dll_path: &std::path::Path) -> Result<(), Error> {
+        wer::register(dll_path)
     }
 
     /// Detaches this handler, removing it from the handler stack.
@@ -43,53 +218,26 @@ impl CrashHandler {
     /// This is done automatically when this [`CrashHandler`] is dropped.
     #[inline]
     pub fn detach(self) {
-        state::detach();
+        state::detach(self.id);
     }
 
     // Sends the specified user exception
     #[allow(clippy::unused_self)]
+    #[inline]
     pub fn simulate_exception(&self, exception_code: Option<i32>) -> crate::CrashEventResult {
-        // Normally this would be an unsafe function, since this unsafe encompasses
-        // the entirety of the body, however the user is really not required to
-        // uphold any guarantees on their end, so no real need to declare the
-        // function itself unsafe.
-        unsafe {
-            let lock = state::HANDLER.lock();
-            if let Some(handler) = &*lock {
-                let mut exception_record: state::EXCEPTION_RECORD = std::mem::zeroed();
-                let mut exception_context = std::mem::MaybeUninit::uninit();
-
-                state::RtlCaptureContext(exception_context.as_mut_ptr());
-
-                let mut exception_context = exception_context.assume_init();
-
-                let exception_ptrs = state::EXCEPTION_POINTERS {
-                    ExceptionRecord: &mut exception_record,
-                    ContextRecord: &mut exception_context,
-                };
-
-                let exception_code =
-                    exception_code.unwrap_or(state::STATUS_NONCONTINUABLE_EXCEPTION);
-                exception_record.ExceptionCode = exception_code;
-
-                let cc = crash_context::CrashContext {
-                    exception_pointers: (&exception_ptrs as *const state::EXCEPTION_POINTERS)
-                        .cast(),
-                    process_id: std::process::id(),
-                    thread_id: state::GetCurrentThreadId(),
-                    exception_code,
-                };
-
-                handler.user_handler.on_crash(&cc)
-            } else {
-                crate::CrashEventResult::Handled(false)
-            }
-        }
+        state::simulate_exception(exception_code)
     }
 }
 
+/// Routes a panic through the attached handler, see [`crate::panic`]
+#[cfg(feature = "panic")]
+#[inline]
+pub(crate) fn simulate_panic() -> crate::CrashEventResult {
+    state::simulate_exception(Some(ExceptionCode::Panic as i32))
+}
+
 impl Drop for CrashHandler {
     fn drop(&mut self) {
-        state::detach();
+        state::detach(self.id);
     }
 }
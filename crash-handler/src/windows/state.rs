@@ -1,11 +1,15 @@
 #![allow(non_camel_case_types, clippy::exit)]
 
-use crate::Error;
+use crate::{
+    events::{EventId, Events},
+    Error,
+};
 pub(super) use windows_sys::Win32::{
     Foundation::{STATUS_INVALID_PARAMETER, STATUS_NONCONTINUABLE_EXCEPTION},
     System::{
         Diagnostics::Debug::{
-            RtlCaptureContext, SetUnhandledExceptionFilter, EXCEPTION_POINTERS, EXCEPTION_RECORD,
+            AddVectoredExceptionHandler, RemoveVectoredExceptionHandler, RtlCaptureContext,
+            SetUnhandledExceptionFilter, EXCEPTION_POINTERS, EXCEPTION_RECORD,
             LPTOP_LEVEL_EXCEPTION_FILTER,
         },
         Threading::GetCurrentThreadId,
@@ -52,7 +56,12 @@ pub(super) static HANDLER: parking_lot::Mutex<Option<HandlerInner>> =
     parking_lot::const_mutex(None);
 
 pub(super) struct HandlerInner {
-    pub(super) user_handler: Box<dyn crate::CrashEvent>,
+    pub(super) events: Events,
+    /// Whether exceptions are handled by a vectored handler or the unhandled
+    /// exception filter
+    mode: super::HandlerMode,
+    /// The handle to the vectored exception handler, if one was added
+    vectored_handler: usize,
     /// The previously installed filter before this handler installed its own
     previous_filter: LPTOP_LEVEL_EXCEPTION_FILTER,
     /// The previously installed invalid parameter handler
@@ -62,18 +71,29 @@ pub(super) struct HandlerInner {
 }
 
 impl HandlerInner {
-    pub(crate) fn new(user_handler: Box<dyn crate::CrashEvent>) -> Self {
+    pub(crate) fn new(events: Events, mode: super::HandlerMode) -> Self {
         // Note that breakpad has flags so the user can choose which error handlers
         // to install, but for now we just install all of them
 
         // SAFETY: syscalls
         unsafe {
-            let previous_filter = SetUnhandledExceptionFilter(Some(handle_exception));
+            let (previous_filter, vectored_handler) = match mode {
+                super::HandlerMode::LastChance => {
+                    (SetUnhandledExceptionFilter(Some(handle_exception)), 0)
+                }
+                super::HandlerMode::FirstChance => (
+                    None,
+                    // Add the handler as the first to be called
+                    AddVectoredExceptionHandler(1, Some(handle_vectored_exception)) as usize,
+                ),
+            };
             let previous_iph = _set_invalid_parameter_handler(Some(handle_invalid_parameter));
             let previous_pch = _set_purecall_handler(Some(handle_pure_virtual_call));
 
             Self {
-                user_handler,
+                events,
+                mode,
+                vectored_handler,
                 previous_filter,
                 previous_iph,
                 previous_pch,
@@ -86,34 +106,119 @@ impl HandlerInner {
     pub(crate) fn restore_previous_handlers(&self) {
         // SAFETY: syscalls
         unsafe {
-            SetUnhandledExceptionFilter(self.previous_filter);
+            if self.mode == super::HandlerMode::LastChance {
+                SetUnhandledExceptionFilter(self.previous_filter);
+            }
             _set_invalid_parameter_handler(self.previous_iph);
             _set_purecall_handler(self.previous_pch);
         }
     }
+
+    /// Sets the handlers back to our internal ones
+    fn set_handlers(&self) {
+        // SAFETY: syscalls
+        unsafe {
+            if self.mode == super::HandlerMode::LastChance {
+                SetUnhandledExceptionFilter(Some(handle_exception));
+            }
+            _set_invalid_parameter_handler(Some(handle_invalid_parameter));
+            _set_purecall_handler(Some(handle_pure_virtual_call));
+        }
+    }
 }
 
 impl Drop for HandlerInner {
     fn drop(&mut self) {
         self.restore_previous_handlers();
+
+        if self.vectored_handler != 0 {
+            // SAFETY: syscall
+            unsafe {
+                RemoveVectoredExceptionHandler(self.vectored_handler as *const _);
+            }
+        }
     }
 }
 
-pub(super) fn attach(on_crash: Box<dyn crate::CrashEvent>) -> Result<(), Error> {
+/// Attaches the event, installing our handlers in the specified mode if this
+/// is the first one to be attached
+pub(super) fn attach(
+    on_crash: Box<dyn crate::CrashEvent>,
+    priority: i32,
+    mode: super::HandlerMode,
+) -> Result<EventId, Error> {
     let mut lock = HANDLER.lock();
 
-    if lock.is_some() {
-        return Err(Error::HandlerAlreadyInstalled);
+    if let Some(handler) = &mut *lock {
+        return Ok(handler.events.insert(on_crash, priority));
     }
 
-    *lock = Some(HandlerInner::new(on_crash));
-    Ok(())
+    let mut events = Events::default();
+    let id = events.insert(on_crash, priority);
+    *lock = Some(HandlerInner::new(events, mode));
+
+    #[cfg(feature = "panic")]
+    crate::panic::install();
+
+    Ok(id)
 }
 
-pub(super) fn detach() {
+/// Detaches the event, and if it was the last one, our handlers
+pub(super) fn detach(id: EventId) {
     let mut lock = HANDLER.lock();
+
+    let Some(handler) = &mut *lock else {
+        return;
+    };
+
+    if !handler.events.remove(id) || !handler.events.is_empty() {
+        return;
+    }
+
     // The previous handlers are restored on drop
     lock.take();
+    super::wer::unregister();
+
+    #[cfg(feature = "panic")]
+    crate::panic::uninstall();
+}
+
+pub(super) fn simulate_exception(exception_code: Option<i32>) -> crate::CrashEventResult {
+    // Normally this would be an unsafe function, since this unsafe encompasses
+    // the entirety of the body, however the user is really not required to
+    // uphold any guarantees on their end, so no real need to declare the
+    // function itself unsafe.
+    unsafe {
+        let lock = HANDLER.lock();
+        if let Some(handler) = &*lock {
+            let mut exception_record: EXCEPTION_RECORD = std::mem::zeroed();
+            let mut exception_context = std::mem::MaybeUninit::uninit();
+
+            RtlCaptureContext(exception_context.as_mut_ptr());
+
+            let mut exception_context = exception_context.assume_init();
+
+            let exception_ptrs = EXCEPTION_POINTERS {
+                ExceptionRecord: &mut exception_record,
+                ContextRecord: &mut exception_context,
+            };
+
+            let exception_code = exception_code.unwrap_or(STATUS_NONCONTINUABLE_EXCEPTION);
+            exception_record.ExceptionCode = exception_code;
+
+            let cc = crash_context::CrashContext {
+                exception_pointers: (&exception_ptrs as *const EXCEPTION_POINTERS).cast(),
+                process_id: std::process::id(),
+                thread_id: GetCurrentThreadId(),
+                exception_code,
+                reason: crash_reason(&exception_record),
+            };
+
+            handler.events.on_crash(&cc)
+        } else {
+            crate::CrashEventResult::Reraise
+        }
+    }
 }
 
 /// While handling any exceptions, especially when calling user code, we restore
@@ -139,15 +244,6 @@ impl<'scope> AutoHandler<'scope> {
     }
 }
 
-/// Sets the handlers back to our internal ones
-fn set_handlers() {
-    unsafe {
-        SetUnhandledExceptionFilter(Some(handle_exception));
-        _set_invalid_parameter_handler(Some(handle_invalid_parameter));
-        _set_purecall_handler(Some(handle_pure_virtual_call));
-    }
-}
-
 impl<'scope> std::ops::Deref for AutoHandler<'scope> {
     type Target = HandlerInner;
 
@@ -159,7 +255,9 @@ impl<'scope> std::ops::Deref for AutoHandler<'scope> {
 impl<'scope> Drop for AutoHandler<'scope> {
     fn drop(&mut self) {
         // Restore our handlers
-        set_handlers();
+        if let Some(hi) = &*self.lock {
+            hi.set_handlers();
+        }
     }
 }
 
@@ -167,26 +265,50 @@ impl<'scope> Drop for AutoHandler<'scope> {
 const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
 /// Enter the exception handler.
 pub(super) const EXCEPTION_EXECUTE_HANDLER: i32 = 1;
+/// Continue execution at the point where the exception occurred
+const EXCEPTION_CONTINUE_EXECUTION: i32 = -1;
+
+thread_local! {
+    /// Whether the thread is currently running the user's handler from the
+    /// vectored exception handler
+    static IN_VECTORED_HANDLER: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
 
 use crate::CrashEventResult;
 
+/// Determines the reason for the exception from its record, the first
+/// parameter of which is the fast fail code for fail fast exceptions
+#[inline]
+pub(super) fn crash_reason(record: &EXCEPTION_RECORD) -> crash_context::CrashReason {
+    let fast_fail_code =
+        (record.NumberParameters > 0).then_some(record.ExceptionInformation[0] as u32);
+    crash_context::CrashReason::from_exception(record.ExceptionCode, fast_fail_code)
+}
+
 /// Called on the exception thread when an unhandled exception occurs.
 /// Signals the exception handler thread to handle the exception.
 pub(super) unsafe extern "system" fn handle_exception(
     except_info: *const EXCEPTION_POINTERS,
 ) -> i32 {
+    // The exception occurred within `catch_crash`, so jump straight back to it
+    // before touching any of our own state
+    if let Some(jmp_buf) = crate::recover::take_recovery_point() {
+        super::jmp::longjmp(jmp_buf, (*(*except_info).ExceptionRecord).ExceptionCode);
+    }
+
     let jump = {
         let lock = HANDLER.lock();
         if let Some(current_handler) = AutoHandler::new(lock) {
             let code = (*(*except_info).ExceptionRecord).ExceptionCode;
 
-            match current_handler.user_handler.on_crash(&crate::CrashContext {
+            match current_handler.events.on_crash(&crate::CrashContext {
                 exception_pointers: except_info.cast(),
                 process_id: std::process::id(),
                 thread_id: GetCurrentThreadId(),
                 exception_code: code,
+                reason: crash_reason(&*(*except_info).ExceptionRecord),
             }) {
-                CrashEventResult::Handled(true) => {
+                CrashEventResult::Handled { exit: None } => {
                     // The handler fully handled the exception.  Returning
                     // EXCEPTION_EXECUTE_HANDLER indicates this to the system, and usually
                     // results in the application being terminated.
@@ -196,7 +318,9 @@ pub(super) unsafe extern "system" fn handle_exception(
                     // application to be restarted.
                     return EXCEPTION_EXECUTE_HANDLER;
                 }
-                CrashEventResult::Handled(false) => {
+                CrashEventResult::Handled { exit: Some(code) } => crate::exit_process(code),
+                CrashEventResult::Continue => return EXCEPTION_CONTINUE_EXECUTION,
+                CrashEventResult::Reraise => {
                     // There was an exception, it was a breakpoint or something else ignored
                     // above, or it was passed to the handler, which decided not to handle it.
                     // Give the previous handler a chance to do something with the exception.
@@ -219,6 +343,49 @@ pub(super) unsafe extern "system" fn handle_exception(
     super::jmp::longjmp(jump.0, jump.1);
 }
 
+/// Called on the exception thread for every exception, before any frame based
+/// handlers, when attached in [`super::HandlerMode::FirstChance`] mode
+unsafe extern "system" fn handle_vectored_exception(except_info: *mut EXCEPTION_POINTERS) -> i32 {
+    // Exceptions raised by the user's handler would otherwise be delivered to
+    // it again, deadlocking on the handler lock
+    if IN_VECTORED_HANDLER.with(|ivh| ivh.replace(true)) {
+        return EXCEPTION_CONTINUE_SEARCH;
+    }
+
+    let result = {
+        let lock = HANDLER.lock();
+        if let Some(current_handler) = &*lock {
+            let code = (*(*except_info).ExceptionRecord).ExceptionCode;
+
+            current_handler.events.on_crash(&crate::CrashContext {
+                exception_pointers: except_info.cast_const().cast(),
+                process_id: std::process::id(),
+                thread_id: GetCurrentThreadId(),
+                exception_code: code,
+                reason: crash_reason(&*(*except_info).ExceptionRecord),
+            })
+        } else {
+            CrashEventResult::Reraise
+        }
+    };
+
+    IN_VECTORED_HANDLER.with(|ivh| ivh.set(false));
+
+    match result {
+        // Terminate the process the same way the system would for an
+        // unhandled exception, before any other handler can see it
+        CrashEventResult::Handled { exit } => {
+            crate::exit_process(exit.unwrap_or((*(*except_info).ExceptionRecord).ExceptionCode))
+        }
+        // The handler dealt with the exception, eg. by fixing up the context,
+        // so execution can continue where the exception occurred
+        CrashEventResult::Continue => EXCEPTION_CONTINUE_EXECUTION,
+        // Let the exception be processed as normal
+        CrashEventResult::Reraise => EXCEPTION_CONTINUE_SEARCH,
+        CrashEventResult::Jump { jmp_buf, value } => super::jmp::longjmp(jmp_buf, value),
+    }
+}
+
 /// Handler for invalid parameters to CRT functions, this is not an exception so
 /// the context (shouldn't be) isn't compromised
 ///
@@ -236,6 +403,10 @@ unsafe extern "C" fn handle_invalid_parameter(
     line: u32,
     reserved: usize,
 ) {
+    if let Some(jmp_buf) = crate::recover::take_recovery_point() {
+        super::jmp::longjmp(jmp_buf, STATUS_INVALID_PARAMETER);
+    }
+
     let jump = {
         let lock = HANDLER.lock();
         if let Some(current_handler) = AutoHandler::new(lock) {
@@ -257,14 +428,20 @@ unsafe extern "C" fn handle_invalid_parameter(
 
             exception_record.ExceptionCode = STATUS_INVALID_PARAMETER;
 
-            match current_handler.user_handler.on_crash(&crate::CrashContext {
+            match current_handler.events.on_crash(&crate::CrashContext {
                 exception_pointers: (&exception_ptrs as *const EXCEPTION_POINTERS).cast(),
                 process_id: std::process::id(),
                 thread_id: GetCurrentThreadId(),
                 exception_code: STATUS_INVALID_PARAMETER,
+                reason: crash_context::CrashReason::Exception,
             }) {
-                CrashEventResult::Handled(true) => return,
-                CrashEventResult::Handled(false) => {
+                CrashEventResult::Handled { exit } => {
+                    crate::exit_process(exit.unwrap_or(STATUS_INVALID_PARAMETER))
+                }
+                // The CRT function that was passed the invalid parameter
+                // returns an error to its caller
+                CrashEventResult::Continue => return,
+                CrashEventResult::Reraise => {
                     if let Some(prev_iph) = current_handler.previous_iph {
                         prev_iph(expression, function, file, line, reserved);
                     } else {
@@ -306,6 +483,10 @@ unsafe extern "C" fn handle_invalid_parameter(
 /// context (shouldn't be) isn't compromised
 #[no_mangle]
 unsafe extern "C" fn handle_pure_virtual_call() {
+    if let Some(jmp_buf) = crate::recover::take_recovery_point() {
+        super::jmp::longjmp(jmp_buf, STATUS_NONCONTINUABLE_EXCEPTION);
+    }
+
     let jump = {
         let lock = HANDLER.lock();
         if let Some(current_handler) = AutoHandler::new(lock) {
@@ -327,19 +508,23 @@ unsafe extern "C" fn handle_pure_virtual_call() {
 
             exception_record.ExceptionCode = STATUS_NONCONTINUABLE_EXCEPTION;
 
-            match current_handler.user_handler.on_crash(&crate::CrashContext {
+            match current_handler.events.on_crash(&crate::CrashContext {
                 exception_pointers: (&exception_ptrs as *const EXCEPTION_POINTERS).cast(),
                 process_id: std::process::id(),
                 thread_id: GetCurrentThreadId(),
                 exception_code: STATUS_NONCONTINUABLE_EXCEPTION,
+                reason: crash_context::CrashReason::Exception,
             }) {
-                CrashEventResult::Handled(true) => {
-                    // The handler either took care of the invalid parameter problem itself,
-                    // or passed it on to another handler. "Swallow" it by exiting, paralleling
-                    // the behavior of "swallowing" exceptions.
-                    std::process::exit(0);
+                CrashEventResult::Handled { exit } => {
+                    // The handler took care of the pure virtual call itself, so
+                    // "swallow" it by exiting, paralleling the behavior of
+                    // "swallowing" exceptions.
+                    crate::exit_process(exit.unwrap_or(STATUS_NONCONTINUABLE_EXCEPTION))
                 }
-                CrashEventResult::Handled(false) => {
+                // There is nowhere to continue to, so this is the same as
+                // reraising without a previous handler
+                CrashEventResult::Continue => return,
+                CrashEventResult::Reraise => {
                     if let Some(pch) = current_handler.previous_pch {
                         // The handler didn't fully handle the exception.  Give it to the
                         // previous purecall handler.
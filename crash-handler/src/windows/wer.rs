@@ -0,0 +1,258 @@
+//! Support for [WER runtime exception modules](https://docs.microsoft.com/en-us/windows/win32/api/werapi/nf-werapi-werregisterruntimeexceptionmodule).
+//!
+//! Some crashes, such as [fast fails](https://docs.microsoft.com/en-us/cpp/intrinsics/fastfail)
+//! raised for heap corruption or stack buffer overruns, or a stack overflow
+//! that leaves too little stack to run the unhandled exception filter, never
+//! reach the filter installed by the [`crate::CrashHandler`]. These are
+//! identified by the [`crash_context::CrashReason`] of the crash context. Windows Error
+//! Reporting (WER) however is still notified of these crashes, and can load a
+//! helper DLL, registered via [`crate::CrashHandler::register_wer_module`],
+//! into the `WerFault.exe` process that is handling the crash.
+//!
+//! The helper DLL then calls [`handle_exception`] from its
+//! `OutOfProcessExceptionEventCallback` export, which creates a thread in
+//! the crashed process that runs the attached handler with the crash context
+//! that WER provided.
+//!
+//! ```ignore
+//! use windows_sys::Win32::{
+//!     Foundation::{BOOL, E_NOTIMPL, HRESULT, S_OK},
+//!     System::ErrorReporting::WER_RUNTIME_EXCEPTION_INFORMATION,
+//! };
+//!
+//! #[no_mangle]
+//! pub unsafe extern "system" fn OutOfProcessExceptionEventCallback(
+//!     context: *const std::ffi::c_void,
+//!     exception_information: *const WER_RUNTIME_EXCEPTION_INFORMATION,
+//!     ownership_claimed: *mut BOOL,
+//!     _event_name: *mut u16,
+//!     _event_name_len: *mut u32,
+//!     _signature_count: *mut u32,
+//! ) -> HRESULT {
+//!     let claimed = crash_handler::wer::handle_exception(context, &*exception_information)
+//!         .unwrap_or(false);
+//!     *ownership_claimed = claimed.into();
+//!     S_OK
+//! }
+//!
+//! // WER requires these to be exported as well, even if they are never
+//! // called since we never claim ownership of the crash
+//! #[no_mangle]
+//! pub extern "system" fn OutOfProcessExceptionEventSignatureCallback() -> HRESULT {
+//!     E_NOTIMPL
+//! }
+//!
+//! #[no_mangle]
+//! pub extern "system" fn OutOfProcessExceptionEventDebuggerLaunchCallback() -> HRESULT {
+//!     E_NOTIMPL
+//! }
+//! ```
+//!
+//! Note that the helper DLL must be built with the same version of this crate
+//! and for the same architecture as the process it is registered for, as the
+//! memory of the crashed process is accessed directly.
+
+use super::state;
+use crate::{CrashEventResult, Error};
+use std::{cell::UnsafeCell, ffi::c_void};
+use windows_sys::Win32::{
+    Foundation::CloseHandle,
+    System::{
+        Diagnostics::Debug::{
+            ReadProcessMemory, WriteProcessMemory, CONTEXT, EXCEPTION_POINTERS, EXCEPTION_RECORD,
+        },
+        ErrorReporting::{
+            WerRegisterRuntimeExceptionModule, WerUnregisterRuntimeExceptionModule,
+            WER_RUNTIME_EXCEPTION_INFORMATION,
+        },
+        Threading::{CreateRemoteThread, GetExitCodeThread, GetThreadId, WaitForSingleObject},
+    },
+};
+
+/// Waits forever
+const INFINITE: u32 = u32::MAX;
+
+/// The state shared between the crashed process and the helper DLL, which
+/// accesses it via `Read/WriteProcessMemory`
+#[repr(C)]
+struct Registration {
+    /// The address of [`handle_wer_exception`] in the crashed process
+    entry: usize,
+    /// The id of the thread that crashed
+    thread_id: u32,
+    /// The exception record for the crash, copied from WER
+    exception_record: EXCEPTION_RECORD,
+    /// The context of the crashing thread, copied from WER
+    context: CONTEXT,
+}
+
+struct RegistrationCell(UnsafeCell<Registration>);
+
+// SAFETY: The registration is only written to by the helper DLL, from another
+// process, before it creates the thread that reads it
+unsafe impl Sync for RegistrationCell {}
+
+static REGISTRATION: RegistrationCell = RegistrationCell(UnsafeCell::new(Registration {
+    entry: 0,
+    thread_id: 0,
+    // SAFETY: both are POD
+    exception_record: unsafe { std::mem::zeroed() },
+    context: unsafe { std::mem::zeroed() },
+}));
+
+/// The path of the currently registered helper DLL, nul terminated
+static MODULE: parking_lot::Mutex<Option<Vec<u16>>> = parking_lot::const_mutex(None);
+
+pub(super) fn register(dll_path: &std::path::Path) -> Result<(), Error> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut module = MODULE.lock();
+
+    let dll_path: Vec<u16> = dll_path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+
+    // SAFETY: syscalls, the registration lives for the duration of the process
+    unsafe {
+        let registration = REGISTRATION.0.get();
+        (*registration).entry = handle_wer_exception as *const () as usize;
+
+        if let Some(previous) = module.take() {
+            WerUnregisterRuntimeExceptionModule(previous.as_ptr(), registration.cast());
+        }
+
+        let hr = WerRegisterRuntimeExceptionModule(dll_path.as_ptr(), registration.cast());
+        if hr < 0 {
+            return Err(std::io::Error::from_raw_os_error(hr).into());
+        }
+    }
+
+    *module = Some(dll_path);
+    Ok(())
+}
+
+pub(super) fn unregister() {
+    if let Some(dll_path) = MODULE.lock().take() {
+        // SAFETY: syscall
+        unsafe {
+            WerUnregisterRuntimeExceptionModule(dll_path.as_ptr(), REGISTRATION.0.get().cast());
+        }
+    }
+}
+
+/// Runs the attached handler for the crash that WER notified the helper DLL
+/// of, from a thread created in the crashed process by [`handle_exception`].
+///
+/// Returns 1 if the handler handled the crash
+unsafe extern "system" fn handle_wer_exception(param: *mut c_void) -> u32 {
+    let registration = &mut *param.cast::<Registration>();
+
+    // The crashing thread may have been holding the lock, eg. if it overflowed
+    // its stack while running the handler, so don't wait on it
+    let Some(lock) = state::HANDLER.try_lock() else {
+        return 0;
+    };
+    let Some(handler) = &*lock else {
+        return 0;
+    };
+
+    let exception_ptrs = EXCEPTION_POINTERS {
+        ExceptionRecord: &mut registration.exception_record,
+        ContextRecord: &mut registration.context,
+    };
+
+    let cc = crate::CrashContext {
+        exception_pointers: (&exception_ptrs as *const EXCEPTION_POINTERS).cast(),
+        process_id: std::process::id(),
+        thread_id: registration.thread_id,
+        exception_code: registration.exception_record.ExceptionCode,
+        reason: state::crash_reason(&registration.exception_record),
+    };
+
+    // Jumping or continuing is not possible since we are not on the crashing
+    // thread, and WER terminates the process regardless
+    match handler.events.on_crash(&cc) {
+        CrashEventResult::Handled { .. } => 1,
+        _ => 0,
+    }
+}
+
+/// Runs the handler attached in the crashed process described by the
+/// exception information that WER passed to the helper DLL's
+/// `OutOfProcessExceptionEventCallback`, along with the `context` pointer,
+/// and waits for it to finish.
+///
+/// Returns `true` if the handler handled the crash.
+///
+/// # Safety
+///
+/// This must only be called from a helper DLL loaded by WER, with the
+/// parameters passed to `OutOfProcessExceptionEventCallback` for a process
+/// that registered the helper DLL via [`crate::CrashHandler::register_wer_module`]
+///
+/// # Errors
+///
+/// An error is returned if the memory of the crashed process cannot be
+/// accessed, or the thread running the handler could not be created
+pub unsafe fn handle_exception(
+    context: *const c_void,
+    exception_information: &WER_RUNTIME_EXCEPTION_INFORMATION,
+) -> Result<bool, Error> {
+    let process = exception_information.hProcess;
+
+    let mut registration: Registration = std::mem::zeroed();
+    if ReadProcessMemory(
+        process,
+        context,
+        (&mut registration as *mut Registration).cast(),
+        std::mem::size_of::<Registration>(),
+        std::ptr::null_mut(),
+    ) == 0
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    registration.thread_id = GetThreadId(exception_information.hThread);
+    registration.exception_record = exception_information.exceptionRecord;
+    registration.context = exception_information.context;
+
+    if WriteProcessMemory(
+        process,
+        context,
+        (&registration as *const Registration).cast(),
+        std::mem::size_of::<Registration>(),
+        std::ptr::null_mut(),
+    ) == 0
+    {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let thread = CreateRemoteThread(
+        process,
+        std::ptr::null(),
+        0,
+        std::mem::transmute::<usize, Option<unsafe extern "system" fn(*mut c_void) -> u32>>(
+            registration.entry,
+        ),
+        context,
+        0,
+        std::ptr::null_mut(),
+    );
+    if thread == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    WaitForSingleObject(thread, INFINITE);
+
+    let mut exit_code = 0;
+    let res = GetExitCodeThread(thread, &mut exit_code);
+    CloseHandle(thread);
+
+    if res == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    Ok(exit_code == 1)
+}
@@ -0,0 +1,187 @@
+//! Async signal safe writing and formatting.
+//!
+//! Logging from within a [`crate::CrashEvent`] is tricky, as the usual
+//! suspects such as `println!` or `eprintln!` lock stdout/stderr, which may
+//! already be held by the crashing thread, and can allocate. The utilities in
+//! this module format into buffers on the stack and write directly to a raw
+//! file descriptor with `write(2)`, so that crash details can be written to
+//! stderr, or a file that was opened before the crash, without violating
+//! [signal safety](https://man7.org/linux/man-pages/man7/signal-safety.7.html).
+//!
+//! ```
+//! use crash_handler::{dprintf, write};
+//!
+//! write::write_str(write::STDERR, "crashed at ");
+//! write::write_hex(write::STDERR, 0xdead_beef);
+//! write::write_str(write::STDERR, "\n");
+//!
+//! dprintf!(write::STDERR, "signal {} in thread {}\n", 11, 1234);
+//! ```
+
+/// A raw file descriptor
+pub type RawFd = libc::c_int;
+
+/// The file descriptor for stdout
+pub const STDOUT: RawFd = 1;
+/// The file descriptor for stderr
+pub const STDERR: RawFd = 2;
+
+/// The maximum length of a formatted `u64`, in decimal
+pub const DEC_BUF_LEN: usize = 20;
+/// The maximum length of a formatted `u64`, in hex, including the `0x` prefix
+pub const HEX_BUF_LEN: usize = 18;
+
+/// Writes all of the bytes to the file descriptor, retrying if interrupted.
+///
+/// # Errors
+///
+/// The call to `write(2)` failed, or the file descriptor was closed
+pub fn write_bytes(fd: RawFd, mut buf: &[u8]) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        // SAFETY: syscall, the buffer is valid for the specified length
+        let written = unsafe {
+            cfg_if::cfg_if! {
+                if #[cfg(target_os = "windows")] {
+                    libc::write(fd, buf.as_ptr().cast(), buf.len().min(u32::MAX as usize) as u32) as isize
+                } else {
+                    libc::write(fd, buf.as_ptr().cast(), buf.len())
+                }
+            }
+        };
+
+        match written {
+            n if n > 0 => buf = &buf[n as usize..],
+            0 => return Err(std::io::ErrorKind::WriteZero.into()),
+            _ => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the string to the file descriptor.
+///
+/// Errors are ignored, as there is rarely anything useful to do about them
+/// while handling a crash, use [`write_bytes`] to observe them.
+#[inline]
+pub fn write_str(fd: RawFd, s: &str) {
+    let _res = write_bytes(fd, s.as_bytes());
+}
+
+/// Writes the integer to the file descriptor, in decimal
+#[inline]
+pub fn write_dec(fd: RawFd, n: u64) {
+    let mut buf = [0u8; DEC_BUF_LEN];
+    write_str(fd, format_dec(n, &mut buf));
+}
+
+/// Writes the integer to the file descriptor, in hex with a `0x` prefix
+#[inline]
+pub fn write_hex(fd: RawFd, n: u64) {
+    let mut buf = [0u8; HEX_BUF_LEN];
+    write_str(fd, format_hex(n, &mut buf));
+}
+
+/// Formats the integer in decimal into the end of the buffer, returning the
+/// formatted string
+pub fn format_dec(mut n: u64, buf: &mut [u8; DEC_BUF_LEN]) -> &str {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+
+        if n == 0 {
+            break;
+        }
+    }
+
+    // SAFETY: only ascii digits were written
+    unsafe { std::str::from_utf8_unchecked(&buf[start..]) }
+}
+
+/// Formats the integer in lowercase hex, with a `0x` prefix, into the end of
+/// the buffer, returning the formatted string
+pub fn format_hex(mut n: u64, buf: &mut [u8; HEX_BUF_LEN]) -> &str {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = DIGITS[(n & 0xf) as usize];
+        n >>= 4;
+
+        if n == 0 {
+            break;
+        }
+    }
+
+    buf[start - 2..start].copy_from_slice(b"0x");
+
+    // SAFETY: only ascii was written
+    unsafe { std::str::from_utf8_unchecked(&buf[start - 2..]) }
+}
+
+/// A [`std::fmt::Write`] implementation that writes directly to a file
+/// descriptor, used by [`crate::dprintf!`].
+///
+/// Note that while `core::fmt` itself neither allocates nor takes locks, the
+/// [`std::fmt::Display`] and [`std::fmt::Debug`] implementations of the
+/// arguments being formatted might, so it is up to the caller to only format
+/// types that are safe to format, such as integers and strings.
+pub struct FdWriter {
+    fd: RawFd,
+}
+
+impl FdWriter {
+    /// Creates a writer for the file descriptor, which is not closed when the
+    /// writer is dropped
+    #[inline]
+    pub fn new(fd: RawFd) -> Self {
+        Self { fd }
+    }
+}
+
+impl std::fmt::Write for FdWriter {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> std::fmt::Result {
+        write_bytes(self.fd, s.as_bytes()).map_err(|_e| std::fmt::Error)
+    }
+}
+
+/// Formats and writes the arguments directly to the file descriptor, similarly
+/// to [`dprintf(3)`](https://man7.org/linux/man-pages/man3/dprintf.3.html).
+///
+/// See [`write::FdWriter`](crate::write::FdWriter) for caveats
+#[macro_export]
+macro_rules! dprintf {
+    ($fd:expr, $($arg:tt)*) => {{
+        let _res = ::std::fmt::Write::write_fmt(
+            &mut $crate::write::FdWriter::new($fd),
+            ::std::format_args!($($arg)*),
+        );
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_integers() {
+        let mut dec = [0u8; DEC_BUF_LEN];
+        assert_eq!(format_dec(0, &mut dec), "0");
+        assert_eq!(format_dec(1234567890, &mut dec), "1234567890");
+        assert_eq!(format_dec(u64::MAX, &mut dec), u64::MAX.to_string());
+
+        let mut hex = [0u8; HEX_BUF_LEN];
+        assert_eq!(format_hex(0, &mut hex), "0x0");
+        assert_eq!(format_hex(0xdead_beef, &mut hex), "0xdeadbeef");
+        assert_eq!(format_hex(u64::MAX, &mut hex), "0xffffffffffffffff");
+    }
+}
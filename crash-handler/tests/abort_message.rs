@@ -0,0 +1,62 @@
+//! Ensures that the abort message is recorded for aborts, and only for aborts
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::Mutex;
+
+#[test]
+fn records_abort_message() {
+    static MESSAGES: Mutex<Vec<Option<String>>> = Mutex::new(Vec::new());
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            MESSAGES
+                .lock()
+                .unwrap()
+                .push(cc.abort_message().map(str::to_owned));
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    ch::abort_message::set("queue is empty\0and this is cut off");
+    handler.simulate_signal(ch::Signal::Abort);
+    handler.simulate_signal(ch::Signal::Trap);
+
+    ch::abort_message::set(&"é".repeat(crash_context::ABORT_MESSAGE_LEN));
+    handler.simulate_signal(ch::Signal::Abort);
+
+    ch::abort_message::clear();
+    handler.simulate_signal(ch::Signal::Abort);
+
+    ch::abort_message::install_panic_hook();
+    assert!(std::panic::catch_unwind(|| panic!("oh no: {}", 42)).is_err());
+    let panic_line = line!() - 1;
+    handler.simulate_signal(ch::Signal::Abort);
+
+    // Taken out of the lock, as with the `panic` feature a failed assertion
+    // would invoke the handler again
+    let messages = std::mem::take(&mut *MESSAGES.lock().unwrap());
+    assert_eq!(messages[0].as_deref(), Some("queue is empty"));
+    assert_eq!(messages[1], None);
+    // Truncated on a character boundary
+    assert_eq!(
+        messages[2].as_deref(),
+        Some(
+            "é".repeat((crash_context::ABORT_MESSAGE_LEN - 1) / 2)
+                .as_str()
+        )
+    );
+    // glibc hasn't recorded anything either
+    assert_eq!(messages[3], None);
+    // The location of the panic, and its formatted message
+    let panicked = messages[4].as_deref().unwrap();
+    assert!(
+        panicked.starts_with(&format!("panicked at {}:{panic_line}:", file!())),
+        "{panicked}"
+    );
+    assert!(panicked.ends_with(": oh no: 42"), "{panicked}");
+    // With the `panic` feature, the panic itself was reported with it as well
+    assert_eq!(messages.last(), messages.get(4));
+}
@@ -0,0 +1,53 @@
+//! Ensures that aborts following a failed allocation are classified as such
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::Mutex;
+
+#[global_allocator]
+static ALLOCATOR: ch::alloc_failure::RecordingAllocator =
+    ch::alloc_failure::RecordingAllocator::new(std::alloc::System);
+
+#[test]
+fn classifies_allocation_failure() {
+    static CRASHES: Mutex<Vec<(ch::CrashReason, Option<String>)>> = Mutex::new(Vec::new());
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            CRASHES
+                .lock()
+                .unwrap()
+                .push((cc.reason, cc.abort_message().map(str::to_owned)));
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    let mut huge = Vec::<u8>::new();
+    assert!(huge.try_reserve_exact(1 << 60).is_err());
+    handler.simulate_signal(ch::Signal::Abort);
+    handler.simulate_signal(ch::Signal::Segv);
+
+    // The failure is forgotten once the thread allocates successfully
+    std::hint::black_box(vec![1u8; 16]);
+    handler.simulate_signal(ch::Signal::Abort);
+
+    // Only the thread that failed to allocate is blamed
+    std::thread::spawn(|| assert!(Vec::<u8>::new().try_reserve_exact(1 << 60).is_err()))
+        .join()
+        .unwrap();
+    handler.simulate_signal(ch::Signal::Abort);
+
+    let crashes = std::mem::take(&mut *CRASHES.lock().unwrap());
+    assert_eq!(
+        crashes[0],
+        (
+            ch::CrashReason::AllocationFailure,
+            Some("memory allocation of 1152921504606846976 bytes failed".to_owned())
+        )
+    );
+    assert_eq!(crashes[1], (ch::CrashReason::Signal, None));
+    assert_eq!(crashes[2], (ch::CrashReason::Signal, None));
+    assert_eq!(crashes[3], (ch::CrashReason::Signal, None));
+}
@@ -0,0 +1,43 @@
+//! Ensures the size of the alternate signal stack can be configured
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+fn current_alt_stack_size() -> usize {
+    // SAFETY: syscall
+    unsafe {
+        let mut stack: libc::stack_t = std::mem::zeroed();
+        assert_eq!(libc::sigaltstack(std::ptr::null(), &mut stack), 0);
+        assert_eq!(stack.ss_flags & libc::SS_DISABLE, 0);
+        stack.ss_size
+    }
+}
+
+#[test]
+fn configures_alt_stack_size() {
+    fn on_crash() -> Box<dyn ch::CrashEvent> {
+        unsafe { ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Reraise) }
+    }
+
+    assert!(matches!(
+        ch::CrashHandler::builder()
+            .alt_stack_size(1)
+            .attach(on_crash()),
+        Err(ch::Error::InvalidAltStackSize { requested: 1, .. })
+    ));
+
+    const SIZE: usize = 1024 * 1024;
+
+    let handler = ch::CrashHandler::builder()
+        .alt_stack_size(SIZE)
+        .attach(on_crash())
+        .unwrap();
+
+    // The thread that attached the handler uses the configured size...
+    assert!(current_alt_stack_size() >= SIZE);
+    // ...as do threads that are created afterwards
+    assert!(std::thread::spawn(current_alt_stack_size).join().unwrap() >= SIZE);
+
+    handler.detach();
+}
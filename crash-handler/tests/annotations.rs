@@ -0,0 +1,77 @@
+//! Ensures that annotations can be set, and are available to the crash handler
+#![allow(unsafe_code)]
+
+use ch::annotations;
+use crash_handler as ch;
+
+fn collect() -> Vec<(String, String)> {
+    let mut set = Vec::new();
+    annotations::for_each(|key, value| set.push((key.to_owned(), value.to_owned())));
+    set.sort();
+    set
+}
+
+#[test]
+fn annotations() {
+    annotations::set("version", "1.0.0");
+    annotations::set("user", "someone");
+    annotations::set("version", "1.0.1");
+
+    assert_eq!(
+        collect(),
+        [
+            ("user".to_owned(), "someone".to_owned()),
+            ("version".to_owned(), "1.0.1".to_owned())
+        ]
+    );
+
+    annotations::remove("user");
+    assert_eq!(collect(), [("version".to_owned(), "1.0.1".to_owned())]);
+
+    // Values are truncated on a char boundary
+    annotations::clear();
+    let long = format!("a{}", "ü".repeat(crash_context::MAX_ANNOTATION_VALUE_LEN));
+    annotations::set("long", &long);
+    let set = collect();
+    assert_eq!(set[0].0, "long");
+    assert_eq!(set[0].1.len(), crash_context::MAX_ANNOTATION_VALUE_LEN - 1);
+
+    annotations::clear();
+    assert!(collect().is_empty());
+
+    // Once every slot is used, the oldest key is evicted
+    for i in 0..=crash_context::MAX_ANNOTATIONS {
+        annotations::set(&i.to_string(), "");
+    }
+    let set = collect();
+    assert_eq!(set.len(), crash_context::MAX_ANNOTATIONS);
+    assert!(!set.iter().any(|(key, _)| key == "0"));
+
+    annotations::clear();
+    annotations::set("version", "1.0.1");
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let handler = ch::CrashHandler::attach(unsafe {
+            ch::make_crash_event(|cc: &ch::CrashContext| {
+                assert_eq!(cc.annotation_count, crash_context::MAX_ANNOTATIONS);
+
+                // We're in the same process, so can just read the slots directly
+                let slots = std::slice::from_raw_parts(
+                    cc.annotations as *const crash_context::Annotation,
+                    cc.annotation_count,
+                );
+                let set: Vec<_> = slots.iter().filter_map(|slot| slot.get()).collect();
+                assert_eq!(set, [("version", "1.0.1")]);
+
+                ch::CrashEventResult::Handled { exit: None }
+            })
+        })
+        .unwrap();
+
+        assert!(matches!(
+            handler.simulate_signal(ch::Signal::Trap),
+            ch::CrashEventResult::Handled { exit: None }
+        ));
+    }
+}
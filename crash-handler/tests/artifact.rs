@@ -0,0 +1,49 @@
+//! Ensures that crash files are only visible in their directory once they are
+//! finalized
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use crash_handler::{artifact::CrashFile, write::write_bytes};
+use std::os::fd::AsRawFd;
+
+fn entries(dir: &std::path::Path) -> Vec<String> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    entries
+}
+
+#[test]
+fn finalizes_crash_file() {
+    let dir = std::env::temp_dir().join(format!("crash-file-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    assert!(CrashFile::create(&dir, "nested/crash").is_err());
+    assert!(CrashFile::create(dir.join("missing"), "crash").is_err());
+
+    let file = CrashFile::create(&dir, "crash.txt").unwrap();
+    write_bytes(file.as_raw_fd(), b"crashed\n").unwrap();
+
+    // Only the fallback for filesystems without O_TMPFILE is visible
+    assert!(entries(&dir).iter().all(|name| name.ends_with(".tmp")));
+
+    file.finalize().unwrap();
+    file.finalize().unwrap();
+    drop(file);
+
+    assert_eq!(entries(&dir), ["crash.txt"]);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("crash.txt")).unwrap(),
+        "crashed\n"
+    );
+
+    // A file that is never finalized leaves nothing behind
+    let file = CrashFile::create(&dir, "unused.txt").unwrap();
+    write_bytes(file.as_raw_fd(), b"unused\n").unwrap();
+    drop(file);
+
+    assert_eq!(entries(&dir), ["crash.txt"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
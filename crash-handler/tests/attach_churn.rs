@@ -0,0 +1,112 @@
+//! Ensures that signals are handled while many threads attach and detach
+//! handlers at the same time, and that the original handlers are restored
+//! once every handler has been detached
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+#[test]
+fn handles_signals_while_churning() {
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    const CHURN_THREADS: usize = 4;
+    const SIGNAL_THREADS: usize = 2;
+    const SIGNALS: usize = 2000;
+
+    // SAFETY: syscall
+    let original = unsafe {
+        let mut original: libc::sigaction = mem::zeroed();
+        assert_eq!(
+            libc::sigaction(libc::SIGUSR2, std::ptr::null(), &mut original),
+            0
+        );
+        original
+    };
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[libc::SIGUSR2])
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| {
+                HANDLED.fetch_add(1, Ordering::Relaxed);
+                ch::CrashEventResult::Continue
+            })
+        })
+        .unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let churn: Vec<_> = (0..CHURN_THREADS)
+        .map(|i| {
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                let mut attached = Vec::new();
+                while !stop.load(Ordering::Relaxed) {
+                    // Handlers that pass the signal on to the one above, with
+                    // priorities both above and below it, some of which stay
+                    // attached for a while
+                    let priority = ch::DEFAULT_PRIORITY + (i as i32 % 3) - 1;
+                    attached.push(
+                        ch::CrashHandler::builder()
+                            .priority(priority)
+                            .attach(unsafe {
+                                ch::make_crash_event(|_cc: &ch::CrashContext| {
+                                    ch::CrashEventResult::Reraise
+                                })
+                            })
+                            .unwrap(),
+                    );
+
+                    if attached.len() > i {
+                        attached.remove(0).detach();
+                    }
+                }
+
+                for handler in attached {
+                    handler.detach();
+                }
+            })
+        })
+        .collect();
+
+    let signal: Vec<_> = (0..SIGNAL_THREADS)
+        .map(|_| {
+            std::thread::spawn(|| {
+                for _ in 0..SIGNALS {
+                    // SAFETY: syscall
+                    unsafe {
+                        libc::raise(libc::SIGUSR2);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in signal {
+        thread.join().unwrap();
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    for thread in churn {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(HANDLED.load(Ordering::Relaxed), SIGNAL_THREADS * SIGNALS);
+
+    handler.detach();
+
+    // SAFETY: syscall
+    unsafe {
+        let mut current: libc::sigaction = mem::zeroed();
+        assert_eq!(
+            libc::sigaction(libc::SIGUSR2, std::ptr::null(), &mut current),
+            0
+        );
+        assert_eq!(current.sa_sigaction, original.sa_sigaction);
+    }
+}
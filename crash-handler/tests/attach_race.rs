@@ -0,0 +1,58 @@
+//! Ensures that signals are handled while other handlers are being attached
+//! and detached on another thread
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+#[test]
+fn handles_signals_while_attaching() {
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    const SIGNALS: usize = 2000;
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[libc::SIGUSR2])
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| {
+                HANDLED.fetch_add(1, Ordering::Relaxed);
+                ch::CrashEventResult::Continue
+            })
+        })
+        .unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let churn = {
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                // Higher priority, so it is invoked first, but passes the
+                // signal on to the handler above
+                let other = ch::CrashHandler::builder()
+                    .priority(ch::DEFAULT_PRIORITY + 1)
+                    .attach(unsafe {
+                        ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Reraise)
+                    })
+                    .unwrap();
+                other.detach();
+            }
+        })
+    };
+
+    for _ in 0..SIGNALS {
+        // SAFETY: syscall
+        unsafe {
+            libc::raise(libc::SIGUSR2);
+        }
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    churn.join().unwrap();
+
+    assert_eq!(HANDLED.load(Ordering::Relaxed), SIGNALS);
+
+    handler.detach();
+}
@@ -0,0 +1,33 @@
+//! Ensures that handlers are only installed for the signals that are requested
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+fn current_handler(sig: ch::Signal) -> usize {
+    // SAFETY: syscall
+    unsafe {
+        let mut sa: libc::sigaction = std::mem::zeroed();
+        assert_eq!(libc::sigaction(sig as i32, std::ptr::null(), &mut sa), 0);
+        sa.sa_sigaction
+    }
+}
+
+#[test]
+fn only_installs_requested_signals() {
+    // Note that std installs its own SIGSEGV handler to report stack overflows
+    let original_segv = current_handler(ch::Signal::Segv);
+    let original_trap = current_handler(ch::Signal::Trap);
+
+    let handler = ch::CrashHandler::attach_with(&[ch::Signal::Segv], unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Reraise)
+    })
+    .unwrap();
+
+    assert_ne!(current_handler(ch::Signal::Segv), original_segv);
+    assert_eq!(current_handler(ch::Signal::Trap), original_trap);
+
+    handler.detach();
+
+    assert_eq!(current_handler(ch::Signal::Segv), original_segv);
+}
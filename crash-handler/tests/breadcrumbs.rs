@@ -0,0 +1,79 @@
+//! Ensures that breadcrumbs can be added, and are available to the crash handler
+#![allow(unsafe_code)]
+
+use ch::breadcrumbs::{self, BreadcrumbLevel};
+use crash_handler as ch;
+
+fn collect() -> Vec<(BreadcrumbLevel, String)> {
+    let mut set = Vec::new();
+    breadcrumbs::for_each(|_timestamp, level, message| set.push((level, message.to_owned())));
+    set
+}
+
+#[test]
+fn breadcrumbs() {
+    breadcrumbs::add(BreadcrumbLevel::Info, "first");
+    breadcrumbs::add(BreadcrumbLevel::Error, "second");
+
+    assert_eq!(
+        collect(),
+        [
+            (BreadcrumbLevel::Info, "first".to_owned()),
+            (BreadcrumbLevel::Error, "second".to_owned())
+        ]
+    );
+
+    // Messages are truncated on a char boundary
+    breadcrumbs::clear();
+    let long = format!("a{}", "ü".repeat(crash_context::MAX_BREADCRUMB_MESSAGE_LEN));
+    breadcrumbs::add(BreadcrumbLevel::Debug, &long);
+    let set = collect();
+    assert_eq!(
+        set[0].1.len(),
+        crash_context::MAX_BREADCRUMB_MESSAGE_LEN - 1
+    );
+
+    breadcrumbs::clear();
+    assert!(collect().is_empty());
+
+    // Once the ring is full, the oldest breadcrumbs are replaced, and the
+    // rest are still visited oldest first
+    for i in 0..crash_context::MAX_BREADCRUMBS + 10 {
+        breadcrumbs::add(BreadcrumbLevel::Info, &i.to_string());
+    }
+    let set = collect();
+    assert_eq!(set.len(), crash_context::MAX_BREADCRUMBS);
+    assert_eq!(set[0].1, "10");
+    assert_eq!(
+        set.last().unwrap().1,
+        (crash_context::MAX_BREADCRUMBS + 9).to_string()
+    );
+
+    breadcrumbs::clear();
+    breadcrumbs::add(BreadcrumbLevel::Warning, "about to crash");
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let handler = ch::CrashHandler::attach(unsafe {
+            ch::make_crash_event(|cc: &ch::CrashContext| {
+                assert_eq!(cc.breadcrumb_count, crash_context::MAX_BREADCRUMBS);
+
+                // We're in the same process, so can just read the slots directly
+                let slots = std::slice::from_raw_parts(
+                    cc.breadcrumbs as *const crash_context::Breadcrumb,
+                    cc.breadcrumb_count,
+                );
+                let set: Vec<_> = slots.iter().filter_map(|slot| slot.get()).collect();
+                assert_eq!(set, [(BreadcrumbLevel::Warning, "about to crash")]);
+
+                ch::CrashEventResult::Handled { exit: None }
+            })
+        })
+        .unwrap();
+
+        assert!(matches!(
+            handler.simulate_signal(ch::Signal::Trap),
+            ch::CrashEventResult::Handled { exit: None }
+        ));
+    }
+}
@@ -0,0 +1,64 @@
+//! Ensures that the callback can be invoked on a dedicated thread, while the
+//! thread that raised the signal is parked until it has returned
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::Mutex;
+
+#[test]
+fn runs_on_callback_thread() {
+    // The thread the callback ran on, and the thread the signal was raised on
+    static RAN: Mutex<Vec<(String, i32)>> = Mutex::new(Vec::new());
+
+    let kick = libc::SIGRTMIN() + 3;
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[kick])
+        .callback_thread(true)
+        .attach(unsafe {
+            ch::make_crash_event(|cc: &ch::CrashContext| {
+                // Outside of the signal handler, so allocating and locking is fine
+                let name = std::thread::current().name().unwrap_or_default().to_owned();
+                RAN.lock().unwrap().push((name, cc.tid));
+                ch::CrashEventResult::Continue
+            })
+        })
+        .unwrap();
+
+    // SAFETY: syscalls
+    let tid = unsafe {
+        libc::raise(kick);
+        libc::syscall(libc::SYS_gettid) as i32
+    };
+
+    assert!(matches!(
+        handler.simulate_signal(ch::Signal::Segv),
+        ch::CrashEventResult::Continue
+    ));
+
+    {
+        let ran = RAN.lock().unwrap();
+        assert_eq!(ran.len(), 2);
+        for (name, crashed) in ran.iter() {
+            assert_eq!(name, "crash-callback");
+            assert_eq!(*crashed, tid);
+        }
+    }
+
+    // The thread is gone once the handler is detached
+    handler.detach();
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            let name = std::thread::current().name().unwrap_or_default().to_owned();
+            RAN.lock().unwrap().push((name, 0));
+            ch::CrashEventResult::Continue
+        })
+    })
+    .unwrap();
+    handler.simulate_signal(ch::Signal::Segv);
+    handler.detach();
+
+    assert_ne!(RAN.lock().unwrap()[2].0, "crash-callback");
+}
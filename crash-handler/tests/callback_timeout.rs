@@ -0,0 +1,52 @@
+//! Ensures that a callback that never returns doesn't hang the process
+//! forever if a timeout is configured, and that the process still dies with
+//! the original signal
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::{os::unix::process::ExitStatusExt, time::Duration};
+
+const CHILD_ENV: &str = "CRASH_HANDLER_CALLBACK_TIMEOUT_CHILD";
+
+#[test]
+fn callback_timeout() {
+    if std::env::var_os(CHILD_ENV).is_some() {
+        let _handler = ch::CrashHandler::builder()
+            .callback_timeout(Duration::from_millis(200))
+            .attach(unsafe {
+                ch::make_crash_event(|_cc: &ch::CrashContext| loop {
+                    std::thread::sleep(Duration::from_secs(1));
+                })
+            })
+            .unwrap();
+
+        unsafe {
+            sadness_generator::raise_segfault();
+        }
+    }
+
+    // Run ourselves in a child process, since we expect the crash to kill it
+    let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "callback_timeout", "--nocapture"])
+        .env(CHILD_ENV, "1")
+        .spawn()
+        .expect("failed to spawn child");
+
+    let mut waited = Duration::ZERO;
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+
+        if waited > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("child process hung in the callback");
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+        waited += Duration::from_millis(50);
+    };
+
+    assert_eq!(status.signal(), Some(libc::SIGSEGV));
+}
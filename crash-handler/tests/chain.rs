@@ -0,0 +1,49 @@
+//! Ensures that signals we don't handle are chained to the handler that was
+//! installed before ours, while keeping our own handler installed
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static PREVIOUS_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn previous_handler(sig: i32, info: *mut libc::siginfo_t, _uc: *mut libc::c_void) {
+    assert_eq!(sig, libc::SIGTRAP);
+    assert!(!info.is_null());
+    PREVIOUS_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test]
+fn chains_to_previous_handler() {
+    // SAFETY: syscalls
+    unsafe {
+        let mut sa: libc::sigaction = std::mem::zeroed();
+        libc::sigemptyset(&mut sa.sa_mask);
+        sa.sa_sigaction = previous_handler as *const () as usize;
+        sa.sa_flags = libc::SA_SIGINFO;
+        assert_eq!(libc::sigaction(libc::SIGTRAP, &sa, std::ptr::null_mut()), 0);
+    }
+
+    static OUR_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            OUR_CALLS.fetch_add(1, Ordering::Relaxed);
+            ch::CrashEventResult::Reraise
+        })
+    })
+    .unwrap();
+
+    for i in 1..=2 {
+        // SAFETY: syscall
+        unsafe {
+            libc::raise(libc::SIGTRAP);
+        }
+
+        assert_eq!(OUR_CALLS.load(Ordering::Relaxed), i);
+        assert_eq!(PREVIOUS_CALLS.load(Ordering::Relaxed), i);
+    }
+
+    handler.detach();
+}
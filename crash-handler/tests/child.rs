@@ -0,0 +1,117 @@
+//! Ensures that the state changes of a child process are interpreted
+//! correctly, and that a child that crashed without a handler of its own can
+//! be reported by its parent
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{self as ch, child};
+
+/// Forks a child that runs the specified function, which must only use async
+/// signal safe functions, then exits
+fn spawn(f: fn()) -> libc::pid_t {
+    // SAFETY: syscall, the child only does async signal safe things
+    unsafe {
+        let pid = libc::fork();
+        assert_ne!(pid, -1);
+        if pid == 0 {
+            f();
+            libc::_exit(0);
+        }
+        pid
+    }
+}
+
+#[test]
+fn interprets_child_status() {
+    let pid = spawn(|| unsafe { libc::_exit(3) });
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    assert_eq!(status.pid, pid);
+    assert_eq!(status.exit, child::ChildExit::Exited(3));
+    assert!(status.crash_context().is_none());
+
+    // Crashes with the default disposition, without writing a core dump
+    let pid = spawn(|| unsafe {
+        let no_core = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+        libc::signal(libc::SIGSEGV, libc::SIG_DFL);
+        libc::raise(libc::SIGSEGV);
+    });
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    assert_eq!(
+        status.exit,
+        child::ChildExit::Killed {
+            signal: libc::SIGSEGV,
+            core_dumped: false,
+        }
+    );
+
+    let cc = status.crash_context().expect("the child crashed");
+    assert_eq!(cc.pid, pid);
+    assert_eq!(cc.tid, pid);
+    assert_eq!(cc.siginfo.ssi_signo, libc::SIGSEGV as u32);
+    assert_eq!(cc.siginfo.ssi_pid, pid as u32);
+    assert_ne!(cc.time.wall, 0);
+
+    // Killed, but not crashed
+    let pid = spawn(|| unsafe {
+        libc::raise(libc::SIGKILL);
+    });
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    assert!(matches!(
+        status.exit,
+        child::ChildExit::Killed {
+            signal: libc::SIGKILL,
+            ..
+        }
+    ));
+    assert!(status.crash_signal().is_none());
+
+    // Stopped, then continued
+    let pid = spawn(|| unsafe {
+        libc::raise(libc::SIGSTOP);
+    });
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    assert_eq!(status.exit, child::ChildExit::Stopped(libc::SIGSTOP));
+
+    // SAFETY: syscall
+    unsafe {
+        libc::kill(pid, libc::SIGCONT);
+    }
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    assert_eq!(status.exit, child::ChildExit::Continued);
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    assert_eq!(status.exit, child::ChildExit::Exited(0));
+
+    // Nothing left to wait for
+    assert!(child::wait(Some(pid), true).is_err());
+}
+
+#[test]
+fn reports_child_crash() {
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            assert_eq!(cc.siginfo.ssi_signo, libc::SIGABRT as u32);
+            assert_ne!(cc.pid, std::process::id() as i32);
+            ch::CrashEventResult::Handled { exit: Some(1) }
+        })
+    })
+    .unwrap();
+
+    let pid = spawn(|| unsafe {
+        libc::signal(libc::SIGABRT, libc::SIG_DFL);
+        libc::raise(libc::SIGABRT);
+    });
+    let status = child::wait(Some(pid), false).unwrap().unwrap();
+    let cc = status.crash_context().expect("the child crashed");
+
+    // Reporting the crash of the child doesn't terminate the parent
+    assert!(matches!(
+        handler.simulate_context(&cc),
+        ch::CrashEventResult::Handled { exit: Some(1) }
+    ));
+
+    handler.detach();
+}
@@ -0,0 +1,66 @@
+//! Ensures that crashes on many threads at nearly the same time are each
+//! handled, one at a time, without deadlocking
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+mod shared;
+
+use crash_handler as ch;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Barrier,
+};
+
+#[test]
+fn handles_concurrent_crashes() {
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    static IN_CALLBACK: AtomicBool = AtomicBool::new(false);
+    static OVERLAPPED: AtomicBool = AtomicBool::new(false);
+
+    const THREADS: usize = 8;
+    const CRASHES: usize = 200;
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            if IN_CALLBACK.swap(true, Ordering::SeqCst) {
+                OVERLAPPED.store(true, Ordering::SeqCst);
+            }
+
+            assert_eq!(cc.siginfo.ssi_signo, libc::SIGSEGV as u32);
+            HANDLED.fetch_add(1, Ordering::SeqCst);
+
+            // Give the other threads the chance to crash while this crash is
+            // still being handled
+            std::thread::yield_now();
+
+            IN_CALLBACK.store(false, Ordering::SeqCst);
+            ch::CrashEventResult::Jump {
+                jmp_buf: shared::recovery_point(),
+                value: 1,
+            }
+        })
+    })
+    .unwrap();
+
+    let barrier = Arc::new(Barrier::new(THREADS));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                for _ in 0..CRASHES {
+                    barrier.wait();
+                    shared::crash_and_recover();
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(HANDLED.load(Ordering::SeqCst), THREADS * CRASHES);
+    assert!(!OVERLAPPED.load(Ordering::SeqCst));
+
+    handler.detach();
+}
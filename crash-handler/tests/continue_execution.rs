@@ -0,0 +1,31 @@
+//! Ensures that execution resumes after a signal sent to the process if the
+//! handler requests it, leaving our handler installed
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn continues_execution() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            ch::CrashEventResult::Continue
+        })
+    })
+    .unwrap();
+
+    for i in 1..=2 {
+        // SAFETY: syscall
+        unsafe {
+            libc::raise(libc::SIGTRAP);
+        }
+
+        assert_eq!(CALLS.load(Ordering::Relaxed), i);
+    }
+
+    handler.detach();
+}
@@ -0,0 +1,111 @@
+#![cfg(unix)]
+#![allow(unsafe_code)]
+
+use crash_handler::crash_loop::{self, CrashLog};
+use std::time::{Duration, SystemTime};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("crash-loop-{name}-{}", std::process::id()))
+}
+
+#[test]
+fn detects_crash_loop() {
+    let path = temp_path("status");
+    let now = now();
+    std::fs::write(
+        &path,
+        format!("{}\ngarbage\n{}\n{}\n", now - 7200, now - 20, now - 10),
+    )
+    .unwrap();
+
+    let status = crash_loop::status(&path, Duration::from_secs(600), 2);
+    assert_eq!(status.recent_crashes, 2);
+    assert!(status.looping);
+    assert_eq!(
+        status.last_crash,
+        Some(SystemTime::UNIX_EPOCH + Duration::from_secs(now - 10))
+    );
+
+    let status = crash_loop::status(&path, Duration::from_secs(600), 3);
+    assert!(!status.looping);
+
+    // Opening the log keeps the crashes, but drops the malformed line
+    let log = CrashLog::open(&path).unwrap();
+    assert!(log.record());
+    drop(log);
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(contents.lines().count(), 4);
+    assert_eq!(
+        crash_loop::status(&path, Duration::from_secs(600), 3).recent_crashes,
+        3
+    );
+
+    std::fs::remove_file(path).unwrap();
+
+    let missing = crash_loop::status(temp_path("missing"), Duration::from_secs(600), 1);
+    assert_eq!(missing.recent_crashes, 0);
+    assert!(missing.last_crash.is_none());
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn skips_handler_over_limit() {
+    use crash_handler as ch;
+
+    const HANDLED: i32 = 42;
+
+    /// Attaches a handler limited to a single crash per hour in a child
+    /// process, and returns the wait status of the child after it crashes
+    fn crash_in_child(path: &std::path::Path) -> i32 {
+        let pid = unsafe { libc::fork() };
+        assert_ne!(pid, -1);
+
+        if pid == 0 {
+            let log = CrashLog::open(path).unwrap().max_dumps_per_hour(1);
+
+            let _handler = ch::CrashHandler::builder()
+                .crash_log(log)
+                .attach(unsafe {
+                    ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Handled {
+                        exit: Some(HANDLED),
+                    })
+                })
+                .unwrap();
+
+            unsafe {
+                libc::raise(libc::SIGABRT);
+                libc::_exit(0);
+            }
+        }
+
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        status
+    }
+
+    let path = temp_path("limit");
+    let _ = std::fs::remove_file(&path);
+
+    let status = crash_in_child(&path);
+    assert!(libc::WIFEXITED(status), "child terminated with {status}");
+    assert_eq!(libc::WEXITSTATUS(status), HANDLED);
+
+    // The first crash counts towards the limit, so the handler is skipped
+    let status = crash_in_child(&path);
+    assert!(libc::WIFSIGNALED(status), "child exited with {status}");
+    assert_eq!(libc::WTERMSIG(status), libc::SIGABRT);
+
+    let status = crash_loop::status(&path, Duration::from_secs(60), 2);
+    assert_eq!(status.recent_crashes, 2);
+    assert!(status.looping);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,35 @@
+//! Ensures the crash context records when the crash occurred, relative to when
+//! the process was started and the handler was attached, along with the CPU
+//! that was captured when it was attached, and the memory usage at the time
+//! of the crash
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+#[test]
+fn crash_time() {
+    let before = std::time::SystemTime::now();
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(move |cc: &ch::CrashContext| {
+            assert_ne!(cc.process_start.wall, 0);
+            assert!(cc.process_start.wall_time().unwrap() <= before);
+            assert!(cc.process_start.monotonic <= cc.attach_time.monotonic);
+            assert!(cc.attach_time.wall_time().unwrap() >= before);
+            assert!(cc.attach_time.monotonic <= cc.time.monotonic);
+            assert!(cc.time.wall_time().unwrap() <= std::time::SystemTime::now());
+            assert_eq!(cc.cpu, crash_context::CpuInfo::capture());
+            assert!(cc.memory.resident > 0);
+            assert!(cc.memory.size >= cc.memory.resident);
+
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    assert!(matches!(
+        handler.simulate_signal(ch::Signal::Trap),
+        ch::CrashEventResult::Handled { exit: None }
+    ));
+}
@@ -0,0 +1,94 @@
+//! Ensures that a thread that crashes while another thread's crash is being
+//! handled is parked, recorded as having also crashed, and handled in turn
+//! once the first crash is
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+mod shared;
+
+use crash_handler as ch;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+/// Reads the crashed threads recorded in the context
+fn recorded(cc: &ch::CrashContext) -> Vec<crash_context::CrashedThread> {
+    // SAFETY: the context was captured in this process, so the slots are ours
+    let slots = unsafe {
+        std::slice::from_raw_parts(
+            cc.crashed_threads as *const crash_context::CrashedThread,
+            cc.crashed_thread_count,
+        )
+    };
+    slots.iter().filter(|t| t.is_valid()).copied().collect()
+}
+
+#[test]
+fn records_threads_that_also_crashed() {
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    static FIRST_HANDLING: AtomicBool = AtomicBool::new(false);
+    static SECOND_TID: AtomicU32 = AtomicU32::new(0);
+    static RECORDED_TID: AtomicU32 = AtomicU32::new(0);
+    static RECORDED_SIGNO: AtomicU32 = AtomicU32::new(0);
+    static PARKED: AtomicUsize = AtomicUsize::new(0);
+    static SECOND_SAW: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            if HANDLED.fetch_add(1, Ordering::SeqCst) == 0 {
+                FIRST_HANDLING.store(true, Ordering::SeqCst);
+
+                // Wait for the second thread to crash and be parked
+                let mut parked = Vec::new();
+                for _ in 0..5000 {
+                    ch::crashed_threads::for_each(|thread| parked.push(*thread));
+                    if !parked.is_empty() {
+                        break;
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                PARKED.store(parked.len(), Ordering::SeqCst);
+
+                if let [thread] = recorded(cc)[..] {
+                    RECORDED_TID.store(thread.tid, Ordering::SeqCst);
+                    RECORDED_SIGNO.store(thread.signo, Ordering::SeqCst);
+                }
+            } else {
+                // The second thread's crash is no longer one that also crashed
+                SECOND_SAW.store(recorded(cc).len(), Ordering::SeqCst);
+            }
+
+            ch::CrashEventResult::Jump {
+                jmp_buf: shared::recovery_point(),
+                value: 1,
+            }
+        })
+    })
+    .unwrap();
+
+    let first = std::thread::spawn(shared::crash_and_recover);
+    let second = std::thread::spawn(|| {
+        while !FIRST_HANDLING.load(Ordering::SeqCst) {
+            std::thread::yield_now();
+        }
+
+        // SAFETY: syscall
+        SECOND_TID.store(
+            unsafe { libc::syscall(libc::SYS_gettid) } as u32,
+            Ordering::SeqCst,
+        );
+        shared::crash_and_recover();
+    });
+
+    first.join().unwrap();
+    second.join().unwrap();
+
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    assert_eq!(PARKED.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        RECORDED_TID.load(Ordering::SeqCst),
+        SECOND_TID.load(Ordering::SeqCst)
+    );
+    assert_eq!(RECORDED_SIGNO.load(Ordering::SeqCst), libc::SIGSEGV as u32);
+    assert_eq!(SECOND_SAW.load(Ordering::SeqCst), 0);
+
+    handler.detach();
+}
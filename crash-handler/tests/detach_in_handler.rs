@@ -0,0 +1,37 @@
+//! Ensures that the handler can be detached from within the user's handler
+//! itself without deadlocking
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+static HANDLER: parking_lot::Mutex<Option<ch::CrashHandler>> = parking_lot::const_mutex(None);
+
+#[test]
+fn detaches_in_handler() {
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            if let Some(handler) = HANDLER.lock().take() {
+                handler.detach();
+            }
+            ch::CrashEventResult::Continue
+        })
+    })
+    .unwrap();
+
+    *HANDLER.lock() = Some(handler);
+
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(libc::SIGTRAP);
+    }
+
+    assert!(HANDLER.lock().is_none());
+
+    // The handler can be attached again afterwards
+    ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Reraise)
+    })
+    .unwrap()
+    .detach();
+}
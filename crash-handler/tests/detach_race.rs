@@ -0,0 +1,81 @@
+//! Ensures that crashes are handled, either by our handler or the one that
+//! was installed before it, while our handler is repeatedly attached and
+//! detached on another thread
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+mod shared;
+
+use crash_handler as ch;
+use std::{
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+static PREVIOUS: AtomicUsize = AtomicUsize::new(0);
+
+/// The handler installed before ours, which recovers from the crash
+extern "C" fn previous_handler(_sig: i32, _info: *mut libc::siginfo_t, _uc: *mut libc::c_void) {
+    PREVIOUS.fetch_add(1, Ordering::SeqCst);
+    // SAFETY: the buffer is set before crashing, on this thread
+    unsafe { ch::jmp::siglongjmp(shared::recovery_point(), 1) }
+}
+
+#[test]
+fn handles_crashes_while_detaching() {
+    static HANDLED: AtomicUsize = AtomicUsize::new(0);
+    const CRASHES: usize = 20000;
+
+    // SAFETY: syscalls
+    let original = unsafe {
+        let mut sa: libc::sigaction = mem::zeroed();
+        libc::sigemptyset(&mut sa.sa_mask);
+        sa.sa_sigaction = previous_handler as *const () as usize;
+        sa.sa_flags = libc::SA_SIGINFO;
+
+        let mut original = mem::zeroed();
+        assert_eq!(libc::sigaction(libc::SIGSEGV, &sa, &mut original), 0);
+        original
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let churn = {
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                // The only handler, so every attach and detach installs and
+                // restores the signal handlers
+                let handler = ch::CrashHandler::attach(unsafe {
+                    ch::make_crash_event(|_cc: &ch::CrashContext| {
+                        HANDLED.fetch_add(1, Ordering::SeqCst);
+                        ch::CrashEventResult::Reraise
+                    })
+                })
+                .unwrap();
+                handler.detach();
+            }
+        })
+    };
+
+    for _ in 0..CRASHES {
+        shared::crash_and_recover();
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    churn.join().unwrap();
+
+    // Every crash ends up in the previous handler, whether it was chained to
+    // by ours or not
+    assert_eq!(PREVIOUS.load(Ordering::SeqCst), CRASHES);
+    assert!(HANDLED.load(Ordering::SeqCst) <= CRASHES);
+
+    // SAFETY: syscalls
+    unsafe {
+        let mut current: libc::sigaction = mem::zeroed();
+        assert_eq!(libc::sigaction(libc::SIGSEGV, &original, &mut current), 0);
+        assert_eq!(current.sa_sigaction, previous_handler as *const () as usize);
+    }
+}
@@ -0,0 +1,54 @@
+//! Ensures that memory excluded from dumps is marked so that the kernel omits
+//! it from core dumps, until it is included again
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::dump_exclusion;
+
+/// Retrieves the `VmFlags` of the mapping starting at the address
+fn vm_flags(address: usize) -> String {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let start = format!("{address:x}-");
+
+    let mut lines = smaps.lines().skip_while(|line| !line.starts_with(&start));
+    lines
+        .find_map(|line| line.strip_prefix("VmFlags:"))
+        .expect("failed to find mapping")
+        .to_owned()
+}
+
+#[test]
+fn excludes_from_core_dumps() {
+    // SAFETY: syscalls
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let size = 4 * page_size;
+    let map = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    assert_ne!(map, libc::MAP_FAILED);
+    let address = map as usize;
+
+    assert!(!vm_flags(address).contains(" dd"));
+
+    // The whole mapping is covered even though the range isn't page aligned
+    dump_exclusion::exclude_from_dumps((address + 1) as *const u8, size - 2).unwrap();
+    assert!(vm_flags(address).contains(" dd"));
+
+    dump_exclusion::include_in_dumps((address + 1) as *const u8, size - 2).unwrap();
+    assert!(!vm_flags(address).contains(" dd"));
+
+    // SAFETY: syscall
+    unsafe {
+        libc::munmap(map, size);
+    }
+
+    // The memory is no longer mapped
+    assert!(dump_exclusion::exclude_from_dumps(address as *const u8, size).is_err());
+}
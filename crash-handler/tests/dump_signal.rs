@@ -0,0 +1,64 @@
+//! Ensures that the dump signal invokes the callback on the dump thread, and
+//! that the process keeps running afterwards
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::{sync::Mutex, time::Duration};
+
+#[test]
+fn dumps_on_signal() {
+    static DUMPS: Mutex<Vec<(ch::CrashReason, u32, u32, i32)>> = Mutex::new(Vec::new());
+
+    let handler = ch::CrashHandler::builder()
+        .dump_signal(Some(libc::SIGUSR2))
+        .attach(unsafe {
+            ch::make_crash_event(|cc: &ch::CrashContext| {
+                DUMPS.lock().unwrap().push((
+                    cc.reason,
+                    cc.siginfo.ssi_signo,
+                    cc.siginfo.ssi_pid,
+                    cc.tid,
+                ));
+                ch::CrashEventResult::Handled { exit: None }
+            })
+        })
+        .unwrap();
+
+    // SAFETY: syscalls
+    let (pid, tid) = unsafe {
+        libc::kill(libc::getpid(), libc::SIGUSR2);
+        (libc::getpid(), libc::syscall(libc::SYS_gettid) as i32)
+    };
+
+    for _ in 0..100 {
+        if !DUMPS.lock().unwrap().is_empty() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    let dumps = std::mem::take(&mut *DUMPS.lock().unwrap());
+    assert_eq!(dumps.len(), 1);
+    let (reason, signo, sender, handled_on) = dumps[0];
+    assert_eq!(reason, ch::CrashReason::DumpRequest);
+    assert_eq!(signo, libc::SIGUSR2 as u32);
+    assert_eq!(sender, pid as u32);
+    // The callback is invoked on the dump thread rather than in the signal
+    // handler of whichever thread received the signal
+    assert_ne!(handled_on, tid);
+
+    handler.detach();
+}
+
+#[test]
+fn rejects_crash_signals() {
+    assert!(matches!(
+        ch::CrashHandler::builder()
+            .dump_signal(Some(libc::SIGSEGV))
+            .attach(unsafe {
+                ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Continue)
+            }),
+        Err(ch::Error::InvalidSignal(libc::SIGSEGV))
+    ));
+}
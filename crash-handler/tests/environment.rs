@@ -0,0 +1,64 @@
+//! Ensures that the arguments and filtered environment variables are
+//! captured when attaching, and recorded in the crash context
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+/// Reads the environment recorded in the context
+fn recorded(cc: &ch::CrashContext) -> &[u8] {
+    // SAFETY: the context was captured in this process, so the environment is
+    // ours
+    unsafe { std::slice::from_raw_parts(cc.environment as *const u8, cc.environment_len) }
+}
+
+#[test]
+fn captures_environment() {
+    std::env::set_var("CH_ENV_TEST_KEPT", "kept");
+    std::env::set_var("CH_ENV_TEST_SECRET", "hunter2");
+    std::env::set_var("CH_ENV_TEST_DROPPED", "dropped");
+
+    let handler = ch::CrashHandler::builder()
+        .capture_args(true)
+        .capture_env(|key, value| match key {
+            "CH_ENV_TEST_KEPT" => Some(value.to_owned()),
+            "CH_ENV_TEST_SECRET" => Some("<redacted>".to_owned()),
+            _ => None,
+        })
+        .attach(unsafe {
+            ch::make_crash_event(|cc: &ch::CrashContext| {
+                assert_ne!(cc.environment, 0);
+                let env = crash_context::Environment::parse(recorded(cc)).unwrap();
+
+                let args: Vec<_> = std::env::args().collect();
+                assert_eq!(env.args().collect::<Vec<_>>(), args);
+                let mut vars: Vec<_> = env.vars().collect();
+                vars.sort_unstable();
+                assert_eq!(
+                    vars,
+                    [
+                        ("CH_ENV_TEST_KEPT", "kept"),
+                        ("CH_ENV_TEST_SECRET", "<redacted>")
+                    ]
+                );
+
+                ch::CrashEventResult::Handled { exit: None }
+            })
+        })
+        .unwrap();
+
+    // Changes after attaching aren't reflected
+    std::env::set_var("CH_ENV_TEST_KEPT", "changed");
+
+    assert!(matches!(
+        handler.simulate_signal(ch::Signal::Trap),
+        ch::CrashEventResult::Handled { exit: None }
+    ));
+}
+
+#[test]
+fn allowlist() {
+    let mut filter = ch::environment::allowlist(["RUST_LOG"]);
+    assert_eq!(filter("RUST_LOG", "debug").as_deref(), Some("debug"));
+    assert_eq!(filter("HOME", "/root"), None);
+}
@@ -0,0 +1,54 @@
+//! Ensures that an event loop that stops iterating is reported as hung, and
+//! that the latency of its iterations is recorded
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{self as ch, hang_monitor};
+use std::{sync::Mutex, time::Duration};
+
+#[test]
+fn reports_unresponsive_event_loop() {
+    static HANGS: Mutex<Vec<(ch::CrashReason, i32)>> = Mutex::new(Vec::new());
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            HANGS.lock().unwrap().push((cc.reason, cc.tid));
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    let monitor =
+        hang_monitor::HangMonitor::start_for_event_loop(Duration::from_millis(300)).unwrap();
+
+    for _ in 0..20 {
+        hang_monitor::report_iteration();
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    assert!(HANGS.lock().unwrap().is_empty());
+
+    // A single slow iteration that exceeds the timeout
+    std::thread::sleep(Duration::from_millis(800));
+    hang_monitor::report_iteration();
+
+    // SAFETY: syscall
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as i32;
+    assert_eq!(
+        std::mem::take(&mut *HANGS.lock().unwrap()),
+        [(ch::CrashReason::Hang, tid)]
+    );
+
+    let latency = hang_monitor::iteration_latency();
+    assert_eq!(latency.count(), 20);
+    assert!(latency.percentile(0.5).unwrap() >= Duration::from_millis(10));
+    assert!(latency.percentile(0.5).unwrap() < Duration::from_millis(300));
+    assert!(latency.max() >= Duration::from_millis(800));
+    assert_eq!(latency.percentile(1.0), Some(latency.max()));
+
+    hang_monitor::reset_iteration_latency();
+    hang_monitor::report_iteration();
+    assert_eq!(hang_monitor::iteration_latency().count(), 0);
+
+    monitor.stop();
+    handler.detach();
+}
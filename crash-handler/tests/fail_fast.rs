@@ -0,0 +1,46 @@
+//! Ensures that heap corruption and fail fast exceptions are identified by the
+//! crash reason, as they bypass frame based exception handling
+#![cfg(target_os = "windows")]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+#[test]
+fn identifies_fail_fast() {
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            let expected = match cc.exception_code {
+                code if code == ch::ExceptionCode::HeapCorruption as i32 => {
+                    ch::CrashReason::HeapCorruption
+                }
+                code if code == ch::ExceptionCode::FailFast as i32 => {
+                    ch::CrashReason::StackBufferOverrun
+                }
+                _ => ch::CrashReason::Exception,
+            };
+            assert_eq!(cc.reason, expected);
+
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    for code in [
+        ch::ExceptionCode::HeapCorruption,
+        ch::ExceptionCode::FailFast,
+        ch::ExceptionCode::Segv,
+    ] {
+        assert!(matches!(
+            handler.simulate_exception(Some(code as i32)),
+            ch::CrashEventResult::Handled { exit: None }
+        ));
+    }
+
+    assert_eq!(
+        ch::CrashReason::from_exception(
+            ch::ExceptionCode::FailFast as i32,
+            Some(crash_context::FAST_FAIL_FATAL_APP_EXIT)
+        ),
+        ch::CrashReason::FailFast(crash_context::FAST_FAIL_FATAL_APP_EXIT)
+    );
+}
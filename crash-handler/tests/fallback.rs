@@ -0,0 +1,58 @@
+//! Ensures that crashes are written to the fallback file, and can be read back
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{self as ch, fallback};
+
+#[test]
+fn writes_fallback_file() {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("fallback-{}.crash", std::process::id()));
+    let report_path = dir.join(format!("fallback-{}.txt", std::process::id()));
+
+    let file = fallback::FallbackFile::open(&path)
+        .unwrap()
+        .report(&report_path)
+        .unwrap();
+
+    let handler = ch::CrashHandler::builder()
+        .fallback_file(file)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Handled {
+                exit: None,
+            })
+        })
+        .unwrap();
+
+    let cc = ch::CrashContext::synthetic(libc::SIGSEGV, 0xdead_b000, 0x7ff0_1000);
+    handler.simulate_context(&cc);
+    let cc = ch::CrashContext::synthetic(libc::SIGILL, 0xdead_c000, 0x7ff0_2000);
+    handler.simulate_context(&cc);
+
+    // Not a crash, so it isn't written
+    let cc = ch::CrashContext::synthetic(libc::SIGUSR1, 0xdead_d000, 0x7ff0_3000);
+    handler.simulate_context(&cc);
+
+    handler.detach();
+
+    let crashes = fallback::read(&path).unwrap();
+    assert_eq!(
+        crashes
+            .iter()
+            .map(|cc| (cc.siginfo.ssi_signo as i32, cc.instruction_pointer()))
+            .collect::<Vec<_>>(),
+        [(libc::SIGSEGV, 0xdead_b000), (libc::SIGILL, 0xdead_c000)]
+    );
+
+    let report = std::fs::read_to_string(&report_path).unwrap();
+    assert!(report.contains("Signal: SIGSEGV"));
+    assert!(report.contains("Signal: SIGILL"));
+    assert!(!report.contains("SIGUSR1"));
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(report_path).unwrap();
+
+    assert!(fallback::read(dir.join("fallback-missing.crash"))
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,90 @@
+//! Ensures the handler behaves as configured in a child created by `fork`
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+/// The code the callback exits the child with
+const HANDLED: i32 = 42;
+
+fn attach(behavior: ch::ForkBehavior) -> ch::CrashHandler {
+    ch::CrashHandler::builder()
+        .raw_signals(&[libc::SIGUSR1])
+        .fork_behavior(behavior)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Handled {
+                exit: Some(HANDLED),
+            })
+        })
+        .unwrap()
+}
+
+/// Forks a child that raises `SIGUSR1`, after running `before_raise`, and
+/// returns its wait status
+fn raise_in_child(before_raise: impl FnOnce()) -> i32 {
+    let pid = unsafe { libc::fork() };
+    assert_ne!(pid, -1);
+
+    if pid == 0 {
+        before_raise();
+        unsafe {
+            libc::raise(libc::SIGUSR1);
+            libc::_exit(0);
+        }
+    }
+
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+    status
+}
+
+fn assert_handled(status: i32) {
+    assert!(libc::WIFEXITED(status), "child terminated with {status}");
+    assert_eq!(libc::WEXITSTATUS(status), HANDLED);
+}
+
+// The configuration of the signal handlers is shared by every attached
+// handler, so each case is run in sequence from a single test
+#[test]
+fn fork_behavior() {
+    {
+        let _handler = attach(ch::ForkBehavior::Keep);
+        assert_handled(raise_in_child(|| {}));
+    }
+
+    {
+        let handler = attach(ch::ForkBehavior::Disarm);
+
+        let status = raise_in_child(|| {});
+        assert!(libc::WIFSIGNALED(status), "child exited with {status}");
+        assert_eq!(libc::WTERMSIG(status), libc::SIGUSR1);
+
+        assert_handled(raise_in_child(|| handler.reattach_after_fork().unwrap()));
+    }
+
+    // A child detaching the handler must not clear the parent's marker
+    let path = std::env::temp_dir().join(format!("fork-marker-{}", std::process::id()));
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[libc::SIGUSR2])
+        .crash_marker(&path)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Continue)
+        })
+        .unwrap();
+
+    let pid = unsafe { libc::fork() };
+    assert_ne!(pid, -1);
+
+    if pid == 0 {
+        handler.detach();
+        unsafe { libc::_exit(0) };
+    }
+
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+    assert!(path.exists());
+
+    handler.detach();
+    assert!(!path.exists());
+}
@@ -0,0 +1,49 @@
+//! Ensures that a thread that stops pinging the hang monitor is reported as
+//! hung, with its own context
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::{sync::Mutex, time::Duration};
+
+#[test]
+fn reports_hang() {
+    static HANGS: Mutex<Vec<(ch::CrashReason, i32, u32)>> = Mutex::new(Vec::new());
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            HANGS
+                .lock()
+                .unwrap()
+                .push((cc.reason, cc.tid, cc.siginfo.ssi_signo));
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    let monitor = ch::hang_monitor::HangMonitor::start(Duration::from_millis(200)).unwrap();
+
+    // Pinging in time is not a hang
+    for _ in 0..10 {
+        monitor.ping();
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(HANGS.lock().unwrap().is_empty());
+
+    // SAFETY: syscall
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as i32;
+
+    // Hanging for several timeouts is only reported once
+    monitor.ping();
+    std::thread::sleep(Duration::from_millis(1000));
+    let hangs = std::mem::take(&mut *HANGS.lock().unwrap());
+    assert_eq!(hangs, [(ch::CrashReason::Hang, tid, 0)]);
+
+    // But once the thread makes progress, it can be reported again
+    monitor.ping();
+    std::thread::sleep(Duration::from_millis(1000));
+    assert_eq!(HANGS.lock().unwrap().len(), 1);
+
+    monitor.stop();
+    handler.detach();
+}
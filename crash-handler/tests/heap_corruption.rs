@@ -0,0 +1,10 @@
+//! Heap corruption on Windows terminates the process without raising an
+//! exception that can be handled, just as with abort
+#![cfg(unix)]
+
+mod shared;
+
+#[test]
+fn handles_heap_corruption() {
+    shared::handles_crash(shared::SadnessFlavor::HeapCorruption);
+}
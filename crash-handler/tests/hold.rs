@@ -0,0 +1,68 @@
+//! Ensures that a held crash keeps the crashing thread parked until it is
+//! released, and that the disposition it is released with is the one used
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+#[test]
+fn held_until_released() {
+    static RELEASED: AtomicBool = AtomicBool::new(false);
+
+    let kick = libc::SIGRTMIN() + 4;
+
+    assert!(ch::CrashGuard::hold().is_none());
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[kick])
+        .callback_thread(true)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| {
+                let guard = ch::CrashGuard::hold().expect("failed to hold the crash");
+                assert!(ch::CrashGuard::hold().is_none());
+
+                // The crash is processed elsewhere, which decides that the
+                // process can continue after all
+                std::thread::spawn(move || {
+                    std::thread::sleep(Duration::from_millis(50));
+                    RELEASED.store(true, Ordering::Relaxed);
+                    guard.release(ch::CrashEventResult::Continue);
+                });
+
+                ch::CrashEventResult::Handled { exit: Some(1) }
+            })
+        })
+        .unwrap();
+
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(kick);
+    }
+    assert!(RELEASED.swap(false, Ordering::Relaxed));
+
+    assert!(matches!(
+        handler.simulate_signal(ch::Signal::Segv),
+        ch::CrashEventResult::Continue
+    ));
+    assert!(RELEASED.load(Ordering::Relaxed));
+
+    handler.detach();
+
+    // Dropping the guard without releasing it keeps the result of the callback
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            drop(ch::CrashGuard::hold().expect("failed to hold the crash"));
+            ch::CrashEventResult::Reraise
+        })
+    })
+    .unwrap();
+    assert!(matches!(
+        handler.simulate_signal(ch::Signal::Segv),
+        ch::CrashEventResult::Reraise
+    ));
+    handler.detach();
+}
@@ -0,0 +1,117 @@
+//! Ensures that a failure to install a signal handler is reported precisely,
+//! and that the handlers that were already installed are rolled back
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+/// Installs a filter on the calling thread that denies replacing the handler
+/// for `SIGSEGV` with `EPERM`, while still allowing it to be retrieved
+fn deny_segv_handler() {
+    const LD_W_ABS: u16 = 0x20;
+    const JEQ_K: u16 = 0x15;
+    const RET_K: u16 = 0x06;
+
+    let stmt = |code, k| libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |k, jt, jf| libc::sock_filter {
+        code: JEQ_K,
+        jt,
+        jf,
+        k,
+    };
+
+    let filter = [
+        // seccomp_data::arch
+        stmt(LD_W_ABS, 4),
+        jump(crash_context::NATIVE_AUDIT_ARCH, 0, 9),
+        // seccomp_data::nr
+        stmt(LD_W_ABS, 0),
+        jump(libc::SYS_rt_sigaction as u32, 0, 7),
+        // The signal, seccomp_data::args[0]
+        stmt(LD_W_ABS, 16),
+        jump(libc::SIGSEGV as u32, 0, 5),
+        // The new action, seccomp_data::args[1], which is null when only
+        // retrieving the current one
+        stmt(LD_W_ABS, 24),
+        jump(0, 0, 2),
+        stmt(LD_W_ABS, 28),
+        jump(0, 1, 0),
+        stmt(RET_K, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32),
+        stmt(RET_K, libc::SECCOMP_RET_ALLOW),
+    ];
+
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut _,
+    };
+
+    // SAFETY: syscalls
+    unsafe {
+        assert_eq!(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0), 0);
+        assert_eq!(
+            libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog),
+            0,
+            "failed to install seccomp filter"
+        );
+    }
+}
+
+fn current_handler(sig: i32) -> usize {
+    // SAFETY: syscall
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        assert_eq!(libc::sigaction(sig, std::ptr::null(), &mut action), 0);
+        action.sa_sigaction
+    }
+}
+
+#[test]
+fn reports_install_failure() {
+    // The filter only applies to the thread that installs it, so keep it off
+    // the main test thread
+    std::thread::spawn(|| {
+        let before = current_handler(libc::SIGBUS);
+
+        deny_segv_handler();
+
+        let Err(err) = ch::CrashHandler::attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Reraise)
+        }) else {
+            panic!("attaching should have failed");
+        };
+
+        match &err {
+            ch::Error::Install {
+                step,
+                error,
+                seccomp,
+                rolled_back,
+            } => {
+                assert_eq!(*step, ch::InstallStep::InstallHandler(libc::SIGSEGV));
+                assert_eq!(error.raw_os_error(), Some(libc::EPERM));
+                assert!(seccomp);
+                assert!(rolled_back);
+            }
+            other => panic!("unexpected error {other}"),
+        }
+
+        assert!(err.to_string().contains("(denied by seccomp)"));
+
+        // SIGBUS is installed before SIGSEGV, and must have been restored
+        assert_eq!(current_handler(libc::SIGBUS), before);
+    })
+    .join()
+    .unwrap();
+
+    // Nothing was left behind, so attaching on another thread still works
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Reraise)
+    })
+    .unwrap();
+    handler.detach();
+}
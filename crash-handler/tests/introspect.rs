@@ -0,0 +1,82 @@
+//! Ensures that the installed signal handlers and alternate stack are
+//! reported, including when another handler replaces ours
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{self as ch, introspect};
+
+extern "C" fn foreign_handler(_sig: libc::c_int) {}
+
+#[test]
+fn reports_handlers() {
+    assert!(introspect::installed_signals().is_empty());
+    let before = introspect::disposition(libc::SIGUSR1).unwrap();
+    assert_eq!(before, introspect::Disposition::Default);
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[libc::SIGUSR1])
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Continue)
+        })
+        .unwrap();
+
+    let installed = handler.installed_signals();
+    assert!(installed.iter().all(|installed| !installed.replaced));
+    for sig in [libc::SIGSEGV, libc::SIGABRT, libc::SIGUSR1] {
+        assert!(installed.iter().any(|installed| installed.signal == sig));
+    }
+
+    let usr1 = installed
+        .iter()
+        .find(|installed| installed.signal == libc::SIGUSR1)
+        .unwrap();
+    assert_eq!(usr1.previous, introspect::Disposition::Default);
+    assert!(matches!(
+        usr1.current,
+        introspect::Disposition::Handler {
+            siginfo: true,
+            on_stack: true,
+            ..
+        }
+    ));
+
+    // SAFETY: syscall
+    unsafe {
+        libc::signal(
+            libc::SIGUSR1,
+            foreign_handler as *const () as libc::sighandler_t,
+        );
+    }
+
+    let usr1 = handler
+        .installed_signals()
+        .into_iter()
+        .find(|installed| installed.signal == libc::SIGUSR1)
+        .unwrap();
+    assert!(usr1.replaced);
+    assert_eq!(
+        usr1.current,
+        introspect::Disposition::Handler {
+            address: foreign_handler as *const () as usize,
+            siginfo: false,
+            on_stack: false,
+        }
+    );
+
+    // Threads created after attaching get an alternate stack from us
+    std::thread::spawn(|| {
+        assert!(matches!(
+            introspect::alt_stack(),
+            introspect::AltStack::Ours { .. }
+        ));
+    })
+    .join()
+    .unwrap();
+
+    handler.detach();
+    assert!(introspect::installed_signals().is_empty());
+    assert_eq!(
+        introspect::disposition(libc::SIGUSR1),
+        Some(introspect::Disposition::Default)
+    );
+}
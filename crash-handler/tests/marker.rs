@@ -0,0 +1,47 @@
+//! Ensures that a crash marker is only reported if it wasn't cleared
+#![allow(unsafe_code)]
+
+use crash_handler::marker::{AbnormalExit, CrashMarker};
+
+#[test]
+fn reports_uncleared_markers() {
+    let path = std::env::temp_dir().join(format!("crash-marker-{}", std::process::id()));
+
+    let marker = CrashMarker::create(&path).unwrap();
+    assert_eq!(marker.path(), path);
+
+    // Forgetting the marker is the same as the process being killed before
+    // it could clear it
+    let _marker = std::mem::ManuallyDrop::new(marker);
+
+    let previous = CrashMarker::previous_run(&path).unwrap();
+    assert_eq!(previous.pid, std::process::id());
+    assert_eq!(previous.cause, AbnormalExit::Unknown);
+    assert!(previous.started <= std::time::SystemTime::now());
+
+    // Creating a new marker replaces the previous one, and clearing it means
+    // the next run won't report anything
+    CrashMarker::create(&path).unwrap().clear();
+    assert!(!path.exists());
+    assert!(CrashMarker::previous_run(&path).is_none());
+}
+
+#[test]
+fn clears_marker_on_detach() {
+    let path = std::env::temp_dir().join(format!("crash-marker-handler-{}", std::process::id()));
+
+    let handler = crash_handler::CrashHandler::builder()
+        .crash_marker(&path)
+        .attach(unsafe {
+            crash_handler::make_crash_event(|_cc| crash_handler::CrashEventResult::Reraise)
+        })
+        .unwrap();
+
+    assert_eq!(
+        CrashMarker::previous_run(&path).unwrap().pid,
+        std::process::id()
+    );
+
+    handler.detach();
+    assert!(CrashMarker::previous_run(&path).is_none());
+}
@@ -0,0 +1,49 @@
+//! Ensures that the memory pressure watcher invokes its callback with a context
+//! marked as memory pressure rather than a crash
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use crash_handler::{
+    memory_pressure::{MemoryWatcher, Trigger},
+    CrashReason,
+};
+use std::{sync::mpsc, time::Duration};
+
+#[test]
+fn fires_on_rss_limit() {
+    let (tx, rx) = mpsc::channel();
+
+    let watcher = MemoryWatcher::start(
+        Trigger::Rss {
+            limit: 1,
+            interval: Duration::from_millis(10),
+        },
+        move |cc| {
+            let _res = tx.send((cc.reason, cc.pid, cc.siginfo.ssi_signo));
+        },
+    )
+    .unwrap();
+
+    let (reason, pid, signo) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(reason, CrashReason::MemoryPressure);
+    assert_eq!(pid, std::process::id() as i32);
+    assert_eq!(signo, 0);
+
+    // The limit is never dropped below, so the trigger doesn't fire again
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+
+    watcher.stop();
+}
+
+#[test]
+fn rejects_invalid_stall_window() {
+    // The window must be at least 500ms, and kernels without pressure stall
+    // information fail to open the file at all
+    assert!(MemoryWatcher::start(
+        Trigger::Stall {
+            stall: Duration::from_micros(500),
+            window: Duration::from_millis(1),
+        },
+        |_cc| {},
+    )
+    .is_err());
+}
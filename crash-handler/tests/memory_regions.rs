@@ -0,0 +1,122 @@
+//! Ensures that memory regions can be included, and are available to the crash handler
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use ch::memory_regions;
+use crash_handler as ch;
+
+fn collect() -> Vec<(usize, usize, String)> {
+    let mut set = Vec::new();
+    memory_regions::for_each(|address, size, tag| set.push((address, size, tag.to_owned())));
+    set
+}
+
+#[test]
+fn memory_regions() {
+    let first = [1u8; 64];
+    let second = [2u8; 128];
+
+    memory_regions::include_region(first.as_ptr(), first.len(), "first");
+    memory_regions::include_region(second.as_ptr(), second.len(), "second");
+
+    assert_eq!(
+        collect(),
+        [
+            (first.as_ptr() as usize, first.len(), "first".to_owned()),
+            (second.as_ptr() as usize, second.len(), "second".to_owned())
+        ]
+    );
+
+    // Including a region with an existing tag replaces it
+    memory_regions::include_region(second.as_ptr(), 16, "first");
+    assert_eq!(
+        collect(),
+        [
+            (second.as_ptr() as usize, 16, "first".to_owned()),
+            (second.as_ptr() as usize, second.len(), "second".to_owned())
+        ]
+    );
+
+    memory_regions::exclude_region("first");
+    assert_eq!(
+        collect(),
+        [(second.as_ptr() as usize, second.len(), "second".to_owned())]
+    );
+
+    memory_regions::clear();
+    assert!(collect().is_empty());
+
+    // Once every slot is used, the region included the longest time ago is evicted
+    for i in 0..crash_context::MAX_MEMORY_REGIONS + 1 {
+        memory_regions::include_region(first.as_ptr(), i, &i.to_string());
+    }
+    let set = collect();
+    assert_eq!(set.len(), crash_context::MAX_MEMORY_REGIONS);
+    assert!(!set.iter().any(|(_, _, tag)| tag == "0"));
+
+    memory_regions::clear();
+
+    // Redactions are expanded to whole pages, and are neither included nor
+    // affected by clearing the included regions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let secret = first.as_ptr() as usize;
+    assert!(memory_regions::redact_region(first.as_ptr(), first.len()));
+    assert!(memory_regions::redact_region(first.as_ptr(), first.len()));
+    memory_regions::clear();
+    assert!(collect().is_empty());
+
+    let redacted = || {
+        let mut set = Vec::new();
+        memory_regions::for_each_redacted(|address, size| set.push((address, size)));
+        set
+    };
+    let start = secret & !(page_size - 1);
+    let end = (secret + first.len() + page_size - 1) & !(page_size - 1);
+    assert_eq!(redacted(), [(start, end - start)]);
+
+    memory_regions::unredact_region(first.as_ptr(), first.len());
+    assert!(redacted().is_empty());
+
+    // Redactions are never evicted
+    for i in 0..crash_context::MAX_REDACTED_REGIONS {
+        assert!(memory_regions::redact_region(
+            (i * page_size) as *const u8,
+            1
+        ));
+    }
+    assert!(!memory_regions::redact_region(first.as_ptr(), first.len()));
+    for i in 0..crash_context::MAX_REDACTED_REGIONS {
+        memory_regions::unredact_region((i * page_size) as *const u8, 1);
+    }
+
+    memory_regions::include_region(first.as_ptr(), first.len(), "state");
+    let address = first.as_ptr() as u64;
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(move |cc: &ch::CrashContext| {
+            assert_eq!(
+                cc.memory_region_count,
+                crash_context::MAX_MEMORY_REGION_SLOTS
+            );
+
+            // We're in the same process, so can just read the slots directly
+            let slots = std::slice::from_raw_parts(
+                cc.memory_regions as *const crash_context::MemoryRegion,
+                cc.memory_region_count,
+            );
+            let set: Vec<_> = slots
+                .iter()
+                .filter_map(|slot| Some((slot.address, slot.size, slot.tag()?)))
+                .collect();
+            assert_eq!(set, [(address, 64, "state")]);
+
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    assert!(matches!(
+        handler.simulate_signal(ch::Signal::Trap),
+        ch::CrashEventResult::Handled { exit: None }
+    ));
+}
@@ -0,0 +1,46 @@
+//! Ensures that the loaded modules can be enumerated and attributed addresses
+#![cfg(not(any(target_os = "freebsd", target_os = "openbsd")))]
+
+use crash_handler::modules;
+
+#[test]
+fn enumerates_modules() {
+    let list = modules::enumerate();
+    assert!(!list.as_slice().is_empty());
+
+    // The test executable itself is a module
+    let exe = std::env::current_exe().unwrap();
+    let module = list
+        .find(enumerates_modules as *const () as usize)
+        .expect("failed to find the module of a function");
+    assert_eq!(
+        std::fs::canonicalize(module.path()).unwrap(),
+        std::fs::canonicalize(exe).unwrap()
+    );
+
+    // As is libc, which is always built with a build id
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let libc = list
+            .find(libc::getpid as *const () as usize)
+            .expect("failed to find libc");
+        assert!(!libc.build_id().is_empty());
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let module = list
+            .find(libc::getpid as *const () as usize)
+            .expect("failed to find libsystem");
+        assert_eq!(module.build_id().len(), 16);
+    }
+
+    // Refreshing the cache stores the same modules
+    modules::refresh();
+    modules::with_cached(|cached| {
+        assert_eq!(cached.len(), list.as_slice().len());
+        assert!(cached
+            .iter()
+            .any(|module| module.contains(enumerates_modules as *const () as usize)));
+    });
+}
@@ -0,0 +1,45 @@
+//! Ensures that handling a signal doesn't allocate, other than within the
+//! user's callback
+#![cfg(all(
+    feature = "alloc-check",
+    any(target_os = "linux", target_os = "android")
+))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+#[global_allocator]
+static ALLOC: ch::alloc_check::DetectingAllocator = ch::alloc_check::DetectingAllocator;
+
+#[test]
+fn reraise_does_not_allocate() {
+    let _handler = ch::CrashHandler::builder()
+        .raw_signals(&[libc::SIGUSR1])
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| {
+                // Allocating in the callback itself is fine
+                drop(vec![0u8; 64]);
+                ch::CrashEventResult::Reraise
+            })
+        })
+        .unwrap();
+
+    // The handlers are restored before the signal is raised again, so it
+    // has to happen in a separate process
+    let pid = unsafe { libc::fork() };
+    assert_ne!(pid, -1);
+
+    if pid == 0 {
+        unsafe {
+            libc::raise(libc::SIGUSR1);
+            libc::_exit(0);
+        }
+    }
+
+    let mut status = 0;
+    assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+
+    // Any allocation would have aborted the child instead
+    assert!(libc::WIFSIGNALED(status), "child exited with {status}");
+    assert_eq!(libc::WTERMSIG(status), libc::SIGUSR1);
+}
@@ -0,0 +1,36 @@
+//! Ensures that panics are routed through the crash handler
+#![cfg(feature = "panic")]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[test]
+fn handles_panic() {
+    static HANDLED: AtomicBool = AtomicBool::new(false);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                assert_eq!(_cc.reason, ch::CrashReason::Panic);
+                assert_eq!(_cc.siginfo.ssi_signo, libc::SIGABRT as u32);
+            }
+            #[cfg(target_os = "windows")]
+            assert_eq!(_cc.exception_code, ch::ExceptionCode::Panic as i32);
+
+            let message = ch::panic_message().expect("we should have a panic message");
+            assert!(message.contains("oh no"), "{message}");
+
+            HANDLED.store(true, Ordering::Relaxed);
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    assert!(std::panic::catch_unwind(|| panic!("oh no")).is_err());
+    assert!(HANDLED.load(Ordering::Relaxed));
+    assert!(ch::panic_message().is_none());
+
+    handler.detach();
+}
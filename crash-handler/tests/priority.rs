@@ -0,0 +1,86 @@
+//! Ensures that multiple handlers can be attached at the same time, and that
+//! they are invoked in order of priority until one of them handles the crash
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+static CALLS: parking_lot::Mutex<Vec<&'static str>> = parking_lot::const_mutex(Vec::new());
+
+fn attach(
+    name: &'static str,
+    priority: i32,
+    result: fn() -> ch::CrashEventResult,
+) -> ch::CrashHandler {
+    ch::CrashHandler::attach_with_priority(priority, unsafe {
+        ch::make_crash_event(move |_cc: &ch::CrashContext| {
+            CALLS.lock().push(name);
+            result()
+        })
+    })
+    .unwrap()
+}
+
+fn take_calls() -> Vec<&'static str> {
+    std::mem::take(&mut *CALLS.lock())
+}
+
+#[test]
+fn invokes_in_priority_order() {
+    let reraise = || ch::CrashEventResult::Reraise;
+    let handled = || ch::CrashEventResult::Handled { exit: None };
+
+    let low = attach("low", -1, handled);
+    let first = attach("first", ch::DEFAULT_PRIORITY, reraise);
+    let high = attach("high", 10, reraise);
+    let second = attach("second", ch::DEFAULT_PRIORITY, reraise);
+
+    // Handlers with the same priority are invoked most recently attached first
+    assert!(matches!(
+        high.simulate_signal(ch::Signal::Trap),
+        ch::CrashEventResult::Handled { .. }
+    ));
+    assert_eq!(take_calls(), ["high", "second", "first", "low"]);
+
+    // Once a handler doesn't reraise, no further handlers are invoked
+    let highest = attach("highest", 20, handled);
+    assert!(matches!(
+        high.simulate_signal(ch::Signal::Trap),
+        ch::CrashEventResult::Handled { .. }
+    ));
+    assert_eq!(take_calls(), ["highest"]);
+
+    highest.detach();
+    low.detach();
+
+    // If every handler reraises, so do we
+    assert!(matches!(
+        high.simulate_signal(ch::Signal::Trap),
+        ch::CrashEventResult::Reraise
+    ));
+    assert_eq!(take_calls(), ["high", "second", "first"]);
+
+    // Actual signals are dispatched the same way, and our signal handler stays
+    // installed until the last handler is detached
+    let cont = attach("continue", -1, || ch::CrashEventResult::Continue);
+
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(libc::SIGTRAP);
+    }
+
+    assert_eq!(take_calls(), ["high", "second", "first", "continue"]);
+
+    drop(first);
+    drop(second);
+    drop(high);
+
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(libc::SIGTRAP);
+    }
+
+    assert_eq!(take_calls(), ["continue"]);
+
+    cont.detach();
+}
@@ -0,0 +1,42 @@
+//! Ensures that signals outside of [`crash_handler::Signal`], eg. realtime
+//! signals, can be routed through the handler by their raw number
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+#[test]
+fn handles_realtime_signal() {
+    static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+    let kick = libc::SIGRTMIN() + 2;
+
+    assert!(matches!(
+        ch::CrashHandler::builder()
+            .raw_signals(&[libc::SIGKILL])
+            .attach(unsafe {
+                ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Continue)
+            }),
+        Err(ch::Error::InvalidSignal(libc::SIGKILL))
+    ));
+
+    let handler = ch::CrashHandler::builder()
+        .raw_signals(&[kick])
+        .attach(unsafe {
+            ch::make_crash_event(|cc: &ch::CrashContext| {
+                RECEIVED.store(cc.siginfo.ssi_signo as i32, Ordering::Relaxed);
+                ch::CrashEventResult::Continue
+            })
+        })
+        .unwrap();
+
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(kick);
+    }
+
+    assert_eq!(RECEIVED.load(Ordering::Relaxed), kick);
+
+    handler.detach();
+}
@@ -0,0 +1,55 @@
+//! Ensures that crashes within `catch_crash` are recovered from, without
+//! invoking the user's handler or breaking the handler for later crashes
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn recovers_from_crashes() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            ch::CrashEventResult::Continue
+        })
+    })
+    .unwrap();
+
+    assert_eq!(ch::recover::catch_crash(|| 42), Ok(42));
+
+    for _ in 0..2 {
+        let caught = ch::recover::catch_crash(|| unsafe { sadness_generator::raise_segfault() });
+        assert_eq!(
+            caught,
+            Err(ch::recover::CaughtCrash {
+                code: libc::SIGSEGV
+            })
+        );
+    }
+
+    // Nested calls are caught by the innermost call
+    let outer = ch::recover::catch_crash(|| {
+        ch::recover::catch_crash(|| unsafe { sadness_generator::raise_trap() })
+    });
+    assert_eq!(
+        outer,
+        Ok(Err(ch::recover::CaughtCrash {
+            code: libc::SIGTRAP
+        }))
+    );
+
+    assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+
+    // Signals outside of `catch_crash` are delivered to the handler as normal,
+    // which means the handler state was not left locked
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(libc::SIGTRAP);
+    }
+    assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+
+    handler.detach();
+}
@@ -0,0 +1,51 @@
+//! Ensures that a crash inside the user's handler kills the process rather
+//! than re-entering the handler or deadlocking. Note that the handler blocks
+//! the signals it handles while it is running, so a fault inside of it kills
+//! the process regardless, but `abort` explicitly unblocks `SIGABRT`
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::{os::unix::process::ExitStatusExt, time::Duration};
+
+const CHILD_ENV: &str = "CRASH_HANDLER_RECURSIVE_CHILD";
+
+#[test]
+fn recursive_crash() {
+    if std::env::var_os(CHILD_ENV).is_some() {
+        let _handler = ch::CrashHandler::attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| {
+                sadness_generator::raise_abort();
+            })
+        })
+        .unwrap();
+
+        unsafe {
+            sadness_generator::raise_segfault();
+        }
+    }
+
+    // Run ourselves in a child process, since we expect the crash to kill it
+    let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "recursive_crash", "--nocapture"])
+        .env(CHILD_ENV, "1")
+        .spawn()
+        .expect("failed to spawn child");
+
+    let mut waited = Duration::ZERO;
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+
+        if waited > Duration::from_secs(10) {
+            child.kill().unwrap();
+            panic!("child process deadlocked");
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+        waited += Duration::from_millis(50);
+    };
+
+    assert_eq!(status.signal(), Some(libc::SIGABRT));
+}
@@ -0,0 +1,81 @@
+//! Ensures that the handler installed by a sanitizer before ours is invoked
+//! after the callback, so that the sanitizer still reports the crash
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+mod shared;
+
+use crash_handler::{self as ch, sanitizers};
+use std::{
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Stands in for the `AddressSanitizer` runtime, which is how it is detected,
+/// and is exported by the build script
+#[no_mangle]
+pub extern "C" fn __asan_init() {}
+
+/// Incremented by each handler, so that their order can be checked
+static INVOKED: AtomicUsize = AtomicUsize::new(0);
+static CALLBACK: AtomicUsize = AtomicUsize::new(0);
+static SANITIZER: AtomicUsize = AtomicUsize::new(0);
+
+/// The sanitizer's handler, which would print its report and exit
+extern "C" fn sanitizer_handler(_sig: i32, _info: *mut libc::siginfo_t, _uc: *mut libc::c_void) {
+    SANITIZER.store(INVOKED.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+    // SAFETY: the buffer is set before crashing, on this thread
+    unsafe { ch::jmp::siglongjmp(shared::recovery_point(), 1) }
+}
+
+fn attach(policy: ch::SanitizerPolicy) -> Result<ch::CrashHandler, ch::Error> {
+    ch::CrashHandler::builder()
+        .sanitizer_policy(policy)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| {
+                CALLBACK.store(INVOKED.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                ch::CrashEventResult::Handled { exit: None }
+            })
+        })
+}
+
+#[test]
+fn chains_to_sanitizer() {
+    assert_eq!(
+        sanitizers::detect(),
+        Some(sanitizers::Tool::AddressSanitizer)
+    );
+
+    // SAFETY: syscalls
+    let original = unsafe {
+        let mut sa: libc::sigaction = mem::zeroed();
+        libc::sigemptyset(&mut sa.sa_mask);
+        sa.sa_sigaction = sanitizer_handler as *const () as usize;
+        sa.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+
+        let mut original = mem::zeroed();
+        assert_eq!(libc::sigaction(libc::SIGSEGV, &sa, &mut original), 0);
+        original
+    };
+
+    assert!(matches!(
+        attach(ch::SanitizerPolicy::StepAside),
+        Err(ch::Error::SanitizerDetected(
+            sanitizers::Tool::AddressSanitizer
+        ))
+    ));
+
+    let handler = attach(ch::SanitizerPolicy::Chain).unwrap();
+    shared::crash_and_recover();
+    handler.detach();
+
+    // The callback handled the crash, but the sanitizer was still invoked
+    // after it
+    assert_eq!(CALLBACK.load(Ordering::SeqCst), 1);
+    assert_eq!(SANITIZER.load(Ordering::SeqCst), 2);
+
+    // SAFETY: syscall
+    unsafe {
+        libc::sigaction(libc::SIGSEGV, &original, std::ptr::null_mut());
+    }
+}
@@ -0,0 +1,39 @@
+//! Ensures that the handler steps aside when a tool that reports crashes
+//! itself is detected, if configured to
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{self as ch, sanitizers};
+
+fn attach(policy: ch::SanitizerPolicy) -> Result<ch::CrashHandler, ch::Error> {
+    ch::CrashHandler::builder()
+        .sanitizer_policy(policy)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Continue)
+        })
+}
+
+#[test]
+fn detects_valgrind() {
+    assert_eq!(sanitizers::detect(), None);
+    attach(ch::SanitizerPolicy::StepAside).unwrap().detach();
+
+    // Valgrind's preload library isn't actually loaded, but the environment
+    // is the same as under Valgrind
+    std::env::set_var(
+        "LD_PRELOAD",
+        "/usr/libexec/valgrind/vgpreload_core-amd64-linux.so:/usr/libexec/valgrind/vgpreload_memcheck-amd64-linux.so",
+    );
+    assert_eq!(sanitizers::detect(), Some(sanitizers::Tool::Valgrind));
+
+    assert!(matches!(
+        attach(ch::SanitizerPolicy::StepAside),
+        Err(ch::Error::SanitizerDetected(sanitizers::Tool::Valgrind))
+    ));
+    assert!(ch::introspect::installed_signals().is_empty());
+
+    attach(ch::SanitizerPolicy::Chain).unwrap().detach();
+    attach(ch::SanitizerPolicy::Ignore).unwrap().detach();
+
+    std::env::remove_var("LD_PRELOAD");
+}
@@ -0,0 +1,80 @@
+//! Ensures that a syscall forbidden by a seccomp filter is decoded from the
+//! `SIGSYS` it raises
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+static VIOLATION: parking_lot::Mutex<Option<ch::SeccompViolation>> = parking_lot::const_mutex(None);
+
+/// Installs a filter on the calling thread that traps `getppid`
+fn forbid_getppid() {
+    const LD_W_ABS: u16 = 0x20;
+    const JEQ_K: u16 = 0x15;
+    const RET_K: u16 = 0x06;
+
+    let stmt = |code, k| libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |k, jf| libc::sock_filter {
+        code: JEQ_K,
+        jt: 0,
+        jf,
+        k,
+    };
+
+    let filter = [
+        // seccomp_data::arch
+        stmt(LD_W_ABS, 4),
+        jump(crash_context::NATIVE_AUDIT_ARCH, 3),
+        // seccomp_data::nr
+        stmt(LD_W_ABS, 0),
+        jump(libc::SYS_getppid as u32, 1),
+        stmt(RET_K, libc::SECCOMP_RET_TRAP),
+        stmt(RET_K, libc::SECCOMP_RET_ALLOW),
+    ];
+
+    let prog = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut _,
+    };
+
+    // SAFETY: syscalls
+    unsafe {
+        assert_eq!(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0), 0);
+        assert_eq!(
+            libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog),
+            0,
+            "failed to install seccomp filter"
+        );
+    }
+}
+
+#[test]
+fn decodes_seccomp_violation() {
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            *VIOLATION.lock() = cc.seccomp_violation();
+            ch::CrashEventResult::Continue
+        })
+    })
+    .unwrap();
+
+    forbid_getppid();
+
+    // SAFETY: syscall, which is trapped rather than performed, and returns
+    // once the handler has continued execution
+    unsafe {
+        libc::syscall(libc::SYS_getppid);
+    }
+
+    let violation = VIOLATION.lock().take().expect("no seccomp violation");
+    assert_eq!(violation.syscall, libc::SYS_getppid as i32);
+    assert!(violation.is_native_arch());
+    assert_ne!(violation.call_addr, 0);
+
+    handler.detach();
+}
@@ -1,15 +1,50 @@
 #![allow(unsafe_code)]
+// Each test only uses some of the helpers
+#![allow(dead_code)]
 
+#[allow(unused_imports)]
 pub use ch::debug_print;
 use crash_handler as ch;
 
 pub use sadness_generator::SadnessFlavor;
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+thread_local! {
+    /// Where the crash on the current thread jumps back to
+    static JMP_BUF: std::cell::Cell<*mut ch::jmp::JmpBuf> = const { std::cell::Cell::new(std::ptr::null_mut()) };
+}
+
+/// Crashes, and returns once a handler has jumped back to the
+/// [`recovery_point`]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline(never)]
+pub fn crash_and_recover() {
+    let mut jmp_buf = std::mem::MaybeUninit::<ch::jmp::JmpBuf>::uninit();
+
+    // SAFETY: the buffer outlives the crash, which jumps back before we return
+    if unsafe { ch::jmp::sigsetjmp(jmp_buf.as_mut_ptr(), 1) } == 0 {
+        JMP_BUF.with(|jb| jb.set(jmp_buf.as_mut_ptr()));
+        // SAFETY: the crash is handled by jumping back above
+        unsafe { sadness_generator::raise_segfault() }
+    }
+
+    JMP_BUF.with(|jb| jb.set(std::ptr::null_mut()));
+}
+
+/// Where a crash raised by [`crash_and_recover`] on the current thread jumps
+/// back to
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn recovery_point() -> *mut ch::jmp::JmpBuf {
+    JMP_BUF.with(|jb| jb.get())
+}
+
 pub fn handles_crash(flavor: SadnessFlavor) {
-    let mut _handler = None;
+    // The handler is never dropped, as unwinding a foreign exception runs the
+    // cleanup for every frame, which would detach it before the crash
+    let mut _handler = std::mem::ManuallyDrop::new(None);
 
     unsafe {
-        _handler = Some(
+        *_handler = Some(
             ch::CrashHandler::attach(ch::make_crash_event(move |cc: &ch::CrashContext| {
                 cfg_if::cfg_if! {
                     if #[cfg(any(target_os = "linux", target_os = "android"))] {
@@ -18,29 +53,90 @@ pub fn handles_crash(flavor: SadnessFlavor) {
                         assert_eq!(
                             cc.siginfo.ssi_signo,
                             match flavor {
-                                SadnessFlavor::Abort => Signal::Abort,
+                                SadnessFlavor::Abort
+                                | SadnessFlavor::HeapCorruption
+                                | SadnessFlavor::CppException => Signal::Abort,
                                 SadnessFlavor::Bus => Signal::Bus,
                                 SadnessFlavor::DivideByZero => Signal::Fpe,
                                 SadnessFlavor::Illegal => Signal::Illegal,
-                                SadnessFlavor::Segfault | SadnessFlavor::StackOverflow { .. } => {
-                                    Signal::Segv
-                                }
+                                SadnessFlavor::Segfault
+                                | SadnessFlavor::WildWrite
+                                | SadnessFlavor::StackOverflow { .. } => Signal::Segv,
                                 SadnessFlavor::Trap => Signal::Trap,
                             } as u32,
                         );
 
+                        assert_eq!(
+                            cc.reason,
+                            match flavor {
+                                SadnessFlavor::StackOverflow { .. } => ch::CrashReason::StackOverflow,
+                                SadnessFlavor::Trap => ch::CrashReason::Breakpoint,
+                                // ud2 and unimp are also what __builtin_trap emits
+                                SadnessFlavor::Illegal if cfg!(any(target_arch = "x86", target_arch = "x86_64", target_arch = "riscv64")) => {
+                                    ch::CrashReason::Trap
+                                }
+                                _ => ch::CrashReason::Signal,
+                            }
+                        );
+
+                        if matches!(flavor, SadnessFlavor::Segfault | SadnessFlavor::WildWrite) {
+                            let fault = cc.fault().expect("segfaults should have a fault address");
+                            assert_eq!(
+                                fault.address as usize,
+                                if flavor == SadnessFlavor::Segfault {
+                                    sadness_generator::SEGFAULT_ADDRESS as usize
+                                } else {
+                                    sadness_generator::WILD_WRITE_TARGET.as_ptr() as usize
+                                }
+                            );
+                            assert_eq!(
+                                fault.access,
+                                if cfg!(target_arch = "riscv64") {
+                                    ch::AccessType::Unknown
+                                } else {
+                                    ch::AccessType::Write
+                                }
+                            );
+                        }
+
+                        // glibc records the double free it detected
+                        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+                        if flavor == SadnessFlavor::HeapCorruption {
+                            assert!(cc.abort_message().is_some_and(|message| message.contains("free")));
+                        }
+
                         //assert_eq!(cc.tid, tid);
 
                         // At least on linux these...aren't set. Which is weird
                         //assert_eq!(cc.siginfo.ssi_pid, std::process::id());
                         //assert_eq!(cc.siginfo.ssi_tid, tid as u32);
+                    } else if #[cfg(any(target_os = "freebsd", target_os = "openbsd"))] {
+                        use ch::Signal;
+
+                        assert_eq!(
+                            cc.siginfo.si_signo,
+                            match flavor {
+                                SadnessFlavor::Abort
+                                | SadnessFlavor::HeapCorruption
+                                | SadnessFlavor::CppException => Signal::Abort,
+                                SadnessFlavor::Bus => Signal::Bus,
+                                SadnessFlavor::DivideByZero => Signal::Fpe,
+                                SadnessFlavor::Illegal => Signal::Illegal,
+                                SadnessFlavor::Segfault
+                                | SadnessFlavor::WildWrite
+                                | SadnessFlavor::StackOverflow { .. } => Signal::Segv,
+                                SadnessFlavor::Trap => Signal::Trap,
+                            } as i32,
+                        );
                     } else if #[cfg(target_os = "macos")] {
                         use ch::ExceptionType;
 
                         let exc = cc.exception.expect("we should have an exception");
 
                         let expected = match flavor {
-                            SadnessFlavor::Abort => {
+                            SadnessFlavor::Abort
+                            | SadnessFlavor::HeapCorruption
+                            | SadnessFlavor::CppException => {
                                 assert_eq!(exc.code, 0x10003); // EXC_SOFT_SIGNAL
                                 assert_eq!(exc.subcode.unwrap(), libc::SIGABRT as _);
 
@@ -48,6 +144,7 @@ pub fn handles_crash(flavor: SadnessFlavor) {
                             }
                             SadnessFlavor::Bus
                             | SadnessFlavor::Segfault
+                            | SadnessFlavor::WildWrite
                             | SadnessFlavor::StackOverflow { .. } => {
                                 if flavor == SadnessFlavor::Segfault {
                                     // For EXC_BAD_ACCESS exceptions, the subcode will be the
@@ -72,7 +169,8 @@ pub fn handles_crash(flavor: SadnessFlavor) {
                             SadnessFlavor::Illegal => ExceptionCode::Illegal,
                             SadnessFlavor::InvalidParameter => ExceptionCode::InvalidParameter,
                             SadnessFlavor::Purecall => ExceptionCode::Purecall,
-                            SadnessFlavor::Segfault => ExceptionCode::Segv,
+                            SadnessFlavor::Segfault | SadnessFlavor::WildWrite => ExceptionCode::Segv,
+                            SadnessFlavor::CppException => ExceptionCode::Panic,
                             SadnessFlavor::StackOverflow { .. }=> ExceptionCode::StackOverflow,
                             SadnessFlavor::Trap => ExceptionCode::Trap,
                         };
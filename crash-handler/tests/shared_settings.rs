@@ -0,0 +1,26 @@
+//! Ensures that the configuration of a handler that isn't the first to be
+//! attached, which is discarded, has no side effects
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+
+#[test]
+fn skips_marker_of_later_handlers() {
+    let path = std::env::temp_dir().join(format!("crash-marker-later-{}", std::process::id()));
+
+    let first = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc| ch::CrashEventResult::Reraise)
+    })
+    .unwrap();
+
+    let second = ch::CrashHandler::builder()
+        .crash_marker(&path)
+        .attach(unsafe { ch::make_crash_event(|_cc| ch::CrashEventResult::Reraise) })
+        .unwrap();
+
+    assert!(!path.exists());
+
+    second.detach();
+    first.detach();
+}
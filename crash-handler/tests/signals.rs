@@ -0,0 +1,79 @@
+//! Ensures that crash signals sent to the process are delivered to a thread
+//! that doesn't have them blocked
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{self as ch, signals};
+use std::{
+    sync::atomic::{AtomicI32, Ordering},
+    time::Duration,
+};
+
+fn is_blocked(sig: libc::c_int) -> bool {
+    // SAFETY: syscalls
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::pthread_sigmask(libc::SIG_BLOCK, std::ptr::null(), &mut set);
+        libc::sigismember(&set, sig) == 1
+    }
+}
+
+#[test]
+fn redirects_process_signals() {
+    static HANDLED_ON: AtomicI32 = AtomicI32::new(0);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|_cc: &ch::CrashContext| {
+            HANDLED_ON.store(libc::syscall(libc::SYS_gettid) as i32, Ordering::Relaxed);
+            ch::CrashEventResult::Continue
+        })
+    })
+    .unwrap();
+
+    std::thread::spawn(|| {
+        {
+            let _blocked = signals::BlockedCrashSignals::enter();
+            assert!(is_blocked(libc::SIGSEGV));
+            assert!(is_blocked(libc::SIGTRAP));
+            // Unrelated signals are untouched
+            assert!(!is_blocked(libc::SIGUSR1));
+
+            // SAFETY: syscalls
+            let tid = unsafe {
+                libc::kill(libc::getpid(), libc::SIGTRAP);
+                libc::syscall(libc::SYS_gettid) as i32
+            };
+
+            // The signal can't be delivered to this thread, so it is
+            // delivered to another one
+            for _ in 0..100 {
+                if HANDLED_ON.load(Ordering::Relaxed) != 0 {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            let handled_on = HANDLED_ON.load(Ordering::Relaxed);
+            assert_ne!(handled_on, 0);
+            assert_ne!(handled_on, tid);
+        }
+
+        assert!(!is_blocked(libc::SIGSEGV));
+
+        signals::block_crash_signals_on_this_thread();
+        assert!(is_blocked(libc::SIGABRT));
+
+        // Spawned threads inherit the mask, but can unblock them
+        std::thread::spawn(|| {
+            assert!(is_blocked(libc::SIGABRT));
+            signals::unblock_crash_signals_on_this_thread();
+            assert!(!is_blocked(libc::SIGABRT));
+        })
+        .join()
+        .unwrap();
+    })
+    .join()
+    .unwrap();
+
+    handler.detach();
+}
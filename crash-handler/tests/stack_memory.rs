@@ -0,0 +1,86 @@
+//! Ensures that the crashing thread's stack can be captured, and that capture
+//! stops at the end of readable memory
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::{stack_memory, CrashContext};
+
+#[test]
+fn captures_stack() {
+    let marker = std::hint::black_box([0xa5u8; 64]);
+    let cc = CrashContext::capture();
+
+    let mut buf = [0u8; 16 * 1024];
+    let stack = stack_memory::capture(&cc, &mut buf).unwrap();
+
+    assert_eq!(stack.start, stack.stack_pointer - stack_memory::RED_ZONE);
+    assert_eq!(stack.bytes().len(), 16 * 1024);
+
+    // The locals of this function are above the captured stack pointer
+    assert!(stack
+        .bytes()
+        .windows(marker.len())
+        .any(|window| window == marker));
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+#[test]
+fn stops_at_unreadable_memory() {
+    fn set_sp(cc: &mut CrashContext, sp: usize) {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "x86_64")] {
+                cc.context.uc_mcontext.gregs[libc::REG_RSP as usize] = sp as i64;
+            } else {
+                cc.context.uc_mcontext.sp = sp as u64;
+            }
+        }
+    }
+
+    // Map 3 pages, and make the first and last inaccessible
+    // SAFETY: syscalls
+    let base = unsafe {
+        let base = libc::mmap(
+            std::ptr::null_mut(),
+            3 * 4096,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+        assert_ne!(base, libc::MAP_FAILED);
+        assert_eq!(libc::mprotect(base, 4096, libc::PROT_NONE), 0);
+        assert_eq!(
+            libc::mprotect(
+                base.cast::<u8>().add(2 * 4096).cast(),
+                4096,
+                libc::PROT_NONE
+            ),
+            0
+        );
+        base as usize
+    };
+
+    let mut cc = CrashContext::capture();
+    let mut buf = [0u8; 16 * 1024];
+
+    // The capture is limited to the end of the readable page
+    set_sp(&mut cc, base + 4096 + 1024);
+    let stack = stack_memory::capture(&cc, &mut buf).unwrap();
+    assert_eq!(stack.start, stack.stack_pointer - stack_memory::RED_ZONE);
+    assert_eq!(stack.bytes().len(), 3 * 1024 + stack_memory::RED_ZONE);
+
+    // The red zone is skipped if it's not readable
+    set_sp(&mut cc, base + 4096);
+    let stack = stack_memory::capture(&cc, &mut buf).unwrap();
+    assert_eq!(stack.start, base + 4096);
+    assert_eq!(stack.bytes().len(), 4096);
+
+    // Nothing is captured if the stack pointer isn't readable
+    set_sp(&mut cc, base + 2 * 4096);
+    assert!(stack_memory::capture(&cc, &mut buf).is_none());
+
+    // SAFETY: syscall
+    unsafe {
+        libc::munmap(base as *mut _, 3 * 4096);
+    }
+}
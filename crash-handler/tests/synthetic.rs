@@ -0,0 +1,40 @@
+//! Ensures that a synthetic context is routed through the attached handlers
+//! exactly as it was created
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::sync::Mutex;
+
+#[test]
+fn routes_synthetic_context() {
+    static SEEN: Mutex<Option<(u32, u64, u64, u64)>> = Mutex::new(None);
+
+    let handler = ch::CrashHandler::attach(unsafe {
+        ch::make_crash_event(|cc: &ch::CrashContext| {
+            *SEEN.lock().unwrap() = Some((
+                cc.siginfo.ssi_signo,
+                cc.siginfo.ssi_addr,
+                cc.instruction_pointer(),
+                cc.stack_pointer(),
+            ));
+            ch::CrashEventResult::Handled { exit: None }
+        })
+    })
+    .unwrap();
+
+    let mut cc = ch::CrashContext::synthetic(libc::SIGBUS, 0xdead_b000, 0x7ff0_1000);
+    cc.siginfo.ssi_addr = 0x40;
+
+    // The result is returned rather than terminating the process
+    assert!(matches!(
+        handler.simulate_context(&cc),
+        ch::CrashEventResult::Handled { exit: None }
+    ));
+    assert_eq!(
+        SEEN.lock().unwrap().take(),
+        Some((libc::SIGBUS as u32, 0x40, 0xdead_b000, 0x7ff0_1000))
+    );
+
+    handler.detach();
+}
@@ -0,0 +1,106 @@
+//! Ensures that the process is terminated so that its parent observes the
+//! conventional wait status for the signal, either by actually being killed
+//! by it, or by exiting with 128 plus the signal number
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler as ch;
+use std::os::unix::process::ExitStatusExt;
+
+const CHILD_ENV: &str = "CRASH_HANDLER_TERMINATION_CHILD";
+
+fn run_child(test: &str, termination: &str) -> std::process::ExitStatus {
+    std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", test, "--nocapture"])
+        .env(CHILD_ENV, termination)
+        .status()
+        .expect("failed to run child")
+}
+
+fn crash_child(termination: ch::Termination) -> ! {
+    if termination == ch::Termination::CoreDump {
+        // SAFETY: syscalls
+        unsafe {
+            let limit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: libc::RLIM_INFINITY,
+            };
+            libc::setrlimit(libc::RLIMIT_CORE, &limit);
+            libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0);
+        }
+    }
+
+    let _handler = ch::CrashHandler::builder()
+        .termination(termination)
+        .attach(unsafe {
+            ch::make_crash_event(|_cc: &ch::CrashContext| ch::CrashEventResult::Handled {
+                exit: None,
+            })
+        })
+        .unwrap();
+
+    // Raised rather than caused by a fault, so that the signal has to be sent
+    // again rather than just occurring again once the handler returns
+    // SAFETY: syscall
+    unsafe {
+        libc::raise(libc::SIGBUS);
+    }
+
+    unreachable!("the signal should have terminated the process");
+}
+
+#[test]
+fn reraises_signal() {
+    if std::env::var_os(CHILD_ENV).is_some() {
+        crash_child(ch::Termination::Reraise);
+    }
+
+    let status = run_child("reraises_signal", "reraise");
+    assert_eq!(status.signal(), Some(libc::SIGBUS));
+}
+
+#[test]
+fn exits_with_signal_code() {
+    if std::env::var_os(CHILD_ENV).is_some() {
+        crash_child(ch::Termination::ExitCode);
+    }
+
+    let status = run_child("exits_with_signal_code", "exit");
+    assert_eq!(status.signal(), None);
+    assert_eq!(status.code(), Some(128 + libc::SIGBUS));
+}
+
+#[test]
+fn dumps_core() {
+    if std::env::var_os(CHILD_ENV).is_some() {
+        crash_child(ch::Termination::CoreDump);
+    }
+
+    // The hard limit has to allow core dumps in the first place
+    // SAFETY: syscall
+    let hard_limit = unsafe {
+        let mut limit: libc::rlimit = std::mem::zeroed();
+        libc::getrlimit(libc::RLIMIT_CORE, &mut limit);
+        limit.rlim_max
+    };
+    if hard_limit != libc::RLIM_INFINITY {
+        return;
+    }
+
+    // Any core file that is written relative to the working directory, which
+    // depends on the system's core_pattern, is written to a temporary one
+    let dir = std::env::temp_dir().join(format!("crash-handler-core-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "dumps_core", "--nocapture"])
+        .env(CHILD_ENV, "core")
+        .current_dir(&dir)
+        .status()
+        .expect("failed to run child");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(status.signal(), Some(libc::SIGBUS));
+    assert!(status.core_dumped());
+}
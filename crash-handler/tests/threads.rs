@@ -0,0 +1,76 @@
+//! Ensures that every other thread can be suspended, and their contexts
+//! captured
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::threads;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+#[test]
+fn suspends_and_captures() {
+    const THREADS: usize = 4;
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let counter = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let handles: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let stop = stop.clone();
+            let counter = counter.clone();
+            let tx = tx.clone();
+
+            std::thread::spawn(move || {
+                // SAFETY: syscall
+                tx.send(unsafe { libc::syscall(libc::SYS_gettid) } as i32)
+                    .unwrap();
+
+                while !stop.load(Ordering::Relaxed) {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    std::hint::spin_loop();
+                }
+            })
+        })
+        .collect();
+
+    let tids: Vec<i32> = rx.iter().take(THREADS).collect();
+
+    {
+        let suspended = threads::suspend_and_capture().unwrap();
+
+        // Only one set of threads can be suspended at a time
+        assert!(threads::suspend_and_capture().is_none());
+
+        for tid in &tids {
+            let cc = suspended
+                .iter()
+                .find(|cc| cc.tid == *tid)
+                .expect("failed to capture thread");
+            assert_eq!(cc.pid, std::process::id() as i32);
+            assert!(crash_handler::stack_memory::stack_pointer(cc).is_some());
+        }
+
+        assert_eq!(suspended.unresponsive().count(), 0);
+
+        // The threads don't make progress while suspended
+        let before = counter.load(Ordering::Relaxed);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(counter.load(Ordering::Relaxed), before);
+    }
+
+    // But do once resumed
+    let before = counter.load(Ordering::Relaxed);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert_ne!(counter.load(Ordering::Relaxed), before);
+
+    stop.store(true, Ordering::Relaxed);
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    // The threads can be suspended again
+    drop(threads::suspend_and_capture().unwrap());
+}
@@ -0,0 +1,41 @@
+//! Ensures that the crashing thread's stack can be unwound without unwind
+//! information
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use crash_handler::{
+    modules,
+    unwind::{self, Frame, FrameTrust},
+    CrashContext,
+};
+
+#[inline(never)]
+fn capture() -> CrashContext {
+    std::hint::black_box(CrashContext::capture())
+}
+
+#[test]
+fn unwinds_stack() {
+    modules::refresh();
+
+    let cc = capture();
+
+    let mut frames = [Frame::EMPTY; 64];
+    let frames = unwind::unwind(&cc, &mut frames);
+
+    // The crashing frame is always the instruction pointer
+    assert!(frames.len() > 1);
+    assert_eq!(frames[0].trust, FrameTrust::Context);
+
+    // This function is one of the callers
+    let exe = modules::enumerate();
+    let this = exe
+        .find(unwinds_stack as *const () as usize)
+        .expect("failed to find the test executable");
+    assert!(frames[1..]
+        .iter()
+        .any(|frame| this.contains(frame.address) && frame.trust != FrameTrust::Context));
+
+    // The number of frames is bounded by the buffer
+    let mut frames = [Frame::EMPTY; 2];
+    assert_eq!(unwind::unwind(&cc, &mut frames).len(), 2);
+}
@@ -0,0 +1,6 @@
+mod shared;
+
+#[test]
+fn handles_wild_write() {
+    shared::handles_crash(shared::SadnessFlavor::WildWrite);
+}
@@ -10,10 +10,16 @@ struct Command {
     id: String,
     /// The signal/exception to raise
     #[clap(long, arg_enum)]
-    signal: Signal,
+    signal: Option<Signal>,
+    /// Requests a live dump with the specified reason instead of crashing
+    #[clap(long)]
+    live_dump: Option<String>,
     /// Raises the signal on a separate thread rather than the main thread
     #[clap(long)]
     use_thread: bool,
+    /// Sends a `<kind>:<message>` user message to the server before crashing
+    #[clap(long)]
+    message: Vec<String>,
     /// Waits on a debugger to attach
     #[clap(long)]
     wait_on_debugger: bool,
@@ -53,14 +59,37 @@ fn real_main() -> anyhow::Result<()> {
         }
     };
 
+    if let Some(reason) = cmd.live_dump {
+        // Spawn some threads so that there is more than the requesting thread
+        // to capture in the dump
+        for _ in 0..10 {
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::MAX);
+            });
+        }
+
+        md_client.request_live_dump(&reason)?;
+        println!("live dump finished");
+        return Ok(());
+    }
+
+    for msg in &cmd.message {
+        let (kind, msg) = msg
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("message '{}' is not <kind>:<message>", msg))?;
+        md_client.send_message(kind.parse()?, msg)?;
+    }
+
     let _handler = crash_handler::CrashHandler::attach(unsafe {
         crash_handler::make_crash_event(move |cc: &crash_handler::CrashContext| {
             let handled = md_client.request_dump(cc).is_ok();
-            crash_handler::CrashEventResult::Handled(handled)
+            crash_handler::CrashEventResult::from(handled)
         })
     });
 
-    let signal = cmd.signal;
+    let signal = cmd
+        .signal
+        .ok_or_else(|| anyhow::anyhow!("one of --signal or --live-dump is required"))?;
 
     let raise_signal = move || {
         // SAFETY: we're about to intentionally crash ourselves via shenanigans,
@@ -79,6 +79,8 @@ use std::{
 pub struct Server {
     pub id: String,
     pub dump_rx: mpsc::Receiver<PathBuf>,
+    /// The user messages sent by the client via [`minidumper::Client::send_message`]
+    pub message_rx: mpsc::Receiver<(u32, Vec<u8>)>,
     exit_run_loop: Arc<AtomicBool>,
     run_loop: Option<std::thread::JoinHandle<()>>,
 }
@@ -115,6 +117,7 @@ pub fn spinup_server(id: &str, dump_path: Option<PathBuf>) -> Server {
     struct Inner {
         _id: String,
         dump_tx: Mutex<mpsc::Sender<PathBuf>>,
+        message_tx: Mutex<mpsc::Sender<(u32, Vec<u8>)>>,
         dump_path: PathBuf,
     }
 
@@ -148,16 +151,22 @@ pub fn spinup_server(id: &str, dump_path: Option<PathBuf>) -> Server {
             minidumper::LoopAction::Continue
         }
 
-        fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {
-            unreachable!("we only test crashes");
+        fn on_message(&self, kind: u32, buffer: Vec<u8>) {
+            self.message_tx
+                .lock()
+                .expect("unable to acquire lock")
+                .send((kind, buffer))
+                .expect("couldn't send message");
         }
     }
 
     let (tx, rx) = mpsc::channel();
+    let (message_tx, message_rx) = mpsc::channel();
 
     let inner = Inner {
         _id: id.to_owned(),
         dump_tx: Mutex::new(tx),
+        message_tx: Mutex::new(message_tx),
         dump_path,
     };
 
@@ -173,17 +182,17 @@ pub fn spinup_server(id: &str, dump_path: Option<PathBuf>) -> Server {
     Server {
         id: id.to_owned(),
         dump_rx: rx,
+        message_rx,
         exit_run_loop,
         run_loop: Some(run_loop),
     }
 }
 
-pub fn run_client(id: &str, signal: Signal, use_thread: bool) {
-    use std::env;
-
+/// Gets the path of the `crash-client` binary built alongside the tests
+pub fn crash_client_path() -> PathBuf {
     // Adapted from
     // https://github.com/rust-lang/cargo/blob/485670b3983b52289a2f353d589c57fae2f60f82/tests/testsuite/support/mod.rs#L507
-    let mut cmd_path = env::current_exe().expect("failed to get exe path");
+    let mut cmd_path = std::env::current_exe().expect("failed to get exe path");
     cmd_path.pop();
     if cmd_path.ends_with("deps") {
         cmd_path.pop();
@@ -194,16 +203,57 @@ pub fn run_client(id: &str, signal: Signal, use_thread: bool) {
         cmd_path.set_extension("exe");
     }
 
+    cmd_path
+}
+
+pub fn run_client(id: &str, signal: Signal, use_thread: bool) {
+    let status = spawn_client(
+        id,
+        signal,
+        &SpawnOptions {
+            use_thread,
+            ..Default::default()
+        },
+    );
+
+    // Ensure it was interrupted and did not exit properly
+    #[cfg(unix)]
+    assert!(status.code().is_none());
+    #[cfg(windows)]
+    {
+        // TODO: check that the status code matches the underlying error value
+        println!("client exited with {:?}", status.code());
+    }
+}
+
+/// Options for how the `crash-client` is run by [`spawn_crash`]
+#[derive(Default)]
+pub struct SpawnOptions {
+    /// Raises the signal on a separate thread rather than the main thread
+    pub use_thread: bool,
+    /// User messages the client sends to the server before crashing
+    pub messages: Vec<(u32, String)>,
+    /// The path the minidump is written to, defaults to `.dumps/<id>.dmp`
+    pub dump_path: Option<PathBuf>,
+}
+
+fn spawn_client(id: &str, signal: Signal, opts: &SpawnOptions) -> std::process::ExitStatus {
+    let cmd_path = crash_client_path();
+
     println!("running client: {}", cmd_path.display());
     let mut cmd = std::process::Command::new(&cmd_path);
     cmd.stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped());
-    cmd.args(&["--id", id, "--signal", &signal.to_string()]);
-    if use_thread {
+    cmd.args(["--id", id, "--signal", &signal.to_string()]);
+    if opts.use_thread {
         cmd.arg("--use-thread");
     }
 
-    let wait_for_debugger = env::var("DEBUG").is_ok();
+    for (kind, msg) in &opts.messages {
+        cmd.args(["--message", &format!("{}:{}", kind, msg)]);
+    }
+
+    let wait_for_debugger = std::env::var("DEBUG").is_ok();
     if wait_for_debugger {
         cmd.arg("--wait-on-debugger");
     }
@@ -217,13 +267,100 @@ pub fn run_client(id: &str, signal: Signal, use_thread: bool) {
     println!("{}", stdout);
     eprintln!("{}", stderr);
 
-    // Ensure it was interrupted and did not exit properly
-    #[cfg(unix)]
-    assert!(output.status.code().is_none());
-    #[cfg(windows)]
-    {
-        // TODO: check that the status code matches the underlying error value
-        println!("client exited with {:?}", output.status.code());
+    output.status
+}
+
+/// What the parent process observed after crashing a child via [`spawn_crash`]
+pub struct CrashOutcome {
+    /// The exit status of the `crash-client`
+    pub status: std::process::ExitStatus,
+    /// The minidump written by the server, if the client requested one
+    pub minidump: Option<Vec<u8>>,
+    /// The user messages the server received from the client
+    pub messages: Vec<(u32, Vec<u8>)>,
+}
+
+impl CrashOutcome {
+    /// Asserts that the child was terminated by the crash rather than exiting
+    /// on its own, and, where the platform allows us to know it, that it was
+    /// terminated by the signal that was raised
+    pub fn assert_crashed(&self, signal: Signal) {
+        assert!(
+            !self.status.success(),
+            "client exited successfully instead of crashing"
+        );
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                use std::os::unix::process::ExitStatusExt;
+
+                let expected = match signal {
+                    // Breakpoints don't trigger again once the handler
+                    // returns, as execution resumes after the breakpoint
+                    // instruction, which is followed by an abort
+                    Signal::Abort | Signal::Trap => libc::SIGABRT,
+                    Signal::Bus => libc::SIGBUS,
+                    Signal::Fpe => libc::SIGFPE,
+                    Signal::Illegal => libc::SIGILL,
+                    Signal::Segv | Signal::StackOverflow | Signal::StackOverflowCThread => {
+                        libc::SIGSEGV
+                    }
+                };
+
+                assert_eq!(self.status.signal(), Some(expected), "{}", self.status);
+            } else if #[cfg(unix)] {
+                let _ = signal;
+                assert!(self.status.code().is_none(), "{}", self.status);
+            } else {
+                let _ = signal;
+            }
+        }
+    }
+
+    /// Asserts a minidump was written and that it describes the crash
+    pub fn assert_minidump(&self, signal: Signal) {
+        assert_minidump(
+            self.minidump
+                .as_deref()
+                .expect("the server did not receive a minidump"),
+            signal,
+        );
+    }
+}
+
+/// Spawns a `crash-client` connected to a new server, crashes it in the
+/// specified way, then collects everything the parent can observe about the
+/// crash so that tests can assert on the fatal path end to end, rather than
+/// bailing out of the crash handler in the same process
+pub fn spawn_crash(id: &str, signal: Signal, opts: SpawnOptions) -> CrashOutcome {
+    capture_output();
+
+    let server = spinup_server(id, opts.dump_path.clone());
+    let status = spawn_client(id, signal, &opts);
+
+    let minidump = server
+        .dump_rx
+        .recv_timeout(std::time::Duration::from_secs(1))
+        .ok()
+        .map(|dump_path| match std::fs::read(&dump_path) {
+            Ok(buf) => buf,
+            Err(e) => {
+                panic!(
+                    "failed to read minidump from {}: {}",
+                    dump_path.display(),
+                    e
+                );
+            }
+        });
+
+    // Messages are sent before the crash on the same connection, so they have
+    // all been received by the time the dump has
+    let messages = server.message_rx.try_iter().collect();
+
+    CrashOutcome {
+        status,
+        minidump,
+        messages,
     }
 }
 
@@ -0,0 +1,179 @@
+//! Ensures every stream written by the in-process minidump writer can be read
+//! by a third-party parser, and is laid out the way Breakpad lays it out, so
+//! that the minidumps can be processed by existing Breakpad/Crashpad tooling
+#![cfg(any(target_os = "linux", target_os = "android"))]
+#![allow(unsafe_code)]
+
+use crash_handler::memory_regions;
+use minidump::{Minidump, Module};
+use minidumper_test::*;
+use std::os::unix::io::AsRawFd;
+
+/// Matches the alignment Breakpad uses for every allocation in the file
+const ALIGNMENT: u32 = 8;
+
+fn write_minidump(name: &str, cc: &crash_handler::CrashContext) -> Vec<u8> {
+    let dump_path = std::path::PathBuf::from(format!(".dumps/{name}.dmp"));
+    std::fs::create_dir_all(dump_path.parent().unwrap()).unwrap();
+    let file = std::fs::File::create(&dump_path).expect("failed to create dump file");
+
+    minidumper::in_process::write_minidump(file.as_raw_fd(), cc).expect("failed to write minidump");
+    drop(file);
+
+    std::fs::read(&dump_path).expect("failed to read minidump")
+}
+
+#[test]
+fn streams_round_trip() {
+    let state = [0xabu8; 512];
+    memory_regions::include_region(state.as_ptr(), state.len(), "state");
+
+    // Write the minidump from within the handler, so that the context records
+    // the location of the included region like it would in an actual crash
+    let written = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let handler = {
+        let written = written.clone();
+        crash_handler::CrashHandler::attach(unsafe {
+            crash_handler::make_crash_event(move |cc: &crash_handler::CrashContext| {
+                *written.lock().unwrap() = Some((write_minidump("compat", cc), cc.clone()));
+                crash_handler::CrashEventResult::Handled { exit: None }
+            })
+        })
+        .unwrap()
+    };
+    handler.simulate_signal(crash_handler::Signal::Segv);
+    drop(handler);
+    memory_regions::clear();
+
+    let (md_buf, cc) = written
+        .lock()
+        .unwrap()
+        .take()
+        .expect("minidump wasn't written");
+    let md = Minidump::read(md_buf.as_slice()).expect("failed to parse minidump");
+
+    // Every stream is within the file, aligned, and doesn't overlap with any
+    // other stream
+    let mut streams: Vec<_> = md.all_streams().collect();
+    assert_eq!(streams.len(), 7);
+    streams.sort_by_key(|stream| stream.location.rva);
+    for (i, stream) in streams.iter().enumerate() {
+        let end = stream.location.rva as usize + stream.location.data_size as usize;
+        assert!(
+            end <= md_buf.len(),
+            "stream {:#x} is truncated",
+            stream.stream_type
+        );
+        assert_eq!(
+            stream.location.rva % ALIGNMENT,
+            0,
+            "stream {:#x} is unaligned",
+            stream.stream_type
+        );
+
+        if let Some(next) = streams.get(i + 1) {
+            assert!(end <= next.location.rva as usize);
+        }
+    }
+
+    let system_info: minidump::MinidumpSystemInfo =
+        md.get_stream().expect("unable to parse system info");
+    assert_eq!(system_info.os, get_native_os());
+    assert_eq!(system_info.cpu, get_native_cpu());
+    let csd_version = system_info
+        .csd_version()
+        .expect("missing the kernel version");
+    assert!(!csd_version.is_empty());
+
+    let misc = md.get_stream::<minidump::MinidumpMiscInfo>().ok();
+
+    // The thread context must decode to the same registers we captured
+    let threads: minidump::MinidumpThreadList<'_> =
+        md.get_stream().expect("unable to parse thread list");
+    assert_eq!(threads.threads.len(), 1);
+    let thread = &threads.threads[0];
+    assert_eq!(thread.raw.thread_id, cc.tid as u32);
+    assert_eq!(thread.raw.thread_context.rva % ALIGNMENT, 0);
+    assert_eq!(thread.raw.stack.memory.rva % ALIGNMENT, 0);
+
+    let context = thread
+        .context(&system_info, misc.as_ref())
+        .expect("unable to parse thread context");
+    assert_eq!(context.get_instruction_pointer(), cc.instruction_pointer());
+
+    let sp = context.get_stack_pointer();
+    let stack = thread.stack.as_ref().expect("missing stack memory");
+    assert!(stack.base_address <= sp && sp < stack.base_address + stack.size);
+
+    let exc: minidump::MinidumpException<'_> =
+        md.get_stream().expect("unable to parse exception stream");
+    assert_eq!(exc.get_crashing_thread_id(), cc.tid as u32);
+    assert_eq!(
+        exc.raw.exception_record.exception_code,
+        libc::SIGSEGV as u32
+    );
+    let exc_context = exc
+        .context(&system_info, misc.as_ref())
+        .expect("unable to parse exception context");
+    assert_eq!(
+        exc_context.get_instruction_pointer(),
+        context.get_instruction_pointer()
+    );
+
+    // The stack, the memory around the instruction pointer, and the included
+    // region are all available
+    let memory: minidump::MinidumpMemoryList<'_> =
+        md.get_stream().expect("unable to parse memory list");
+    for block in memory.iter() {
+        assert_eq!(block.desc.memory.rva % ALIGNMENT, 0);
+        assert_eq!(block.bytes.len() as u64, block.size);
+    }
+    assert!(memory.memory_at_address(sp).is_some());
+    assert!(memory.memory_at_address(cc.instruction_pointer()).is_some());
+
+    let region = memory
+        .memory_at_address(state.as_ptr() as u64)
+        .expect("missing the included memory region");
+    assert_eq!(region.base_address, state.as_ptr() as u64);
+    assert_eq!(region.bytes, &state[..]);
+
+    // Every module is named, and has a build id
+    let modules: minidump::MinidumpModuleList =
+        md.get_stream().expect("unable to parse module list");
+    assert!(modules.iter().count() > 0);
+    for module in modules.iter() {
+        assert!(!module.name.is_empty());
+        assert_eq!(module.raw.module_name_rva % ALIGNMENT, 0);
+        if module.raw.cv_record.data_size > 0 {
+            assert_eq!(module.raw.cv_record.rva % ALIGNMENT, 0);
+            assert!(module.code_identifier().is_some());
+        }
+    }
+
+    let exe = std::env::current_exe().unwrap();
+    let main = modules
+        .iter()
+        .find(|module| std::path::Path::new(&*module.name) == exe)
+        .expect("unable to find the main executable");
+    assert!(main.debug_identifier().is_some());
+
+    let maps: minidump::MinidumpLinuxMaps<'_> =
+        md.get_stream().expect("unable to parse linux maps");
+    assert!(maps.memory_info_at_address(sp).is_some());
+
+    // Our own stream is preserved as is by the parser, and it must not prevent
+    // the standard ones from being read
+    let tags = md
+        .get_raw_stream(crash_context::MEMORY_REGION_TAGS_STREAM)
+        .expect("missing the memory region tags");
+    assert_eq!(u32::from_le_bytes(tags[..4].try_into().unwrap()), 1);
+    assert_eq!(
+        u64::from_le_bytes(tags[4..12].try_into().unwrap()),
+        state.as_ptr() as u64
+    );
+    assert_eq!(
+        u64::from_le_bytes(tags[12..20].try_into().unwrap()),
+        state.len() as u64
+    );
+    assert!(tags[20..].starts_with(b"state\0"));
+}
@@ -0,0 +1,17 @@
+use minidumper_test::*;
+
+#[test]
+fn crash_outcome() {
+    let outcome = spawn_crash(
+        "harness-segv",
+        Signal::Segv,
+        SpawnOptions {
+            messages: vec![(1, "before the crash".to_owned())],
+            ..Default::default()
+        },
+    );
+
+    outcome.assert_crashed(Signal::Segv);
+    assert_eq!(outcome.messages, vec![(1, b"before the crash".to_vec())]);
+    outcome.assert_minidump(Signal::Segv);
+}
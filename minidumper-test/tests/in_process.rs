@@ -0,0 +1,59 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use minidumper_test::*;
+use std::os::unix::io::AsRawFd;
+
+#[test]
+fn in_process() {
+    let dump_path = std::path::PathBuf::from(".dumps/in-process.dmp");
+    std::fs::create_dir_all(dump_path.parent().unwrap()).unwrap();
+    let file = std::fs::File::create(&dump_path).expect("failed to create dump file");
+
+    let mut cc = crash_handler::CrashContext::capture();
+    cc.siginfo.ssi_signo = libc::SIGABRT as u32;
+
+    minidumper::in_process::write_minidump(file.as_raw_fd(), &cc)
+        .expect("failed to write minidump");
+    drop(file);
+
+    let md_buf = std::fs::read(&dump_path).expect("failed to read minidump");
+    let md = minidump::Minidump::read(md_buf.as_slice()).expect("failed to parse minidump");
+
+    let exc: minidump::MinidumpException<'_> =
+        md.get_stream().expect("unable to find exception stream");
+    assert_eq!(exc.get_crashing_thread_id(), cc.tid as u32);
+    assert!(matches!(
+        exc.get_crash_reason(get_native_os(), get_native_cpu()),
+        minidump::CrashReason::LinuxGeneral(
+            minidump_common::errors::ExceptionCodeLinux::SIGABRT,
+            _
+        )
+    ));
+
+    let threads: minidump::MinidumpThreadList<'_> =
+        md.get_stream().expect("unable to find thread list");
+    assert_eq!(threads.threads.len(), 1);
+    let thread = &threads.threads[0];
+    assert_eq!(thread.raw.thread_id, cc.tid as u32);
+    assert!(thread.raw.stack.memory.data_size > 0);
+
+    let system_info: minidump::MinidumpSystemInfo =
+        md.get_stream().expect("unable to find system info");
+    assert_eq!(system_info.os, get_native_os());
+    assert_eq!(system_info.cpu, get_native_cpu());
+
+    let modules: minidump::MinidumpModuleList =
+        md.get_stream().expect("unable to find module list");
+    let exe = std::env::current_exe().unwrap();
+    let main = modules
+        .iter()
+        .find(|module| std::path::Path::new(&*module.name) == exe)
+        .expect("unable to find the main executable");
+
+    use minidump::Module;
+    assert!(main.code_identifier().is_some());
+    assert!(modules.iter().any(|module| module.name.contains("libc")));
+
+    let _maps: minidump::MinidumpLinuxMaps<'_> =
+        md.get_stream().expect("unable to find linux maps");
+}
@@ -0,0 +1,105 @@
+use minidumper_test::*;
+use std::sync::{atomic, mpsc, Arc, Mutex};
+
+#[test]
+fn live_dump() {
+    capture_output();
+
+    let id = "live-dump";
+    let dump_path = std::path::PathBuf::from(format!(".dumps/{}.dmp", id));
+    std::fs::create_dir_all(dump_path.parent().unwrap()).unwrap();
+
+    struct Handler {
+        dump_path: std::path::PathBuf,
+        dump_tx: Mutex<mpsc::Sender<(String, minidumper::MinidumpBinary)>>,
+    }
+
+    impl minidumper::ServerHandler for Handler {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            let file = std::fs::File::create(&self.dump_path)?;
+            Ok((file, self.dump_path.clone()))
+        }
+
+        fn on_minidump_created(
+            &self,
+            _result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            panic!("should not be called");
+        }
+
+        fn on_live_dump_created(
+            &self,
+            reason: &str,
+            result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            let md_bin = result.expect("failed to write minidump");
+            md_bin
+                .file
+                .sync_all()
+                .expect("failed to flush minidump file");
+
+            self.dump_tx
+                .lock()
+                .unwrap()
+                .send((reason.to_owned(), md_bin))
+                .expect("couldn't send minidump");
+
+            minidumper::LoopAction::Continue
+        }
+
+        fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {
+            unreachable!("we only test live dumps");
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut server = minidumper::Server::with_name(id).expect("failed to start server");
+    let shutdown = Arc::new(atomic::AtomicBool::new(false));
+    let is_shutdown = shutdown.clone();
+    let server_loop = std::thread::spawn(move || {
+        server.run(
+            Box::new(Handler {
+                dump_path,
+                dump_tx: Mutex::new(tx),
+            }),
+            &is_shutdown,
+            None,
+        )
+    });
+
+    let output = std::process::Command::new(crash_client_path())
+        .args(["--id", id, "--live-dump", "hung"])
+        .output()
+        .expect("failed to run crash-client");
+
+    println!("{}", String::from_utf8_lossy(&output.stdout));
+    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+
+    // Unlike a crash, the client keeps running after the dump
+    assert!(output.status.success());
+
+    let (reason, md_bin) = rx
+        .recv_timeout(std::time::Duration::from_secs(1))
+        .expect("failed to receive dump");
+
+    shutdown.store(true, atomic::Ordering::Relaxed);
+    server_loop.join().unwrap().unwrap();
+
+    assert_eq!(reason, "hung");
+
+    let md_buf = std::fs::read(&md_bin.path).expect("failed to read minidump");
+    let md = minidump::Minidump::read(md_buf.as_slice()).expect("failed to parse minidump");
+
+    // The requesting thread, the main thread, and the 10 sleeping threads
+    let threads: minidump::MinidumpThreadList<'_> =
+        md.get_stream().expect("unable to find thread list");
+    assert!(threads.threads.len() > 10);
+
+    let system_info: minidump::MinidumpSystemInfo =
+        md.get_stream().expect("unable to find system info");
+    assert_eq!(system_info.os, get_native_os());
+    assert_eq!(system_info.cpu, get_native_cpu());
+}
@@ -0,0 +1,62 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use minidumper_test::*;
+
+#[test]
+fn ptrace_dumper() {
+    let mut child = std::process::Command::new("sleep")
+        .arg("30")
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .expect("failed to spawn child");
+    // Give the child time to actually exec
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let pid = child.id() as i32;
+
+    let dump_path = std::path::PathBuf::from(".dumps/ptrace-dumper.dmp");
+    std::fs::create_dir_all(dump_path.parent().unwrap()).unwrap();
+    let mut file = std::fs::File::create(&dump_path).expect("failed to create dump file");
+
+    let result = minidumper::ptrace_dumper::write_minidump_for_process(pid, pid, &mut file);
+
+    child.kill().expect("failed to kill child");
+    child.wait().expect("failed to wait on child");
+
+    let md_buf = result.expect("failed to write minidump");
+    let md = minidump::Minidump::read(md_buf.as_slice()).expect("failed to parse minidump");
+
+    let threads: minidump::MinidumpThreadList<'_> =
+        md.get_stream().expect("unable to find thread list");
+    assert!(threads
+        .threads
+        .iter()
+        .any(|thread| thread.raw.thread_id == pid as u32));
+
+    let system_info: minidump::MinidumpSystemInfo =
+        md.get_stream().expect("unable to find system info");
+    assert_eq!(system_info.os, get_native_os());
+    assert_eq!(system_info.cpu, get_native_cpu());
+
+    let modules: minidump::MinidumpModuleList =
+        md.get_stream().expect("unable to find module list");
+    assert!(modules.iter().any(|module| module.name.contains("sleep")));
+
+    // The open files are a u32 total and count, followed by entries of the
+    // fd, kind, inode, and description
+    let open_files = md
+        .get_raw_stream(crash_context::OPEN_FILES_STREAM)
+        .expect("unable to find open files");
+    let read_u32 =
+        |offset: usize| u32::from_le_bytes(open_files[offset..offset + 4].try_into().unwrap());
+    assert!(read_u32(4) >= 1);
+    assert_eq!(read_u32(0), read_u32(4));
+
+    // stdin is the first fd, and a pipe
+    assert_eq!(read_u32(8), 0);
+    assert_eq!(read_u32(12), crash_context::OPEN_FILE_PIPE);
+    let len = read_u32(24) as usize;
+    assert!(std::str::from_utf8(&open_files[28..28 + len])
+        .unwrap()
+        .starts_with("pipe:["));
+}
@@ -33,9 +33,29 @@ pub enum Error {
     #[error(transparent)]
     Writer(#[from] minidump_writer::errors::WriterError),
     /// An error occurred reading or writing binary data
-    #[cfg(any(target_os = "windows", target_os = "macos"))]
     #[error(transparent)]
     Scroll(#[from] scroll::Error),
+    /// The Sentry DSN provided to [`crate::upload::Uploader::sentry`] was
+    /// invalid
+    #[cfg(feature = "upload")]
+    #[error("the Sentry DSN is invalid")]
+    InvalidDsn,
+    /// Every attempt to upload a crash failed, this is the error for the last
+    /// attempt
+    #[cfg(feature = "upload")]
+    #[error(transparent)]
+    Upload(#[from] Box<ureq::Error>),
+    /// The kind of a user message is reserved, see [`crate::MAX_MESSAGE_KIND`]
+    #[error("message kind {0} is reserved")]
+    InvalidMessageKind(u32),
     #[error("protocol error occurred: {0}")]
     ProtocolError(&'static str),
+    /// The server rejected the client when it connected, as they were built
+    /// for a different architecture or pointer width
+    #[error("the server is incompatible with this client")]
+    Incompatible,
+    /// The server doesn't support the request, as it runs an older version of
+    /// the protocol, see [`crate::PROTOCOL_VERSION`]
+    #[error("the server does not support {0}")]
+    Unsupported(&'static str),
 }
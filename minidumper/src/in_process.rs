@@ -0,0 +1,1328 @@
+//! Async signal safe, in-process minidump writing for Linux/Android.
+//!
+//! Writing minidumps via a [`crate::Server`] in another process is always
+//! preferable, as the server can suspend and inspect every thread in the
+//! crashed process. However, that isn't always possible, eg. when sandboxed,
+//! so this module can instead write a minidump directly from inside a crash
+//! handler, using only syscalls, and memory that is either on the stack or
+//! allocated statically.
+//!
+//! As the registers of threads other than the crashing one can't be retrieved
+//! without `ptrace`, the minidump only contains the crashing thread. It
+//! contains the following streams.
+//!
+//! * `ThreadListStream` - The crashing thread, with its context and stack
+//! * `MemoryListStream` - The crashing thread's stack, the memory around the
+//!   instruction pointer, and any regions included via `crash_handler::memory_regions`
+//! * `ExceptionStream` - The signal that caused the crash
+//! * `SystemInfoStream` - The CPU and OS
+//! * `ModuleListStream` - Every ELF that is mapped into the process, along with
+//!   its build id so that it can be symbolicated
+//! * `LinuxMaps` - The raw contents of `/proc/self/maps`
+//! * [`crash_context::MEMORY_REGION_TAGS_STREAM`] - The tag of each included
+//!   memory region
+//! * [`crash_context::CRASHED_THREADS_STREAM`] - Every other thread that
+//!   crashed while the crash was being handled, and was parked by the handler
+//!
+//! Any memory redacted via `crash_handler::memory_regions` is written as
+//! zeroes, regardless of which stream it would otherwise appear in.
+
+#![allow(unsafe_code)]
+
+use crate::Error;
+use minidump_writer::{
+    crash_context::CrashContext as CpuContext,
+    minidump_cpu::RawContextCPU,
+    minidump_format::{format, MDCPUArchitecture, PlatformId},
+};
+use scroll::{
+    ctx::{SizeWith, TryIntoCtx},
+    Pwrite,
+};
+use std::{
+    cell::UnsafeCell,
+    ops::Range,
+    os::unix::io::RawFd,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// The maximum amount of the crashing thread's stack that is written to the
+/// minidump, which should be plenty for unwinding
+const MAX_STACK_SIZE: usize = 64 * 1024;
+/// The amount of memory before and after the instruction pointer that is
+/// written to the minidump
+const IP_MEMORY_SIZE: usize = 256;
+/// The maximum amount of memory written for each region included via
+/// `crash_handler::memory_regions`
+const MAX_REGION_SIZE: usize = 1024 * 1024;
+/// The size of the buffer used to serialize individual minidump structures,
+/// the largest of which is the thread context, and to copy memory
+const SCRATCH_SIZE: usize = 2048;
+/// The maximum length of a line in `/proc/self/maps` that we parse, any line
+/// longer than this will have its pathname truncated
+const MAX_MAPS_LINE: usize = 1024;
+/// The maximum length of a build id we record, which is more than enough for
+/// the 20 byte SHA-1 build ids generated by default
+const MAX_BUILD_ID: usize = 64;
+
+/// The size of the red zone below the stack pointer that leaf functions are
+/// allowed to use without adjusting the stack pointer
+#[cfg(target_arch = "x86_64")]
+const RED_ZONE: usize = 128;
+#[cfg(not(target_arch = "x86_64"))]
+const RED_ZONE: usize = 0;
+
+const STREAM_COUNT: u32 = 8;
+/// The alignment of every stream, memory block and string in the minidump
+const ALIGNMENT: u32 = 8;
+
+/// Written in place of redacted memory, and as padding
+static ZEROES: [u8; SCRATCH_SIZE] = [0; SCRATCH_SIZE];
+
+/// The buffers used while writing a minidump, which together are too large
+/// to put on the stack of a signal handler, as that is usually a small
+/// alternate signal stack, eg. the 16KiB default of `crash_handler`
+struct Buffers {
+    /// Every memory region slot, included and redacted
+    slots: [crash_context::MemoryRegion; crash_context::MAX_MEMORY_REGION_SLOTS],
+    /// The included memory regions that were written
+    regions: [(
+        format::MINIDUMP_MEMORY_DESCRIPTOR,
+        crash_context::MemoryRegion,
+    ); crash_context::MAX_MEMORY_REGIONS],
+    /// The other threads that crashed
+    threads: [crash_context::CrashedThread; crash_context::MAX_CRASHED_THREADS],
+    /// The address ranges that are written as zeroes
+    redacted: [(usize, usize); crash_context::MAX_REDACTED_REGIONS],
+    /// Used to serialize individual structures, and to copy memory
+    scratch: [u8; SCRATCH_SIZE],
+    /// The line of `/proc/self/maps` being parsed
+    line: [u8; MAX_MAPS_LINE],
+    /// The path of the module being accumulated from `/proc/self/maps`
+    module_path: [u8; MAX_MAPS_LINE],
+}
+
+struct SharedBuffers(UnsafeCell<Buffers>);
+
+// SAFETY: The buffers are only accessed by the thread that claimed them by
+// setting `OWNER` to its id
+unsafe impl Sync for SharedBuffers {}
+
+static BUFFERS: SharedBuffers = SharedBuffers(UnsafeCell::new(Buffers {
+    slots: [crash_context::MemoryRegion::EMPTY; crash_context::MAX_MEMORY_REGION_SLOTS],
+    regions: [(
+        format::MINIDUMP_MEMORY_DESCRIPTOR {
+            start_of_memory_range: 0,
+            memory: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                data_size: 0,
+                rva: 0,
+            },
+        },
+        crash_context::MemoryRegion::EMPTY,
+    ); crash_context::MAX_MEMORY_REGIONS],
+    threads: [crash_context::CrashedThread::EMPTY; crash_context::MAX_CRASHED_THREADS],
+    redacted: [(0, 0); crash_context::MAX_REDACTED_REGIONS],
+    scratch: [0; SCRATCH_SIZE],
+    line: [0; MAX_MAPS_LINE],
+    module_path: [0; MAX_MAPS_LINE],
+}));
+/// The id of the thread that is writing a minidump with [`BUFFERS`], or 0 if
+/// there is none
+static OWNER: AtomicI32 = AtomicI32::new(0);
+
+/// Exclusive access to [`BUFFERS`], which is released when dropped
+struct Claim(&'static mut Buffers);
+
+impl Claim {
+    /// Waits for any other thread that is writing a minidump to finish.
+    ///
+    /// This is async signal safe.
+    fn acquire() -> Result<Self, Error> {
+        // SAFETY: syscall
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as i32;
+
+        loop {
+            match OWNER.compare_exchange(0, tid, Ordering::Acquire, Ordering::Relaxed) {
+                // SAFETY: no other thread accesses the buffers until we
+                // release them
+                Ok(_) => return Ok(Self(unsafe { &mut *BUFFERS.0.get() })),
+                // The thread crashed while writing a minidump, so it would
+                // wait for itself forever
+                Err(owner) if owner == tid => {
+                    return Err(std::io::Error::from(std::io::ErrorKind::WouldBlock).into());
+                }
+                Err(_) => {
+                    // SAFETY: syscall
+                    unsafe { libc::sched_yield() };
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        OWNER.store(0, Ordering::Release);
+    }
+}
+
+/// Writes a minidump for the crash described by the [`crash_context::CrashContext`]
+/// to the specified file descriptor, which must be a regular file opened for
+/// writing before the crash occurred, eg. a `crash_handler::artifact::CrashFile`,
+/// which is then finalized once the minidump has been written.
+///
+/// This only uses [async signal safe](https://man7.org/linux/man-pages/man7/signal-safety.7.html)
+/// operations, and thus can be called directly from a signal handler. Note
+/// that the minidump is written starting at offset 0 of the file, regardless
+/// of the current file offset.
+///
+/// Its larger buffers are allocated statically rather than on the stack, so
+/// that it fits on a small alternate signal stack. Only one minidump is thus
+/// written at a time, and a call on another thread waits for the current one
+/// to finish.
+///
+/// # Errors
+///
+/// An error will be returned if writing to the file descriptor fails, or if
+/// called again on the same thread while writing a minidump, eg. if writing
+/// the minidump crashed
+pub fn write_minidump(fd: RawFd, crash_context: &crash_context::CrashContext) -> Result<(), Error> {
+    let claim = Claim::acquire()?;
+    let Buffers {
+        slots,
+        regions,
+        threads,
+        redacted,
+        scratch,
+        line,
+        module_path,
+    } = &mut *claim.0;
+
+    let mut w = Writer {
+        fd,
+        offset: size_of::<format::MINIDUMP_HEADER>(),
+        redacted,
+        redacted_count: 0,
+        scratch,
+    };
+
+    // Read the memory regions up front, as the redacted ones apply to every
+    // bit of memory we write
+    slots.fill(crash_context::MemoryRegion::EMPTY);
+    for (i, slot) in slots.iter_mut().enumerate().take(
+        crash_context
+            .memory_region_count
+            .min(crash_context::MAX_MEMORY_REGION_SLOTS),
+    ) {
+        if let Some(region) = read_memory::<crash_context::MemoryRegion>(
+            crash_context.memory_regions + i * std::mem::size_of::<crash_context::MemoryRegion>(),
+        ) {
+            *slot = region;
+        }
+    }
+
+    for range in slots.iter().filter_map(|region| region.redacted()) {
+        w.redact(range.start as usize..range.end as usize);
+    }
+
+    let mut directory = <[format::MINIDUMP_DIRECTORY; STREAM_COUNT as usize]>::default();
+    let directory_rva = w.offset;
+    w.offset += size_of::<format::MINIDUMP_DIRECTORY>() * directory.len() as u32;
+
+    let (ip, sp) = {
+        let cpu = CpuContext {
+            inner: crash_context.clone(),
+        };
+        (cpu.get_instruction_pointer(), cpu.get_stack_pointer())
+    };
+
+    let stack = w.append_memory(stack_range(sp, line))?;
+    let ip_memory = match mapping_containing(ip, line) {
+        Some(mapping) => Some(w.append_memory(
+            ip.saturating_sub(IP_MEMORY_SIZE).max(mapping.start)
+                ..(ip + IP_MEMORY_SIZE).min(mapping.end),
+        )?),
+        None => None,
+    };
+
+    let mut region_count = 0;
+    for region in slots.iter() {
+        if region.tag().is_none() || region_count == regions.len() {
+            continue;
+        }
+
+        let start = region.address as usize;
+        let len = (region.size as usize).min(MAX_REGION_SIZE);
+        if let Some(memory) = w.append_readable_memory(start..start.saturating_add(len))? {
+            regions[region_count] = (memory, *region);
+            region_count += 1;
+        }
+    }
+    let regions = &regions[..region_count];
+
+    let thread_context = {
+        let mut raw = RawContextCPU::default();
+        fill_cpu_context(crash_context, &mut raw)?;
+        w.align()?;
+        w.append_struct(raw)?
+    };
+
+    let thread_id = crash_context.tid as u32;
+
+    // ThreadListStream
+    {
+        w.align()?;
+        let rva = w.offset;
+        w.append_struct(1u32)?;
+        w.append_struct(format::MINIDUMP_THREAD {
+            thread_id,
+            suspend_count: 0,
+            priority_class: 0,
+            priority: 0,
+            teb: 0,
+            stack,
+            thread_context,
+        })?;
+
+        directory[0] = w.directory(format::MINIDUMP_STREAM_TYPE::ThreadListStream, rva);
+    }
+
+    // MemoryListStream
+    {
+        w.align()?;
+        let rva = w.offset;
+        w.append_struct(1 + u32::from(ip_memory.is_some()) + regions.len() as u32)?;
+        w.append_struct(stack)?;
+        if let Some(ip_memory) = ip_memory {
+            w.append_struct(ip_memory)?;
+        }
+        for (memory, _region) in regions {
+            w.append_struct(*memory)?;
+        }
+
+        directory[1] = w.directory(format::MINIDUMP_STREAM_TYPE::MemoryListStream, rva);
+    }
+
+    // ExceptionStream
+    {
+        w.align()?;
+        let rva = w.offset;
+        let siginfo = &crash_context.siginfo;
+        w.append_struct(format::MINIDUMP_EXCEPTION_STREAM {
+            thread_id,
+            __align: 0,
+            exception_record: format::MINIDUMP_EXCEPTION {
+                exception_code: siginfo.ssi_signo,
+                exception_flags: siginfo.ssi_code as u32,
+                exception_address: siginfo.ssi_addr,
+                ..Default::default()
+            },
+            thread_context,
+        })?;
+
+        directory[2] = w.directory(format::MINIDUMP_STREAM_TYPE::ExceptionStream, rva);
+    }
+
+    directory[3] = write_system_info(&mut w)?;
+    directory[4] = write_module_list(&mut w, line, module_path)?;
+
+    // LinuxMaps
+    {
+        w.align()?;
+        let rva = w.offset;
+        let maps = Maps::open().ok_or_else(std::io::Error::last_os_error)?;
+        loop {
+            let read = maps.read(&mut w.scratch[..]);
+            if read == 0 {
+                break;
+            }
+            w.append_scratch(read)?;
+        }
+
+        directory[5] = w.directory(format::MINIDUMP_STREAM_TYPE::LinuxMaps, rva);
+    }
+
+    // MEMORY_REGION_TAGS_STREAM
+    {
+        w.align()?;
+        let rva = w.offset;
+        w.append(&(regions.len() as u32).to_le_bytes())?;
+        for (memory, region) in regions {
+            w.append(&memory.start_of_memory_range.to_le_bytes())?;
+            w.append(&u64::from(memory.memory.data_size).to_le_bytes())?;
+
+            let mut tag = [0u8; crash_context::MAX_MEMORY_REGION_TAG_LEN];
+            let len = (region.tag_len as usize).min(tag.len());
+            tag[..len].copy_from_slice(&region.tag[..len]);
+            w.append(&tag)?;
+        }
+
+        directory[6] = format::MINIDUMP_DIRECTORY {
+            stream_type: crash_context::MEMORY_REGION_TAGS_STREAM,
+            location: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                data_size: w.offset - rva,
+                rva,
+            },
+        };
+    }
+
+    // CRASHED_THREADS_STREAM
+    {
+        let mut thread_count = 0;
+        for i in 0..crash_context
+            .crashed_thread_count
+            .min(crash_context::MAX_CRASHED_THREADS)
+        {
+            if let Some(thread) = read_memory::<crash_context::CrashedThread>(
+                crash_context.crashed_threads
+                    + i * std::mem::size_of::<crash_context::CrashedThread>(),
+            )
+            .filter(|thread| thread.is_valid())
+            {
+                threads[thread_count] = thread;
+                thread_count += 1;
+            }
+        }
+
+        w.align()?;
+        let rva = w.offset;
+        w.append(&(thread_count as u32).to_le_bytes())?;
+        for thread in &threads[..thread_count] {
+            w.append(&thread.tid.to_le_bytes())?;
+            w.append(&thread.signo.to_le_bytes())?;
+            w.append(&thread.code.to_le_bytes())?;
+            w.append(&[0u8; 4])?;
+            w.append(&thread.address.to_le_bytes())?;
+            w.append(&thread.instruction_pointer.to_le_bytes())?;
+            w.append(&thread.stack_pointer.to_le_bytes())?;
+        }
+
+        directory[7] = format::MINIDUMP_DIRECTORY {
+            stream_type: crash_context::CRASHED_THREADS_STREAM,
+            location: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                data_size: w.offset - rva,
+                rva,
+            },
+        };
+    }
+
+    for (i, entry) in directory.iter().enumerate() {
+        w.write_struct(
+            directory_rva + i as u32 * size_of::<format::MINIDUMP_DIRECTORY>(),
+            entry.clone(),
+        )?;
+    }
+
+    w.write_struct(
+        0,
+        format::MINIDUMP_HEADER {
+            signature: format::MINIDUMP_SIGNATURE,
+            version: format::MINIDUMP_VERSION,
+            stream_count: STREAM_COUNT,
+            stream_directory_rva: directory_rva,
+            checksum: 0,
+            // SAFETY: syscall
+            time_date_stamp: unsafe { libc::time(std::ptr::null_mut()) } as u32,
+            flags: 0,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// The size of a minidump structure as it is written to the file, which can
+/// differ from its size in memory due to padding
+#[inline]
+fn size_of<T: SizeWith<scroll::Endian>>() -> u32 {
+    T::size_with(&scroll::Endian::Little) as u32
+}
+
+/// Converts the thread context into its minidump equivalent
+#[cfg(target_arch = "x86_64")]
+fn fill_cpu_context(
+    cc: &crash_context::CrashContext,
+    out: &mut RawContextCPU,
+) -> Result<(), Error> {
+    use libc::{
+        REG_CSGSFS, REG_EFL, REG_R10, REG_R11, REG_R12, REG_R13, REG_R14, REG_R15, REG_R8, REG_R9,
+        REG_RAX, REG_RBP, REG_RBX, REG_RCX, REG_RDI, REG_RDX, REG_RIP, REG_RSI, REG_RSP,
+    };
+
+    // This is the same as minidump-writer's conversion, except that it
+    // doesn't reinterpret the (unaligned) u32 float registers as u128s
+    out.context_flags = format::ContextFlagsAmd64::CONTEXT_AMD64_FULL.bits();
+
+    let gregs = &cc.context.uc_mcontext.gregs;
+    out.cs = (gregs[REG_CSGSFS as usize] & 0xffff) as u16;
+    out.fs = ((gregs[REG_CSGSFS as usize] >> 32) & 0xffff) as u16;
+    out.gs = ((gregs[REG_CSGSFS as usize] >> 16) & 0xffff) as u16;
+
+    out.eflags = gregs[REG_EFL as usize] as u32;
+
+    out.rax = gregs[REG_RAX as usize] as u64;
+    out.rcx = gregs[REG_RCX as usize] as u64;
+    out.rdx = gregs[REG_RDX as usize] as u64;
+    out.rbx = gregs[REG_RBX as usize] as u64;
+    out.rsp = gregs[REG_RSP as usize] as u64;
+    out.rbp = gregs[REG_RBP as usize] as u64;
+    out.rsi = gregs[REG_RSI as usize] as u64;
+    out.rdi = gregs[REG_RDI as usize] as u64;
+    out.r8 = gregs[REG_R8 as usize] as u64;
+    out.r9 = gregs[REG_R9 as usize] as u64;
+    out.r10 = gregs[REG_R10 as usize] as u64;
+    out.r11 = gregs[REG_R11 as usize] as u64;
+    out.r12 = gregs[REG_R12 as usize] as u64;
+    out.r13 = gregs[REG_R13 as usize] as u64;
+    out.r14 = gregs[REG_R14 as usize] as u64;
+    out.r15 = gregs[REG_R15 as usize] as u64;
+    out.rip = gregs[REG_RIP as usize] as u64;
+
+    fn copy_registers(dst: &mut [u128], src: &[u32]) {
+        for (dst, src) in dst.iter_mut().zip(src.chunks_exact(4)) {
+            *dst = src
+                .iter()
+                .rev()
+                .fold(0, |acc, reg| (acc << 32) | u128::from(*reg));
+        }
+    }
+
+    // The float state is left zeroed if it wasn't captured
+    let Some(fs) = cc.float_state() else {
+        return Ok(());
+    };
+
+    let mut float_save = format::XMM_SAVE_AREA32 {
+        control_word: fs.cwd,
+        status_word: fs.swd,
+        tag_word: fs.ftw as u8,
+        error_opcode: fs.fop,
+        error_offset: fs.rip as u32,
+        data_offset: fs.rdp as u32,
+        mx_csr: fs.mxcsr,
+        mx_csr_mask: fs.mxcr_mask,
+        ..Default::default()
+    };
+
+    copy_registers(&mut float_save.float_registers, &fs.st_space);
+    copy_registers(&mut float_save.xmm_registers, &fs.xmm_space);
+
+    out.mx_csr = fs.mxcsr;
+    out.float_save
+        .pwrite_with(float_save, 0, scroll::Endian::Little)?;
+
+    Ok(())
+}
+
+/// Converts the thread context into its minidump equivalent
+#[cfg(not(target_arch = "x86_64"))]
+#[allow(clippy::unnecessary_wraps)]
+fn fill_cpu_context(
+    cc: &crash_context::CrashContext,
+    out: &mut RawContextCPU,
+) -> Result<(), Error> {
+    CpuContext { inner: cc.clone() }.fill_cpu_context(out);
+    Ok(())
+}
+
+/// Determines the range of the stack to write, starting slightly below the
+/// stack pointer, up to the end of the stack or [`MAX_STACK_SIZE`]. Note that
+/// in the case of a stack overflow the stack pointer won't actually be inside
+/// the stack mapping, but just below it.
+fn stack_range(sp: usize, line: &mut [u8; MAX_MAPS_LINE]) -> Range<usize> {
+    let start = sp.saturating_sub(RED_ZONE);
+    let mut range = start..start;
+
+    Maps::for_each(line, |mapping| {
+        if mapping.range.end <= start || !mapping.readable {
+            return true;
+        }
+
+        if mapping.range.start <= sp + MAX_STACK_SIZE {
+            range = start.max(mapping.range.start)..mapping.range.end.min(sp + MAX_STACK_SIZE);
+        }
+
+        false
+    });
+
+    range
+}
+
+/// Retrieves the range of the readable mapping that contains the address
+fn mapping_containing(addr: usize, line: &mut [u8; MAX_MAPS_LINE]) -> Option<Range<usize>> {
+    let mut range = None;
+    Maps::for_each(line, |mapping| {
+        if mapping.range.end <= addr {
+            return true;
+        }
+
+        if mapping.range.start <= addr && mapping.readable {
+            range = Some(mapping.range.clone());
+        }
+
+        false
+    });
+
+    range
+}
+
+fn write_system_info(w: &mut Writer<'_>) -> Result<format::MINIDUMP_DIRECTORY, Error> {
+    // SAFETY: syscall
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    // SAFETY: syscall
+    unsafe { libc::uname(&mut uts) };
+
+    let field = |f: &[libc::c_char]| -> &[u8] {
+        // SAFETY: c_char and u8 have the same layout
+        let f = unsafe { &*(f as *const [libc::c_char] as *const [u8]) };
+        &f[..f.iter().position(|c| *c == 0).unwrap_or(f.len())]
+    };
+
+    // Breakpad records the full kernel description in the CSD version, which
+    // is normally used for service pack information on Windows
+    w.align()?;
+    let csd_version_rva = w.offset;
+    {
+        let mut csd = [0u8; 3 * 65 + 2];
+        let mut len = 0;
+        for (i, part) in [
+            field(&uts.release),
+            field(&uts.version),
+            field(&uts.machine),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if i > 0 {
+                csd[len] = b' ';
+                len += 1;
+            }
+            csd[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        w.append_string(&csd[..len])?;
+    }
+
+    // The kernel release is eg. `5.15.0-48-generic`
+    let mut version = [0u32; 3];
+    for (v, part) in version.iter_mut().zip(
+        field(&uts.release)
+            .split(|c| !c.is_ascii_digit())
+            .filter(|p| !p.is_empty()),
+    ) {
+        *v = part.iter().fold(0u32, |acc, d| {
+            acc.wrapping_mul(10).wrapping_add(u32::from(d - b'0'))
+        });
+    }
+
+    // SAFETY: syscall
+    let number_of_processors = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) == 0 {
+            libc::CPU_COUNT(&set).min(u8::MAX as i32) as u8
+        } else {
+            0
+        }
+    };
+
+    let (processor_architecture, processor_level, processor_revision, cpu) = cpu_info();
+
+    w.align()?;
+    let rva = w.offset;
+    w.append_struct(format::MINIDUMP_SYSTEM_INFO {
+        processor_architecture: processor_architecture as u16,
+        processor_level,
+        processor_revision,
+        number_of_processors,
+        product_type: 0,
+        major_version: version[0],
+        minor_version: version[1],
+        build_number: version[2],
+        platform_id: if cfg!(target_os = "android") {
+            PlatformId::Android
+        } else {
+            PlatformId::Linux
+        } as u32,
+        csd_version_rva,
+        suite_mask: 0,
+        reserved2: 0,
+        cpu: format::CPU_INFORMATION { data: cpu },
+    })?;
+
+    Ok(w.directory(format::MINIDUMP_STREAM_TYPE::SystemInfoStream, rva))
+}
+
+/// Retrieves the architecture, level, revision, and architecture specific
+/// information for the CPU
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn cpu_info() -> (MDCPUArchitecture, u16, u16, [u8; 24]) {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::__cpuid;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::__cpuid;
+
+    let (vendor, version) = (__cpuid(0), __cpuid(1));
+
+    let family = (version.eax >> 8) & 0xf;
+    let model = (version.eax >> 4) & 0xf;
+    let stepping = version.eax & 0xf;
+
+    let mut data = [0u8; 24];
+    for (i, reg) in [vendor.ebx, vendor.edx, vendor.ecx, version.eax, version.edx]
+        .into_iter()
+        .enumerate()
+    {
+        data[i * 4..i * 4 + 4].copy_from_slice(&reg.to_le_bytes());
+    }
+
+    (
+        if cfg!(target_arch = "x86_64") {
+            MDCPUArchitecture::PROCESSOR_ARCHITECTURE_AMD64
+        } else {
+            MDCPUArchitecture::PROCESSOR_ARCHITECTURE_INTEL
+        },
+        family as u16,
+        ((model << 8) | stepping) as u16,
+        data,
+    )
+}
+
+/// Retrieves the architecture, level, revision, and architecture specific
+/// information for the CPU
+#[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+fn cpu_info() -> (MDCPUArchitecture, u16, u16, [u8; 24]) {
+    // SAFETY: reads the auxiliary vector, which is already in memory
+    let hwcaps = unsafe { libc::getauxval(libc::AT_HWCAP) } as u32;
+
+    // The MIDR isn't accessible from userspace, so we only record the hwcaps
+    let mut data = [0u8; 24];
+    data[4..8].copy_from_slice(&hwcaps.to_le_bytes());
+
+    (
+        if cfg!(target_arch = "aarch64") {
+            MDCPUArchitecture::PROCESSOR_ARCHITECTURE_ARM64_OLD
+        } else {
+            MDCPUArchitecture::PROCESSOR_ARCHITECTURE_ARM
+        },
+        0,
+        0,
+        data,
+    )
+}
+
+fn write_module_list(
+    w: &mut Writer<'_>,
+    line: &mut [u8; MAX_MAPS_LINE],
+    module_path: &mut [u8; MAX_MAPS_LINE],
+) -> Result<format::MINIDUMP_DIRECTORY, Error> {
+    // We can't allocate, so we first count the modules so that we can reserve
+    // space for the list, then fill in each module as we write its name and
+    // build id after the list
+    let mut count = 0u32;
+    for_each_module(line, module_path, |_| {
+        count += 1;
+        Ok(true)
+    })?;
+
+    w.align()?;
+    let rva = w.offset;
+    let module_size = size_of::<format::MINIDUMP_MODULE>();
+    w.offset += 4 + count * module_size;
+
+    let mut written = 0;
+    for_each_module(line, module_path, |module| {
+        // The mappings may have changed since we counted them
+        if written == count {
+            return Ok(false);
+        }
+
+        let module_name_rva = w.append_string(module.path)?;
+
+        let mut build_id = [0u8; MAX_BUILD_ID];
+        let cv_record = match read_build_id(module.range.start, &mut build_id) {
+            Some(len) => {
+                w.align()?;
+                let start = w.offset;
+                w.append(&(format::CvSignature::Elf as u32).to_le_bytes())?;
+                w.append(&build_id[..len])?;
+                format::MINIDUMP_LOCATION_DESCRIPTOR {
+                    data_size: w.offset - start,
+                    rva: start,
+                }
+            }
+            None => format::MINIDUMP_LOCATION_DESCRIPTOR::default(),
+        };
+
+        w.write_struct(
+            rva + 4 + written * module_size,
+            format::MINIDUMP_MODULE {
+                base_of_image: module.range.start as u64,
+                size_of_image: (module.range.end - module.range.start) as u32,
+                module_name_rva,
+                cv_record,
+                ..Default::default()
+            },
+        )?;
+
+        written += 1;
+        Ok(true)
+    })?;
+
+    w.write_struct(rva, written)?;
+
+    Ok(format::MINIDUMP_DIRECTORY {
+        stream_type: format::MINIDUMP_STREAM_TYPE::ModuleListStream as u32,
+        location: format::MINIDUMP_LOCATION_DESCRIPTOR {
+            data_size: 4 + written * module_size,
+            rva,
+        },
+    })
+}
+
+struct Module<'maps> {
+    range: Range<usize>,
+    path: &'maps [u8],
+}
+
+/// Invokes the callback for each ELF mapped into the process, which span all
+/// of the consecutive mappings for the same file, starting from the mapping
+/// of the beginning of the file that contains the ELF header
+fn for_each_module(
+    line: &mut [u8; MAX_MAPS_LINE],
+    path: &mut [u8; MAX_MAPS_LINE],
+    mut cb: impl FnMut(&Module<'_>) -> Result<bool, Error>,
+) -> Result<(), Error> {
+    // The range of the current module, and the length of its path
+    let mut current: Option<(Range<usize>, usize)> = None;
+    let mut result = Ok(());
+
+    let mut emit = |current: &mut Option<(Range<usize>, usize)>, path: &[u8]| {
+        let Some((range, len)) = current.take() else {
+            return true;
+        };
+
+        match cb(&Module {
+            range,
+            path: &path[..len],
+        }) {
+            Ok(keep_going) => keep_going,
+            Err(err) => {
+                result = Err(err);
+                false
+            }
+        }
+    };
+
+    let mut keep_going = true;
+    Maps::for_each(line, |mapping| {
+        if let Some((range, len)) = &mut current {
+            if &path[..*len] == mapping.path {
+                range.end = mapping.range.end;
+                return true;
+            }
+
+            keep_going = emit(&mut current, &path[..]);
+            if !keep_going {
+                return false;
+            }
+        }
+
+        if mapping.offset == 0
+            && mapping.readable
+            && mapping.path.first() == Some(&b'/')
+            && read_memory::<[u8; 4]>(mapping.range.start) == Some(*b"\x7fELF")
+        {
+            path[..mapping.path.len()].copy_from_slice(mapping.path);
+            current = Some((mapping.range.clone(), mapping.path.len()));
+        }
+
+        true
+    });
+
+    if keep_going {
+        emit(&mut current, &path[..]);
+    }
+
+    result
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(target_pointer_width = "64")] {
+        type Phdr = libc::Elf64_Phdr;
+        /// Offsets of `e_phoff`, `e_phentsize`, and `e_phnum` in the ELF header
+        const EHDR_PH_OFFSETS: (usize, usize, usize) = (0x20, 0x36, 0x38);
+    } else {
+        type Phdr = libc::Elf32_Phdr;
+        /// Offsets of `e_phoff`, `e_phentsize`, and `e_phnum` in the ELF header
+        const EHDR_PH_OFFSETS: (usize, usize, usize) = (0x1c, 0x2a, 0x2c);
+    }
+}
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Reads the GNU build id from the notes of the ELF loaded at the specified
+/// address, returning its length
+fn read_build_id(base: usize, build_id: &mut [u8; MAX_BUILD_ID]) -> Option<usize> {
+    let (phoff, phentsize, phnum) = EHDR_PH_OFFSETS;
+    let phoff = read_memory::<usize>(base + phoff)?;
+    let phentsize = read_memory::<u16>(base + phentsize)? as usize;
+    let phnum = read_memory::<u16>(base + phnum)? as usize;
+
+    if phentsize != std::mem::size_of::<Phdr>() {
+        return None;
+    }
+
+    // The program headers are mapped as part of the first PT_LOAD segment,
+    // whose virtual address is also what the load bias is relative to
+    let phdr = |i: usize| read_memory::<Phdr>(base + phoff + i * phentsize);
+
+    let load_bias = (0..phnum)
+        .filter_map(phdr)
+        .find(|ph| ph.p_type == libc::PT_LOAD)
+        .map(|ph| {
+            base.wrapping_sub(ph.p_vaddr as usize & !(ph.p_align as usize).saturating_sub(1))
+        })?;
+
+    for ph in (0..phnum).filter_map(phdr) {
+        if ph.p_type != libc::PT_NOTE {
+            continue;
+        }
+
+        let mut note = load_bias.wrapping_add(ph.p_vaddr as usize);
+        let end = note + ph.p_memsz as usize;
+
+        while note + 12 <= end {
+            let [namesz, descsz, kind] = read_memory::<[u32; 3]>(note)?;
+            let name = note + 12;
+            let desc = name + ((namesz as usize + 3) & !3);
+
+            if kind == NT_GNU_BUILD_ID
+                && namesz == 4
+                && read_memory::<[u8; 4]>(name) == Some(*b"GNU\0")
+            {
+                let len = (descsz as usize).min(MAX_BUILD_ID);
+                return read_memory_into(desc, &mut build_id[..len]).then_some(len);
+            }
+
+            note = desc + ((descsz as usize + 3) & !3);
+        }
+    }
+
+    None
+}
+
+/// Reads memory from our own process without risking a fault, in case the
+/// address is not actually mapped
+fn read_memory_into(addr: usize, buf: &mut [u8]) -> bool {
+    let local = libc::iovec {
+        iov_base: buf.as_mut_ptr().cast(),
+        iov_len: buf.len(),
+    };
+    let remote = libc::iovec {
+        iov_base: addr as *mut _,
+        iov_len: buf.len(),
+    };
+
+    // SAFETY: syscall
+    let read = unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+    read == buf.len() as isize
+}
+
+fn read_memory<T: Copy>(addr: usize) -> Option<T> {
+    let mut val = std::mem::MaybeUninit::<T>::uninit();
+    // SAFETY: the slice covers exactly the memory of `val`, and is only
+    // assumed initialized if the whole thing was read
+    unsafe {
+        let buf = std::slice::from_raw_parts_mut(val.as_mut_ptr().cast(), std::mem::size_of::<T>());
+        read_memory_into(addr, buf).then(|| val.assume_init())
+    }
+}
+
+struct Writer<'buf> {
+    fd: RawFd,
+    offset: u32,
+    /// The address ranges that are written as zeroes
+    redacted: &'buf mut [(usize, usize); crash_context::MAX_REDACTED_REGIONS],
+    redacted_count: usize,
+    scratch: &'buf mut [u8; SCRATCH_SIZE],
+}
+
+impl Writer<'_> {
+    fn write(&self, offset: u32, bytes: &[u8]) -> Result<(), Error> {
+        let mut written = 0;
+        while written < bytes.len() {
+            // SAFETY: syscall
+            let rv = unsafe {
+                libc::pwrite(
+                    self.fd,
+                    bytes[written..].as_ptr().cast(),
+                    bytes.len() - written,
+                    (offset as usize + written) as libc::off_t,
+                )
+            };
+
+            match rv {
+                0 => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+                rv if rv < 0 => {
+                    let err = std::io::Error::last_os_error();
+                    if err.kind() != std::io::ErrorKind::Interrupted {
+                        return Err(err.into());
+                    }
+                }
+                rv => written += rv as usize,
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn append(&mut self, bytes: &[u8]) -> Result<format::MINIDUMP_LOCATION_DESCRIPTOR, Error> {
+        self.write(self.offset, bytes)?;
+        Ok(self.advance(bytes.len()))
+    }
+
+    /// Appends the first `len` bytes of the scratch buffer
+    #[inline]
+    fn append_scratch(
+        &mut self,
+        len: usize,
+    ) -> Result<format::MINIDUMP_LOCATION_DESCRIPTOR, Error> {
+        self.write(self.offset, &self.scratch[..len])?;
+        Ok(self.advance(len))
+    }
+
+    #[inline]
+    fn advance(&mut self, len: usize) -> format::MINIDUMP_LOCATION_DESCRIPTOR {
+        let location = format::MINIDUMP_LOCATION_DESCRIPTOR {
+            data_size: len as u32,
+            rva: self.offset,
+        };
+        self.offset += len as u32;
+        location
+    }
+
+    fn write_struct<T>(&mut self, offset: u32, val: T) -> Result<(), Error>
+    where
+        T: TryIntoCtx<scroll::Endian, Error = scroll::Error>,
+    {
+        let size = self.scratch.pwrite_with(val, 0, scroll::Endian::Little)?;
+        self.write(offset, &self.scratch[..size])
+    }
+
+    fn append_struct<T>(&mut self, val: T) -> Result<format::MINIDUMP_LOCATION_DESCRIPTOR, Error>
+    where
+        T: TryIntoCtx<scroll::Endian, Error = scroll::Error>,
+    {
+        let size = self.scratch.pwrite_with(val, 0, scroll::Endian::Little)?;
+        self.append_scratch(size)
+    }
+
+    /// Writes the memory in our own process directly to the file, which fails
+    /// gracefully rather than faulting if the memory is not actually readable
+    fn append_memory(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<format::MINIDUMP_MEMORY_DESCRIPTOR, Error> {
+        self.align()?;
+        let rva = self.offset;
+        let mut address = range.start;
+
+        while address < range.end {
+            // Write everything up to the next redaction as is, and the
+            // redaction itself as zeroes
+            let redacted = self.next_redaction(address..range.end);
+
+            // SAFETY: the kernel validates the memory range for us
+            let bytes = unsafe {
+                std::slice::from_raw_parts(address as *const u8, redacted.start - address)
+            };
+            self.append(bytes)?;
+            self.append_zeroes(redacted.len())?;
+            address = redacted.end;
+        }
+
+        Ok(format::MINIDUMP_MEMORY_DESCRIPTOR {
+            start_of_memory_range: range.start as u64,
+            memory: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                data_size: self.offset - rva,
+                rva,
+            },
+        })
+    }
+
+    /// Writes as much of the memory in our own process as is readable, stopping
+    /// at the first chunk that isn't, returning `None` if none of it was
+    fn append_readable_memory(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<Option<format::MINIDUMP_MEMORY_DESCRIPTOR>, Error> {
+        self.align()?;
+        let rva = self.offset;
+        let mut address = range.start;
+
+        while address < range.end {
+            let len = (range.end - address).min(SCRATCH_SIZE);
+            if !read_memory_into(address, &mut self.scratch[..len]) {
+                break;
+            }
+
+            zero_redacted(
+                &self.redacted[..self.redacted_count],
+                address,
+                &mut self.scratch[..len],
+            );
+            self.append_scratch(len)?;
+            address += len;
+        }
+
+        Ok(
+            (address > range.start).then(|| format::MINIDUMP_MEMORY_DESCRIPTOR {
+                start_of_memory_range: range.start as u64,
+                memory: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                    data_size: self.offset - rva,
+                    rva,
+                },
+            }),
+        )
+    }
+
+    /// Pads the file so that the next structure starts at an 8 byte aligned
+    /// offset, as Breakpad does, since some readers expect every stream and
+    /// memory block to be aligned
+    #[inline]
+    fn align(&mut self) -> Result<(), Error> {
+        let padding = self.offset.wrapping_neg() % ALIGNMENT;
+        self.append_zeroes(padding as usize)
+    }
+
+    #[inline]
+    fn append_zeroes(&mut self, mut len: usize) -> Result<(), Error> {
+        while len > 0 {
+            let chunk = len.min(SCRATCH_SIZE);
+            self.append(&ZEROES[..chunk])?;
+            len -= chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Adds a range that is written as zeroes, ignoring any that don't fit
+    #[inline]
+    fn redact(&mut self, range: Range<usize>) {
+        if self.redacted_count < self.redacted.len() {
+            self.redacted[self.redacted_count] = (range.start, range.end);
+            self.redacted_count += 1;
+        }
+    }
+
+    /// Retrieves the lowest part of the range that is redacted, or an empty
+    /// range at its end if none of it is
+    fn next_redaction(&self, range: Range<usize>) -> Range<usize> {
+        self.redacted[..self.redacted_count]
+            .iter()
+            .map(|&(start, end)| start.max(range.start)..end.min(range.end))
+            .filter(|redacted| !redacted.is_empty())
+            .min_by_key(|redacted| redacted.start)
+            .unwrap_or(range.end..range.end)
+    }
+
+    /// Writes a `MINIDUMP_STRING`, which is the length in bytes followed by
+    /// the UTF-16 encoded string, returning its RVA
+    fn append_string(&mut self, s: &[u8]) -> Result<u32, Error> {
+        let utf16 = || {
+            s.utf8_chunks().flat_map(|chunk| {
+                chunk.valid().encode_utf16().chain(
+                    (!chunk.invalid().is_empty()).then_some(char::REPLACEMENT_CHARACTER as u16),
+                )
+            })
+        };
+
+        self.align()?;
+        let rva = self.offset;
+        self.append(&((utf16().count() * 2) as u32).to_le_bytes())?;
+
+        let mut len = 0;
+        // Include the null terminator
+        for c in utf16().chain(std::iter::once(0)) {
+            if len == SCRATCH_SIZE {
+                self.append_scratch(len)?;
+                len = 0;
+            }
+
+            self.scratch[len..len + 2].copy_from_slice(&c.to_le_bytes());
+            len += 2;
+        }
+        self.append_scratch(len)?;
+
+        Ok(rva)
+    }
+
+    #[inline]
+    fn directory(
+        &self,
+        stream_type: format::MINIDUMP_STREAM_TYPE,
+        rva: u32,
+    ) -> format::MINIDUMP_DIRECTORY {
+        format::MINIDUMP_DIRECTORY {
+            stream_type: stream_type as u32,
+            location: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                data_size: self.offset - rva,
+                rva,
+            },
+        }
+    }
+}
+
+/// Zeroes every redacted byte of the memory that was read from `address`
+fn zero_redacted(redacted: &[(usize, usize)], address: usize, chunk: &mut [u8]) {
+    for &(start, end) in redacted {
+        let start = start.max(address);
+        let end = end.min(address + chunk.len());
+        if start < end {
+            chunk[start - address..end - address].fill(0);
+        }
+    }
+}
+
+struct Mapping<'line> {
+    range: Range<usize>,
+    readable: bool,
+    offset: usize,
+    path: &'line [u8],
+}
+
+impl<'line> Mapping<'line> {
+    /// Parses a line in `/proc/<pid>/maps`, eg.
+    /// `7f6b1c000000-7f6b1c021000 r-xp 00000000 08:01 1234     /usr/lib/libc.so.6`
+    fn parse(line: &'line [u8]) -> Option<Self> {
+        let mut fields = line.split(|b| *b == b' ').filter(|f| !f.is_empty());
+
+        let mut range = fields.next()?.split(|b| *b == b'-');
+        let start = parse_hex(range.next()?)?;
+        let end = parse_hex(range.next()?)?;
+
+        let perms = fields.next()?;
+        let offset = parse_hex(fields.next()?)?;
+        // device and inode
+        fields.next()?;
+        fields.next()?;
+
+        Some(Self {
+            range: start..end,
+            readable: perms.first() == Some(&b'r'),
+            offset,
+            path: fields.next().unwrap_or_default(),
+        })
+    }
+}
+
+#[inline]
+fn parse_hex(s: &[u8]) -> Option<usize> {
+    if s.is_empty() {
+        return None;
+    }
+
+    s.iter().try_fold(0usize, |acc, c| {
+        let digit = match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            b'A'..=b'F' => c - b'A' + 10,
+            _ => return None,
+        };
+
+        acc.checked_mul(16)?.checked_add(digit as usize)
+    })
+}
+
+/// `/proc/self/maps` read with raw syscalls
+struct Maps {
+    fd: RawFd,
+}
+
+impl Maps {
+    fn open() -> Option<Self> {
+        // SAFETY: syscall
+        let fd = unsafe {
+            libc::open(
+                c"/proc/self/maps".as_ptr(),
+                libc::O_RDONLY | libc::O_CLOEXEC,
+            )
+        };
+        (fd != -1).then_some(Self { fd })
+    }
+
+    #[inline]
+    fn read(&self, buf: &mut [u8]) -> usize {
+        loop {
+            // SAFETY: syscall
+            let read = unsafe { libc::read(self.fd, buf.as_mut_ptr().cast(), buf.len()) };
+            if read >= 0 {
+                return read as usize;
+            } else if std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                return 0;
+            }
+        }
+    }
+
+    /// Invokes the callback for each mapping, in ascending address order,
+    /// until the callback returns `false`. Paths longer than fit in the line
+    /// buffer are truncated.
+    fn for_each(buf: &mut [u8; MAX_MAPS_LINE], mut cb: impl FnMut(&Mapping<'_>) -> bool) {
+        let Some(maps) = Self::open() else {
+            return;
+        };
+
+        let mut filled = 0;
+        let mut skip_line = false;
+
+        'read: loop {
+            let read = maps.read(&mut buf[filled..]);
+            if read == 0 {
+                break;
+            }
+
+            filled += read;
+
+            let mut start = 0;
+            while let Some(nl) = buf[start..filled].iter().position(|b| *b == b'\n') {
+                let line = &buf[start..start + nl];
+                start += nl + 1;
+
+                if skip_line {
+                    skip_line = false;
+                    continue;
+                }
+
+                if let Some(mapping) = Mapping::parse(line) {
+                    if !cb(&mapping) {
+                        break 'read;
+                    }
+                }
+            }
+
+            if start == 0 && filled == buf.len() {
+                // The line didn't fit, parse what we have and skip the rest
+                if !skip_line {
+                    if let Some(mapping) = Mapping::parse(&buf[..]) {
+                        if !cb(&mapping) {
+                            break;
+                        }
+                    }
+                }
+
+                skip_line = true;
+                filled = 0;
+            } else {
+                buf.copy_within(start..filled, 0);
+                filled -= start;
+            }
+        }
+    }
+}
+
+impl Drop for Maps {
+    fn drop(&mut self) {
+        // SAFETY: syscall
+        unsafe { libc::close(self.fd) };
+    }
+}
@@ -32,6 +32,10 @@ cfg_if::cfg_if! {
             thread_id: u32,
             /// The top level exception code, also found in the `EXCEPTION_POINTERS.ExceptionRecord.ExceptionCode`
             exception_code: i32,
+            /// The fast fail code, see [`crash_context::CrashReason::fast_fail_code`]
+            fast_fail_code: u32,
+            /// Boolean to indicate if there is a fast fail code
+            has_fast_fail_code: u8,
         }
     } else if #[cfg(target_os = "macos")] {
         mod mac;
@@ -64,18 +68,144 @@ cfg_if::cfg_if! {
     }
 }
 
+/// Sent by a [`Client`] to request a minidump of its process while it
+/// continues running, followed by the utf-8 reason for the request
+#[derive(scroll::Pwrite, scroll::Pread, scroll::SizeWith)]
+struct LiveDumpRequest {
+    /// The process id of the client process
+    process_id: u32,
+    /// The id of the thread in the client process that requested the dump
+    thread_id: u32,
+}
+
 mod client;
 mod server;
+#[cfg(not(target_os = "macos"))]
+pub mod spool;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod supervisor;
 
-pub use client::Client;
-pub use server::Server;
+pub use client::{Client, Reconnect};
+pub use server::{Server, ShutdownSummary};
 
 const CRASH: u32 = 0;
 #[cfg_attr(target_os = "macos", allow(dead_code))]
 const CRASH_ACK: u32 = 1;
 const PING: u32 = 2;
 const PONG: u32 = 3;
-const USER: u32 = 4;
+const LIVE_DUMP: u32 = 4;
+const LIVE_DUMP_ACK: u32 = 5;
+/// Sent by a [`Client`] when it is dropped, so that the server can tell a
+/// clean disconnect apart from the client process dying
+const GOODBYE: u32 = 6;
+const USER: u32 = 7;
+/// The largest kind that can be used for a user message sent via
+/// [`Client::send_message`], as user kinds are sent offset by [`USER`], and
+/// the kinds above that are reserved
+pub const MAX_MESSAGE_KIND: u32 = HELLO - USER - 1;
+/// Sent by the [`Server`] in reply to a [`PING`] carrying the [`Hello`] of a
+/// [`Client`], instead of a [`PONG`]. This is never a user message kind, see
+/// [`MAX_MESSAGE_KIND`]
+const HELLO: u32 = u32::MAX;
+
+/// The version of the protocol spoken by the [`Client`] and [`Server`] of this
+/// crate, which is incremented whenever a message is added or changed.
+///
+/// When a client connects, it exchanges a [`Hello`] with the server, and both
+/// sides use the lower of their versions, so that eg. a newer client can still
+/// talk to an older server that has been running since before an upgrade.
+/// Servers that predate the handshake are version 0.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// The message kinds that are supported, as a bit per kind, [`USER`] covering
+/// every user message
+const CAPABILITIES: u64 =
+    (1 << CRASH) | (1 << PING) | (1 << LIVE_DUMP) | (1 << GOODBYE) | (1 << USER);
+
+/// The architecture this crate was built for, as crash contexts are sent as
+/// their raw representation, which differs between architectures
+const ARCH: u32 = if cfg!(target_arch = "x86_64") {
+    1
+} else if cfg!(target_arch = "x86") {
+    2
+} else if cfg!(target_arch = "aarch64") {
+    3
+} else if cfg!(target_arch = "arm") {
+    4
+} else if cfg!(target_arch = "riscv64") {
+    5
+} else {
+    0
+};
+
+/// Sent by a [`Client`] as the payload of a [`PING`] when it connects, and by
+/// the [`Server`] in reply, see [`PROTOCOL_VERSION`]
+#[derive(scroll::Pwrite, scroll::Pread, scroll::SizeWith)]
+struct Hello {
+    /// The [`PROTOCOL_VERSION`] of the sender
+    version: u32,
+    /// The architecture of the sender, see [`ARCH`]
+    arch: u32,
+    /// The size of a pointer of the sender, in bits
+    pointer_width: u32,
+    /// In the reply of the server, whether the client can talk to it, which
+    /// is only the case if both have the same architecture and pointer width
+    accepted: u32,
+    /// The message kinds the sender supports, see [`CAPABILITIES`]
+    capabilities: u64,
+}
+
+/// The size of a serialized [`Hello`]
+const HELLO_LEN: usize = 24;
+
+impl Hello {
+    fn current() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            arch: ARCH,
+            pointer_width: usize::BITS,
+            accepted: 1,
+            capabilities: CAPABILITIES,
+        }
+    }
+
+    /// The [`Hello`] of a server that predates the handshake, which supports
+    /// every message kind that existed at that point
+    fn legacy() -> Self {
+        Self {
+            version: 0,
+            capabilities: CAPABILITIES,
+            ..Self::current()
+        }
+    }
+
+    #[inline]
+    fn is_compatible(&self, other: &Self) -> bool {
+        self.arch == other.arch && self.pointer_width == other.pointer_width
+    }
+
+    /// Whether the sender supports the specified message kind
+    #[inline]
+    fn supports(&self, kind: u32) -> bool {
+        self.capabilities & (1 << kind.min(USER)) != 0
+    }
+}
+
+/// Sent by a [`Client`] after its [`Hello`], followed by its utf-8 name and
+/// then the path of its executable, so that the [`Server`] can tell its
+/// clients apart, see [`crate::ClientInfo`]. Servers before version 2 ignore
+/// it.
+#[derive(scroll::Pwrite, scroll::Pread, scroll::SizeWith)]
+struct Identity {
+    /// The process id of the client process
+    process_id: u32,
+    /// The length of the name that follows, which is empty if the client
+    /// hasn't named itself
+    name_len: u32,
+}
+
+/// The size of a serialized [`Identity`]
+const IDENTITY_LEN: usize = 8;
 
 /// A socket name.
 ///
@@ -153,6 +283,26 @@ impl Header {
 mod test {
     use super::Header;
 
+    #[test]
+    fn hello_len() {
+        use scroll::ctx::SizeWith;
+
+        assert_eq!(
+            super::Hello::size_with(&scroll::Endian::default()),
+            super::HELLO_LEN
+        );
+    }
+
+    #[test]
+    fn identity_len() {
+        use scroll::ctx::SizeWith;
+
+        assert_eq!(
+            super::Identity::size_with(&scroll::Endian::default()),
+            super::IDENTITY_LEN
+        );
+    }
+
     #[test]
     fn header_bytes() {
         let expected = Header {
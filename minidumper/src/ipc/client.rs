@@ -1,81 +1,220 @@
 use super::{Header, SocketName, Stream};
 use crate::Error;
-use std::io::IoSlice;
+use std::{io::IoSlice, time::Duration};
 
 /// Client side of the connection, which runs in the process that may (or has)
 /// crashed to communicate with an external monitor process.
 pub struct Client {
+    conn: parking_lot::RwLock<Connection>,
+    /// The name of the socket, so that we can reconnect if the server goes
+    /// away, eg. because it was restarted
+    socket_name: Option<OwnedSocketName>,
+    /// How we reconnect, if at all
+    reconnect: Option<Reconnect>,
+    /// The name the client identifies itself with, see [`Self::set_name`]
+    name: String,
+    /// Where crashes are written if they can't be sent to the server
+    #[cfg(not(target_os = "macos"))]
+    spool: Option<super::spool::Spool>,
+}
+
+/// A connection to the server, which is replaced if the client reconnects
+struct Connection {
     socket: Stream,
+    /// What the server told us about itself when we connected
+    server: super::Hello,
     /// On Macos we need this additional mach port based client to send crash
     /// contexts, as, unfortunately, it's the best (though hopefully not only?)
     /// way to get the real info needed by the minidump writer to write the
     /// minidump
     #[cfg(target_os = "macos")]
     port: crash_context::ipc::Client,
+    /// Incremented every time the client reconnects, so that threads that
+    /// lost the same connection only reconnect once
+    generation: u64,
+}
+
+/// A [`SocketName`] that is kept by the client, so that it can reconnect
+enum OwnedSocketName {
+    Path(std::path::PathBuf),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Abstract(String),
+}
+
+impl OwnedSocketName {
+    fn as_name(&self) -> SocketName<'_> {
+        match self {
+            Self::Path(path) => SocketName::Path(path),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Self::Abstract(name) => SocketName::Abstract(name),
+        }
+    }
+}
+
+impl<'scope> From<SocketName<'scope>> for OwnedSocketName {
+    fn from(sn: SocketName<'scope>) -> Self {
+        match sn {
+            SocketName::Path(path) => Self::Path(path.to_owned()),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            SocketName::Abstract(name) => Self::Abstract(name.to_owned()),
+        }
+    }
+}
+
+/// How a [`Client`] reconnects to a server that has gone away, eg. because
+/// the monitor process was restarted, see [`Client::set_reconnect`]
+#[derive(Copy, Clone, Debug)]
+pub struct Reconnect {
+    /// The number of times to try to connect before giving up
+    pub attempts: u32,
+    /// How long to wait after the first failed attempt, which is doubled
+    /// after each subsequent failed attempt
+    pub initial_backoff: Duration,
+    /// The longest to wait between attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for Reconnect {
+    /// 5 attempts over about 1.5 seconds
+    fn default() -> Self {
+        Self {
+            attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
 }
 
 impl Client {
     /// Creates a new client with the given name.
     ///
+    /// If the server goes away, eg. because it was restarted, the client
+    /// reconnects to the same name, see [`Self::set_reconnect`].
+    ///
     /// # Errors
     ///
     /// The specified socket name is invalid, or a connection cannot be made
     /// with a server
     pub fn with_name<'scope>(name: impl Into<SocketName<'scope>>) -> Result<Self, Error> {
         let sn = name.into();
+        let conn = Connection::open(&sn, "")?;
 
-        cfg_if::cfg_if! {
-            if #[cfg(any(target_os = "linux", target_os = "android"))] {
-                let socket_addr = match sn {
-                    SocketName::Path(path) => {
-                        uds::UnixSocketAddr::from_path(path).map_err(|_err| Error::InvalidName)?
-                    }
-                    SocketName::Abstract(name) => {
-                        uds::UnixSocketAddr::from_abstract(name).map_err(|_err| Error::InvalidName)?
-                    }
-                };
+        Ok(Self {
+            conn: parking_lot::RwLock::new(conn),
+            socket_name: Some(sn.into()),
+            reconnect: Some(Reconnect::default()),
+            name: String::new(),
+            #[cfg(not(target_os = "macos"))]
+            spool: None,
+        })
+    }
 
-                let socket = Stream::connect_unix_addr(&socket_addr)?;
-            } else if #[cfg(target_os = "windows")] {
-                let SocketName::Path(path) = sn;
-                let socket = Stream::connect(path)?;
-            } else if #[cfg(target_os = "macos")] {
-                let SocketName::Path(path) = sn;
-                let socket = Stream::connect(path)?;
+    /// Creates a client from the socket inherited from the
+    /// [`crate::supervisor::Supervisor`] that spawned this process.
+    ///
+    /// As the socket has no name, the client can't reconnect.
+    ///
+    /// # Errors
+    ///
+    /// [`crate::supervisor::SOCKET_FD_ENV`] is not set to a file descriptor,
+    /// ie. the process was not spawned by a supervisor
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn from_env() -> Result<Self, Error> {
+        use std::os::unix::io::FromRawFd;
 
-                // Note that sun_path is limited to 108 characters including null,
-                // while a mach port name is limited to 128 including null, so
-                // the length is already effectively checked here
-                let port_name = std::ffi::CString::new(path.to_str().ok_or(Error::InvalidPortName)?).map_err(|_err| Error::InvalidPortName)?;
-                let port = crash_context::ipc::Client::create(&port_name)?;
-            } else {
-                compile_error!("unimplemented target platform");
-            }
+        let fd: i32 = std::env::var(super::supervisor::SOCKET_FD_ENV)
+            .ok()
+            .and_then(|fd| fd.parse().ok())
+            .ok_or(Error::InvalidName)?;
+
+        // Ensure the socket isn't inherited by any processes we spawn in turn
+        #[allow(unsafe_code)]
+        // SAFETY: syscall
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(std::io::Error::last_os_error().into());
         }
 
-        let s = Self {
+        #[allow(unsafe_code)]
+        // SAFETY: the descriptor was inherited from the supervisor, and checked
+        // to be open above
+        let socket = unsafe { Stream::from_raw_fd(fd) };
+
+        let mut conn = Connection {
             socket,
-            #[cfg(target_os = "macos")]
-            port,
+            server: super::Hello::legacy(),
+            generation: 0,
         };
+        conn.server = conn.handshake("")?;
 
-        #[cfg(target_os = "macos")]
-        {
-            // Since we aren't sending crash requests as id 0 like for other
-            // platforms, we instead abuse it to send the pid of this process
-            // so that the server can pair the port and the socket together
-            let id_buf = std::process::id().to_ne_bytes();
-            s.send_message_impl(0, &id_buf)?;
-            let mut ack = [0u8; 1];
-            s.socket.recv(&mut ack)?;
-        }
+        Ok(Self {
+            conn: parking_lot::RwLock::new(conn),
+            socket_name: None,
+            reconnect: None,
+            name: String::new(),
+            spool: None,
+        })
+    }
 
-        Ok(s)
+    /// Sets how the client reconnects if the server goes away, eg. because it
+    /// was restarted, or disables reconnecting if `None`.
+    ///
+    /// The client reconnects when sending a message, or waiting for a reply,
+    /// fails because the connection was closed, after which the message is
+    /// sent again to the new server. [`Self::request_dump`] never reconnects,
+    /// as it is called while the process is crashing, see
+    /// [`Self::set_spool_dir`] instead.
+    #[inline]
+    pub fn set_reconnect(&mut self, reconnect: Option<Reconnect>) {
+        self.reconnect = reconnect;
+    }
+
+    /// Sets the directory crashes are written to if they can't be sent to the
+    /// server, eg. because it is being restarted at the moment of the crash,
+    /// so that they can be reported later, see [`crate::spool`].
+    ///
+    /// Not available on macOS, as crashes are sent as the task port of the
+    /// process, which can't be written to a file.
+    ///
+    /// # Errors
+    ///
+    /// The directory could not be created
+    #[cfg(not(target_os = "macos"))]
+    pub fn set_spool_dir(&mut self, dir: impl AsRef<std::path::Path>) -> Result<(), Error> {
+        self.spool = Some(super::spool::Spool::new(dir.as_ref())?);
+        Ok(())
+    }
+
+    /// Sets the name the server identifies this client by, eg. `tab-3` or
+    /// `gpu`, which is passed to [`crate::ServerHandler::on_client_identified`],
+    /// and included in the [`crate::storage::DumpInfo`] of the minidumps of
+    /// this process.
+    ///
+    /// # Errors
+    ///
+    /// The send to the server fails, or the server sends an invalid response
+    pub fn set_name(&mut self, name: &str) -> Result<(), Error> {
+        self.name = name.to_owned();
+        let server = self.with_connection(|conn| conn.handshake(name))?;
+        self.conn.get_mut().server = server;
+        Ok(())
+    }
+
+    /// The version of the protocol used to talk to the server, which is the
+    /// lower of [`crate::PROTOCOL_VERSION`] and the version of the server, or
+    /// 0 if the server predates versioning
+    #[inline]
+    pub fn protocol_version(&self) -> u32 {
+        self.conn.read().server.version
     }
 
     /// Requests that the server generate a minidump for the specified crash
     /// context. This blocks until the server has finished writing the minidump.
     ///
+    /// If the request can't be sent, eg. because the server is being
+    /// restarted, and a [spool directory](Self::set_spool_dir) is set, the
+    /// crash context is written to it instead, and the error is still
+    /// returned.
+    ///
     /// # Linux
     ///
     /// This uses a [`crash_context::CrashContext`] by reference as the size of
@@ -98,50 +237,51 @@ impl Client {
     /// (apologies for the terrible documentation, blame Apple) before calling
     /// this method
     pub fn request_dump(&self, crash_context: &crash_context::CrashContext) -> Result<(), Error> {
-        cfg_if::cfg_if! {
-            if #[cfg(any(target_os = "linux", target_os = "android"))] {
-                let crash_ctx_buffer = crash_context.as_bytes();
-            } else if #[cfg(target_os = "windows")] {
-                use scroll::Pwrite;
-                let mut buf = [0u8; 24];
-                let written = buf.pwrite(
-                    super::DumpRequest {
-                        exception_pointers: crash_context.exception_pointers as _,
-                        process_id: crash_context.process_id,
-                        thread_id: crash_context.thread_id,
-                        exception_code: crash_context.exception_code,
-                    },
-                    0,
-                )?;
+        // Never wait for another thread that is reconnecting, as it might
+        // never get the chance to finish
+        let result = match self.conn.try_read() {
+            Some(conn) => conn.request_dump(crash_context),
+            None => Err(Error::ProtocolError("the client is reconnecting")),
+        };
 
-                let crash_ctx_buffer = &buf[..written];
-            } else if #[cfg(target_os = "macos")] {
-                self.port.send_crash_context(
-                    crash_context,
-                    Some(std::time::Duration::from_secs(2)),
-                    Some(std::time::Duration::from_secs(5))
-                )?;
-                Ok(())
+        #[cfg(not(target_os = "macos"))]
+        if let (Err(_), Some(spool)) = (&result, &self.spool) {
+            let mut buf = [0u8; CRASH_PAYLOAD_LEN];
+            if let Ok(written) = crash_payload(crash_context, &mut buf) {
+                let _res = spool.write(&buf[..written]);
             }
         }
 
-        #[cfg(not(target_os = "macos"))]
-        {
-            self.send_message_impl(0, crash_ctx_buffer)?;
-
-            // Wait for the server to send back an ack that it has finished
-            // with the crash context
-            let mut ack = [0u8; std::mem::size_of::<Header>()];
-            self.socket.recv(&mut ack)?;
-
-            let header = Header::from_bytes(&ack);
-
-            if header.filter(|hdr| hdr.kind == super::CRASH_ACK).is_none() {
-                return Err(Error::ProtocolError("received invalid response to crash"));
-            }
+        result
+    }
 
-            Ok(())
-        }
+    /// Requests that the server generate a minidump of this process, including
+    /// every thread, without it having crashed, eg. when it appears to be hung.
+    /// This blocks until the server has finished writing the minidump, after
+    /// which this process continues running as normal.
+    ///
+    /// The `reason` is passed to [`crate::ServerHandler::on_live_dump_created`]
+    /// so that the server can differentiate between live dumps and crashes.
+    ///
+    /// # Linux
+    ///
+    /// The server attaches to this process with `ptrace`, so it must have the
+    /// same permissions as for [`crate::ptrace_dumper`].
+    ///
+    /// # Macos
+    ///
+    /// Once the server has acknowledged the request, the task port of this
+    /// process is sent to it via the mach port, the same as for a crash, so
+    /// that it can read the state of every thread and the memory of the
+    /// process. Unlike for a crash, there is no exception, and the thread that
+    /// requested the dump is recorded as the crashing thread.
+    ///
+    /// # Errors
+    ///
+    /// The server doesn't support live dumps, the send to the server fails,
+    /// or the server sends an invalid response
+    pub fn request_live_dump(&self, reason: &str) -> Result<(), Error> {
+        self.with_connection(|conn| conn.request_live_dump(reason))
     }
 
     /// Sends a message to the server.
@@ -161,12 +301,20 @@ impl Client {
     ///
     /// # Errors
     ///
-    /// The send to the server fails
+    /// The `kind` is greater than [`crate::MAX_MESSAGE_KIND`], the message is
+    /// larger than 4GiB, or the send to the server fails
     #[inline]
     pub fn send_message(&self, kind: u32, buf: impl AsRef<[u8]>) -> Result<(), Error> {
-        debug_assert!(kind < u32::MAX - super::USER);
+        if kind > super::MAX_MESSAGE_KIND {
+            return Err(Error::InvalidMessageKind(kind));
+        }
+
+        let buf = buf.as_ref();
+        if u32::try_from(buf.len()).is_err() {
+            return Err(Error::ProtocolError("message is too large"));
+        }
 
-        self.send_message_impl(kind + super::USER, buf.as_ref())
+        self.with_connection(|conn| conn.send(kind + super::USER, buf))
 
         // TODO: should we have an ACK? IPC is a (relatively) reliable communication
         // method, and reserving receives from the server for the exclusive
@@ -184,10 +332,283 @@ impl Client {
     /// The send to the server fails
     #[inline]
     pub fn ping(&self) -> Result<(), Error> {
-        self.send_message_impl(super::PING, &[])?;
+        self.with_connection(Connection::ping)
+    }
+
+    /// Runs the operation on the connection, reconnecting and running it
+    /// again if the connection was closed
+    fn with_connection<T>(&self, op: impl Fn(&Connection) -> Result<T, Error>) -> Result<T, Error> {
+        let (generation, err) = {
+            let conn = self.conn.read();
+            match op(&conn) {
+                Err(err) if is_disconnect(&err) => (conn.generation, err),
+                res => return res,
+            }
+        };
+
+        log::debug!("lost connection to server: {}", err);
+        if !self.reconnect(generation) {
+            return Err(err);
+        }
+
+        op(&self.conn.read())
+    }
+
+    /// Replaces the connection that was lost, returning false if the client
+    /// can't reconnect, or gave up
+    fn reconnect(&self, lost: u64) -> bool {
+        let (Some(sn), Some(reconnect)) = (&self.socket_name, self.reconnect) else {
+            return false;
+        };
+
+        let mut conn = self.conn.write();
+
+        // Another thread already reconnected while we waited for the lock
+        if conn.generation != lost {
+            return true;
+        }
+
+        let mut backoff = reconnect.initial_backoff;
+        for attempt in 1..=reconnect.attempts {
+            match Connection::open(&sn.as_name(), &self.name) {
+                Ok(mut new) => {
+                    log::info!("reconnected to server after {} attempts", attempt);
+                    new.generation = lost + 1;
+                    *conn = new;
+                    return true;
+                }
+                Err(err) => {
+                    log::debug!("failed to reconnect to server: {}", err);
+                }
+            }
+
+            if attempt < reconnect.attempts {
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(reconnect.max_backoff);
+            }
+        }
+
+        false
+    }
+}
+
+impl Connection {
+    /// Connects to the server and identifies ourselves with the specified name
+    fn open(sn: &SocketName<'_>, name: &str) -> Result<Self, Error> {
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let socket_addr = match sn {
+                    SocketName::Path(path) => {
+                        uds::UnixSocketAddr::from_path(path).map_err(|_err| Error::InvalidName)?
+                    }
+                    SocketName::Abstract(name) => {
+                        uds::UnixSocketAddr::from_abstract(name).map_err(|_err| Error::InvalidName)?
+                    }
+                };
+
+                let socket = Stream::connect_unix_addr(&socket_addr)?;
+            } else if #[cfg(target_os = "windows")] {
+                let SocketName::Path(path) = sn;
+                let socket = Stream::connect(path)?;
+            } else if #[cfg(target_os = "macos")] {
+                let SocketName::Path(path) = sn;
+                let socket = Stream::connect(path)?;
+
+                // Note that sun_path is limited to 108 characters including null,
+                // while a mach port name is limited to 128 including null, so
+                // the length is already effectively checked here
+                let port_name = std::ffi::CString::new(path.to_str().ok_or(Error::InvalidPortName)?).map_err(|_err| Error::InvalidPortName)?;
+                let port = crash_context::ipc::Client::create(&port_name)?;
+            } else {
+                compile_error!("unimplemented target platform");
+            }
+        }
+
+        let mut conn = Self {
+            socket,
+            server: super::Hello::legacy(),
+            #[cfg(target_os = "macos")]
+            port,
+            generation: 0,
+        };
+
+        #[cfg(target_os = "macos")]
+        {
+            // Since we aren't sending crash requests as id 0 like for other
+            // platforms, we instead abuse it to send the pid of this process
+            // so that the server can pair the port and the socket together
+            let id_buf = std::process::id().to_ne_bytes();
+            conn.send(0, &id_buf)?;
+            let mut ack = [0u8; 1];
+            conn.recv(&mut ack)?;
+        }
+
+        conn.server = conn.handshake(name)?;
+
+        Ok(conn)
+    }
+
+    /// Exchanges a [`super::Hello`] with the server, returning the one the
+    /// server replied with. The hello is followed by the [`super::Identity`]
+    /// of this process, with the specified name.
+    ///
+    /// The hello is sent as the payload of a ping, which servers that predate
+    /// the handshake reply to with a plain pong, in which case they are
+    /// treated as supporting every message kind that existed at that point.
+    fn handshake(&self, name: &str) -> Result<super::Hello, Error> {
+        use scroll::{Pread, Pwrite};
+
+        let mut hello = [0u8; super::HELLO_LEN + super::IDENTITY_LEN];
+        let mut written = hello.pwrite(super::Hello::current(), 0)?;
+        written += hello.pwrite(
+            super::Identity {
+                process_id: std::process::id(),
+                name_len: name.len() as u32,
+            },
+            written,
+        )?;
+
+        let executable = std::env::current_exe().unwrap_or_default();
+        let executable = executable.to_string_lossy();
+
+        let mut buf = Vec::with_capacity(written + name.len() + executable.len());
+        buf.extend_from_slice(&hello[..written]);
+        buf.extend_from_slice(name.as_bytes());
+        buf.extend_from_slice(executable.as_bytes());
+
+        self.send(super::PING, &buf)?;
+
+        let mut reply = [0u8; std::mem::size_of::<Header>() + super::HELLO_LEN];
+        let len = self.recv(&mut reply)?;
+        let (header, body) = reply[..len].split_at(len.min(std::mem::size_of::<Header>()));
+
+        match Header::from_bytes(header).map(|hdr| hdr.kind) {
+            Some(super::PONG) => Ok(super::Hello::legacy()),
+            Some(super::HELLO) => {
+                let server: super::Hello = body.pread(0)?;
+                if server.accepted == 0 {
+                    return Err(Error::Incompatible);
+                }
+
+                Ok(super::Hello {
+                    version: server.version.min(super::PROTOCOL_VERSION),
+                    capabilities: server.capabilities & super::CAPABILITIES,
+                    ..server
+                })
+            }
+            _ => Err(Error::ProtocolError(
+                "received invalid response to handshake",
+            )),
+        }
+    }
+
+    fn request_dump(&self, crash_context: &crash_context::CrashContext) -> Result<(), Error> {
+        cfg_if::cfg_if! {
+            if #[cfg(target_os = "macos")] {
+                self.port.send_crash_context(
+                    crash_context,
+                    Some(std::time::Duration::from_secs(2)),
+                    Some(std::time::Duration::from_secs(5))
+                )?;
+                Ok(())
+            } else {
+                let mut buf = [0u8; CRASH_PAYLOAD_LEN];
+                let written = crash_payload(crash_context, &mut buf)?;
+
+                self.send(super::CRASH, &buf[..written])?;
+
+                // Wait for the server to send back an ack that it has finished
+                // with the crash context
+                let mut ack = [0u8; std::mem::size_of::<Header>()];
+                self.recv(&mut ack)?;
+
+                let header = Header::from_bytes(&ack);
+
+                if header.filter(|hdr| hdr.kind == super::CRASH_ACK).is_none() {
+                    return Err(Error::ProtocolError("received invalid response to crash"));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn request_live_dump(&self, reason: &str) -> Result<(), Error> {
+        use scroll::Pwrite;
+
+        if !self.server.supports(super::LIVE_DUMP) {
+            return Err(Error::Unsupported("live dumps"));
+        }
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                #[allow(unsafe_code)]
+                // SAFETY: syscall
+                let thread_id = unsafe { libc::syscall(libc::SYS_gettid) } as u32;
+            } else if #[cfg(target_os = "windows")] {
+                #[allow(unsafe_code)]
+                // SAFETY: syscall
+                let thread_id = unsafe { windows_sys::Win32::System::Threading::GetCurrentThreadId() };
+            } else if #[cfg(target_os = "macos")] {
+                // The thread is identified by the task port context instead
+                let thread_id = 0;
+            }
+        }
+
+        let mut req_buf = [0u8; 8];
+        let written = req_buf.pwrite(
+            super::LiveDumpRequest {
+                process_id: std::process::id(),
+                thread_id,
+            },
+            0,
+        )?;
+
+        let mut buf = Vec::with_capacity(written + reason.len());
+        buf.extend_from_slice(&req_buf[..written]);
+        buf.extend_from_slice(reason.as_bytes());
+
+        self.send(super::LIVE_DUMP, &buf)?;
+
+        // Wait for the server to send back an ack that it has finished
+        // dumping this process, or on macOS, that it is ready to receive the
+        // task port that allows it to do so
+        let mut ack = [0u8; std::mem::size_of::<Header>()];
+        self.recv(&mut ack)?;
+
+        let header = Header::from_bytes(&ack);
+
+        if header
+            .filter(|hdr| hdr.kind == super::LIVE_DUMP_ACK)
+            .is_none()
+        {
+            return Err(Error::ProtocolError(
+                "received invalid response to live dump",
+            ));
+        }
+
+        // The server acks the message on the port once the minidump has been
+        // written, after which the thread right we captured is no longer needed
+        #[cfg(target_os = "macos")]
+        {
+            let crash_context = crash_context::CrashContext::capture();
+            let sent = self.port.send_crash_context(
+                &crash_context,
+                Some(std::time::Duration::from_secs(2)),
+                Some(std::time::Duration::from_secs(5)),
+            );
+            crash_context.release();
+            sent?;
+        }
+
+        Ok(())
+    }
+
+    fn ping(&self) -> Result<(), Error> {
+        self.send(super::PING, &[])?;
 
         let mut pong = [0u8; std::mem::size_of::<Header>()];
-        self.socket.recv(&mut pong)?;
+        self.recv(&mut pong)?;
 
         let header = Header::from_bytes(&pong);
 
@@ -198,7 +619,7 @@ impl Client {
         }
     }
 
-    fn send_message_impl(&self, kind: u32, buf: &[u8]) -> Result<(), Error> {
+    fn send(&self, kind: u32, buf: &[u8]) -> Result<(), Error> {
         let header = Header {
             kind,
             size: buf.len() as u32,
@@ -209,4 +630,76 @@ impl Client {
         self.socket.send_vectored(&io_bufs)?;
         Ok(())
     }
+
+    /// Receives a reply from the server, the server closing the connection
+    /// being an error so that we can reconnect
+    fn recv(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let len = self.socket.recv(buf)?;
+        if len == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into());
+        }
+
+        Ok(len)
+    }
+}
+
+/// Whether the error means the connection to the server was lost
+fn is_disconnect(err: &Error) -> bool {
+    use std::io::ErrorKind;
+
+    matches!(
+        err,
+        Error::Io(err) if matches!(
+            err.kind(),
+            ErrorKind::BrokenPipe
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+        )
+    )
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        const CRASH_PAYLOAD_LEN: usize = crash_context::CrashContext::SERIALIZED_LEN;
+    } else if #[cfg(target_os = "windows")] {
+        const CRASH_PAYLOAD_LEN: usize = 32;
+    }
+}
+
+/// Serializes the crash context into the payload of a crash request
+#[cfg(not(target_os = "macos"))]
+fn crash_payload(
+    crash_context: &crash_context::CrashContext,
+    buf: &mut [u8; CRASH_PAYLOAD_LEN],
+) -> Result<usize, Error> {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            crash_context
+                .serialize_into(buf)
+                .ok_or(Error::ProtocolError("crash context buffer is too small"))
+        } else if #[cfg(target_os = "windows")] {
+            use scroll::Pwrite;
+            let fast_fail_code = crash_context.reason.fast_fail_code();
+            Ok(buf.pwrite(
+                super::DumpRequest {
+                    exception_pointers: crash_context.exception_pointers as _,
+                    process_id: crash_context.process_id,
+                    thread_id: crash_context.thread_id,
+                    exception_code: crash_context.exception_code,
+                    fast_fail_code: fast_fail_code.unwrap_or_default(),
+                    has_fast_fail_code: fast_fail_code.is_some().into(),
+                },
+                0,
+            )?)
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // Let the server know we disconnected on purpose, it doesn't matter
+        // if it has already gone away
+        let _res = self.conn.get_mut().send(super::GOODBYE, &[]);
+    }
 }
@@ -10,12 +10,36 @@ pub struct Server {
     #[cfg(target_os = "macos")]
     port: crash_context::ipc::Server,
     /// For abstract sockets, we don't have to worry about cleanup as it is
-    /// handled by the OS, but on Windows and MacOS we need to clean them up
+    /// handled by the OS, but on Windows and macOS we need to clean them up
     /// manually. We basically rely on the crash monitor program this Server
     /// is running in to exit cleanly, which should be mostly true, but we
     /// may need to harden this code if people experience issues with socket
     /// paths not being cleaned up reliably
     socket_path: Option<std::path::PathBuf>,
+    /// The event loop, in which the listener is registered with key 0
+    poll: Poller,
+    events: Vec<Event>,
+    clients: Vec<ClientConn>,
+    /// The key the next socket or pidfd is registered with
+    next_key: usize,
+    /// The processes of clients that have disconnected, but not yet exited
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    disconnected: Vec<ClientProcess>,
+}
+
+/// What happened to the clients of a [`Server`] that was shut down, see
+/// [`Server::shutdown`]
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownSummary {
+    /// Whether every request sent by a client was handled before the
+    /// deadline, including writing the minidumps that were requested
+    pub drained: bool,
+    /// The clients that were still connected when the server shut down
+    pub disconnected: Vec<crate::ClientInfo>,
+    /// The clients whose requests weren't handled before the deadline, which
+    /// are also [`Self::disconnected`], eg. so that they can be persisted and
+    /// reported by the server that replaces this one
+    pub unfinished: Vec<crate::ClientInfo>,
 }
 
 struct ClientConn {
@@ -25,13 +49,127 @@ struct ClientConn {
     key: usize,
     /// Last time a message was sent from the client
     last_update: Instant,
+    /// Who the client is, see [`crate::ServerHandler::on_client_identified`]
+    info: crate::ClientInfo,
     /// We pair the pid of the client process so that we know which connection
     /// to drop when a crash is received on the mach port
     #[cfg(target_os = "macos")]
     pid: Option<u32>,
+    /// The reason for the live dump the client requested, if its task port
+    /// is yet to be received on the mach port
+    #[cfg(target_os = "macos")]
+    live_dump: Option<String>,
+    /// The process of the client, so that we can tell how it exited
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    process: Option<ClientProcess>,
+    /// How the process of the client exited, if it told us before doing so
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    exit: Option<crate::ClientExit>,
+}
+
+/// Watches the process of a client, see [`crate::ServerHandler::on_client_exited`]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct ClientProcess {
+    pid: u32,
+    /// A pidfd for the process, which becomes readable once it has exited, or
+    /// -1 if the kernel doesn't support them
+    pidfd: i32,
+    /// The key we associated with the pidfd
+    key: usize,
+    /// How the process exited, if it told us before doing so
+    exit: Option<crate::ClientExit>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl ClientProcess {
+    fn watch(socket: &Connection, poll: &Poller, key: usize) -> Option<Self> {
+        let pid = socket.initial_peer_credentials().ok()?.pid()?.get();
+
+        #[allow(unsafe_code)]
+        // SAFETY: syscall
+        let mut pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) } as i32;
+        if pidfd == -1 {
+            log::debug!(
+                "failed to open pidfd for {}: {}",
+                pid,
+                std::io::Error::last_os_error()
+            );
+        } else if let Err(e) = poll.add(pidfd, Event::readable(key)) {
+            log::error!("failed to register pidfd: {}", e);
+
+            #[allow(unsafe_code)]
+            // SAFETY: syscall, we own the pidfd
+            unsafe {
+                libc::close(pidfd);
+            }
+            pidfd = -1;
+        }
+
+        Some(Self {
+            pid,
+            pidfd,
+            key,
+            exit: None,
+        })
+    }
+
+    /// Called once the client's socket has been closed. The process is
+    /// considered to have exited immediately if we can't wait on its pidfd,
+    /// otherwise it is watched until it does.
+    fn disconnected(
+        self,
+        poll: &Poller,
+        disconnected: &mut Vec<Self>,
+        handler: &dyn crate::ServerHandler,
+    ) -> LoopAction {
+        if self.pidfd == -1 {
+            self.exited(poll, handler)
+        } else {
+            disconnected.push(self);
+            LoopAction::Continue
+        }
+    }
+
+    fn exited(self, poll: &Poller, handler: &dyn crate::ServerHandler) -> LoopAction {
+        if self.pidfd != -1 {
+            if let Err(e) = poll.delete(self.pidfd) {
+                log::error!("failed to deregister pidfd: {}", e);
+            }
+        }
+
+        let exit = self.exit.unwrap_or(crate::ClientExit::Vanished);
+        log::debug!("client process {} exited: {:?}", self.pid, exit);
+        handler.on_client_exited(self.pid, exit)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for ClientProcess {
+    fn drop(&mut self) {
+        if self.pidfd != -1 {
+            #[allow(unsafe_code)]
+            // SAFETY: syscall, we own the pidfd
+            unsafe {
+                libc::close(self.pidfd);
+            }
+        }
+    }
 }
 
 impl ClientConn {
+    /// Called once the client's socket has been closed, as the process can't
+    /// be watched directly on this platform
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn exited(&self, handler: &dyn crate::ServerHandler) -> LoopAction {
+        let Some(pid) = self.info.pid else {
+            return LoopAction::Continue;
+        };
+
+        let exit = self.exit.unwrap_or(crate::ClientExit::Vanished);
+        log::debug!("client process {} exited: {:?}", pid, exit);
+        handler.on_client_exited(pid, exit)
+    }
+
     fn recv(&mut self, handler: &dyn crate::ServerHandler) -> Option<(u32, Vec<u8>)> {
         use std::io::IoSliceMut;
 
@@ -125,11 +263,20 @@ impl Server {
             }
         }
 
+        let poll = Poller::new()?;
+        poll.add(&listener, Event::readable(0))?;
+
         Ok(Self {
             listener: Some(listener),
             #[cfg(target_os = "macos")]
             port,
             socket_path,
+            poll,
+            events: Vec::new(),
+            clients: Vec::new(),
+            next_key: 1,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            disconnected: Vec::new(),
         })
     }
 
@@ -157,254 +304,723 @@ impl Server {
         shutdown: &std::sync::atomic::AtomicBool,
         stale_timeout: Option<std::time::Duration>,
     ) -> Result<(), Error> {
-        let poll = Poller::new()?;
-        let mut events = Vec::new();
+        loop {
+            if shutdown.load(std::sync::atomic::Ordering::Relaxed) {
+                break;
+            }
+
+            match self.poll_events(
+                handler.as_ref(),
+                Some(Duration::from_millis(10)),
+                stale_timeout,
+            ) {
+                Ok(LoopAction::Continue) => {}
+                Ok(LoopAction::Exit) => break,
+                Err(err) => {
+                    self.disconnect_all();
+                    return Err(err);
+                }
+            }
+        }
 
-        poll.add(self.listener.as_ref().unwrap(), Event::readable(0))?;
+        self.disconnect_all();
+        Ok(())
+    }
+
+    /// Processes the connections and messages that are ready, waiting up to
+    /// `timeout` for any to arrive, which allows the server to be driven by a
+    /// loop that also does other work, eg. serving an HTTP status endpoint,
+    /// rather than dedicating a thread to [`Self::run`].
+    ///
+    /// A timeout of [`Duration::ZERO`] never blocks, which is the best fit
+    /// for an event loop that is already told when the server is ready, eg.
+    /// via the file descriptor of the server on Linux/Android, which can be
+    /// registered with `epoll`, `mio`, or tokio's `AsyncFd`, and is readable
+    /// whenever there is work to do. Note that on macOS, crashes arrive on a
+    /// mach port rather than a socket, so this must be called periodically.
+    ///
+    /// See [`Self::run`] for `stale_timeout`.
+    ///
+    /// Returns [`LoopAction::Exit`] if the handler requested the server to
+    /// exit, in which case every client is disconnected, the same as when
+    /// [`Self::run`] exits.
+    ///
+    /// # Errors
+    ///
+    /// This method uses basic I/O event notification via [`polling`] which
+    /// can fail for a number of different reasons
+    pub fn poll_once(
+        &mut self,
+        handler: &dyn crate::ServerHandler,
+        timeout: Option<Duration>,
+        stale_timeout: Option<Duration>,
+    ) -> Result<LoopAction, Error> {
+        let action = self.poll_events(handler, timeout, stale_timeout)?;
+        if action == LoopAction::Exit {
+            self.disconnect_all();
+        }
+
+        Ok(action)
+    }
+
+    /// Shuts the server down gracefully, eg. before the service running it is
+    /// restarted.
+    ///
+    /// New clients are no longer accepted, and the socket name is released
+    /// immediately so that a new server can be created with it. The requests
+    /// that connected clients have already sent, including requests for
+    /// minidumps, continue to be handled until there are none left, or the
+    /// `deadline` passes. The clients that are still connected are then
+    /// disconnected, calling [`crate::ServerHandler::on_client_disconnected_from`]
+    /// for each.
+    ///
+    /// # Errors
+    ///
+    /// This method uses basic I/O event notification via [`polling`] which
+    /// can fail for a number of different reasons
+    pub fn shutdown(
+        &mut self,
+        handler: &dyn crate::ServerHandler,
+        deadline: Instant,
+    ) -> Result<ShutdownSummary, Error> {
+        if let Some(listener) = self.listener.take() {
+            if let Err(e) = self.poll.delete(&listener) {
+                log::error!("failed to deregister listener: {}", e);
+            }
+        }
+
+        if let Some(path) = self.socket_path.take() {
+            let _res = std::fs::remove_file(path);
+        }
 
-        let mut clients = Vec::new();
-        let mut id = 1;
+        let mut summary = ShutdownSummary::default();
 
         loop {
-            if shutdown.load(std::sync::atomic::Ordering::Relaxed) {
-                return Ok(());
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+
+            let timeout = remaining.min(Duration::from_millis(10));
+            if self.poll_events(handler, Some(timeout), None)? == LoopAction::Exit {
+                log::debug!("handler exited message loop while draining");
+                break;
+            }
+
+            // Nothing happened while we waited, so everything the clients
+            // sent has been handled
+            if self.events.is_empty() && !self.has_pending_work() {
+                summary.drained = true;
+                break;
+            }
+        }
+
+        if !summary.drained {
+            // Check which clients sent something we didn't get to, without
+            // handling it
+            self.events.clear();
+            self.poll.wait(&mut self.events, Some(Duration::ZERO))?;
+
+            for cc in &self.clients {
+                #[cfg(target_os = "macos")]
+                let pending = cc.live_dump.is_some();
+                #[cfg(not(target_os = "macos"))]
+                let pending = false;
+
+                if pending || self.events.iter().any(|event| event.key == cc.key) {
+                    summary.unfinished.push(cc.info.clone());
+                }
+            }
+        }
+
+        let mut remaining = self.clients.len();
+        for cc in &self.clients {
+            remaining -= 1;
+            summary.disconnected.push(cc.info.clone());
+            // The server is going away regardless of what the handler wants
+            let _action = handler.on_client_disconnected_from(&cc.info, remaining);
+        }
+
+        log::info!(
+            "shut down with {} clients connected, {} unfinished",
+            summary.disconnected.len(),
+            summary.unfinished.len()
+        );
+
+        self.disconnect_all();
+        Ok(summary)
+    }
+
+    /// Whether a client is waiting on something other than a message, ie. on
+    /// macOS, a live dump whose task port hasn't been received yet
+    fn has_pending_work(&self) -> bool {
+        #[cfg(target_os = "macos")]
+        {
+            self.clients.iter().any(|cc| cc.live_dump.is_some())
+        }
+        #[cfg(not(target_os = "macos"))]
+        {
+            false
+        }
+    }
+
+    /// Drops every client connection, without notifying the handler, as the
+    /// message loop is exiting
+    fn disconnect_all(&mut self) {
+        for cc in self.clients.drain(..) {
+            if let Err(e) = self.poll.delete(&cc.socket) {
+                log::error!("failed to deregister socket: {}", e);
             }
+        }
 
-            events.clear();
-            poll.wait(&mut events, Some(Duration::from_millis(10)))?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        self.disconnected.clear();
+    }
 
+    fn poll_events(
+        &mut self,
+        handler: &dyn crate::ServerHandler,
+        timeout: Option<Duration>,
+        stale_timeout: Option<Duration>,
+    ) -> Result<LoopAction, Error> {
+        let Self {
+            listener,
             #[cfg(target_os = "macos")]
-            if self.check_mach_port(&poll, &mut clients, handler.as_ref())? == LoopAction::Exit {
-                return Ok(());
+            port,
+            poll,
+            events,
+            clients,
+            next_key,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            disconnected,
+            ..
+        } = self;
+        let poll = &*poll;
+
+        events.clear();
+        poll.wait(events, timeout)?;
+
+        #[cfg(target_os = "macos")]
+        if Self::check_mach_port(port, poll, clients, handler)? == LoopAction::Exit {
+            return Ok(LoopAction::Exit);
+        }
+
+        for event in events.iter() {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if let Some(action) =
+                Self::check_exited(event.key, poll, clients, disconnected, handler)
+            {
+                if action == LoopAction::Exit {
+                    log::debug!("client exit exited message loop");
+                    return Ok(LoopAction::Exit);
+                }
+
+                continue;
             }
 
-            for event in events.iter() {
-                if event.key == 0 {
-                    match self.listener.as_ref().unwrap().accept_unix_addr() {
-                        Ok((accepted, _addr)) => {
-                            let key = id;
-                            id += 1;
-
-                            poll.add(&accepted, Event::readable(key))?;
-
-                            log::debug!("accepted connection {}", key);
-                            clients.push(ClientConn {
-                                socket: accepted,
-                                key,
-                                last_update: Instant::now(),
-                                #[cfg(target_os = "macos")]
-                                pid: None,
-                            });
-
-                            if handler.on_client_connected(clients.len()) == LoopAction::Exit {
-                                log::debug!("on_client_connected exited message loop");
-                                return Ok(());
-                            }
-                        }
-                        Err(err) => {
-                            log::error!("failed to accept socket connection: {}", err);
+            // The listener is gone once the server is shutting down
+            if let (0, Some(listener)) = (event.key, listener.as_ref()) {
+                match listener.accept_unix_addr() {
+                    Ok((accepted, _addr)) => {
+                        let key = *next_key;
+                        *next_key += 1;
+
+                        poll.add(&accepted, Event::readable(key))?;
+
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        let process = {
+                            let process = ClientProcess::watch(&accepted, poll, *next_key);
+                            *next_key += 1;
+                            process
+                        };
+
+                        let info = crate::ClientInfo {
+                            id: key,
+                            #[cfg(any(target_os = "linux", target_os = "android"))]
+                            pid: process.as_ref().map(|process| process.pid),
+                            ..Default::default()
+                        };
+
+                        log::debug!("accepted connection {}", key);
+                        clients.push(ClientConn {
+                            socket: accepted,
+                            key,
+                            last_update: Instant::now(),
+                            info,
+                            #[cfg(target_os = "macos")]
+                            pid: None,
+                            #[cfg(target_os = "macos")]
+                            live_dump: None,
+                            #[cfg(any(target_os = "linux", target_os = "android"))]
+                            process,
+                            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                            exit: None,
+                        });
+
+                        if handler.on_client_connected(clients.len()) == LoopAction::Exit {
+                            log::debug!("on_client_connected exited message loop");
+                            return Ok(LoopAction::Exit);
                         }
                     }
+                    Err(err) => {
+                        log::error!("failed to accept socket connection: {}", err);
+                    }
+                }
 
-                    // We need to reregister insterest every time
-                    poll.modify(self.listener.as_ref().unwrap(), Event::readable(0))?;
-                } else if let Some(pos) = clients.iter().position(|cc| cc.key == event.key) {
-                    clients[pos].last_update = Instant::now();
-
-                    let deregister = match clients[pos].recv(handler.as_ref()) {
-                        Some((super::CRASH, buffer)) => {
-                            cfg_if::cfg_if! {
-                                if #[cfg(target_os = "macos")] {
-                                    use scroll::Pread;
-                                    let pid: u32 = buffer.pread(0)?;
-                                    clients[pos].pid = Some(pid);
-
-                                    if let Err(e) = clients[pos].socket.send(&[1]) {
-                                        log::error!("failed to send ack: {}", e);
-                                    }
+                // We need to reregister insterest every time
+                poll.modify(listener, Event::readable(0))?;
+            } else if let Some(pos) = clients.iter().position(|cc| cc.key == event.key) {
+                clients[pos].last_update = Instant::now();
+
+                let deregister = match clients[pos].recv(handler) {
+                    Some((super::CRASH, buffer)) => {
+                        cfg_if::cfg_if! {
+                            if #[cfg(target_os = "macos")] {
+                                use scroll::Pread;
+                                let pid: u32 = buffer.pread(0)?;
+                                clients[pos].pid = Some(pid);
+                                clients[pos].info.pid = Some(pid);
+
+                                if let Err(e) = clients[pos].socket.send(&[1]) {
+                                    log::error!("failed to send ack: {}", e);
+                                }
 
-                                    None
-                                } else {
-                                    let cc = clients.swap_remove(pos);
-
-                                    cfg_if::cfg_if! {
-                                        if #[cfg(any(target_os = "linux", target_os = "android"))] {
-                                            let peer_creds = cc.socket.initial_peer_credentials()?;
-
-                                            let pid = peer_creds.pid().ok_or(Error::UnknownClientPid)?;
-
-                                            let crash_ctx = crash_context::CrashContext::from_bytes(&buffer).ok_or_else(|| {
-                                                Error::from(std::io::Error::new(
-                                                    std::io::ErrorKind::InvalidData,
-                                                    "client sent an incorrectly sized buffer",
-                                                ))
-                                            })?;
-
-                                            // Validate that the crash info and the socket agree on the pid
-                                            if pid.get() != crash_ctx.pid as u32 {
-                                                return Err(Error::UnknownClientPid);
-                                            }
-                                        } else if #[cfg(target_os = "windows")] {
-                                            use scroll::Pread;
-                                            let dump_request: super::DumpRequest = buffer.pread(0)?;
-
-                                            // MiniDumpWriteDump primarily uses `EXCEPTION_POINTERS` for its crash
-                                            // context information, but inside that is an `EXCEPTION_RECORD`, which
-                                            // is an internally linked list, so rather than recurse and allocate until
-                                            // the end of that linked list, we just retrieve the actual pointer from
-                                            // the client process, and inform the dump writer that they are pointers
-                                            // to a different process, as MiniDumpWriteDump will internally read
-                                            // the processes memory as needed
-                                            let exception_pointers = dump_request.exception_pointers as *const std::ffi::c_void;
-
-                                            let crash_ctx = crash_context::CrashContext {
-                                                exception_pointers,
-                                                process_id: dump_request.process_id,
-                                                thread_id: dump_request.thread_id,
-                                                exception_code: dump_request.exception_code,
-                                            };
+                                None
+                            } else {
+                                #[allow(unused_mut)]
+                                let mut cc = clients.swap_remove(pos);
+
+                                cfg_if::cfg_if! {
+                                    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                                        if let Some(process) = &mut cc.process {
+                                            process.exit = Some(crate::ClientExit::Crashed);
                                         }
-                                    }
 
-                                    let action =
-                                        match Self::handle_crash_request(crash_ctx, handler.as_ref()) {
-                                            Err(err) => {
-                                                log::error!("failed to capture minidump: {}", err);
-                                                LoopAction::Continue
-                                            }
-                                            Ok(action) => {
-                                                log::info!("captured minidump");
-                                                action
-                                            }
+                                        let peer_creds = cc.socket.initial_peer_credentials()?;
+
+                                        let pid = peer_creds.pid().ok_or(Error::UnknownClientPid)?;
+
+                                        let crash_ctx = crash_context::CrashContext::deserialize(&buffer).map_err(|e| {
+                                            Error::from(std::io::Error::new(
+                                                std::io::ErrorKind::InvalidData,
+                                                e,
+                                            ))
+                                        })?;
+
+                                        // Validate that the crash info and the socket agree on the pid
+                                        if pid.get() != crash_ctx.pid as u32 {
+                                            return Err(Error::UnknownClientPid);
+                                        }
+                                        let pid = pid.get();
+                                    } else if #[cfg(target_os = "windows")] {
+                                        cc.exit = Some(crate::ClientExit::Crashed);
+
+                                        use scroll::Pread;
+                                        let dump_request: super::DumpRequest = buffer.pread(0)?;
+
+                                        // MiniDumpWriteDump primarily uses `EXCEPTION_POINTERS` for its crash
+                                        // context information, but inside that is an `EXCEPTION_RECORD`, which
+                                        // is an internally linked list, so rather than recurse and allocate until
+                                        // the end of that linked list, we just retrieve the actual pointer from
+                                        // the client process, and inform the dump writer that they are pointers
+                                        // to a different process, as MiniDumpWriteDump will internally read
+                                        // the processes memory as needed
+                                        let exception_pointers = dump_request.exception_pointers as *const std::ffi::c_void;
+
+                                        let crash_ctx = crash_context::CrashContext {
+                                            exception_pointers,
+                                            process_id: dump_request.process_id,
+                                            thread_id: dump_request.thread_id,
+                                            exception_code: dump_request.exception_code,
+                                            reason: crash_context::CrashReason::from_exception(
+                                                dump_request.exception_code,
+                                                (dump_request.has_fast_fail_code != 0)
+                                                    .then_some(dump_request.fast_fail_code),
+                                            ),
                                         };
+                                        let pid = dump_request.process_id;
+                                    }
+                                }
 
-                                    let ack = Header {
-                                        kind: super::CRASH_ACK,
-                                        size: 0,
+                                let action =
+                                    match Self::handle_crash_request(crash_ctx, pid, &cc.info, handler) {
+                                        Err(err) => {
+                                            log::error!("failed to capture minidump: {}", err);
+                                            LoopAction::Continue
+                                        }
+                                        Ok(action) => {
+                                            log::info!("captured minidump");
+                                            action
+                                        }
                                     };
 
-                                    if let Err(e) = cc.socket.send(ack.as_bytes()) {
-                                        log::error!("failed to send ack: {}", e);
-                                    }
+                                let ack = Header {
+                                    kind: super::CRASH_ACK,
+                                    size: 0,
+                                };
 
-                                    if action == LoopAction::Exit {
-                                        log::debug!("user handler requested exit after minidump creation");
-                                        return Ok(());
-                                    }
+                                if let Err(e) = cc.socket.send(ack.as_bytes()) {
+                                    log::error!("failed to send ack: {}", e);
+                                }
 
-                                    Some(cc.socket)
+                                if action == LoopAction::Exit {
+                                    log::debug!("user handler requested exit after minidump creation");
+                                    return Ok(LoopAction::Exit);
                                 }
+
+                                Some(cc)
                             }
                         }
-                        Some((super::PING, _buffer)) => {
-                            let pong = Header {
-                                kind: super::PONG,
-                                size: 0,
-                            };
+                    }
+                    Some((super::PING, buffer)) => {
+                        let pong = Header {
+                            kind: super::PONG,
+                            size: 0,
+                        };
+
+                        // Only the ping a client sends when it connects
+                        // carries a payload, its hello
+                        let reply = if buffer.is_empty() {
+                            pong.as_bytes().to_vec()
+                        } else {
+                            Self::handshake(&buffer)
+                        };
+
+                        if let Err(e) = clients[pos].socket.send(&reply) {
+                            log::error!("failed to send PONG: {}", e);
+
+                            Some(clients.swap_remove(pos))
+                        } else if Self::identify(&mut clients[pos].info, &buffer) {
+                            log::debug!("client identified as {:?}", clients[pos].info);
+
+                            if handler.on_client_identified(&clients[pos].info) == LoopAction::Exit
+                            {
+                                log::debug!("on_client_identified exited message loop");
+                                return Ok(LoopAction::Exit);
+                            }
+
+                            None
+                        } else {
+                            None
+                        }
+                    }
+                    Some((super::PONG, _buffer)) => None,
+                    Some((super::GOODBYE, _buffer)) => {
+                        #[cfg(any(target_os = "linux", target_os = "android"))]
+                        if let Some(process) = &mut clients[pos].process {
+                            process.exit = Some(crate::ClientExit::Clean);
+                        }
+                        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                        {
+                            clients[pos].exit = Some(crate::ClientExit::Clean);
+                        }
 
-                            if let Err(e) = clients[pos].socket.send(pong.as_bytes()) {
-                                log::error!("failed to send PONG: {}", e);
+                        None
+                    }
+                    #[cfg(target_os = "macos")]
+                    Some((super::LIVE_DUMP, buffer)) => {
+                        // The dump is written once the task port is
+                        // received on the mach port, see `check_mach_port`
+                        match Self::register_live_dump(&mut clients[pos], &buffer) {
+                            Ok(()) => {
+                                let ack = Header {
+                                    kind: super::LIVE_DUMP_ACK,
+                                    size: 0,
+                                };
+
+                                if let Err(e) = clients[pos].socket.send(ack.as_bytes()) {
+                                    log::error!("failed to send ack: {}", e);
+                                }
 
-                                let cc = clients.swap_remove(pos);
-                                Some(cc.socket)
-                            } else {
                                 None
                             }
+                            Err(err) => {
+                                log::error!("invalid live dump request: {}", err);
+                                Some(clients.swap_remove(pos))
+                            }
                         }
-                        Some((super::PONG, _buffer)) => None,
-                        Some((kind, buffer)) => {
-                            handler.on_message(
-                                kind - super::USER, /* give the user back the original code they specified */
-                                buffer,
-                            );
-
-                            // We only send acks for crash dump requests
-                            // if let Err(e) = clients[pos].socket.send(&[1]) {
-                            //     log::error!("failed to send ack: {}", e);
-                            // }
+                    }
+                    #[cfg(not(target_os = "macos"))]
+                    Some((super::LIVE_DUMP, buffer)) => {
+                        let action =
+                            match Self::handle_live_dump_request(&clients[pos], &buffer, handler) {
+                                Err(err) => {
+                                    log::error!("failed to capture live minidump: {}", err);
+                                    LoopAction::Continue
+                                }
+                                Ok(action) => {
+                                    log::info!("captured live minidump");
+                                    action
+                                }
+                            };
 
-                            None
-                        }
-                        None => {
-                            log::debug!("client closed socket {}", pos);
-                            let cc = clients.swap_remove(pos);
-                            Some(cc.socket)
-                        }
-                    };
+                        let ack = Header {
+                            kind: super::LIVE_DUMP_ACK,
+                            size: 0,
+                        };
 
-                    if let Some(socket) = deregister {
-                        if let Err(e) = poll.delete(&socket) {
-                            log::error!("failed to deregister socket: {}", e);
+                        if let Err(e) = clients[pos].socket.send(ack.as_bytes()) {
+                            log::error!("failed to send ack: {}", e);
                         }
 
-                        if handler.on_client_disconnected(clients.len()) == LoopAction::Exit {
-                            log::debug!("on_client_disconnected exited message loop");
-                            return Ok(());
+                        if action == LoopAction::Exit {
+                            log::debug!("user handler requested exit after live minidump creation");
+                            return Ok(LoopAction::Exit);
                         }
-                    } else {
-                        poll.modify(&clients[pos].socket, Event::readable(clients[pos].key))?;
+
+                        None
                     }
-                }
-            }
+                    Some((kind, buffer)) if (super::USER..super::HELLO).contains(&kind) => {
+                        handler.on_message_from(
+                            &clients[pos].info,
+                            kind - super::USER, /* give the user back the original code they specified */
+                            buffer,
+                        );
+
+                        // We only send acks for crash dump requests
+                        // if let Err(e) = clients[pos].socket.send(&[1]) {
+                        //     log::error!("failed to send ack: {}", e);
+                        // }
+
+                        None
+                    }
+                    Some((kind, _buffer)) => {
+                        // Only the server sends the other kinds
+                        log::warn!("ignoring unexpected message kind {}", kind);
+                        None
+                    }
+                    None => {
+                        log::debug!("client closed socket {}", pos);
+                        Some(clients.swap_remove(pos))
+                    }
+                };
 
-            if let Some(st) = stale_timeout {
-                let before = clients.len();
+                if let Some(cc) = deregister {
+                    if let Err(e) = poll.delete(&cc.socket) {
+                        log::error!("failed to deregister socket: {}", e);
+                    }
 
-                // Reap any connections that haven't sent a message in the period
-                // specified by the user
-                clients.retain(|conn| {
-                    let keep = conn.last_update.elapsed() < st;
+                    if handler.on_client_disconnected_from(&cc.info, clients.len())
+                        == LoopAction::Exit
+                    {
+                        log::debug!("on_client_disconnected exited message loop");
+                        return Ok(LoopAction::Exit);
+                    }
 
-                    if !keep {
-                        log::debug!("dropping stale connection {:?}", conn.last_update.elapsed());
-                        if let Err(e) = poll.delete(&conn.socket) {
-                            log::error!("failed to deregister timed-out socket: {}", e);
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    if let Some(process) = cc.process {
+                        if process.disconnected(poll, disconnected, handler) == LoopAction::Exit {
+                            log::debug!("client exit exited message loop");
+                            return Ok(LoopAction::Exit);
                         }
                     }
+                    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                    if cc.exited(handler) == LoopAction::Exit {
+                        log::debug!("client exit exited message loop");
+                        return Ok(LoopAction::Exit);
+                    }
+                } else {
+                    poll.modify(&clients[pos].socket, Event::readable(clients[pos].key))?;
+                }
+            }
+        }
 
-                    keep
-                });
+        if let Some(st) = stale_timeout {
+            // Reap any connections that haven't sent a message in the period
+            // specified by the user
+            let (stale, fresh): (Vec<_>, Vec<_>) = clients
+                .drain(..)
+                .partition(|conn| conn.last_update.elapsed() >= st);
+            *clients = fresh;
+
+            let mut remaining = stale.len();
+            for conn in stale {
+                remaining -= 1;
+
+                log::debug!("dropping stale connection {:?}", conn.last_update.elapsed());
+                if let Err(e) = poll.delete(&conn.socket) {
+                    log::error!("failed to deregister timed-out socket: {}", e);
+                }
 
-                if before > clients.len()
-                    && handler.on_client_disconnected(clients.len()) == LoopAction::Exit
+                // The stale connections that haven't been reported yet
+                // are still counted as connected
+                if handler.on_client_disconnected_from(&conn.info, clients.len() + remaining)
+                    == LoopAction::Exit
                 {
                     log::debug!("on_client_disconnected exited message loop");
-                    return Ok(());
+                    return Ok(LoopAction::Exit);
+                }
+
+                #[cfg(any(target_os = "linux", target_os = "android"))]
+                if let Some(process) = conn.process {
+                    if process.disconnected(poll, disconnected, handler) == LoopAction::Exit {
+                        log::debug!("client exit exited message loop");
+                        return Ok(LoopAction::Exit);
+                    }
+                }
+                #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                if conn.exited(handler) == LoopAction::Exit {
+                    log::debug!("client exit exited message loop");
+                    return Ok(LoopAction::Exit);
                 }
             }
         }
+
+        Ok(LoopAction::Continue)
+    }
+
+    /// Checks if the event is for the pidfd of a client process, reporting
+    /// how it exited if so
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn check_exited(
+        key: usize,
+        poll: &Poller,
+        clients: &mut Vec<ClientConn>,
+        disconnected: &mut Vec<ClientProcess>,
+        handler: &dyn crate::ServerHandler,
+    ) -> Option<LoopAction> {
+        let is_pidfd = |process: &ClientProcess| process.key == key && process.pidfd != -1;
+
+        let process = if let Some(pos) = disconnected.iter().position(is_pidfd) {
+            disconnected.swap_remove(pos)
+        } else {
+            let pos = clients
+                .iter()
+                .position(|cc| cc.process.as_ref().is_some_and(is_pidfd))?;
+
+            // The process has exited, but its socket is still open, eg.
+            // because it was inherited by a child process
+            let mut cc = clients.swap_remove(pos);
+            if let Err(e) = poll.delete(&cc.socket) {
+                log::error!("failed to deregister socket: {}", e);
+            }
+
+            let process = cc.process.take()?;
+            if handler.on_client_disconnected_from(&cc.info, clients.len()) == LoopAction::Exit {
+                return Some(LoopAction::Exit);
+            }
+
+            process
+        };
+
+        Some(process.exited(poll, handler))
+    }
+
+    /// Replies to the [`super::Hello`] of a client that just connected with
+    /// our own, rejecting the client if it can't talk to us
+    fn handshake(buffer: &[u8]) -> Vec<u8> {
+        use scroll::{Pread, Pwrite};
+
+        let mut hello = super::Hello::current();
+        match buffer.pread::<super::Hello>(0) {
+            Ok(client) if hello.is_compatible(&client) => {
+                log::debug!(
+                    "client speaks protocol version {}, we speak {}",
+                    client.version,
+                    hello.version
+                );
+            }
+            Ok(client) => {
+                log::warn!(
+                    "rejecting client for architecture {} with {} bit pointers",
+                    client.arch,
+                    client.pointer_width
+                );
+                hello.accepted = 0;
+            }
+            Err(err) => {
+                log::warn!("rejecting client with invalid hello: {}", err);
+                hello.accepted = 0;
+            }
+        }
+
+        let header = Header {
+            kind: super::HELLO,
+            size: super::HELLO_LEN as u32,
+        };
+
+        let mut reply = header.as_bytes().to_vec();
+        reply.resize(reply.len() + super::HELLO_LEN, 0);
+        let hello_start = reply.len() - super::HELLO_LEN;
+        // The buffer is exactly large enough
+        let _written = reply[hello_start..].pwrite(hello, 0);
+
+        reply
+    }
+
+    /// Records the [`super::Identity`] that follows the [`super::Hello`] of a
+    /// client, returning false if there is none, ie. the client predates it
+    fn identify(client: &mut crate::ClientInfo, buffer: &[u8]) -> bool {
+        use scroll::Pread;
+
+        let Some(rest) = buffer.get(super::HELLO_LEN..) else {
+            return false;
+        };
+
+        let offset = &mut 0;
+        let Ok(identity) = rest.gread::<super::Identity>(offset) else {
+            return false;
+        };
+
+        let rest = &rest[*offset..];
+        let (name, executable) = rest.split_at((identity.name_len as usize).min(rest.len()));
+
+        // On Linux/Android the pid is already known from the credentials of
+        // the socket, which unlike the identity can't be made up
+        match client.pid {
+            Some(pid) if pid != identity.process_id => {
+                log::warn!(
+                    "client {} claims to be process {}",
+                    pid,
+                    identity.process_id
+                );
+            }
+            Some(_) => {}
+            None => client.pid = Some(identity.process_id),
+        }
+
+        client.name = (!name.is_empty()).then(|| String::from_utf8_lossy(name).into_owned());
+        client.executable = (!executable.is_empty())
+            .then(|| String::from_utf8_lossy(executable).into_owned().into());
+
+        true
     }
 
     fn handle_crash_request(
         crash_context: crash_context::CrashContext,
+        pid: u32,
+        client: &crate::ClientInfo,
         handler: &dyn crate::ServerHandler,
     ) -> Result<LoopAction, Error> {
-        let (mut minidump_file, minidump_path) = handler.create_minidump_file()?;
+        let info = crate::storage::DumpInfo {
+            pid,
+            kind: crate::storage::DumpKind::Crash,
+            reason: crash_reason(&crash_context),
+            client: client.clone(),
+        };
+        let (mut minidump_file, minidump_path) = handler.create_minidump_file_for(&info)?;
 
         cfg_if::cfg_if! {
             if #[cfg(any(target_os = "linux", target_os = "android"))] {
-                let mut writer =
-                    minidump_writer::minidump_writer::MinidumpWriter::new(crash_context.pid, crash_context.tid);
-                writer.set_crash_context(minidump_writer::crash_context::CrashContext { inner: crash_context });
+                let result = crate::ptrace_dumper::write_minidump(crash_context, &mut minidump_file);
             } else if #[cfg(target_os = "windows")] {
-                #[allow(unsafe_code)]
-                // SAFETY: Unfortunately this is a bit dangerous since we are relying on the crashing process
-                // to still be alive and still have the interior pointers in the crash context still at the
-                // same location in memory, unfortunately it's a bit hard to communicate this through so
-                // many layers, so really, we are falling back on Windows to actually correctly handle
-                // if the interior pointers have become invalid which it should? do ok with
-                let result = unsafe {
-                    minidump_writer::minidump_writer::MinidumpWriter::dump_crash_context(crash_context, &mut minidump_file)
-                };
+                // The crashing process is blocked until we acknowledge the
+                // request, so the exception pointers it sent are still valid
+                let result = crate::windows_dumper::write_minidump(
+                    &crash_context,
+                    &mut minidump_file,
+                    handler.minidump_type(&info),
+                );
             } else if #[cfg(target_os = "macos")] {
                 let mut writer = minidump_writer::minidump_writer::MinidumpWriter::with_crash_context(crash_context);
             }
         }
 
-        #[cfg(not(target_os = "windows"))]
+        #[cfg(target_os = "macos")]
         let result = writer.dump(&mut minidump_file);
 
         // Notify the user handler about the minidump, even if we failed to write it
+        // Linux/Android already produce our own error type
+        #[allow(clippy::useless_conversion)]
         Ok(handler.on_minidump_created(
             result
                 .map(|_contents| crate::MinidumpBinary {
@@ -419,36 +1035,192 @@ impl Server {
         ))
     }
 
+    /// Writes a minidump of a client process that is still running, unlike
+    /// [`Self::handle_crash_request`] the client stays connected
+    #[cfg(not(target_os = "macos"))]
+    #[cfg_attr(target_os = "windows", allow(unused_variables))]
+    fn handle_live_dump_request(
+        conn: &ClientConn,
+        buffer: &[u8],
+        handler: &dyn crate::ServerHandler,
+    ) -> Result<LoopAction, Error> {
+        use scroll::Pread;
+
+        let offset = &mut 0;
+        let request: super::LiveDumpRequest = buffer.gread(offset)?;
+        let reason = String::from_utf8_lossy(&buffer[*offset..]);
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let peer_creds = conn.socket.initial_peer_credentials()?;
+                let pid = peer_creds.pid().ok_or(Error::UnknownClientPid)?;
+
+                // Validate that the request and the socket agree on the pid
+                if pid.get() != request.process_id {
+                    return Err(Error::UnknownClientPid);
+                }
+            }
+        }
+
+        let info = crate::storage::DumpInfo {
+            pid: request.process_id,
+            kind: crate::storage::DumpKind::Live,
+            reason: reason.clone().into_owned(),
+            client: conn.info.clone(),
+        };
+        let (mut minidump_file, minidump_path) = handler.create_minidump_file_for(&info)?;
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let result = crate::ptrace_dumper::write_minidump_for_process(
+                    request.process_id as i32,
+                    request.thread_id as i32,
+                    &mut minidump_file,
+                );
+            } else if #[cfg(target_os = "windows")] {
+                // Without exception pointers, the context of every thread is
+                // retrieved from the process itself
+                let crash_context = crash_context::CrashContext {
+                    exception_pointers: std::ptr::null(),
+                    process_id: request.process_id,
+                    thread_id: request.thread_id,
+                    exception_code: 0,
+                    reason: crash_context::CrashReason::Exception,
+                };
+
+                let result = crate::windows_dumper::write_minidump(
+                    &crash_context,
+                    &mut minidump_file,
+                    handler.minidump_type(&info),
+                );
+            }
+        }
+
+        #[allow(clippy::useless_conversion)]
+        Ok(handler.on_live_dump_created(
+            &reason,
+            result
+                .map(|_contents| crate::MinidumpBinary {
+                    file: minidump_file,
+                    path: minidump_path,
+                    #[cfg(target_os = "windows")]
+                    contents: None,
+                    #[cfg(not(target_os = "windows"))]
+                    contents: Some(_contents),
+                })
+                .map_err(crate::Error::from),
+        ))
+    }
+
+    /// Records the live dump requested by a client, which is written once its
+    /// task port is received, see [`Self::handle_live_dump_context`]
+    #[cfg(target_os = "macos")]
+    fn register_live_dump(conn: &mut ClientConn, buffer: &[u8]) -> Result<(), Error> {
+        use scroll::Pread;
+
+        let offset = &mut 0;
+        let request: super::LiveDumpRequest = buffer.gread(offset)?;
+
+        // The pid was already sent when the client connected
+        if conn.pid != Some(request.process_id) {
+            return Err(Error::UnknownClientPid);
+        }
+
+        conn.live_dump = Some(String::from_utf8_lossy(&buffer[*offset..]).into_owned());
+        Ok(())
+    }
+
+    /// Writes a minidump of a client process that is still running, with the
+    /// task port the client sent after requesting it
+    #[cfg(target_os = "macos")]
+    fn handle_live_dump_context(
+        crash_context: crash_context::CrashContext,
+        pid: u32,
+        reason: String,
+        client: &crate::ClientInfo,
+        handler: &dyn crate::ServerHandler,
+    ) -> Result<LoopAction, Error> {
+        let info = crate::storage::DumpInfo {
+            pid,
+            kind: crate::storage::DumpKind::Live,
+            reason,
+            client: client.clone(),
+        };
+        let (mut minidump_file, minidump_path) = handler.create_minidump_file_for(&info)?;
+
+        let mut writer =
+            minidump_writer::minidump_writer::MinidumpWriter::with_crash_context(crash_context);
+        let result = writer.dump(&mut minidump_file);
+
+        Ok(handler.on_live_dump_created(
+            &info.reason,
+            result
+                .map(|contents| crate::MinidumpBinary {
+                    file: minidump_file,
+                    path: minidump_path,
+                    contents: Some(contents),
+                })
+                .map_err(crate::Error::from),
+        ))
+    }
+
     #[cfg(target_os = "macos")]
     fn check_mach_port(
-        &mut self,
+        port: &mut crash_context::ipc::Server,
         poll: &Poller,
         clients: &mut Vec<ClientConn>,
         handler: &dyn crate::ServerHandler,
     ) -> Result<LoopAction, Error> {
         // We use a really short timeout for receiving on the mach port since we check it
         // frequently rather than spawning a separate thread and blocking
-        if let Some(mut rcc) = self
-            .port
-            .try_recv_crash_context(Some(Duration::from_millis(1)))?
-        {
+        if let Some(mut rcc) = port.try_recv_crash_context(Some(Duration::from_millis(1)))? {
             // Try to find a client connection that matches the port sender
             let pos = clients
                 .iter()
                 .position(|cc| cc.pid == Some(rcc.pid))
                 .ok_or(Error::UnknownClientPid)?;
-            let cc = clients.swap_remove(pos);
 
-            let action = match Self::handle_crash_request(rcc.crash_context, handler) {
-                Err(err) => {
-                    log::error!("failed to capture minidump: {}", err);
-                    LoopAction::Continue
-                }
-                Ok(action) => {
-                    log::info!("captured minidump");
-                    action
+            // The client requested a live dump rather than crashed, so it
+            // stays connected
+            if let Some(reason) = clients[pos].live_dump.take() {
+                let action = match Self::handle_live_dump_context(
+                    rcc.crash_context,
+                    rcc.pid,
+                    reason,
+                    &clients[pos].info,
+                    handler,
+                ) {
+                    Err(err) => {
+                        log::error!("failed to capture live minidump: {}", err);
+                        LoopAction::Continue
+                    }
+                    Ok(action) => {
+                        log::info!("captured live minidump");
+                        action
+                    }
+                };
+
+                if let Err(e) = rcc.acker.send_ack(1, Some(Duration::from_secs(2))) {
+                    log::error!("failed to send ack: {}", e);
                 }
-            };
+
+                return Ok(action);
+            }
+
+            let mut cc = clients.swap_remove(pos);
+            cc.exit = Some(crate::ClientExit::Crashed);
+
+            let action =
+                match Self::handle_crash_request(rcc.crash_context, rcc.pid, &cc.info, handler) {
+                    Err(err) => {
+                        log::error!("failed to capture minidump: {}", err);
+                        LoopAction::Continue
+                    }
+                    Ok(action) => {
+                        log::info!("captured minidump");
+                        action
+                    }
+                };
 
             if let Err(e) = rcc.acker.send_ack(1, Some(Duration::from_secs(2))) {
                 log::error!("failed to send ack: {}", e);
@@ -458,13 +1230,64 @@ impl Server {
                 log::error!("failed to deregister socket: {}", e);
             }
 
-            Ok(action)
+            if action == LoopAction::Exit
+                || handler.on_client_disconnected_from(&cc.info, clients.len()) == LoopAction::Exit
+            {
+                return Ok(LoopAction::Exit);
+            }
+
+            Ok(cc.exited(handler))
         } else {
             Ok(LoopAction::Continue)
         }
     }
 }
 
+/// A short description of a crash, see [`crate::storage::DumpInfo::reason`]
+pub(super) fn crash_reason(crash_context: &crash_context::CrashContext) -> String {
+    cfg_if::cfg_if! {
+        if #[cfg(any(target_os = "linux", target_os = "android"))] {
+            use crash_context::CrashReason;
+
+            let reason = match crash_context.reason {
+                CrashReason::StackOverflow => "stack-overflow",
+                CrashReason::Panic => "panic",
+                CrashReason::MemoryPressure => "memory-pressure",
+                CrashReason::AllocationFailure => "allocation-failure",
+                CrashReason::Hang => "hang",
+                CrashReason::DumpRequest => "dump-request",
+                _ => match crash_context.siginfo.ssi_signo as i32 {
+                    libc::SIGABRT => "SIGABRT",
+                    libc::SIGBUS => "SIGBUS",
+                    libc::SIGFPE => "SIGFPE",
+                    libc::SIGILL => "SIGILL",
+                    libc::SIGSEGV => "SIGSEGV",
+                    libc::SIGSYS => "SIGSYS",
+                    libc::SIGTRAP => "SIGTRAP",
+                    signo => return format!("signal-{signo}"),
+                },
+            };
+
+            reason.to_owned()
+        } else if #[cfg(target_os = "windows")] {
+            format!("exception-{:08x}", crash_context.exception_code as u32)
+        } else if #[cfg(target_os = "macos")] {
+            crash_context
+                .exception
+                .map_or_else(String::new, |exc| format!("exception-{}", exc.kind))
+        }
+    }
+}
+
+/// The file descriptor of the event loop of the server, which is readable
+/// whenever [`Server::poll_once`] has work to do
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl std::os::unix::io::AsRawFd for Server {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.poll.as_raw_fd()
+    }
+}
+
 impl Drop for Server {
     fn drop(&mut self) {
         let _ = self.listener.take();
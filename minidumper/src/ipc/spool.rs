@@ -0,0 +1,200 @@
+//! Crashes that a [`crate::Client`] couldn't send to the server, eg. because
+//! the server was being restarted at the moment of the crash.
+//!
+//! If a spool directory is set via [`crate::Client::set_spool_dir`], the
+//! crash context is written to a file in it instead, which can be retrieved
+//! with [`pending`] once the server is running again, eg. when it starts.
+//! As the process that crashed is gone by then, a minidump can't be written
+//! for it, but the crash can still be reported with the details in its
+//! context, eg. the signal or exception and the crashing thread.
+//!
+//! ```no_run
+//! for crash in minidumper::spool::pending("/var/crashes/spool").unwrap() {
+//!     println!("process {} crashed: {}", crash.pid, crash.reason());
+//!     std::fs::remove_file(&crash.path).unwrap();
+//! }
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Identifies a spool file, followed by the payload of the crash request
+/// the client couldn't send
+const MAGIC: &[u8; 8] = b"MDSPOOL1";
+
+/// The extension of spool files
+const EXTENSION: &str = "crash";
+
+/// Where a client writes its crash if it can't be sent, the path being built
+/// up front as nothing can be allocated while crashing
+pub(crate) struct Spool {
+    #[cfg(unix)]
+    path: std::ffi::CString,
+    #[cfg(windows)]
+    path: PathBuf,
+}
+
+impl Spool {
+    pub(crate) fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+
+        let started = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = dir.join(format!("{}-{started}.{EXTENSION}", std::process::id()));
+
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                use std::os::unix::ffi::OsStrExt;
+
+                let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+            }
+        }
+
+        Ok(Self { path })
+    }
+
+    /// Writes the payload of the crash request, only using async signal safe
+    /// functions on unix
+    pub(crate) fn write(&self, payload: &[u8]) -> io::Result<()> {
+        cfg_if::cfg_if! {
+            if #[cfg(unix)] {
+                #[allow(unsafe_code)]
+                // SAFETY: syscall
+                let fd = unsafe {
+                    libc::open(
+                        self.path.as_ptr(),
+                        libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_CLOEXEC,
+                        0o600,
+                    )
+                };
+                if fd == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let mut written = true;
+                for part in [&MAGIC[..], payload] {
+                    #[allow(unsafe_code)]
+                    // SAFETY: syscall, the buffer is valid for its length
+                    let len = unsafe { libc::write(fd, part.as_ptr().cast(), part.len()) };
+                    written &= len == part.len() as isize;
+                }
+
+                #[allow(unsafe_code)]
+                // SAFETY: syscall, we own the descriptor
+                unsafe {
+                    libc::close(fd);
+                }
+
+                if !written {
+                    return Err(io::Error::from(io::ErrorKind::WriteZero));
+                }
+
+                Ok(())
+            } else {
+                use std::io::Write;
+
+                let mut file = fs::File::create(&self.path)?;
+                file.write_all(MAGIC)?;
+                file.write_all(payload)
+            }
+        }
+    }
+}
+
+/// A crash a client couldn't send to the server, see the [module level docs](self)
+pub struct SpooledCrash {
+    /// The spool file, which should be removed once the crash is reported
+    pub path: PathBuf,
+    /// The pid of the process that crashed
+    pub pid: u32,
+    /// The context of the crash. On Windows, the exception pointers are null,
+    /// as they pointed into the process that crashed.
+    pub crash_context: crash_context::CrashContext,
+}
+
+impl SpooledCrash {
+    /// A short description of the crash, see [`crate::storage::DumpInfo::reason`]
+    pub fn reason(&self) -> String {
+        super::server::crash_reason(&self.crash_context)
+    }
+
+    fn read(path: PathBuf) -> io::Result<Self> {
+        let contents = fs::read(&path)?;
+        let payload = contents
+            .strip_prefix(&MAGIC[..])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a spool file"))?;
+
+        cfg_if::cfg_if! {
+            if #[cfg(any(target_os = "linux", target_os = "android"))] {
+                let crash_context = crash_context::CrashContext::deserialize(payload)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let pid = crash_context.pid as u32;
+            } else if #[cfg(target_os = "windows")] {
+                use scroll::Pread;
+
+                let request: super::DumpRequest = payload
+                    .pread(0)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let crash_context = crash_context::CrashContext {
+                    exception_pointers: std::ptr::null(),
+                    process_id: request.process_id,
+                    thread_id: request.thread_id,
+                    exception_code: request.exception_code,
+                    reason: crash_context::CrashReason::from_exception(
+                        request.exception_code,
+                        (request.has_fast_fail_code != 0).then_some(request.fast_fail_code),
+                    ),
+                };
+                let pid = request.process_id;
+            }
+        }
+
+        Ok(Self {
+            path,
+            pid,
+            crash_context,
+        })
+    }
+}
+
+/// Retrieves every crash that was written to the spool directory, oldest
+/// first. Files that aren't valid spool files are skipped.
+///
+/// # Errors
+///
+/// The directory could not be read, it not existing is not an error
+pub fn pending(dir: impl AsRef<Path>) -> io::Result<Vec<SpooledCrash>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    let mut crashes = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(std::time::SystemTime::UNIX_EPOCH);
+
+        match SpooledCrash::read(path) {
+            Ok(crash) => crashes.push((modified, crash)),
+            Err(err) => log::warn!("skipping invalid spool file: {}", err),
+        }
+    }
+
+    crashes.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.path.cmp(&b.1.path)));
+    Ok(crashes
+        .into_iter()
+        .map(|(_modified, crash)| crash)
+        .collect())
+}
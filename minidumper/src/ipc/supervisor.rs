@@ -0,0 +1,422 @@
+//! Spawning and monitoring a child process for crashes, Linux/Android only.
+//!
+//! Monitoring a process out of process normally means picking a socket name,
+//! running a [`crate::Server`] on it, passing the name to the child so it can
+//! connect a [`crate::Client`], and separately waiting on the child to find
+//! out how it exited. A [`Supervisor`] packages all of that: it spawns a
+//! [`Command`] with one end of an already connected socket pair inherited by
+//! the child, which it retrieves with [`crate::Client::from_env`], and opens a
+//! [pidfd](https://man7.org/linux/man-pages/man2/pidfd_open.2.html) for the
+//! child, so that crashes, messages, and the child exiting are all surfaced as
+//! [`Event`]s from a single place.
+//!
+//! The events can be consumed by iterating the [`Supervisor`], which blocks
+//! until the next event, or from an async runtime by waiting for the file
+//! descriptor returned by [`Supervisor::as_raw_fd`] to become readable and
+//! then calling [`Supervisor::next_event`] with a zero timeout.
+
+use super::{Connection, Header};
+use crate::{Error, MinidumpBinary};
+use polling::{Event as PollEvent, Poller};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    os::unix::{
+        io::{AsRawFd, FromRawFd, IntoRawFd, RawFd},
+        process::{CommandExt, ExitStatusExt},
+    },
+    path::PathBuf,
+    process::{Child, Command, ExitStatus},
+    time::Duration,
+};
+
+/// The environment variable the number of the inherited socket's file
+/// descriptor is passed to the child in
+pub const SOCKET_FD_ENV: &str = "MINIDUMPER_SOCKET_FD";
+
+/// Creates the file a minidump is written to, and returns it along with its path
+pub type CreateMinidumpFile = Box<dyn FnMut() -> Result<(File, PathBuf), std::io::Error> + Send>;
+
+const SOCKET_KEY: usize = 0;
+const PIDFD_KEY: usize = 1;
+
+/// Something that happened to a supervised child process
+pub enum Event {
+    /// The child crashed and requested a minidump, which was written, or
+    /// failed to be written, before the child was allowed to continue
+    /// terminating.
+    Crashed {
+        /// The context the child's crash handler sent
+        context: Box<crash_context::CrashContext>,
+        /// The minidump of the crash
+        minidump: Result<MinidumpBinary, Error>,
+    },
+    /// The child requested a minidump via [`crate::Client::request_live_dump`]
+    /// and is still running
+    LiveDump {
+        /// The reason the child gave for requesting the dump
+        reason: String,
+        /// The minidump of the child
+        minidump: Result<MinidumpBinary, Error>,
+    },
+    /// The child sent a message via [`crate::Client::send_message`]
+    Message {
+        /// The kind the child specified
+        kind: u32,
+        /// The contents of the message
+        buffer: Vec<u8>,
+    },
+    /// The child was killed by the OOM killer, as recorded in the kernel log.
+    ///
+    /// As the OOM killer uses `SIGKILL`, the child's crash handler never
+    /// runs, so there is no preceding [`Event::Crashed`].
+    OomKilled,
+    /// The child exited for any other reason, including after a crash. This
+    /// is always the last event.
+    Exited(ExitStatus),
+}
+
+/// Spawns and monitors a child process, see the [module level docs](self)
+pub struct Supervisor {
+    child: Child,
+    pidfd: RawFd,
+    socket: Option<Connection>,
+    poller: Poller,
+    create_file: CreateMinidumpFile,
+    pending: VecDeque<Event>,
+    exited: bool,
+}
+
+impl Supervisor {
+    /// Spawns the command with a socket connected to the supervisor, which
+    /// the child process can connect a [`crate::Client`] to via
+    /// [`crate::Client::from_env`]. Minidumps requested by the child are
+    /// written to the files returned by `create_file`.
+    ///
+    /// # Errors
+    ///
+    /// The socket pair or pidfd couldn't be created, eg. because the kernel
+    /// is older than 5.3, or the command failed to spawn.
+    pub fn spawn(cmd: &mut Command, create_file: CreateMinidumpFile) -> Result<Self, Error> {
+        let (parent, child_end) = uds::UnixSeqpacketConn::pair()?;
+        parent.set_nonblocking(true)?;
+
+        let child_fd = child_end.as_raw_fd();
+        cmd.env(SOCKET_FD_ENV, child_fd.to_string());
+
+        #[allow(unsafe_code)]
+        // SAFETY: fcntl is async signal safe, and only affects the child
+        unsafe {
+            cmd.pre_exec(move || {
+                // The pair is created close-on-exec, so it needs to be cleared
+                // for the child to inherit its end
+                if libc::fcntl(child_fd, libc::F_SETFD, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let child = cmd.spawn()?;
+        drop(child_end);
+
+        #[allow(unsafe_code)]
+        // SAFETY: syscall, the child has not been reaped so its pid is valid
+        let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, child.id(), 0) } as RawFd;
+        if pidfd == -1 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        #[allow(unsafe_code)]
+        // SAFETY: the fd is owned by the blocking connection we're converting
+        let socket = unsafe { Connection::from_raw_fd(parent.into_raw_fd()) };
+
+        let poller = Poller::new()?;
+        poller.add(&socket, PollEvent::readable(SOCKET_KEY))?;
+        poller.add(pidfd, PollEvent::readable(PIDFD_KEY))?;
+
+        Ok(Self {
+            child,
+            pidfd,
+            socket: Some(socket),
+            poller,
+            create_file,
+            pending: VecDeque::new(),
+            exited: false,
+        })
+    }
+
+    /// The process id of the child
+    #[inline]
+    pub fn id(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Waits up to the specified timeout, or indefinitely if `None`, for the
+    /// next event, returning `None` if the timeout elapsed, or if the child
+    /// has already exited and every event has been returned.
+    ///
+    /// # Errors
+    ///
+    /// Waiting on the socket or pidfd failed
+    pub fn next_event(&mut self, timeout: Option<Duration>) -> Result<Option<Event>, Error> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+
+            if self.exited {
+                return Ok(None);
+            }
+
+            let mut events = Vec::new();
+            self.poller.wait(&mut events, timeout)?;
+
+            if events.is_empty() && timeout.is_some() {
+                return Ok(None);
+            }
+
+            // The socket is always drained before reaping, so that eg. a
+            // crash is reported before the exit it causes
+            if events.iter().any(|ev| ev.key == SOCKET_KEY) {
+                self.drain_socket();
+            }
+
+            if events.iter().any(|ev| ev.key == PIDFD_KEY) {
+                self.drain_socket();
+                self.reap()?;
+            }
+        }
+    }
+
+    /// Receives every message currently queued on the socket
+    fn drain_socket(&mut self) {
+        loop {
+            let Some(socket) = &self.socket else {
+                return;
+            };
+
+            match recv(socket) {
+                Ok(Some((kind, buffer))) => self.handle_message(kind, buffer),
+                Ok(None) => {
+                    log::debug!("child closed socket");
+                    if let Some(socket) = self.socket.take() {
+                        if let Err(e) = self.poller.delete(&socket) {
+                            log::error!("failed to deregister socket: {}", e);
+                        }
+                    }
+                    return;
+                }
+                Err(_would_block) => break,
+            }
+        }
+
+        if let Some(socket) = &self.socket {
+            if let Err(e) = self.poller.modify(socket, PollEvent::readable(SOCKET_KEY)) {
+                log::error!("failed to reregister socket: {}", e);
+            }
+        }
+    }
+
+    fn handle_message(&mut self, kind: u32, buffer: Vec<u8>) {
+        match kind {
+            super::CRASH => {
+                let event = match crash_context::CrashContext::deserialize(&buffer) {
+                    Ok(context) if context.pid as u32 == self.child.id() => {
+                        let minidump = self.write_minidump(|file| {
+                            crate::ptrace_dumper::write_minidump(context.clone(), file)
+                        });
+
+                        Some(Event::Crashed {
+                            context: Box::new(context),
+                            minidump,
+                        })
+                    }
+                    Ok(_context) => {
+                        log::error!("crash request is not for the supervised child");
+                        None
+                    }
+                    Err(e) => {
+                        log::error!("failed to deserialize crash context: {}", e);
+                        None
+                    }
+                };
+
+                self.pending.extend(event);
+                self.send(super::CRASH_ACK);
+            }
+            super::LIVE_DUMP => {
+                use scroll::Pread;
+
+                let offset = &mut 0;
+                match buffer.gread::<super::LiveDumpRequest>(offset) {
+                    Ok(request) if request.process_id == self.child.id() => {
+                        let minidump = self.write_minidump(|file| {
+                            crate::ptrace_dumper::write_minidump_for_process(
+                                request.process_id as i32,
+                                request.thread_id as i32,
+                                file,
+                            )
+                        });
+
+                        self.pending.push_back(Event::LiveDump {
+                            reason: String::from_utf8_lossy(&buffer[*offset..]).into_owned(),
+                            minidump,
+                        });
+                    }
+                    Ok(_request) => {
+                        log::error!("live dump request is not for the supervised child");
+                    }
+                    Err(e) => log::error!("failed to read live dump request: {}", e),
+                }
+
+                self.send(super::LIVE_DUMP_ACK);
+            }
+            super::PING => self.send(super::PONG),
+            super::PONG | super::GOODBYE => {}
+            kind => self.pending.push_back(Event::Message {
+                kind: kind - super::USER,
+                buffer,
+            }),
+        }
+    }
+
+    fn write_minidump(
+        &mut self,
+        write: impl FnOnce(&mut File) -> Result<Vec<u8>, Error>,
+    ) -> Result<MinidumpBinary, Error> {
+        let (mut file, path) = (self.create_file)()?;
+        let contents = write(&mut file)?;
+
+        Ok(MinidumpBinary {
+            file,
+            path,
+            contents: Some(contents),
+        })
+    }
+
+    fn send(&self, kind: u32) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        if let Err(e) = socket.send(Header { kind, size: 0 }.as_bytes()) {
+            log::error!("failed to send {}: {}", kind, e);
+        }
+    }
+
+    /// Reaps the child once its pidfd is readable, which only happens once it
+    /// has exited
+    fn reap(&mut self) -> Result<(), Error> {
+        let Some(status) = self.child.try_wait()? else {
+            self.poller
+                .modify(self.pidfd, PollEvent::readable(PIDFD_KEY))?;
+            return Ok(());
+        };
+
+        self.exited = true;
+
+        if status.signal() == Some(libc::SIGKILL) && was_oom_killed(self.child.id()) {
+            self.pending.push_back(Event::OomKilled);
+        } else {
+            self.pending.push_back(Event::Exited(status));
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for Supervisor {
+    type Item = Result<Event, Error>;
+
+    /// Blocks until the next event, ending once the child has exited
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event(None).transpose()
+    }
+}
+
+impl AsRawFd for Supervisor {
+    /// The file descriptor that becomes readable when there is an event to
+    /// retrieve via [`Supervisor::next_event`]
+    #[inline]
+    fn as_raw_fd(&self) -> RawFd {
+        self.poller.as_raw_fd()
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if let Err(e) = self.poller.delete(self.pidfd) {
+            log::debug!("failed to deregister pidfd: {}", e);
+        }
+
+        #[allow(unsafe_code)]
+        // SAFETY: syscall, we own the pidfd
+        unsafe {
+            libc::close(self.pidfd);
+        }
+    }
+}
+
+/// Receives the next message, if any, returning `Ok(None)` if the child
+/// closed its end of the socket
+fn recv(socket: &Connection) -> Result<Option<(u32, Vec<u8>)>, std::io::Error> {
+    use std::io::IoSliceMut;
+
+    let mut hdr_buf = [0u8; std::mem::size_of::<Header>()];
+    let (len, _trunc) = socket.peek(&mut hdr_buf)?;
+
+    if len == 0 {
+        return Ok(None);
+    }
+
+    let header = Header::from_bytes(&hdr_buf).ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid message header")
+    })?;
+
+    let mut buffer = vec![0; header.size as usize];
+    socket.recv_vectored(&mut [IoSliceMut::new(&mut hdr_buf), IoSliceMut::new(&mut buffer)])?;
+
+    Ok(Some((header.kind, buffer)))
+}
+
+/// Searches the kernel log for the OOM killer killing the specified process,
+/// the same as `crash_handler::marker` does for a previous run
+fn was_oom_killed(pid: u32) -> bool {
+    use std::{io::Read, os::unix::fs::OpenOptionsExt};
+
+    let Ok(mut kmsg) = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open("/dev/kmsg")
+    else {
+        return false;
+    };
+
+    // Each read returns exactly one record, which are limited to 1024 bytes
+    // of text plus their metadata
+    let mut record = [0u8; 8 * 1024];
+    let summary = format!(",pid={},", pid);
+    let killed = format!("Killed process {} ", pid);
+
+    loop {
+        match kmsg.read(&mut record) {
+            Ok(0) => return false,
+            Ok(len) => {
+                let record = String::from_utf8_lossy(&record[..len]);
+                // The metadata is separated from the message by the first ';'
+                if let Some((_, message)) = record.split_once(';') {
+                    if (message.starts_with("oom-kill:") && message.contains(&summary))
+                        || message.contains(&killed)
+                    {
+                        return true;
+                    }
+                }
+            }
+            // The record was overwritten in the ring buffer before we read it
+            Err(err) if err.raw_os_error() == Some(libc::EPIPE) => {}
+            // WouldBlock once every record has been read
+            Err(_) => return false,
+        }
+    }
+}
@@ -1,6 +1,12 @@
 //! Implements support for Unix domain sockets for Windows. This should probably
 //! be a part of an external crate such as `uds`, but currently no Rust crates
 //! support them, or if they do, use outdated dependencies such as winapi
+//!
+//! Unix domain sockets are supported since Windows 10 1803, and unlike named
+//! pipes can be polled like any other socket, so the framed protocol is the
+//! same as on the other platforms. Windows doesn't support credentials for
+//! them, so access is instead restricted via the security descriptor of the
+//! socket file, which only allows the user the server runs as to connect.
 
 #![allow(clippy::mem_forget, unsafe_code)]
 
@@ -11,6 +17,8 @@ use std::{
 use windows_sys::Win32::{
     Foundation::{self as found, HANDLE},
     Networking::WinSock as ws,
+    Security::{self as sec, Authorization as auth},
+    System::{Memory::LocalFree, Threading as thread},
 };
 
 pub(crate) fn init() {
@@ -255,6 +263,116 @@ impl Drop for Socket {
     }
 }
 
+/// Frees memory allocated by the system on our behalf when dropped
+struct Local(*mut std::ffi::c_void);
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            // SAFETY: syscall, the memory was allocated with LocalAlloc
+            unsafe {
+                LocalFree(self.0 as _);
+            }
+        }
+    }
+}
+
+/// Returns the nul terminated wide string at `ptr`, without the terminator
+///
+/// # Safety
+///
+/// The pointer must point to a valid, nul terminated wide string
+unsafe fn wide_str<'s>(ptr: *const u16) -> &'s [u16] {
+    let len = (0..).take_while(|&i| *ptr.add(i) != 0).count();
+    std::slice::from_raw_parts(ptr, len)
+}
+
+/// Returns the SID of the user the current process runs as, in its string
+/// form, eg. `S-1-5-21-...`
+fn current_user_sid() -> io::Result<Vec<u16>> {
+    // SAFETY: syscalls, every pointer is valid for the duration of each call
+    unsafe {
+        let mut token: HANDLE = 0;
+        if thread::OpenProcessToken(thread::GetCurrentProcess(), sec::TOKEN_QUERY, &mut token) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        // TOKEN_USER is followed by the SID it points to
+        let mut user = [0u64; 16];
+        let mut len = 0;
+        let queried = sec::GetTokenInformation(
+            token,
+            sec::TokenUser,
+            user.as_mut_ptr().cast(),
+            std::mem::size_of_val(&user) as u32,
+            &mut len,
+        );
+        found::CloseHandle(token);
+        if queried == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let user = &*user.as_ptr().cast::<sec::TOKEN_USER>();
+
+        let mut sid: *mut u16 = std::ptr::null_mut();
+        if auth::ConvertSidToStringSidW(user.User.Sid, &mut sid) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sid = Local(sid.cast());
+
+        Ok(wide_str(sid.0.cast()).to_vec())
+    }
+}
+
+/// The DACL the socket file is given, a protected one, so that nothing is
+/// inherited from the directory, granting full access to the user and
+/// `SYSTEM`
+fn socket_sddl(user_sid: &[u16]) -> Vec<u16> {
+    let mut sddl: Vec<u16> = "D:P(A;;FA;;;SY)(A;;FA;;;".encode_utf16().collect();
+    sddl.extend_from_slice(user_sid);
+    sddl.extend(")".encode_utf16());
+    sddl
+}
+
+/// Replaces the DACL of the socket file with one that only grants access to
+/// the user the current process runs as, and `SYSTEM`, as connecting to the
+/// socket requires write access to it, which would otherwise be inherited
+/// from the directory it is in, eg. allowing any user to connect to a socket
+/// in a shared temporary directory
+fn restrict_to_current_user(path: &std::path::Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut sddl = socket_sddl(&current_user_sid()?);
+    sddl.push(0);
+
+    // SAFETY: syscalls, every pointer is valid for the duration of each call
+    unsafe {
+        let mut descriptor: sec::PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+        if auth::ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            sddl.as_ptr(),
+            auth::SDDL_REVISION_1,
+            &mut descriptor,
+            std::ptr::null_mut(),
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let descriptor = Local(descriptor);
+
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        if sec::SetFileSecurityW(
+            path.as_ptr(),
+            sec::DACL_SECURITY_INFORMATION | sec::PROTECTED_DACL_SECURITY_INFORMATION,
+            descriptor.0,
+        ) == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
 /// A Unix domain socket server
 pub(crate) struct UnixListener(Socket);
 
@@ -277,6 +395,15 @@ impl UnixListener {
             return Err(io::Error::last_os_error());
         }
 
+        // Clients can't connect until we listen, so there's no window where
+        // another user could connect
+        if let Err(err) = restrict_to_current_user(path.as_ref()) {
+            // The file was created by the bind, and would otherwise prevent
+            // the name from being bound again
+            let _ = std::fs::remove_file(path.as_ref());
+            return Err(err);
+        }
+
         // SAFETY: syscall
         if unsafe {
             ws::listen(inner.as_raw_socket() as _, 128 /* backlog */)
@@ -395,3 +522,72 @@ impl IntoRawSocket for UnixStream {
         ret
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::os::windows::ffi::OsStrExt;
+
+    /// Tests that the DACL of a bound socket file only grants access to the
+    /// current user, and `SYSTEM`, rather than inheriting the ACEs of the
+    /// directory it is in
+    #[test]
+    fn socket_restricted_to_current_user() {
+        let path = std::env::temp_dir().join(format!("minidumper-dacl-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+
+        // SAFETY: syscalls, every pointer is valid for the duration of each call
+        let sddl = unsafe {
+            let mut len = 0;
+            sec::GetFileSecurityW(
+                wide.as_ptr(),
+                sec::DACL_SECURITY_INFORMATION,
+                std::ptr::null_mut(),
+                0,
+                &mut len,
+            );
+            assert_ne!(len, 0, "{}", io::Error::last_os_error());
+
+            let mut descriptor = vec![0u64; (len as usize).div_ceil(8)];
+            assert_ne!(
+                sec::GetFileSecurityW(
+                    wide.as_ptr(),
+                    sec::DACL_SECURITY_INFORMATION,
+                    descriptor.as_mut_ptr().cast(),
+                    len,
+                    &mut len,
+                ),
+                0,
+                "{}",
+                io::Error::last_os_error()
+            );
+
+            let mut sddl: *mut u16 = std::ptr::null_mut();
+            assert_ne!(
+                auth::ConvertSecurityDescriptorToStringSecurityDescriptorW(
+                    descriptor.as_mut_ptr().cast(),
+                    auth::SDDL_REVISION_1,
+                    sec::DACL_SECURITY_INFORMATION,
+                    &mut sddl,
+                    std::ptr::null_mut(),
+                ),
+                0,
+                "{}",
+                io::Error::last_os_error()
+            );
+            let sddl = Local(sddl.cast());
+
+            String::from_utf16(wide_str(sddl.0.cast())).unwrap()
+        };
+
+        drop(listener);
+        let _ = std::fs::remove_file(&path);
+
+        let expected = String::from_utf16(&socket_sddl(&current_user_sid().unwrap())).unwrap();
+        assert_eq!(sddl, expected);
+    }
+}
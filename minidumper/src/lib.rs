@@ -6,13 +6,29 @@ pub use errors::Error;
 use std::{fs::File, path::PathBuf};
 
 mod ipc;
-pub use ipc::{Client, Server};
+#[cfg(not(target_os = "macos"))]
+pub use ipc::spool;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use ipc::supervisor;
+pub use ipc::{Client, Reconnect, Server, ShutdownSummary, MAX_MESSAGE_KIND, PROTOCOL_VERSION};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod in_process;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod ptrace_dumper;
+#[cfg(feature = "upload")]
+pub mod reporter;
+pub mod storage;
+#[cfg(feature = "upload")]
+pub mod upload;
+#[cfg(target_os = "windows")]
+pub mod windows_dumper;
 
 /// The result of a successful minidump generation.
 pub struct MinidumpBinary {
-    /// The file the minidump was written to, as provided by [`ServerHandler::create_minidump_file`]
+    /// The file the minidump was written to, as provided by [`ServerHandler::create_minidump_file_for`]
     pub file: File,
-    /// The path to the file as provided by [`ServerHandler::create_minidump_file`].
+    /// The path to the file as provided by [`ServerHandler::create_minidump_file_for`].
     pub path: PathBuf,
     /// The in-memory contents of the minidump, if available
     pub contents: Option<Vec<u8>>,
@@ -29,20 +45,111 @@ pub enum LoopAction {
     Continue,
 }
 
+/// How a client process exited, see [`ServerHandler::on_client_exited`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ClientExit {
+    /// The [`Client`] was dropped before the process exited
+    Clean,
+    /// The client requested a minidump for a crash before the process exited
+    Crashed,
+    /// The process exited without sending anything, eg. because it was killed
+    /// by `SIGKILL` or the OOM killer. On platforms other than Linux/Android,
+    /// this is also the case for a connection that was reaped for being stale
+    Vanished,
+}
+
+/// The identity of a client connected to the [`Server`], so that a server
+/// monitoring many processes can tell them apart
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// An id assigned by the server, unique among the clients of a single
+    /// [`Server::run`]
+    pub id: usize,
+    /// The pid of the client process, which on Linux/Android is taken from
+    /// the credentials of the socket rather than what the client claims
+    pub pid: Option<u32>,
+    /// The path of the executable of the client process
+    pub executable: Option<PathBuf>,
+    /// The name the client assigned itself, see [`Client::set_name`]
+    pub name: Option<String>,
+}
+
 /// Allows user code to hook into the server to avoid hardcoding too many details
+///
+/// The [`Server`] owns the sockets and the writing of minidumps, and calls
+/// the handler at each point in the lifetime of a client, so that the
+/// handler only needs to decide where minidumps are stored and what happens
+/// to them afterwards, eg. uploading them with the `upload` module when that
+/// feature is enabled.
+///
+/// 1. [`Self::on_client_connected`] once a client has connected, followed by
+///    [`Self::on_client_identified`] once it has told the server who it is
+/// 2. [`Self::on_message_from`] for every message the client sends with
+///    [`Client::send_message`]
+/// 3. [`Self::create_minidump_file_for`] when the client requests a minidump,
+///    followed by [`Self::on_minidump_created`], or
+///    [`Self::on_live_dump_created`] for a live dump, once it has been written
+///    or failed to be written
+/// 4. [`Self::on_client_disconnected_from`] once the connection is closed,
+///    and [`Self::on_client_exited`] with how the process of the client exited
+///
+/// Only [`Self::create_minidump_file`], [`Self::on_minidump_created`] and
+/// [`Self::on_message`] must be implemented, every other method has a
+/// default that does nothing, or calls the more general method it refines.
 pub trait ServerHandler: Send + Sync {
     /// Called when a crash request has been received and a backing file needs
     /// to be created to store it.
     fn create_minidump_file(&self) -> Result<(File, PathBuf), std::io::Error>;
+    /// Called instead of [`Self::create_minidump_file`] with the details of
+    /// the minidump that will be written, eg. so that they can be used to
+    /// name the file, see [`storage::DumpDirectory`].
+    ///
+    /// Defaults to calling [`Self::create_minidump_file`].
+    fn create_minidump_file_for(
+        &self,
+        _info: &storage::DumpInfo,
+    ) -> Result<(File, PathBuf), std::io::Error> {
+        self.create_minidump_file()
+    }
+    /// The [`MINIDUMP_TYPE`](https://learn.microsoft.com/en-us/windows/win32/api/minidumpapiset/ne-minidumpapiset-minidump_type)
+    /// flags the minidump with the specified details is written with, which
+    /// determine what it contains, eg. `MiniDumpWithFullMemory` to include
+    /// all of the memory of the client process.
+    ///
+    /// Defaults to [`windows_dumper::DEFAULT_MINIDUMP_TYPE`].
+    #[cfg(target_os = "windows")]
+    fn minidump_type(&self, _info: &storage::DumpInfo) -> u32 {
+        windows_dumper::DEFAULT_MINIDUMP_TYPE
+    }
     /// Called when a crash has been fully written as a minidump to the provided
     /// file. Also returns the full heap buffer as well.
     ///
     /// A return value of true indicates that the message loop should exit and
     /// stop processing messages.
     fn on_minidump_created(&self, result: Result<MinidumpBinary, Error>) -> LoopAction;
-    /// Called when the client sends a user message sent from the client with
-    /// `send_message`
+    /// Called when a minidump requested by a client via
+    /// [`Client::request_live_dump`] has been written, with the reason the
+    /// client gave for requesting it. The client process is still running.
+    ///
+    /// Defaults to calling [`Self::on_minidump_created`].
+    fn on_live_dump_created(
+        &self,
+        _reason: &str,
+        result: Result<MinidumpBinary, Error>,
+    ) -> LoopAction {
+        self.on_minidump_created(result)
+    }
+    /// Called when the client sends a user message with [`Client::send_message`],
+    /// with the kind the client specified, eg. for health checks, log lines,
+    /// or application specific commands
     fn on_message(&self, kind: u32, buffer: Vec<u8>);
+    /// Called instead of [`Self::on_message`] with the identity of the client
+    /// that sent the message.
+    ///
+    /// Defaults to calling [`Self::on_message`].
+    fn on_message_from(&self, _client: &ClientInfo, kind: u32, buffer: Vec<u8>) {
+        self.on_message(kind, buffer);
+    }
     /// Optional allocation function for the buffer used to store a message.
     ///
     /// Defaults to creating a new vec.
@@ -59,4 +166,32 @@ pub trait ServerHandler: Send + Sync {
     fn on_client_disconnected(&self, _num_clients: usize) -> LoopAction {
         LoopAction::Continue
     }
+    /// Called when a client has told the Server who it is, which it does
+    /// when it connects, and again whenever it changes its name via
+    /// [`Client::set_name`].
+    fn on_client_identified(&self, _client: &ClientInfo) -> LoopAction {
+        LoopAction::Continue
+    }
+    /// Called instead of [`Self::on_client_disconnected`] with the identity
+    /// of the client that disconnected.
+    ///
+    /// Defaults to calling [`Self::on_client_disconnected`].
+    fn on_client_disconnected_from(&self, _client: &ClientInfo, num_clients: usize) -> LoopAction {
+        self.on_client_disconnected(num_clients)
+    }
+    /// Called when the process of a client has exited, with the pid of the
+    /// process and how it exited.
+    ///
+    /// The process is monitored via a [pidfd](https://man7.org/linux/man-pages/man2/pidfd_open.2.html)
+    /// rather than the socket, so this is called even if the socket outlives
+    /// the process, eg. because it was inherited by a child process. If the
+    /// kernel doesn't support pidfds, ie. is older than 5.3, this is instead
+    /// called when the socket is closed.
+    ///
+    /// On other platforms, this is called when the socket is closed, for
+    /// clients whose pid is known, ie. every client that sent its identity or
+    /// requested a minidump.
+    fn on_client_exited(&self, _pid: u32, _exit: ClientExit) -> LoopAction {
+        LoopAction::Continue
+    }
 }
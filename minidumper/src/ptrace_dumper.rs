@@ -0,0 +1,267 @@
+//! `ptrace` based minidump writing for Linux/Android.
+//!
+//! Unlike [`crate::in_process`], this is meant to be run from a separate
+//! process, such as a [`crate::Server`] or a watchdog, that attaches to the
+//! crashed process with `ptrace`, suspends every one of its threads, and reads
+//! their registers and stacks, producing a complete minidump that includes
+//! every thread rather than only the crashing one.
+//!
+//! Note that attaching to another process requires the appropriate
+//! permissions, which, depending on the value of `/proc/sys/kernel/yama/ptrace_scope`,
+//! means the dumping process must either be an ancestor of the crashed process
+//! or have been allowed via [`PR_SET_PTRACER`](https://man7.org/linux/man-pages/man2/prctl.2.html).
+
+#![allow(unsafe_code)]
+
+mod open_files;
+
+use crate::Error;
+use minidump_writer::{
+    app_memory::AppMemory, crash_context::CrashContext, minidump_format::format,
+    minidump_writer::MinidumpWriter,
+};
+use scroll::{ctx::SizeWith, Pread, Pwrite};
+use std::{fs::File, ops::Range, os::unix::fs::FileExt};
+
+/// Writes a minidump for the crash described by the [`crash_context::CrashContext`],
+/// which was sent by the crashed process, to the specified file.
+///
+/// The crashing thread's context is taken from the crash context, as the
+/// thread is executing the crash handler at this point, while the context of
+/// every other thread is retrieved via `ptrace`.
+///
+/// Any regions included via `crash_handler::memory_regions` are read from the
+/// crashed process and appended to the `MemoryListStream`. Unlike
+/// [`crate::in_process`], their tags are not recorded. Any memory redacted via
+/// `crash_handler::memory_regions` is zero filled in the `MemoryListStream`,
+/// which also contains the stack of every thread, once the minidump has been
+/// written.
+///
+/// Threads that also crashed while the crash was being handled, see
+/// `crash_handler::crashed_threads`, are included like every other thread,
+/// with the context they were parked in the signal handler with.
+///
+/// The file descriptors open in the crashed process are recorded in a
+/// [`crash_context::OPEN_FILES_STREAM`].
+///
+/// The contents of the minidump are also returned.
+///
+/// # Errors
+///
+/// An error will be returned if the process could not be attached to, or the
+/// minidump could not be written
+pub fn write_minidump(
+    crash_context: crash_context::CrashContext,
+    file: &mut File,
+) -> Result<Vec<u8>, Error> {
+    let regions = memory_regions(&crash_context);
+
+    let pid = crash_context.pid;
+    let mut writer = MinidumpWriter::new(pid, crash_context.tid);
+    writer.set_app_memory(
+        regions
+            .iter()
+            .filter(|region| region.tag().is_some())
+            .map(|region| AppMemory {
+                ptr: region.address as usize,
+                length: (region.size as usize).min(MAX_REGION_SIZE),
+            })
+            .collect(),
+    );
+    writer.set_crash_context(CrashContext {
+        inner: crash_context,
+    });
+
+    let mut contents = writer.dump(file)?;
+
+    let redacted: Vec<_> = regions
+        .iter()
+        .filter_map(|region| region.redacted())
+        .collect();
+    redact(&mut contents, file, &redacted)?;
+
+    append_stream(
+        &mut contents,
+        file,
+        crash_context::OPEN_FILES_STREAM,
+        &open_files::stream(pid),
+    )?;
+
+    Ok(contents)
+}
+
+/// Writes a minidump of the specified process to the specified file, without
+/// a crash context, eg. for a process that is hung rather than crashed.
+///
+/// The `blamed_thread` is recorded as the thread that caused the minidump to
+/// be written, and must be one of the threads of the process.
+///
+/// As with [`write_minidump`], the file descriptors open in the process are
+/// recorded in a [`crash_context::OPEN_FILES_STREAM`].
+///
+/// The contents of the minidump are also returned.
+///
+/// # Errors
+///
+/// An error will be returned if the process could not be attached to, or the
+/// minidump could not be written
+pub fn write_minidump_for_process(
+    pid: libc::pid_t,
+    blamed_thread: libc::pid_t,
+    file: &mut File,
+) -> Result<Vec<u8>, Error> {
+    let mut writer = MinidumpWriter::new(pid, blamed_thread);
+    let mut contents = writer.dump(file)?;
+
+    append_stream(
+        &mut contents,
+        file,
+        crash_context::OPEN_FILES_STREAM,
+        &open_files::stream(pid),
+    )?;
+
+    Ok(contents)
+}
+
+/// The maximum amount of memory written for each region included via
+/// `crash_handler::memory_regions`, same as [`crate::in_process`]
+const MAX_REGION_SIZE: usize = 1024 * 1024;
+
+/// Reads the memory region slots of the crashed process, skipping those that
+/// can't be read
+fn memory_regions(crash_context: &crash_context::CrashContext) -> Vec<crash_context::MemoryRegion> {
+    (0..crash_context
+        .memory_region_count
+        .min(crash_context::MAX_MEMORY_REGION_SLOTS))
+        .filter_map(|i| {
+            read_region(
+                crash_context.pid,
+                crash_context.memory_regions
+                    + i * std::mem::size_of::<crash_context::MemoryRegion>(),
+            )
+        })
+        .collect()
+}
+
+/// Zero fills the redacted memory in the `MemoryListStream` of the minidump,
+/// both in its contents and in the file it was written to
+fn redact(contents: &mut [u8], file: &File, redacted: &[Range<u64>]) -> Result<(), Error> {
+    if redacted.is_empty() {
+        return Ok(());
+    }
+
+    let le = scroll::Endian::Little;
+    let header: format::MINIDUMP_HEADER = contents.pread_with(0, le)?;
+
+    for i in 0..header.stream_count as usize {
+        let entry: format::MINIDUMP_DIRECTORY = contents.pread_with(
+            header.stream_directory_rva as usize + i * format::MINIDUMP_DIRECTORY::size_with(&le),
+            le,
+        )?;
+        if entry.stream_type != format::MINIDUMP_STREAM_TYPE::MemoryListStream as u32 {
+            continue;
+        }
+
+        let list = entry.location.rva as usize;
+        let count: u32 = contents.pread_with(list, le)?;
+
+        for j in 0..count as usize {
+            let memory: format::MINIDUMP_MEMORY_DESCRIPTOR = contents.pread_with(
+                list + 4 + j * format::MINIDUMP_MEMORY_DESCRIPTOR::size_with(&le),
+                le,
+            )?;
+            let start = memory.start_of_memory_range;
+            let end = start + u64::from(memory.memory.data_size);
+
+            for range in redacted {
+                let (rstart, rend) = (range.start.max(start), range.end.min(end));
+                if rstart >= rend {
+                    continue;
+                }
+
+                let rva = memory.memory.rva as usize + (rstart - start) as usize;
+                let Some(bytes) = contents.get_mut(rva..rva + (rend - rstart) as usize) else {
+                    continue;
+                };
+
+                bytes.fill(0);
+                file.write_all_at(bytes, rva as u64)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Appends a stream that `minidump_writer` doesn't know about to the
+/// minidump, both to its contents and to the file it was written to.
+///
+/// As the stream directory has no room for another entry, the stream is
+/// written after the end of the minidump, followed by a copy of the
+/// directory with the stream added to it, and the header is pointed at the
+/// new directory.
+fn append_stream(
+    contents: &mut Vec<u8>,
+    file: &File,
+    stream_type: u32,
+    stream: &[u8],
+) -> Result<(), Error> {
+    let le = scroll::Endian::Little;
+    let mut header: format::MINIDUMP_HEADER = contents.pread_with(0, le)?;
+    let entry_size = format::MINIDUMP_DIRECTORY::size_with(&le);
+    let directory: &[u8] = contents.pread_with(
+        header.stream_directory_rva as usize,
+        header.stream_count as usize * entry_size,
+    )?;
+    let directory = directory.to_vec();
+
+    let end = contents.len();
+    let align = |contents: &mut Vec<u8>| contents.resize(contents.len().next_multiple_of(8), 0);
+
+    align(contents);
+    let rva = contents.len();
+    contents.extend_from_slice(stream);
+
+    align(contents);
+    let directory_rva = contents.len();
+    contents.extend_from_slice(&directory);
+    contents.resize(directory_rva + directory.len() + entry_size, 0);
+    contents.pwrite_with(
+        format::MINIDUMP_DIRECTORY {
+            stream_type,
+            location: format::MINIDUMP_LOCATION_DESCRIPTOR {
+                data_size: stream.len() as u32,
+                rva: rva as u32,
+            },
+        },
+        directory_rva + directory.len(),
+        le,
+    )?;
+
+    header.stream_count += 1;
+    header.stream_directory_rva = directory_rva as u32;
+    let header_len = contents.pwrite_with(header, 0, le)?;
+
+    file.write_all_at(&contents[end..], end as u64)?;
+    file.write_all_at(&contents[..header_len], 0)?;
+
+    Ok(())
+}
+
+/// Reads a single [`crash_context::MemoryRegion`] slot from the memory of the
+/// crashed process
+fn read_region(pid: libc::pid_t, address: usize) -> Option<crash_context::MemoryRegion> {
+    let mut region = crash_context::MemoryRegion::EMPTY;
+    let local = libc::iovec {
+        iov_base: std::ptr::addr_of_mut!(region).cast(),
+        iov_len: std::mem::size_of::<crash_context::MemoryRegion>(),
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut _,
+        iov_len: local.iov_len,
+    };
+
+    // SAFETY: syscall, the local buffer is the size of the region
+    let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+    (read == local.iov_len as isize).then_some(region)
+}
@@ -0,0 +1,206 @@
+//! Enumeration of the file descriptors that are open in the crashed process,
+//! see [`crash_context::OPEN_FILES_STREAM`].
+//!
+//! The descriptors are read from `/proc/<pid>/fd`, and any sockets among them
+//! are resolved to their protocol and addresses via the tables in
+//! `/proc/<pid>/net`, which are those of the network namespace of the
+//! crashed process.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::PathBuf,
+};
+
+/// The maximum number of file descriptors recorded, so that a process that
+/// leaked an enormous number of them doesn't produce an enormous minidump
+const MAX_OPEN_FILES: usize = 32 * 1024;
+
+/// Builds the contents of the [`crash_context::OPEN_FILES_STREAM`] for the
+/// process
+pub(super) fn stream(pid: libc::pid_t) -> Vec<u8> {
+    let mut fds: Vec<(i32, PathBuf)> = std::fs::read_dir(format!("/proc/{pid}/fd"))
+        .map(|dir| {
+            dir.filter_map(|entry| {
+                let entry = entry.ok()?;
+                let fd = entry.file_name().to_str()?.parse().ok()?;
+                Some((fd, std::fs::read_link(entry.path()).ok()?))
+            })
+            .collect()
+        })
+        .unwrap_or_default();
+    fds.sort_unstable_by_key(|(fd, _)| *fd);
+
+    let targets: Vec<_> = fds
+        .iter()
+        .map(|(fd, target)| (*fd, target.to_string_lossy()))
+        .collect();
+
+    // Reading the socket tables is only worth it if there are any sockets
+    let sockets = if targets
+        .iter()
+        .any(|(_, target)| classify(target).0 == crash_context::OPEN_FILE_SOCKET)
+    {
+        sockets(pid)
+    } else {
+        HashMap::new()
+    };
+
+    let count = targets.len().min(MAX_OPEN_FILES);
+
+    let mut stream = Vec::new();
+    stream.extend_from_slice(&(targets.len() as u32).to_le_bytes());
+    stream.extend_from_slice(&(count as u32).to_le_bytes());
+
+    for (fd, target) in &targets[..count] {
+        let (kind, inode) = classify(target);
+        let description = if kind == crash_context::OPEN_FILE_SOCKET {
+            sockets.get(&inode).map(String::as_str)
+        } else {
+            None
+        }
+        .unwrap_or(target.as_ref());
+
+        stream.extend_from_slice(&fd.to_le_bytes());
+        stream.extend_from_slice(&kind.to_le_bytes());
+        stream.extend_from_slice(&inode.to_le_bytes());
+        stream.extend_from_slice(&(description.len() as u32).to_le_bytes());
+        stream.extend_from_slice(description.as_bytes());
+    }
+
+    stream
+}
+
+/// Determines the kind, and the inode if it has one, of an open file from
+/// the target of its `/proc/<pid>/fd` link
+fn classify(target: &str) -> (u32, u64) {
+    let inode = |prefix: &str| {
+        target
+            .strip_prefix(prefix)
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|inode| inode.parse().ok())
+    };
+
+    if let Some(inode) = inode("socket:[") {
+        (crash_context::OPEN_FILE_SOCKET, inode)
+    } else if let Some(inode) = inode("pipe:[") {
+        (crash_context::OPEN_FILE_PIPE, inode)
+    } else if target.starts_with("anon_inode:") {
+        (crash_context::OPEN_FILE_ANON_INODE, 0)
+    } else if target.starts_with('/') {
+        (crash_context::OPEN_FILE_PATH, 0)
+    } else {
+        (crash_context::OPEN_FILE_OTHER, 0)
+    }
+}
+
+/// Describes every TCP, UDP, and Unix socket in the network namespace of the
+/// process, keyed by their inode
+fn sockets(pid: libc::pid_t) -> HashMap<u64, String> {
+    let mut sockets = HashMap::new();
+
+    for proto in ["tcp", "tcp6", "udp", "udp6"] {
+        let Ok(table) = std::fs::read_to_string(format!("/proc/{pid}/net/{proto}")) else {
+            continue;
+        };
+
+        // sl local_address rem_address st ... inode, where inode is the tenth
+        for line in table.lines().skip(1) {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (Some(local), Some(remote), Some(state), Some(inode)) = (
+                fields.get(1).and_then(|addr| parse_address(addr)),
+                fields.get(2).and_then(|addr| parse_address(addr)),
+                fields.get(3),
+                fields.get(9).and_then(|inode| inode.parse::<u64>().ok()),
+            ) else {
+                continue;
+            };
+
+            // Sockets that are no longer attached to a descriptor, eg. in
+            // TIME_WAIT, have no inode
+            if inode == 0 {
+                continue;
+            }
+
+            let mut description = format!("{proto} {local} -> {remote}");
+            if let Some(state) = tcp_state(state).filter(|_| proto.starts_with("tcp")) {
+                description.push(' ');
+                description.push_str(state);
+            }
+
+            sockets.insert(inode, description);
+        }
+    }
+
+    if let Ok(table) = std::fs::read_to_string(format!("/proc/{pid}/net/unix")) {
+        // Num RefCount Protocol Flags Type St Inode Path
+        for line in table.lines().skip(1) {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (Some(kind), Some(inode)) = (
+                fields.get(4),
+                fields.get(6).and_then(|inode| inode.parse::<u64>().ok()),
+            ) else {
+                continue;
+            };
+
+            let mut description = String::from(match *kind {
+                "0001" => "unix stream",
+                "0002" => "unix dgram",
+                "0005" => "unix seqpacket",
+                _ => "unix",
+            });
+            // Abstract sockets start with a @, unnamed ones have no path
+            if let Some(path) = fields.get(7) {
+                description.push(' ');
+                description.push_str(path);
+            }
+
+            sockets.insert(inode, description);
+        }
+    }
+
+    sockets
+}
+
+/// Parses an address in the `/proc/net/{tcp,udp}[6]` format, which is the
+/// address as hex encoded 32-bit words in native byte order, followed by the
+/// port in hex, eg. `0100007F:1F90` for `127.0.0.1:8080` on little endian
+fn parse_address(address: &str) -> Option<SocketAddr> {
+    let (ip, port) = address.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    let mut octets = [0u8; 16];
+    if ip.len() != 8 && ip.len() != 32 {
+        return None;
+    }
+    for (i, word) in octets.chunks_exact_mut(4).take(ip.len() / 8).enumerate() {
+        let value = u32::from_str_radix(ip.get(i * 8..i * 8 + 8)?, 16).ok()?;
+        word.copy_from_slice(&value.to_ne_bytes());
+    }
+
+    let ip = if ip.len() == 8 {
+        IpAddr::V4(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(octets))
+    };
+
+    Some(SocketAddr::new(ip, port))
+}
+
+/// The name of a TCP state, as it appears in the `st` column
+fn tcp_state(state: &str) -> Option<&'static str> {
+    Some(match u8::from_str_radix(state, 16).ok()? {
+        0x01 => "ESTABLISHED",
+        0x02 => "SYN_SENT",
+        0x03 => "SYN_RECV",
+        0x04 => "FIN_WAIT1",
+        0x05 => "FIN_WAIT2",
+        0x06 => "TIME_WAIT",
+        0x07 => "CLOSE",
+        0x08 => "CLOSE_WAIT",
+        0x09 => "LAST_ACK",
+        0x0a => "LISTEN",
+        0x0b => "CLOSING",
+        _ => return None,
+    })
+}
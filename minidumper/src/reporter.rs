@@ -0,0 +1,403 @@
+//! Persistent queue of crashes waiting to be uploaded, for machines that are
+//! often offline, eg. laptops and mobile devices.
+//!
+//! A [`Queue`] moves each enqueued minidump, along with its annotations and
+//! context, into a spool directory, and removes it once [`Queue::flush`] has
+//! uploaded it. Every crash is stored in its own pair of files, and its upload
+//! state is persisted, so a flush that is interrupted, eg. by the process
+//! exiting or the network dropping, resumes with the crashes that weren't
+//! uploaded yet, with the same backoff, the next time the queue is flushed.
+//!
+//! ```no_run
+//! use minidumper::{reporter::Queue, upload::{Crash, Uploader}};
+//! use std::time::Duration;
+//!
+//! let queue = Queue::new(
+//!     "/var/spool/my-app/crashes",
+//!     // The queue retries failed uploads itself
+//!     Uploader::sentry("https://public@o0.ingest.sentry.io/42").unwrap().max_attempts(1),
+//! )
+//! .max_age(Duration::from_secs(30 * 24 * 60 * 60));
+//!
+//! queue.enqueue(Crash::new("/tmp/crash.dmp").annotation("release", "1.0.0")).unwrap();
+//!
+//! // Eg. periodically, or when the network becomes available
+//! let summary = queue.flush().unwrap();
+//! println!("uploaded {} crashes, {} still pending", summary.uploaded, summary.pending);
+//! ```
+
+use crate::{
+    upload::{Crash, Uploader},
+    Error,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// The delay before the first retry of a crash if none is specified, see
+/// [`Queue::backoff`]
+pub const DEFAULT_BACKOFF: Duration = Duration::from_secs(60);
+/// The maximum delay between retries of a crash if none is specified, see
+/// [`Queue::backoff`]
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// The extension of the minidump of a queued crash
+const DUMP_EXT: &str = "dmp";
+/// The extension of the metadata of a queued crash, which is written after
+/// the minidump, so that a crash is only queued once both exist
+const META_EXT: &str = "meta";
+
+/// The outcome of a [`Queue::flush`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FlushSummary {
+    /// The number of crashes that were uploaded and removed from the queue
+    pub uploaded: usize,
+    /// The number of crashes that were removed from the queue without being
+    /// uploaded, either because they exceeded the [`Queue::max_age`], or
+    /// because the service rejected them
+    pub dropped: usize,
+    /// The number of crashes that are still queued
+    pub pending: usize,
+}
+
+/// A crash in a [`Queue`]
+#[derive(Clone, Debug)]
+pub struct QueuedCrash {
+    /// The crash, whose minidump is in the spool directory
+    pub crash: Crash,
+    /// When the crash was enqueued
+    pub created: SystemTime,
+    /// The number of failed attempts to upload the crash
+    pub attempts: u32,
+    /// The earliest time at which the next attempt will be made
+    pub next_attempt: SystemTime,
+    meta: PathBuf,
+}
+
+/// Persists crashes to a spool directory, and uploads them with an
+/// exponential backoff
+#[derive(Clone)]
+pub struct Queue {
+    dir: PathBuf,
+    uploader: Uploader,
+    max_age: Option<Duration>,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Queue {
+    /// Creates a queue in the specified directory, which is created when the
+    /// first crash is enqueued, and uploads crashes via the uploader.
+    ///
+    /// As the queue retries crashes itself, the uploader should usually be
+    /// configured to only make a single attempt, see
+    /// [`Uploader::max_attempts`].
+    pub fn new(dir: impl Into<PathBuf>, uploader: Uploader) -> Self {
+        Self {
+            dir: dir.into(),
+            uploader,
+            max_age: None,
+            backoff: DEFAULT_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+        }
+    }
+
+    /// Sets the maximum age of a crash, once it is exceeded the crash is
+    /// removed from the queue without being uploaded
+    #[inline]
+    pub fn max_age(mut self, max: Duration) -> Self {
+        self.max_age = Some(max);
+        self
+    }
+
+    /// Sets the delay before a crash is retried after the first failed
+    /// attempt, which is doubled for every subsequent failed attempt, up to
+    /// the maximum
+    #[inline]
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// The spool directory
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Moves the minidump of the crash into the spool directory, and persists
+    /// its annotations and context alongside it, returning the path of the
+    /// minidump in the spool directory.
+    ///
+    /// # Errors
+    ///
+    /// The spool directory could not be created, or the crash could not be
+    /// written to it
+    pub fn enqueue(&self, mut crash: Crash) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let dump = self.dir.join(&id).with_extension(DUMP_EXT);
+
+        // The minidump may be on a different file system
+        if fs::rename(&crash.minidump, &dump).is_err() {
+            fs::copy(&crash.minidump, &dump)?;
+            fs::remove_file(&crash.minidump)?;
+        }
+        crash.minidump = dump.clone();
+
+        let now = SystemTime::now();
+        let queued = QueuedCrash {
+            crash,
+            created: now,
+            attempts: 0,
+            next_attempt: now,
+            meta: self.dir.join(&id).with_extension(META_EXT),
+        };
+
+        if let Err(err) = write_meta(&queued) {
+            let _ = fs::remove_file(&dump);
+            return Err(err);
+        }
+
+        Ok(dump)
+    }
+
+    /// Retrieves every crash in the queue, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// The spool directory could not be read, it not existing is not an error
+    pub fn pending(&self) -> io::Result<Vec<QueuedCrash>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut queued = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(META_EXT) {
+                continue;
+            }
+
+            // The crash may have been removed since the directory was read
+            let Ok(contents) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            match read_meta(&contents, path.with_extension(DUMP_EXT)) {
+                Some(mut crash) => {
+                    crash.meta = path;
+                    queued.push(crash);
+                }
+                None => log::warn!(
+                    "ignoring queued crash with invalid metadata '{}'",
+                    path.display()
+                ),
+            }
+        }
+
+        queued.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.meta.cmp(&b.meta)));
+        Ok(queued)
+    }
+
+    /// Uploads every crash whose next attempt is due, oldest first, blocking
+    /// until they have all been attempted.
+    ///
+    /// Crashes that exceed the [`Self::max_age`] are removed first. If an
+    /// upload fails due to a network error, the machine is assumed to be
+    /// offline, and the remaining crashes aren't attempted until the next
+    /// flush. If the service rejects a crash with a client error, other than
+    /// `429`, it is removed, as retrying it would never succeed.
+    ///
+    /// # Errors
+    ///
+    /// The spool directory could not be read, or the state of a crash could
+    /// not be persisted
+    pub fn flush(&self) -> Result<FlushSummary, Error> {
+        let mut summary = FlushSummary::default();
+        let now = SystemTime::now();
+        let mut offline = false;
+
+        for mut queued in self.pending()? {
+            let age = now.duration_since(queued.created).unwrap_or_default();
+            if self.max_age.is_some_and(|max| age > max) {
+                log::debug!(
+                    "dropping crash '{}' queued {age:?} ago",
+                    queued.crash.minidump.display()
+                );
+                remove(&queued)?;
+                summary.dropped += 1;
+                continue;
+            }
+
+            if offline || queued.next_attempt > now {
+                summary.pending += 1;
+                continue;
+            }
+
+            // The minidump may not have been moved into place yet, or been
+            // removed by something else
+            if !queued.crash.minidump.exists() {
+                summary.pending += 1;
+                continue;
+            }
+
+            match self.uploader.upload(&queued.crash) {
+                Ok(()) => {
+                    remove(&queued)?;
+                    summary.uploaded += 1;
+                }
+                Err(Error::Upload(err)) if !is_retryable(&err) => {
+                    log::warn!(
+                        "dropping crash '{}' rejected by the service: {err}",
+                        queued.crash.minidump.display()
+                    );
+                    remove(&queued)?;
+                    summary.dropped += 1;
+                }
+                Err(err) => {
+                    log::debug!(
+                        "failed to upload crash '{}': {err}",
+                        queued.crash.minidump.display()
+                    );
+
+                    offline = matches!(&err, Error::Upload(inner) if matches!(**inner, ureq::Error::Transport(_)));
+
+                    let backoff = self
+                        .backoff
+                        .saturating_mul(1u32.checked_shl(queued.attempts).unwrap_or(u32::MAX))
+                        .min(self.max_backoff);
+                    queued.attempts += 1;
+                    queued.next_attempt = SystemTime::now() + backoff;
+                    write_meta(&queued)?;
+                    summary.pending += 1;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+#[inline]
+fn is_retryable(err: &ureq::Error) -> bool {
+    match err {
+        ureq::Error::Status(status, _) => *status == 429 || *status >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// Removes both files of a queued crash
+fn remove(queued: &QueuedCrash) -> io::Result<()> {
+    for path in [&queued.crash.minidump, &queued.meta] {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+#[inline]
+fn millis(time: SystemTime) -> u128 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
+
+/// Writes the metadata of the crash, replacing it atomically so that it is
+/// never torn, as one line per field, eg. `attempts 2`
+fn write_meta(queued: &QueuedCrash) -> io::Result<()> {
+    let mut meta = format!(
+        "created {}\nattempts {}\nnext_attempt {}\n",
+        millis(queued.created),
+        queued.attempts,
+        millis(queued.next_attempt)
+    );
+
+    if let Some(context) = &queued.crash.context {
+        meta.push_str("context ");
+        meta.push_str(&escape(context));
+        meta.push('\n');
+    }
+
+    for (key, value) in &queued.crash.annotations {
+        meta.push_str("annotation ");
+        meta.push_str(&escape(key));
+        meta.push('\t');
+        meta.push_str(&escape(value));
+        meta.push('\n');
+    }
+
+    let tmp = queued.meta.with_extension("tmp");
+    fs::write(&tmp, meta)?;
+    fs::rename(&tmp, &queued.meta)
+}
+
+/// Parses the metadata written by [`write_meta`]
+fn read_meta(contents: &str, minidump: PathBuf) -> Option<QueuedCrash> {
+    let time = |s: &str| -> Option<SystemTime> {
+        Some(SystemTime::UNIX_EPOCH + Duration::from_millis(s.parse().ok()?))
+    };
+
+    let mut crash = Crash::new(minidump);
+    let (mut created, mut attempts, mut next_attempt) = (None, 0, None);
+
+    for line in contents.lines() {
+        let (field, value) = line.split_once(' ')?;
+        match field {
+            "created" => created = Some(time(value)?),
+            "attempts" => attempts = value.parse().ok()?,
+            "next_attempt" => next_attempt = Some(time(value)?),
+            "context" => crash.context = Some(unescape(value)),
+            "annotation" => {
+                let (key, value) = value.split_once('\t')?;
+                crash.annotations.push((unescape(key), unescape(value)));
+            }
+            // Ignore fields added by newer versions
+            _ => {}
+        }
+    }
+
+    let created = created?;
+    Some(QueuedCrash {
+        crash,
+        created,
+        attempts,
+        next_attempt: next_attempt.unwrap_or(created),
+        meta: PathBuf::new(),
+    })
+}
+
+/// Escapes the characters that delimit fields in the metadata
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
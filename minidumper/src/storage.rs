@@ -0,0 +1,359 @@
+//! Naming and retention of the minidumps written by the [`crate::Server`].
+//!
+//! A [`DumpDirectory`] can be used to implement
+//! [`crate::ServerHandler::create_minidump_file_for`], so that a long running
+//! monitor process doesn't fill the disk with minidumps that are never
+//! consumed, eg. on a kiosk that is rarely online to upload them.
+//!
+//! ```no_run
+//! use minidumper::{storage::{DumpDirectory, DumpInfo}, Error, LoopAction, MinidumpBinary};
+//! use std::{fs::File, path::PathBuf};
+//!
+//! struct Handler {
+//!     dumps: DumpDirectory,
+//! }
+//!
+//! impl minidumper::ServerHandler for Handler {
+//!     fn create_minidump_file(&self) -> Result<(File, PathBuf), std::io::Error> {
+//!         unreachable!("create_minidump_file_for is implemented")
+//!     }
+//!
+//!     fn create_minidump_file_for(&self, info: &DumpInfo) -> Result<(File, PathBuf), std::io::Error> {
+//!         self.dumps.create(info)
+//!     }
+//!
+//!     fn on_minidump_created(&self, _result: Result<MinidumpBinary, Error>) -> LoopAction {
+//!         // Upload every dump that is still on disk, oldest first, removing
+//!         // each one once it has been uploaded
+//!         for dump in self.dumps.pending().unwrap_or_default() {
+//!             // ...
+//!         }
+//!
+//!         LoopAction::Continue
+//!     }
+//!
+//!     fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {}
+//! }
+//!
+//! let handler = Handler {
+//!     dumps: DumpDirectory::new("/var/crashes")
+//!         .template("{timestamp}-{pid}-{reason}.dmp")
+//!         .max_dumps(10)
+//!         .max_total_bytes(500 * 1024 * 1024),
+//! };
+//! ```
+
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The template used if none is specified, see [`DumpDirectory::template`]
+pub const DEFAULT_TEMPLATE: &str = "{timestamp}-{pid}-{reason}.dmp";
+
+/// Why a minidump is being written
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DumpKind {
+    /// The client crashed, see [`crate::Client::request_dump`]
+    Crash,
+    /// The client is still running, see [`crate::Client::request_live_dump`]
+    Live,
+}
+
+/// Describes a minidump that is about to be written, see
+/// [`crate::ServerHandler::create_minidump_file_for`]
+#[derive(Clone, Debug)]
+pub struct DumpInfo {
+    /// The pid of the client process the minidump is of
+    pub pid: u32,
+    pub kind: DumpKind,
+    /// A short description of the crash, eg. `SIGSEGV` or `stack-overflow`,
+    /// or the reason the client gave for requesting a live dump
+    pub reason: String,
+    /// The client the minidump is of, see [`crate::ClientInfo`]
+    pub client: crate::ClientInfo,
+}
+
+/// A minidump that is still in a [`DumpDirectory`]
+#[derive(Clone, Debug)]
+pub struct PendingDump {
+    pub path: PathBuf,
+    /// The size of the file in bytes
+    pub size: u64,
+    /// When the file was last modified, ie. when the minidump was written
+    pub modified: SystemTime,
+}
+
+/// Creates minidump files in a directory, named according to a template, and
+/// removes the oldest ones to keep the directory within its limits
+#[derive(Clone, Debug)]
+pub struct DumpDirectory {
+    dir: PathBuf,
+    template: String,
+    max_dumps: Option<usize>,
+    max_total_bytes: Option<u64>,
+}
+
+impl DumpDirectory {
+    /// Creates minidumps in the specified directory, which is created if it
+    /// doesn't exist, named according to [`DEFAULT_TEMPLATE`], and with no
+    /// limits
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            template: DEFAULT_TEMPLATE.to_owned(),
+            max_dumps: None,
+            max_total_bytes: None,
+        }
+    }
+
+    /// Sets the template used to name minidump files, in which the following
+    /// placeholders are replaced with the details of the minidump
+    ///
+    /// * `{timestamp}` - The current time, in seconds since the Unix epoch
+    /// * `{pid}` - The pid of the client process
+    /// * `{kind}` - Either `crash` or `live`, see [`DumpKind`]
+    /// * `{reason}` - See [`DumpInfo::reason`]
+    /// * `{client}` - The [name](crate::ClientInfo::name) of the client, or
+    ///   if it has none, the file name of its executable
+    ///
+    /// Any character in a replaced value that isn't alphanumeric, `-` or `_`
+    /// is replaced with `_`, and the reason and client are truncated to 64
+    /// characters.
+    /// If the file already exists, a number is appended to the name rather
+    /// than overwriting it.
+    ///
+    /// Only files whose names end with the text after the last placeholder,
+    /// eg. `.dmp`, are considered to be minidumps, so that other files in the
+    /// directory are never removed.
+    #[inline]
+    pub fn template(mut self, template: impl Into<String>) -> Self {
+        self.template = template.into();
+        self
+    }
+
+    /// Sets the maximum number of minidumps kept in the directory, once it is
+    /// reached the oldest minidumps are removed before a new one is created
+    #[inline]
+    pub fn max_dumps(mut self, max: usize) -> Self {
+        self.max_dumps = Some(max);
+        self
+    }
+
+    /// Sets the maximum combined size of the minidumps kept in the directory,
+    /// once it is exceeded the oldest minidumps are removed before a new one
+    /// is created.
+    ///
+    /// As the size of a minidump isn't known until it is written, the newest
+    /// minidump can exceed the limit until [`Self::enforce_retention`] is
+    /// called, or the next minidump is created.
+    #[inline]
+    pub fn max_total_bytes(mut self, max: u64) -> Self {
+        self.max_total_bytes = Some(max);
+        self
+    }
+
+    /// The directory the minidumps are created in
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Creates the file for a new minidump, after removing the oldest
+    /// minidumps to make room for it.
+    ///
+    /// # Errors
+    ///
+    /// The directory or file could not be created
+    pub fn create(&self, info: &DumpInfo) -> io::Result<(File, PathBuf)> {
+        fs::create_dir_all(&self.dir)?;
+        self.evict(1)?;
+
+        let name = self.file_name(info);
+        let suffix = self.suffix();
+        let stem = &name[..name.len() - suffix.len()];
+
+        let mut attempt = 0;
+        loop {
+            let path = if attempt == 0 {
+                self.dir.join(&name)
+            } else {
+                self.dir.join(format!("{stem}-{attempt}{suffix}"))
+            };
+
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => return Ok((file, path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => attempt += 1,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Retrieves every minidump in the directory, oldest first, eg. so that
+    /// they can be uploaded and then removed.
+    ///
+    /// # Errors
+    ///
+    /// The directory could not be read, it not existing is not an error
+    pub fn pending(&self) -> io::Result<Vec<PendingDump>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let suffix = self.suffix();
+        let mut dumps = Vec::new();
+
+        for entry in entries {
+            let entry = entry?;
+
+            if !entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.ends_with(suffix))
+            {
+                continue;
+            }
+
+            // The file may have been removed since the directory was read
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            dumps.push(PendingDump {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+
+        dumps.sort_by(|a, b| {
+            a.modified
+                .cmp(&b.modified)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        Ok(dumps)
+    }
+
+    /// Removes the oldest minidumps until the directory is within its limits,
+    /// returning the paths of the minidumps that were removed.
+    ///
+    /// # Errors
+    ///
+    /// The directory could not be read, or a minidump could not be removed
+    pub fn enforce_retention(&self) -> io::Result<Vec<PathBuf>> {
+        self.evict(0)
+    }
+
+    /// Removes the oldest minidumps until there is room for the specified
+    /// number of new ones
+    fn evict(&self, incoming: usize) -> io::Result<Vec<PathBuf>> {
+        if self.max_dumps.is_none() && self.max_total_bytes.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let mut dumps = self.pending()?.into_iter();
+        let mut count = dumps.len();
+        let mut total: u64 = dumps.as_slice().iter().map(|dump| dump.size).sum();
+        let mut evicted = Vec::new();
+
+        while self.max_dumps.is_some_and(|max| count + incoming > max)
+            || self.max_total_bytes.is_some_and(|max| total > max)
+        {
+            let Some(oldest) = dumps.next() else {
+                break;
+            };
+
+            match fs::remove_file(&oldest.path) {
+                Ok(()) => {}
+                // Already removed, eg. by the user after uploading it
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+
+            log::debug!("evicted minidump {}", oldest.path.display());
+
+            count -= 1;
+            total -= oldest.size;
+            evicted.push(oldest.path);
+        }
+
+        Ok(evicted)
+    }
+
+    /// The literal text after the last placeholder in the template
+    fn suffix(&self) -> &str {
+        self.template
+            .rfind('}')
+            .map_or(self.template.as_str(), |end| &self.template[end + 1..])
+    }
+
+    fn file_name(&self, info: &DumpInfo) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let kind = match info.kind {
+            DumpKind::Crash => "crash",
+            DumpKind::Live => "live",
+        };
+
+        let reason = if info.reason.is_empty() {
+            "unknown"
+        } else {
+            &info.reason
+        };
+
+        let client = info
+            .client
+            .name
+            .as_deref()
+            .filter(|name| !name.is_empty())
+            .or_else(|| {
+                info.client
+                    .executable
+                    .as_deref()
+                    .and_then(Path::file_stem)
+                    .and_then(|stem| stem.to_str())
+            })
+            .unwrap_or("unknown");
+
+        self.template
+            .replace("{timestamp}", &timestamp.to_string())
+            .replace("{pid}", &info.pid.to_string())
+            .replace("{kind}", kind)
+            .replace("{reason}", &sanitize(reason))
+            .replace("{client}", &sanitize(client))
+    }
+}
+
+/// The maximum number of characters of [`DumpInfo::reason`], or of the client,
+/// used in a name
+const MAX_REASON_LEN: usize = 64;
+
+/// Replaces any character that isn't safe to use in a file name
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .take(MAX_REASON_LEN)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,102 @@
+//! [`MiniDumpWriteDump`](https://learn.microsoft.com/en-us/windows/win32/api/minidumpapiset/nf-minidumpapiset-minidumpwritedump)
+//! based minidump writing for Windows.
+//!
+//! This is meant to be run from a separate process, such as a [`crate::Server`],
+//! as `MiniDumpWriteDump` suspends every thread of the process it is dumping,
+//! which is unreliable if that is the calling process. The minidumps are
+//! standard `.dmp` files that can be opened by WinDbg or Visual Studio, and
+//! their contents are determined by the [`MINIDUMP_TYPE`](https://learn.microsoft.com/en-us/windows/win32/api/minidumpapiset/ne-minidumpapiset-minidump_type)
+//! flags they are written with, see [`crate::ServerHandler::minidump_type`].
+
+#![allow(unsafe_code)]
+
+use crate::Error;
+use std::{fs::File, os::windows::io::AsRawHandle};
+use windows_sys::Win32::{
+    Foundation::{CloseHandle, HANDLE},
+    System::{
+        Diagnostics::Debug::{
+            MiniDumpNormal, MiniDumpWithThreadInfo, MiniDumpWithUnloadedModules, MiniDumpWriteDump,
+            EXCEPTION_POINTERS, MINIDUMP_EXCEPTION_INFORMATION,
+        },
+        Threading::{OpenProcess, PROCESS_DUP_HANDLE, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+    },
+};
+
+/// The `MINIDUMP_TYPE` used unless [`crate::ServerHandler::minidump_type`]
+/// is overridden, which contains the stack of every thread, the modules that
+/// are and were loaded, and the times and priority of every thread, which is
+/// enough to symbolicate and unwind each stack while keeping the minidump
+/// small
+pub const DEFAULT_MINIDUMP_TYPE: u32 =
+    MiniDumpNormal | MiniDumpWithThreadInfo | MiniDumpWithUnloadedModules;
+
+/// Writes a minidump for the crash described by the [`crash_context::CrashContext`],
+/// which was sent by the crashed process, to the specified file, with the
+/// specified `MINIDUMP_TYPE` flags.
+///
+/// The exception pointers of the crash context, if any, are pointers into
+/// the crashed process, which must still be alive and waiting for the
+/// minidump to be written, as is the case while the client is blocked in
+/// [`crate::Client::request_dump`]. Without exception pointers, eg. for a
+/// process that is hung rather than crashed, the minidump doesn't contain an
+/// exception, and the context of every thread is retrieved from the process
+/// itself.
+///
+/// # Errors
+///
+/// An error will be returned if the process could not be opened, or the
+/// minidump could not be written
+pub fn write_minidump(
+    crash_context: &crash_context::CrashContext,
+    file: &mut File,
+    minidump_type: u32,
+) -> Result<(), Error> {
+    // SAFETY: syscall
+    let process = unsafe {
+        OpenProcess(
+            PROCESS_QUERY_INFORMATION | PROCESS_VM_READ | PROCESS_DUP_HANDLE,
+            0,
+            crash_context.process_id,
+        )
+    };
+    if process == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let exception = MINIDUMP_EXCEPTION_INFORMATION {
+        ThreadId: crash_context.thread_id,
+        ExceptionPointers: crash_context.exception_pointers as *mut EXCEPTION_POINTERS,
+        // The pointers are in the address space of the crashed process
+        ClientPointers: 1,
+    };
+
+    // SAFETY: syscall, the handles are valid for the duration of the call
+    let written = unsafe {
+        MiniDumpWriteDump(
+            process,
+            crash_context.process_id,
+            file.as_raw_handle() as HANDLE,
+            minidump_type,
+            if crash_context.exception_pointers.is_null() {
+                std::ptr::null()
+            } else {
+                &exception
+            },
+            std::ptr::null(),
+            std::ptr::null(),
+        )
+    };
+    let result = if written == 0 {
+        Err(std::io::Error::last_os_error().into())
+    } else {
+        Ok(())
+    };
+
+    // SAFETY: syscall, we own the handle
+    unsafe {
+        CloseHandle(process);
+    }
+
+    result
+}
@@ -0,0 +1,99 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use minidumper::ClientExit;
+use std::sync::{atomic, Arc};
+
+const CHILD_ENV: &str = "MINIDUMPER_CLIENT_EXIT_CHILD";
+
+/// Only does anything when spawned by [`reports_client_exits`]
+#[test]
+fn exiting_child() {
+    let Some(name) = std::env::var_os(CHILD_ENV) else {
+        return;
+    };
+
+    let client = minidumper::Client::with_name(name.to_str().unwrap()).unwrap();
+    client.ping().unwrap();
+
+    if std::env::args().any(|arg| arg == "vanish") {
+        #[allow(unsafe_code)]
+        // SAFETY: syscalls
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGKILL);
+        }
+    }
+
+    drop(client);
+}
+
+/// Tests that the server can tell a client that disconnected cleanly apart
+/// from one whose process was killed
+#[test]
+fn reports_client_exits() {
+    let name = "reports_client_exits";
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    struct Server {
+        exits: Arc<parking_lot::Mutex<Vec<(u32, ClientExit)>>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("should not be called");
+        }
+
+        fn on_minidump_created(
+            &self,
+            _result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            panic!("should not be called");
+        }
+
+        fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {}
+
+        fn on_client_exited(&self, pid: u32, exit: ClientExit) -> minidumper::LoopAction {
+            self.exits.lock().push((pid, exit));
+            minidumper::LoopAction::Continue
+        }
+    }
+
+    let exits = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+    let server_handler = Server {
+        exits: exits.clone(),
+    };
+
+    let shutdown = Arc::new(atomic::AtomicBool::new(false));
+    let is_shutdown = shutdown.clone();
+    let server_loop =
+        std::thread::spawn(move || server.run(Box::new(server_handler), &is_shutdown, None));
+
+    let spawn = |arg: &str| {
+        std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "exiting_child", "--nocapture", arg])
+            .env(CHILD_ENV, name)
+            .status()
+            .unwrap()
+    };
+
+    let clean = spawn("clean");
+    assert!(clean.success());
+
+    let vanish = spawn("vanish");
+    assert!(!vanish.success());
+
+    let start = std::time::Instant::now();
+    while exits.lock().len() < 2 && start.elapsed() < std::time::Duration::from_secs(5) {
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+
+    shutdown.store(true, atomic::Ordering::Relaxed);
+    server_loop.join().unwrap().unwrap();
+
+    let exits = exits.lock();
+    let kinds: Vec<_> = exits.iter().map(|(_pid, exit)| *exit).collect();
+    assert_eq!(kinds, [ClientExit::Clean, ClientExit::Vanished]);
+}
@@ -55,6 +55,12 @@ fn ipc_messages() {
         assert!(client.send_message(i, format!("msg #{i}")).is_ok(), "{i}");
     }
 
+    // Kinds that would collide with the internal messages are rejected
+    assert!(matches!(
+        client.send_message(minidumper::MAX_MESSAGE_KIND + 1, "reserved"),
+        Err(minidumper::Error::InvalidMessageKind(_))
+    ));
+
     shutdown.store(true, atomic::Ordering::Relaxed);
     server_loop.join().unwrap().unwrap();
 
@@ -211,6 +217,7 @@ fn ping() {
     });
 
     let client = minidumper::Client::with_name(name).unwrap();
+    assert_eq!(client.protocol_version(), minidumper::PROTOCOL_VERSION);
 
     let start = std::time::Instant::now();
     for i in 0..3 {
@@ -231,3 +238,543 @@ fn ping() {
 
     assert!(client.ping().is_err(), "server should be gone");
 }
+
+/// Tests that a synthetic crash context is sent to the server just like an
+/// actual crash, so that dump processing can be tested without crashing
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn synthetic_crash() {
+    let name = "synthetic_crash";
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    struct Server {
+        reasons: Arc<parking_lot::Mutex<Vec<(u32, String)>>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("should not be called");
+        }
+
+        fn create_minidump_file_for(
+            &self,
+            info: &minidumper::storage::DumpInfo,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            self.reasons.lock().push((info.pid, info.reason.clone()));
+
+            let path = std::env::temp_dir().join(format!("{}.dmp", uuid::Uuid::new_v4()));
+            Ok((std::fs::File::create(&path)?, path))
+        }
+
+        fn on_minidump_created(
+            &self,
+            result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            // The stack pointer is made up, so whether the minidump could be
+            // written doesn't matter, only that the request was processed
+            if let Ok(binary) = result {
+                let _ = std::fs::remove_file(binary.path);
+            }
+            minidumper::LoopAction::Exit
+        }
+
+        fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {
+            panic!("should not be called");
+        }
+    }
+
+    let reasons = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let server_handler = Server {
+        reasons: reasons.clone(),
+    };
+
+    let shutdown = Arc::new(atomic::AtomicBool::new(false));
+    let server_loop =
+        std::thread::spawn(move || server.run(Box::new(server_handler), &shutdown, None));
+
+    let client = minidumper::Client::with_name(name).unwrap();
+
+    let cc = crash_context::CrashContext::synthetic(libc::SIGBUS, 0x1000, 0x2000);
+    client.request_dump(&cc).unwrap();
+
+    server_loop.join().unwrap().unwrap();
+
+    assert_eq!(
+        reasons.lock().as_slice(),
+        [(std::process::id(), "SIGBUS".to_owned())]
+    );
+}
+
+/// Tests that a server monitoring several clients can tell them apart
+#[test]
+fn identifies_clients() {
+    let name = "identifies_clients";
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    struct Server {
+        events: Arc<parking_lot::Mutex<Vec<String>>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("should not be called");
+        }
+
+        fn on_minidump_created(
+            &self,
+            _result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            panic!("should not be called");
+        }
+
+        fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {
+            panic!("on_message_from should be called instead");
+        }
+
+        fn on_message_from(&self, client: &minidumper::ClientInfo, kind: u32, buffer: Vec<u8>) {
+            assert_eq!(client.pid, Some(std::process::id()));
+            assert_eq!(client.executable, std::env::current_exe().ok());
+
+            self.events.lock().push(format!(
+                "{}: {kind} {}",
+                client.name.as_deref().unwrap_or("unnamed"),
+                String::from_utf8(buffer).unwrap()
+            ));
+        }
+
+        fn on_client_identified(&self, client: &minidumper::ClientInfo) -> minidumper::LoopAction {
+            if let Some(name) = &client.name {
+                self.events.lock().push(format!("identified {name}"));
+            }
+
+            minidumper::LoopAction::Continue
+        }
+
+        fn on_client_disconnected_from(
+            &self,
+            client: &minidumper::ClientInfo,
+            num_clients: usize,
+        ) -> minidumper::LoopAction {
+            self.events.lock().push(format!(
+                "disconnected {}",
+                client.name.as_deref().unwrap_or("unnamed")
+            ));
+
+            if num_clients == 0 {
+                minidumper::LoopAction::Exit
+            } else {
+                minidumper::LoopAction::Continue
+            }
+        }
+    }
+
+    let events = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let server_handler = Server {
+        events: events.clone(),
+    };
+
+    let shutdown = Arc::new(atomic::AtomicBool::new(false));
+    let server_loop =
+        std::thread::spawn(move || server.run(Box::new(server_handler), &shutdown, None));
+
+    let mut tab_one = minidumper::Client::with_name(name).unwrap();
+    tab_one.set_name("tab-1").unwrap();
+    let mut tab_two = minidumper::Client::with_name(name).unwrap();
+    tab_two.set_name("tab-2").unwrap();
+
+    // The messages of different clients can be received in any order, so
+    // wait for each to be received before sending the next
+    tab_one.send_message(1, "one").unwrap();
+    tab_one.ping().unwrap();
+    tab_two.send_message(2, "two").unwrap();
+    tab_two.ping().unwrap();
+    drop(tab_one);
+    drop(tab_two);
+
+    server_loop.join().unwrap().unwrap();
+
+    let mut events = events.lock().clone();
+    events[4..].sort();
+    assert_eq!(
+        events,
+        [
+            "identified tab-1",
+            "identified tab-2",
+            "tab-1: 1 one",
+            "tab-2: 2 two",
+            "disconnected tab-1",
+            "disconnected tab-2",
+        ]
+    );
+}
+
+/// Tests that the server can be driven by a loop that also does other work,
+/// rather than by [`minidumper::Server::run`]
+#[test]
+fn poll_once() {
+    let name = "poll_once";
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    struct Server {
+        messages: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("should not be called");
+        }
+
+        fn on_minidump_created(
+            &self,
+            _result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            panic!("should not be called");
+        }
+
+        fn on_message(&self, _kind: u32, buffer: Vec<u8>) {
+            self.messages
+                .lock()
+                .push(String::from_utf8(buffer).unwrap());
+        }
+
+        fn on_client_disconnected(&self, num_clients: usize) -> minidumper::LoopAction {
+            if num_clients == 0 {
+                minidumper::LoopAction::Exit
+            } else {
+                minidumper::LoopAction::Continue
+            }
+        }
+    }
+
+    let handler = Server {
+        messages: parking_lot::Mutex::new(Vec::new()),
+    };
+
+    let client = std::thread::spawn(move || {
+        let client = minidumper::Client::with_name(name).unwrap();
+        client.send_message(1, "polled").unwrap();
+    });
+
+    let mut other_work = 0;
+    while server
+        .poll_once(&handler, Some(std::time::Duration::ZERO), None)
+        .unwrap()
+        == minidumper::LoopAction::Continue
+    {
+        other_work += 1;
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    client.join().unwrap();
+
+    assert_eq!(handler.messages.lock().as_slice(), ["polled"]);
+    assert!(other_work > 0);
+}
+
+/// Tests that a server that is shut down handles what its clients already
+/// sent, but doesn't accept new clients
+#[test]
+fn graceful_shutdown() {
+    let name = "graceful_shutdown";
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    struct Server {
+        messages: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("should not be called");
+        }
+
+        fn on_minidump_created(
+            &self,
+            _result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            panic!("should not be called");
+        }
+
+        fn on_message(&self, _kind: u32, buffer: Vec<u8>) {
+            self.messages
+                .lock()
+                .push(String::from_utf8(buffer).unwrap());
+        }
+    }
+
+    let handler = Server {
+        messages: parking_lot::Mutex::new(Vec::new()),
+    };
+
+    let (sent_tx, sent_rx) = std::sync::mpsc::channel();
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+    let client = std::thread::spawn(move || {
+        let client = minidumper::Client::with_name(name).unwrap();
+        client.send_message(1, "before shutdown").unwrap();
+        sent_tx.send(()).unwrap();
+        let _ = done_rx.recv();
+    });
+
+    // Handle the handshake, the message may or may not have been handled by
+    // the time the server is shut down, but must have been once it has
+    while sent_rx.try_recv().is_err() {
+        server
+            .poll_once(&handler, Some(std::time::Duration::from_millis(1)), None)
+            .unwrap();
+    }
+
+    let summary = server
+        .shutdown(
+            &handler,
+            std::time::Instant::now() + std::time::Duration::from_secs(1),
+        )
+        .unwrap();
+
+    assert!(summary.drained);
+    assert_eq!(summary.disconnected.len(), 1);
+    assert!(summary.unfinished.is_empty());
+    assert_eq!(handler.messages.lock().as_slice(), ["before shutdown"]);
+
+    assert!(minidumper::Client::with_name(name).is_err());
+
+    done_tx.send(()).unwrap();
+    client.join().unwrap();
+}
+
+/// Tests that a client reconnects to a server that was restarted, and spools
+/// crashes it can't send
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn reconnects_and_spools() {
+    let name = "reconnects_and_spools";
+    let spool_dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+
+    struct Server {
+        messages: parking_lot::Mutex<Vec<String>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("should not be called");
+        }
+
+        fn on_minidump_created(
+            &self,
+            _result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            panic!("should not be called");
+        }
+
+        fn on_message(&self, _kind: u32, buffer: Vec<u8>) {
+            self.messages
+                .lock()
+                .push(String::from_utf8(buffer).unwrap());
+        }
+    }
+
+    let handler = Server {
+        messages: parking_lot::Mutex::new(Vec::new()),
+    };
+
+    let poll_until = |server: &mut minidumper::Server, count: usize| {
+        while handler.messages.lock().len() < count {
+            server
+                .poll_once(&handler, Some(std::time::Duration::from_millis(1)), None)
+                .unwrap();
+        }
+    };
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    let (restarted_tx, restarted_rx) = std::sync::mpsc::channel::<()>();
+    let (gone_tx, gone_rx) = std::sync::mpsc::channel::<()>();
+    let client_spool_dir = spool_dir.clone();
+    let client = std::thread::spawn(move || {
+        let mut client = minidumper::Client::with_name(name).unwrap();
+        client.set_spool_dir(&client_spool_dir).unwrap();
+        client.send_message(1, "first server").unwrap();
+
+        restarted_rx.recv().unwrap();
+        client.send_message(1, "second server").unwrap();
+
+        gone_rx.recv().unwrap();
+        let cc = crash_context::CrashContext::synthetic(libc::SIGSEGV, 0x1000, 0x2000);
+        assert!(client.request_dump(&cc).is_err());
+    });
+
+    poll_until(&mut server, 1);
+    drop(server);
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+    restarted_tx.send(()).unwrap();
+    poll_until(&mut server, 2);
+    drop(server);
+
+    gone_tx.send(()).unwrap();
+    client.join().unwrap();
+
+    assert_eq!(
+        handler.messages.lock().as_slice(),
+        ["first server", "second server"]
+    );
+
+    let crashes = minidumper::spool::pending(&spool_dir).unwrap();
+    assert_eq!(crashes.len(), 1);
+    assert_eq!(crashes[0].pid, std::process::id());
+    assert_eq!(crashes[0].reason(), "SIGSEGV");
+
+    std::fs::remove_dir_all(&spool_dir).unwrap();
+}
+
+const LIFECYCLE_CHILD_ENV: &str = "MINIDUMPER_LIFECYCLE_CHILD";
+
+/// Only does anything when spawned by [`lifecycle_order`]
+#[test]
+fn lifecycle_child() {
+    let Some(name) = std::env::var_os(LIFECYCLE_CHILD_ENV) else {
+        return;
+    };
+
+    let client = minidumper::Client::with_name(name.to_str().unwrap()).unwrap();
+    client.send_message(1, "hello").unwrap();
+
+    // Exits without dropping the client, as the process would after a crash
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let cc = crash_context::CrashContext::synthetic(libc::SIGSEGV, 0x1000, 0x2000);
+        client.request_dump(&cc).unwrap();
+        #[allow(clippy::exit)]
+        std::process::exit(0);
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    drop(client);
+}
+
+/// Tests that the handler is called at each point in the lifetime of a client
+/// in the order documented on [`minidumper::ServerHandler`]
+#[test]
+fn lifecycle_order() {
+    let name = "lifecycle_order";
+
+    let mut server = minidumper::Server::with_name(name).unwrap();
+
+    struct Server {
+        events: Arc<parking_lot::Mutex<Vec<String>>>,
+    }
+
+    impl minidumper::ServerHandler for Server {
+        fn create_minidump_file(
+            &self,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            panic!("create_minidump_file_for should be called instead");
+        }
+
+        fn create_minidump_file_for(
+            &self,
+            _info: &minidumper::storage::DumpInfo,
+        ) -> Result<(std::fs::File, std::path::PathBuf), std::io::Error> {
+            self.events.lock().push("dump requested".to_owned());
+
+            let path = std::env::temp_dir().join(format!("{}.dmp", uuid::Uuid::new_v4()));
+            Ok((std::fs::File::create(&path)?, path))
+        }
+
+        fn on_minidump_created(
+            &self,
+            result: Result<minidumper::MinidumpBinary, minidumper::Error>,
+        ) -> minidumper::LoopAction {
+            // The context is made up, so whether the minidump could be
+            // written doesn't matter, only that it was reported
+            if let Ok(binary) = result {
+                let _ = std::fs::remove_file(binary.path);
+            }
+            self.events.lock().push("dump complete".to_owned());
+            minidumper::LoopAction::Continue
+        }
+
+        fn on_message(&self, _kind: u32, _buffer: Vec<u8>) {
+            self.events.lock().push("message".to_owned());
+        }
+
+        fn on_client_connected(&self, _num_clients: usize) -> minidumper::LoopAction {
+            self.events.lock().push("connected".to_owned());
+            minidumper::LoopAction::Continue
+        }
+
+        fn on_client_identified(&self, _client: &minidumper::ClientInfo) -> minidumper::LoopAction {
+            self.events.lock().push("identified".to_owned());
+            minidumper::LoopAction::Continue
+        }
+
+        fn on_client_disconnected_from(
+            &self,
+            _client: &minidumper::ClientInfo,
+            _num_clients: usize,
+        ) -> minidumper::LoopAction {
+            self.events.lock().push("disconnected".to_owned());
+            minidumper::LoopAction::Continue
+        }
+
+        fn on_client_exited(
+            &self,
+            _pid: u32,
+            exit: minidumper::ClientExit,
+        ) -> minidumper::LoopAction {
+            self.events.lock().push(format!("exited {exit:?}"));
+            minidumper::LoopAction::Exit
+        }
+    }
+
+    let events = Arc::new(parking_lot::Mutex::new(Vec::new()));
+    let server_handler = Server {
+        events: events.clone(),
+    };
+
+    let shutdown = Arc::new(atomic::AtomicBool::new(false));
+    let server_loop =
+        std::thread::spawn(move || server.run(Box::new(server_handler), &shutdown, None));
+
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["--exact", "lifecycle_child", "--nocapture"])
+        .env(LIFECYCLE_CHILD_ENV, name)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    server_loop.join().unwrap().unwrap();
+
+    let expected: &[&str] = if cfg!(any(target_os = "linux", target_os = "android")) {
+        &[
+            "connected",
+            "identified",
+            "message",
+            "dump requested",
+            "dump complete",
+            "disconnected",
+            "exited Crashed",
+        ]
+    } else {
+        &[
+            "connected",
+            "identified",
+            "message",
+            "disconnected",
+            "exited Clean",
+        ]
+    };
+    assert_eq!(events.lock().as_slice(), expected);
+}
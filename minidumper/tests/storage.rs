@@ -0,0 +1,103 @@
+use minidumper::storage::{DumpDirectory, DumpInfo, DumpKind};
+use std::io::Write;
+
+fn info(reason: &str) -> DumpInfo {
+    DumpInfo {
+        pid: 1234,
+        kind: DumpKind::Crash,
+        reason: reason.to_owned(),
+        client: minidumper::ClientInfo::default(),
+    }
+}
+
+fn temp_dir() -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("minidumper-storage-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn names_dumps_from_template() {
+    let dir = temp_dir();
+    let dumps = DumpDirectory::new(&dir).template("{kind}-{pid}-{reason}.dmp");
+
+    let (_file, path) = dumps.create(&info("SIGSEGV")).unwrap();
+    assert_eq!(path, dir.join("crash-1234-SIGSEGV.dmp"));
+
+    // Existing dumps are never overwritten
+    let (_file, path) = dumps.create(&info("SIGSEGV")).unwrap();
+    assert_eq!(path, dir.join("crash-1234-SIGSEGV-1.dmp"));
+
+    // The reason can't escape the directory
+    let (_file, path) = dumps.create(&info("../hung thread")).unwrap();
+    assert_eq!(path, dir.join("crash-1234-___hung_thread.dmp"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn names_dumps_by_client() {
+    let dir = temp_dir();
+    let dumps = DumpDirectory::new(&dir).template("{client}-{reason}.dmp");
+
+    let (_file, path) = dumps.create(&info("SIGSEGV")).unwrap();
+    assert_eq!(path, dir.join("unknown-SIGSEGV.dmp"));
+
+    // The executable is used if the client has no name
+    let mut tab = info("SIGSEGV");
+    tab.client.executable = Some("/opt/browser/renderer".into());
+    let (_file, path) = dumps.create(&tab).unwrap();
+    assert_eq!(path, dir.join("renderer-SIGSEGV.dmp"));
+
+    tab.client.name = Some("tab 3".to_owned());
+    let (_file, path) = dumps.create(&tab).unwrap();
+    assert_eq!(path, dir.join("tab_3-SIGSEGV.dmp"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn evicts_oldest_dumps() {
+    let dir = temp_dir();
+
+    // Not a minidump, so it is never evicted
+    std::fs::write(dir.join("config.json"), "{}").unwrap();
+
+    let dumps = DumpDirectory::new(&dir)
+        .template("{reason}.dmp")
+        .max_dumps(3)
+        .max_total_bytes(250);
+
+    for i in 0..4 {
+        let (mut file, _path) = dumps.create(&info(&i.to_string())).unwrap();
+        file.write_all(&[0; 100]).unwrap();
+
+        // Ensure the modification times differ
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+
+    // The count limit is enforced before each dump is created, but the newest
+    // dump puts the directory over its size limit
+    let pending: Vec<_> = dumps
+        .pending()
+        .unwrap()
+        .into_iter()
+        .map(|dump| dump.path)
+        .collect();
+    assert_eq!(
+        pending,
+        vec![dir.join("1.dmp"), dir.join("2.dmp"), dir.join("3.dmp")]
+    );
+
+    assert_eq!(dumps.enforce_retention().unwrap(), vec![dir.join("1.dmp")]);
+    assert_eq!(dumps.pending().unwrap().len(), 2);
+    assert!(dir.join("config.json").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn missing_directory_has_no_pending_dumps() {
+    let dumps = DumpDirectory::new(std::env::temp_dir().join("minidumper-storage-missing"));
+    assert!(dumps.pending().unwrap().is_empty());
+}
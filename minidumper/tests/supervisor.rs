@@ -0,0 +1,48 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+use minidumper::supervisor::{Event, Supervisor};
+
+const CHILD_ENV: &str = "MINIDUMPER_SUPERVISOR_CHILD";
+
+/// Only does anything when spawned by [`supervises_child`]
+#[test]
+fn supervised_child() {
+    if std::env::var_os(CHILD_ENV).is_none() {
+        return;
+    }
+
+    let client = minidumper::Client::from_env().unwrap();
+    client.send_message(1, "hello from the child").unwrap();
+    client.ping().unwrap();
+
+    #[allow(clippy::exit)]
+    std::process::exit(3);
+}
+
+/// Tests that the supervisor forwards user messages from the child, and
+/// reports when it exits
+#[test]
+fn supervises_child() {
+    let mut cmd = std::process::Command::new(std::env::current_exe().unwrap());
+    cmd.args(["--exact", "supervised_child", "--nocapture"])
+        .env(CHILD_ENV, "1");
+
+    let supervisor =
+        Supervisor::spawn(&mut cmd, Box::new(|| panic!("should not be called"))).unwrap();
+
+    let events = supervisor.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(events.len(), 2);
+
+    match &events[0] {
+        Event::Message { kind, buffer } => {
+            assert_eq!(*kind, 1);
+            assert_eq!(buffer, b"hello from the child");
+        }
+        _ => panic!("unexpected event"),
+    }
+
+    match &events[1] {
+        Event::Exited(status) => assert_eq!(status.code(), Some(3)),
+        _ => panic!("unexpected event"),
+    }
+}
@@ -0,0 +1,215 @@
+//! Ensures crashes are uploaded in the expected format, and failed uploads are
+//! retried
+#![cfg(feature = "upload")]
+
+use minidumper::upload::{Crash, Uploader};
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    time::Duration,
+};
+
+/// Accepts a connection for each status, responding with the status once the
+/// request has been read, and returns the headers and body of each request
+fn serve(statuses: &'static [u16]) -> (String, std::thread::JoinHandle<Vec<(String, Vec<u8>)>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for status in statuses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut headers = String::new();
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = len.trim().parse().unwrap();
+                }
+                headers.push_str(&line);
+            }
+
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            requests.push((headers, body));
+
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status} Whatever\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            )
+            .unwrap();
+        }
+
+        requests
+    });
+
+    (url, server)
+}
+
+fn crash() -> (Crash, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("minidumper-upload-{}.dmp", uuid::Uuid::new_v4()));
+    std::fs::write(&path, b"MDMP not really").unwrap();
+
+    let crash = Crash::new(&path)
+        .annotation("release", "1.0.0")
+        .context(r#"{"pid":1234}"#);
+    (crash, path)
+}
+
+#[test]
+fn uploads_sentry_envelope() {
+    let (url, server) = serve(&[503, 200]);
+    let (crash, path) = crash();
+
+    let dsn = url.replace("http://", "http://public@") + "/42";
+    Uploader::sentry(&dsn)
+        .unwrap()
+        .retry_delay(Duration::from_millis(10))
+        .upload(&crash)
+        .unwrap();
+
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+
+    let (headers, body) = &requests[1];
+    assert!(headers.starts_with("POST /api/42/envelope/ HTTP/1.1\r\n"));
+    assert!(headers.contains("sentry_key=public"));
+
+    let body = String::from_utf8(body.clone()).unwrap();
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines.len(), 5);
+    assert!(
+        lines[2].contains(r#""tags":{"release":"1.0.0"},"extra":{"crash_context":{"pid":1234}}"#)
+    );
+    assert!(lines[3].contains(r#""length":15,"#));
+    assert!(lines[3].contains(r#""attachment_type":"event.minidump""#));
+    assert_eq!(lines[4], "MDMP not really");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn uploads_multipart() {
+    let (url, server) = serve(&[200]);
+    let (crash, path) = crash();
+
+    Uploader::multipart(format!("{url}/submit"))
+        .upload(&crash)
+        .unwrap();
+
+    let requests = server.join().unwrap();
+    let (headers, body) = &requests[0];
+    assert!(headers.starts_with("POST /submit HTTP/1.1\r\n"));
+    assert!(headers.contains("multipart/form-data; boundary=minidumper-"));
+
+    let body = String::from_utf8(body.clone()).unwrap();
+    assert!(body
+        .contains("name=\"release\"\r\ncontent-type: text/plain; charset=utf-8\r\n\r\n1.0.0\r\n"));
+    assert!(body
+        .contains("name=\"context\"\r\ncontent-type: application/json\r\n\r\n{\"pid\":1234}\r\n"));
+    assert!(body.contains("name=\"upload_file_minidump\"; filename=\"minidumper-upload-"));
+    assert!(body.contains("\r\n\r\nMDMP not really\r\n--minidumper-"));
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn gives_up_on_client_errors() {
+    let (url, server) = serve(&[400]);
+    let (crash, path) = crash();
+
+    assert!(Uploader::multipart(url)
+        .retry_delay(Duration::from_millis(10))
+        .upload(&crash)
+        .is_err());
+    assert_eq!(server.join().unwrap().len(), 1);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn queue_persists_until_online() {
+    use minidumper::reporter::{FlushSummary, Queue};
+
+    let dir = std::env::temp_dir().join(format!("minidumper-queue-{}", uuid::Uuid::new_v4()));
+
+    // Nothing is listening on the port, so the machine appears to be offline
+    let offline = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+
+    let queue = Queue::new(&dir, Uploader::multipart(offline).max_attempts(1))
+        .backoff(Duration::ZERO, Duration::ZERO);
+
+    let (first, first_path) = crash();
+    let (second, second_path) = crash();
+    let queued = queue.enqueue(first).unwrap();
+    assert!(queued.starts_with(&dir));
+    assert!(!first_path.exists());
+    // Crashes are ordered by the millisecond they were enqueued in
+    std::thread::sleep(Duration::from_millis(2));
+    queue.enqueue(second.annotation("tab", "a\tb\nc")).unwrap();
+    assert!(!second_path.exists());
+
+    // Once the first upload fails, the second isn't attempted
+    assert_eq!(
+        queue.flush().unwrap(),
+        FlushSummary {
+            uploaded: 0,
+            dropped: 0,
+            pending: 2,
+        }
+    );
+
+    let pending = queue.pending().unwrap();
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[0].attempts, 1);
+    assert_eq!(pending[1].attempts, 0);
+    assert_eq!(
+        pending[1].crash.annotations,
+        [
+            ("release".to_owned(), "1.0.0".to_owned()),
+            ("tab".to_owned(), "a\tb\nc".to_owned())
+        ]
+    );
+    assert_eq!(pending[1].crash.context.as_deref(), Some(r#"{"pid":1234}"#));
+
+    // A new queue in the same directory resumes where the last one left off
+    let (url, server) = serve(&[200, 200]);
+    let queue = Queue::new(&dir, Uploader::multipart(url).max_attempts(1))
+        .backoff(Duration::ZERO, Duration::ZERO);
+    assert_eq!(
+        queue.flush().unwrap(),
+        FlushSummary {
+            uploaded: 2,
+            dropped: 0,
+            pending: 0,
+        }
+    );
+    assert_eq!(server.join().unwrap().len(), 2);
+    assert!(queue.pending().unwrap().is_empty());
+
+    // Crashes that are too old are dropped without being uploaded
+    let (old, _) = crash();
+    queue.enqueue(old).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    let queue = queue.max_age(Duration::from_millis(1));
+    assert_eq!(
+        queue.flush().unwrap(),
+        FlushSummary {
+            uploaded: 0,
+            dropped: 1,
+            pending: 0,
+        }
+    );
+    assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -21,6 +21,13 @@ pub enum SadnessFlavor {
     /// * `EXCEPTION_ACCESS_VIOLATION` on Windows
     /// * `EXC_BAD_ACCESS` on Macos
     Segfault,
+    /// Writes to memory that is mapped but read only, rather than to an
+    /// address that isn't mapped at all like [`Self::Segfault`]
+    ///
+    /// * `SIGSEGV` on Linux
+    /// * `EXCEPTION_ACCESS_VIOLATION` on Windows
+    /// * `EXC_BAD_ACCESS` on Macos
+    WildWrite,
     /// * `SIGFPE` on Linux
     /// * `EXCEPTION_INT_DIVIDE_BY_ZERO` on Windows
     /// * `EXC_ARITHMETIC` on Macos
@@ -59,6 +66,23 @@ pub enum SadnessFlavor {
     /// file descriptor then attempting to perform the operation that was guarded
     #[cfg(target_os = "macos")]
     Guard,
+    /// Frees the same heap allocation twice, which the allocator detects as
+    /// heap corruption and aborts the process
+    ///
+    /// * `SIGABRT` on Linux (glibc) and Macos
+    ///
+    /// This is not implemented on Windows as the heap terminates the process
+    /// via [`RtlReportFatalFailure`](https://docs.microsoft.com/en-us/windows/win32/api/heapapi/nf-heapapi-heapsetinformation)
+    /// when corruption is detected, which, like [`Self::Abort`], is not
+    /// delivered to exception handlers.
+    #[cfg(unix)]
+    HeapCorruption,
+    /// Throws a C++-style exception that is not caught by anything, the same
+    /// as a C++ library throwing across an FFI boundary into Rust code
+    ///
+    /// * `SIGABRT` on Unix
+    /// * `0xe06d7363` (the MSVC C++ exception code) on Windows
+    CppException,
 }
 
 impl SadnessFlavor {
@@ -72,6 +96,7 @@ impl SadnessFlavor {
             #[cfg(unix)]
             Self::Abort => raise_abort(),
             Self::Segfault => raise_segfault(),
+            Self::WildWrite => raise_wild_write(),
             Self::DivideByZero => raise_floating_point_exception(),
             Self::Illegal => raise_illegal_instruction(),
             #[cfg(unix)]
@@ -101,6 +126,9 @@ impl SadnessFlavor {
             Self::InvalidParameter => raise_invalid_parameter(),
             #[cfg(target_os = "macos")]
             Self::Guard => raise_guard_exception(),
+            #[cfg(unix)]
+            Self::HeapCorruption => raise_heap_corruption(),
+            Self::CppException => raise_cpp_exception(),
         }
     }
 }
@@ -137,6 +165,23 @@ pub unsafe fn raise_segfault() -> ! {
     std::process::abort()
 }
 
+/// Read only data that [`raise_wild_write`] attempts to write to, the address
+/// of which will be the fault address of the resulting crash
+pub static WILD_WRITE_TARGET: [u8; 16] = *b"you can't touch!";
+
+/// [`SadnessFlavor::WildWrite`]
+///
+/// # Safety
+///
+/// This is not safe. It intentionally crashes.
+pub unsafe fn raise_wild_write() -> ! {
+    let bad_ptr = WILD_WRITE_TARGET.as_ptr() as *mut u8;
+    std::ptr::write_volatile(bad_ptr, 1);
+
+    // If we get here the linker placed the static in writable memory
+    std::process::abort()
+}
+
 /// [`SadnessFlavor::DivideByZero`]
 ///
 /// # Safety
@@ -156,10 +201,11 @@ pub unsafe fn raise_floating_point_exception() -> ! {
             );
             divisor
         }
-        #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+        #[cfg(any(target_arch = "arm", target_arch = "aarch64", target_arch = "riscv64"))]
         {
-            // Unfortunately ARM by default will not raise SIGFPE on divide
-            // by 0 and just return 0, so we just explicitly raise here for now
+            // Unfortunately ARM and RISC-V will not raise SIGFPE on divide
+            // by 0 and just return 0 or all ones respectively, so we just
+            // explicitly raise here for now
             libc::raise(libc::SIGFPE);
             0
         }
@@ -180,6 +226,8 @@ pub unsafe fn raise_illegal_instruction() -> ! {
     asm!("ud2");
     #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
     asm!("udf #0");
+    #[cfg(target_arch = "riscv64")]
+    asm!("unimp");
 
     std::process::abort()
 }
@@ -239,6 +287,8 @@ pub unsafe fn raise_trap() -> ! {
     asm!(".inst 0xe7f001f0");
     #[cfg(target_arch = "aarch64")]
     asm!(".inst 0xd4200000");
+    #[cfg(target_arch = "riscv64")]
+    asm!("ebreak");
 
     std::process::abort()
 }
@@ -447,3 +497,87 @@ pub unsafe fn raise_guard_exception() -> ! {
 
     std::process::abort()
 }
+
+/// [`SadnessFlavor::HeapCorruption`]
+///
+/// # Safety
+///
+/// This is not safe. It intentionally crashes.
+#[cfg(unix)]
+pub unsafe fn raise_heap_corruption() -> ! {
+    let alloc = libc::malloc(32);
+    assert!(!alloc.is_null(), "failed to allocate, unable to crash");
+
+    libc::free(alloc);
+    // The allocator checks for double frees and aborts when it finds one
+    libc::free(alloc);
+
+    std::process::abort()
+}
+
+/// [`SadnessFlavor::CppException`]
+///
+/// # Safety
+///
+/// This is not safe. It intentionally crashes.
+#[cfg(unix)]
+pub unsafe fn raise_cpp_exception() -> ! {
+    /// `_Unwind_Exception`, padded so that it is at least as large as the
+    /// largest variant, ARM EHABI's `_Unwind_Control_Block`
+    #[repr(C, align(16))]
+    struct UnwindException {
+        exception_class: u64,
+        exception_cleanup: Option<extern "C" fn(i32, *mut UnwindException)>,
+        private: [usize; 20],
+    }
+
+    extern "C" {
+        fn _Unwind_RaiseException(exception: *mut UnwindException) -> i32;
+    }
+
+    /// The class `__cxa_throw` uses for exceptions thrown by GNU C++
+    const GNU_CXX_CLASS: u64 = u64::from_be_bytes(*b"GNUCC++\0");
+
+    // Like `__cxa_allocate_exception`, the exception is heap allocated as it
+    // needs to outlive the frames that are unwound
+    let exception = Box::into_raw(Box::new(UnwindException {
+        exception_class: GNU_CXX_CLASS,
+        exception_cleanup: None,
+        private: [0; 20],
+    }));
+
+    // Nothing can catch this exception, Rust frames included, so this either
+    // returns `_URC_END_OF_STACK`, or the Rust runtime aborts as it refuses
+    // to unwind foreign exceptions
+    _Unwind_RaiseException(exception);
+
+    // This is what `__cxa_throw` does via `std::terminate` when the
+    // exception was not caught
+    std::process::abort()
+}
+
+/// [`SadnessFlavor::CppException`]
+///
+/// # Safety
+///
+/// This is not safe. It intentionally crashes.
+#[cfg(target_os = "windows")]
+pub unsafe fn raise_cpp_exception() -> ! {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn RaiseException(code: u32, flags: u32, num_args: u32, args: *const usize);
+    }
+
+    /// The exception code used by `throw` in MSVC C++, the same one used by
+    /// Rust panics
+    const MSVC_CPP_EXCEPTION: u32 = 0xe06d7363;
+    const EXCEPTION_NONCONTINUABLE: u32 = 0x1;
+
+    RaiseException(
+        MSVC_CPP_EXCEPTION,
+        EXCEPTION_NONCONTINUABLE,
+        0,
+        std::ptr::null(),
+    );
+    std::process::abort()
+}